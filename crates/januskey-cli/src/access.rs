@@ -0,0 +1,156 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Access: who may ask the daemon for what
+// API tokens name a client and grant it one role. Readers see status and
+// history; operators change files, undo and manage transactions;
+// compliance officers obliterate. Only a hash of each token is kept in
+// the config, and the daemon compares presented tokens by that hash.
+
+use crate::actor::validate_actor;
+use crate::error::Result;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+/// API token clients present to a daemon that requires one
+pub const TOKEN_ENV: &str = "JANUSKEY_DAEMON_TOKEN";
+
+/// Prefix of generated tokens, so they are recognisable in a leak
+const TOKEN_PREFIX: &str = "jkt_";
+
+/// What a role is allowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// Status and history
+    Read,
+    /// Execute operations, undo, and begin, commit and roll back
+    /// transactions
+    Operate,
+    /// Securely destroy stored content
+    Obliterate,
+}
+
+/// A role an API token grants
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Role {
+    Reader,
+    Operator,
+    ComplianceOfficer,
+}
+
+impl Role {
+    /// Whether this role may do what `permission` covers. Every role
+    /// reads; only operators change and undo, and only compliance
+    /// officers obliterate.
+    pub fn permits(self, permission: Permission) -> bool {
+        match permission {
+            Permission::Read => true,
+            Permission::Operate => self == Role::Operator,
+            Permission::Obliterate => self == Role::ComplianceOfficer,
+        }
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Role::Reader => write!(f, "reader"),
+            Role::Operator => write!(f, "operator"),
+            Role::ComplianceOfficer => write!(f, "compliance-officer"),
+        }
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "reader" => Ok(Role::Reader),
+            "operator" => Ok(Role::Operator),
+            "compliance-officer" => Ok(Role::ComplianceOfficer),
+            other => Err(format!(
+                "unknown role '{}' (expected reader, operator or compliance-officer)",
+                other
+            )),
+        }
+    }
+}
+
+/// An API token the daemon accepts, as kept in `Config.daemon_tokens`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiToken {
+    /// Who the token was issued to; recorded with what it is used for
+    pub name: String,
+    pub role: Role,
+    /// Hex SHA-256 of the token
+    pub sha256: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl ApiToken {
+    /// Issue a token named `name` granting `role`. Returns the record to
+    /// keep and the token itself, which is shown once and not stored.
+    /// Operations done with the token are recorded against `name`, so it
+    /// must be a valid actor name.
+    pub fn issue(name: &str, role: Role) -> Result<(Self, String)> {
+        validate_actor(name)?;
+        let mut bytes = [0u8; 32];
+        rand::rng().fill_bytes(&mut bytes);
+        let token = format!("{}{}", TOKEN_PREFIX, hex::encode(bytes));
+        Ok((
+            Self {
+                name: name.to_string(),
+                role,
+                sha256: hash_token(&token),
+                created_at: chrono::Utc::now(),
+            },
+            token,
+        ))
+    }
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// The configured token matching `presented`, if any
+pub fn authenticate<'a>(tokens: &'a [ApiToken], presented: &str) -> Option<&'a ApiToken> {
+    let hash = hash_token(presented);
+    tokens
+        .iter()
+        .find(|token| bool::from(token.sha256.as_bytes().ct_eq(hash.as_bytes())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roles_and_tokens() {
+        assert!(Role::Reader.permits(Permission::Read));
+        assert!(!Role::Reader.permits(Permission::Operate));
+        assert!(Role::Operator.permits(Permission::Operate));
+        assert!(!Role::Operator.permits(Permission::Obliterate));
+        assert!(Role::ComplianceOfficer.permits(Permission::Obliterate));
+        assert!(!Role::ComplianceOfficer.permits(Permission::Operate));
+        assert_eq!(
+            "compliance-officer".parse::<Role>().unwrap(),
+            Role::ComplianceOfficer
+        );
+        assert!("admin".parse::<Role>().is_err());
+
+        let (record, token) = ApiToken::issue("ci", Role::Operator).unwrap();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert!(!serde_json::to_string(&record).unwrap().contains(&token));
+        let tokens = vec![record];
+        assert_eq!(authenticate(&tokens, &token).unwrap().name, "ci");
+        assert!(authenticate(&tokens, "jkt_wrong").is_none());
+        assert!(ApiToken::issue("two words", Role::Reader).is_err());
+    }
+}
@@ -5,15 +5,19 @@
 // JanusKey Attestation & Audit Log Module
 // Tamper-evident logging with cryptographic attestations
 
+use crate::backend::write_atomic;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::fs::{self, File, OpenOptions};
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
-use crate::keys::{KeyAlgorithm, KeyPurpose, KeyState};
+use crate::actor::Actor;
+use crate::audit_sink::{AuditEvent, AuditSinks};
+use crate::keys::{KeyAlgorithm, KeyPurpose, KeyState, ObliterationProof};
+use crate::Config;
 
 /// Audit event types
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -22,6 +26,8 @@ pub enum AuditEventType {
     StoreInitialized,
     /// Key store unlocked
     StoreUnlocked,
+    /// Wrong passphrase given to unlock the key store
+    UnlockFailed,
     /// New key generated
     KeyGenerated,
     /// Key retrieved (decrypted)
@@ -36,6 +42,14 @@ pub enum AuditEventType {
     BackupCreated,
     /// Store restored from backup
     BackupRestored,
+    /// Daemon request allowed by its token's role
+    AccessGranted,
+    /// Daemon request refused: no token, an unknown one, or a role
+    /// without the permission
+    AccessDenied,
+    /// Daemon request let change protected paths or paths outside the
+    /// root
+    GuardOverridden,
 }
 
 impl std::fmt::Display for AuditEventType {
@@ -43,6 +57,7 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             AuditEventType::StoreInitialized => write!(f, "STORE_INITIALIZED"),
             AuditEventType::StoreUnlocked => write!(f, "STORE_UNLOCKED"),
+            AuditEventType::UnlockFailed => write!(f, "UNLOCK_FAILED"),
             AuditEventType::KeyGenerated => write!(f, "KEY_GENERATED"),
             AuditEventType::KeyRetrieved => write!(f, "KEY_RETRIEVED"),
             AuditEventType::KeyRotated => write!(f, "KEY_ROTATED"),
@@ -50,10 +65,48 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             AuditEventType::KeyObliterated => write!(f, "KEY_OBLITERATED"),
             AuditEventType::BackupCreated => write!(f, "BACKUP_CREATED"),
             AuditEventType::BackupRestored => write!(f, "BACKUP_RESTORED"),
+            AuditEventType::AccessGranted => write!(f, "ACCESS_GRANTED"),
+            AuditEventType::AccessDenied => write!(f, "ACCESS_DENIED"),
+            AuditEventType::GuardOverridden => write!(f, "GUARD_OVERRIDDEN"),
         }
     }
 }
 
+impl std::str::FromStr for AuditEventType {
+    type Err = String;
+
+    /// Parse a displayed name, ignoring case and `-`/`_` differences
+    /// (`KEY_OBLITERATED`, `key-obliterated`)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const ALL: [AuditEventType; 13] = [
+            AuditEventType::StoreInitialized,
+            AuditEventType::StoreUnlocked,
+            AuditEventType::UnlockFailed,
+            AuditEventType::KeyGenerated,
+            AuditEventType::KeyRetrieved,
+            AuditEventType::KeyRotated,
+            AuditEventType::KeyRevoked,
+            AuditEventType::KeyObliterated,
+            AuditEventType::BackupCreated,
+            AuditEventType::BackupRestored,
+            AuditEventType::AccessGranted,
+            AuditEventType::AccessDenied,
+            AuditEventType::GuardOverridden,
+        ];
+        let wanted = s.replace('-', "_");
+        ALL.into_iter()
+            .find(|event| event.to_string().eq_ignore_ascii_case(&wanted))
+            .ok_or_else(|| {
+                let names: Vec<String> = ALL.iter().map(|e| e.to_string()).collect();
+                format!(
+                    "unknown event type '{}' (expected one of {})",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
 /// Key-specific event details
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KeyEventDetails {
@@ -110,28 +163,63 @@ pub fn compute_hash(&self) -> String {
     }
 }
 
+/// Hash linking the first entry of a log
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Where the log ended after its last entry, and that entry's hash, kept
+/// beside the log so appending does not re-read it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Tail {
+    /// Length of the log through the last entry's newline
+    offset: u64,
+    hash: String,
+}
+
 /// Audit log manager
 pub struct AuditLog {
     log_path: PathBuf,
+    /// Sidecar holding the log's [`Tail`]
+    tail_path: PathBuf,
     /// Secret for HMAC attestations (derived from store)
     attestation_key: Option<[u8; 32]>,
+    /// Where entries are forwarded after being logged
+    sinks: AuditSinks,
 }
 
 impl AuditLog {
-    /// Create audit log manager for a directory
+    /// Create audit log manager for a directory, forwarding to the sinks
+    /// in its config
     pub fn new(root: &Path) -> Self {
-        let log_path = root.join(".januskey").join("keys").join("audit.log");
+        let keys_dir = root.join(".januskey").join("keys");
         Self {
-            log_path,
+            log_path: keys_dir.join("audit.log"),
+            tail_path: keys_dir.join("audit.tail"),
             attestation_key: None,
+            // The local log does not depend on the config; with one that
+            // does not parse, nothing is forwarded
+            sinks: Config::load(root)
+                .map(|config| AuditSinks::from_config(&config.audit_sinks))
+                .unwrap_or_else(|e| {
+                    tracing::warn!("audit events are not forwarded: {}", e);
+                    AuditSinks::new()
+                }),
         }
     }
 
+    /// Forward entries to `sinks` instead of the configured ones
+    pub fn with_sinks(mut self, sinks: AuditSinks) -> Self {
+        self.sinks = sinks;
+        self
+    }
+
     /// Initialize audit log with attestation key
     pub fn init(&mut self, attestation_key: [u8; 32]) -> std::io::Result<()> {
         self.attestation_key = Some(attestation_key);
+        self.create()
+    }
 
-        // Create log file if it doesn't exist
+    /// Create the log file, readable only by its owner, if it doesn't exist
+    fn create(&self) -> std::io::Result<()> {
         if !self.log_path.exists() {
             if let Some(parent) = self.log_path.parent() {
                 fs::create_dir_all(parent)?;
@@ -154,38 +242,59 @@ pub fn new(root: &Path) -> Self {
         self.attestation_key = Some(key);
     }
 
-    /// Get the last entry's hash (for chain linking)
+    /// Get the last entry's hash (for chain linking).
+    ///
+    /// The tail sidecar answers when it describes the log as it is. A log
+    /// that grew past it (the sidecar write after an append was lost) is
+    /// read only from there; one shorter than it (replaced, say by a
+    /// restore) or without one is read in full.
     fn get_last_hash(&self) -> std::io::Result<String> {
-        if !self.log_path.exists() {
-            return Ok("0".repeat(64)); // Genesis hash
-        }
-
-        let file = File::open(&self.log_path)?;
-        let reader = BufReader::new(file);
-
-        let mut last_entry: Option<AuditEntry> = None;
-        for line in reader.lines() {
-            let line = line?;
-            if line.trim().is_empty() {
-                continue;
+        let len = match fs::metadata(&self.log_path) {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(GENESIS_HASH.to_string())
             }
-            if let Ok(entry) = serde_json::from_str::<AuditEntry>(&line) {
-                last_entry = Some(entry);
-            }
-        }
+            Err(e) => return Err(e),
+        };
+        let (start, known) = match self.read_tail() {
+            Some(tail) if tail.offset == len => return Ok(tail.hash),
+            Some(tail) if tail.offset < len => (tail.offset, Some(tail.hash)),
+            _ => (0, None),
+        };
 
-        Ok(last_entry
+        let (entries, _) = self.read_from(start)?;
+        Ok(entries
+            .last()
             .map(|e| e.compute_hash())
-            .unwrap_or_else(|| "0".repeat(64)))
+            .or(known)
+            .unwrap_or_else(|| GENESIS_HASH.to_string()))
     }
 
-    /// Compute HMAC-SHA256 attestation
-    fn compute_attestation(&self, data: &str, previous_hash: &str) -> String {
-        let key = self.attestation_key.unwrap_or([0u8; 32]);
+    fn read_tail(&self) -> Option<Tail> {
+        serde_json::from_slice(&fs::read(&self.tail_path).ok()?).ok()
+    }
+
+    /// Compute HMAC-SHA256 attestation. Failed unlocks are attested with
+    /// the all-zero key, since the real one is not known when the
+    /// passphrase is wrong, and so are daemon access decisions, since the
+    /// daemon never holds it; they are still bound into the chain.
+    fn compute_attestation(
+        &self,
+        event_type: AuditEventType,
+        data: &str,
+        previous_hash: &str,
+    ) -> String {
+        let key = match event_type {
+            AuditEventType::UnlockFailed
+            | AuditEventType::AccessGranted
+            | AuditEventType::AccessDenied
+            | AuditEventType::GuardOverridden => [0u8; 32],
+            _ => self.attestation_key.unwrap_or([0u8; 32]),
+        };
 
         // Simple HMAC-SHA256: H(key || data || previous_hash)
         let mut hasher = Sha256::new();
-        hasher.update(&key);
+        hasher.update(key);
         hasher.update(data.as_bytes());
         hasher.update(previous_hash.as_bytes());
         hex::encode(hasher.finalize())
@@ -211,7 +320,7 @@ pub fn log_event(
             event_type,
             actor
         );
-        let attestation = self.compute_attestation(&attestation_data, &previous_hash);
+        let attestation = self.compute_attestation(event_type, &attestation_data, &previous_hash);
 
         let entry = AuditEntry {
             event_id,
@@ -224,15 +333,35 @@ pub fn log_event(
             attestation,
         };
 
-        // Append to log file
-        let mut file = OpenOptions::new()
+        // Append to log file, as one write so the line is not interleaved
+        let file = OpenOptions::new()
             .create(true)
             .append(true)
             .open(&self.log_path)?;
 
         let json = serde_json::to_string(&entry)
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-        writeln!(file, "{}", json)?;
+        let mut writer = BufWriter::new(file);
+        writeln!(writer, "{}", json)?;
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+
+        // The entry is durable before the tail points past it, so a crash
+        // between the two leaves a tail that is behind, never ahead. A
+        // tail that fails to write is caught up by the next append.
+        file.sync_data()?;
+        let tail = Tail {
+            offset: file.metadata()?.len(),
+            hash: entry.compute_hash(),
+        };
+        let written = serde_json::to_vec(&tail)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+            .and_then(|json| write_atomic(&self.tail_path, &json));
+        if let Err(e) = written {
+            tracing::warn!(error = %e, "failed to record the audit log's tail");
+        }
+
+        // Forwarding is best effort: the local chain is the record of truth
+        let _ = self.sinks.emit(&AuditEvent::from(&entry));
 
         Ok(entry)
     }
@@ -247,6 +376,15 @@ pub fn log_store_unlock(&self) -> std::io::Result<AuditEntry> {
         self.log_event(AuditEventType::StoreUnlocked, None, None)
     }
 
+    /// Log a failed unlock, the `consecutive`th since the last success
+    pub fn log_unlock_failed(&self, consecutive: u32) -> std::io::Result<AuditEntry> {
+        self.log_event(
+            AuditEventType::UnlockFailed,
+            None,
+            Some(format!("{} consecutive failed unlock(s)", consecutive)),
+        )
+    }
+
     /// Log key generation
     pub fn log_key_generated(
         &self,
@@ -333,6 +471,45 @@ pub fn log_key_revoked(
         )
     }
 
+    /// Log key obliteration, recording the proof's commitment
+    pub fn log_key_obliterated(
+        &self,
+        proof: &ObliterationProof,
+        old_state: KeyState,
+    ) -> std::io::Result<AuditEntry> {
+        let details = KeyEventDetails {
+            key_id: proof.key_id,
+            fingerprint: proof.fingerprint.clone(),
+            algorithm: None,
+            purpose: None,
+            old_state: Some(old_state),
+            new_state: Some(KeyState::Obliterated),
+            rotated_to: None,
+            rotated_from: None,
+        };
+        let reason = format!("Obliteration proof: {}", proof.commitment);
+        self.log_event(AuditEventType::KeyObliterated, Some(details), Some(reason))
+    }
+
+    /// Log the daemon allowing or refusing a request; `details` names the
+    /// token, its role and the request. The log is created if the key
+    /// store has never been initialized.
+    pub fn log_access(&self, granted: bool, details: String) -> std::io::Result<AuditEntry> {
+        self.create()?;
+        let event_type = match granted {
+            true => AuditEventType::AccessGranted,
+            false => AuditEventType::AccessDenied,
+        };
+        self.log_event(event_type, None, Some(details))
+    }
+
+    /// Log the daemon honouring a request's guard overrides; `details`
+    /// names the overrides and the request
+    pub fn log_guard_override(&self, details: String) -> std::io::Result<AuditEntry> {
+        self.create()?;
+        self.log_event(AuditEventType::GuardOverridden, None, Some(details))
+    }
+
     /// Log backup creation
     pub fn log_backup_created(&self, path: &Path) -> std::io::Result<AuditEntry> {
         let reason = format!("Backup created at: {}", path.display());
@@ -362,6 +539,34 @@ pub fn read_all(&self) -> std::io::Result<Vec<AuditEntry>> {
         Ok(entries)
     }
 
+    /// Read the complete entries written after byte `offset`.
+    ///
+    /// Returns them with the offset to continue from, which stops before
+    /// any partly written final line. A log shorter than `offset` (for
+    /// example, replaced by a restore) is read from the start.
+    pub fn read_from(&self, offset: u64) -> std::io::Result<(Vec<AuditEntry>, u64)> {
+        let mut file = match File::open(&self.log_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+            Err(e) => return Err(e),
+        };
+        let offset = if file.metadata()?.len() < offset {
+            0
+        } else {
+            offset
+        };
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+
+        let complete = buf.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        let entries = buf[..complete]
+            .split(|&b| b == b'\n')
+            .filter_map(|line| serde_json::from_slice::<AuditEntry>(line).ok())
+            .collect();
+        Ok((entries, offset + complete as u64))
+    }
+
     /// Read last N entries
     pub fn read_last_n(&self, n: usize) -> std::io::Result<Vec<AuditEntry>> {
         let all = self.read_all()?;
@@ -382,8 +587,7 @@ pub fn verify_integrity(&self) -> std::io::Result<IntegrityReport> {
             });
         }
 
-        let genesis_hash = "0".repeat(64);
-        let mut expected_previous = genesis_hash;
+        let mut expected_previous = GENESIS_HASH.to_string();
 
         for (i, entry) in entries.iter().enumerate() {
             // Verify chain link
@@ -408,7 +612,7 @@ pub fn verify_integrity(&self) -> std::io::Result<IntegrityReport> {
                 entry.actor
             );
             let expected_attestation =
-                self.compute_attestation(&attestation_data, &entry.previous_hash);
+                self.compute_attestation(entry.event_type, &attestation_data, &entry.previous_hash);
 
             if entry.attestation != expected_attestation {
                 return Ok(IntegrityReport {
@@ -449,7 +653,7 @@ pub fn export_json(&self, output: &Path) -> std::io::Result<()> {
         let entries = self.read_all()?;
         let json = serde_json::to_string_pretty(&entries)
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-        fs::write(output, json)
+        write_atomic(output, json.as_bytes())
     }
 }
 
@@ -462,11 +666,16 @@ pub struct IntegrityReport {
     pub message: String,
 }
 
-/// Get current actor (user@hostname)
+/// Get current actor (user@hostname, then " as <actor>" if one is declared)
 fn get_actor() -> String {
-    let user = whoami::username();
+    let actor = Actor::from_env();
     let host = whoami::fallible::hostname().unwrap_or_else(|_| "unknown".to_string());
-    format!("{}@{}", user, host)
+    match actor.declared {
+        Some(declared) if declared != actor.os_user => {
+            format!("{}@{} as {}", actor.os_user, host, declared)
+        }
+        _ => format!("{}@{}", actor.os_user, host),
+    }
 }
 
 #[cfg(test)]
@@ -552,4 +761,79 @@ fn test_key_history() {
             .expect("failed to get key history");
         assert_eq!(history.len(), 3);
     }
+
+    #[test]
+    fn test_read_from_offset() {
+        let tmp = TempDir::new().expect("failed to create temp dir");
+        let mut log = AuditLog::new(tmp.path());
+        log.init([4u8; 32]).expect("failed to init audit log");
+        log.log_store_init().expect("failed to log store init");
+
+        let (entries, offset) = log.read_from(0).expect("failed to read log");
+        assert_eq!(entries.len(), 1);
+        let (entries, same) = log.read_from(offset).unwrap();
+        assert!(entries.is_empty());
+        assert_eq!(same, offset);
+
+        log.log_store_unlock().expect("failed to log store unlock");
+        let (entries, next) = log.read_from(offset).expect("failed to read log");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].event_type, AuditEventType::StoreUnlocked);
+        assert!(next > offset);
+
+        // Partly written lines wait for their newline
+        OpenOptions::new()
+            .append(true)
+            .open(&log.log_path)
+            .unwrap()
+            .write_all(b"{\"event_id\":")
+            .unwrap();
+        assert_eq!(log.read_from(next).unwrap().1, next);
+    }
+
+    #[test]
+    fn test_tail_follows_appends_and_replacements() {
+        let tmp = TempDir::new().expect("failed to create temp dir");
+        let mut log = AuditLog::new(tmp.path());
+        log.init([5u8; 32]).expect("failed to init audit log");
+        let first = log.log_store_init().expect("failed to log store init");
+        let tail = log.read_tail().expect("tail recorded");
+        assert_eq!(tail.hash, first.compute_hash());
+        assert_eq!(tail.offset, fs::metadata(&log.log_path).unwrap().len());
+
+        // A tail write lost after an append: only the new entries are read
+        log.log_store_unlock().unwrap();
+        write_atomic(&log.tail_path, &serde_json::to_vec(&tail).unwrap()).unwrap();
+        log.log_backup_created(Path::new("backup.jks")).unwrap();
+        assert!(log.verify_integrity().unwrap().valid);
+
+        // A log replaced by a shorter one is read in full
+        let saved = fs::read(&log.log_path).unwrap();
+        log.log_store_unlock().unwrap();
+        let stale = fs::read(&log.tail_path).unwrap();
+        fs::write(&log.log_path, &saved).unwrap();
+        fs::write(&log.tail_path, stale).unwrap();
+        log.log_store_unlock().unwrap();
+        let report = log.verify_integrity().unwrap();
+        assert!(report.valid);
+        assert_eq!(report.total_entries, 4);
+
+        // A missing tail falls back to reading the log
+        fs::remove_file(&log.tail_path).unwrap();
+        log.log_store_unlock().unwrap();
+        assert!(log.verify_integrity().unwrap().valid);
+    }
+
+    #[test]
+    fn test_event_type_from_str() {
+        assert_eq!(
+            "key-obliterated".parse::<AuditEventType>(),
+            Ok(AuditEventType::KeyObliterated)
+        );
+        assert_eq!(
+            "KEY_ROTATED".parse::<AuditEventType>(),
+            Ok(AuditEventType::KeyRotated)
+        );
+        assert!("rotated".parse::<AuditEventType>().is_err());
+    }
 }
@@ -0,0 +1,500 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Audit Sinks: forward audit events to SIEM systems
+// Syslog, JSON-lines files and HTTP webhooks, as JSON, CEF or ECS
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use crate::attestation::{AuditEntry, AuditEventType};
+use crate::obliteration::ObliterationRecord;
+
+/// Vendor and product fields of CEF headers
+const CEF_VENDOR: &str = "JanusKey";
+const CEF_PRODUCT: &str = "januskey";
+
+/// ECS version the `ecs` format follows
+const ECS_VERSION: &str = "8.11";
+
+/// Syslog facility for security/authorization messages (`authpriv`)
+const SYSLOG_FACILITY: u8 = 10;
+
+/// Encoding of a forwarded event
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditFormat {
+    /// The normalized [`AuditEvent`] as JSON
+    #[default]
+    Json,
+    /// ArcSight Common Event Format
+    Cef,
+    /// Elastic Common Schema JSON
+    Ecs,
+}
+
+/// Where to forward audit events, as written in `Config.audit_sinks`:
+///
+/// ```json
+/// "audit_sinks": [
+///   { "type": "syslog", "address": "siem.example:514", "format": "cef" },
+///   { "type": "json_lines", "path": "/var/log/januskey.jsonl", "format": "ecs" },
+///   { "type": "webhook", "url": "https://siem.example/hec", "format": "ecs" }
+/// ]
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuditSinkConfig {
+    /// RFC 5424 syslog over UDP to `host:port`, or to the local `/dev/log`
+    /// socket when no address is given
+    Syslog {
+        #[serde(default)]
+        address: Option<String>,
+        #[serde(default)]
+        format: AuditFormat,
+    },
+    /// One event per line, appended to a file
+    JsonLines {
+        path: PathBuf,
+        #[serde(default)]
+        format: AuditFormat,
+    },
+    /// HTTP POST of each event (feature `webhook`)
+    Webhook {
+        url: String,
+        #[serde(default)]
+        format: AuditFormat,
+    },
+}
+
+/// An audit event normalized for forwarding
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    /// Event name, e.g. `KEY_OBLITERATED` or `CONTENT_OBLITERATED`
+    pub action: String,
+    /// ECS event category: `iam` for key events, `file` for content
+    pub category: String,
+    /// CEF severity, 0 (lowest) to 10
+    pub severity: u8,
+    pub actor: String,
+    pub key_id: Option<Uuid>,
+    pub fingerprint: Option<String>,
+    pub content_hash: Option<String>,
+    pub reason: Option<String>,
+}
+
+impl From<&AuditEntry> for AuditEvent {
+    fn from(entry: &AuditEntry) -> Self {
+        let severity = match entry.event_type {
+            AuditEventType::KeyObliterated => 9,
+            AuditEventType::KeyRevoked | AuditEventType::BackupRestored => 7,
+            AuditEventType::KeyRetrieved
+            | AuditEventType::KeyRotated
+            | AuditEventType::UnlockFailed
+            | AuditEventType::AccessDenied => 5,
+            _ => 3,
+        };
+        Self {
+            id: entry.event_id.to_string(),
+            timestamp: entry.timestamp,
+            action: entry.event_type.to_string(),
+            category: "iam".to_string(),
+            severity,
+            actor: entry.actor.clone(),
+            key_id: entry.key_details.as_ref().map(|kd| kd.key_id),
+            fingerprint: entry.key_details.as_ref().map(|kd| kd.fingerprint.clone()),
+            content_hash: None,
+            reason: entry.reason.clone(),
+        }
+    }
+}
+
+impl From<&ObliterationRecord> for AuditEvent {
+    fn from(record: &ObliterationRecord) -> Self {
+        let reason = match (&record.reason, &record.legal_basis) {
+            (Some(reason), Some(basis)) => Some(format!("{} ({})", reason, basis)),
+            (reason, basis) => reason.clone().or_else(|| basis.clone()),
+        };
+        let reason = match (reason, &record.hold_override) {
+            (Some(reason), Some(held)) => {
+                Some(format!("{}; legal hold overridden, {}", reason, held))
+            }
+            (None, Some(held)) => Some(format!("legal hold overridden, {}", held)),
+            (reason, None) => reason,
+        };
+        Self {
+            id: record.id.clone(),
+            timestamp: record.timestamp,
+            action: "CONTENT_OBLITERATED".to_string(),
+            category: "file".to_string(),
+            severity: if record.hold_override.is_some() {
+                10
+            } else {
+                9
+            },
+            actor: match &record.actor {
+                Some(actor) => format!("{} as {}", record.user, actor),
+                None => record.user.clone(),
+            },
+            key_id: None,
+            fingerprint: None,
+            content_hash: Some(record.content_hash.to_string()),
+            reason,
+        }
+    }
+}
+
+impl AuditEvent {
+    /// Encode as one line (no trailing newline)
+    pub fn format(&self, format: AuditFormat) -> String {
+        match format {
+            AuditFormat::Json => serde_json::to_string(self).unwrap_or_default(),
+            AuditFormat::Cef => self.to_cef(),
+            AuditFormat::Ecs => self.to_ecs().to_string(),
+        }
+    }
+
+    fn to_cef(&self) -> String {
+        let mut extension = vec![
+            ("rt", self.timestamp.timestamp_millis().to_string()),
+            ("suser", self.actor.clone()),
+            ("externalId", self.id.clone()),
+        ];
+        if let Some(key_id) = self.key_id {
+            extension.push(("cs1Label", "keyId".to_string()));
+            extension.push(("cs1", key_id.to_string()));
+        }
+        if let Some(fingerprint) = &self.fingerprint {
+            extension.push(("cs2Label", "fingerprint".to_string()));
+            extension.push(("cs2", fingerprint.clone()));
+        }
+        if let Some(hash) = &self.content_hash {
+            extension.push(("fileHash", hash.clone()));
+        }
+        if let Some(reason) = &self.reason {
+            extension.push(("reason", reason.clone()));
+        }
+
+        let extension: Vec<String> = extension
+            .into_iter()
+            .map(|(key, value)| format!("{}={}", key, cef_extension_escape(&value)))
+            .collect();
+        format!(
+            "CEF:0|{}|{}|{}|{}|{}|{}|{}",
+            CEF_VENDOR,
+            CEF_PRODUCT,
+            env!("CARGO_PKG_VERSION"),
+            cef_header_escape(&self.action),
+            cef_header_escape(&self.action.replace('_', " ").to_lowercase()),
+            self.severity,
+            extension.join(" ")
+        )
+    }
+
+    fn to_ecs(&self) -> serde_json::Value {
+        let mut event = serde_json::json!({
+            "@timestamp": self.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+            "ecs": { "version": ECS_VERSION },
+            "event": {
+                "id": self.id,
+                "kind": "event",
+                "category": [self.category],
+                "action": self.action.to_lowercase(),
+                "severity": self.severity,
+                "provider": CEF_PRODUCT,
+            },
+            "user": { "name": self.actor },
+        });
+        if let Some(reason) = &self.reason {
+            event["event"]["reason"] = reason.clone().into();
+        }
+        if let Some(hash) = &self.content_hash {
+            event["file"] =
+                serde_json::json!({ "hash": { "sha256": hash.trim_start_matches("sha256:") } });
+        }
+        if self.key_id.is_some() || self.fingerprint.is_some() {
+            event["januskey"] = serde_json::json!({
+                "key": { "id": self.key_id, "fingerprint": self.fingerprint }
+            });
+        }
+        event
+    }
+}
+
+/// Escape a CEF header field
+fn cef_header_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+/// Escape a CEF extension value
+fn cef_extension_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\r', "\\r")
+        .replace('\n', "\\n")
+}
+
+/// A destination for forwarded audit events
+pub trait AuditSink: Send + Sync {
+    fn emit(&self, event: &AuditEvent) -> io::Result<()>;
+}
+
+/// Sends RFC 5424 messages to a syslog daemon
+struct SyslogSink {
+    address: Option<String>,
+    format: AuditFormat,
+}
+
+impl SyslogSink {
+    fn message(&self, event: &AuditEvent) -> String {
+        // Map CEF severity onto syslog severity (2 crit .. 6 info)
+        let severity = match event.severity {
+            9.. => 2,
+            7..=8 => 4,
+            5..=6 => 5,
+            _ => 6,
+        };
+        let host = whoami::fallible::hostname().unwrap_or_else(|_| "-".to_string());
+        format!(
+            "<{}>1 {} {} januskey {} {} - {}",
+            SYSLOG_FACILITY * 8 + severity,
+            event.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+            host,
+            std::process::id(),
+            event.action,
+            event.format(self.format)
+        )
+    }
+}
+
+impl AuditSink for SyslogSink {
+    fn emit(&self, event: &AuditEvent) -> io::Result<()> {
+        let message = self.message(event);
+        match &self.address {
+            Some(address) => {
+                let target = address.to_socket_addrs()?.next().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("cannot resolve {}", address),
+                    )
+                })?;
+                let bind = if target.is_ipv4() {
+                    "0.0.0.0:0"
+                } else {
+                    "[::]:0"
+                };
+                UdpSocket::bind(bind)?.send_to(message.as_bytes(), target)?;
+                Ok(())
+            }
+            #[cfg(unix)]
+            None => {
+                let socket = std::os::unix::net::UnixDatagram::unbound()?;
+                socket.send_to(message.as_bytes(), "/dev/log")?;
+                Ok(())
+            }
+            #[cfg(not(unix))]
+            None => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "syslog sinks need an address on this platform",
+            )),
+        }
+    }
+}
+
+/// Appends one encoded event per line to a file
+struct JsonLinesSink {
+    path: PathBuf,
+    format: AuditFormat,
+}
+
+impl AuditSink for JsonLinesSink {
+    fn emit(&self, event: &AuditEvent) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", event.format(self.format))
+    }
+}
+
+/// POSTs each encoded event to a URL
+#[cfg(feature = "webhook")]
+struct WebhookSink {
+    url: String,
+    format: AuditFormat,
+    client: reqwest::blocking::Client,
+}
+
+#[cfg(feature = "webhook")]
+impl AuditSink for WebhookSink {
+    fn emit(&self, event: &AuditEvent) -> io::Result<()> {
+        let content_type = match self.format {
+            AuditFormat::Cef => "text/plain",
+            AuditFormat::Json | AuditFormat::Ecs => "application/json",
+        };
+        self.client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, content_type)
+            .body(event.format(self.format))
+            .send()
+            .and_then(|response| response.error_for_status())
+            .map(|_| ())
+            .map_err(io::Error::other)
+    }
+}
+
+/// Stands in for a sink this build cannot provide, failing every event
+#[cfg(not(feature = "webhook"))]
+struct UnsupportedSink(&'static str);
+
+#[cfg(not(feature = "webhook"))]
+impl AuditSink for UnsupportedSink {
+    fn emit(&self, _event: &AuditEvent) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, self.0))
+    }
+}
+
+/// The sinks audit events are forwarded to
+#[derive(Default)]
+pub struct AuditSinks {
+    sinks: Vec<Box<dyn AuditSink>>,
+}
+
+impl AuditSinks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build the sinks described by `Config.audit_sinks`
+    pub fn from_config(configs: &[AuditSinkConfig]) -> Self {
+        let mut sinks = Self::new();
+        for config in configs {
+            match config {
+                AuditSinkConfig::Syslog { address, format } => sinks.push(Box::new(SyslogSink {
+                    address: address.clone(),
+                    format: *format,
+                })),
+                AuditSinkConfig::JsonLines { path, format } => {
+                    sinks.push(Box::new(JsonLinesSink {
+                        path: path.clone(),
+                        format: *format,
+                    }))
+                }
+                #[cfg(feature = "webhook")]
+                AuditSinkConfig::Webhook { url, format } => sinks.push(Box::new(WebhookSink {
+                    url: url.clone(),
+                    format: *format,
+                    client: reqwest::blocking::Client::new(),
+                })),
+                #[cfg(not(feature = "webhook"))]
+                AuditSinkConfig::Webhook { .. } => sinks.push(Box::new(UnsupportedSink(
+                    "webhook audit sinks require januskey built with the `webhook` feature",
+                ))),
+            }
+        }
+        sinks
+    }
+
+    /// Add a sink
+    pub fn push(&mut self, sink: Box<dyn AuditSink>) {
+        self.sinks.push(sink);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// Send an event to every sink, returning the errors of those that
+    /// failed. One failing sink does not stop the others.
+    pub fn emit(&self, event: &AuditEvent) -> Vec<io::Error> {
+        self.sinks
+            .iter()
+            .filter_map(|sink| sink.emit(event).err())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn event() -> AuditEvent {
+        AuditEvent {
+            id: "4f1c".to_string(),
+            timestamp: DateTime::parse_from_rfc3339("2026-03-01T12:00:00Z")
+                .unwrap()
+                .with_timezone(&Utc),
+            action: "KEY_OBLITERATED".to_string(),
+            category: "iam".to_string(),
+            severity: 9,
+            actor: "alice@host".to_string(),
+            key_id: Some(Uuid::nil()),
+            fingerprint: Some("0011223344556677".to_string()),
+            content_hash: None,
+            reason: Some("proof=abc|def".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_cef_and_ecs_formats() {
+        let cef = event().format(AuditFormat::Cef);
+        assert!(cef.starts_with("CEF:0|JanusKey|januskey|"));
+        assert!(cef.contains("|KEY_OBLITERATED|key obliterated|9|rt=1772366400000 "));
+        assert!(cef.contains("cs2=0011223344556677"));
+        assert!(cef.ends_with("reason=proof\\=abc|def"));
+
+        let ecs: serde_json::Value =
+            serde_json::from_str(&event().format(AuditFormat::Ecs)).unwrap();
+        assert_eq!(ecs["event"]["action"], "key_obliterated");
+        assert_eq!(ecs["event"]["category"][0], "iam");
+        assert_eq!(ecs["user"]["name"], "alice@host");
+        assert_eq!(ecs["januskey"]["key"]["fingerprint"], "0011223344556677");
+    }
+
+    #[test]
+    fn test_json_lines_and_syslog_sinks() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("siem/events.jsonl");
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sinks = AuditSinks::from_config(&[
+            AuditSinkConfig::JsonLines {
+                path: path.clone(),
+                format: AuditFormat::Json,
+            },
+            AuditSinkConfig::Syslog {
+                address: Some(receiver.local_addr().unwrap().to_string()),
+                format: AuditFormat::Cef,
+            },
+        ]);
+
+        assert!(sinks.emit(&event()).is_empty());
+        assert!(sinks.emit(&event()).is_empty());
+
+        let lines: Vec<AuditEvent> = fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].action, "KEY_OBLITERATED");
+
+        let mut buf = [0u8; 2048];
+        let len = receiver.recv(&mut buf).unwrap();
+        let message = std::str::from_utf8(&buf[..len]).unwrap();
+        assert!(message.starts_with("<82>1 2026-03-01T12:00:00.000Z "));
+        assert!(message.contains(" KEY_OBLITERATED - CEF:0|JanusKey|"));
+    }
+}
@@ -0,0 +1,469 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Storage Backends: where JanusKey history lives
+// Parses storage URIs and connects the matching FileBackend
+
+use crate::error::{JanusError, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub use reversible_core::backend::{
+    clone_file, move_file, require, supports_reflink, write_atomic, CachingBackend, Capabilities,
+    FileBackend, LocalBackend, RetryBackend, RetryPolicy,
+};
+
+#[cfg(any(feature = "s3", feature = "azure", feature = "gcs"))]
+mod object;
+#[cfg(feature = "ssh")]
+mod ssh;
+
+#[cfg(any(feature = "s3", feature = "azure", feature = "gcs"))]
+pub use object::ObjectStoreBackend;
+#[cfg(feature = "ssh")]
+pub use ssh::SshBackend;
+
+/// Default SSH port
+const SSH_PORT: u16 = 22;
+
+/// How remote backends move large files.
+///
+/// Files larger than `chunk_bytes` are transferred in chunks of that size,
+/// each retried on its own, so a dropped connection costs one chunk rather
+/// than the whole file. SSH uploads go to a partial file first and resume
+/// from its size when retried, even by a later run; object stores upload
+/// chunks as the parts of a multipart upload and download them as byte
+/// ranges, up to `parallel` at a time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransferPolicy {
+    /// Size of a transfer chunk, in bytes (object stores require at least
+    /// 5 MiB for every multipart part but the last)
+    pub chunk_bytes: u64,
+    /// Chunks in flight at once, for object stores
+    pub parallel: usize,
+    /// Cap on transfer bandwidth in bytes per second, reads and writes
+    /// together (no cap if unset)
+    pub max_bytes_per_sec: Option<u64>,
+}
+
+impl Default for TransferPolicy {
+    fn default() -> Self {
+        Self {
+            chunk_bytes: 8 * 1024 * 1024,
+            parallel: 4,
+            max_bytes_per_sec: None,
+        }
+    }
+}
+
+impl TransferPolicy {
+    /// Byte ranges of the chunks of a `len`-byte file
+    pub fn chunks(&self, len: u64) -> impl Iterator<Item = std::ops::Range<u64>> {
+        let chunk = self.chunk_bytes.max(1);
+        (0..len.div_ceil(chunk)).map(move |i| i * chunk..((i + 1) * chunk).min(len))
+    }
+}
+
+/// Paces transfers to a bandwidth cap.
+///
+/// Each call to [`Throttle::consume`] reserves time for its bytes after
+/// those reserved before it and sleeps until its slot begins, so bursts
+/// are spread out and the average rate stays under the cap.
+#[derive(Debug, Default)]
+pub struct Throttle {
+    bytes_per_sec: Option<u64>,
+    /// When the bandwidth already reserved is used up
+    next_free: Mutex<Option<Instant>>,
+}
+
+impl Throttle {
+    /// Throttle to `bytes_per_sec`, or not at all
+    pub fn new(bytes_per_sec: Option<u64>) -> Self {
+        Self {
+            bytes_per_sec: bytes_per_sec.filter(|&rate| rate > 0),
+            next_free: Mutex::new(None),
+        }
+    }
+
+    /// Wait until `bytes` may be sent or received
+    pub fn consume(&self, bytes: u64) {
+        let Some(rate) = self.bytes_per_sec else {
+            return;
+        };
+        let now = Instant::now();
+        let start = {
+            let mut next_free = self.next_free.lock().unwrap_or_else(|e| e.into_inner());
+            let start = next_free.filter(|&t| t > now).unwrap_or(now);
+            *next_free = Some(start + Duration::from_secs_f64(bytes as f64 / rate as f64));
+            start
+        };
+        if start > now {
+            std::thread::sleep(start - now);
+        }
+    }
+}
+
+/// A location of its own for the content store (`Config.content_storage`),
+/// such as an S3 bucket, so history of large files does not need local
+/// disk. The operation and transaction logs stay where `storage_path` puts
+/// them; recently used blobs are cached under `.januskey/content-cache`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentStorage {
+    /// Path or backend URI holding the blobs, as for `storage_path`
+    pub storage: String,
+    /// Most bytes of blobs to cache locally
+    #[serde(default = "default_cache_bytes")]
+    pub cache_bytes: u64,
+}
+
+fn default_cache_bytes() -> u64 {
+    256 * 1024 * 1024
+}
+
+impl ContentStorage {
+    /// Connect to the backend, returning it and the content directory on
+    /// it. Remote backends are retried per `retry` and cached; a relative
+    /// local path is relative to `root`, and not cached.
+    pub fn connect(
+        &self,
+        root: &Path,
+        transfer: &TransferPolicy,
+        retry: &RetryPolicy,
+    ) -> Result<(Arc<dyn FileBackend>, PathBuf)> {
+        let uri = StorageUri::parse(&self.storage)?;
+        if let StorageUri::Local(path) = &uri {
+            return Ok((Arc::new(LocalBackend), root.join(path).join("content")));
+        }
+        let backend = retry.run(|| uri.connect_with(transfer, retry))?;
+        let backend = Arc::new(RetryBackend::new(backend, retry.clone()));
+        Ok((
+            Arc::new(CachingBackend::new(
+                backend,
+                root.join(".januskey").join("content-cache"),
+                self.cache_bytes,
+            )),
+            uri.root().join("content"),
+        ))
+    }
+}
+
+/// Location of a JanusKey store, parsed from `Config.storage_path`.
+///
+/// Accepted forms:
+/// - `/path/to/store` or `file:///path/to/store` — local (or mounted) directory
+/// - `ssh://[user@]host[:port]/path/to/store` — SFTP (feature `ssh`)
+/// - `s3://bucket[/prefix]` — S3-compatible object storage (feature `s3`)
+/// - `azure://container[/prefix]` — Azure Blob Storage (feature `azure`)
+/// - `gs://bucket[/prefix]` — Google Cloud Storage (feature `gcs`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageUri {
+    /// Directory on the local filesystem
+    Local(PathBuf),
+    /// Directory on an SSH server
+    Ssh {
+        user: Option<String>,
+        host: String,
+        port: u16,
+        path: PathBuf,
+    },
+    /// Key prefix in an S3 bucket
+    S3 { bucket: String, prefix: PathBuf },
+    /// Blob name prefix in an Azure Blob Storage container
+    Azure { container: String, prefix: PathBuf },
+    /// Object name prefix in a Google Cloud Storage bucket
+    Gcs { bucket: String, prefix: PathBuf },
+}
+
+impl StorageUri {
+    /// Parse a storage path or URI
+    pub fn parse(uri: &str) -> Result<Self> {
+        let invalid =
+            |why: &str| JanusError::Backend(format!("invalid storage URI '{}': {}", uri, why));
+
+        let Some((scheme, rest)) = uri.split_once("://") else {
+            return Ok(Self::Local(PathBuf::from(uri)));
+        };
+
+        match scheme {
+            "file" => Ok(Self::Local(PathBuf::from(rest))),
+            "ssh" => {
+                let (authority, path) = rest
+                    .split_once('/')
+                    .ok_or_else(|| invalid("missing remote path"))?;
+                let (user, host_port) = match authority.rsplit_once('@') {
+                    Some((user, host)) => (Some(user.to_string()), host),
+                    None => (None, authority),
+                };
+                let (host, port) = match host_port.rsplit_once(':') {
+                    Some((host, port)) => (
+                        host,
+                        port.parse().map_err(|_| invalid("port is not a number"))?,
+                    ),
+                    None => (host_port, SSH_PORT),
+                };
+                if host.is_empty() {
+                    return Err(invalid("missing host"));
+                }
+                Ok(Self::Ssh {
+                    user,
+                    host: host.to_string(),
+                    port,
+                    path: PathBuf::from(format!("/{}", path)),
+                })
+            }
+            "s3" | "azure" | "gs" => {
+                let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+                if bucket.is_empty() {
+                    return Err(invalid(if scheme == "azure" {
+                        "missing container"
+                    } else {
+                        "missing bucket"
+                    }));
+                }
+                let (bucket, prefix) =
+                    (bucket.to_string(), PathBuf::from(prefix.trim_matches('/')));
+                Ok(match scheme {
+                    "s3" => Self::S3 { bucket, prefix },
+                    "azure" => Self::Azure {
+                        container: bucket,
+                        prefix,
+                    },
+                    _ => Self::Gcs { bucket, prefix },
+                })
+            }
+            other => Err(invalid(&format!("unsupported scheme '{}'", other))),
+        }
+    }
+
+    /// Root of the store within the backend's namespace
+    pub fn root(&self) -> PathBuf {
+        match self {
+            Self::Local(path) => path.clone(),
+            Self::Ssh { path, .. } => path.clone(),
+            Self::S3 { prefix, .. } | Self::Azure { prefix, .. } | Self::Gcs { prefix, .. } => {
+                prefix.clone()
+            }
+        }
+    }
+
+    /// Connect to the backend for this location
+    pub fn connect(&self) -> Result<Arc<dyn FileBackend>> {
+        self.connect_with(&TransferPolicy::default(), &RetryPolicy::default())
+    }
+
+    /// Connect to the backend for this location, transferring large files
+    /// per `transfer` and retrying each chunk per `retry`
+    #[cfg_attr(
+        not(any(feature = "ssh", feature = "s3", feature = "azure", feature = "gcs")),
+        allow(unused_variables)
+    )]
+    pub fn connect_with(
+        &self,
+        transfer: &TransferPolicy,
+        retry: &RetryPolicy,
+    ) -> Result<Arc<dyn FileBackend>> {
+        match self {
+            Self::Local(_) => Ok(Arc::new(LocalBackend)),
+            #[cfg(feature = "ssh")]
+            Self::Ssh {
+                user, host, port, ..
+            } => Ok(Arc::new(
+                SshBackend::connect(user.as_deref(), host, *port)?
+                    .with_transfer(transfer.clone(), retry.clone()),
+            )),
+            #[cfg(feature = "s3")]
+            Self::S3 { bucket, .. } => Ok(Arc::new(
+                ObjectStoreBackend::s3(bucket)?.with_transfer(transfer.clone(), retry.clone()),
+            )),
+            #[cfg(feature = "azure")]
+            Self::Azure { container, .. } => Ok(Arc::new(
+                ObjectStoreBackend::azure(container)?
+                    .with_transfer(transfer.clone(), retry.clone()),
+            )),
+            #[cfg(feature = "gcs")]
+            Self::Gcs { bucket, .. } => Ok(Arc::new(
+                ObjectStoreBackend::gcs(bucket)?.with_transfer(transfer.clone(), retry.clone()),
+            )),
+            #[allow(unreachable_patterns)]
+            _ => Err(JanusError::Backend(format!(
+                "{} storage requires januskey built with the `{}` feature",
+                self,
+                self.feature()
+            ))),
+        }
+    }
+
+    /// Cargo feature that provides this backend
+    fn feature(&self) -> &'static str {
+        match self {
+            Self::Local(_) => "default",
+            Self::Ssh { .. } => "ssh",
+            Self::S3 { .. } => "s3",
+            Self::Azure { .. } => "azure",
+            Self::Gcs { .. } => "gcs",
+        }
+    }
+}
+
+impl fmt::Display for StorageUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Local(path) => write!(f, "{}", path.display()),
+            Self::Ssh {
+                user,
+                host,
+                port,
+                path,
+            } => {
+                write!(f, "ssh://")?;
+                if let Some(user) = user {
+                    write!(f, "{}@", user)?;
+                }
+                write!(f, "{}", host)?;
+                if *port != SSH_PORT {
+                    write!(f, ":{}", port)?;
+                }
+                write!(f, "{}", path.display())
+            }
+            Self::S3 { bucket, prefix } => write_object_uri(f, "s3", bucket, prefix),
+            Self::Azure { container, prefix } => write_object_uri(f, "azure", container, prefix),
+            Self::Gcs { bucket, prefix } => write_object_uri(f, "gs", bucket, prefix),
+        }
+    }
+}
+
+fn write_object_uri(
+    f: &mut fmt::Formatter<'_>,
+    scheme: &str,
+    bucket: &str,
+    prefix: &std::path::Path,
+) -> fmt::Result {
+    if prefix.as_os_str().is_empty() {
+        write!(f, "{}://{}", scheme, bucket)
+    } else {
+        write!(f, "{}://{}/{}", scheme, bucket, prefix.display())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_local() {
+        assert_eq!(
+            StorageUri::parse("/srv/jk").unwrap(),
+            StorageUri::Local(PathBuf::from("/srv/jk"))
+        );
+        assert_eq!(
+            StorageUri::parse("file:///srv/jk").unwrap(),
+            StorageUri::Local(PathBuf::from("/srv/jk"))
+        );
+    }
+
+    #[test]
+    fn test_parse_ssh() {
+        let uri = StorageUri::parse("ssh://alice@backup.example:2222/srv/jk/team").unwrap();
+        assert_eq!(
+            uri,
+            StorageUri::Ssh {
+                user: Some("alice".to_string()),
+                host: "backup.example".to_string(),
+                port: 2222,
+                path: PathBuf::from("/srv/jk/team"),
+            }
+        );
+        assert_eq!(
+            uri.to_string(),
+            "ssh://alice@backup.example:2222/srv/jk/team"
+        );
+
+        let uri = StorageUri::parse("ssh://backup.example/jk").unwrap();
+        assert_eq!(uri.root(), PathBuf::from("/jk"));
+        assert!(StorageUri::parse("ssh://backup.example").is_err());
+        assert!(StorageUri::parse("ssh://host:port/jk").is_err());
+    }
+
+    #[test]
+    fn test_parse_s3() {
+        let uri = StorageUri::parse("s3://team-history/projects/web/").unwrap();
+        assert_eq!(
+            uri,
+            StorageUri::S3 {
+                bucket: "team-history".to_string(),
+                prefix: PathBuf::from("projects/web"),
+            }
+        );
+        assert_eq!(uri.to_string(), "s3://team-history/projects/web");
+        assert!(StorageUri::parse("s3://").is_err());
+        assert!(StorageUri::parse("ftp://host/path").is_err());
+    }
+
+    #[test]
+    fn test_parse_azure_and_gcs() {
+        let uri = StorageUri::parse("azure://history/team").unwrap();
+        assert_eq!(
+            uri,
+            StorageUri::Azure {
+                container: "history".to_string(),
+                prefix: PathBuf::from("team"),
+            }
+        );
+        assert_eq!(uri.to_string(), "azure://history/team");
+
+        let uri = StorageUri::parse("gs://team-history").unwrap();
+        assert_eq!(
+            uri,
+            StorageUri::Gcs {
+                bucket: "team-history".to_string(),
+                prefix: PathBuf::new(),
+            }
+        );
+        assert_eq!(uri.to_string(), "gs://team-history");
+        assert_eq!(uri.root(), PathBuf::new());
+        assert!(StorageUri::parse("azure://").is_err());
+    }
+
+    #[test]
+    fn test_transfer_chunks() {
+        let policy = TransferPolicy {
+            chunk_bytes: 4,
+            ..TransferPolicy::default()
+        };
+        assert_eq!(policy.chunks(10).collect::<Vec<_>>(), [0..4, 4..8, 8..10]);
+        assert_eq!(policy.chunks(8).count(), 2);
+        assert_eq!(policy.chunks(0).count(), 0);
+    }
+
+    #[test]
+    fn test_throttle_paces_to_rate() {
+        let throttle = Throttle::new(Some(10_000));
+        let start = Instant::now();
+        for _ in 0..4 {
+            throttle.consume(1_000);
+        }
+        // The first chunk goes at once; the other three wait 0.1s each
+        assert!(start.elapsed() >= Duration::from_millis(300));
+
+        let unthrottled = Throttle::new(None);
+        let start = Instant::now();
+        unthrottled.consume(u64::MAX);
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
+    #[cfg(not(feature = "ssh"))]
+    #[test]
+    fn test_connect_without_feature() {
+        let err = StorageUri::parse("ssh://host/jk")
+            .unwrap()
+            .connect()
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("`ssh` feature"));
+    }
+}
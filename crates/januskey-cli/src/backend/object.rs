@@ -0,0 +1,292 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Object Storage Backend: JanusKey store in S3, Azure Blob or GCS
+// One backend over the `object_store` crate; only the constructors differ
+
+use super::{Capabilities, FileBackend, RetryPolicy, Throttle, TransferPolicy};
+use crate::error::{JanusError, Result};
+use futures::TryStreamExt;
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectStore, PutPayload, WriteMultipart};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+
+/// Backend storing files as objects, keyed by their path.
+///
+/// Object stores have no directories, so `create_dir_all` is a no-op and
+/// `list_files` lists by key prefix. Calls block on a private runtime.
+///
+/// Files larger than a [`TransferPolicy`] chunk are uploaded as multipart
+/// uploads and downloaded as byte ranges, several chunks at a time. A
+/// failed download retries only the chunks still missing.
+pub struct ObjectStoreBackend {
+    /// Backend name for messages
+    name: &'static str,
+    /// Underlying object store
+    store: Arc<dyn ObjectStore>,
+    /// Runtime driving the async object store client
+    runtime: Runtime,
+    /// How large files are chunked
+    transfer: TransferPolicy,
+    /// How each group of downloaded chunks is retried
+    retry: RetryPolicy,
+    /// Bandwidth cap shared by every transfer
+    throttle: Throttle,
+}
+
+impl ObjectStoreBackend {
+    /// Wrap an object store
+    pub fn new(name: &'static str, store: Arc<dyn ObjectStore>) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        Ok(Self {
+            name,
+            store,
+            runtime,
+            transfer: TransferPolicy::default(),
+            retry: RetryPolicy::default(),
+            throttle: Throttle::default(),
+        })
+    }
+
+    /// Builder: chunk large transfers per `transfer`, retrying downloaded
+    /// chunks per `retry`
+    pub fn with_transfer(mut self, transfer: TransferPolicy, retry: RetryPolicy) -> Self {
+        self.throttle = Throttle::new(transfer.max_bytes_per_sec);
+        self.transfer = transfer;
+        self.retry = retry;
+        self
+    }
+
+    /// Upload `content` as a multipart upload of chunk-sized parts, up to
+    /// `parallel` in flight; the upload is aborted if any part fails
+    fn write_multipart(&self, path: &Path, content: &[u8]) -> Result<()> {
+        let location = key(path);
+        let upload = self.block_on(self.store.put_multipart(&location))?;
+        let mut writer =
+            WriteMultipart::new_with_chunk_size(upload, self.transfer.chunk_bytes as usize);
+        let result = self.runtime.block_on(async {
+            for range in self.transfer.chunks(content.len() as u64) {
+                writer
+                    .wait_for_capacity(self.transfer.parallel.max(1))
+                    .await?;
+                self.throttle.consume(range.end - range.start);
+                writer.write(&content[range.start as usize..range.end as usize]);
+            }
+            Ok::<_, object_store::Error>(())
+        });
+        match result {
+            Ok(()) => {
+                self.block_on(writer.finish())?;
+                Ok(())
+            }
+            Err(e) => {
+                let _ = self.runtime.block_on(writer.abort());
+                Err(object_err(e))
+            }
+        }
+    }
+
+    /// Download a `size`-byte object as byte ranges, `parallel` at a time
+    fn read_chunked(&self, path: &Path, size: u64) -> Result<Vec<u8>> {
+        let location = key(path);
+        let ranges: Vec<_> = self.transfer.chunks(size).collect();
+        let mut content = Vec::with_capacity(size as usize);
+        for group in ranges.chunks(self.transfer.parallel.max(1)) {
+            self.throttle
+                .consume(group.iter().map(|r| r.end - r.start).sum());
+            let parts = self.retry.run(|| {
+                self.block_on(futures::future::try_join_all(group.iter().map(|range| {
+                    self.store
+                        .get_range(&location, range.start as usize..range.end as usize)
+                })))
+            })?;
+            for part in parts {
+                content.extend_from_slice(&part);
+            }
+        }
+        Ok(content)
+    }
+
+    /// Connect to an S3 bucket, configured from the standard `AWS_*`
+    /// environment variables (credentials, region, endpoint)
+    #[cfg(feature = "s3")]
+    pub fn s3(bucket: &str) -> Result<Self> {
+        let store = object_store::aws::AmazonS3Builder::from_env()
+            .with_bucket_name(bucket)
+            .build()
+            .map_err(object_err)?;
+        Self::new("s3", Arc::new(store))
+    }
+
+    /// Connect to an Azure Blob Storage container, configured from the
+    /// `AZURE_*` environment variables (account, access key or service
+    /// principal)
+    #[cfg(feature = "azure")]
+    pub fn azure(container: &str) -> Result<Self> {
+        let store = object_store::azure::MicrosoftAzureBuilder::from_env()
+            .with_container_name(container)
+            .build()
+            .map_err(object_err)?;
+        Self::new("azure", Arc::new(store))
+    }
+
+    /// Connect to a Google Cloud Storage bucket, configured from the
+    /// `GOOGLE_*` environment variables (service account credentials)
+    #[cfg(feature = "gcs")]
+    pub fn gcs(bucket: &str) -> Result<Self> {
+        let store = object_store::gcp::GoogleCloudStorageBuilder::from_env()
+            .with_bucket_name(bucket)
+            .build()
+            .map_err(object_err)?;
+        Self::new("gcs", Arc::new(store))
+    }
+
+    fn block_on<T>(&self, f: impl Future<Output = object_store::Result<T>>) -> Result<T> {
+        self.runtime.block_on(f).map_err(object_err)
+    }
+}
+
+/// Map a filesystem-style path to an object key
+fn key(path: &Path) -> ObjectPath {
+    ObjectPath::from(path.to_string_lossy().trim_start_matches('/'))
+}
+
+impl FileBackend for ObjectStoreBackend {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        // Objects are immutable and carry no permissions; renames copy and
+        // delete
+        Capabilities {
+            range_reads: true,
+            server_side_copy: true,
+            ..Capabilities::NONE
+        }
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        let location = key(path);
+        let chunk_bytes = self.transfer.chunk_bytes as usize;
+        // Small objects are read whole; larger ones report their size
+        let whole = self.runtime.block_on(async {
+            let result = self.store.get(&location).await?;
+            if result.meta.size > chunk_bytes {
+                return Ok(Err(result.meta.size));
+            }
+            result.bytes().await.map(Ok)
+        });
+        match whole {
+            Ok(Ok(bytes)) => {
+                self.throttle.consume(bytes.len() as u64);
+                Ok(bytes.to_vec())
+            }
+            Ok(Err(size)) => self.read_chunked(path, size as u64),
+            Err(object_store::Error::NotFound { .. }) => {
+                Err(JanusError::FileNotFound(path.display().to_string()))
+            }
+            Err(e) => Err(object_err(e)),
+        }
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+        if content.len() as u64 > self.transfer.chunk_bytes {
+            return self.write_multipart(path, content);
+        }
+        self.throttle.consume(content.len() as u64);
+        let payload = PutPayload::from(content.to_vec());
+        self.block_on(self.store.put(&key(path), payload))?;
+        Ok(())
+    }
+
+    fn read_range(&self, path: &Path, offset: u64, len: u64) -> Result<Vec<u8>> {
+        self.throttle.consume(len);
+        let range = offset as usize..(offset + len) as usize;
+        let bytes = self.block_on(self.store.get_range(&key(path), range))?;
+        Ok(bytes.to_vec())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.block_on(self.store.head(&key(path))).is_ok()
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        self.block_on(self.store.delete(&key(path)))
+    }
+
+    fn create_dir_all(&self, _path: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.block_on(self.store.rename(&key(from), &key(to)))
+    }
+
+    fn list_files(&self, dir: &Path) -> Result<Vec<(PathBuf, u64)>> {
+        let prefix = key(dir);
+        let objects: Vec<_> = self.block_on(self.store.list(Some(&prefix)).try_collect())?;
+        Ok(objects
+            .into_iter()
+            .map(|meta| (PathBuf::from(meta.location.as_ref()), meta.size as u64))
+            .collect())
+    }
+}
+
+fn object_err(e: object_store::Error) -> JanusError {
+    JanusError::Backend(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content_store::ContentStore;
+    use object_store::memory::InMemory;
+
+    #[test]
+    fn test_content_store_on_object_backend() {
+        let backend: Arc<dyn FileBackend> =
+            Arc::new(ObjectStoreBackend::new("memory", Arc::new(InMemory::new())).unwrap());
+        let store =
+            ContentStore::new_with_backend(backend.clone(), PathBuf::from("team/content"), true)
+                .unwrap();
+
+        let hash = store.store(b"shared history").unwrap();
+        assert!(store.exists(&hash));
+        assert_eq!(store.retrieve(&hash).unwrap(), b"shared history");
+        assert_eq!(store.count().unwrap(), 1);
+
+        store.delete(&hash).unwrap();
+        assert!(!store.exists(&hash));
+    }
+
+    #[test]
+    fn test_chunked_transfer() {
+        let backend = ObjectStoreBackend::new("memory", Arc::new(InMemory::new()))
+            .unwrap()
+            .with_transfer(
+                TransferPolicy {
+                    chunk_bytes: 1024,
+                    parallel: 2,
+                    max_bytes_per_sec: None,
+                },
+                RetryPolicy::none(),
+            );
+        let content: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let path = Path::new("team/content/large");
+
+        backend.write(path, &content).unwrap();
+        assert_eq!(backend.read(path).unwrap(), content);
+        assert_eq!(
+            backend.read_range(path, 5000, 10).unwrap(),
+            &content[5000..5010]
+        );
+        assert_eq!(backend.list_files(Path::new("team")).unwrap()[0].1, 10_000);
+    }
+}
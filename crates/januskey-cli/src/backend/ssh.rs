@@ -0,0 +1,355 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// SSH Backend: JanusKey store on a remote host over SFTP
+
+use super::{Capabilities, FileBackend, RetryPolicy, Throttle, TransferPolicy};
+use crate::error::{JanusError, Result};
+use sha2::{Digest, Sha256};
+use ssh2::{CheckResult, KnownHostFileKind, OpenFlags, OpenType, RenameFlags, Session, Sftp};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Private keys tried (in order) when the SSH agent cannot authenticate
+const IDENTITY_FILES: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// Seconds between keepalive messages on an idle session
+const KEEPALIVE_INTERVAL: u32 = 30;
+
+/// Backend storing files on an SSH server via SFTP.
+///
+/// The host key must already be present in `~/.ssh/known_hosts`.
+/// Authentication uses the SSH agent, then the default identity files.
+/// One session is reused for every call and kept alive while idle; if a
+/// call fails and the session no longer answers, the next call reconnects.
+///
+/// Files larger than a [`TransferPolicy`] chunk move a chunk at a time,
+/// each chunk retried on its own. Uploads are written to a partial file
+/// named for their content and renamed into place once complete, so an
+/// interrupted upload of the same content resumes where it stopped.
+pub struct SshBackend {
+    user: String,
+    host: String,
+    port: u16,
+    /// SFTP channel (the session is kept alive alongside it), `None` once
+    /// the connection has dropped
+    sftp: Mutex<Option<(Session, Sftp)>>,
+    /// How large files are chunked
+    transfer: TransferPolicy,
+    /// How each chunk is retried
+    retry: RetryPolicy,
+    /// Bandwidth cap shared by every transfer
+    throttle: Throttle,
+}
+
+impl SshBackend {
+    /// Connect and authenticate to `host:port`
+    pub fn connect(user: Option<&str>, host: &str, port: u16) -> Result<Self> {
+        let user = user.map(str::to_string).unwrap_or_else(whoami::username);
+        let connection = open_session(&user, host, port)?;
+        Ok(Self {
+            user,
+            host: host.to_string(),
+            port,
+            sftp: Mutex::new(Some(connection)),
+            transfer: TransferPolicy::default(),
+            retry: RetryPolicy::default(),
+            throttle: Throttle::default(),
+        })
+    }
+
+    /// Builder: chunk large transfers per `transfer`, retrying each chunk
+    /// per `retry`
+    pub fn with_transfer(mut self, transfer: TransferPolicy, retry: RetryPolicy) -> Self {
+        self.throttle = Throttle::new(transfer.max_bytes_per_sec);
+        self.transfer = transfer;
+        self.retry = retry;
+        self
+    }
+
+    /// Upload `content` a chunk at a time to a partial file, resuming after
+    /// whatever an earlier attempt left there, then move it to `path`
+    fn write_chunked(&self, path: &Path, content: &[u8]) -> Result<()> {
+        let partial = partial_path(path, content);
+        let len = content.len() as u64;
+        let uploaded =
+            self.with_sftp(|sftp| Ok(sftp.stat(&partial).ok().and_then(|stat| stat.size)))?;
+        let resume_from = match uploaded {
+            // Named for its content, so whatever it holds is a prefix of it
+            Some(size) if size <= len => size,
+            Some(_) => {
+                self.remove_file(&partial)?;
+                0
+            }
+            None => 0,
+        };
+        if resume_from > 0 {
+            tracing::info!(path = %path.display(), resume_from, len, "resuming upload");
+        }
+
+        for range in self.transfer.chunks(len).filter(|r| r.end > resume_from) {
+            self.throttle
+                .consume(range.end - range.start.max(resume_from));
+            self.retry.run(|| {
+                self.with_sftp(|sftp| {
+                    let flags = OpenFlags::WRITE | OpenFlags::CREATE;
+                    let mut file = sftp
+                        .open_mode(&partial, flags, 0o644, OpenType::File)
+                        .map_err(ssh_err)?;
+                    // A retried chunk carries on from what reached the server
+                    let offset = file
+                        .stat()
+                        .map_err(ssh_err)?
+                        .size
+                        .unwrap_or(0)
+                        .min(range.end);
+                    file.seek(SeekFrom::Start(offset))?;
+                    file.write_all(&content[offset as usize..range.end as usize])?;
+                    Ok(())
+                })
+            })?;
+        }
+        self.rename(&partial, path)
+    }
+
+    /// Run `f` with exclusive access to the SFTP channel, reconnecting
+    /// first if the previous session dropped
+    fn with_sftp<T>(&self, f: impl FnOnce(&Sftp) -> Result<T>) -> Result<T> {
+        let mut guard = self
+            .sftp
+            .lock()
+            .map_err(|_| JanusError::Backend("ssh session lock poisoned".to_string()))?;
+        let connection = match guard.take() {
+            Some(connection) => connection,
+            None => open_session(&self.user, &self.host, self.port)?,
+        };
+
+        let result = f(&connection.1);
+        // Keep the session unless the failure was the connection itself
+        if result.is_ok() || connection.0.keepalive_send().is_ok() {
+            *guard = Some(connection);
+        }
+        result
+    }
+}
+
+/// Open an authenticated session and its SFTP channel
+fn open_session(user: &str, host: &str, port: u16) -> Result<(Session, Sftp)> {
+    let tcp = TcpStream::connect((host, port))?;
+    let mut session = Session::new().map_err(ssh_err)?;
+    session.set_tcp_stream(tcp);
+    session.handshake().map_err(ssh_err)?;
+
+    verify_host_key(&session, host, port)?;
+    authenticate(&session, user)?;
+    if !session.authenticated() {
+        return Err(JanusError::PermissionDenied(format!(
+            "ssh authentication failed for {}@{}",
+            user, host
+        )));
+    }
+    session.set_keepalive(true, KEEPALIVE_INTERVAL);
+
+    let sftp = session.sftp().map_err(ssh_err)?;
+    Ok((session, sftp))
+}
+
+impl FileBackend for SshBackend {
+    fn name(&self) -> &'static str {
+        "ssh"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        // SFTP has no truncate or server-side copy
+        Capabilities {
+            append: true,
+            atomic_rename: true,
+            range_reads: true,
+            permissions: true,
+            ..Capabilities::NONE
+        }
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        let chunk_bytes = self.transfer.chunk_bytes;
+        // Small files are read whole; larger ones report their size
+        let whole = self.with_sftp(|sftp| {
+            let mut file = sftp
+                .open(path)
+                .map_err(|_| JanusError::FileNotFound(path.display().to_string()))?;
+            let size = file.stat().map_err(ssh_err)?.size.unwrap_or(0);
+            if size > chunk_bytes {
+                return Ok(Err(size));
+            }
+            let mut content = Vec::new();
+            file.read_to_end(&mut content)?;
+            Ok(Ok(content))
+        })?;
+        let size = match whole {
+            Ok(content) => {
+                self.throttle.consume(content.len() as u64);
+                return Ok(content);
+            }
+            Err(size) => size,
+        };
+
+        let mut content = Vec::with_capacity(size as usize);
+        for range in self.transfer.chunks(size) {
+            self.throttle.consume(range.end - range.start);
+            let chunk = self
+                .retry
+                .run(|| self.read_range(path, range.start, range.end - range.start))?;
+            content.extend_from_slice(&chunk);
+        }
+        Ok(content)
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            self.create_dir_all(parent)?;
+        }
+        if content.len() as u64 > self.transfer.chunk_bytes {
+            return self.write_chunked(path, content);
+        }
+        self.throttle.consume(content.len() as u64);
+        self.with_sftp(|sftp| {
+            let mut file = sftp.create(path).map_err(ssh_err)?;
+            file.write_all(content)?;
+            Ok(())
+        })
+    }
+
+    fn read_range(&self, path: &Path, offset: u64, len: u64) -> Result<Vec<u8>> {
+        self.with_sftp(|sftp| {
+            let mut file = sftp
+                .open(path)
+                .map_err(|_| JanusError::FileNotFound(path.display().to_string()))?;
+            file.seek(SeekFrom::Start(offset))?;
+            let mut content = vec![0; len as usize];
+            file.read_exact(&mut content)?;
+            Ok(content)
+        })
+    }
+
+    fn append(&self, path: &Path, content: &[u8]) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            self.create_dir_all(parent)?;
+        }
+        self.with_sftp(|sftp| {
+            let flags = OpenFlags::WRITE | OpenFlags::APPEND | OpenFlags::CREATE;
+            let mut file = sftp
+                .open_mode(path, flags, 0o644, OpenType::File)
+                .map_err(ssh_err)?;
+            file.write_all(content)?;
+            Ok(())
+        })
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.with_sftp(|sftp| Ok(sftp.stat(path).is_ok()))
+            .unwrap_or(false)
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        self.with_sftp(|sftp| sftp.unlink(path).map_err(ssh_err))
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        self.with_sftp(|sftp| {
+            let mut ancestors: Vec<&Path> = path.ancestors().collect();
+            ancestors.reverse();
+            for dir in ancestors {
+                if dir.as_os_str().is_empty() || sftp.stat(dir).is_ok() {
+                    continue;
+                }
+                sftp.mkdir(dir, 0o755).map_err(ssh_err)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        let flags = RenameFlags::OVERWRITE | RenameFlags::ATOMIC | RenameFlags::NATIVE;
+        self.with_sftp(|sftp| sftp.rename(from, to, Some(flags)).map_err(ssh_err))
+    }
+
+    fn list_files(&self, dir: &Path) -> Result<Vec<(PathBuf, u64)>> {
+        self.with_sftp(|sftp| {
+            let mut files = Vec::new();
+            let mut pending = vec![dir.to_path_buf()];
+            while let Some(dir) = pending.pop() {
+                let Ok(entries) = sftp.readdir(&dir) else {
+                    continue;
+                };
+                for (path, stat) in entries {
+                    if stat.is_dir() {
+                        pending.push(path);
+                    } else if stat.is_file() {
+                        files.push((path, stat.size.unwrap_or(0)));
+                    }
+                }
+            }
+            Ok(files)
+        })
+    }
+}
+
+/// Refuse hosts whose key is not in `~/.ssh/known_hosts`
+fn verify_host_key(session: &Session, host: &str, port: u16) -> Result<()> {
+    let mut known_hosts = session.known_hosts().map_err(ssh_err)?;
+    if let Some(home) = dirs::home_dir() {
+        let _ = known_hosts.read_file(
+            &home.join(".ssh").join("known_hosts"),
+            KnownHostFileKind::OpenSSH,
+        );
+    }
+
+    let (key, _) = session
+        .host_key()
+        .ok_or_else(|| JanusError::Backend(format!("{} sent no host key", host)))?;
+
+    match known_hosts.check_port(host, port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::Mismatch => Err(JanusError::PermissionDenied(format!(
+            "host key for {} does not match ~/.ssh/known_hosts",
+            host
+        ))),
+        CheckResult::NotFound | CheckResult::Failure => Err(JanusError::PermissionDenied(format!(
+            "host key for {} not found in ~/.ssh/known_hosts",
+            host
+        ))),
+    }
+}
+
+/// Try the SSH agent, then the default identity files
+fn authenticate(session: &Session, user: &str) -> Result<()> {
+    if session.userauth_agent(user).is_ok() {
+        return Ok(());
+    }
+
+    let Some(ssh_dir) = dirs::home_dir().map(|home| home.join(".ssh")) else {
+        return Ok(());
+    };
+    for name in IDENTITY_FILES {
+        let key = ssh_dir.join(name);
+        if key.exists() && session.userauth_pubkey_file(user, None, &key, None).is_ok() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Partial file an upload of `content` to `path` goes to, named for the
+/// content so that only an upload of the same bytes resumes it
+fn partial_path(path: &Path, content: &[u8]) -> PathBuf {
+    let digest = hex::encode(&Sha256::digest(content)[..8]);
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.{}.partial", name, digest))
+}
+
+fn ssh_err(e: ssh2::Error) -> JanusError {
+    JanusError::Backend(format!("ssh: {}", e))
+}
@@ -0,0 +1,253 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// JanusKey Completion: `jk completions` scripts and the values they ask
+// `jk __complete` for (operation, transaction and key IDs, tracked paths)
+
+use anyhow::Result;
+use clap::{Arg, CommandFactory};
+use clap_complete::Shell;
+use januskey::keys::KeyManager;
+use januskey::JanusKey;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use crate::Cli;
+
+/// What the word being completed stands for, when it names something in
+/// the store rather than a file or a fixed choice
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    Operation,
+    Transaction,
+    Key,
+    /// A path history has recorded, which may no longer exist
+    Path,
+}
+
+impl ValueKind {
+    /// The kind of values `arg` takes, by its value name
+    fn of(arg: &Arg) -> Option<Self> {
+        let name = arg
+            .get_value_names()
+            .and_then(|names| names.first())
+            .map_or_else(
+                || arg.get_id().as_str().to_uppercase(),
+                |name| name.to_string(),
+            );
+        match name.as_str() {
+            "OPERATION_ID" => Some(Self::Operation),
+            "TRANSACTION_ID" => Some(Self::Transaction),
+            "KEY_ID" => Some(Self::Key),
+            "PATH" | "GLOB" => Some(Self::Path),
+            _ => None,
+        }
+    }
+}
+
+/// The kind of value the last of `words` (a `jk` command line without
+/// `jk`, cut off at the word being completed) is for
+pub fn value_kind(words: &[&str]) -> Option<ValueKind> {
+    let (current, before) = words.split_last()?;
+    if current.starts_with('-') {
+        return None;
+    }
+    let mut root = Cli::command();
+    root.build();
+    let mut command = &root;
+    let mut positional = 0;
+    let mut option: Option<&Arg> = None;
+    for word in before {
+        if option.take().is_some() {
+            continue;
+        }
+        if *word == "--" {
+            continue;
+        }
+        let flag = if let Some(long) = word.strip_prefix("--") {
+            // A value given as --flag=value is not a separate word
+            (!long.contains('=')).then(|| {
+                command
+                    .get_arguments()
+                    .find(|arg| arg.get_long() == Some(long))
+            })
+        } else if let Some(shorts) = word.strip_prefix('-') {
+            shorts.chars().last().map(|short| {
+                command
+                    .get_arguments()
+                    .find(|arg| arg.get_short() == Some(short))
+            })
+        } else {
+            None
+        };
+        match flag {
+            Some(arg) => option = arg.filter(|arg| arg.get_action().takes_values()),
+            None if word.starts_with('-') => {}
+            None => match command.find_subcommand(word) {
+                Some(subcommand) => {
+                    command = subcommand;
+                    positional = 0;
+                }
+                None => positional += 1,
+            },
+        }
+    }
+
+    let arg = match option {
+        Some(arg) => arg,
+        None => {
+            let positionals: Vec<&Arg> = command.get_positionals().collect();
+            match positionals.get(positional) {
+                Some(arg) => *arg,
+                // Extra words go to a last argument taking several
+                None => positionals
+                    .last()
+                    .copied()
+                    .filter(|arg| arg.get_num_args().is_some_and(|n| n.max_values() > 1))?,
+            }
+        }
+    };
+    ValueKind::of(arg)
+}
+
+/// Values of `kind` in `jk`'s store starting with `prefix`, newest first
+/// for IDs. Operation and transaction IDs are shortened as `jk history`
+/// shows them; a transaction is also offered when its name matches.
+pub fn candidates(jk: &JanusKey, kind: ValueKind, prefix: &str) -> Vec<String> {
+    let shorten = |id: &str, len: usize| id[..len.max(prefix.len()).min(id.len())].to_string();
+    match kind {
+        ValueKind::Operation => {
+            let len = jk.metadata_store.short_id_len();
+            jk.metadata_store
+                .operations()
+                .iter()
+                .rev()
+                .filter(|op| op.id.starts_with(prefix))
+                .map(|op| shorten(&op.id, len))
+                .collect()
+        }
+        ValueKind::Transaction => jk
+            .transaction_manager
+            .all()
+            .iter()
+            .rev()
+            .filter(|tx| {
+                tx.id.starts_with(prefix)
+                    || (!prefix.is_empty()
+                        && tx
+                            .name
+                            .as_deref()
+                            .is_some_and(|name| name.starts_with(prefix)))
+            })
+            .map(|tx| {
+                if tx.id.starts_with(prefix) {
+                    shorten(&tx.id, 8)
+                } else {
+                    tx.id.clone()
+                }
+            })
+            .collect(),
+        ValueKind::Key => KeyManager::new(&jk.root)
+            .key_ids()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|id| id.to_string())
+            .filter(|id| id.starts_with(prefix))
+            .collect(),
+        ValueKind::Path => {
+            let paths: BTreeSet<String> = jk
+                .metadata_store
+                .operations()
+                .iter()
+                .flat_map(|op| std::iter::once(&op.path).chain(&op.path_secondary))
+                .map(|path| {
+                    path.strip_prefix(&jk.root)
+                        .unwrap_or(path)
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .filter(|path| path.starts_with(prefix))
+                .collect();
+            paths.into_iter().collect()
+        }
+    }
+}
+
+/// `jk __complete`: print the values for the last of `words`, one per
+/// line, or nothing so the shell falls back to its own completion
+pub fn cmd_complete(dir: &Path, words: &[String]) -> Result<()> {
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+    let Some(kind) = value_kind(&words) else {
+        return Ok(());
+    };
+    if !JanusKey::is_initialized(dir) {
+        return Ok(());
+    }
+    // Completion must never fail noisily in the middle of a prompt
+    let Ok(jk) = JanusKey::open(dir) else {
+        return Ok(());
+    };
+    let prefix = words.last().copied().unwrap_or_default();
+    for value in candidates(&jk, kind, prefix) {
+        println!("{}", value);
+    }
+    Ok(())
+}
+
+/// `jk completions`: clap's script for `shell`, then for shells that can
+/// call back into `jk`, a wrapper asking `jk __complete` for store values
+pub fn cmd_completions(shell: Shell) -> Result<()> {
+    let mut stdout = std::io::stdout();
+    clap_complete::generate(shell, &mut Cli::command(), "jk", &mut stdout);
+    if let Some(hook) = dynamic_hook(shell) {
+        print!("{}", hook);
+    }
+    Ok(())
+}
+
+/// Shell code that completes store values through `jk __complete` and
+/// otherwise defers to the generated completion
+fn dynamic_hook(shell: Shell) -> Option<&'static str> {
+    match shell {
+        Shell::Bash => Some(
+            r#"
+_jk_dynamic() {
+    local values
+    values="$(jk __complete -- "${COMP_WORDS[@]:1:COMP_CWORD}" 2>/dev/null)"
+    if [[ -n "${values}" ]]; then
+        local IFS=$'\n'
+        COMPREPLY=(${values})
+        return 0
+    fi
+    _jk "$@"
+}
+if [[ "${BASH_VERSINFO[0]}" -eq 4 && "${BASH_VERSINFO[1]}" -ge 4 || "${BASH_VERSINFO[0]}" -gt 4 ]]; then
+    complete -F _jk_dynamic -o nosort -o bashdefault -o default jk
+else
+    complete -F _jk_dynamic -o bashdefault -o default jk
+fi
+"#,
+        ),
+        Shell::Zsh => Some(
+            r#"
+_jk_dynamic() {
+    local -a values
+    values=(${(f)"$(jk __complete -- "${(@)words[2,CURRENT]}" 2>/dev/null)"})
+    if (( ${#values} )); then
+        compadd -a values
+    else
+        _jk "$@"
+    fi
+}
+compdef _jk_dynamic jk
+"#,
+        ),
+        Shell::Fish => Some(
+            r#"
+complete -c jk -a '(jk __complete -- (commandline -opc)[2..-1] (commandline -ct) 2>/dev/null)'
+"#,
+        ),
+        _ => None,
+    }
+}
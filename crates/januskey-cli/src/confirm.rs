@@ -0,0 +1,288 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Confirmation Policy: when a batch of changes asks before it runs
+// Decides from configured thresholds and path lists; the prompt itself is
+// supplied by the caller, a terminal for the CLI or a dialog elsewhere
+
+use crate::error::{JanusError, Result};
+use crate::metadata::OperationType;
+use crate::policy::PathPolicies;
+use crate::select::MATCH_OPTIONS;
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// When to ask before a batch of changes (`Config.confirmation`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConfirmationConfig {
+    /// Ask when more than this many files would see an operation of the
+    /// type; types not listed are never counted
+    pub max_files: BTreeMap<OperationType, usize>,
+    /// Ask when the files changed hold more than this many bytes
+    pub max_bytes: Option<u64>,
+    /// Always ask for paths matching these globs (relative to the root)
+    pub always: Vec<String>,
+    /// Never ask on account of paths matching these globs, unless they are
+    /// also in `always`
+    pub never: Vec<String>,
+}
+
+impl Default for ConfirmationConfig {
+    fn default() -> Self {
+        Self {
+            max_files: BTreeMap::from([(OperationType::Delete, 10), (OperationType::Modify, 5)]),
+            max_bytes: None,
+            always: Vec::new(),
+            never: Vec::new(),
+        }
+    }
+}
+
+/// Why a batch needs confirming
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfirmationReason {
+    /// More files would see `op_type` than its threshold allows
+    TooManyFiles {
+        op_type: OperationType,
+        count: usize,
+        limit: usize,
+    },
+    /// The files changed hold more bytes than allowed
+    TooManyBytes { bytes: u64, limit: u64 },
+    /// A path is on the `always` list
+    AlwaysConfirm(PathBuf),
+}
+
+impl fmt::Display for ConfirmationReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooManyFiles {
+                op_type,
+                count,
+                limit,
+            } => write!(
+                f,
+                "{} of {} files (more than {})",
+                op_type.to_string().to_lowercase(),
+                count,
+                limit
+            ),
+            Self::TooManyBytes { bytes, limit } => {
+                write!(f, "{} bytes changed (more than {})", bytes, limit)
+            }
+            Self::AlwaysConfirm(path) => write!(f, "{} always needs confirming", path.display()),
+        }
+    }
+}
+
+/// A batch awaiting confirmation: why, and what it changes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfirmationRequest {
+    pub reasons: Vec<ConfirmationReason>,
+    /// Every path the batch changes, with its operation type
+    pub changes: Vec<(OperationType, PathBuf)>,
+    /// Bytes held by the files changed, before the change
+    pub bytes: u64,
+}
+
+/// Asks whether a batch should go ahead. Closures taking the request are
+/// prompts too.
+pub trait Prompt {
+    /// `true` to run the batch
+    fn confirm(&mut self, request: &ConfirmationRequest) -> Result<bool>;
+}
+
+impl<F: FnMut(&ConfirmationRequest) -> Result<bool>> Prompt for F {
+    fn confirm(&mut self, request: &ConfirmationRequest) -> Result<bool> {
+        self(request)
+    }
+}
+
+/// The configured confirmation rules, ready to evaluate against batches of
+/// changes under `root`. Paths a path policy marks `auto_confirm` count
+/// as if they were on the `never` list.
+#[derive(Debug, Clone)]
+pub struct ConfirmationPolicy {
+    root: PathBuf,
+    max_files: BTreeMap<OperationType, usize>,
+    max_bytes: Option<u64>,
+    always: Vec<Pattern>,
+    never: Vec<Pattern>,
+    policies: PathPolicies,
+}
+
+impl ConfirmationPolicy {
+    /// Compile `config` for paths under `root`, alongside the path
+    /// `policies`
+    pub fn new(root: &Path, config: &ConfirmationConfig, policies: PathPolicies) -> Result<Self> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    Pattern::new(pattern).map_err(|e| {
+                        JanusError::InvalidPattern(format!("confirmation {}: {}", pattern, e))
+                    })
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+        Ok(Self {
+            root: root.to_path_buf(),
+            max_files: config.max_files.clone(),
+            max_bytes: config.max_bytes,
+            always: compile(&config.always)?,
+            never: compile(&config.never)?,
+            policies,
+        })
+    }
+
+    fn matches(&self, patterns: &[Pattern], path: &Path) -> bool {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        patterns
+            .iter()
+            .any(|pattern| pattern.matches_path_with(relative, MATCH_OPTIONS))
+    }
+
+    /// Whether `changes` need confirming, and why. Sizes are of the files
+    /// as they are on disk now.
+    pub fn check<P: AsRef<Path>>(
+        &self,
+        changes: &[(OperationType, P)],
+    ) -> Option<ConfirmationRequest> {
+        let mut reasons = Vec::new();
+        let mut counts: BTreeMap<OperationType, usize> = BTreeMap::new();
+        let mut bytes = 0;
+        for (op_type, path) in changes {
+            let path = path.as_ref();
+            if self.matches(&self.always, path) {
+                reasons.push(ConfirmationReason::AlwaysConfirm(path.to_path_buf()));
+                continue;
+            }
+            if self.matches(&self.never, path) || self.policies.resolve(path).auto_confirm {
+                continue;
+            }
+            *counts.entry(*op_type).or_default() += 1;
+            bytes += std::fs::symlink_metadata(path).map_or(0, |m| m.len());
+        }
+        for (op_type, count) in counts {
+            match self.max_files.get(&op_type) {
+                Some(&limit) if count > limit => reasons.push(ConfirmationReason::TooManyFiles {
+                    op_type,
+                    count,
+                    limit,
+                }),
+                _ => {}
+            }
+        }
+        if let Some(limit) = self.max_bytes.filter(|&limit| bytes > limit) {
+            reasons.push(ConfirmationReason::TooManyBytes { bytes, limit });
+        }
+
+        (!reasons.is_empty()).then(|| ConfirmationRequest {
+            reasons,
+            changes: changes
+                .iter()
+                .map(|(op_type, path)| (*op_type, path.as_ref().to_path_buf()))
+                .collect(),
+            bytes,
+        })
+    }
+
+    /// Whether `changes` may go ahead: without asking if they need no
+    /// confirmation, else as `prompt` answers
+    pub fn confirm<P: AsRef<Path>>(
+        &self,
+        changes: &[(OperationType, P)],
+        prompt: &mut dyn Prompt,
+    ) -> Result<bool> {
+        match self.check(changes) {
+            Some(request) => prompt.confirm(&request),
+            None => Ok(true),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::PathPolicy;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_confirmation_policy() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        let paths: Vec<PathBuf> = (0..6).map(|i| root.join(format!("{}.txt", i))).collect();
+        for path in &paths {
+            fs::write(path, "0123456789").unwrap();
+        }
+        let modifies: Vec<_> = paths.iter().map(|p| (OperationType::Modify, p)).collect();
+        let deletes: Vec<_> = paths.iter().map(|p| (OperationType::Delete, p)).collect();
+
+        let defaults = ConfirmationPolicy::new(
+            root,
+            &ConfirmationConfig::default(),
+            PathPolicies::default(),
+        )
+        .unwrap();
+        let request = defaults.check(&modifies).unwrap();
+        assert_eq!(
+            request.reasons,
+            vec![ConfirmationReason::TooManyFiles {
+                op_type: OperationType::Modify,
+                count: 6,
+                limit: 5
+            }]
+        );
+        assert_eq!(request.bytes, 60);
+        assert!(defaults.check(&deletes).is_none());
+        assert!(defaults.check(&modifies[..5]).is_none());
+
+        let config = ConfirmationConfig {
+            max_bytes: Some(15),
+            always: vec!["5.txt".to_string()],
+            never: vec!["[0-3].txt".to_string()],
+            ..ConfirmationConfig::default()
+        };
+        let policies = PathPolicies::new(
+            root,
+            &[PathPolicy {
+                pattern: "4.txt".to_string(),
+                history: None,
+                encrypt_content: None,
+                max_versions: None,
+                auto_confirm: Some(true),
+            }],
+            &[],
+            false,
+        )
+        .unwrap();
+        let policy = ConfirmationPolicy::new(root, &config, policies).unwrap();
+        let request = policy.check(&deletes).unwrap();
+        assert_eq!(
+            request.reasons,
+            vec![ConfirmationReason::AlwaysConfirm(paths[5].clone())]
+        );
+        assert!(policy.check(&deletes[..5]).is_none());
+
+        let config = ConfirmationConfig {
+            max_bytes: Some(15),
+            ..ConfirmationConfig::default()
+        };
+        let policy = ConfirmationPolicy::new(root, &config, PathPolicies::default()).unwrap();
+        let mut asked = 0;
+        let mut decline = |request: &ConfirmationRequest| {
+            asked += 1;
+            assert_eq!(request.changes.len(), 2);
+            Ok(false)
+        };
+        assert!(!policy.confirm(&deletes[..2], &mut decline).unwrap());
+        assert!(policy.confirm(&deletes[..1], &mut decline).unwrap());
+        assert_eq!(asked, 1);
+    }
+}
@@ -0,0 +1,1037 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Daemon: one long-lived store per directory, served over a Unix socket
+// The daemon holds the store lock and its history in memory; writes are
+// serialized, reads run concurrently, and `jk` proxies through it when
+// the socket is present instead of reloading the log on every call.
+// With API tokens configured, each request is checked against the role
+// its token grants (see access.rs).
+
+use crate::access::{authenticate, ApiToken, Permission, TOKEN_ENV};
+use crate::actor::{validate_actor, Actor};
+use crate::attestation::AuditLog;
+use crate::content_store::ContentHash;
+use crate::error::{JanusError, Result};
+use crate::guard;
+#[cfg(target_os = "linux")]
+use crate::intercept::Interceptor;
+use crate::metadata::OperationMetadata;
+use crate::obliteration::{DeltaDependents, ObliterationRecord};
+use crate::operations::FileOperation;
+use crate::refs::{self, References};
+use crate::transaction::Transaction;
+use crate::{JanusKey, StoreStatus};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+/// Socket the daemon listens on, in `.januskey/`
+pub const SOCKET_NAME: &str = "daemon.sock";
+
+/// Lock file held for as long as a daemon serves the store, in `.januskey/`
+const LOCK_NAME: &str = "daemon.lock";
+
+/// How long the daemon waits without requests before refreshing the
+/// search index, when `search_index` is configured
+const INDEX_WHEN_IDLE_FOR: Duration = Duration::from_secs(30);
+
+/// Set to bypass a running daemon and open the store directly
+pub const NO_DAEMON_ENV: &str = "JANUSKEY_NO_DAEMON";
+
+/// Path of the daemon socket for `root`
+pub fn socket_path(root: &Path) -> PathBuf {
+    root.join(".januskey").join(SOCKET_NAME)
+}
+
+/// A request, one JSON object per line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum Request {
+    Ping,
+    Status,
+    /// The last `limit` operations, newest first
+    History {
+        limit: usize,
+    },
+    /// Execute each operation on its own, carrying on past failures
+    Execute {
+        operations: Vec<FileOperation>,
+        #[serde(default)]
+        no_history: bool,
+    },
+    /// [`JanusKey::ensure_capacity`]
+    EnsureCapacity {
+        bytes: u64,
+    },
+    /// Undo `id` (with `cascade`, and what depends on it), or else the
+    /// last `count` operations. Refused if the restored content would not
+    /// fit on the disk, unless `force` is set.
+    Undo {
+        id: Option<String>,
+        count: usize,
+        #[serde(default)]
+        cascade: bool,
+        #[serde(default)]
+        force: bool,
+    },
+    Begin {
+        name: Option<String>,
+    },
+    Commit,
+    /// Roll back the active transaction; the disk check is as for `Undo`
+    Rollback {
+        #[serde(default)]
+        force: bool,
+    },
+    /// Securely destroy stored content (see
+    /// [`ObliterationManager::obliterate`](crate::obliteration::ObliterationManager::obliterate))
+    Obliterate {
+        content_hash: String,
+        #[serde(default)]
+        reason: Option<String>,
+        #[serde(default)]
+        legal_basis: Option<String>,
+        #[serde(default)]
+        dependents: DeltaDependents,
+    },
+    /// Stop serving once this request is answered
+    Shutdown,
+}
+
+impl Request {
+    /// What a token's role must permit for this request; `None` for a
+    /// ping, which anyone connected may send
+    pub fn permission(&self) -> Option<Permission> {
+        match self {
+            Request::Ping => None,
+            Request::Status | Request::History { .. } => Some(Permission::Read),
+            Request::Obliterate { .. } => Some(Permission::Obliterate),
+            Request::Execute { .. }
+            | Request::EnsureCapacity { .. }
+            | Request::Undo { .. }
+            | Request::Begin { .. }
+            | Request::Commit
+            | Request::Rollback { .. }
+            | Request::Shutdown => Some(Permission::Operate),
+        }
+    }
+
+    /// The request as recorded in the audit log
+    fn describe(&self) -> String {
+        match self {
+            Request::Ping => "ping".to_string(),
+            Request::Status => "status".to_string(),
+            Request::History { .. } => "history".to_string(),
+            Request::Execute { operations, .. } => {
+                format!("execute {} operations", operations.len())
+            }
+            Request::EnsureCapacity { .. } => "ensure_capacity".to_string(),
+            Request::Undo { id: Some(id), .. } => format!("undo {}", id),
+            Request::Undo { count, .. } => format!("undo last {}", count),
+            Request::Begin { .. } => "begin".to_string(),
+            Request::Commit => "commit".to_string(),
+            Request::Rollback { .. } => "rollback".to_string(),
+            Request::Obliterate { content_hash, .. } => format!("obliterate {}", content_hash),
+            Request::Shutdown => "shutdown".to_string(),
+        }
+    }
+}
+
+/// A request with the actor and references the client declared, whether
+/// it allows changing protected paths and paths outside the root, and its
+/// API token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub refs: References,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_protected: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_outside: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    #[serde(flatten)]
+    pub request: Request,
+}
+
+/// The result of undoing one operation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UndoOutcome {
+    /// The operation undone
+    pub operation: OperationMetadata,
+    /// Its undo, or why it failed
+    pub result: std::result::Result<OperationMetadata, String>,
+}
+
+/// A response, one JSON object per line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum Response {
+    Pong,
+    Status(StoreStatus),
+    Operations {
+        operations: Vec<OperationMetadata>,
+    },
+    /// One result per executed operation, in order
+    Executed {
+        results: Vec<std::result::Result<OperationMetadata, String>>,
+    },
+    Undone {
+        outcomes: Vec<UndoOutcome>,
+    },
+    Transaction(Transaction),
+    Obliterated(ObliterationRecord),
+    Done,
+    Error {
+        message: String,
+        /// Stable code of the error (see [`JanusError::code`]); absent for
+        /// a malformed request
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        code: Option<String>,
+    },
+}
+
+/// A store served over [`socket_path`] until a [`Request::Shutdown`]
+pub struct Daemon {
+    jk: RwLock<JanusKey>,
+    listener: UnixListener,
+    socket: PathBuf,
+    /// Held locked while serving; released when dropped
+    _lock: File,
+    shutdown: AtomicBool,
+    last_request: Mutex<Instant>,
+    /// Whether the store changed since the search index was refreshed
+    index_stale: AtomicBool,
+    /// Capturing what other processes change, until served
+    #[cfg(target_os = "linux")]
+    interceptor: Mutex<Option<Interceptor>>,
+    /// Tokens requests must present; none to serve only the daemon's user
+    tokens: Vec<ApiToken>,
+    /// Where access decisions are recorded, if auditing is enabled
+    audit: Option<AuditLog>,
+}
+
+impl Daemon {
+    /// Open the store for `root` and listen on its socket. Fails if
+    /// another daemon already serves it; a socket left by one that died is
+    /// replaced.
+    pub fn bind(root: &Path) -> Result<Self> {
+        let jk = JanusKey::open(root)?;
+        let dir = jk.root.join(".januskey");
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(dir.join(LOCK_NAME))?;
+        match lock.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(JanusError::Daemon(format!(
+                    "a daemon already serves {}",
+                    jk.root.display()
+                )))
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+
+        let socket = socket_path(&jk.root);
+        if socket.exists() {
+            fs::remove_file(&socket)?;
+        }
+        let listener = UnixListener::bind(&socket)?;
+        // The socket carries the daemon user's write access to the tree:
+        // only that user may connect, unless tokens decide who does what
+        let tokens = jk.config.daemon_tokens.clone();
+        let mode = if tokens.is_empty() { 0o600 } else { 0o660 };
+        fs::set_permissions(&socket, fs::Permissions::from_mode(mode))?;
+        let audit = jk.config.audit_enabled.then(|| AuditLog::new(&jk.root));
+
+        Ok(Self {
+            jk: RwLock::new(jk),
+            listener,
+            socket,
+            _lock: lock,
+            shutdown: AtomicBool::new(false),
+            last_request: Mutex::new(Instant::now()),
+            index_stale: AtomicBool::new(true),
+            #[cfg(target_os = "linux")]
+            interceptor: Mutex::new(None),
+            tokens,
+            audit,
+        })
+    }
+
+    /// Capture files under the root before other processes change them,
+    /// and record the changes, while serving (see [`crate::intercept`])
+    #[cfg(target_os = "linux")]
+    pub fn intercept(&self, fail_closed: bool) -> Result<()> {
+        let interceptor = Interceptor::new(&self.read())?.fail_closed(fail_closed);
+        *self.interceptor.lock().unwrap_or_else(|e| e.into_inner()) = Some(interceptor);
+        Ok(())
+    }
+
+    /// Where clients connect
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+
+    /// [`JanusKey::unlock_content`], for the undos clients ask for
+    pub fn unlock_content(&self, passphrase: &str) -> Result<usize> {
+        self.jk
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .unlock_content(passphrase)
+    }
+
+    /// Serve clients, each on its own thread, until one asks to shut down.
+    /// With `search_index` configured, the search index is refreshed
+    /// whenever the store has changed and no request has arrived for a
+    /// while.
+    pub fn serve(&self) -> Result<()> {
+        std::thread::scope(|scope| {
+            if self.read().config.search_index {
+                scope.spawn(|| self.index_when_idle());
+            }
+            #[cfg(target_os = "linux")]
+            if let Some(interceptor) = self
+                .interceptor
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .take()
+            {
+                scope.spawn(|| {
+                    if let Err(e) = interceptor.run(&self.jk, &self.shutdown) {
+                        tracing::error!("interception stopped: {}", e);
+                    }
+                });
+            }
+            for stream in self.listener.incoming() {
+                if self.shutdown.load(Ordering::SeqCst) {
+                    break;
+                }
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        tracing::warn!("daemon accept failed: {}", e);
+                        continue;
+                    }
+                };
+                scope.spawn(move || {
+                    if let Err(e) = self.serve_client(stream) {
+                        tracing::warn!("daemon client failed: {}", e);
+                    }
+                });
+            }
+        });
+        Ok(())
+    }
+
+    /// Refresh the search index once the store has changed and gone quiet,
+    /// until shut down
+    fn index_when_idle(&self) {
+        while !self.shutdown.load(Ordering::SeqCst) {
+            std::thread::sleep(Duration::from_millis(500));
+            let idle = self
+                .last_request
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .elapsed()
+                >= INDEX_WHEN_IDLE_FOR;
+            if idle && self.index_stale.swap(false, Ordering::SeqCst) {
+                let jk = self.read();
+                if let Err(e) = jk.search_index().update(&jk.content_store) {
+                    tracing::warn!("daemon search indexing failed: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Answer one client's requests until it disconnects
+    fn serve_client(&self, stream: UnixStream) -> Result<()> {
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            *self.last_request.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+            let response = match serde_json::from_str::<ClientRequest>(&line) {
+                Ok(request) => self
+                    .authorize(request)
+                    .and_then(|request| match request.request {
+                        Request::Shutdown => {
+                            self.shutdown.store(true, Ordering::SeqCst);
+                            // Wake the accept loop so it sees the flag
+                            let _ = UnixStream::connect(&self.socket);
+                            Ok(Response::Done)
+                        }
+                        _ => self.handle(request),
+                    })
+                    .unwrap_or_else(|e| Response::Error {
+                        message: e.to_string(),
+                        code: Some(e.code().to_string()),
+                    }),
+                Err(e) => Response::Error {
+                    message: format!("malformed request: {}", e),
+                    code: None,
+                },
+            };
+            serde_json::to_writer(&mut writer, &response)?;
+            writer.write_all(b"\n")?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Check `request`'s token grants what it asks for, when tokens are
+    /// configured. An allowed request is recorded against the token's
+    /// name. Refusals, and allowed requests beyond reading, go to the
+    /// audit log.
+    ///
+    /// The socket is open to the daemon user's group when tokens are, so
+    /// a token's request may not override the path guards: its
+    /// `allow_protected` and `allow_outside` are dropped.
+    fn authorize(&self, mut request: ClientRequest) -> Result<ClientRequest> {
+        let Some(permission) = request.request.permission() else {
+            return Ok(request);
+        };
+        if self.tokens.is_empty() {
+            return Ok(request);
+        }
+        let token = request
+            .token
+            .as_deref()
+            .and_then(|presented| authenticate(&self.tokens, presented));
+        let refusal = match token {
+            None if request.token.is_none() => Some(format!(
+                "this daemon requires an API token; set {}",
+                TOKEN_ENV
+            )),
+            None => Some("unknown API token".to_string()),
+            Some(token) if !token.role.permits(permission) => Some(format!(
+                "{} is a {} and may not {}",
+                token.name,
+                token.role,
+                request.request.describe()
+            )),
+            Some(_) => None,
+        };
+
+        if refusal.is_some() || permission != Permission::Read {
+            let details = match token {
+                Some(token) => format!(
+                    "{} ({}): {}",
+                    token.name,
+                    token.role,
+                    request.request.describe()
+                ),
+                None => format!("unauthenticated: {}", request.request.describe()),
+            };
+            if let Some(audit) = &self.audit {
+                if let Err(e) = audit.log_access(refusal.is_none(), details) {
+                    tracing::warn!("failed to audit daemon access: {}", e);
+                }
+            }
+        }
+        if let Some(refusal) = refusal {
+            return Err(JanusError::AccessDenied(refusal));
+        }
+        if request.allow_protected || request.allow_outside {
+            tracing::warn!("ignoring path guard overrides sent with an API token");
+            request.allow_protected = false;
+            request.allow_outside = false;
+        }
+        request.actor = token.map(|token| token.name.clone());
+        Ok(request)
+    }
+
+    /// Record that `request`'s path guard overrides are honoured
+    fn audit_overrides(&self, request: &ClientRequest) {
+        let overrides: Vec<&str> = [
+            (request.allow_protected, "allow_protected"),
+            (request.allow_outside, "allow_outside"),
+        ]
+        .into_iter()
+        .filter_map(|(set, name)| set.then_some(name))
+        .collect();
+        if overrides.is_empty() {
+            return;
+        }
+        let details = format!("{}: {}", overrides.join(", "), request.request.describe());
+        if let Some(audit) = &self.audit {
+            if let Err(e) = audit.log_guard_override(details) {
+                tracing::warn!("failed to audit path guard override: {}", e);
+            }
+        }
+    }
+
+    fn handle(&self, request: ClientRequest) -> Result<Response> {
+        // Reads share the store; anything else waits for sole access
+        match request.request {
+            Request::Ping => return Ok(Response::Pong),
+            Request::Status => return Ok(Response::Status(self.read().status()?)),
+            Request::History { limit } => {
+                let operations = self
+                    .read()
+                    .metadata_store
+                    .last_n(limit)
+                    .into_iter()
+                    .cloned()
+                    .collect();
+                return Ok(Response::Operations { operations });
+            }
+            _ => {}
+        }
+
+        let actor = match &request.actor {
+            Some(name) => {
+                validate_actor(name)?;
+                Actor {
+                    declared: Some(name.clone()),
+                    ..Actor::os()
+                }
+            }
+            None => Actor::os(),
+        };
+        let mut jk = self.jk.write().unwrap_or_else(|e| e.into_inner());
+        self.index_stale.store(true, Ordering::SeqCst);
+        jk.metadata_store.set_actor(&actor);
+        jk.transaction_manager.set_actor(&actor);
+        jk.set_refs(request.refs.clone());
+        jk.set_allow_protected(request.allow_protected);
+        jk.set_allow_outside(request.allow_outside);
+        self.audit_overrides(&request);
+
+        Ok(match request.request {
+            Request::Execute {
+                operations,
+                no_history,
+            } => Response::Executed {
+                results: operations
+                    .into_iter()
+                    .map(|operation| {
+                        let result = if no_history {
+                            jk.execute_without_history(operation)
+                        } else {
+                            jk.execute(operation)
+                        };
+                        result.map_err(|e| e.to_string())
+                    })
+                    .collect(),
+            },
+            Request::EnsureCapacity { bytes } => {
+                jk.ensure_capacity(bytes)?;
+                Response::Done
+            }
+            Request::Undo {
+                id,
+                count,
+                cascade,
+                force,
+            } => Response::Undone {
+                outcomes: undo(&mut jk, id, count, cascade, force)?,
+            },
+            Request::Begin { name } => {
+                Response::Transaction(jk.transaction_manager.begin(name)?.clone())
+            }
+            Request::Commit => Response::Transaction(jk.transaction_manager.commit()?),
+            Request::Rollback { force } => {
+                if !force {
+                    let active = jk
+                        .transaction_manager
+                        .active()
+                        .ok_or(JanusError::NoActiveTransaction)?;
+                    let bytes = active
+                        .rollback_remaining()
+                        .filter_map(|op_id| jk.metadata_store.get(op_id))
+                        .filter(|op| !op.undone)
+                        .map(|op| op.restore_bytes())
+                        .sum();
+                    jk.ensure_disk_space(bytes)?;
+                }
+                Response::Transaction(jk.rollback()?)
+            }
+            Request::Obliterate {
+                content_hash,
+                reason,
+                legal_basis,
+                dependents,
+            } => Response::Obliterated(
+                jk.obliteration_manager()?
+                    .on_dependents(dependents)
+                    .obliterate(
+                        &jk.content_store,
+                        &ContentHash(content_hash),
+                        reason,
+                        legal_basis,
+                    )?,
+            ),
+            Request::Ping | Request::Status | Request::History { .. } | Request::Shutdown => {
+                unreachable!("handled above")
+            }
+        })
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, JanusKey> {
+        self.jk.read().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.socket);
+    }
+}
+
+/// Undo for [`Request::Undo`]
+fn undo(
+    jk: &mut JanusKey,
+    id: Option<String>,
+    count: usize,
+    cascade: bool,
+    force: bool,
+) -> Result<Vec<UndoOutcome>> {
+    let Some(id) = id else {
+        if !force {
+            let ops = jk.metadata_store.last_n(count);
+            jk.ensure_disk_space(ops.iter().map(|op| op.restore_bytes()).sum())?;
+        }
+        return Ok(jk
+            .undo_recent(count)
+            .into_iter()
+            .map(|(operation, result)| UndoOutcome {
+                operation,
+                result: result.map_err(|e| e.to_string()),
+            })
+            .collect());
+    };
+
+    let id = jk.metadata_store.find(&id)?.id.clone();
+    // Newest first, the order they are undone in
+    let mut ids = vec![id.clone()];
+    if cascade {
+        ids.extend(jk.metadata_store.dependency_graph().descendants(&id));
+    }
+    ids.reverse();
+    let targets = ids
+        .iter()
+        .map(|id| {
+            jk.metadata_store
+                .get(id)
+                .cloned()
+                .ok_or_else(|| JanusError::InvalidOperationId(id.clone()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if !force {
+        let bytes = targets
+            .iter()
+            .filter(|op| !op.undone)
+            .map(|op| op.restore_bytes())
+            .sum();
+        jk.ensure_disk_space(bytes)?;
+    }
+
+    let undone = if cascade {
+        jk.undo_cascade(&id)?
+    } else {
+        vec![jk.undo(&id)?]
+    };
+    Ok(targets
+        .into_iter()
+        .zip(undone)
+        .map(|(operation, undo)| UndoOutcome {
+            operation,
+            result: Ok(undo),
+        })
+        .collect())
+}
+
+/// A connection to the daemon serving a directory
+pub struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    /// Declared actor sent with every request
+    actor: Option<String>,
+    /// References sent with every request, from [`refs::REFS_ENV`]
+    refs: References,
+    /// Whether requests may change protected paths, from
+    /// [`guard::ALLOW_PROTECTED_ENV`]
+    allow_protected: bool,
+    /// Whether requests may change paths outside the root, from
+    /// [`guard::ALLOW_OUTSIDE_ENV`]
+    allow_outside: bool,
+    /// API token sent with every request, from [`TOKEN_ENV`]
+    token: Option<String>,
+}
+
+impl Client {
+    /// Connect to the daemon serving `root`, if one is. `None` when there
+    /// is no socket, nothing listens on it, or [`NO_DAEMON_ENV`] is set.
+    pub fn connect(root: &Path) -> Result<Option<Self>> {
+        if std::env::var_os(NO_DAEMON_ENV).is_some() {
+            return Ok(None);
+        }
+        let socket = socket_path(root);
+        if !socket.exists() {
+            return Ok(None);
+        }
+        let stream = match UnixStream::connect(&socket) {
+            Ok(stream) => stream,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::ConnectionRefused | std::io::ErrorKind::NotFound
+                ) =>
+            {
+                return Ok(None)
+            }
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            actor: Actor::from_env().declared,
+            refs: refs::from_env()?,
+            allow_protected: guard::allow_protected_from_env(),
+            allow_outside: guard::allow_outside_from_env(),
+            token: std::env::var(TOKEN_ENV)
+                .ok()
+                .filter(|token| !token.is_empty()),
+        }))
+    }
+
+    /// Send `request` and wait for the answer. An error response becomes
+    /// [`JanusError::Daemon`].
+    pub fn call(&mut self, request: Request) -> Result<Response> {
+        let request = ClientRequest {
+            actor: self.actor.clone(),
+            refs: self.refs.clone(),
+            allow_protected: self.allow_protected,
+            allow_outside: self.allow_outside,
+            token: self.token.clone(),
+            request,
+        };
+        serde_json::to_writer(&mut self.writer, &request)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(JanusError::Daemon("connection closed".to_string()));
+        }
+        match serde_json::from_str(&line)? {
+            Response::Error { message, .. } => Err(JanusError::Daemon(message)),
+            response => Ok(response),
+        }
+    }
+
+    pub fn status(&mut self) -> Result<StoreStatus> {
+        match self.call(Request::Status)? {
+            Response::Status(status) => Ok(status),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Execute one operation, recorded like [`JanusKey::execute`] unless
+    /// `no_history` is set
+    pub fn execute(
+        &mut self,
+        operation: FileOperation,
+        no_history: bool,
+    ) -> Result<OperationMetadata> {
+        let response = self.call(Request::Execute {
+            operations: vec![operation],
+            no_history,
+        })?;
+        match response {
+            Response::Executed { mut results } if results.len() == 1 => {
+                results.remove(0).map_err(JanusError::Daemon)
+            }
+            other => Err(unexpected(other)),
+        }
+    }
+
+    pub fn ensure_capacity(&mut self, bytes: u64) -> Result<()> {
+        self.call(Request::EnsureCapacity { bytes }).map(|_| ())
+    }
+
+    /// See [`Request::Undo`]
+    pub fn undo(
+        &mut self,
+        id: Option<String>,
+        count: usize,
+        cascade: bool,
+        force: bool,
+    ) -> Result<Vec<UndoOutcome>> {
+        match self.call(Request::Undo {
+            id,
+            count,
+            cascade,
+            force,
+        })? {
+            Response::Undone { outcomes } => Ok(outcomes),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    pub fn begin(&mut self, name: Option<String>) -> Result<Transaction> {
+        self.transaction(Request::Begin { name })
+    }
+
+    pub fn commit(&mut self) -> Result<Transaction> {
+        self.transaction(Request::Commit)
+    }
+
+    pub fn rollback(&mut self, force: bool) -> Result<Transaction> {
+        self.transaction(Request::Rollback { force })
+    }
+
+    /// See [`Request::Obliterate`]
+    pub fn obliterate(
+        &mut self,
+        content_hash: &ContentHash,
+        reason: Option<String>,
+        legal_basis: Option<String>,
+        dependents: DeltaDependents,
+    ) -> Result<ObliterationRecord> {
+        match self.call(Request::Obliterate {
+            content_hash: content_hash.0.clone(),
+            reason,
+            legal_basis,
+            dependents,
+        })? {
+            Response::Obliterated(record) => Ok(record),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Ask the daemon to stop once it has answered
+    pub fn shutdown(&mut self) -> Result<()> {
+        self.call(Request::Shutdown).map(|_| ())
+    }
+
+    fn transaction(&mut self, request: Request) -> Result<Transaction> {
+        match self.call(request)? {
+            Response::Transaction(tx) => Ok(tx),
+            other => Err(unexpected(other)),
+        }
+    }
+}
+
+fn unexpected(response: Response) -> JanusError {
+    JanusError::Daemon(format!("unexpected response {:?}", response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_concurrent_clients() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().canonicalize().unwrap();
+        JanusKey::init(&root).unwrap();
+        let daemon = Daemon::bind(&root).unwrap();
+        assert!(matches!(Daemon::bind(&root), Err(JanusError::Daemon(_))));
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| daemon.serve().unwrap());
+
+            let writers: Vec<_> = (0..4)
+                .map(|i| {
+                    let root = &root;
+                    scope.spawn(move || {
+                        let mut client = Client::connect(root).unwrap().unwrap();
+                        for j in 0..5 {
+                            let operation = FileOperation::Create {
+                                path: root.join(format!("{}-{}.txt", i, j)),
+                                content: vec![b'x'; 10],
+                                mode: None,
+                            };
+                            client.execute(operation, false).unwrap();
+                            client.status().unwrap();
+                        }
+                    })
+                })
+                .collect();
+            for writer in writers {
+                writer.join().unwrap();
+            }
+
+            let mut client = Client::connect(&root).unwrap().unwrap();
+            assert_eq!(client.status().unwrap().operations, 20);
+            let outcomes = client.undo(None, 2, false, false).unwrap();
+            assert!(outcomes.iter().all(|outcome| outcome.result.is_ok()));
+            assert!(matches!(
+                client.call(Request::Commit),
+                Err(JanusError::Daemon(_))
+            ));
+            client.shutdown().unwrap();
+        });
+        drop(daemon);
+
+        // Everything the daemon did is on disk for the next process
+        assert!(Client::connect(&root).unwrap().is_none());
+        let jk = JanusKey::open(&root).unwrap();
+        assert_eq!(jk.metadata_store.count(), 22);
+    }
+
+    #[test]
+    fn test_tokens_and_roles() {
+        use crate::access::Role;
+        use crate::attestation::AuditEventType;
+
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().canonicalize().unwrap();
+        let mut jk = JanusKey::init(&root).unwrap();
+        let mut issued = std::collections::HashMap::new();
+        for (name, role) in [
+            ("viewer", Role::Reader),
+            ("ops", Role::Operator),
+            ("dpo", Role::ComplianceOfficer),
+        ] {
+            let (record, token) = ApiToken::issue(name, role).unwrap();
+            jk.config.daemon_tokens.push(record);
+            issued.insert(name, token);
+        }
+        jk.config.protected_paths = vec!["secrets".to_string()];
+        jk.config.save(&root).unwrap();
+        drop(jk);
+        fs::write(root.join("a.txt"), "personal data").unwrap();
+        fs::create_dir(root.join("secrets")).unwrap();
+        fs::write(root.join("secrets/key"), "k").unwrap();
+
+        let daemon = Daemon::bind(&root).unwrap();
+        assert_eq!(
+            fs::metadata(daemon.socket()).unwrap().permissions().mode() & 0o777,
+            0o660
+        );
+        let connect = |name: Option<&str>| {
+            let mut client = Client::connect(&root).unwrap().unwrap();
+            client.token = name.map(|name| issued[name].clone());
+            client
+        };
+        let denied = |result: Result<Response>| match result {
+            Err(JanusError::Daemon(message)) => assert!(message.contains("denied"), "{}", message),
+            other => panic!("expected a refusal, got {:?}", other),
+        };
+
+        let hash = std::thread::scope(|scope| {
+            scope.spawn(|| daemon.serve().unwrap());
+
+            let mut anonymous = connect(None);
+            assert!(matches!(anonymous.call(Request::Ping), Ok(Response::Pong)));
+            denied(anonymous.call(Request::Status));
+
+            let mut viewer = connect(Some("viewer"));
+            viewer.status().unwrap();
+            denied(viewer.call(Request::Undo {
+                id: None,
+                count: 1,
+                cascade: false,
+                force: false,
+            }));
+
+            let mut ops = connect(Some("ops"));
+            let deleted = ops
+                .execute(
+                    FileOperation::Delete {
+                        path: root.join("a.txt"),
+                    },
+                    false,
+                )
+                .unwrap();
+            assert_eq!(deleted.actor.as_deref(), Some("ops"));
+            // A token cannot lift the path guards
+            ops.allow_protected = true;
+            let delete_secret = FileOperation::Delete {
+                path: root.join("secrets/key"),
+            };
+            assert!(ops.execute(delete_secret, false).is_err());
+            assert!(root.join("secrets/key").exists());
+            let hash = deleted.content_hash.clone().unwrap();
+            denied(ops.call(Request::Obliterate {
+                content_hash: hash.0.clone(),
+                reason: None,
+                legal_basis: None,
+                dependents: DeltaDependents::Refuse,
+            }));
+
+            let mut dpo = connect(Some("dpo"));
+            denied(dpo.call(Request::Commit));
+            dpo.obliterate(
+                &hash,
+                Some("erasure request".to_string()),
+                None,
+                DeltaDependents::Refuse,
+            )
+            .unwrap();
+
+            denied(dpo.call(Request::Shutdown));
+            ops.shutdown().unwrap();
+            hash
+        });
+        drop(daemon);
+
+        let audit = AuditLog::new(&root);
+        let entries = audit.read_all().unwrap();
+        let count = |event| entries.iter().filter(|e| e.event_type == event).count();
+        assert_eq!(count(AuditEventType::AccessDenied), 5);
+        assert_eq!(count(AuditEventType::AccessGranted), 4);
+        assert_eq!(count(AuditEventType::GuardOverridden), 0);
+        assert!(entries.iter().any(|e| e.reason.as_deref()
+            == Some(&format!("dpo (compliance-officer): obliterate {}", hash))));
+        assert!(audit.verify_integrity().unwrap().valid);
+    }
+
+    #[test]
+    fn test_guard_overrides_are_audited() {
+        use crate::attestation::AuditEventType;
+
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().canonicalize().unwrap();
+        let mut jk = JanusKey::init(&root).unwrap();
+        jk.config.protected_paths = vec!["secrets".to_string()];
+        jk.config.save(&root).unwrap();
+        drop(jk);
+        fs::create_dir(root.join("secrets")).unwrap();
+        fs::write(root.join("secrets/key"), "k").unwrap();
+
+        let daemon = Daemon::bind(&root).unwrap();
+        std::thread::scope(|scope| {
+            scope.spawn(|| daemon.serve().unwrap());
+            let mut client = Client::connect(&root).unwrap().unwrap();
+            client.allow_protected = true;
+            let operation = FileOperation::Delete {
+                path: root.join("secrets/key"),
+            };
+            client.execute(operation, false).unwrap();
+            client.shutdown().unwrap();
+        });
+        drop(daemon);
+
+        assert!(!root.join("secrets/key").exists());
+        let entries = AuditLog::new(&root).read_all().unwrap();
+        let overrides: Vec<_> = entries
+            .iter()
+            .filter(|e| e.event_type == AuditEventType::GuardOverridden)
+            .collect();
+        assert_eq!(overrides.len(), 1);
+        assert_eq!(
+            overrides[0].reason.as_deref(),
+            Some("allow_protected: execute 1 operations")
+        );
+    }
+}
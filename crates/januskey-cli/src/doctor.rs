@@ -0,0 +1,629 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Doctor: environment and store health diagnostics for `jk doctor`
+// Each check looks at one thing that can quietly stop undo from working:
+// the daemon lock, the filesystem, the clock, the logs, the keystore and
+// free space. Nothing is changed; `jk heal` and `jk gc` repair.
+
+use crate::backend::{supports_reflink, StorageUri};
+use crate::transaction::TransactionState;
+use crate::{human_bytes, Config, JanusKey};
+use chrono::{TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Newest recorded time allowed ahead of this clock before it is reported
+const CLOCK_SKEW_ALLOWED: TimeDelta = TimeDelta::minutes(5);
+
+/// An open transaction older than this is reported as forgotten
+const STALE_TRANSACTION: TimeDelta = TimeDelta::days(1);
+
+/// Filesystems reached over the network, where memory-mapped files can
+/// fault when the server truncates them
+const NETWORK_FILESYSTEMS: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "9p",
+    "afs",
+    "fuse.sshfs",
+];
+
+/// How a check came out, in increasing severity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// The outcome of one check
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Check {
+    /// Stable name (`lock`, `filesystem`, `clock`, ...)
+    pub name: String,
+    pub status: CheckStatus,
+    pub message: String,
+}
+
+impl Check {
+    fn new(name: &str, status: CheckStatus, message: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+/// Every check run on a directory, in order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DoctorReport {
+    pub root: PathBuf,
+    /// The worst status of any check
+    pub status: CheckStatus,
+    pub checks: Vec<Check>,
+}
+
+/// Run every check on the JanusKey directory `root`. A check that cannot
+/// run (the store does not open, say) fails; the others still run.
+pub fn diagnose(root: &Path) -> DoctorReport {
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let mut checks = vec![check_config(&root)];
+    let config = Config::load(&root).unwrap_or_default();
+    let store_dir = local_store_dir(&root, &config);
+
+    let jk = match JanusKey::open(&root) {
+        Ok(jk) => {
+            checks.push(check_store(&jk));
+            checks.push(check_backend(&jk));
+            Some(jk)
+        }
+        Err(e) => {
+            checks.push(Check::new("store", CheckStatus::Fail, e.to_string()));
+            None
+        }
+    };
+    checks.push(check_lock(&root));
+    checks.push(check_filesystem(&store_dir, &config));
+    checks.push(check_reflink(&store_dir));
+    checks.push(check_xattr(&store_dir));
+    if let Some(jk) = &jk {
+        checks.push(check_clock(jk));
+        checks.push(check_transactions(jk));
+    }
+    checks.push(check_journal(&root, &store_dir));
+    checks.push(check_keystore(&root));
+    checks.push(check_free_space(&store_dir, jk.as_ref()));
+
+    DoctorReport {
+        status: checks
+            .iter()
+            .map(|check| check.status)
+            .max()
+            .unwrap_or(CheckStatus::Ok),
+        root,
+        checks,
+    }
+}
+
+/// The local directory history is kept in: `.januskey/`, or a local
+/// `storage_path`. With remote storage only `.januskey/` is local.
+fn local_store_dir(root: &Path, config: &Config) -> PathBuf {
+    match config.storage_uri() {
+        Ok(Some(uri @ StorageUri::Local(_))) => root.join(uri.root()),
+        _ => root.join(".januskey"),
+    }
+}
+
+fn check_config(root: &Path) -> Check {
+    let config = match Config::load(root) {
+        Ok(config) => config,
+        Err(e) => return Check::new("config", CheckStatus::Fail, e.to_string()),
+    };
+    match config.validate(root) {
+        Ok(()) => Check::new(
+            "config",
+            CheckStatus::Ok,
+            format!("valid (version {})", config.version),
+        ),
+        Err(e) => Check::new("config", CheckStatus::Fail, e.to_string()),
+    }
+}
+
+fn check_store(jk: &JanusKey) -> Check {
+    match jk.status() {
+        Ok(status) => Check::new(
+            "store",
+            CheckStatus::Ok,
+            format!(
+                "{} operations, {} blobs ({}){}",
+                status.operations,
+                status.blobs,
+                human_bytes(status.bytes),
+                status
+                    .storage
+                    .map(|uri| format!(" on {}", uri))
+                    .unwrap_or_default()
+            ),
+        ),
+        Err(e) => Check::new("store", CheckStatus::Fail, e.to_string()),
+    }
+}
+
+/// What the backends history is stored on cannot do natively, and what
+/// works differently or not at all because of it
+fn check_backend(jk: &JanusKey) -> Check {
+    let log = jk.metadata_store.backend();
+    let content = jk.content_store.backend();
+    let mut limits = Vec::new();
+    if !log.capabilities().append {
+        limits.push(format!(
+            "{} has no append, so the log is kept in short segments",
+            log.name()
+        ));
+    }
+    if !content.capabilities().range_reads {
+        limits.push(format!(
+            "{} has no range reads, so blobs stay unpacked",
+            content.name()
+        ));
+    }
+    if !content.capabilities().truncate {
+        limits.push(format!(
+            "{} cannot truncate in place, so secure obliteration is refused",
+            content.name()
+        ));
+    }
+
+    let names = if log.name() == content.name() {
+        log.name().to_string()
+    } else {
+        format!("{} (log), {} (content)", log.name(), content.name())
+    };
+    if limits.is_empty() {
+        Check::new("backend", CheckStatus::Ok, names)
+    } else {
+        Check::new(
+            "backend",
+            CheckStatus::Warn,
+            format!("{}: {}", names, limits.join("; ")),
+        )
+    }
+}
+
+/// Whether a daemon holds the store, or died leaving its socket behind
+#[cfg(unix)]
+fn check_lock(root: &Path) -> Check {
+    use std::fs::{OpenOptions, TryLockError};
+    let dir = root.join(".januskey");
+    let lock = OpenOptions::new().write(true).open(dir.join("daemon.lock"));
+    let held = match lock {
+        Ok(file) => match file.try_lock() {
+            Ok(()) => false,
+            Err(TryLockError::WouldBlock) => true,
+            Err(TryLockError::Error(e)) => {
+                return Check::new("lock", CheckStatus::Warn, format!("daemon.lock: {}", e))
+            }
+        },
+        Err(_) => false,
+    };
+    let socket = crate::daemon::socket_path(root);
+    match (held, socket.exists()) {
+        (true, _) => Check::new("lock", CheckStatus::Ok, "held by a running jk daemon"),
+        (false, true) => Check::new(
+            "lock",
+            CheckStatus::Warn,
+            "daemon.sock was left by a daemon that is no longer running; \
+             the next `jk daemon` replaces it",
+        ),
+        (false, false) => Check::new("lock", CheckStatus::Ok, "not held; no daemon is running"),
+    }
+}
+
+#[cfg(not(unix))]
+fn check_lock(_root: &Path) -> Check {
+    Check::new("lock", CheckStatus::Ok, "no daemon on this platform")
+}
+
+/// The type of filesystem `dir` is on, from the longest mount point
+/// containing it
+#[cfg(target_os = "linux")]
+fn filesystem_type(dir: &Path) -> Option<String> {
+    let mounts = fs::read_to_string("/proc/self/mounts").ok()?;
+    let dir = dir.canonicalize().ok()?;
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let mount_point = fields.nth(1)?.replace("\\040", " ");
+            let fs_type = fields.next()?;
+            dir.starts_with(&mount_point)
+                .then(|| (mount_point.len(), fs_type.to_string()))
+        })
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, fs_type)| fs_type)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn filesystem_type(_dir: &Path) -> Option<String> {
+    None
+}
+
+fn check_filesystem(store_dir: &Path, config: &Config) -> Check {
+    match filesystem_type(store_dir) {
+        Some(fs_type) if NETWORK_FILESYSTEMS.contains(&fs_type.as_str()) && config.memory_map => {
+            Check::new(
+                "filesystem",
+                CheckStatus::Warn,
+                format!(
+                    "{} is on {}, a network filesystem, with memory_map on: \
+                     a file truncated on the server can crash jk; turn memory_map off",
+                    store_dir.display(),
+                    fs_type
+                ),
+            )
+        }
+        Some(fs_type) => Check::new(
+            "filesystem",
+            CheckStatus::Ok,
+            format!("{} is on {}", store_dir.display(), fs_type),
+        ),
+        None => Check::new(
+            "filesystem",
+            CheckStatus::Ok,
+            format!("type of {} unknown on this platform", store_dir.display()),
+        ),
+    }
+}
+
+fn check_reflink(store_dir: &Path) -> Check {
+    match supports_reflink(store_dir) {
+        Ok(true) => Check::new(
+            "reflink",
+            CheckStatus::Ok,
+            "supported: captures and copies clone data instead of copying it",
+        ),
+        Ok(false) => Check::new(
+            "reflink",
+            CheckStatus::Ok,
+            "not supported: captures and copies are full copies",
+        ),
+        Err(e) => Check::new(
+            "reflink",
+            CheckStatus::Fail,
+            format!("cannot write to {}: {}", store_dir.display(), e),
+        ),
+    }
+}
+
+#[cfg(unix)]
+fn check_xattr(store_dir: &Path) -> Check {
+    let probe = store_dir.join(format!(".xattr-probe.{}", uuid::Uuid::new_v4().simple()));
+    if let Err(e) = fs::write(&probe, b"januskey") {
+        return Check::new(
+            "xattr",
+            CheckStatus::Fail,
+            format!("cannot write to {}: {}", store_dir.display(), e),
+        );
+    }
+    let supported = xattr::set(&probe, "user.januskey.probe", b"1").is_ok();
+    let _ = fs::remove_file(&probe);
+    match supported {
+        true => Check::new("xattr", CheckStatus::Ok, "extended attributes supported"),
+        false => Check::new(
+            "xattr",
+            CheckStatus::Warn,
+            "extended attributes not supported: files restored here lose theirs",
+        ),
+    }
+}
+
+#[cfg(not(unix))]
+fn check_xattr(_store_dir: &Path) -> Check {
+    Check::new("xattr", CheckStatus::Ok, "not checked on this platform")
+}
+
+/// Whether this clock is behind the times history was recorded at.
+/// Undo and gc order operations by time, so a slow clock misorders new
+/// operations before ones already recorded.
+fn check_clock(jk: &JanusKey) -> Check {
+    let newest = jk
+        .metadata_store
+        .all_operations()
+        .iter()
+        .map(|op| op.timestamp)
+        .chain(jk.transaction_manager.all().iter().map(|tx| tx.started_at))
+        .max();
+    let now = Utc::now();
+    match newest {
+        Some(newest) if newest - now > CLOCK_SKEW_ALLOWED => Check::new(
+            "clock",
+            CheckStatus::Warn,
+            format!(
+                "history was recorded up to {}s ahead of this clock ({}); \
+                 new operations will sort before it",
+                (newest - now).num_seconds(),
+                newest.format("%Y-%m-%d %H:%M:%S UTC")
+            ),
+        ),
+        _ => Check::new(
+            "clock",
+            CheckStatus::Ok,
+            "no history recorded ahead of this clock",
+        ),
+    }
+}
+
+/// Transactions left open or partly rolled back
+fn check_transactions(jk: &JanusKey) -> Check {
+    let partial = jk
+        .transaction_manager
+        .all()
+        .iter()
+        .filter(|tx| tx.state == TransactionState::PartiallyRolledBack)
+        .count();
+    if partial > 0 {
+        return Check::new(
+            "transactions",
+            CheckStatus::Warn,
+            format!(
+                "{} partially rolled back; finish with `jk rollback --resume`",
+                partial
+            ),
+        );
+    }
+    match jk.transaction_manager.active() {
+        Some(tx) if Utc::now() - tx.started_at > STALE_TRANSACTION => Check::new(
+            "transactions",
+            CheckStatus::Warn,
+            format!(
+                "{} has been open since {}; commit or roll it back",
+                &tx.id[..8],
+                tx.started_at.format("%Y-%m-%d %H:%M:%S")
+            ),
+        ),
+        Some(tx) => Check::new(
+            "transactions",
+            CheckStatus::Ok,
+            format!("{} is open", &tx.id[..8]),
+        ),
+        None => Check::new("transactions", CheckStatus::Ok, "none open"),
+    }
+}
+
+/// Log lines, transactions and content lost or left behind by interrupted
+/// writes (see [`JanusKey::heal`]), and temporary files of atomic writes
+/// that never finished
+fn check_journal(root: &Path, store_dir: &Path) -> Check {
+    let temporary = [root.join(".januskey"), store_dir.to_path_buf()]
+        .iter()
+        .flat_map(|dir| walkdir::WalkDir::new(dir).max_depth(2))
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy();
+            entry.file_type().is_file() && name.starts_with('.') && name.ends_with(".tmp")
+        })
+        .map(|entry| entry.into_path())
+        .collect::<std::collections::BTreeSet<_>>()
+        .len();
+
+    let report = match JanusKey::heal(root, true) {
+        Ok(report) => report,
+        Err(e) => return Check::new("journal", CheckStatus::Fail, e.to_string()),
+    };
+    let mut problems = Vec::new();
+    let mut status = CheckStatus::Ok;
+    if !report.damaged.is_empty() || report.transactions_error.is_some() {
+        status = CheckStatus::Fail;
+        problems.push(format!(
+            "{} unreadable log lines{}; run `jk heal`",
+            report.damaged.len(),
+            if report.transactions_error.is_some() {
+                " and an unreadable transaction log"
+            } else {
+                ""
+            }
+        ));
+    }
+    if !report.missing_operations.is_empty() || !report.missing_content.is_empty() {
+        status = status.max(CheckStatus::Warn);
+        problems.push(format!(
+            "{} operations missing from transactions, {} without their content",
+            report.missing_operations.len(),
+            report.missing_content.len()
+        ));
+    }
+    if temporary > 0 {
+        status = status.max(CheckStatus::Warn);
+        problems.push(format!(
+            "{} temporary files left by interrupted writes",
+            temporary
+        ));
+    }
+    if !report.orphaned_blobs.is_empty() {
+        let bytes = report.orphaned_blobs.iter().map(|(_, size)| size).sum();
+        problems.push(format!(
+            "{} blobs ({}) no operation needs; `jk gc` frees them",
+            report.orphaned_blobs.len(),
+            human_bytes(bytes)
+        ));
+    }
+    match problems.is_empty() {
+        true => Check::new(
+            "journal",
+            status,
+            format!("{} operations readable and complete", report.operations),
+        ),
+        false => Check::new("journal", status, problems.join("; ")),
+    }
+}
+
+/// Whether the keystore can be read by anyone but its owner
+#[cfg(unix)]
+fn check_keystore(root: &Path) -> Check {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = root.join(".januskey").join("keys");
+    let keystore = dir.join("keystore.jks");
+    let Ok(meta) = fs::metadata(&keystore) else {
+        return Check::new("keystore", CheckStatus::Ok, "no keystore");
+    };
+    let mode = meta.permissions().mode() & 0o777;
+    let dir_mode = fs::metadata(&dir).map_or(0, |m| m.permissions().mode() & 0o777);
+    if mode & 0o077 != 0 {
+        Check::new(
+            "keystore",
+            CheckStatus::Fail,
+            format!(
+                "keystore.jks is mode {:o}, open to other users; chmod 600 {}",
+                mode,
+                keystore.display()
+            ),
+        )
+    } else if dir_mode & 0o022 != 0 {
+        Check::new(
+            "keystore",
+            CheckStatus::Warn,
+            format!(
+                "{} is mode {:o}: others can replace the keystore; chmod 700 it",
+                dir.display(),
+                dir_mode
+            ),
+        )
+    } else {
+        Check::new("keystore", CheckStatus::Ok, format!("mode {:o}", mode))
+    }
+}
+
+#[cfg(not(unix))]
+fn check_keystore(_root: &Path) -> Check {
+    Check::new("keystore", CheckStatus::Ok, "not checked on this platform")
+}
+
+/// Free space where content is stored, and room under `max_store_bytes`
+fn check_free_space(store_dir: &Path, jk: Option<&JanusKey>) -> Check {
+    let (available, total) = match (fs4::available_space(store_dir), fs4::total_space(store_dir)) {
+        (Ok(available), Ok(total)) => (available, total),
+        (Err(e), _) | (_, Err(e)) => {
+            return Check::new("free_space", CheckStatus::Fail, e.to_string());
+        }
+    };
+    let percent = |part: u64, whole: u64| part as f64 * 100.0 / whole.max(1) as f64;
+    let free = percent(available, total);
+    let mut message = format!(
+        "{} of {} free ({:.0}%)",
+        human_bytes(available),
+        human_bytes(total),
+        free
+    );
+    let mut status = match free {
+        f if f < 1.0 => CheckStatus::Fail,
+        f if f < 5.0 => CheckStatus::Warn,
+        _ => CheckStatus::Ok,
+    };
+    let quota = jk.and_then(|jk| {
+        let max = jk.config.max_store_bytes?;
+        Some((jk.content_store.total_size().ok()?, max))
+    });
+    if let Some((used, max)) = quota {
+        let used_percent = percent(used, max);
+        message.push_str(&format!(
+            "; store at {:.0}% of max_store_bytes ({} of {})",
+            used_percent,
+            human_bytes(used),
+            human_bytes(max)
+        ));
+        if used_percent >= 90.0 {
+            status = status.max(CheckStatus::Warn);
+        }
+    }
+    Check::new("free_space", status, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_diagnose_fresh_and_damaged_stores() {
+        let tmp = TempDir::new().unwrap();
+        let report = diagnose(tmp.path());
+        assert_eq!(report.checks[1].name, "store");
+        assert_eq!(report.status, CheckStatus::Fail);
+
+        let mut jk = JanusKey::init(tmp.path()).unwrap();
+        fs::write(tmp.path().join("a.txt"), "one").unwrap();
+        jk.delete("a.txt").unwrap();
+        let report = diagnose(tmp.path());
+        let names: Vec<&str> = report.checks.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "config",
+                "store",
+                "backend",
+                "lock",
+                "filesystem",
+                "reflink",
+                "xattr",
+                "clock",
+                "transactions",
+                "journal",
+                "keystore",
+                "free_space"
+            ]
+        );
+        let status = |report: &DoctorReport, name: &str| {
+            report
+                .checks
+                .iter()
+                .find(|check| check.name == name)
+                .unwrap()
+                .status
+        };
+        assert_eq!(status(&report, "journal"), CheckStatus::Ok);
+        assert_eq!(status(&report, "config"), CheckStatus::Ok);
+        assert_eq!(status(&report, "backend"), CheckStatus::Ok);
+
+        // An operation recorded an hour ahead, a torn write's temporary
+        // file, and a keystore readable by everyone
+        let mut op = jk.metadata_store.operations()[0].clone();
+        op.id = "future".to_string();
+        op.timestamp = Utc::now() + TimeDelta::hours(1);
+        jk.metadata_store.append(op).unwrap();
+        fs::write(tmp.path().join(".januskey/.config.json.1234.tmp"), "").unwrap();
+        let keys = tmp.path().join(".januskey/keys");
+        fs::create_dir_all(&keys).unwrap();
+        fs::write(keys.join("keystore.jks"), "").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(keys.join("keystore.jks"), fs::Permissions::from_mode(0o644))
+                .unwrap();
+        }
+
+        let report = diagnose(tmp.path());
+        assert_eq!(status(&report, "clock"), CheckStatus::Warn);
+        assert_eq!(status(&report, "journal"), CheckStatus::Warn);
+        #[cfg(unix)]
+        assert_eq!(status(&report, "keystore"), CheckStatus::Fail);
+        assert_eq!(report.status, CheckStatus::Fail);
+
+        // A misspelt setting fails the config, and with it the store
+        let config_path = Config::path(tmp.path());
+        let mut document: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&config_path).unwrap()).unwrap();
+        document["compresion"] = serde_json::Value::Bool(false);
+        fs::write(&config_path, document.to_string()).unwrap();
+        let report = diagnose(tmp.path());
+        assert_eq!(status(&report, "config"), CheckStatus::Fail);
+        assert_eq!(status(&report, "store"), CheckStatus::Fail);
+    }
+}
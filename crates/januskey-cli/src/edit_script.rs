@@ -0,0 +1,312 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Edit Scripts: the sed-like commands behind `jk modify`
+// Substitutions and line deletes/appends/inserts, applied in order
+
+use crate::delta::LineDiff;
+use thiserror::Error;
+
+/// A script line that could not be parsed
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("line {line}: {message}")]
+pub struct ScriptError {
+    /// 1-based line in the script
+    pub line: usize,
+    pub message: String,
+}
+
+/// Which lines a line command applies to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+    /// A 1-based line number
+    Line(usize),
+    /// An inclusive range of 1-based line numbers
+    Range(usize, usize),
+    /// The last line (`$`)
+    Last,
+    /// Every line containing the text (`/text/`)
+    Contains(String),
+}
+
+/// One command of an edit script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditCommand {
+    /// `s/search/replace/[g]`: literal substitution, first match or all
+    Substitute {
+        search: String,
+        replace: String,
+        global: bool,
+    },
+    /// `<addr>d`: delete the addressed lines
+    Delete(Address),
+    /// `<addr>a text`: add a line after each addressed line
+    Append(Address, String),
+    /// `<addr>i text`: add a line before each addressed line
+    Insert(Address, String),
+}
+
+/// Commands run in order, each over the whole content left by the previous
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EditScript {
+    pub commands: Vec<EditCommand>,
+}
+
+impl EditScript {
+    /// Parse a script: one command per line; blank lines and lines
+    /// starting with `#` are skipped
+    pub fn parse(script: &str) -> Result<Self, ScriptError> {
+        let mut commands = Vec::new();
+        for (i, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            commands.push(EditCommand::parse(line).map_err(|message| ScriptError {
+                line: i + 1,
+                message,
+            })?);
+        }
+        Ok(Self { commands })
+    }
+
+    /// Apply every command to `content`
+    pub fn apply(&self, content: &str) -> String {
+        self.commands
+            .iter()
+            .fold(content.to_string(), |content, command| {
+                command.apply(&content)
+            })
+    }
+}
+
+impl EditCommand {
+    /// Parse a single command
+    pub fn parse(command: &str) -> Result<Self, String> {
+        if let Some(rest) = command.strip_prefix("s/") {
+            let parts: Vec<&str> = rest.split('/').collect();
+            if parts.len() < 2 {
+                return Err("expected s/search/replace/[g]".to_string());
+            }
+            if parts[0].is_empty() {
+                return Err("empty search text".to_string());
+            }
+            return Ok(Self::Substitute {
+                search: parts[0].to_string(),
+                replace: parts[1].to_string(),
+                global: parts.get(2).is_some_and(|f| f.contains('g')),
+            });
+        }
+
+        let (address, rest) = Address::parse(command)?;
+        let mut chars = rest.chars();
+        let op = chars.next();
+        let text = chars.as_str();
+        // `a text`, `a\text` and `a\` + ` text` all add "text"
+        let text = text.strip_prefix('\\').unwrap_or(text);
+        let text = text.strip_prefix(' ').unwrap_or(text).to_string();
+        match op {
+            Some('d') if text.is_empty() => Ok(Self::Delete(address)),
+            Some('a') => Ok(Self::Append(address, text)),
+            Some('i') => Ok(Self::Insert(address, text)),
+            _ => Err(format!("unknown command: {}", command)),
+        }
+    }
+
+    /// Apply this command to `content`
+    pub fn apply(&self, content: &str) -> String {
+        let address = match self {
+            Self::Substitute {
+                search,
+                replace,
+                global,
+            } => {
+                return if *global {
+                    content.replace(search, replace)
+                } else {
+                    content.replacen(search, replace, 1)
+                };
+            }
+            Self::Delete(address) | Self::Append(address, _) | Self::Insert(address, _) => address,
+        };
+
+        let lines: Vec<&str> = content.split_inclusive('\n').collect();
+        let mut out = String::with_capacity(content.len());
+        for (i, line) in lines.iter().enumerate() {
+            if !address.matches(i + 1, lines.len(), line) {
+                out.push_str(line);
+                continue;
+            }
+            match self {
+                Self::Insert(_, text) => {
+                    out.push_str(text);
+                    out.push('\n');
+                    out.push_str(line);
+                }
+                Self::Append(_, text) => {
+                    out.push_str(line);
+                    if !line.ends_with('\n') {
+                        out.push('\n');
+                    }
+                    out.push_str(text);
+                    out.push('\n');
+                }
+                _ => {}
+            }
+        }
+        out
+    }
+}
+
+/// `new` with only the `accepted` hunks of `diff` (the line diff from
+/// `original` to `new`) applied to `original`; the rest keep their
+/// original lines. Line endings are kept as they are in each text.
+pub fn apply_hunks(original: &str, new: &str, diff: &LineDiff, accepted: &[bool]) -> String {
+    if accepted.iter().all(|&a| a) {
+        return new.to_string();
+    }
+    let original_lines: Vec<&str> = original.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let mut out = String::with_capacity(original.len());
+    let mut next = 0;
+    for (hunk, &accept) in diff.hunks.iter().zip(accepted) {
+        out.push_str(&original_lines[next..hunk.original_start].concat());
+        if accept {
+            out.push_str(&new_lines[hunk.new_start..hunk.new_start + hunk.new_count].concat());
+        } else {
+            out.push_str(
+                &original_lines[hunk.original_start..hunk.original_start + hunk.original_count]
+                    .concat(),
+            );
+        }
+        next = hunk.original_start + hunk.original_count;
+    }
+    out.push_str(&original_lines[next..].concat());
+    out
+}
+
+impl Address {
+    /// Split a leading address off a command
+    fn parse(command: &str) -> Result<(Self, &str), String> {
+        if let Some(rest) = command.strip_prefix('$') {
+            return Ok((Self::Last, rest));
+        }
+        if let Some(rest) = command.strip_prefix('/') {
+            let end = rest
+                .find('/')
+                .ok_or_else(|| format!("unterminated address: {}", command))?;
+            if end == 0 {
+                return Err("empty address text".to_string());
+            }
+            return Ok((Self::Contains(rest[..end].to_string()), &rest[end + 1..]));
+        }
+
+        let number = |s: &str| -> Result<(usize, usize), String> {
+            let digits = s.len() - s.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            match s[..digits].parse::<usize>() {
+                Ok(n) if n > 0 => Ok((n, digits)),
+                _ => Err(format!(
+                    "expected a line number, '$' or /text/: {}",
+                    command
+                )),
+            }
+        };
+        let (start, len) = number(command)?;
+        let rest = &command[len..];
+        match rest.strip_prefix(',') {
+            Some(rest) => {
+                let (end, len) = number(rest)?;
+                if end < start {
+                    return Err(format!("range ends before it starts: {}", command));
+                }
+                Ok((Self::Range(start, end), &rest[len..]))
+            }
+            None => Ok((Self::Line(start), rest)),
+        }
+    }
+
+    /// Whether `line` (1-based `number` of `total`) is addressed
+    fn matches(&self, number: usize, total: usize, line: &str) -> bool {
+        match self {
+            Self::Line(n) => number == *n,
+            Self::Range(start, end) => (*start..=*end).contains(&number),
+            Self::Last => number == total,
+            Self::Contains(text) => line.contains(text.as_str()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        let script = EditScript::parse(
+            "# tidy up\n\
+             s/foo/bar/g\n\
+             \n\
+             2,3d\n\
+             /TODO/d\n\
+             $a the end\n\
+             1i\\header\n",
+        )
+        .unwrap();
+        assert_eq!(
+            script.commands,
+            vec![
+                EditCommand::Substitute {
+                    search: "foo".to_string(),
+                    replace: "bar".to_string(),
+                    global: true,
+                },
+                EditCommand::Delete(Address::Range(2, 3)),
+                EditCommand::Delete(Address::Contains("TODO".to_string())),
+                EditCommand::Append(Address::Last, "the end".to_string()),
+                EditCommand::Insert(Address::Line(1), "header".to_string()),
+            ]
+        );
+
+        let err = EditScript::parse("s/a/b/\n0d\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(EditScript::parse("3x").is_err());
+        assert!(EditScript::parse("3,1d").is_err());
+    }
+
+    #[test]
+    fn test_apply_in_order() {
+        let script =
+            EditScript::parse("s/one/uno/\n2d\n$a four\n1i zero\n/uno/a one-and-a-half").unwrap();
+        assert_eq!(
+            script.apply("one\ntwo\nthree"),
+            "zero\nuno\none-and-a-half\nthree\nfour\n"
+        );
+    }
+
+    #[test]
+    fn test_apply_selected_hunks() {
+        let original: String = (0..20).map(|i| format!("line {}\r\n", i)).collect();
+        let script = EditScript {
+            commands: vec![
+                EditCommand::parse("2d").unwrap(),
+                EditCommand::parse("$a end").unwrap(),
+            ],
+        };
+        let new = script.apply(&original);
+        let diff = crate::delta::line_diff(&original, &new);
+        assert_eq!(diff.hunks.len(), 2);
+
+        assert_eq!(apply_hunks(&original, &new, &diff, &[true, true]), new);
+        assert_eq!(
+            apply_hunks(&original, &new, &diff, &[false, false]),
+            original
+        );
+        let first_only = apply_hunks(&original, &new, &diff, &[true, false]);
+        assert!(!first_only.contains("line 1\r\n"));
+        assert!(first_only.ends_with("line 19\r\n"));
+        let last_only = apply_hunks(&original, &new, &diff, &[false, true]);
+        assert!(last_only.starts_with("line 0\r\nline 1\r\n"));
+        assert!(last_only.ends_with("line 19\r\nend\n"));
+    }
+}
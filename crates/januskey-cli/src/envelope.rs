@@ -0,0 +1,424 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Envelope Encryption: exports sealed to recipients' X25519 public keys
+// A random content key is wrapped for each recipient via ECDH and HKDF.
+// Exports can instead be sealed in the age format, which `age -d` opens
+// without JanusKey; the same X25519 keys serve as age recipients.
+
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use bech32::{FromBase32, ToBase32, Variant};
+use curve25519_dalek::montgomery::MontgomeryPoint;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::str::FromStr;
+use thiserror::Error;
+use uuid::Uuid;
+use zeroize::Zeroize;
+
+use crate::keys::{hkdf_sha256, x25519_public_key, KeyAlgorithm, KeyError, KeyManager, SecretKey};
+
+/// Format identifier recorded in every envelope
+pub const ENVELOPE_FORMAT: &str = "januskey-envelope/1";
+
+/// First line of every age file
+const AGE_HEADER: &[u8] = b"age-encryption.org/v1\n";
+
+/// Envelope errors
+#[derive(Error, Debug)]
+pub enum EnvelopeError {
+    #[error("Key error: {0}")]
+    Key(#[from] KeyError),
+
+    #[error("Key {0} is not an X25519 key")]
+    NotAgreementKey(Uuid),
+
+    #[error("Invalid X25519 public key: {0}")]
+    InvalidPublicKey(String),
+
+    #[error("No recipients given")]
+    NoRecipients,
+
+    #[error("Not encrypted to this key")]
+    NotRecipient,
+
+    #[error("Decryption failed: wrong key or tampered envelope")]
+    Decryption,
+
+    #[error("Unsupported envelope format: {0}")]
+    UnsupportedFormat(String),
+
+    #[error("Malformed envelope: {0}")]
+    Malformed(String),
+
+    #[error("age: {0}")]
+    Age(String),
+
+    #[error("Serialization error: {0}")]
+    Serde(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, EnvelopeError>;
+
+/// The content key, wrapped for one recipient
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnvelopeRecipient {
+    /// Hex-encoded X25519 public key of the recipient
+    pub public_key: String,
+    /// Hex-encoded ephemeral X25519 public key the wrapping key was
+    /// agreed with
+    pub ephemeral_key: String,
+    /// Hex-encoded AES-256-GCM nonce and wrapped content key
+    pub nonce: String,
+    pub wrapped_key: String,
+}
+
+/// Content encrypted so that any one of its recipients can decrypt it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    pub format: String,
+    pub recipients: Vec<EnvelopeRecipient>,
+    /// Hex-encoded AES-256-GCM nonce of the content
+    pub nonce: String,
+    /// Base64-encoded AES-256-GCM ciphertext of the content
+    pub ciphertext: String,
+}
+
+/// How content sealed to recipients is encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SealFormat {
+    /// A JSON [`Envelope`]
+    #[default]
+    Envelope,
+    /// An age file, which `age -d` decrypts with the recipient's identity
+    Age,
+}
+
+/// Parse an X25519 public key, hex-encoded (as `jk keys export-pub`
+/// prints) or as an age recipient (`age1...`)
+pub fn parse_public_key(key: &str) -> Result<[u8; 32]> {
+    let trimmed = key.trim();
+    let parsed = if trimmed.starts_with("age1") {
+        bech32::decode(trimmed)
+            .ok()
+            .filter(|(hrp, _, variant)| hrp == "age" && *variant == Variant::Bech32)
+            .and_then(|(_, data, _)| Vec::<u8>::from_base32(&data).ok())
+            .and_then(|bytes| bytes.try_into().ok())
+    } else {
+        decode_hex(trimmed)
+    };
+    parsed.ok_or_else(|| EnvelopeError::InvalidPublicKey(key.to_string()))
+}
+
+/// An X25519 public key as an age recipient (`age1...`)
+pub fn age_recipient(public_key: &[u8; 32]) -> String {
+    bech32::encode("age", public_key.to_base32(), Variant::Bech32).expect("HRP is valid")
+}
+
+/// An X25519 secret key as an age identity (`AGE-SECRET-KEY-1...`), for
+/// `age -d -i`
+pub fn age_identity(secret: &SecretKey) -> String {
+    bech32::encode(
+        "age-secret-key-",
+        secret.as_bytes().to_base32(),
+        Variant::Bech32,
+    )
+    .expect("HRP is valid")
+    .to_uppercase()
+}
+
+/// Encrypt `plaintext` to each of `recipients` in `format`, ready to write
+pub fn seal(plaintext: &[u8], recipients: &[[u8; 32]], format: SealFormat) -> Result<Vec<u8>> {
+    match format {
+        SealFormat::Envelope => Envelope::seal(plaintext, recipients)?.to_bytes(),
+        SealFormat::Age => seal_age(plaintext, recipients),
+    }
+}
+
+/// Whether `bytes` are sealed in either format
+pub fn is_sealed(bytes: &[u8]) -> bool {
+    bytes.starts_with(AGE_HEADER) || Envelope::from_bytes(bytes).is_some()
+}
+
+/// Decrypt what [`seal`] wrote, in either format, with the X25519 secret
+/// key of one of the recipients
+pub fn open(bytes: &[u8], secret: &SecretKey) -> Result<Vec<u8>> {
+    if bytes.starts_with(AGE_HEADER) {
+        return open_age(bytes, secret);
+    }
+    Envelope::from_bytes(bytes)
+        .ok_or_else(|| EnvelopeError::Malformed("neither an envelope nor an age file".to_string()))?
+        .open(secret)
+}
+
+/// [`open`] with an X25519 key from an unlocked key store
+pub fn open_with(bytes: &[u8], keys: &KeyManager, key_id: Uuid) -> Result<Vec<u8>> {
+    if keys.get(key_id)?.algorithm != KeyAlgorithm::X25519 {
+        return Err(EnvelopeError::NotAgreementKey(key_id));
+    }
+    open(bytes, &keys.retrieve(key_id)?)
+}
+
+fn seal_age(plaintext: &[u8], recipients: &[[u8; 32]]) -> Result<Vec<u8>> {
+    if recipients.is_empty() {
+        return Err(EnvelopeError::NoRecipients);
+    }
+    let recipients = recipients
+        .iter()
+        .map(|key| {
+            age::x25519::Recipient::from_str(&age_recipient(key))
+                .map_err(|e| EnvelopeError::InvalidPublicKey(e.to_string()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let age_error = |e: std::io::Error| EnvelopeError::Age(e.to_string());
+
+    let encryptor =
+        age::Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn age::Recipient))
+            .map_err(|e| EnvelopeError::Age(e.to_string()))?;
+    let mut sealed = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut sealed).map_err(age_error)?;
+    writer.write_all(plaintext).map_err(age_error)?;
+    writer.finish().map_err(age_error)?;
+    Ok(sealed)
+}
+
+fn open_age(bytes: &[u8], secret: &SecretKey) -> Result<Vec<u8>> {
+    let identity = age::x25519::Identity::from_str(&age_identity(secret))
+        .map_err(|e| EnvelopeError::Age(e.to_string()))?;
+    let decryptor =
+        age::Decryptor::new_buffered(bytes).map_err(|e| EnvelopeError::Malformed(e.to_string()))?;
+    let mut reader = decryptor
+        .decrypt(std::iter::once(&identity as &dyn age::Identity))
+        .map_err(|e| match e {
+            age::DecryptError::NoMatchingKeys => EnvelopeError::NotRecipient,
+            _ => EnvelopeError::Decryption,
+        })?;
+    let mut plaintext = Vec::new();
+    reader
+        .read_to_end(&mut plaintext)
+        .map_err(|_| EnvelopeError::Decryption)?;
+    Ok(plaintext)
+}
+
+impl Envelope {
+    /// Encrypt `plaintext` to each of `recipients`' X25519 public keys
+    pub fn seal(plaintext: &[u8], recipients: &[[u8; 32]]) -> Result<Self> {
+        if recipients.is_empty() {
+            return Err(EnvelopeError::NoRecipients);
+        }
+
+        let mut content_key = random::<32>();
+        let nonce = random::<12>();
+        let ciphertext = encrypt(&content_key, &nonce, plaintext);
+        let wrapped = recipients
+            .iter()
+            .map(|recipient| {
+                // A fresh ephemeral key per recipient, as in ECIES
+                let mut ephemeral_secret = random::<32>();
+                let ephemeral = x25519_public_key(&ephemeral_secret);
+                let shared = MontgomeryPoint(*recipient).mul_clamped(ephemeral_secret);
+                ephemeral_secret.zeroize();
+                let mut key = wrapping_key(&shared, &ephemeral, recipient);
+                let nonce = random::<12>();
+                let wrapped_key = encrypt(&key, &nonce, &content_key);
+                key.zeroize();
+                EnvelopeRecipient {
+                    public_key: hex::encode(recipient),
+                    ephemeral_key: hex::encode(ephemeral),
+                    nonce: hex::encode(nonce),
+                    wrapped_key: hex::encode(wrapped_key),
+                }
+            })
+            .collect();
+        content_key.zeroize();
+
+        Ok(Self {
+            format: ENVELOPE_FORMAT.to_string(),
+            recipients: wrapped,
+            nonce: hex::encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        })
+    }
+
+    /// Decrypt with the X25519 secret key of one of the recipients
+    pub fn open(&self, secret: &SecretKey) -> Result<Vec<u8>> {
+        if self.format != ENVELOPE_FORMAT {
+            return Err(EnvelopeError::UnsupportedFormat(self.format.clone()));
+        }
+        let malformed = |what: &str| EnvelopeError::Malformed(format!("invalid {}", what));
+
+        let public_key = x25519_public_key(secret.as_bytes());
+        let recipient = self
+            .recipients
+            .iter()
+            .find(|r| r.public_key.eq_ignore_ascii_case(&hex::encode(public_key)))
+            .ok_or(EnvelopeError::NotRecipient)?;
+        let ephemeral: [u8; 32] =
+            decode_hex(&recipient.ephemeral_key).ok_or_else(|| malformed("ephemeral key"))?;
+        let shared = MontgomeryPoint(ephemeral).mul_clamped(*secret.as_bytes());
+        let mut key = wrapping_key(&shared, &ephemeral, &public_key);
+        let wrapped_key =
+            hex::decode(&recipient.wrapped_key).map_err(|_| malformed("wrapped key"))?;
+        let content_key = decrypt(
+            &key,
+            &decode_hex(&recipient.nonce).ok_or_else(|| malformed("nonce"))?,
+            &wrapped_key,
+        );
+        key.zeroize();
+        let mut content_key: [u8; 32] = content_key?
+            .try_into()
+            .map_err(|_| EnvelopeError::Decryption)?;
+
+        let ciphertext = BASE64
+            .decode(&self.ciphertext)
+            .map_err(|_| malformed("ciphertext"))?;
+        let plaintext = decrypt(
+            &content_key,
+            &decode_hex(&self.nonce).ok_or_else(|| malformed("nonce"))?,
+            &ciphertext,
+        );
+        content_key.zeroize();
+        plaintext
+    }
+
+    /// Decrypt with an X25519 key from an unlocked key store
+    pub fn open_with(&self, keys: &KeyManager, key_id: Uuid) -> Result<Vec<u8>> {
+        if keys.get(key_id)?.algorithm != KeyAlgorithm::X25519 {
+            return Err(EnvelopeError::NotAgreementKey(key_id));
+        }
+        self.open(&keys.retrieve(key_id)?)
+    }
+
+    /// Encode for writing to disk
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec_pretty(self)?)
+    }
+
+    /// Decode an envelope, or `None` if `bytes` are not one
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        serde_json::from_slice::<Self>(bytes)
+            .ok()
+            .filter(|envelope| envelope.format == ENVELOPE_FORMAT)
+    }
+}
+
+/// Key that wraps the content key for one recipient, bound to both public
+/// keys of the exchange
+fn wrapping_key(shared: &MontgomeryPoint, ephemeral: &[u8; 32], recipient: &[u8; 32]) -> [u8; 32] {
+    hkdf_sha256(
+        shared.as_bytes(),
+        &[ephemeral.as_slice(), recipient.as_slice()].concat(),
+        ENVELOPE_FORMAT.as_bytes(),
+    )
+}
+
+fn encrypt(key: &[u8; 32], nonce: &[u8; 12], plaintext: &[u8]) -> Vec<u8> {
+    Aes256Gcm::new(key.into())
+        .encrypt(Nonce::from_slice(nonce), plaintext)
+        .expect("AES-GCM encryption of in-memory data cannot fail")
+}
+
+fn decrypt(key: &[u8; 32], nonce: &[u8; 12], ciphertext: &[u8]) -> Result<Vec<u8>> {
+    Aes256Gcm::new(key.into())
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| EnvelopeError::Decryption)
+}
+
+fn random<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    rand::rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+fn decode_hex<const N: usize>(s: &str) -> Option<[u8; N]> {
+    hex::decode(s).ok()?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open_for_each_recipient() {
+        let alice = SecretKey::new([1u8; 32]);
+        let bob = SecretKey::new([2u8; 32]);
+        let eve = SecretKey::new([3u8; 32]);
+        let recipients = [
+            x25519_public_key(alice.as_bytes()),
+            x25519_public_key(bob.as_bytes()),
+        ];
+
+        let envelope = Envelope::seal(b"signed bundle", &recipients).unwrap();
+        let envelope = Envelope::from_bytes(&envelope.to_bytes().unwrap()).unwrap();
+        assert_eq!(envelope.open(&alice).unwrap(), b"signed bundle");
+        assert_eq!(envelope.open(&bob).unwrap(), b"signed bundle");
+        assert!(matches!(
+            envelope.open(&eve),
+            Err(EnvelopeError::NotRecipient)
+        ));
+        assert!(Envelope::from_bytes(b"{\"payload\": \"\"}").is_none());
+        assert!(matches!(
+            Envelope::seal(b"", &[]),
+            Err(EnvelopeError::NoRecipients)
+        ));
+    }
+
+    #[test]
+    fn test_tampered_envelope_fails() {
+        let alice = SecretKey::new([1u8; 32]);
+        let mut envelope =
+            Envelope::seal(b"proof", &[x25519_public_key(alice.as_bytes())]).unwrap();
+        let mut ciphertext = BASE64.decode(&envelope.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        envelope.ciphertext = BASE64.encode(ciphertext);
+        assert!(matches!(
+            envelope.open(&alice),
+            Err(EnvelopeError::Decryption)
+        ));
+    }
+
+    #[test]
+    fn test_age_round_trip_and_key_encodings() {
+        let alice = SecretKey::new([1u8; 32]);
+        let eve = SecretKey::new([3u8; 32]);
+        let public_key = x25519_public_key(alice.as_bytes());
+
+        // The encodings agree with the age crate's own
+        let identity = age::x25519::Identity::from_str(&age_identity(&alice)).unwrap();
+        let recipient = age_recipient(&public_key);
+        assert_eq!(identity.to_public().to_string(), recipient);
+        assert_eq!(parse_public_key(&recipient).unwrap(), public_key);
+        assert_eq!(
+            parse_public_key(&hex::encode(public_key)).unwrap(),
+            public_key
+        );
+        assert!(parse_public_key("age1notakey").is_err());
+
+        let sealed = seal(b"signed bundle", &[public_key], SealFormat::Age).unwrap();
+        assert!(sealed.starts_with(AGE_HEADER));
+        assert!(is_sealed(&sealed));
+        assert_eq!(open(&sealed, &alice).unwrap(), b"signed bundle");
+        assert!(matches!(
+            open(&sealed, &eve),
+            Err(EnvelopeError::NotRecipient)
+        ));
+        // Readable by age itself
+        let plaintext = age::decrypt(&identity, &sealed).unwrap();
+        assert_eq!(plaintext, b"signed bundle");
+
+        let mut tampered = sealed.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(matches!(
+            open(&tampered, &alice),
+            Err(EnvelopeError::Decryption)
+        ));
+        assert!(!is_sealed(b"{}"));
+    }
+}
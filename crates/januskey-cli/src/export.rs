@@ -0,0 +1,341 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Signed History Export: operation records bundled for forensic hand-off
+// Bundles (and files signed with `jk sign`) carry detached Ed25519
+// signatures and verify without the store
+
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::keys::{KeyAlgorithm, KeyError, KeyManager};
+use crate::metadata::OperationMetadata;
+
+/// Format identifier recorded in every export payload
+pub const EXPORT_FORMAT: &str = "januskey-history-export/1";
+
+/// Format identifier recorded in every detached file signature
+pub const SIGNATURE_FORMAT: &str = "januskey-signature/1";
+
+/// History export errors
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("Key error: {0}")]
+    Key(#[from] KeyError),
+
+    #[error("Key {0} is not an Ed25519 key")]
+    NotSigningKey(Uuid),
+
+    #[error("Export carries no signatures")]
+    Unsigned,
+
+    #[error("Invalid signature by key {0}")]
+    BadSignature(Uuid),
+
+    #[error("File does not match its signature (SHA-256 {0})")]
+    ContentChanged(String),
+
+    #[error("Unsupported export format: {0}")]
+    UnsupportedFormat(String),
+
+    #[error("Malformed export: {0}")]
+    Malformed(String),
+
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Serialization error: {0}")]
+    Serde(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, ExportError>;
+
+/// On-disk encoding of a signed export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportEncoding {
+    Json,
+    Cbor,
+}
+
+/// The signed content: selected operations and where they came from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryExport {
+    pub format: String,
+    pub exported_at: DateTime<Utc>,
+    /// Working directory the operations were recorded in
+    pub source: String,
+    pub operations: Vec<OperationMetadata>,
+}
+
+impl HistoryExport {
+    /// Export the given operations, recorded under `source`
+    pub fn new(source: String, operations: Vec<OperationMetadata>) -> Self {
+        Self {
+            format: EXPORT_FORMAT.to_string(),
+            exported_at: Utc::now(),
+            source,
+            operations,
+        }
+    }
+}
+
+/// Detached Ed25519 signature over the payload bytes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportSignature {
+    pub key_id: Uuid,
+    /// Key store fingerprint of the signing key
+    pub fingerprint: String,
+    /// Hex-encoded Ed25519 public key
+    pub public_key: String,
+    /// Hex-encoded Ed25519 signature
+    pub signature: String,
+}
+
+impl ExportSignature {
+    /// Sign `payload` with `key`
+    pub fn new(key_id: Uuid, fingerprint: String, key: &SigningKey, payload: &[u8]) -> Self {
+        Self {
+            key_id,
+            fingerprint,
+            public_key: hex::encode(key.verifying_key().as_bytes()),
+            signature: hex::encode(key.sign(payload).to_bytes()),
+        }
+    }
+
+    /// Sign `payload` with an Ed25519 key from an unlocked key store
+    pub fn with_key_store(keys: &KeyManager, key_id: Uuid, payload: &[u8]) -> Result<Self> {
+        let metadata = keys.get(key_id)?;
+        if metadata.algorithm != KeyAlgorithm::Ed25519 {
+            return Err(ExportError::NotSigningKey(key_id));
+        }
+        let secret = keys.retrieve(key_id)?;
+        Ok(Self::new(
+            key_id,
+            metadata.fingerprint,
+            &SigningKey::from_bytes(secret.as_bytes()),
+            payload,
+        ))
+    }
+
+    /// Check this signature against `payload`
+    pub fn verify(&self, payload: &[u8]) -> Result<()> {
+        let bad = || ExportError::BadSignature(self.key_id);
+        let public_key: [u8; 32] = decode_hex(&self.public_key).ok_or_else(bad)?;
+        let signature: [u8; 64] = decode_hex(&self.signature).ok_or_else(bad)?;
+        VerifyingKey::from_bytes(&public_key)
+            .map_err(|_| bad())?
+            .verify(payload, &Signature::from_bytes(&signature))
+            .map_err(|_| bad())
+    }
+}
+
+/// A serialized [`HistoryExport`] with signatures over its exact bytes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedExport {
+    /// JSON text of the [`HistoryExport`]; signatures cover these bytes
+    pub payload: String,
+    pub signatures: Vec<ExportSignature>,
+}
+
+impl SignedExport {
+    /// Serialize an export, ready to be signed
+    pub fn new(export: &HistoryExport) -> Result<Self> {
+        Ok(Self {
+            payload: serde_json::to_string(export)?,
+            signatures: Vec::new(),
+        })
+    }
+
+    /// Sign with an Ed25519 key from an unlocked key store
+    pub fn sign_with(&mut self, keys: &KeyManager, key_id: Uuid) -> Result<()> {
+        let signature = ExportSignature::with_key_store(keys, key_id, self.payload.as_bytes())?;
+        self.signatures.push(signature);
+        Ok(())
+    }
+
+    /// Add a signature by `key`
+    pub fn sign(&mut self, key_id: Uuid, fingerprint: String, key: &SigningKey) {
+        self.signatures.push(ExportSignature::new(
+            key_id,
+            fingerprint,
+            key,
+            self.payload.as_bytes(),
+        ));
+    }
+
+    /// Verify every signature and decode the payload.
+    ///
+    /// Fails if the export is unsigned or any signature does not match.
+    /// Callers must still check that the public keys belong to the
+    /// expected signers.
+    pub fn verify(&self) -> Result<HistoryExport> {
+        if self.signatures.is_empty() {
+            return Err(ExportError::Unsigned);
+        }
+        for signature in &self.signatures {
+            signature.verify(self.payload.as_bytes())?;
+        }
+
+        let export: HistoryExport = serde_json::from_str(&self.payload)?;
+        if export.format != EXPORT_FORMAT {
+            return Err(ExportError::UnsupportedFormat(export.format));
+        }
+        Ok(export)
+    }
+
+    /// Encode for writing to disk
+    pub fn to_bytes(&self, encoding: ExportEncoding) -> Result<Vec<u8>> {
+        match encoding {
+            ExportEncoding::Json => Ok(serde_json::to_vec_pretty(self)?),
+            ExportEncoding::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(self, &mut bytes)
+                    .map_err(|e| ExportError::Malformed(e.to_string()))?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    /// Decode a JSON or CBOR export (detected from the first byte)
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        match bytes.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'{') => Ok(serde_json::from_slice(bytes)?),
+            Some(_) => {
+                ciborium::from_reader(bytes).map_err(|e| ExportError::Malformed(e.to_string()))
+            }
+            None => Err(ExportError::Malformed("empty file".to_string())),
+        }
+    }
+}
+
+/// A detached signature over a file's exact bytes (`jk sign`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileSignature {
+    pub format: String,
+    pub signed_at: DateTime<Utc>,
+    /// SHA-256 of the signed bytes, to tell a changed file from a forged
+    /// signature
+    pub sha256: String,
+    #[serde(flatten)]
+    pub signature: ExportSignature,
+}
+
+impl FileSignature {
+    /// Sign `content` with an Ed25519 key from an unlocked key store
+    pub fn sign_with(keys: &KeyManager, key_id: Uuid, content: &[u8]) -> Result<Self> {
+        Ok(Self {
+            format: SIGNATURE_FORMAT.to_string(),
+            signed_at: Utc::now(),
+            sha256: hex::encode(Sha256::digest(content)),
+            signature: ExportSignature::with_key_store(keys, key_id, content)?,
+        })
+    }
+
+    /// Check the signature against `content`. Callers must still check
+    /// that the public key belongs to the expected signer.
+    pub fn verify(&self, content: &[u8]) -> Result<()> {
+        if self.format != SIGNATURE_FORMAT {
+            return Err(ExportError::UnsupportedFormat(self.format.clone()));
+        }
+        if hex::encode(Sha256::digest(content)) != self.sha256 {
+            return Err(ExportError::ContentChanged(self.sha256.clone()));
+        }
+        self.signature.verify(content)
+    }
+
+    /// Encode for writing to disk
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = serde_json::to_vec_pretty(self)?;
+        bytes.push(b'\n');
+        Ok(bytes)
+    }
+
+    /// Decode a signature file
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+fn decode_hex<const N: usize>(s: &str) -> Option<[u8; N]> {
+    hex::decode(s).ok()?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::OperationType;
+    use std::path::PathBuf;
+
+    fn signed() -> SignedExport {
+        let op = OperationMetadata::new(OperationType::Delete, PathBuf::from("/tmp/evidence.txt"));
+        let export = HistoryExport::new("/tmp".to_string(), vec![op]);
+        let mut signed = SignedExport::new(&export).unwrap();
+        signed.sign(
+            Uuid::new_v4(),
+            "0011223344556677".to_string(),
+            &SigningKey::from_bytes(&[7u8; 32]),
+        );
+        signed
+    }
+
+    #[test]
+    fn test_sign_and_verify_roundtrip() {
+        let signed = signed();
+        for encoding in [ExportEncoding::Json, ExportEncoding::Cbor] {
+            let bytes = signed.to_bytes(encoding).unwrap();
+            let export = SignedExport::from_bytes(&bytes).unwrap().verify().unwrap();
+            assert_eq!(export.operations.len(), 1);
+            assert_eq!(export.operations[0].op_type, OperationType::Delete);
+        }
+    }
+
+    #[test]
+    fn test_file_signature() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let mut keys = KeyManager::new(tmp.path());
+        keys.init("test-passphrase").unwrap();
+        let key_id = keys
+            .generate(
+                KeyAlgorithm::Ed25519,
+                crate::keys::KeyPurpose::Signing,
+                None,
+                None,
+            )
+            .unwrap();
+
+        let signature = FileSignature::sign_with(&keys, key_id, b"release.tar").unwrap();
+        let signature = FileSignature::from_bytes(&signature.to_bytes().unwrap()).unwrap();
+        signature.verify(b"release.tar").unwrap();
+        assert_eq!(
+            signature.signature.public_key,
+            hex::encode(keys.public_key(key_id).unwrap())
+        );
+        assert!(matches!(
+            signature.verify(b"release.tgz"),
+            Err(ExportError::ContentChanged(_))
+        ));
+
+        let mut forged = signature.clone();
+        forged.signature.signature = hex::encode([0u8; 64]);
+        assert!(matches!(
+            forged.verify(b"release.tar"),
+            Err(ExportError::BadSignature(_))
+        ));
+    }
+
+    #[test]
+    fn test_tampered_export_fails() {
+        let mut signed = signed();
+        signed.payload = signed.payload.replace("evidence", "innocent");
+        assert!(matches!(signed.verify(), Err(ExportError::BadSignature(_))));
+
+        signed.signatures.clear();
+        assert!(matches!(signed.verify(), Err(ExportError::Unsigned)));
+    }
+}
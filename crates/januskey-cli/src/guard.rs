@@ -0,0 +1,326 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Path Guard: keep operations off the store and other critical paths
+// Deleting or overwriting `.januskey/` would destroy the history that
+// undoes everything else, so operations refuse to change it, anything
+// inside it, or a directory holding it, unless explicitly allowed. With
+// `confine_to_root` they also refuse paths outside the root, so a typo
+// cannot change `/etc/hosts` with its history kept in some project.
+
+use crate::backend::StorageUri;
+use crate::error::{JanusError, Result};
+use crate::operations::FileOperation;
+use crate::Config;
+use std::path::{Component, Path, PathBuf};
+
+/// Environment variable that, set to anything but empty, lets operations
+/// change protected paths (`--allow-protected`)
+pub const ALLOW_PROTECTED_ENV: &str = "JANUSKEY_ALLOW_PROTECTED";
+
+/// Environment variable that, set to anything but empty, lets operations
+/// change paths outside a confined root (`--allow-outside`)
+pub const ALLOW_OUTSIDE_ENV: &str = "JANUSKEY_ALLOW_OUTSIDE";
+
+/// Whether [`ALLOW_PROTECTED_ENV`] is set
+pub fn allow_protected_from_env() -> bool {
+    env_flag(ALLOW_PROTECTED_ENV)
+}
+
+/// Whether [`ALLOW_OUTSIDE_ENV`] is set
+pub fn allow_outside_from_env() -> bool {
+    env_flag(ALLOW_OUTSIDE_ENV)
+}
+
+fn env_flag(name: &str) -> bool {
+    std::env::var_os(name).is_some_and(|value| !value.is_empty())
+}
+
+/// Paths operations may not change
+#[derive(Debug, Clone, Default)]
+pub struct PathGuard {
+    /// Protected files and directories, resolved
+    protected: Vec<PathBuf>,
+    /// Let operations change them anyway
+    allowed: bool,
+    /// Directory, resolved, operations must stay inside, if confined
+    root: Option<PathBuf>,
+    /// Let operations change paths outside `root` anyway
+    outside_allowed: bool,
+}
+
+impl PathGuard {
+    /// Protect `paths`, each with everything beneath it
+    pub fn new(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        Self {
+            protected: paths.into_iter().map(|path| resolve(&path)).collect(),
+            ..Self::default()
+        }
+    }
+
+    /// The guard for the JanusKey at `root`: its `.januskey/` directory,
+    /// local storage and content directories, and the configured
+    /// `protected_paths`, relative to `root` unless absolute, confined to
+    /// `root` if `confine_to_root` is set. The overrides are taken from
+    /// [`ALLOW_PROTECTED_ENV`] and [`ALLOW_OUTSIDE_ENV`].
+    pub fn for_root(root: &Path, config: &Config) -> Self {
+        let mut paths = vec![root.join(".januskey")];
+        let local = |storage: &str| match StorageUri::parse(storage) {
+            Ok(uri @ StorageUri::Local(_)) => Some(root.join(uri.root())),
+            _ => None,
+        };
+        if let Ok(Some(uri @ StorageUri::Local(_))) = config.storage_uri() {
+            paths.push(root.join(uri.root()));
+        }
+        paths.extend(
+            config
+                .content_storage
+                .as_ref()
+                .and_then(|content| local(&content.storage)),
+        );
+        paths.extend(config.protected_paths.iter().map(|path| root.join(path)));
+        let guard = Self::new(paths)
+            .allowing(allow_protected_from_env())
+            .allowing_outside(allow_outside_from_env());
+        if config.confine_to_root {
+            guard.confined_to(root)
+        } else {
+            guard
+        }
+    }
+
+    /// Refuse paths outside `root`, symlinks followed
+    pub fn confined_to(mut self, root: &Path) -> Self {
+        self.root = Some(resolve(root));
+        self
+    }
+
+    /// Let operations change paths outside the root they are confined to,
+    /// or not
+    pub fn allowing_outside(mut self, allowed: bool) -> Self {
+        self.outside_allowed = allowed;
+        self
+    }
+
+    /// Let operations change protected paths, or not
+    pub fn allowing(mut self, allowed: bool) -> Self {
+        self.allowed = allowed;
+        self
+    }
+
+    /// Whether protected paths may be changed
+    pub fn is_allowed(&self) -> bool {
+        self.allowed
+    }
+
+    /// The protected path `path` is or is inside, or with `holding` also
+    /// one it holds, if any. With `holding`, `path` is being removed, so a
+    /// symlink there is the link itself rather than its target.
+    pub fn protecting(&self, path: &Path, holding: bool) -> Option<&Path> {
+        let path = if holding {
+            resolve_entry(path)
+        } else {
+            resolve(path)
+        };
+        self.protected
+            .iter()
+            .find(|protected| {
+                path.starts_with(protected) || (holding && protected.starts_with(&path))
+            })
+            .map(PathBuf::as_path)
+    }
+
+    /// Refuse to change `path` if it is or is inside a protected path, or
+    /// is outside the root, unless allowed
+    pub fn check(&self, path: &Path) -> Result<()> {
+        self.refuse(path, false)
+    }
+
+    /// Refuse to remove `path` if it is, is inside, or holds a protected
+    /// path, or is outside the root, unless allowed. A symlink is judged
+    /// by where it is, since removing or moving it leaves its target be.
+    pub fn check_removal(&self, path: &Path) -> Result<()> {
+        self.refuse(path, true)
+    }
+
+    fn refuse(&self, path: &Path, holding: bool) -> Result<()> {
+        let resolved = if holding {
+            resolve_entry(path)
+        } else {
+            resolve(path)
+        };
+        if let Some(root) = self.root.as_ref().filter(|_| !self.outside_allowed) {
+            if !resolved.starts_with(root) {
+                return Err(JanusError::OutsideRoot(if resolved == path {
+                    path.display().to_string()
+                } else {
+                    format!("{} (resolves to {})", path.display(), resolved.display())
+                }));
+            }
+        }
+        if self.allowed {
+            return Ok(());
+        }
+        match self.protecting(path, holding) {
+            Some(protected) if resolved == protected => {
+                Err(JanusError::ProtectedPath(path.display().to_string()))
+            }
+            Some(protected) => Err(JanusError::ProtectedPath(format!(
+                "{} (protects {})",
+                path.display(),
+                protected.display()
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Refuse `operation` if it changes a path [`PathGuard::check`]
+    /// refuses: what it deletes, writes or moves away, and where it moves
+    /// or copies to. Copying from a refused path only reads it and is let
+    /// through.
+    pub fn check_operation(&self, operation: &FileOperation) -> Result<()> {
+        match operation {
+            FileOperation::Delete { path } => self.check_removal(path),
+            FileOperation::Move {
+                source,
+                destination,
+            } => {
+                self.check_removal(source)?;
+                self.check(destination)
+            }
+            FileOperation::Copy { destination, .. } => self.check(destination),
+            _ => self.check(operation.path()),
+        }
+    }
+}
+
+/// `path` with symlinks resolved as far as it exists, and `.` and `..`
+/// removed from the rest
+fn resolve(path: &Path) -> PathBuf {
+    if let Ok(resolved) = path.canonicalize() {
+        return resolved;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => resolve(parent).join(name),
+        _ => normalize(path),
+    }
+}
+
+/// `path` with symlinks resolved in its parent but not its last
+/// component, so a symlink names the link rather than its target
+fn resolve_entry(path: &Path) -> PathBuf {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => resolve(parent).join(name),
+        (Some(_), Some(name)) => resolve(Path::new(".")).join(name),
+        _ => resolve(path),
+    }
+}
+
+/// `path` with `.` and `..` removed lexically
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_protects_store_and_configured_paths() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().canonicalize().unwrap();
+        std::fs::create_dir_all(root.join(".januskey/content")).unwrap();
+        std::fs::create_dir_all(root.join("secrets")).unwrap();
+        let config = Config {
+            protected_paths: vec!["secrets".to_string()],
+            ..Config::default()
+        };
+        let guard = PathGuard::for_root(&root, &config).allowing(false);
+
+        for refused in [
+            root.join(".januskey"),
+            root.join(".januskey/content/ab"),
+            root.join("sub/../.januskey/config.json"),
+            root.join("secrets/key.pem"),
+        ] {
+            assert!(guard.check(&refused).is_err(), "{}", refused.display());
+        }
+        assert!(guard.check(&root).is_ok());
+        assert!(guard.check_removal(&root).is_err());
+        assert_eq!(
+            guard.check(&root.join(".januskey")).unwrap_err().code(),
+            "protected_path"
+        );
+        assert!(guard.check(&root.join("a.txt")).is_ok());
+        assert!(guard.check(&root.join(".januskeyignore")).is_ok());
+
+        let copy_out = FileOperation::Copy {
+            source: root.join(".januskey/config.json"),
+            destination: root.join("config.json"),
+            preserve: Default::default(),
+        };
+        assert!(guard.check_operation(&copy_out).is_ok());
+        let move_in = FileOperation::Move {
+            source: root.join("a.txt"),
+            destination: root.join(".januskey/a.txt"),
+        };
+        assert!(guard.check_operation(&move_in).is_err());
+        assert!(guard.allowing(true).check_operation(&move_in).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_confines_to_root() {
+        let tmp = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        let root = tmp.path().canonicalize().unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.join("link")).unwrap();
+        let config = Config {
+            confine_to_root: true,
+            ..Config::default()
+        };
+        let guard = PathGuard::for_root(&root, &config).allowing_outside(false);
+
+        assert!(guard.check(&root.join("a.txt")).is_ok());
+        assert!(guard.check(&root.join("new/b.txt")).is_ok());
+        for refused in [
+            outside.path().join("hosts"),
+            root.join("../hosts"),
+            root.join("link/hosts"),
+        ] {
+            let error = guard.check(&refused).unwrap_err();
+            assert_eq!(error.code(), "outside_root", "{}", refused.display());
+        }
+        let copy_in = FileOperation::Copy {
+            source: outside.path().join("hosts"),
+            destination: root.join("hosts"),
+            preserve: Default::default(),
+        };
+        assert!(guard.check_operation(&copy_in).is_ok());
+        // Removing or moving the link leaves the target outside alone
+        assert!(guard.check_removal(&root.join("link")).is_ok());
+        let move_link = FileOperation::Move {
+            source: root.join("link"),
+            destination: root.join("moved"),
+        };
+        assert!(guard.check_operation(&move_link).is_ok());
+        assert!(guard.check_removal(&root.join("link/hosts")).is_err());
+        let guard = guard.allowing_outside(true);
+        assert!(guard.check(&root.join("link/hosts")).is_ok());
+        assert!(PathGuard::for_root(&root, &Config::default())
+            .check(&outside.path().join("hosts"))
+            .is_ok());
+    }
+}
@@ -0,0 +1,318 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// History Graph: operations grouped by transaction, with undo and rename links
+// Rendered as text for `jk history --graph` or as Graphviz DOT
+
+use crate::metadata::{OperationMetadata, OperationType};
+use crate::transaction::Transaction;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// How two operations are related
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    /// `to` undid `from` (an undo of an undo is a redo)
+    Undo,
+    /// `to` moved on the file `from` moved into place
+    Rename,
+}
+
+/// A relation between two operations, `from` the earlier
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryLink {
+    pub from: String,
+    pub to: String,
+    pub kind: LinkKind,
+}
+
+/// Operations, the transactions they ran in, and the links between them
+#[derive(Debug, Clone)]
+pub struct HistoryGraph<'a> {
+    /// Operations shown, oldest first
+    operations: Vec<&'a OperationMetadata>,
+    /// Transactions by ID
+    transactions: HashMap<&'a str, &'a Transaction>,
+    /// Links found among all of `history`, shown or not
+    links: Vec<HistoryLink>,
+}
+
+impl<'a> HistoryGraph<'a> {
+    /// Graph `shown` (oldest first), finding links across the whole
+    /// `history` so that an operation's undo is noted even when filtered out
+    pub fn build(
+        history: &'a [OperationMetadata],
+        shown: Vec<&'a OperationMetadata>,
+        transactions: &'a [Transaction],
+    ) -> Self {
+        let undone_by: HashMap<&str, &str> = history
+            .iter()
+            .filter_map(|op| Some((op.id.as_str(), op.undo_operation_id.as_deref()?)))
+            .collect();
+        let mut links = Vec::new();
+        // The move that last brought a file to each path
+        let mut moved_into: HashMap<&Path, &str> = HashMap::new();
+        for op in history {
+            if let Some(undo) = &op.undo_operation_id {
+                links.push(HistoryLink {
+                    from: op.id.clone(),
+                    to: undo.clone(),
+                    kind: LinkKind::Undo,
+                });
+            }
+            match op.op_type {
+                OperationType::Move => {
+                    if let Some(previous) = moved_into.remove(op.path.as_path()) {
+                        // Moving back to undo a move is an undo, not a rename
+                        if undone_by.get(previous) != Some(&op.id.as_str()) {
+                            links.push(HistoryLink {
+                                from: previous.to_string(),
+                                to: op.id.clone(),
+                                kind: LinkKind::Rename,
+                            });
+                        }
+                    }
+                    if let Some(destination) = &op.path_secondary {
+                        moved_into.insert(destination, &op.id);
+                    }
+                }
+                // A different file now, or none
+                OperationType::Create | OperationType::Delete => {
+                    moved_into.remove(op.path.as_path());
+                }
+                _ => {}
+            }
+        }
+
+        Self {
+            operations: shown,
+            transactions: transactions.iter().map(|t| (t.id.as_str(), t)).collect(),
+            links,
+        }
+    }
+
+    /// Every link found, in the order found
+    pub fn links(&self) -> &[HistoryLink] {
+        &self.links
+    }
+
+    /// Text graph, newest first like `git log --graph`: transactions are
+    /// bracketed, and each operation lists what it undid, what undid it and
+    /// the move it continues. Paths are shown relative to `root`.
+    pub fn render_text(&self, root: &Path) -> String {
+        let mut out = String::new();
+        let mut open: Option<&str> = None;
+        for op in self.operations.iter().rev() {
+            let tx = op.transaction_id.as_deref();
+            if open.is_some() && open != tx {
+                out.push_str("└─\n");
+            }
+            if tx.is_some() && open != tx {
+                let _ = writeln!(
+                    out,
+                    "┌─ {}",
+                    self.describe_transaction(tx.unwrap_or_default())
+                );
+            }
+            open = tx;
+
+            let gutter = if tx.is_some() { "│ " } else { "" };
+            let marker = if op.undone { "○" } else { "●" };
+            let _ = write!(
+                out,
+                "{}{} {} {:<6} {}",
+                gutter,
+                marker,
+                short(&op.id),
+                op.op_type.to_string(),
+                describe_paths(op, root)
+            );
+            for link in &self.links {
+                let note = match link.kind {
+                    LinkKind::Undo if link.from == op.id => {
+                        format!("undone by {}", short(&link.to))
+                    }
+                    LinkKind::Undo if link.to == op.id => format!("undoes {}", short(&link.from)),
+                    LinkKind::Rename if link.to == op.id => {
+                        format!("renamed after {}", short(&link.from))
+                    }
+                    _ => continue,
+                };
+                let _ = write!(out, "  [{}]", note);
+            }
+            out.push('\n');
+        }
+        if open.is_some() {
+            out.push_str("└─\n");
+        }
+        out
+    }
+
+    /// Graphviz DOT: one node per operation, a cluster per transaction,
+    /// dashed undo edges and bold rename edges, among the shown operations
+    pub fn to_dot(&self, root: &Path) -> String {
+        let mut out = String::from(
+            "digraph history {\n  rankdir=TB;\n  node [shape=box, fontname=\"monospace\"];\n",
+        );
+
+        let mut clusters: Vec<(&str, Vec<&OperationMetadata>)> = Vec::new();
+        for op in &self.operations {
+            match op.transaction_id.as_deref() {
+                Some(tx) => match clusters.iter_mut().find(|(id, _)| *id == tx) {
+                    Some((_, ops)) => ops.push(op),
+                    None => clusters.push((tx, vec![op])),
+                },
+                None => out.push_str(&dot_node(op, root, "  ")),
+            }
+        }
+        for (tx, ops) in &clusters {
+            let _ = writeln!(out, "  subgraph \"cluster_{}\" {{", tx);
+            let _ = writeln!(out, "    label={};", quote(&self.describe_transaction(tx)));
+            for op in ops {
+                out.push_str(&dot_node(op, root, "    "));
+            }
+            out.push_str("  }\n");
+        }
+
+        // Keep chronological order top to bottom
+        for pair in self.operations.windows(2) {
+            let _ = writeln!(
+                out,
+                "  {} -> {} [style=invis];",
+                quote(&pair[0].id),
+                quote(&pair[1].id)
+            );
+        }
+        let shown = |id: &str| self.operations.iter().any(|op| op.id == id);
+        for link in self.links.iter().filter(|l| shown(&l.from) && shown(&l.to)) {
+            let style = match link.kind {
+                LinkKind::Undo => "style=dashed, label=\"undo\"",
+                LinkKind::Rename => "style=bold, label=\"rename\"",
+            };
+            let _ = writeln!(
+                out,
+                "  {} -> {} [{}, constraint=false];",
+                quote(&link.from),
+                quote(&link.to),
+                style
+            );
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn describe_transaction(&self, id: &str) -> String {
+        match self.transactions.get(id) {
+            Some(tx) => match &tx.name {
+                Some(name) => format!("transaction {} \"{}\" ({})", short(id), name, tx.state),
+                None => format!("transaction {} ({})", short(id), tx.state),
+            },
+            None => format!("transaction {}", short(id)),
+        }
+    }
+}
+
+/// First eight characters of an ID
+fn short(id: &str) -> &str {
+    &id[..id.len().min(8)]
+}
+
+fn relative(path: &Path, root: &Path) -> PathBuf {
+    path.strip_prefix(root).unwrap_or(path).to_path_buf()
+}
+
+fn describe_paths(op: &OperationMetadata, root: &Path) -> String {
+    match &op.path_secondary {
+        Some(secondary) => format!(
+            "{} -> {}",
+            relative(&op.path, root).display(),
+            relative(secondary, root).display()
+        ),
+        None => relative(&op.path, root).display().to_string(),
+    }
+}
+
+fn dot_node(op: &OperationMetadata, root: &Path, indent: &str) -> String {
+    let label = format!(
+        "{} {}\n{}",
+        short(&op.id),
+        op.op_type,
+        describe_paths(op, root)
+    );
+    let style = if op.undone { ", style=dashed" } else { "" };
+    format!(
+        "{}{} [label={}{}];\n",
+        indent,
+        quote(&op.id),
+        quote(&label),
+        style
+    )
+}
+
+/// A DOT string literal
+fn quote(text: &str) -> String {
+    format!(
+        "\"{}\"",
+        text.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_links_and_rendering() {
+        let root = Path::new("/w");
+        let tx = Transaction::new(Some("tidy".to_string()));
+        let modify = OperationMetadata::new(OperationType::Modify, root.join("a.txt"));
+        let first = OperationMetadata::new(OperationType::Move, root.join("a.txt"))
+            .with_secondary_path(root.join("b.txt"))
+            .with_transaction_id(tx.id.clone());
+        let second = OperationMetadata::new(OperationType::Move, root.join("b.txt"))
+            .with_secondary_path(root.join("c.txt"))
+            .with_transaction_id(tx.id.clone());
+        let mut undone = modify.clone();
+        let undo = OperationMetadata::new(OperationType::Modify, root.join("a.txt"));
+        undone.undone = true;
+        undone.undo_operation_id = Some(undo.id.clone());
+        let history = vec![undone, first, second, undo];
+        let transactions = vec![tx];
+
+        let graph = HistoryGraph::build(&history, history.iter().collect(), &transactions);
+        assert_eq!(
+            graph.links(),
+            [
+                HistoryLink {
+                    from: history[0].id.clone(),
+                    to: history[3].id.clone(),
+                    kind: LinkKind::Undo,
+                },
+                HistoryLink {
+                    from: history[1].id.clone(),
+                    to: history[2].id.clone(),
+                    kind: LinkKind::Rename,
+                },
+            ]
+        );
+
+        let text = graph.render_text(root);
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines[0].contains(&format!("undoes {}", short(&history[0].id))));
+        assert!(lines[1].starts_with("┌─ transaction") && lines[1].contains("\"tidy\""));
+        assert!(lines[2].starts_with("│ ● ") && lines[2].contains("b.txt -> c.txt"));
+        assert!(lines[2].contains("renamed after"));
+        assert_eq!(lines[4], "└─");
+        assert!(lines[5].starts_with("○ ") && lines[5].contains("undone by"));
+
+        let dot = graph.to_dot(root);
+        assert!(dot.starts_with("digraph history {"));
+        assert!(dot.contains(&format!("subgraph \"cluster_{}\"", transactions[0].id)));
+        assert!(dot.contains("label=\"undo\""));
+        assert!(dot.contains("label=\"rename\""));
+    }
+}
@@ -0,0 +1,279 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Legal Holds: preserve content under litigation
+// Held operations survive gc and their content refuses obliteration
+
+use crate::actor::Actor;
+use crate::audit_sink::{AuditEvent, AuditSinks};
+use crate::backend::write_atomic;
+use crate::content_store::{ContentHash, ContentStore};
+use crate::error::{JanusError, Result};
+use crate::metadata::{MetadataStore, OperationMetadata};
+use crate::select::MATCH_OPTIONS;
+use chrono::{DateTime, Utc};
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// A hold on everything recorded under paths matching `pattern`, for a
+/// legal case. Released holds are kept as a record.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LegalHold {
+    pub id: String,
+    /// Case or matter reference (`ACME-123`)
+    pub case: String,
+    /// Glob over paths relative to the JanusKey root (`contracts/**`)
+    pub pattern: String,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub created_by: String,
+    pub released_at: Option<DateTime<Utc>>,
+    pub released_by: Option<String>,
+}
+
+impl LegalHold {
+    /// Whether the hold has not been released
+    pub fn is_active(&self) -> bool {
+        self.released_at.is_none()
+    }
+
+    /// Whether the hold covers `path`, relative to `root` or absolute
+    pub fn covers(&self, root: &Path, path: &Path) -> bool {
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        Pattern::new(&self.pattern)
+            .is_ok_and(|pattern| pattern.matches_path_with(relative, MATCH_OPTIONS))
+    }
+
+    /// Whether the hold covers either path of `op`
+    fn covers_operation(&self, root: &Path, op: &OperationMetadata) -> bool {
+        self.covers(root, &op.path)
+            || op
+                .path_secondary
+                .as_deref()
+                .is_some_and(|path| self.covers(root, path))
+    }
+}
+
+/// Obliteration of held content that went ahead anyway
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HoldOverride {
+    pub hold_id: String,
+    pub case: String,
+    /// What was obliterated: a path or a content hash
+    pub target: String,
+    pub justification: String,
+    pub timestamp: DateTime<Utc>,
+    pub user: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct HoldsFile {
+    holds: Vec<LegalHold>,
+    #[serde(default)]
+    overrides: Vec<HoldOverride>,
+}
+
+/// Legal holds and their overrides, kept in `.januskey/holds.json`.
+/// Placing, releasing and overriding a hold are forwarded to the audit
+/// sinks.
+pub struct LegalHolds {
+    path: PathBuf,
+    file: HoldsFile,
+    sinks: AuditSinks,
+}
+
+impl LegalHolds {
+    /// Open the holds at `path`, none if it does not exist
+    pub fn open(path: PathBuf) -> Result<Self> {
+        let file = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(&path)?)
+                .map_err(|e| JanusError::MetadataCorrupted(e.to_string()))?
+        } else {
+            HoldsFile::default()
+        };
+        Ok(Self {
+            path,
+            file,
+            sinks: AuditSinks::new(),
+        })
+    }
+
+    /// Forward hold events to `sinks`
+    pub fn with_sinks(mut self, sinks: AuditSinks) -> Self {
+        self.sinks = sinks;
+        self
+    }
+
+    fn save(&self) -> Result<()> {
+        write_atomic(&self.path, &serde_json::to_vec_pretty(&self.file)?)?;
+        Ok(())
+    }
+
+    /// Every hold, released ones included, oldest first
+    pub fn all(&self) -> &[LegalHold] {
+        &self.file.holds
+    }
+
+    /// Holds still in force
+    pub fn active(&self) -> impl Iterator<Item = &LegalHold> {
+        self.file.holds.iter().filter(|hold| hold.is_active())
+    }
+
+    /// Recorded overrides, oldest first
+    pub fn overrides(&self) -> &[HoldOverride] {
+        &self.file.overrides
+    }
+
+    /// Place a hold on paths matching `pattern` for `case`
+    pub fn add(&mut self, case: &str, pattern: &str, reason: Option<String>) -> Result<LegalHold> {
+        if case.trim().is_empty() {
+            return Err(JanusError::OperationFailed(
+                "a legal hold needs a case reference".to_string(),
+            ));
+        }
+        Pattern::new(pattern)?;
+        let actor = Actor::from_env();
+        let hold = LegalHold {
+            id: Uuid::new_v4().to_string(),
+            case: case.to_string(),
+            pattern: pattern.to_string(),
+            reason,
+            created_at: Utc::now(),
+            created_by: actor.to_string(),
+            released_at: None,
+            released_by: None,
+        };
+        self.file.holds.push(hold.clone());
+        self.save()?;
+        self.emit("LEGAL_HOLD_PLACED", &hold, hold.reason.clone());
+        Ok(hold)
+    }
+
+    /// Release the active holds whose ID (or unique prefix) or case is
+    /// `id_or_case`
+    pub fn release(&mut self, id_or_case: &str) -> Result<Vec<LegalHold>> {
+        let actor = Actor::from_env().to_string();
+        let now = Utc::now();
+        let mut released = Vec::new();
+        for hold in self.file.holds.iter_mut().filter(|hold| hold.is_active()) {
+            if hold.case == id_or_case || hold.id.starts_with(id_or_case) {
+                hold.released_at = Some(now);
+                hold.released_by = Some(actor.clone());
+                released.push(hold.clone());
+            }
+        }
+        if released.is_empty() {
+            return Err(JanusError::InvalidOperationId(format!(
+                "no active legal hold {}",
+                id_or_case
+            )));
+        }
+        self.save()?;
+        for hold in &released {
+            self.emit("LEGAL_HOLD_RELEASED", hold, None);
+        }
+        Ok(released)
+    }
+
+    /// The active hold covering `path`, if any
+    pub fn hold_on_path(&self, root: &Path, path: &Path) -> Option<&LegalHold> {
+        self.active().find(|hold| hold.covers(root, path))
+    }
+
+    /// IDs of the operations an active hold covers
+    pub fn held_operations(&self, root: &Path, metadata_store: &MetadataStore) -> HashSet<String> {
+        metadata_store
+            .operations()
+            .iter()
+            .filter(|op| self.active().any(|hold| hold.covers_operation(root, op)))
+            .map(|op| op.id.clone())
+            .collect()
+    }
+
+    /// Blobs held operations need, including the bases their deltas are
+    /// stored against, with the case holding each
+    pub fn held_content(
+        &self,
+        root: &Path,
+        content_store: &ContentStore,
+        metadata_store: &MetadataStore,
+    ) -> Result<HashMap<ContentHash, String>> {
+        let mut held = HashMap::new();
+        for op in metadata_store.operations() {
+            let Some(hold) = self.active().find(|hold| hold.covers_operation(root, op)) else {
+                continue;
+            };
+            let mut pending: Vec<ContentHash> =
+                [&op.content_hash, &op.new_content_hash, &op.delta_base]
+                    .into_iter()
+                    .flatten()
+                    .chain(op.stream_hashes())
+                    .cloned()
+                    .collect();
+            while let Some(hash) = pending.pop() {
+                if held.contains_key(&hash) {
+                    continue;
+                }
+                if let Some(base) = content_store.delta_base(&hash)? {
+                    pending.push(base);
+                }
+                held.insert(hash, hold.case.clone());
+            }
+        }
+        Ok(held)
+    }
+
+    /// Record that `target`, under `hold`, is being obliterated anyway
+    pub fn record_override(
+        &mut self,
+        hold: &LegalHold,
+        target: &str,
+        justification: &str,
+    ) -> Result<HoldOverride> {
+        let record = HoldOverride {
+            hold_id: hold.id.clone(),
+            case: hold.case.clone(),
+            target: target.to_string(),
+            justification: justification.to_string(),
+            timestamp: Utc::now(),
+            user: Actor::from_env().to_string(),
+        };
+        self.file.overrides.push(record.clone());
+        self.save()?;
+        self.emit(
+            "LEGAL_HOLD_OVERRIDDEN",
+            hold,
+            Some(format!("{}: {}", target, justification)),
+        );
+        Ok(record)
+    }
+
+    /// Forwarding is best effort: the holds file is the record of truth
+    fn emit(&self, action: &str, hold: &LegalHold, reason: Option<String>) {
+        let event = AuditEvent {
+            id: Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            action: action.to_string(),
+            category: "file".to_string(),
+            severity: if action == "LEGAL_HOLD_OVERRIDDEN" {
+                10
+            } else {
+                5
+            },
+            actor: Actor::from_env().to_string(),
+            key_id: None,
+            fingerprint: None,
+            content_hash: None,
+            reason: Some(match reason {
+                Some(reason) => format!("case {} ({}): {}", hold.case, hold.pattern, reason),
+                None => format!("case {} ({})", hold.case, hold.pattern),
+            }),
+        };
+        let _ = self.sinks.emit(&event);
+    }
+}
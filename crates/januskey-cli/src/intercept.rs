@@ -0,0 +1,567 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Interception: capture content before other processes change it (Linux)
+// fanotify permission events hold every open of a file under the root
+// until its content is in the content store, before any truncation.
+// fanotify cannot report unlinks and renames, so inotify does, and they
+// are recorded against the content last captured. Opt in with
+// `jk daemon --intercept`, which needs CAP_SYS_ADMIN.
+
+use crate::content_store::{ContentHash, ContentStore};
+use crate::error::{JanusError, Result};
+use crate::metadata::{FileMetadata, OperationMetadata, OperationType};
+use crate::policy::PathPolicies;
+use crate::select::Excludes;
+use crate::JanusKey;
+use chrono::TimeDelta;
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use nix::sys::fanotify::{
+    EventFFlags, Fanotify, FanotifyEvent, FanotifyResponse, InitFlags, MarkFlags, MaskFlags,
+    Response,
+};
+use nix::sys::inotify::{self, AddWatchFlags, Inotify, InotifyEvent, WatchDescriptor};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+/// Actor that changes seen by the interceptor are recorded against
+pub const INTERCEPT_ACTOR: &str = "intercept";
+
+/// A change the interceptor sees within this long of an operation the
+/// store recorded itself, on the same path, is taken to be that operation
+const RECORDED_WITHIN: TimeDelta = TimeDelta::seconds(5);
+
+/// How often the event loop checks whether it should stop, in ms
+const POLL_INTERVAL_MS: u16 = 500;
+
+/// A file's content as last captured
+#[derive(Debug, Clone)]
+struct Captured {
+    /// Where the content is in the store: the envelope if it is sealed
+    stored: ContentHash,
+    /// Hash of the content itself, when it is sealed
+    plaintext: Option<ContentHash>,
+    size: u64,
+    modified: Option<SystemTime>,
+    metadata: FileMetadata,
+}
+
+impl Captured {
+    fn content_hash(&self) -> &ContentHash {
+        self.plaintext.as_ref().unwrap_or(&self.stored)
+    }
+
+    /// Operation of type `op_type` on `path` that restores this content
+    /// when undone
+    fn operation(&self, op_type: OperationType, path: &Path) -> OperationMetadata {
+        let mut op = OperationMetadata::new(op_type, path.to_path_buf())
+            .with_content_hash(self.stored.clone())
+            .with_original_metadata(self.metadata.clone());
+        op.plaintext_hash = self.plaintext.clone();
+        op.actor = Some(INTERCEPT_ACTOR.to_string());
+        op
+    }
+}
+
+/// What the event loop knows of the tree
+#[derive(Default)]
+struct State {
+    captured: HashMap<PathBuf, Captured>,
+    /// Directory each inotify watch is on
+    watches: HashMap<WatchDescriptor, PathBuf>,
+}
+
+/// fanotify and inotify groups watching a store's root
+pub struct Interceptor {
+    root: PathBuf,
+    excludes: Excludes,
+    policies: PathPolicies,
+    /// The daemon's content store, used without its lock, so capturing
+    /// never waits on a request that is itself waiting on a capture
+    content_store: Arc<ContentStore>,
+    fanotify: Fanotify,
+    inotify: Inotify,
+    /// Refuse opens whose content could not be captured
+    fail_closed: bool,
+}
+
+impl Interceptor {
+    /// The groups to watch `jk`'s root with, capturing into its content
+    /// store. Fails without CAP_SYS_ADMIN.
+    pub fn new(jk: &JanusKey) -> Result<Self> {
+        let fanotify = Fanotify::init(
+            InitFlags::FAN_CLASS_CONTENT | InitFlags::FAN_CLOEXEC | InitFlags::FAN_NONBLOCK,
+            EventFFlags::O_RDONLY | EventFFlags::O_LARGEFILE | EventFFlags::O_CLOEXEC,
+        )
+        .map_err(|e| {
+            JanusError::Daemon(format!(
+                "cannot intercept (fanotify needs CAP_SYS_ADMIN): {}",
+                e
+            ))
+        })?;
+        let inotify =
+            Inotify::init(inotify::InitFlags::IN_CLOEXEC | inotify::InitFlags::IN_NONBLOCK)
+                .map_err(std::io::Error::from)?;
+        Ok(Self {
+            root: jk.root.clone(),
+            excludes: Excludes::from_ignore_file(&jk.root, &[] as &[&str])?,
+            policies: jk.policies()?,
+            content_store: Arc::clone(&jk.content_store),
+            fanotify,
+            inotify,
+            fail_closed: false,
+        })
+    }
+
+    /// Refuse an open, rather than let it through, when its content cannot
+    /// be captured
+    pub fn fail_closed(mut self, fail_closed: bool) -> Self {
+        self.fail_closed = fail_closed;
+        self
+    }
+
+    /// Watch the tree, recording the changes other processes make to it in
+    /// `store`, until `stop` is set. Every file is captured on starting.
+    ///
+    /// Changes the daemon makes itself are recorded by it, not here. The
+    /// groups close on returning, however it returns: opens held by them
+    /// would otherwise wait for ever.
+    pub fn run(self, store: &RwLock<JanusKey>, stop: &AtomicBool) -> Result<()> {
+        let (record, recorded) = mpsc::channel::<OperationMetadata>();
+        let (touch, touched) = mpsc::channel::<PathBuf>();
+        std::thread::scope(|scope| {
+            // Opening a file here is what captures it, through the event
+            // loop, so that nothing can change it unseen in between
+            scope.spawn(move || {
+                for path in touched {
+                    let _ = File::open(path);
+                }
+            });
+            scope.spawn(move || {
+                for op in recorded {
+                    let mut jk = store.write().unwrap_or_else(|e| e.into_inner());
+                    if recorded_already(&jk, &op) {
+                        continue;
+                    }
+                    if let Err(e) = jk.metadata_store.append(op) {
+                        tracing::warn!("cannot record intercepted change: {}", e);
+                    }
+                }
+            });
+
+            let mut state = State::default();
+            let result = self
+                .watch_tree(&mut state, &self.root, &touch)
+                .and_then(|()| self.event_loop(&mut state, &record, &touch, stop));
+            // Let the other threads finish
+            drop((record, touch));
+            result
+        })
+    }
+
+    fn event_loop(
+        &self,
+        state: &mut State,
+        record: &Sender<OperationMetadata>,
+        touch: &Sender<PathBuf>,
+        stop: &AtomicBool,
+    ) -> Result<()> {
+        while !stop.load(Ordering::SeqCst) {
+            let mut fds = [
+                PollFd::new(self.fanotify.as_fd(), PollFlags::POLLIN),
+                PollFd::new(self.inotify.as_fd(), PollFlags::POLLIN),
+            ];
+            match poll(&mut fds, PollTimeout::from(POLL_INTERVAL_MS)) {
+                Ok(0) | Err(Errno::EINTR) => continue,
+                Ok(_) => {}
+                Err(e) => return Err(std::io::Error::from(e).into()),
+            }
+            for event in pending(self.fanotify.read_events())? {
+                self.on_fanotify(state, &event, record)?;
+            }
+            let events = pending(self.inotify.read_events())?;
+            self.on_inotify(state, events, record, touch)?;
+        }
+        Ok(())
+    }
+
+    /// Mark `dir` and the directories under it, and have every file in
+    /// them opened, and so captured. Symbolic links are not followed,
+    /// even when `dir` is one, so nothing outside the tree is watched.
+    fn watch_tree(&self, state: &mut State, dir: &Path, touch: &Sender<PathBuf>) -> Result<()> {
+        let mut walker = walkdir::WalkDir::new(dir)
+            .follow_root_links(false)
+            .into_iter();
+        while let Some(entry) = walker.next() {
+            let entry = entry.map_err(std::io::Error::from)?;
+            if !self.watched(entry.path()) {
+                if entry.file_type().is_dir() {
+                    walker.skip_current_dir();
+                }
+                continue;
+            }
+            if entry.file_type().is_dir() {
+                self.fanotify
+                    .mark(
+                        MarkFlags::FAN_MARK_ADD | MarkFlags::FAN_MARK_ONLYDIR,
+                        MaskFlags::FAN_OPEN_PERM
+                            | MaskFlags::FAN_CLOSE_WRITE
+                            | MaskFlags::FAN_EVENT_ON_CHILD,
+                        None,
+                        Some(entry.path()),
+                    )
+                    .map_err(std::io::Error::from)?;
+                let wd = self
+                    .inotify
+                    .add_watch(
+                        entry.path(),
+                        AddWatchFlags::IN_CREATE
+                            | AddWatchFlags::IN_DELETE
+                            | AddWatchFlags::IN_MOVED_FROM
+                            | AddWatchFlags::IN_MOVED_TO
+                            | AddWatchFlags::IN_ONLYDIR,
+                    )
+                    .map_err(std::io::Error::from)?;
+                state.watches.insert(wd, entry.path().to_path_buf());
+            } else if entry.file_type().is_file() {
+                let _ = touch.send(entry.path().to_path_buf());
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether changes to `path` are captured: it is under the root, not
+    /// in `.januskey/`, not excluded, and its policy keeps history
+    fn watched(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        !relative.starts_with(".januskey")
+            && (relative.as_os_str().is_empty() || !self.excludes.is_excluded(relative))
+            && self.policies.resolve(path).history
+    }
+
+    /// Path of the file an event's descriptor is open on, if it is watched
+    fn watched_file(&self, fd: BorrowedFd<'_>) -> Option<PathBuf> {
+        let path = fs::read_link(format!("/proc/self/fd/{}", fd.as_raw_fd())).ok()?;
+        self.watched(&path).then_some(path)
+    }
+
+    fn on_fanotify(
+        &self,
+        state: &mut State,
+        event: &FanotifyEvent,
+        record: &Sender<OperationMetadata>,
+    ) -> Result<()> {
+        let Some(fd) = event.fd() else {
+            tracing::warn!("intercept event queue overflowed; changes went unseen");
+            return Ok(());
+        };
+        let mask = event.mask();
+        if mask.contains(MaskFlags::FAN_OPEN_PERM) {
+            // The opener waits for this answer, whatever happens
+            let allow = match self.capture(state, fd) {
+                Ok(()) => true,
+                Err(e) => {
+                    tracing::warn!("cannot capture before open: {}", e);
+                    !self.fail_closed
+                }
+            };
+            let response = if allow {
+                Response::FAN_ALLOW
+            } else {
+                Response::FAN_DENY
+            };
+            self.fanotify
+                .write_response(FanotifyResponse::new(fd, response))
+                .map_err(std::io::Error::from)?;
+        }
+        if mask.contains(MaskFlags::FAN_CLOSE_WRITE) {
+            let ours = event.pid() == std::process::id() as i32;
+            if let Err(e) = self.written(state, fd, (!ours).then_some(record)) {
+                tracing::warn!("cannot capture after write: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Capture the content of the file `fd` is open on, unless it is
+    /// unchanged since last captured
+    fn capture(&self, state: &mut State, fd: BorrowedFd<'_>) -> Result<()> {
+        let Some(path) = self.watched_file(fd) else {
+            return Ok(());
+        };
+        let mut file = File::from(fd.try_clone_to_owned()?);
+        let meta = file.metadata()?;
+        if !meta.is_file() {
+            return Ok(());
+        }
+        let modified = meta.modified().ok();
+        let unchanged = state.captured.get(&path).is_some_and(|captured| {
+            captured.size == meta.len() && modified.is_some() && captured.modified == modified
+        });
+        if !unchanged {
+            let mut content = Vec::new();
+            file.read_to_end(&mut content)?;
+            let captured = self.store(&path, &content, modified)?;
+            state.captured.insert(path, captured);
+        }
+        Ok(())
+    }
+
+    /// Capture a file closed after writing, and record the change, unless
+    /// `record` is `None` (the daemon changed it and recorded it itself)
+    fn written(
+        &self,
+        state: &mut State,
+        fd: BorrowedFd<'_>,
+        record: Option<&Sender<OperationMetadata>>,
+    ) -> Result<()> {
+        let Some(path) = self.watched_file(fd) else {
+            return Ok(());
+        };
+        let mut file = File::from(fd.try_clone_to_owned()?);
+        let modified = file.metadata()?.modified().ok();
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+        let hash = self.content_store.hash(&content);
+        if let Some(captured) = state.captured.get_mut(&path) {
+            if *captured.content_hash() == hash {
+                captured.modified = modified;
+                return Ok(());
+            }
+        }
+        let now = self.store(&path, &content, modified)?;
+        if let (Some(record), Some(before)) = (record, state.captured.get(&path)) {
+            let op = before
+                .operation(OperationType::Modify, &path)
+                .with_new_content_hash(now.stored.clone());
+            let _ = record.send(op);
+        }
+        state.captured.insert(path, now);
+        Ok(())
+    }
+
+    /// Store `content` of `path`, sealed if its policy says so
+    fn store(&self, path: &Path, content: &[u8], modified: Option<SystemTime>) -> Result<Captured> {
+        let (stored, plaintext) = if self.policies.resolve(path).encrypt_content {
+            let envelope = self.policies.seal(content)?;
+            (
+                self.content_store.store(&envelope)?,
+                Some(self.content_store.hash(content)),
+            )
+        } else {
+            (self.content_store.store(content)?, None)
+        };
+        Ok(Captured {
+            stored,
+            plaintext,
+            size: content.len() as u64,
+            modified,
+            metadata: FileMetadata::from_path(path)?,
+        })
+    }
+
+    fn on_inotify(
+        &self,
+        state: &mut State,
+        events: Vec<InotifyEvent>,
+        record: &Sender<OperationMetadata>,
+        touch: &Sender<PathBuf>,
+    ) -> Result<()> {
+        // Renames within the tree, by cookie, until their other half
+        let mut moved_from: HashMap<u32, PathBuf> = HashMap::new();
+        for event in events {
+            if event.mask.contains(AddWatchFlags::IN_Q_OVERFLOW) {
+                tracing::warn!("intercept event queue overflowed; changes went unseen");
+                continue;
+            }
+            if event.mask.contains(AddWatchFlags::IN_IGNORED) {
+                state.watches.remove(&event.wd);
+                continue;
+            }
+            let (Some(dir), Some(name)) = (state.watches.get(&event.wd), &event.name) else {
+                continue;
+            };
+            let path = dir.join(name);
+            if !self.watched(&path) {
+                continue;
+            }
+            let is_dir = event.mask.contains(AddWatchFlags::IN_ISDIR);
+            if event.mask.contains(AddWatchFlags::IN_CREATE) && is_dir {
+                self.watch_tree(state, &path, touch)?;
+            } else if event.mask.contains(AddWatchFlags::IN_DELETE) && !is_dir {
+                if let Some(captured) = state.captured.remove(&path) {
+                    let _ = record.send(captured.operation(OperationType::Delete, &path));
+                }
+            } else if event.mask.contains(AddWatchFlags::IN_MOVED_FROM) {
+                moved_from.insert(event.cookie, path);
+            } else if event.mask.contains(AddWatchFlags::IN_MOVED_TO) {
+                // Whatever the rename replaced is gone
+                if let Some(replaced) = state.captured.remove(&path) {
+                    let _ = record.send(replaced.operation(OperationType::Delete, &path));
+                }
+                match moved_from.remove(&event.cookie) {
+                    Some(source) => {
+                        state.rename(&source, &path);
+                        let mut op = OperationMetadata::new(OperationType::Move, source)
+                            .with_secondary_path(path);
+                        op.actor = Some(INTERCEPT_ACTOR.to_string());
+                        let _ = record.send(op);
+                    }
+                    // Moved in from outside the tree
+                    None if is_dir => self.watch_tree(state, &path, touch)?,
+                    None => {
+                        let _ = touch.send(path);
+                    }
+                }
+            }
+        }
+        // Moved out of the tree: as good as deleted from it
+        for source in moved_from.into_values() {
+            for (path, captured) in state.forget(&source) {
+                let _ = record.send(captured.operation(OperationType::Delete, &path));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl State {
+    /// Follow a rename of `from`, a file or directory, to `to`
+    fn rename(&mut self, from: &Path, to: &Path) {
+        for (path, captured) in self.forget(from) {
+            let relative = path.strip_prefix(from).unwrap_or(Path::new(""));
+            self.captured.insert(to.join(relative), captured);
+        }
+        for dir in self.watches.values_mut() {
+            if let Ok(relative) = dir.strip_prefix(from) {
+                *dir = to.join(relative);
+            }
+        }
+    }
+
+    /// Stop tracking `path` and everything under it, returning what was
+    /// captured there
+    fn forget(&mut self, path: &Path) -> Vec<(PathBuf, Captured)> {
+        let paths: Vec<PathBuf> = self
+            .captured
+            .keys()
+            .filter(|captured| captured.starts_with(path))
+            .cloned()
+            .collect();
+        paths
+            .into_iter()
+            .filter_map(|p| self.captured.remove(&p).map(|captured| (p, captured)))
+            .collect()
+    }
+}
+
+/// Events read from a non-blocking group; none if none are waiting
+fn pending<T>(events: nix::Result<Vec<T>>) -> Result<Vec<T>> {
+    match events {
+        Err(Errno::EAGAIN) => Ok(Vec::new()),
+        events => Ok(events.map_err(std::io::Error::from)?),
+    }
+}
+
+/// Whether the store already recorded `op`: an operation of the same type
+/// on the same path, not by the interceptor, shortly before
+fn recorded_already(jk: &JanusKey, op: &OperationMetadata) -> bool {
+    jk.metadata_store
+        .operations()
+        .iter()
+        .rev()
+        .take_while(|recorded| op.timestamp - recorded.timestamp <= RECORDED_WITHIN)
+        .any(|recorded| {
+            recorded.path == op.path
+                && recorded.op_type == op.op_type
+                && recorded.actor.as_deref() != Some(INTERCEPT_ACTOR)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use std::time::{Duration, Instant};
+    use tempfile::TempDir;
+
+    /// Wait up to a few seconds for `done`
+    fn eventually(done: impl Fn() -> bool) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            if done() {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        false
+    }
+
+    #[test]
+    fn test_intercepts_truncate_and_unlink() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        std::fs::write(root.join("a.txt"), "precious").unwrap();
+        std::fs::write(root.join("b.txt"), "also precious").unwrap();
+        JanusKey::init(root).unwrap();
+        // Needs CAP_SYS_ADMIN and a kernel with fanotify
+        let store = RwLock::new(JanusKey::open(root).unwrap());
+        let Ok(interceptor) = Interceptor::new(&store.read().unwrap()) else {
+            return;
+        };
+        let stop = AtomicBool::new(false);
+        let ops = || store.read().unwrap().metadata_store.operations().len();
+
+        std::thread::scope(|scope| {
+            let running = scope.spawn(|| interceptor.run(&store, &stop));
+            // Another process truncates one file and removes the other
+            std::thread::sleep(Duration::from_millis(500));
+            let status = std::process::Command::new("sh")
+                .arg("-c")
+                .arg("echo gone > a.txt && rm b.txt")
+                .current_dir(root)
+                .status()
+                .unwrap();
+            let recorded = eventually(|| ops() == 2);
+            stop.store(true, Ordering::SeqCst);
+            running.join().unwrap().unwrap();
+            assert!(status.success() && recorded);
+        });
+
+        let mut jk = store.into_inner().unwrap();
+        let recorded: Vec<_> = jk
+            .metadata_store
+            .operations()
+            .iter()
+            .map(|op| (op.op_type, op.id.clone()))
+            .collect();
+        assert!(recorded
+            .iter()
+            .any(|(op_type, _)| *op_type == OperationType::Modify));
+        assert!(recorded
+            .iter()
+            .any(|(op_type, _)| *op_type == OperationType::Delete));
+        for (_, id) in recorded.iter().rev() {
+            jk.undo(id).unwrap();
+        }
+        assert_eq!(
+            std::fs::read_to_string(root.join("a.txt")).unwrap(),
+            "precious"
+        );
+        assert_eq!(
+            std::fs::read_to_string(root.join("b.txt")).unwrap(),
+            "also precious"
+        );
+    }
+}
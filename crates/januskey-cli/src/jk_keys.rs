@@ -0,0 +1,64 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2025 Jonathan D.A. Jewell
+//
+// jk-keys: deprecated standalone entry point for `jk keys`
+// Kept as an alias so existing scripts keep working
+
+mod keys_cli;
+
+use clap::Parser;
+use colored::Colorize;
+use std::path::PathBuf;
+
+use keys_cli::KeysCommand;
+
+#[derive(Parser)]
+#[command(name = "jk-keys")]
+#[command(about = "JanusKey cryptographic key management (deprecated: use `jk keys`)")]
+#[command(version)]
+struct Cli {
+    /// Working directory (defaults to current)
+    #[arg(short, long, global = true)]
+    dir: Option<PathBuf>,
+
+    /// Print machine-readable JSON (list, show, status)
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Who the key operations are for, recorded in the audit log next to
+    /// the OS user (defaults to $JANUSKEY_ACTOR, or $SUDO_USER under sudo)
+    #[arg(long, global = true, value_name = "NAME")]
+    actor: Option<String>,
+
+    #[command(subcommand)]
+    command: KeysCommand,
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{} {}", "error:".red().bold(), e);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    eprintln!(
+        "{} jk-keys is deprecated; use `jk keys` instead",
+        "warning:".yellow().bold()
+    );
+
+    let dir = match cli.dir {
+        Some(dir) => dir,
+        None => std::env::current_dir()?,
+    };
+
+    if let Some(actor) = &cli.actor {
+        januskey::actor::validate_actor(actor)?;
+        // Read when each audit entry is written; set before any threads start
+        std::env::set_var(januskey::actor::ACTOR_ENV, actor);
+    }
+
+    keys_cli::run(&dir, cli.json, cli.command)
+}
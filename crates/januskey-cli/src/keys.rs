@@ -3,7 +3,8 @@
 // SPDX-FileCopyrightText: 2025 Jonathan D.A. Jewell
 //
 // JanusKey Key Management Module
-// Implements secure key generation, storage, rotation, and recovery
+// Implements secure key generation, storage, rotation, and recovery, and
+// HKDF-derived subkeys of stored master keys
 
 use aes_gcm::{
     aead::{Aead, KeyInit},
@@ -11,16 +12,22 @@
 };
 use argon2::{Algorithm as Argon2Algorithm, Argon2, Params, Version};
 use chrono::{DateTime, Utc};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::fs;
 use std::path::{Path, PathBuf};
+use subtle::ConstantTimeEq;
 use thiserror::Error;
 use uuid::Uuid;
 use zeroize::{Zeroize, ZeroizeOnDrop};
 
 use crate::attestation::AuditLog;
+use crate::backend::write_atomic;
+use crate::pkcs11::{TokenConfig, TokenSession};
+use crate::tpm::{self, TpmSeal};
 
 /// Key management errors
 #[derive(Error, Debug)]
@@ -34,15 +41,45 @@ pub enum KeyError {
     #[error("Invalid passphrase")]
     InvalidPassphrase,
 
+    #[error("Too many failed unlock attempts; try again in {0} seconds")]
+    Throttled(u64),
+
     #[error("Key not found: {0}")]
     KeyNotFound(Uuid),
 
     #[error("Key already revoked: {0}")]
     AlreadyRevoked(Uuid),
 
+    #[error("Key obliterated: {0}")]
+    Obliterated(Uuid),
+
+    #[error("Key {0} is derived; rotate its master key instead")]
+    Derived(Uuid),
+
+    #[error("Already derived as key {0}")]
+    AlreadyDerived(Uuid),
+
+    #[error("Key {0} is symmetric and has no public key")]
+    NoPublicKey(Uuid),
+
     #[error("Cryptographic error: {0}")]
     CryptoError(String),
 
+    #[error("Key store failed its integrity check: it has been altered or damaged")]
+    Tampered,
+
+    #[error("Malformed key store: {0}")]
+    Malformed(String),
+
+    #[error("Hardware token error: {0}")]
+    Token(String),
+
+    #[error("Hardware token not present; insert it to use the key store")]
+    TokenAbsent,
+
+    #[error("TPM error: {0}")]
+    Tpm(String),
+
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 
@@ -59,13 +96,30 @@ pub enum KeyError {
 const SALT_LENGTH: usize = 16;
 const NONCE_LENGTH: usize = 12;
 const KEY_LENGTH: usize = 32;
-
-/// Key algorithm types
+/// HKDF salt for subkey derivation, fixed so a path always gives the same key
+const SUBKEY_SALT: &[u8] = b"JanusKey subkey v1";
+/// HKDF info for the key authenticating a v2 store
+const STORE_MAC_INFO: &[u8] = b"JanusKey keystore v2 mac";
+const STORE_MAGIC_V1: &str = "JKKEYS01";
+const STORE_MAGIC_V2: &[u8; 8] = b"JKKEYS02";
+/// Largest key store read, in bytes
+const STORE_MAX_BYTES: u64 = 10 * 1024 * 1024;
+/// Name the replaced store is linked under until its bytes are zeroed
+const STORE_ASIDE: &str = "keystore.jks.scrub";
+/// Written once the store is in format v2, after which a v1 store is
+/// refused rather than migrated
+const STORE_V2_MARKER: &str = "keystore.v2";
+/// Consecutive failed unlocks allowed before attempts are delayed
+const UNLOCK_FREE_ATTEMPTS: u32 = 3;
+/// Longest delay imposed between unlock attempts, in seconds
+const UNLOCK_MAX_DELAY_SECS: i64 = 3600;
+
+/// Key algorithm types. Discriminants are stored in key store v2.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum KeyAlgorithm {
-    Aes256Gcm,
-    Ed25519,
-    X25519,
+    Aes256Gcm = 0,
+    Ed25519 = 1,
+    X25519 = 2,
 }
 
 impl std::fmt::Display for KeyAlgorithm {
@@ -78,13 +132,13 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     }
 }
 
-/// Key purpose
+/// Key purpose. Discriminants are stored in key store v2.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum KeyPurpose {
-    Encryption,
-    Signing,
-    KeyWrap,
-    Recovery,
+    Encryption = 0,
+    Signing = 1,
+    KeyWrap = 2,
+    Recovery = 3,
 }
 
 impl std::fmt::Display for KeyPurpose {
@@ -98,15 +152,15 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     }
 }
 
-/// Key lifecycle state
+/// Key lifecycle state. Discriminants are stored in key store v2.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum KeyState {
-    Generated,
-    Active,
-    Rotating,
-    Suspended,
-    Revoked,
-    Obliterated,
+    Generated = 0,
+    Active = 1,
+    Rotating = 2,
+    Suspended = 3,
+    Revoked = 4,
+    Obliterated = 5,
 }
 
 impl std::fmt::Display for KeyState {
@@ -134,9 +188,30 @@ pub struct KeyMetadata {
     pub rotation_of: Option<Uuid>,
     pub fingerprint: String,
     pub description: Option<String>,
+    /// Set for a subkey, which is derived from its master key when needed
+    /// instead of being stored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub derivation: Option<KeyDerivation>,
+}
+
+/// How a subkey is derived: HKDF-SHA256 from the master key, once per
+/// context string in `path`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyDerivation {
+    /// The stored key at the root of the hierarchy
+    pub master: Uuid,
+    /// Context strings from the master down (e.g. `["backup", "offsite"]`)
+    pub path: Vec<String>,
 }
 
-/// Wrapped key (encrypted key material + metadata)
+impl std::fmt::Display for KeyDerivation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.master, self.path.join("/"))
+    }
+}
+
+/// Wrapped key (encrypted key material + metadata). A subkey has no
+/// material: its nonce is zero and its ciphertext empty. (encrypted key material + metadata)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WrappedKey {
     pub metadata: KeyMetadata,
@@ -144,6 +219,75 @@ pub struct WrappedKey {
     pub ciphertext: Vec<u8>,
 }
 
+/// Evidence that a key's wrapped material was destroyed.
+///
+/// The commitment binds the key ID, fingerprint, time of destruction and a
+/// digest of the destroyed ciphertext, so the proof can be checked against
+/// the audit log and any copy of the ciphertext held elsewhere (a backup
+/// made before obliteration still contains the key).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObliterationProof {
+    pub key_id: Uuid,
+    pub fingerprint: String,
+    pub obliterated_at: DateTime<Utc>,
+    /// SHA-256 of the wrapped key bytes that were overwritten
+    pub ciphertext_digest: String,
+    /// SHA-256 over the fields above
+    pub commitment: String,
+}
+
+impl ObliterationProof {
+    fn new(metadata: &KeyMetadata, ciphertext: &[u8]) -> Self {
+        let mut proof = Self {
+            key_id: metadata.id,
+            fingerprint: metadata.fingerprint.clone(),
+            obliterated_at: Utc::now(),
+            ciphertext_digest: hex::encode(Sha256::digest(ciphertext)),
+            commitment: String::new(),
+        };
+        proof.commitment = proof.compute_commitment();
+        proof
+    }
+
+    fn compute_commitment(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.key_id.as_bytes());
+        hasher.update(self.fingerprint.as_bytes());
+        hasher.update(self.obliterated_at.to_rfc3339().as_bytes());
+        hasher.update(self.ciphertext_digest.as_bytes());
+        hex::encode(hasher.finalize())
+    }
+
+    /// Check that the commitment matches the proof's fields
+    pub fn verify(&self) -> bool {
+        self.commitment == self.compute_commitment()
+    }
+}
+
+/// Failed unlock attempts, kept next to the key store so the delay
+/// between guesses survives restarts
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnlockFailures {
+    /// Every failed unlock since the store was created; never decreases
+    pub total: u64,
+    /// Failed unlocks since the last successful one
+    pub consecutive: u32,
+    pub last_failure: Option<DateTime<Utc>>,
+}
+
+impl UnlockFailures {
+    /// When the next unlock may be attempted: the delay doubles with each
+    /// failure past the free attempts, up to an hour
+    pub fn retry_at(&self) -> Option<DateTime<Utc>> {
+        let excess = self.consecutive.checked_sub(UNLOCK_FREE_ATTEMPTS)?;
+        let delay = 1i64
+            .checked_shl(excess)
+            .filter(|d| *d > 0)
+            .map_or(UNLOCK_MAX_DELAY_SECS, |d| d.min(UNLOCK_MAX_DELAY_SECS));
+        Some(self.last_failure? + chrono::Duration::seconds(delay))
+    }
+}
+
 /// Key store header
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct KeyStoreHeader {
@@ -160,6 +304,18 @@ struct KeyStoreData {
     keys: Vec<WrappedKey>,
 }
 
+/// How a key store read from disk was encoded
+enum StoreFormat {
+    /// Pretty-printed JSON, without a store-level MAC; rewritten as v2
+    /// when next unlocked, unless the store has been v2 before
+    V1,
+    /// Binary, with `tag` authenticating the `body` before it
+    V2 {
+        body: Vec<u8>,
+        tag: [u8; KEY_LENGTH],
+    },
+}
+
 /// Secret key material (zeroized on drop)
 #[derive(Clone, Zeroize, ZeroizeOnDrop)]
 pub struct SecretKey {
@@ -177,7 +333,7 @@ impl SecretKey {
 
     pub fn generate() -> Result<Self> {
         let mut bytes = [0u8; KEY_LENGTH];
-        rand::thread_rng().fill_bytes(&mut bytes);
+        rand::rng().fill_bytes(&mut bytes);
         Ok(Self { bytes })
     }
 }
@@ -185,8 +341,9 @@ pub fn generate() -> Result<Self> {
 /// Key manager for JanusKey
 pub struct KeyManager {
     store_path: PathBuf,
-    root_path: PathBuf,
     kek: Option<SecretKey>,
+    /// Session on the token holding the KEK, for a token-backed store
+    token: Option<TokenSession>,
     audit_log: AuditLog,
 }
 
@@ -197,8 +354,8 @@ pub fn new(root: &Path) -> Self {
         let audit_log = AuditLog::new(root);
         Self {
             store_path,
-            root_path: root.to_path_buf(),
             kek: None,
+            token: None,
             audit_log,
         }
     }
@@ -213,20 +370,77 @@ pub fn is_initialized(&self) -> bool {
         self.store_path.join("keystore.jks").exists()
     }
 
+    /// Whether the KEK is held by a hardware token rather than derived
+    /// from a passphrase
+    pub fn uses_token(&self) -> bool {
+        self.store_path.join("token.json").exists()
+    }
+
+    /// Where a token-backed store's KEK is kept
+    pub fn token_config(&self) -> Result<Option<TokenConfig>> {
+        let path = self.store_path.join("token.json");
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&fs::read(&path)?)?))
+    }
+
     /// Initialize key store with passphrase
     pub fn init(&mut self, passphrase: &str) -> Result<()> {
         if self.is_initialized() {
             return Err(KeyError::AlreadyExists);
         }
 
-        fs::create_dir_all(&self.store_path)?;
-
         // Generate salt
         let mut salt = [0u8; SALT_LENGTH];
-        rand::thread_rng().fill_bytes(&mut salt);
+        rand::rng().fill_bytes(&mut salt);
 
         // Derive KEK from passphrase
         let kek = derive_kek(passphrase, &salt)?;
+        self.init_with_kek(kek, salt)
+    }
+
+    /// Initialize key store with a random KEK encrypted to the key pair
+    /// `key_label` on a PKCS#11 token, which is generated if missing.
+    /// Unlocking then takes the token's PIN instead of a passphrase.
+    pub fn init_with_token(
+        &mut self,
+        module: &Path,
+        token_label: Option<&str>,
+        key_label: &str,
+        pin: &str,
+    ) -> Result<()> {
+        if self.is_initialized() {
+            return Err(KeyError::AlreadyExists);
+        }
+
+        let token = TokenSession::open(module, token_label, pin)?;
+        let kek = SecretKey::generate()?;
+        let config = TokenConfig {
+            module: module.to_path_buf(),
+            token_label: token_label.map(String::from),
+            key_label: key_label.to_string(),
+            wrapped_kek: hex::encode(token.wrap(key_label, &kek)?),
+        };
+
+        fs::create_dir_all(&self.store_path)?;
+        let path = self.store_path.join("token.json");
+        write_atomic(&path, &serde_json::to_vec_pretty(&config)?)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        }
+        self.token = Some(token);
+
+        // The salt only keys the store MAC here
+        let mut salt = [0u8; SALT_LENGTH];
+        rand::rng().fill_bytes(&mut salt);
+        self.init_with_kek(kek, salt)
+    }
+
+    fn init_with_kek(&mut self, kek: SecretKey, salt: [u8; SALT_LENGTH]) -> Result<()> {
+        fs::create_dir_all(&self.store_path)?;
 
         // Derive attestation key from KEK
         let mut attestation_key = [0u8; 32];
@@ -239,13 +453,13 @@ pub fn init(&mut self, passphrase: &str) -> Result<()> {
 
         // Generate initial nonce
         let mut nonce = [0u8; NONCE_LENGTH];
-        rand::thread_rng().fill_bytes(&mut nonce);
+        rand::rng().fill_bytes(&mut nonce);
 
         // Create empty key store
         let store = KeyStoreData {
             header: KeyStoreHeader {
-                magic: "JKKEYS01".to_string(),
-                version: 1,
+                magic: String::from_utf8_lossy(STORE_MAGIC_V2).into_owned(),
+                version: 2,
                 salt,
                 nonce,
             },
@@ -269,20 +483,155 @@ pub fn init(&mut self, passphrase: &str) -> Result<()> {
         Ok(())
     }
 
-    /// Unlock key store with passphrase
+    /// Unlock key store with passphrase, or with the token's PIN for a
+    /// token-backed store
     pub fn unlock(&mut self, passphrase: &str) -> Result<()> {
         if !self.is_initialized() {
             return Err(KeyError::NotInitialized);
         }
 
-        let store = self.load_store_raw()?;
-        let kek = derive_kek(passphrase, &store.header.salt)?;
+        let mut failures = self.unlock_failures()?;
+        if let Some(retry_at) = failures.retry_at() {
+            let wait = (retry_at - Utc::now()).num_milliseconds();
+            if wait > 0 {
+                return Err(KeyError::Throttled((wait as u64).div_ceil(1000)));
+            }
+        }
+
+        let (store, format) = self.load_store_raw()?;
+        let (kek, token) = match self.token_config()? {
+            Some(config) => {
+                let opened =
+                    TokenSession::open(&config.module, config.token_label.as_deref(), passphrase)
+                        .and_then(|token| {
+                            let wrapped = hex::decode(&config.wrapped_kek)
+                                .map_err(|e| KeyError::Token(format!("token.json: {}", e)))?;
+                            Ok((token.unwrap(&config.key_label, &wrapped)?, token))
+                        });
+                match opened {
+                    Ok((kek, token)) => (kek, Some(token)),
+                    Err(KeyError::InvalidPassphrase) => {
+                        return Err(self.unlock_failed(&mut failures)?);
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            None => (derive_kek(passphrase, &store.header.salt)?, None),
+        };
+
+        if !self.kek_opens(&kek, &store, &format)? {
+            return Err(self.unlock_failed(&mut failures)?);
+        }
+        if failures.consecutive > 0 {
+            failures.consecutive = 0;
+            self.save_unlock_failures(&failures)?;
+        }
+
+        self.finish_unlock(kek, token, &store, format)
+    }
+
+    /// Whether this store's KEK is sealed to the TPM (`keys/tpm.json`)
+    pub fn is_tpm_sealed(&self) -> bool {
+        self.store_path.join("tpm.json").exists()
+    }
+
+    /// The TPM seal of this store's KEK, if there is one
+    pub fn tpm_seal(&self) -> Result<Option<TpmSeal>> {
+        let path = self.store_path.join("tpm.json");
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&fs::read(&path)?)?))
+    }
+
+    /// Seal the unlocked store's KEK to the TPM under a policy on the PCRs
+    /// `pcrs` (e.g. `sha256:0,7`), so [`unlock_with_tpm`](Self::unlock_with_tpm)
+    /// opens it on this machine in the current boot state. Replaces any
+    /// earlier seal. Token-backed stores are refused: their KEK must stay
+    /// on the token.
+    pub fn seal_to_tpm(&self, pcrs: &str) -> Result<TpmSeal> {
+        if self.uses_token() {
+            return Err(KeyError::Tpm(
+                "the KEK of a token-backed store stays on the token".to_string(),
+            ));
+        }
+        let kek = self.kek.as_ref().ok_or(KeyError::NotInitialized)?;
+        let seal = tpm::seal(kek, pcrs, &self.store_path)?;
+
+        let path = self.store_path.join("tpm.json");
+        write_atomic(&path, &serde_json::to_vec_pretty(&seal)?)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(seal)
+    }
 
-        // Verify passphrase by attempting to decrypt store
-        if !self.verify_kek(&kek, &store)? {
-            return Err(KeyError::InvalidPassphrase);
+    /// Drop the TPM seal, so unlocking takes the passphrase again. Returns
+    /// whether there was one.
+    pub fn forget_tpm(&self) -> Result<bool> {
+        let path = self.store_path.join("tpm.json");
+        if !path.exists() {
+            return Ok(false);
         }
+        fs::remove_file(&path)?;
+        Ok(true)
+    }
+
+    /// Unlock with the KEK sealed to the TPM. Returns `Ok(false)` if the
+    /// store is not sealed; an error means the TPM would not unseal it (a
+    /// different machine, or a changed boot state) and the caller should
+    /// fall back to [`unlock`](Self::unlock). Failures here are not counted
+    /// as failed unlock attempts.
+    pub fn unlock_with_tpm(&mut self) -> Result<bool> {
+        if !self.is_initialized() {
+            return Err(KeyError::NotInitialized);
+        }
+        let seal = match self.tpm_seal()? {
+            Some(seal) => seal,
+            None => return Ok(false),
+        };
+        let kek = tpm::unseal(&seal, &self.store_path)?;
+        let (store, format) = self.load_store_raw()?;
+        if !self.kek_opens(&kek, &store, &format)? {
+            return Err(KeyError::Tpm(
+                "the sealed KEK no longer opens this store; seal it again".to_string(),
+            ));
+        }
+        self.finish_unlock(kek, None, &store, format)?;
+        Ok(true)
+    }
+
+    /// Check a KEK by the store's MAC, or for v1 by attempting to decrypt
+    /// a key. A MAC that fails for a KEK that does decrypt a key means the
+    /// store was changed.
+    fn kek_opens(
+        &self,
+        kek: &SecretKey,
+        store: &KeyStoreData,
+        format: &StoreFormat,
+    ) -> Result<bool> {
+        match format {
+            StoreFormat::V2 { body, tag } => {
+                let authentic = tags_match(&store_mac(kek, &store.header.salt, body), tag);
+                if !authentic && self.verify_kek(kek, store)? {
+                    return Err(KeyError::Tampered);
+                }
+                Ok(authentic)
+            }
+            StoreFormat::V1 => self.verify_kek(kek, store),
+        }
+    }
 
+    /// Hold a verified KEK, migrating a v1 store
+    fn finish_unlock(
+        &mut self,
+        kek: SecretKey,
+        token: Option<TokenSession>,
+        store: &KeyStoreData,
+        format: StoreFormat,
+    ) -> Result<()> {
         // Derive attestation key from KEK
         let mut attestation_key = [0u8; 32];
         let mut hasher = Sha256::new();
@@ -291,12 +640,43 @@ pub fn unlock(&mut self, passphrase: &str) -> Result<()> {
         attestation_key.copy_from_slice(&hasher.finalize());
 
         self.kek = Some(kek);
+        self.token = token;
         self.audit_log.set_attestation_key(attestation_key);
         let _ = self.audit_log.log_store_unlock();
+        self.scrub_aside()?;
+
+        match format {
+            StoreFormat::V1 => {
+                self.save_store(store)?;
+                tracing::info!("migrated key store to format v2");
+            }
+            // Stores written as v2 before the marker existed get it now
+            StoreFormat::V2 { .. } => self.mark_v2()?,
+        }
 
         Ok(())
     }
 
+    /// Count a failed unlock, returning the error to fail it with
+    fn unlock_failed(&self, failures: &mut UnlockFailures) -> Result<KeyError> {
+        failures.total += 1;
+        failures.consecutive += 1;
+        failures.last_failure = Some(Utc::now());
+        self.save_unlock_failures(failures)?;
+        let _ = self.audit_log.log_unlock_failed(failures.consecutive);
+        Ok(KeyError::InvalidPassphrase)
+    }
+
+    /// The KEK, to wrap or unwrap key material. For a token-backed store
+    /// the token must still be present.
+    fn kek(&self) -> Result<&SecretKey> {
+        let kek = self.kek.as_ref().ok_or(KeyError::NotInitialized)?;
+        if let Some(token) = &self.token {
+            token.ensure_present()?;
+        }
+        Ok(kek)
+    }
+
     /// Generate a new key
     pub fn generate(
         &mut self,
@@ -305,16 +685,12 @@ pub fn generate(
         description: Option<String>,
         expires_in_days: Option<u64>,
     ) -> Result<Uuid> {
-        let kek = self.kek.as_ref().ok_or(KeyError::NotInitialized)?;
+        let kek = self.kek()?;
         let mut store = self.load_store()?;
 
         // Generate key material
         let key = SecretKey::generate()?;
-
-        // Calculate fingerprint
-        let mut hasher = Sha256::new();
-        hasher.update(key.as_bytes());
-        let fingerprint = hex::encode(&hasher.finalize()[..8]);
+        let fingerprint = key_fingerprint(&key);
 
         // Create metadata
         let id = Uuid::new_v4();
@@ -331,6 +707,7 @@ pub fn generate(
             rotation_of: None,
             fingerprint: fingerprint.clone(),
             description,
+            derivation: None,
         };
 
         // Wrap key
@@ -347,6 +724,74 @@ pub fn generate(
         Ok(id)
     }
 
+    /// Derive a subkey of `parent` for `context`. Only the derivation is
+    /// stored; the material is re-derived from the master key each time it
+    /// is retrieved, so rotating the master rotates its subkeys. A subkey
+    /// of a subkey extends its derivation path.
+    pub fn derive(
+        &mut self,
+        parent: Uuid,
+        context: &str,
+        purpose: KeyPurpose,
+        description: Option<String>,
+    ) -> Result<Uuid> {
+        let kek = self.kek()?;
+        if context.is_empty() || context.contains('/') {
+            return Err(KeyError::CryptoError(
+                "Derivation context must be non-empty and contain no '/'".to_string(),
+            ));
+        }
+        let mut store = self.load_store()?;
+
+        let parent_meta = &store
+            .keys
+            .iter()
+            .find(|k| k.metadata.id == parent)
+            .ok_or(KeyError::KeyNotFound(parent))?
+            .metadata;
+        check_usable(parent_meta)?;
+        let derivation = match &parent_meta.derivation {
+            Some(d) => KeyDerivation {
+                master: d.master,
+                path: [d.path.clone(), vec![context.to_string()]].concat(),
+            },
+            None => KeyDerivation {
+                master: parent,
+                path: vec![context.to_string()],
+            },
+        };
+        if let Some(existing) = store.keys.iter().find(|k| {
+            k.metadata.derivation.as_ref() == Some(&derivation) && check_usable(&k.metadata).is_ok()
+        }) {
+            return Err(KeyError::AlreadyDerived(existing.metadata.id));
+        }
+
+        let key = derive_subkey(kek, &store, &derivation)?;
+        let fingerprint = key_fingerprint(&key);
+        let id = Uuid::new_v4();
+        let metadata = KeyMetadata {
+            id,
+            algorithm: parent_meta.algorithm,
+            purpose,
+            created_at: Utc::now(),
+            expires_at: parent_meta.expires_at,
+            state: KeyState::Active,
+            rotation_of: None,
+            fingerprint: fingerprint.clone(),
+            description,
+            derivation: Some(derivation),
+        };
+        let algorithm = metadata.algorithm;
+        store.keys.push(subkey_entry(metadata));
+        self.save_store(&store)?;
+
+        let _ = self
+            .audit_log
+            .log_key_generated(id, &fingerprint, algorithm, purpose);
+
+        Ok(id)
+    }
+
     /// List all keys
     pub fn list(&self) -> Result<Vec<KeyMetadata>> {
         if self.kek.is_none() {
@@ -357,6 +802,16 @@ pub fn list(&self) -> Result<Vec<KeyMetadata>> {
         Ok(store.keys.into_iter().map(|k| k.metadata).collect())
     }
 
+    /// IDs of the stored keys, which are not secret and so are read
+    /// without unlocking (for shell completion)
+    pub fn key_ids(&self) -> Result<Vec<Uuid>> {
+        if !self.is_initialized() {
+            return Ok(Vec::new());
+        }
+        let store = self.load_store()?;
+        Ok(store.keys.iter().map(|k| k.metadata.id).collect())
+    }
+
     /// Get key metadata by ID
     pub fn get(&self, id: Uuid) -> Result<KeyMetadata> {
         if self.kek.is_none() {
@@ -374,32 +829,46 @@ pub fn get(&self, id: Uuid) -> Result<KeyMetadata> {
 
     /// Retrieve key material (use carefully!)
     pub fn retrieve(&self, id: Uuid) -> Result<SecretKey> {
-        let kek = self.kek.as_ref().ok_or(KeyError::NotInitialized)?;
+        let kek = self.kek()?;
         let store = self.load_store()?;
 
         let wrapped = store
             .keys
-            .into_iter()
+            .iter()
             .find(|k| k.metadata.id == id)
             .ok_or(KeyError::KeyNotFound(id))?;
 
-        if wrapped.metadata.state == KeyState::Revoked
-            || wrapped.metadata.state == KeyState::Obliterated
-        {
-            return Err(KeyError::AlreadyRevoked(id));
-        }
+        check_usable(&wrapped.metadata)?;
 
         // Log key retrieval
         let _ = self
             .audit_log
             .log_key_retrieved(id, &wrapped.metadata.fingerprint);
 
-        unwrap_key(kek, &wrapped)
+        match &wrapped.metadata.derivation {
+            Some(derivation) => derive_subkey(kek, &store, derivation),
+            None => unwrap_key(kek, wrapped),
+        }
     }
 
-    /// Rotate a key
+    /// Public key of an Ed25519 or X25519 key, for others to verify its
+    /// signatures or encrypt to it
+    pub fn public_key(&self, id: Uuid) -> Result<[u8; KEY_LENGTH]> {
+        let metadata = self.get(id)?;
+        let secret = self.retrieve(id)?;
+        match metadata.algorithm {
+            KeyAlgorithm::Ed25519 => Ok(ed25519_dalek::SigningKey::from_bytes(secret.as_bytes())
+                .verifying_key()
+                .to_bytes()),
+            KeyAlgorithm::X25519 => Ok(x25519_public_key(secret.as_bytes())),
+            KeyAlgorithm::Aes256Gcm => Err(KeyError::NoPublicKey(id)),
+        }
+    }
+
+    /// Rotate a key. Its subkeys are re-derived from the new key under the
+    /// same paths, and the old ones revoked.
     pub fn rotate(&mut self, id: Uuid) -> Result<Uuid> {
-        let kek = self.kek.as_ref().ok_or(KeyError::NotInitialized)?;
+        let kek = self.kek()?;
         let mut store = self.load_store()?;
 
         // Find old key
@@ -409,17 +878,15 @@ pub fn rotate(&mut self, id: Uuid) -> Result<Uuid> {
             .position(|k| k.metadata.id == id)
             .ok_or(KeyError::KeyNotFound(id))?;
 
-        if store.keys[old_idx].metadata.state == KeyState::Revoked {
-            return Err(KeyError::AlreadyRevoked(id));
+        check_usable(&store.keys[old_idx].metadata)?;
+        if store.keys[old_idx].metadata.derivation.is_some() {
+            return Err(KeyError::Derived(id));
         }
 
         // Generate new key with same properties
         let old_meta = &store.keys[old_idx].metadata;
         let new_key = SecretKey::generate()?;
-
-        let mut hasher = Sha256::new();
-        hasher.update(new_key.as_bytes());
-        let fingerprint = hex::encode(&hasher.finalize()[..8]);
+        let fingerprint = key_fingerprint(&new_key);
 
         let new_id = Uuid::new_v4();
         let now = Utc::now();
@@ -436,6 +903,7 @@ pub fn rotate(&mut self, id: Uuid) -> Result<Uuid> {
             rotation_of: Some(id),
             fingerprint: fingerprint.clone(),
             description: old_meta.description.clone(),
+            derivation: None,
         };
 
         // Wrap new key
@@ -445,13 +913,46 @@ pub fn rotate(&mut self, id: Uuid) -> Result<Uuid> {
         // Mark old key as revoked
         let old_fingerprint = store.keys[old_idx].metadata.fingerprint.clone();
         store.keys[old_idx].metadata.state = KeyState::Revoked;
+        let mut rotated = vec![(id, old_fingerprint, new_id, fingerprint)];
+
+        // Re-derive the subkeys under the new key
+        let subkeys: Vec<usize> = (0..store.keys.len())
+            .filter(|&i| {
+                let meta = &store.keys[i].metadata;
+                meta.derivation.as_ref().is_some_and(|d| d.master == id)
+                    && check_usable(meta).is_ok()
+            })
+            .collect();
+        for i in subkeys {
+            let old = store.keys[i].metadata.clone();
+            let derivation = KeyDerivation {
+                master: new_id,
+                ..old.derivation.clone().expect("subkey")
+            };
+            let fingerprint = key_fingerprint(&derive_subkey(kek, &store, &derivation)?);
+            let subkey_id = Uuid::new_v4();
+            store.keys.push(subkey_entry(KeyMetadata {
+                id: subkey_id,
+                created_at: now,
+                expires_at: old.expires_at.map(|_| now + chrono::Duration::days(365)),
+                state: KeyState::Active,
+                rotation_of: Some(old.id),
+                fingerprint: fingerprint.clone(),
+                derivation: Some(derivation),
+                ..old.clone()
+            }));
+            store.keys[i].metadata.state = KeyState::Revoked;
+            rotated.push((old.id, old.fingerprint, subkey_id, fingerprint));
+        }
 
         self.save_store(&store)?;
 
-        // Log rotation event
-        let _ = self
-            .audit_log
-            .log_key_rotated(id, &old_fingerprint, new_id, &fingerprint);
+        // Log rotation events
+        for (old_id, old_fingerprint, new_id, fingerprint) in &rotated {
+            let _ = self
+                .audit_log
+                .log_key_rotated(*old_id, old_fingerprint, *new_id, fingerprint);
+        }
 
         Ok(new_id)
     }
@@ -470,16 +971,26 @@ pub fn revoke(&mut self, id: Uuid) -> Result<()> {
             .find(|k| k.metadata.id == id)
             .ok_or(KeyError::KeyNotFound(id))?;
 
-        if key.metadata.state == KeyState::Revoked {
-            return Err(KeyError::AlreadyRevoked(id));
+        match key.metadata.state {
+            KeyState::Revoked => return Err(KeyError::AlreadyRevoked(id)),
+            KeyState::Obliterated => return Err(KeyError::Obliterated(id)),
+            _ => {}
         }
 
         let fingerprint = key.metadata.fingerprint.clone();
         key.metadata.state = KeyState::Revoked;
+        let subkeys = revoke_subkeys(&mut store, id);
         self.save_store(&store)?;
 
         // Log revocation
         let _ = self.audit_log.log_key_revoked(id, &fingerprint, None);
+        for (subkey, fingerprint) in subkeys {
+            let _ = self.audit_log.log_key_revoked(
+                subkey,
+                &fingerprint,
+                Some(&format!("master key {} revoked", id)),
+            );
+        }
 
         Ok(())
     }
@@ -498,22 +1009,90 @@ pub fn revoke_with_reason(&mut self, id: Uuid, reason: &str) -> Result<()> {
             .find(|k| k.metadata.id == id)
             .ok_or(KeyError::KeyNotFound(id))?;
 
-        if key.metadata.state == KeyState::Revoked {
-            return Err(KeyError::AlreadyRevoked(id));
+        match key.metadata.state {
+            KeyState::Revoked => return Err(KeyError::AlreadyRevoked(id)),
+            KeyState::Obliterated => return Err(KeyError::Obliterated(id)),
+            _ => {}
         }
 
         let fingerprint = key.metadata.fingerprint.clone();
         key.metadata.state = KeyState::Revoked;
+        let subkeys = revoke_subkeys(&mut store, id);
         self.save_store(&store)?;
 
         // Log revocation with reason
         let _ = self
             .audit_log
             .log_key_revoked(id, &fingerprint, Some(reason));
+        for (subkey, fingerprint) in subkeys {
+            let _ = self.audit_log.log_key_revoked(
+                subkey,
+                &fingerprint,
+                Some(&format!("master key {} revoked: {}", id, reason)),
+            );
+        }
 
         Ok(())
     }
 
+    /// Destroy a key's material for good.
+    ///
+    /// The key moves to [`KeyState::Obliterated`] and the store file that
+    /// held its wrapped bytes is zeroed once replaced; only its metadata
+    /// remains. Revoked keys can be obliterated. Subkeys of an obliterated
+    /// master can no longer be derived and are obliterated with it. On
+    /// copy-on-write filesystems and flash storage the old blocks may
+    /// survive the overwrite, so full-disk encryption is still needed for
+    /// that case.
+    pub fn obliterate(&mut self, id: Uuid) -> Result<ObliterationProof> {
+        if self.kek.is_none() {
+            return Err(KeyError::NotInitialized);
+        }
+
+        let mut store = self.load_store()?;
+        let key = store
+            .keys
+            .iter_mut()
+            .find(|k| k.metadata.id == id)
+            .ok_or(KeyError::KeyNotFound(id))?;
+
+        if key.metadata.state == KeyState::Obliterated {
+            return Err(KeyError::Obliterated(id));
+        }
+
+        let old_state = key.metadata.state;
+        let proof = ObliterationProof::new(&key.metadata, &key.ciphertext);
+        key.ciphertext.zeroize();
+        key.ciphertext.clear();
+        key.nonce.zeroize();
+        key.metadata.state = KeyState::Obliterated;
+
+        let mut subkeys = Vec::new();
+        for key in store.keys.iter_mut() {
+            let derived = key
+                .metadata
+                .derivation
+                .as_ref()
+                .is_some_and(|d| d.master == id);
+            if derived && key.metadata.state != KeyState::Obliterated {
+                subkeys.push((
+                    ObliterationProof::new(&key.metadata, &[]),
+                    key.metadata.state,
+                ));
+                key.metadata.state = KeyState::Obliterated;
+            }
+        }
+        self.overwrite_store(&store)?;
+
+        // Log obliteration
+        let _ = self.audit_log.log_key_obliterated(&proof, old_state);
+        for (subkey_proof, old_state) in &subkeys {
+            let _ = self.audit_log.log_key_obliterated(subkey_proof, *old_state);
+        }
+
+        Ok(proof)
+    }
+
     /// Create encrypted backup
     pub fn backup(&self, output: &Path) -> Result<()> {
         if self.kek.is_none() {
@@ -521,7 +1100,7 @@ pub fn backup(&self, output: &Path) -> Result<()> {
         }
 
         let store_path = self.store_path.join("keystore.jks");
-        fs::copy(store_path, output)?;
+        write_atomic(output, &fs::read(store_path)?)?;
 
         #[cfg(unix)]
         {
@@ -535,36 +1114,155 @@ pub fn backup(&self, output: &Path) -> Result<()> {
         Ok(())
     }
 
+    /// Create a backup encrypted to X25519 `recipients` (see
+    /// [`crate::envelope`]), so it can be stored or sent without the
+    /// passphrase being the only protection
+    pub fn backup_sealed(
+        &self,
+        output: &Path,
+        recipients: &[[u8; KEY_LENGTH]],
+        format: crate::envelope::SealFormat,
+    ) -> Result<()> {
+        if self.kek.is_none() {
+            return Err(KeyError::NotInitialized);
+        }
+
+        let store = fs::read(self.store_path.join("keystore.jks"))?;
+        let sealed = crate::envelope::seal(&store, recipients, format)
+            .map_err(|e| KeyError::CryptoError(e.to_string()))?;
+        write_atomic(output, &sealed)?;
+
+        let _ = self.audit_log.log_backup_created(output);
+
+        Ok(())
+    }
+
     // Internal helpers
 
-    fn load_store_raw(&self) -> Result<KeyStoreData> {
+    /// Read the store in either format, without authenticating it
+    fn load_store_raw(&self) -> Result<(KeyStoreData, StoreFormat)> {
+        use std::io::Read;
+
         let path = self.store_path.join("keystore.jks");
-        let content = ({
-            use std::io::Read;
-            std::fs::File::open(&path).and_then(|mut f| {
-                let mut buf = String::new();
-                f.take(10 * 1024 * 1024).read_to_string(&mut buf)?;
-                Ok(buf)
-            })
-        })?;
-        let store: KeyStoreData = serde_json::from_str(&content)?;
-        Ok(store)
+        let mut content = Vec::new();
+        fs::File::open(&path)?
+            .take(STORE_MAX_BYTES)
+            .read_to_end(&mut content)?;
+
+        // A v2 store ends in its MAC tag, whose last bytes may be zero
+        if content.starts_with(STORE_MAGIC_V2) {
+            return decode_store(content);
+        }
+        // A v1 store may have a zeroed tail from an in-place overwrite
+        // that stopped early
+        let end = content.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+        content.truncate(end);
+        let store: KeyStoreData = serde_json::from_slice(&content)?;
+        // A v1 store has no MAC, so one in place of a v2 store could carry
+        // any metadata; it is only migrated if the store was never v2
+        if self.store_path.join(STORE_V2_MARKER).exists() {
+            return Err(KeyError::Tampered);
+        }
+        Ok((store, StoreFormat::V1))
     }
 
+    /// Read the store, checking its MAC when unlocked. Key IDs are read
+    /// without unlocking, so a locked store is not authenticated.
     fn load_store(&self) -> Result<KeyStoreData> {
-        self.load_store_raw()
+        let (store, format) = self.load_store_raw()?;
+        if let Some(kek) = &self.kek {
+            // Unlocking migrates a v1 store, so one seen since was swapped in
+            let StoreFormat::V2 { body, tag } = &format else {
+                return Err(KeyError::Tampered);
+            };
+            if !tags_match(&store_mac(kek, &store.header.salt, body), tag) {
+                return Err(KeyError::Tampered);
+            }
+        }
+        Ok(store)
+    }
+
+    /// Failed unlock attempts so far
+    pub fn unlock_failures(&self) -> Result<UnlockFailures> {
+        let path = self.store_path.join("unlock_failures.json");
+        if !path.exists() {
+            return Ok(UnlockFailures::default());
+        }
+        Ok(serde_json::from_slice(&fs::read(&path)?)?)
+    }
+
+    fn save_unlock_failures(&self, failures: &UnlockFailures) -> Result<()> {
+        let path = self.store_path.join("unlock_failures.json");
+        write_atomic(&path, &serde_json::to_vec_pretty(failures)?)?;
+        Ok(())
     }
 
     fn save_store(&self, store: &KeyStoreData) -> Result<()> {
+        let kek = self.kek.as_ref().ok_or(KeyError::NotInitialized)?;
+        let path = self.store_path.join("keystore.jks");
+        write_atomic(&path, &encode_store(kek, store))?;
+        self.mark_v2()
+    }
+
+    /// Record that the store is in format v2, so v1 is refused from now on
+    fn mark_v2(&self) -> Result<()> {
+        let marker = self.store_path.join(STORE_V2_MARKER);
+        if !marker.exists() {
+            write_atomic(&marker, b"")?;
+        }
+        Ok(())
+    }
+
+    /// Replace the store, then zero the bytes of the file it replaces.
+    ///
+    /// The old file is linked aside before the new store is written
+    /// atomically, so a crash or a full disk leaves one intact store; the
+    /// aside copy is then overwritten in place and unlinked. Only that file
+    /// is scrubbed: copies freed by earlier [`Self::save_store`] calls,
+    /// each of which replaced the store file, are beyond reach.
+    fn overwrite_store(&self, store: &KeyStoreData) -> Result<()> {
+        let kek = self.kek.as_ref().ok_or(KeyError::NotInitialized)?;
         let path = self.store_path.join("keystore.jks");
-        let content = serde_json::to_string_pretty(store)?;
-        fs::write(&path, content)?;
+        let aside = self.store_path.join(STORE_ASIDE);
+        self.scrub_aside()?;
+
+        fs::hard_link(&path, &aside)?;
+        if let Err(e) = write_atomic(&path, &encode_store(kek, store)) {
+            let _ = fs::remove_file(&aside);
+            return Err(e.into());
+        }
+        self.mark_v2()?;
+        self.scrub_aside()
+    }
+
+    /// Zero and remove a store left aside by [`Self::overwrite_store`].
+    /// An aside link made just before an interrupted write still shares
+    /// the live store's bytes, so it is only unlinked.
+    fn scrub_aside(&self) -> Result<()> {
+        use std::io::Write;
+
+        let aside = self.store_path.join(STORE_ASIDE);
+        let old = match fs::read(&aside) {
+            Ok(old) => old,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        if fs::read(self.store_path.join("keystore.jks"))? != old {
+            let mut file = fs::OpenOptions::new().write(true).open(&aside)?;
+            file.write_all(&vec![0u8; old.len()])?;
+            file.sync_all()?;
+        }
+        fs::remove_file(&aside)?;
         Ok(())
     }
 
     fn verify_kek(&self, kek: &SecretKey, store: &KeyStoreData) -> Result<bool> {
-        // If there are any keys, try to unwrap the first one
-        if let Some(wrapped) = store.keys.first() {
+        // If there are any keys, try to unwrap the first one that still has
+        // material
+        let mut live = store.keys.iter().filter(|k| {
+            k.metadata.state != KeyState::Obliterated && k.metadata.derivation.is_none()
+        });
+        if let Some(wrapped) = live.next() {
             match unwrap_key(kek, wrapped) {
                 Ok(_) => Ok(true),
                 Err(KeyError::CryptoError(_)) => Ok(false),
@@ -572,11 +1270,311 @@ fn verify_kek(&self, kek: &SecretKey, store: &KeyStoreData) -> Result<bool> {
             }
         } else {
             // No keys yet, verify by re-deriving and checking magic
-            Ok(store.header.magic == "JKKEYS01")
+            Ok(store.header.magic == STORE_MAGIC_V1)
+        }
+    }
+}
+
+/// Fail if a key has been revoked or obliterated
+fn check_usable(metadata: &KeyMetadata) -> Result<()> {
+    match metadata.state {
+        KeyState::Revoked => Err(KeyError::AlreadyRevoked(metadata.id)),
+        KeyState::Obliterated => Err(KeyError::Obliterated(metadata.id)),
+        _ => Ok(()),
+    }
+}
+
+/// Short fingerprint of key material (first 8 bytes of its SHA-256)
+fn key_fingerprint(key: &SecretKey) -> String {
+    hex::encode(&Sha256::digest(key.as_bytes())[..8])
+}
+
+/// Store entry for a subkey: metadata only
+fn subkey_entry(metadata: KeyMetadata) -> WrappedKey {
+    WrappedKey {
+        metadata,
+        nonce: [0u8; NONCE_LENGTH],
+        ciphertext: Vec::new(),
+    }
+}
+
+/// Revoke the usable subkeys of `master`, returning their IDs and
+/// fingerprints
+fn revoke_subkeys(store: &mut KeyStoreData, master: Uuid) -> Vec<(Uuid, String)> {
+    let mut revoked = Vec::new();
+    for key in store.keys.iter_mut() {
+        let derived = key
+            .metadata
+            .derivation
+            .as_ref()
+            .is_some_and(|d| d.master == master);
+        if derived && check_usable(&key.metadata).is_ok() {
+            key.metadata.state = KeyState::Revoked;
+            revoked.push((key.metadata.id, key.metadata.fingerprint.clone()));
+        }
+    }
+    revoked
+}
+
+/// Derive a subkey's material from its master key along its path
+fn derive_subkey(
+    kek: &SecretKey,
+    store: &KeyStoreData,
+    derivation: &KeyDerivation,
+) -> Result<SecretKey> {
+    let master = store
+        .keys
+        .iter()
+        .find(|k| k.metadata.id == derivation.master)
+        .ok_or(KeyError::KeyNotFound(derivation.master))?;
+    check_usable(&master.metadata)?;
+    let mut key = unwrap_key(kek, master)?;
+    for context in &derivation.path {
+        key = SecretKey::new(hkdf_sha256(key.as_bytes(), SUBKEY_SALT, context.as_bytes()));
+    }
+    Ok(key)
+}
+
+/// MAC of a v2 store's body, under a key derived from the KEK
+fn store_mac(kek: &SecretKey, salt: &[u8; SALT_LENGTH], body: &[u8]) -> [u8; KEY_LENGTH] {
+    let mut key = hkdf_sha256(kek.as_bytes(), salt, STORE_MAC_INFO);
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&key).expect("HMAC takes any key length");
+    key.zeroize();
+    mac.update(body);
+    mac.finalize().into_bytes().into()
+}
+
+/// Compare MAC tags in constant time
+fn tags_match(a: &[u8; KEY_LENGTH], b: &[u8; KEY_LENGTH]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// Encode a store in format v2: the magic, salt and nonce, then each key
+/// as a length-prefixed entry, then an HMAC-SHA256 tag over all of it.
+/// Integers are little-endian; strings and byte strings carry a `u32`
+/// length.
+fn encode_store(kek: &SecretKey, store: &KeyStoreData) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(STORE_MAGIC_V2);
+    out.extend_from_slice(&store.header.salt);
+    out.extend_from_slice(&store.header.nonce);
+    put_u32(&mut out, store.keys.len());
+    for key in &store.keys {
+        let entry = encode_key(key);
+        put_bytes(&mut out, &entry);
+    }
+    let tag = store_mac(kek, &store.header.salt, &out);
+    out.extend_from_slice(&tag);
+    out
+}
+
+fn encode_key(key: &WrappedKey) -> Vec<u8> {
+    let meta = &key.metadata;
+    let mut out = Vec::new();
+    out.extend_from_slice(meta.id.as_bytes());
+    out.push(meta.algorithm as u8);
+    out.push(meta.purpose as u8);
+    out.push(meta.state as u8);
+    put_time(&mut out, &meta.created_at);
+    put_option(&mut out, meta.expires_at.as_ref(), put_time);
+    put_option(&mut out, meta.rotation_of.as_ref(), |out, id| {
+        out.extend_from_slice(id.as_bytes())
+    });
+    put_bytes(&mut out, meta.fingerprint.as_bytes());
+    put_option(&mut out, meta.description.as_ref(), |out, d| {
+        put_bytes(out, d.as_bytes())
+    });
+    put_option(&mut out, meta.derivation.as_ref(), |out, d| {
+        out.extend_from_slice(d.master.as_bytes());
+        put_u32(out, d.path.len());
+        for context in &d.path {
+            put_bytes(out, context.as_bytes());
+        }
+    });
+    out.extend_from_slice(&key.nonce);
+    put_bytes(&mut out, &key.ciphertext);
+    out
+}
+
+fn put_u32(out: &mut Vec<u8>, n: usize) {
+    out.extend_from_slice(&(n as u32).to_le_bytes());
+}
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    put_u32(out, bytes.len());
+    out.extend_from_slice(bytes);
+}
+
+fn put_time(out: &mut Vec<u8>, time: &DateTime<Utc>) {
+    out.extend_from_slice(&time.timestamp().to_le_bytes());
+    out.extend_from_slice(&time.timestamp_subsec_nanos().to_le_bytes());
+}
+
+fn put_option<T>(out: &mut Vec<u8>, value: Option<&T>, put: impl Fn(&mut Vec<u8>, &T)) {
+    match value {
+        Some(value) => {
+            out.push(1);
+            put(out, value);
         }
+        None => out.push(0),
     }
 }
 
+/// Decode a v2 store, splitting off its tag for the caller to check
+fn decode_store(mut content: Vec<u8>) -> Result<(KeyStoreData, StoreFormat)> {
+    let body_len = content
+        .len()
+        .checked_sub(KEY_LENGTH)
+        .ok_or_else(|| KeyError::Malformed("truncated".to_string()))?;
+    let tag: [u8; KEY_LENGTH] = content[body_len..].try_into().expect("tag length");
+    content.truncate(body_len);
+
+    let mut reader = Reader(&content[STORE_MAGIC_V2.len()..]);
+    let header = KeyStoreHeader {
+        magic: String::from_utf8_lossy(STORE_MAGIC_V2).into_owned(),
+        version: 2,
+        salt: reader.array()?,
+        nonce: reader.array()?,
+    };
+    let count = reader.u32()?;
+    let mut keys = Vec::new();
+    for _ in 0..count {
+        let mut entry = Reader(reader.bytes()?);
+        keys.push(entry.key()?);
+        entry.finish()?;
+    }
+    reader.finish()?;
+
+    let store = KeyStoreData { header, keys };
+    Ok((store, StoreFormat::V2 { body: content, tag }))
+}
+
+/// Cursor over a v2 store, failing with [`KeyError::Malformed`] rather
+/// than reading past the end
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(KeyError::Malformed("truncated".to_string()));
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().expect("taken length"))
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        String::from_utf8(self.bytes()?.to_vec())
+            .map_err(|_| KeyError::Malformed("string is not UTF-8".to_string()))
+    }
+
+    fn uuid(&mut self) -> Result<Uuid> {
+        Ok(Uuid::from_bytes(self.array()?))
+    }
+
+    fn time(&mut self) -> Result<DateTime<Utc>> {
+        let secs = i64::from_le_bytes(self.array()?);
+        let nanos = self.u32()?;
+        DateTime::from_timestamp(secs, nanos)
+            .ok_or_else(|| KeyError::Malformed("timestamp out of range".to_string()))
+    }
+
+    fn option<T>(&mut self, read: impl FnOnce(&mut Self) -> Result<T>) -> Result<Option<T>> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => read(self).map(Some),
+            flag => Err(KeyError::Malformed(format!("bad option flag {}", flag))),
+        }
+    }
+
+    fn key(&mut self) -> Result<WrappedKey> {
+        let id = self.uuid()?;
+        let algorithm = match self.u8()? {
+            0 => KeyAlgorithm::Aes256Gcm,
+            1 => KeyAlgorithm::Ed25519,
+            2 => KeyAlgorithm::X25519,
+            n => return Err(KeyError::Malformed(format!("unknown algorithm {}", n))),
+        };
+        let purpose = match self.u8()? {
+            0 => KeyPurpose::Encryption,
+            1 => KeyPurpose::Signing,
+            2 => KeyPurpose::KeyWrap,
+            3 => KeyPurpose::Recovery,
+            n => return Err(KeyError::Malformed(format!("unknown purpose {}", n))),
+        };
+        let state = match self.u8()? {
+            0 => KeyState::Generated,
+            1 => KeyState::Active,
+            2 => KeyState::Rotating,
+            3 => KeyState::Suspended,
+            4 => KeyState::Revoked,
+            5 => KeyState::Obliterated,
+            n => return Err(KeyError::Malformed(format!("unknown key state {}", n))),
+        };
+        let metadata = KeyMetadata {
+            id,
+            algorithm,
+            purpose,
+            state,
+            created_at: self.time()?,
+            expires_at: self.option(Self::time)?,
+            rotation_of: self.option(Self::uuid)?,
+            fingerprint: self.string()?,
+            description: self.option(Self::string)?,
+            derivation: self.option(|r| {
+                let master = r.uuid()?;
+                let path = (0..r.u32()?).map(|_| r.string()).collect::<Result<_>>()?;
+                Ok(KeyDerivation { master, path })
+            })?,
+        };
+        Ok(WrappedKey {
+            metadata,
+            nonce: self.array()?,
+            ciphertext: self.bytes()?.to_vec(),
+        })
+    }
+
+    /// Fail if anything is left over
+    fn finish(&self) -> Result<()> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(KeyError::Malformed("trailing bytes".to_string()))
+        }
+    }
+}
+
+/// HKDF-SHA256 (RFC 5869) extract and expand, for one 32-byte block
+pub(crate) fn hkdf_sha256(ikm: &[u8], salt: &[u8], info: &[u8]) -> [u8; KEY_LENGTH] {
+    let mut okm = [0u8; KEY_LENGTH];
+    Hkdf::<Sha256>::new(Some(salt), ikm)
+        .expand(info, &mut okm)
+        .expect("one block is within HKDF's output limit");
+    okm
+}
+
+/// X25519 public key for a secret scalar (clamped as RFC 7748 requires)
+pub fn x25519_public_key(secret: &[u8; KEY_LENGTH]) -> [u8; KEY_LENGTH] {
+    curve25519_dalek::montgomery::MontgomeryPoint::mul_base_clamped(*secret).to_bytes()
+}
+
 /// Derive Key Encryption Key from passphrase
 fn derive_kek(passphrase: &str, salt: &[u8; SALT_LENGTH]) -> Result<SecretKey> {
     let params = Params::new(
@@ -600,7 +1598,7 @@ fn verify_kek(&self, kek: &SecretKey, store: &KeyStoreData) -> Result<bool> {
 /// Wrap (encrypt) key material
 fn wrap_key(kek: &SecretKey, key: &[u8], metadata: &KeyMetadata) -> Result<WrappedKey> {
     let mut nonce_bytes = [0u8; NONCE_LENGTH];
-    rand::thread_rng().fill_bytes(&mut nonce_bytes);
+    rand::rng().fill_bytes(&mut nonce_bytes);
 
     let cipher = Aes256Gcm::new(kek.as_bytes().into());
     let nonce = Nonce::from_slice(&nonce_bytes);
@@ -710,6 +1708,105 @@ fn test_key_rotation() {
         assert_eq!(new_meta.rotation_of, Some(old_id));
     }
 
+    #[test]
+    fn test_hkdf_rfc5869_vector() {
+        // RFC 5869 test case 1, first 32 bytes of the output
+        let salt: Vec<u8> = (0x00..=0x0c).collect();
+        let info: Vec<u8> = (0xf0..=0xf9).collect();
+        assert_eq!(
+            hex::encode(hkdf_sha256(&[0x0b; 22], &salt, &info)),
+            "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf"
+        );
+    }
+
+    #[test]
+    fn test_subkeys_follow_master_rotation() {
+        let tmp = TempDir::new().expect("failed to create temp dir");
+        let mut km = KeyManager::new(tmp.path());
+        km.init("test-passphrase")
+            .expect("failed to init key manager");
+
+        let master = km
+            .generate(KeyAlgorithm::Aes256Gcm, KeyPurpose::KeyWrap, None, None)
+            .expect("failed to generate master key");
+        let backup = km
+            .derive(master, "backup", KeyPurpose::Encryption, None)
+            .expect("failed to derive subkey");
+        let offsite = km
+            .derive(backup, "offsite", KeyPurpose::Encryption, None)
+            .expect("failed to derive nested subkey");
+        assert!(matches!(
+            km.derive(master, "backup", KeyPurpose::Encryption, None),
+            Err(KeyError::AlreadyDerived(id)) if id == backup
+        ));
+
+        let meta = km.get(offsite).unwrap();
+        let derivation = meta.derivation.clone().unwrap();
+        assert_eq!(derivation.master, master);
+        assert_eq!(derivation.path, ["backup", "offsite"]);
+
+        // Subkeys are derived, not stored, and derivation is deterministic
+        let store = km.load_store().unwrap();
+        let entry = store
+            .keys
+            .iter()
+            .find(|k| k.metadata.id == offsite)
+            .unwrap();
+        assert!(entry.ciphertext.is_empty());
+        let material = km.retrieve(offsite).unwrap();
+        let expected = hkdf_sha256(
+            &hkdf_sha256(
+                km.retrieve(master).unwrap().as_bytes(),
+                SUBKEY_SALT,
+                b"backup",
+            ),
+            SUBKEY_SALT,
+            b"offsite",
+        );
+        assert_eq!(material.as_bytes(), &expected);
+        assert_eq!(meta.fingerprint, key_fingerprint(&material));
+        assert_ne!(km.retrieve(backup).unwrap().as_bytes(), material.as_bytes());
+        assert!(matches!(km.rotate(backup), Err(KeyError::Derived(_))));
+
+        // Rotating the master re-derives the tree
+        let new_master = km.rotate(master).expect("failed to rotate master");
+        assert!(matches!(
+            km.retrieve(offsite),
+            Err(KeyError::AlreadyRevoked(_))
+        ));
+        let keys = km.list().unwrap();
+        let rotated = keys
+            .iter()
+            .find(|k| k.rotation_of == Some(offsite))
+            .expect("subkey was not re-derived");
+        assert_eq!(
+            rotated.derivation,
+            Some(KeyDerivation {
+                master: new_master,
+                path: vec!["backup".to_string(), "offsite".to_string()],
+            })
+        );
+        assert_ne!(rotated.fingerprint, meta.fingerprint);
+        assert_eq!(
+            keys.iter().filter(|k| k.state == KeyState::Active).count(),
+            3
+        );
+
+        // Unlocking does not trip over subkeys, which have no material
+        let mut km2 = KeyManager::new(tmp.path());
+        km2.unlock("test-passphrase").expect("failed to unlock");
+        km2.obliterate(new_master).unwrap();
+        assert!(km2
+            .list()
+            .unwrap()
+            .iter()
+            .filter(|k| k
+                .derivation
+                .as_ref()
+                .is_some_and(|d| d.master == new_master))
+            .all(|k| k.state == KeyState::Obliterated));
+    }
+
     #[test]
     fn test_wrong_passphrase() {
         let tmp = TempDir::new().expect("failed to create temp dir");
@@ -727,4 +1824,326 @@ fn test_wrong_passphrase() {
         let result = km2.unlock("wrong-passphrase");
         assert!(matches!(result, Err(KeyError::InvalidPassphrase)));
     }
+
+    #[test]
+    fn test_store_v2_round_trips_and_detects_tampering() {
+        let tmp = TempDir::new().unwrap();
+        let mut km = KeyManager::new(tmp.path());
+        km.init("test-passphrase").unwrap();
+        let master = km
+            .generate(
+                KeyAlgorithm::X25519,
+                KeyPurpose::KeyWrap,
+                Some("backups".to_string()),
+                Some(30),
+            )
+            .unwrap();
+        let sub = km
+            .derive(master, "offsite", KeyPurpose::Encryption, None)
+            .unwrap();
+        km.revoke(sub).unwrap();
+
+        let path = tmp.path().join(".januskey/keys/keystore.jks");
+        let bytes = fs::read(&path).unwrap();
+        assert!(bytes.starts_with(STORE_MAGIC_V2));
+
+        let mut km2 = KeyManager::new(tmp.path());
+        km2.unlock("test-passphrase").unwrap();
+        let keys = km2.list().unwrap();
+        let original = km.list().unwrap();
+        assert_eq!(
+            serde_json::to_value(&keys).unwrap(),
+            serde_json::to_value(&original).unwrap()
+        );
+        assert_eq!(
+            km2.retrieve(master).unwrap().as_bytes(),
+            km.retrieve(master).unwrap().as_bytes()
+        );
+
+        // Changing the description breaks the MAC, though no key's
+        // wrapping covers it
+        let at = bytes.windows(7).position(|w| w == b"backups").unwrap();
+        let mut altered = bytes.clone();
+        altered[at] = b'B';
+        fs::write(&path, &altered).unwrap();
+        assert!(matches!(
+            KeyManager::new(tmp.path()).unlock("test-passphrase"),
+            Err(KeyError::Tampered)
+        ));
+        assert!(matches!(km.list(), Err(KeyError::Tampered)));
+        // Key IDs are still readable without unlocking
+        assert_eq!(KeyManager::new(tmp.path()).key_ids().unwrap().len(), 2);
+
+        fs::write(&path, &bytes[..bytes.len() - 40]).unwrap();
+        assert!(matches!(
+            KeyManager::new(tmp.path()).unlock("test-passphrase"),
+            Err(KeyError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_store_whose_tag_ends_in_zero_loads() {
+        let tmp = TempDir::new().unwrap();
+        let mut km = KeyManager::new(tmp.path());
+        km.init("test-passphrase").unwrap();
+        let id = km
+            .generate(KeyAlgorithm::Aes256Gcm, KeyPurpose::Encryption, None, None)
+            .unwrap();
+        let material = km.retrieve(id).unwrap();
+
+        // The header nonce is covered by the tag but by nothing else
+        let mut store = km.load_store().unwrap();
+        let kek = km.kek.as_ref().unwrap();
+        let mut attempts = 0u32;
+        while encode_store(kek, &store).last() != Some(&0) {
+            attempts += 1;
+            store.header.nonce[..4].copy_from_slice(&attempts.to_le_bytes());
+        }
+        km.save_store(&store).unwrap();
+        let path = tmp.path().join(".januskey/keys/keystore.jks");
+        assert_eq!(fs::read(&path).unwrap().last(), Some(&0));
+
+        let mut km2 = KeyManager::new(tmp.path());
+        km2.unlock("test-passphrase").unwrap();
+        assert_eq!(km2.retrieve(id).unwrap().as_bytes(), material.as_bytes());
+    }
+
+    #[test]
+    fn test_v1_store_is_migrated_on_unlock() {
+        let tmp = TempDir::new().unwrap();
+        let mut km = KeyManager::new(tmp.path());
+        km.init("test-passphrase").unwrap();
+        let id = km
+            .generate(KeyAlgorithm::Aes256Gcm, KeyPurpose::Encryption, None, None)
+            .unwrap();
+        let material = km.retrieve(id).unwrap();
+
+        // Write the store as v1 wrote it
+        let mut store = km.load_store().unwrap();
+        store.header.magic = STORE_MAGIC_V1.to_string();
+        store.header.version = 1;
+        let path = tmp.path().join(".januskey/keys/keystore.jks");
+        let mut v1 = serde_json::to_vec_pretty(&store).unwrap();
+        // with the zeroed tail an interrupted in-place overwrite left
+        v1.extend_from_slice(&[0; 16]);
+        fs::write(&path, v1).unwrap();
+        // by a version that never wrote v2
+        fs::remove_file(tmp.path().join(".januskey/keys").join(STORE_V2_MARKER)).unwrap();
+        assert!(KeyManager::new(tmp.path()).key_ids().unwrap().contains(&id));
+
+        let mut km2 = KeyManager::new(tmp.path());
+        km2.unlock("test-passphrase").unwrap();
+        assert!(fs::read(&path).unwrap().starts_with(STORE_MAGIC_V2));
+        assert_eq!(km2.retrieve(id).unwrap().as_bytes(), material.as_bytes());
+        assert!(matches!(
+            KeyManager::new(tmp.path()).unlock("wrong-passphrase"),
+            Err(KeyError::InvalidPassphrase)
+        ));
+    }
+
+    #[test]
+    fn test_v2_store_rewritten_as_v1_is_refused() {
+        let tmp = TempDir::new().unwrap();
+        let mut km = KeyManager::new(tmp.path());
+        km.init("test-passphrase").unwrap();
+        let id = km
+            .generate(KeyAlgorithm::Aes256Gcm, KeyPurpose::Encryption, None, None)
+            .unwrap();
+        km.revoke(id).unwrap();
+
+        // Reactivate the key in a v1 store, which carries no MAC
+        let mut store = km.load_store().unwrap();
+        store.header.magic = STORE_MAGIC_V1.to_string();
+        store.header.version = 1;
+        store.keys[0].metadata.state = KeyState::Active;
+        let path = tmp.path().join(".januskey/keys/keystore.jks");
+        fs::write(&path, serde_json::to_vec_pretty(&store).unwrap()).unwrap();
+
+        assert!(matches!(km.get(id), Err(KeyError::Tampered)));
+        assert!(matches!(
+            KeyManager::new(tmp.path()).unlock("test-passphrase"),
+            Err(KeyError::Tampered)
+        ));
+        assert!(!fs::read(&path).unwrap().starts_with(STORE_MAGIC_V2));
+    }
+
+    #[test]
+    fn test_empty_store_rejects_a_wrong_passphrase() {
+        let tmp = TempDir::new().unwrap();
+        KeyManager::new(tmp.path()).init("test-passphrase").unwrap();
+        assert!(matches!(
+            KeyManager::new(tmp.path()).unlock("wrong-passphrase"),
+            Err(KeyError::InvalidPassphrase)
+        ));
+        KeyManager::new(tmp.path())
+            .unlock("test-passphrase")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_failed_unlocks_are_throttled_and_audited() {
+        let tmp = TempDir::new().expect("failed to create temp dir");
+        let mut km = KeyManager::new(tmp.path());
+        km.init("correct-passphrase")
+            .expect("failed to init key manager");
+        km.generate(KeyAlgorithm::Aes256Gcm, KeyPurpose::Encryption, None, None)
+            .expect("failed to generate key");
+
+        let mut km = KeyManager::new(tmp.path());
+        for _ in 0..UNLOCK_FREE_ATTEMPTS {
+            assert!(matches!(
+                km.unlock("wrong-passphrase"),
+                Err(KeyError::InvalidPassphrase)
+            ));
+        }
+        // Even the right passphrase must wait now
+        assert!(matches!(
+            km.unlock("correct-passphrase"),
+            Err(KeyError::Throttled(1))
+        ));
+
+        // Once the delay has passed, success resets the run but not the total
+        let mut failures = km.unlock_failures().unwrap();
+        failures.last_failure = Some(Utc::now() - chrono::Duration::seconds(2));
+        km.save_unlock_failures(&failures).unwrap();
+        km.unlock("correct-passphrase").expect("unlock after delay");
+        let failures = km.unlock_failures().unwrap();
+        assert_eq!((failures.total, failures.consecutive), (3, 0));
+
+        let audit = km.audit_log();
+        let failed = audit
+            .read_all()
+            .unwrap()
+            .into_iter()
+            .filter(|e| e.event_type == crate::attestation::AuditEventType::UnlockFailed)
+            .count();
+        assert_eq!(failed, 3);
+        assert!(audit.verify_integrity().unwrap().valid);
+    }
+
+    #[test]
+    fn test_unlock_delay_doubles_up_to_an_hour() {
+        let now = Utc::now();
+        let delay = |consecutive| {
+            UnlockFailures {
+                total: consecutive as u64,
+                consecutive,
+                last_failure: Some(now),
+            }
+            .retry_at()
+            .map(|at| (at - now).num_seconds())
+        };
+        assert_eq!(delay(2), None);
+        assert_eq!(delay(3), Some(1));
+        assert_eq!(delay(6), Some(8));
+        assert_eq!(delay(40), Some(UNLOCK_MAX_DELAY_SECS));
+        assert_eq!(delay(u32::MAX), Some(UNLOCK_MAX_DELAY_SECS));
+    }
+
+    #[test]
+    fn test_key_obliteration() {
+        let tmp = TempDir::new().expect("failed to create temp dir");
+        let mut km = KeyManager::new(tmp.path());
+
+        km.init("test-passphrase")
+            .expect("failed to init key manager");
+
+        let id = km
+            .generate(KeyAlgorithm::Aes256Gcm, KeyPurpose::Encryption, None, None)
+            .expect("failed to generate key for obliteration");
+        let keep = km
+            .generate(KeyAlgorithm::Aes256Gcm, KeyPurpose::Encryption, None, None)
+            .expect("failed to generate key to keep");
+
+        let proof = km.obliterate(id).expect("failed to obliterate key");
+        assert!(proof.verify());
+        assert_eq!(proof.fingerprint, km.get(id).unwrap().fingerprint);
+        assert_eq!(km.get(id).unwrap().state, KeyState::Obliterated);
+        assert!(matches!(km.retrieve(id), Err(KeyError::Obliterated(_))));
+        assert!(matches!(km.obliterate(id), Err(KeyError::Obliterated(_))));
+        assert!(matches!(km.revoke(id), Err(KeyError::Obliterated(_))));
+
+        let store = km.load_store().unwrap();
+        let wrapped = store.keys.iter().find(|k| k.metadata.id == id).unwrap();
+        assert!(wrapped.ciphertext.is_empty());
+        assert_eq!(wrapped.nonce, [0u8; NONCE_LENGTH]);
+
+        let events = km.audit_log().get_key_history(id).unwrap();
+        assert_eq!(
+            events.last().unwrap().event_type,
+            crate::attestation::AuditEventType::KeyObliterated
+        );
+
+        // The obliterated key is first in the store; unlocking still works
+        let mut km2 = KeyManager::new(tmp.path());
+        km2.unlock("test-passphrase")
+            .expect("failed to unlock after obliteration");
+        assert!(km2.retrieve(keep).is_ok());
+        assert!(matches!(
+            KeyManager::new(tmp.path()).unlock("wrong-passphrase"),
+            Err(KeyError::InvalidPassphrase)
+        ));
+
+        let mut tampered = proof.clone();
+        tampered.fingerprint = "0000000000000000".to_string();
+        assert!(!tampered.verify());
+
+        // A run interrupted before the new store landed left its aside
+        // link on the live store: unlock drops the link, not the bytes
+        let keys = tmp.path().join(".januskey").join("keys");
+        let (store, aside) = (keys.join("keystore.jks"), keys.join(STORE_ASIDE));
+        assert!(!aside.exists());
+        fs::hard_link(&store, &aside).unwrap();
+        let mut km3 = KeyManager::new(tmp.path());
+        km3.unlock("test-passphrase").unwrap();
+        assert!(!aside.exists());
+        assert!(km3.retrieve(keep).is_ok());
+
+        // One interrupted after it landed left the old store aside: it is
+        // zeroed in place before being unlinked
+        fs::write(&aside, b"old store bytes").unwrap();
+        let other_link = keys.join("watch");
+        fs::hard_link(&aside, &other_link).unwrap();
+        KeyManager::new(tmp.path())
+            .unlock("test-passphrase")
+            .unwrap();
+        assert!(!aside.exists());
+        assert_eq!(fs::read(&other_link).unwrap(), vec![0u8; 15]);
+    }
+
+    #[test]
+    fn test_tpm_unlock_falls_back_without_a_usable_seal() {
+        let tmp = TempDir::new().unwrap();
+        let mut km = KeyManager::new(tmp.path());
+        km.init("test-passphrase").unwrap();
+
+        let mut km2 = KeyManager::new(tmp.path());
+        assert!(!km2.is_tpm_sealed());
+        assert!(!km2.unlock_with_tpm().unwrap());
+        assert!(matches!(
+            km2.seal_to_tpm(tpm::DEFAULT_PCRS),
+            Err(KeyError::NotInitialized)
+        ));
+
+        // A seal the TPM cannot open fails as a TPM error, not as a failed
+        // unlock attempt, and leaves the passphrase working
+        let seal = TpmSeal {
+            pcrs: tpm::DEFAULT_PCRS.to_string(),
+            public: "not hex".to_string(),
+            private: String::new(),
+        };
+        fs::write(
+            tmp.path().join(".januskey/keys/tpm.json"),
+            serde_json::to_vec(&seal).unwrap(),
+        )
+        .unwrap();
+        assert!(km2.is_tpm_sealed());
+        assert!(matches!(km2.unlock_with_tpm(), Err(KeyError::Tpm(_))));
+        assert_eq!(km2.unlock_failures().unwrap().consecutive, 0);
+        km2.unlock("test-passphrase").unwrap();
+
+        assert!(km2.forget_tpm().unwrap());
+        assert!(!km2.forget_tpm().unwrap());
+        assert!(!km2.unlock_with_tpm().unwrap());
+    }
 }
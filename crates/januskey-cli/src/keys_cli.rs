@@ -2,40 +2,45 @@
 // Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
 // SPDX-FileCopyrightText: 2025 Jonathan D.A. Jewell
 //
-// JanusKey Key Management CLI
-// Minimal CLI for cryptographic key lifecycle management
+// JanusKey Key Management CLI: the `jk keys` subcommands
+// Also run by the deprecated standalone `jk-keys` binary (jk_keys.rs)
 
-use clap::{Parser, Subcommand};
+use clap::Subcommand;
 use colored::Colorize;
 use dialoguer::{Confirm, Password};
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
-mod attestation;
-mod keys;
-use attestation::AuditEventType;
-use keys::{KeyAlgorithm, KeyManager, KeyPurpose, KeyState};
-
-#[derive(Parser)]
-#[command(name = "jk-keys")]
-#[command(about = "JanusKey cryptographic key management")]
-#[command(version)]
-struct Cli {
-    /// Working directory (defaults to current)
-    #[arg(short, long, global = true)]
-    dir: Option<PathBuf>,
-
-    #[command(subcommand)]
-    command: Commands,
-}
+use januskey::attestation::{AuditEntry, AuditEventType};
+use januskey::envelope::{self, Envelope, SealFormat};
+use januskey::keys::{KeyAlgorithm, KeyManager, KeyPurpose, KeyState};
+use januskey::passphrase;
+use januskey::pkcs11;
+use januskey::tpm;
+use januskey::{JanusKey, OperationMetadata};
 
+/// Key management subcommands
 #[derive(Subcommand)]
-enum Commands {
+pub enum KeysCommand {
     /// Initialize a new key store
     Init {
         /// Skip recovery key generation
         #[arg(long)]
         no_recovery: bool,
+
+        /// Keep the key-encryption key on a hardware token through this
+        /// PKCS#11 module; unlocking then takes the token's PIN
+        #[arg(long, value_name = "MODULE")]
+        pkcs11: Option<PathBuf>,
+
+        /// Label of the token to use (defaults to the first present)
+        #[arg(long, value_name = "LABEL", requires = "pkcs11")]
+        token: Option<String>,
+
+        /// Label of the RSA key pair on the token, generated if missing
+        #[arg(long, value_name = "LABEL", requires = "pkcs11", default_value = pkcs11::DEFAULT_KEY_LABEL)]
+        token_key: String,
     },
 
     /// List all keys in the store
@@ -64,13 +69,70 @@ enum Commands {
         expires: Option<u64>,
     },
 
+    /// Derive a subkey from a master key (or another subkey) for a
+    /// context; only the derivation path is stored
+    Derive {
+        /// Key ID to derive from
+        #[arg(value_name = "KEY_ID")]
+        parent: Uuid,
+
+        /// Context string naming the subkey's use (e.g. backup)
+        #[arg(short, long)]
+        context: String,
+
+        /// Key purpose: encryption, signing, keywrap, recovery
+        #[arg(short, long, default_value = "encryption")]
+        purpose: String,
+
+        /// Description for the key
+        #[arg(short, long)]
+        description: Option<String>,
+    },
+
     /// Show details for a specific key
     Show {
         /// Key ID (UUID)
         key_id: Uuid,
     },
 
-    /// Rotate a key (generate new, revoke old)
+    /// Print the hex-encoded public key of an Ed25519 or X25519 key, for
+    /// others to verify signatures or pass to --recipient
+    ExportPub {
+        /// Key ID (UUID)
+        key_id: Uuid,
+
+        /// Print an X25519 key as an age recipient (age1...)
+        #[arg(long)]
+        age: bool,
+    },
+
+    /// Write an X25519 key as an age identity file, so files encrypted to
+    /// it with --age open with `age -d -i FILE`
+    ExportIdentity {
+        /// Key ID (UUID)
+        key_id: Uuid,
+
+        /// Identity file to create (owner-only)
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+
+    /// Decrypt a file encrypted to one of your X25519 keys (--recipient),
+    /// as an envelope or an age file
+    Decrypt {
+        /// Encrypted file
+        file: PathBuf,
+
+        /// X25519 key ID to decrypt with
+        #[arg(short, long)]
+        key: Uuid,
+
+        /// Where to write the plaintext
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+
+    /// Rotate a key (generate new, revoke old); subkeys are re-derived
     Rotate {
         /// Key ID to rotate
         key_id: Uuid,
@@ -86,11 +148,39 @@ enum Commands {
         force: bool,
     },
 
+    /// Destroy a key's material, keeping only its metadata
+    Obliterate {
+        /// Key ID to obliterate
+        key_id: Uuid,
+
+        /// Skip confirmation
+        #[arg(short, long)]
+        force: bool,
+
+        /// Write the obliteration proof (JSON) to this file
+        #[arg(long, value_name = "FILE")]
+        proof: Option<PathBuf>,
+
+        /// Encrypt the proof to this X25519 public key, hex or age1...
+        /// (repeatable)
+        #[arg(long = "recipient", value_name = "PUBLIC_KEY", requires = "proof")]
+        recipients: Vec<String>,
+    },
+
     /// Create encrypted backup of key store
     Backup {
         /// Output path for backup file
         #[arg(short, long)]
         output: PathBuf,
+
+        /// Also encrypt the backup to this X25519 public key, hex or
+        /// age1... (repeatable)
+        #[arg(long = "recipient", value_name = "PUBLIC_KEY")]
+        recipients: Vec<String>,
+
+        /// Encrypt in the age format, so recipients can use `age -d`
+        #[arg(long, requires = "recipients")]
+        age: bool,
     },
 
     /// Show key store status
@@ -101,15 +191,50 @@ enum Commands {
         #[command(subcommand)]
         command: AuditCommands,
     },
+
+    /// Unlock automatically on this machine with a TPM-sealed KEK (Linux)
+    Tpm {
+        #[command(subcommand)]
+        command: TpmCommands,
+    },
 }
 
 #[derive(Subcommand)]
-enum AuditCommands {
+pub enum TpmCommands {
+    /// Seal the key-encryption key to this machine's TPM and boot state
+    Seal {
+        /// PCRs the seal is bound to, as BANK:INDEX[,INDEX...]
+        #[arg(long, default_value = tpm::DEFAULT_PCRS)]
+        pcrs: String,
+    },
+
+    /// Remove the TPM seal; unlocking takes the passphrase again
+    Remove,
+}
+
+#[derive(Subcommand)]
+pub enum AuditCommands {
     /// Show recent audit entries
     Show {
         /// Number of entries to show
         #[arg(short, long, default_value = "20")]
         limit: usize,
+
+        /// Keep running and print new entries as they are written
+        #[arg(short, long)]
+        follow: bool,
+
+        /// Only show these event types, e.g. KEY_RETRIEVED (repeatable)
+        #[arg(short, long = "event", value_name = "TYPE")]
+        events: Vec<AuditEventType>,
+
+        /// Only show events for this key
+        #[arg(short, long = "key", value_name = "KEY_ID")]
+        key: Option<Uuid>,
+
+        /// With --follow, also print file operations from the operation log
+        #[arg(long, requires = "follow")]
+        operations: bool,
     },
 
     /// Show audit history for a specific key
@@ -129,48 +254,91 @@ enum AuditCommands {
     },
 }
 
-fn main() {
-    if let Err(e) = run() {
-        eprintln!("{} {}", "error:".red().bold(), e);
-        std::process::exit(1);
-    }
-}
-
-fn run() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
-
-    let dir = cli
-        .dir
-        .unwrap_or_else(|| std::env::current_dir().expect("Cannot get current directory"));
-
-    let mut km = KeyManager::new(&dir);
-
-    match cli.command {
-        Commands::Init { no_recovery } => cmd_init(&mut km, no_recovery)?,
-        Commands::List { active } => cmd_list(&mut km, active)?,
-        Commands::Generate {
+/// Run a key management subcommand against the key store in `dir`, printing
+/// JSON where supported when `json` is set
+pub fn run(dir: &Path, json: bool, command: KeysCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let mut km = KeyManager::new(dir);
+
+    match command {
+        KeysCommand::Init {
+            no_recovery,
+            pkcs11: Some(module),
+            token,
+            token_key,
+        } => cmd_init_token(
+            &mut km,
+            dir,
+            no_recovery,
+            &module,
+            token.as_deref(),
+            &token_key,
+        )?,
+        KeysCommand::Init { no_recovery, .. } => cmd_init(&mut km, dir, no_recovery)?,
+        KeysCommand::List { active } => cmd_list(&mut km, active, json)?,
+        KeysCommand::Generate {
             r#type,
             purpose,
             description,
             expires,
         } => cmd_generate(&mut km, &r#type, &purpose, description, expires)?,
-        Commands::Show { key_id } => cmd_show(&mut km, key_id)?,
-        Commands::Rotate { key_id } => cmd_rotate(&mut km, key_id)?,
-        Commands::Revoke { force, key_id } => cmd_revoke(&mut km, key_id, force)?,
-        Commands::Backup { output } => cmd_backup(&mut km, &output)?,
-        Commands::Status => cmd_status(&km)?,
-        Commands::Audit { command } => match command {
-            AuditCommands::Show { limit } => cmd_audit_show(&mut km, limit)?,
+        KeysCommand::Derive {
+            parent,
+            context,
+            purpose,
+            description,
+        } => cmd_derive(&mut km, parent, &context, &purpose, description)?,
+        KeysCommand::Show { key_id } => cmd_show(&mut km, key_id, json)?,
+        KeysCommand::Rotate { key_id } => cmd_rotate(&mut km, key_id)?,
+        KeysCommand::Revoke { force, key_id } => cmd_revoke(&mut km, key_id, force)?,
+        KeysCommand::ExportPub { key_id, age } => cmd_export_pub(&mut km, key_id, age)?,
+        KeysCommand::ExportIdentity { key_id, output } => {
+            cmd_export_identity(&mut km, key_id, &output)?
+        }
+        KeysCommand::Decrypt { file, key, output } => cmd_decrypt(&mut km, &file, key, &output)?,
+        KeysCommand::Obliterate {
+            force,
+            key_id,
+            proof,
+            recipients,
+        } => cmd_obliterate(&mut km, key_id, force, proof.as_deref(), &recipients)?,
+        KeysCommand::Backup {
+            output,
+            recipients,
+            age,
+        } => cmd_backup(&mut km, &output, &recipients, age)?,
+        KeysCommand::Status => cmd_status(&km, dir, json)?,
+        KeysCommand::Audit { command } => match command {
+            AuditCommands::Show {
+                limit,
+                follow,
+                events,
+                key,
+                operations,
+            } => {
+                let filter = AuditFilter { events, key };
+                let offset = cmd_audit_show(&mut km, limit, &filter)?;
+                if follow {
+                    cmd_audit_follow(&km, dir, &filter, offset, operations)?;
+                }
+            }
             AuditCommands::History { key_id } => cmd_audit_history(&mut km, key_id)?,
             AuditCommands::Verify => cmd_audit_verify(&mut km)?,
             AuditCommands::Export { output } => cmd_audit_export(&mut km, &output)?,
         },
+        KeysCommand::Tpm { command } => match command {
+            TpmCommands::Seal { pcrs } => cmd_tpm_seal(&mut km, &pcrs)?,
+            TpmCommands::Remove => cmd_tpm_remove(&km)?,
+        },
     }
 
     Ok(())
 }
 
-fn cmd_init(km: &mut KeyManager, _no_recovery: bool) -> Result<(), Box<dyn std::error::Error>> {
+fn cmd_init(
+    km: &mut KeyManager,
+    dir: &Path,
+    _no_recovery: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
     if km.is_initialized() {
         return Err("Key store already initialized".into());
     }
@@ -196,33 +364,114 @@ fn cmd_init(km: &mut KeyManager, _no_recovery: bool) -> Result<(), Box<dyn std::
         return Err("Passphrase must be at least 8 characters".into());
     }
 
+    let strength = passphrase::estimate(&passphrase);
+    let label = match strength.score {
+        0 | 1 => strength.label().red(),
+        2 => strength.label().yellow(),
+        _ => strength.label().green(),
+    };
+    println!(
+        "Passphrase strength: {} (about 10^{:.0} guesses)",
+        label, strength.guesses_log10
+    );
+    if let Some(warning) = strength.warning {
+        println!("  {} {}", "!".yellow(), warning);
+    }
+    if strength.score < 2 {
+        let confirm = Confirm::new()
+            .with_prompt("Use this weak passphrase anyway?")
+            .default(false)
+            .interact()?;
+
+        if !confirm {
+            println!("{}", "Aborted.".yellow());
+            return Ok(());
+        }
+    }
+
     km.init(&passphrase)?;
 
     println!();
     println!("{}", "✓ Key store initialized successfully".green());
     println!();
+    println!("Location: {}/.januskey/keys/", dir.display());
+    println!();
+    println!("{}", "Next steps:".cyan());
+    println!("  • Generate a key:  jk keys generate --type aes256 --purpose encryption");
+    println!("  • List keys:       jk keys list");
+    println!("  • Create backup:   jk keys backup --output ~/keys-backup.jks");
+
+    Ok(())
+}
+
+fn cmd_init_token(
+    km: &mut KeyManager,
+    dir: &Path,
+    _no_recovery: bool,
+    module: &Path,
+    token: Option<&str>,
+    token_key: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if km.is_initialized() {
+        return Err("Key store already initialized".into());
+    }
+    if !pkcs11::SUPPORTED {
+        return Err("--pkcs11 requires januskey built with the `pkcs11` feature".into());
+    }
+
     println!(
-        "Location: {}/.januskey/keys/",
-        std::env::current_dir()?.display()
+        "{}",
+        "Initializing JanusKey key store on a hardware token...".cyan()
     );
     println!();
-    println!("{}", "Next steps:".cyan());
-    println!("  • Generate a key:  jk-keys generate --type aes256 --purpose encryption");
-    println!("  • List keys:       jk-keys list");
-    println!("  • Create backup:   jk-keys backup --output ~/keys-backup.jks");
+    println!(
+        "{}",
+        "The token and its PIN will be required to unlock the key store.".yellow()
+    );
+    println!();
+
+    let pin = match std::env::var(pkcs11::PIN_ENV) {
+        Ok(pin) => pin,
+        Err(_) => Password::new().with_prompt("Enter token PIN").interact()?,
+    };
+    km.init_with_token(module, token, token_key, &pin)?;
+
+    println!();
+    println!("{}", "✓ Key store initialized successfully".green());
+    println!();
+    println!("Location: {}/.januskey/keys/", dir.display());
+    println!("KEK:      encrypted to {} on the token", token_key.bold());
+    println!();
+    println!(
+        "{}",
+        "Losing the token loses the keys: keep a backup of the store together with a second token holding the same key pair.".yellow()
+    );
 
     Ok(())
 }
 
-fn cmd_list(km: &mut KeyManager, active_only: bool) -> Result<(), Box<dyn std::error::Error>> {
+fn cmd_list(
+    km: &mut KeyManager,
+    active_only: bool,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
     unlock_store(km)?;
 
     let keys = km.list()?;
 
+    if json {
+        let keys: Vec<_> = keys
+            .into_iter()
+            .filter(|k| !active_only || k.state == KeyState::Active)
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&keys)?);
+        return Ok(());
+    }
+
     if keys.is_empty() {
         println!(
             "{}",
-            "No keys in store. Generate one with: jk-keys generate".yellow()
+            "No keys in store. Generate one with: jk keys generate".yellow()
         );
         return Ok(());
     }
@@ -251,6 +500,7 @@ fn cmd_list(km: &mut KeyManager, active_only: bool) -> Result<(), Box<dyn std::e
         let state_str = match key.state {
             KeyState::Active => key.state.to_string().green(),
             KeyState::Revoked => key.state.to_string().red(),
+            KeyState::Obliterated => key.state.to_string().red().bold(),
             KeyState::Rotating => key.state.to_string().yellow(),
             _ => key.state.to_string().normal(),
         };
@@ -290,19 +540,7 @@ fn cmd_generate(
         }
     };
 
-    let key_purpose = match purpose.to_lowercase().as_str() {
-        "encryption" | "encrypt" => KeyPurpose::Encryption,
-        "signing" | "sign" => KeyPurpose::Signing,
-        "keywrap" | "key-wrap" | "wrap" => KeyPurpose::KeyWrap,
-        "recovery" => KeyPurpose::Recovery,
-        _ => {
-            return Err(format!(
-                "Unknown purpose: {}. Use: encryption, signing, keywrap, recovery",
-                purpose
-            )
-            .into())
-        }
-    };
+    let key_purpose = parse_purpose(purpose)?;
 
     println!("{}", "Generating key...".cyan());
 
@@ -326,10 +564,57 @@ fn cmd_generate(
     Ok(())
 }
 
-fn cmd_show(km: &mut KeyManager, key_id: Uuid) -> Result<(), Box<dyn std::error::Error>> {
+fn parse_purpose(purpose: &str) -> Result<KeyPurpose, Box<dyn std::error::Error>> {
+    match purpose.to_lowercase().as_str() {
+        "encryption" | "encrypt" => Ok(KeyPurpose::Encryption),
+        "signing" | "sign" => Ok(KeyPurpose::Signing),
+        "keywrap" | "key-wrap" | "wrap" => Ok(KeyPurpose::KeyWrap),
+        "recovery" => Ok(KeyPurpose::Recovery),
+        _ => Err(format!(
+            "Unknown purpose: {}. Use: encryption, signing, keywrap, recovery",
+            purpose
+        )
+        .into()),
+    }
+}
+
+fn cmd_derive(
+    km: &mut KeyManager,
+    parent: Uuid,
+    context: &str,
+    purpose: &str,
+    description: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    unlock_store(km)?;
+    let key_purpose = parse_purpose(purpose)?;
+
+    let id = km.derive(parent, context, key_purpose, description)?;
+    let meta = km.get(id)?;
+
+    println!("{}", "✓ Subkey derived successfully".green());
+    println!();
+    println!("  ID:          {}", id.to_string().cyan());
+    println!("  Purpose:     {}", meta.purpose);
+    println!("  Fingerprint: {}", meta.fingerprint.cyan());
+    if let Some(derivation) = &meta.derivation {
+        println!("  Derivation:  {}", derivation);
+    }
+
+    Ok(())
+}
+
+fn cmd_show(
+    km: &mut KeyManager,
+    key_id: Uuid,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
     unlock_store(km)?;
 
     let meta = km.get(key_id)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&meta)?);
+        return Ok(());
+    }
 
     println!("{}", "Key Details:".cyan().bold());
     println!();
@@ -361,6 +646,10 @@ fn cmd_show(km: &mut KeyManager, key_id: Uuid) -> Result<(), Box<dyn std::error:
         println!("  Rotated from: {}", rot.to_string().dimmed());
     }
 
+    if let Some(derivation) = &meta.derivation {
+        println!("  Derivation:  {}", derivation);
+    }
+
     if let Some(desc) = meta.description {
         println!("  Description: {}", desc);
     }
@@ -439,7 +728,173 @@ fn cmd_revoke(
     Ok(())
 }
 
-fn cmd_backup(km: &mut KeyManager, output: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+fn cmd_export_pub(
+    km: &mut KeyManager,
+    key_id: Uuid,
+    age: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    unlock_store(km)?;
+    let public_key = km.public_key(key_id)?;
+    if !age {
+        println!("{}", hex::encode(public_key));
+    } else if km.get(key_id)?.algorithm == KeyAlgorithm::X25519 {
+        println!("{}", envelope::age_recipient(&public_key));
+    } else {
+        return Err(format!(
+            "Key {} is not an X25519 key; only those are age recipients",
+            key_id
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn cmd_export_identity(
+    km: &mut KeyManager,
+    key_id: Uuid,
+    output: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    unlock_store(km)?;
+    if km.get(key_id)?.algorithm != KeyAlgorithm::X25519 {
+        return Err(format!(
+            "Key {} is not an X25519 key; only those are age identities",
+            key_id
+        )
+        .into());
+    }
+    let secret = km.retrieve(key_id)?;
+    let public_key = envelope::age_recipient(&km.public_key(key_id)?);
+
+    // Laid out as age-keygen writes identities
+    let identity = format!(
+        "# created: {}\n# public key: {}\n{}\n",
+        chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        public_key,
+        envelope::age_identity(&secret)
+    );
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(output)
+        .map_err(|e| format!("Cannot create {}: {}", output.display(), e))?;
+    std::io::Write::write_all(&mut file, identity.as_bytes())?;
+
+    println!("{}", "✓ Identity written".green());
+    println!("  Output:     {}", output.display());
+    println!("  Public key: {}", public_key);
+    println!();
+    println!(
+        "{}",
+        "The file holds the secret key unencrypted; keep it as safe as the key store.".yellow()
+    );
+    Ok(())
+}
+
+fn cmd_decrypt(
+    km: &mut KeyManager,
+    file: &Path,
+    key_id: Uuid,
+    output: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = std::fs::read(file)?;
+    if !envelope::is_sealed(&bytes) {
+        return Err(format!("{} is not an encrypted file", file.display()).into());
+    }
+    unlock_store(km)?;
+
+    let plaintext = envelope::open_with(&bytes, km, key_id)?;
+    januskey::backend::write_atomic(output, &plaintext)?;
+
+    println!("{}", "✓ Decrypted successfully".green());
+    println!("  Output: {}", output.display());
+    Ok(())
+}
+
+fn parse_recipients(recipients: &[String]) -> Result<Vec<[u8; 32]>, Box<dyn std::error::Error>> {
+    Ok(recipients
+        .iter()
+        .map(|key| envelope::parse_public_key(key))
+        .collect::<Result<_, _>>()?)
+}
+
+fn cmd_obliterate(
+    km: &mut KeyManager,
+    key_id: Uuid,
+    force: bool,
+    proof_path: Option<&Path>,
+    recipients: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let recipients = parse_recipients(recipients)?;
+    unlock_store(km)?;
+
+    let meta = km.get(key_id)?;
+
+    if meta.state == KeyState::Obliterated {
+        return Err("Key is already obliterated".into());
+    }
+
+    if !force {
+        println!(
+            "{}",
+            "WARNING: Obliterating a key destroys it. Data encrypted with it \
+             cannot be recovered!"
+                .red()
+                .bold()
+        );
+        println!();
+        println!("Key to obliterate:");
+        println!("  ID:          {}", key_id);
+        println!("  Algorithm:   {}", meta.algorithm);
+        println!("  Fingerprint: {}", meta.fingerprint);
+        println!();
+
+        let confirm = Confirm::new()
+            .with_prompt("Are you sure you want to obliterate this key?")
+            .default(false)
+            .interact()?;
+
+        if !confirm {
+            println!("{}", "Aborted.".yellow());
+            return Ok(());
+        }
+    }
+
+    let proof = km.obliterate(key_id)?;
+
+    println!();
+    println!("{}", "✓ Key obliterated".green());
+    println!();
+    println!("  Obliterated at: {}", proof.obliterated_at.to_rfc3339());
+    println!("  Proof:          {}", proof.commitment.cyan());
+    if let Some(path) = proof_path {
+        let mut bytes = serde_json::to_vec_pretty(&proof)?;
+        if !recipients.is_empty() {
+            bytes = Envelope::seal(&bytes, &recipients)?.to_bytes()?;
+        }
+        januskey::backend::write_atomic(path, &bytes)?;
+        println!("  Proof written:  {}", path.display());
+    }
+    println!();
+    println!(
+        "{}",
+        "Note: Backups made before now still contain the key.".yellow()
+    );
+
+    Ok(())
+}
+
+fn cmd_backup(
+    km: &mut KeyManager,
+    output: &Path,
+    recipients: &[String],
+    age: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let recipients = parse_recipients(recipients)?;
     unlock_store(km)?;
 
     if output.exists() {
@@ -457,11 +912,31 @@ fn cmd_backup(km: &mut KeyManager, output: &PathBuf) -> Result<(), Box<dyn std::
         }
     }
 
-    km.backup(output)?;
+    if recipients.is_empty() {
+        km.backup(output)?;
+    } else {
+        let format = if age {
+            SealFormat::Age
+        } else {
+            SealFormat::Envelope
+        };
+        km.backup_sealed(output, &recipients, format)?;
+    }
 
     println!("{}", "✓ Backup created successfully".green());
     println!();
     println!("  Location: {}", output.display());
+    if age {
+        println!(
+            "  Encrypted to {} recipient(s) as an age file; restore with age -d or jk keys decrypt",
+            recipients.len()
+        );
+    } else if !recipients.is_empty() {
+        println!(
+            "  Encrypted to {} recipient(s); restore with jk keys decrypt",
+            recipients.len()
+        );
+    }
     println!();
     println!(
         "{}",
@@ -471,7 +946,26 @@ fn cmd_backup(km: &mut KeyManager, output: &PathBuf) -> Result<(), Box<dyn std::
     Ok(())
 }
 
-fn cmd_status(km: &KeyManager) -> Result<(), Box<dyn std::error::Error>> {
+fn cmd_status(km: &KeyManager, dir: &Path, json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let store_path = dir.join(".januskey").join("keys").join("keystore.jks");
+    if json {
+        let failures = if km.is_initialized() {
+            Some(km.unlock_failures()?)
+        } else {
+            None
+        };
+        let status = serde_json::json!({
+            "initialized": km.is_initialized(),
+            "store_path": store_path,
+            "token": km.token_config()?,
+            "tpm": km.tpm_seal()?,
+            "unlock_failures": failures,
+            "retry_at": failures.as_ref().and_then(|f| f.retry_at()),
+        });
+        println!("{}", serde_json::to_string_pretty(&status)?);
+        return Ok(());
+    }
+
     println!("{}", "Key Store Status:".cyan().bold());
     println!();
 
@@ -479,25 +973,87 @@ fn cmd_status(km: &KeyManager) -> Result<(), Box<dyn std::error::Error>> {
         println!("  Initialized: {}", "yes".green());
 
         // Try to get key count without unlocking (just check file exists)
-        println!(
-            "  Store path:  {}/.januskey/keys/keystore.jks",
-            std::env::current_dir()?.display()
-        );
+        println!("  Store path:  {}", store_path.display());
+        if let Some(token) = km.token_config()? {
+            println!(
+                "  KEK:         on token {} ({})",
+                token.token_label.as_deref().unwrap_or("(first present)"),
+                token.module.display()
+            );
+        }
+        if let Some(seal) = km.tpm_seal()? {
+            println!("  TPM seal:    PCRs {}", seal.pcrs);
+        }
+
+        let failures = km.unlock_failures()?;
+        if failures.total > 0 {
+            println!(
+                "  Failed unlocks: {} ({} since the last unlock)",
+                failures.total, failures.consecutive
+            );
+        }
+        if let Some(retry_at) = failures.retry_at().filter(|at| *at > chrono::Utc::now()) {
+            println!(
+                "  {} Unlocking delayed until {}",
+                "!".yellow(),
+                retry_at.format("%Y-%m-%d %H:%M:%S")
+            );
+        }
     } else {
         println!("  Initialized: {}", "no".red());
         println!();
-        println!("Run 'jk-keys init' to create a key store.");
+        println!("Run 'jk keys init' to create a key store.");
     }
 
     Ok(())
 }
 
+fn cmd_tpm_seal(km: &mut KeyManager, pcrs: &str) -> Result<(), Box<dyn std::error::Error>> {
+    unlock_store(km)?;
+    let seal = km.seal_to_tpm(pcrs)?;
+    println!(
+        "{} Sealed the key-encryption key to this TPM (PCRs {})",
+        "✓".green(),
+        seal.pcrs
+    );
+    println!("  The store now unlocks without a passphrase while this boot state holds.");
+    println!("  Keep the passphrase: it is needed after firmware or bootloader updates.");
+    Ok(())
+}
+
+fn cmd_tpm_remove(km: &KeyManager) -> Result<(), Box<dyn std::error::Error>> {
+    if km.forget_tpm()? {
+        println!("{} Removed the TPM seal", "✓".green());
+    } else {
+        println!("The key store is not sealed to a TPM.");
+    }
+    Ok(())
+}
+
 fn unlock_store(km: &mut KeyManager) -> Result<(), Box<dyn std::error::Error>> {
     if !km.is_initialized() {
-        return Err("Key store not initialized. Run 'jk-keys init' first.".into());
+        return Err("Key store not initialized. Run 'jk keys init' first.".into());
+    }
+
+    match km.unlock_with_tpm() {
+        Ok(true) => return Ok(()),
+        Ok(false) => {}
+        Err(e) => eprintln!(
+            "{} TPM unlock failed ({}); falling back to the passphrase",
+            "!".yellow(),
+            e
+        ),
     }
 
-    let passphrase = Password::new().with_prompt("Enter passphrase").interact()?;
+    let (env, prompt) = if km.uses_token() {
+        (pkcs11::PIN_ENV, "Enter token PIN")
+    } else {
+        ("JANUSKEY_PASSPHRASE", "Enter passphrase")
+    };
+    let passphrase = match std::env::var(env) {
+        Ok(passphrase) => passphrase,
+        Err(_) => Password::new().with_prompt(prompt).interact()?,
+    };
 
     km.unlock(&passphrase)?;
     Ok(())
@@ -518,6 +1074,7 @@ fn format_event_type(event_type: AuditEventType) -> colored::ColoredString {
     match event_type {
         AuditEventType::StoreInitialized => "INIT".cyan(),
         AuditEventType::StoreUnlocked => "UNLOCK".normal(),
+        AuditEventType::UnlockFailed => "UNLOCK FAILED".red(),
         AuditEventType::KeyGenerated => "GENERATE".green(),
         AuditEventType::KeyRetrieved => "RETRIEVE".yellow(),
         AuditEventType::KeyRotated => "ROTATE".blue(),
@@ -525,19 +1082,108 @@ fn format_event_type(event_type: AuditEventType) -> colored::ColoredString {
         AuditEventType::KeyObliterated => "OBLITERATE".red().bold(),
         AuditEventType::BackupCreated => "BACKUP".cyan(),
         AuditEventType::BackupRestored => "RESTORE".cyan(),
+        AuditEventType::AccessGranted => "ACCESS".normal(),
+        AuditEventType::AccessDenied => "ACCESS DENIED".red(),
+        AuditEventType::GuardOverridden => "GUARD OVERRIDE".yellow(),
     }
 }
 
-fn cmd_audit_show(km: &mut KeyManager, limit: usize) -> Result<(), Box<dyn std::error::Error>> {
+/// Which audit entries `audit show` prints
+struct AuditFilter {
+    events: Vec<AuditEventType>,
+    key: Option<Uuid>,
+}
+
+impl AuditFilter {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        (self.events.is_empty() || self.events.contains(&entry.event_type))
+            && self.key.is_none_or(|key| {
+                entry
+                    .key_details
+                    .as_ref()
+                    .is_some_and(|kd| kd.key_id == key)
+            })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.events.is_empty() && self.key.is_none()
+    }
+}
+
+/// Print the last `limit` matching entries; returns the log offset they
+/// were read up to
+fn cmd_audit_show(
+    km: &mut KeyManager,
+    limit: usize,
+    filter: &AuditFilter,
+) -> Result<u64, Box<dyn std::error::Error>> {
     unlock_store(km)?;
 
-    let entries = km.audit_log().read_last_n(limit)?;
+    let (mut entries, offset) = km.audit_log().read_from(0)?;
+    entries.retain(|entry| filter.matches(entry));
+    let entries = &entries[entries.len().saturating_sub(limit)..];
 
     if entries.is_empty() {
         println!("{}", "No audit entries found.".yellow());
-        return Ok(());
+        return Ok(offset);
+    }
+
+    print_audit_header();
+    for entry in entries {
+        print_audit_entry(entry);
+    }
+
+    Ok(offset)
+}
+
+/// Poll interval for `audit show --follow`
+const FOLLOW_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Print audit entries (and optionally file operations) as they are
+/// written, until interrupted
+fn cmd_audit_follow(
+    km: &KeyManager,
+    dir: &Path,
+    filter: &AuditFilter,
+    mut offset: u64,
+    operations: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Operations already in the log are not replayed
+    let mut seen: HashSet<String> = HashSet::new();
+    if operations {
+        seen.extend(operation_log(dir)?.into_iter().map(|op| op.id));
     }
 
+    println!();
+    println!("{}", "Following audit log (Ctrl-C to stop)...".dimmed());
+
+    loop {
+        let (entries, next) = km.audit_log().read_from(offset)?;
+        offset = next;
+        for entry in entries.iter().filter(|entry| filter.matches(entry)) {
+            print_audit_entry(entry);
+        }
+
+        // File operations carry no event type or key, so filters hide them
+        if operations && filter.is_empty() {
+            for op in operation_log(dir)? {
+                if seen.insert(op.id.clone()) {
+                    print_operation(&op);
+                }
+            }
+        }
+
+        std::thread::sleep(FOLLOW_INTERVAL);
+    }
+}
+
+/// Current operation log, re-read from the store
+fn operation_log(dir: &Path) -> Result<Vec<OperationMetadata>, Box<dyn std::error::Error>> {
+    let jk = JanusKey::open(dir)?;
+    Ok(jk.metadata_store.operations().to_vec())
+}
+
+fn print_audit_header() {
     println!("{}", "Audit Log:".cyan().bold());
     println!();
     println!(
@@ -548,38 +1194,55 @@ fn cmd_audit_show(km: &mut KeyManager, limit: usize) -> Result<(), Box<dyn std::
         "Details".bold()
     );
     println!("{}", "-".repeat(80));
+}
 
-    for entry in entries {
-        let timestamp = entry.timestamp.format("%Y-%m-%d %H:%M:%S");
-        let event_str = format_event_type(entry.event_type);
-        let actor = if entry.actor.len() > 18 {
-            format!("{}...", &entry.actor[..15])
-        } else {
-            entry.actor.clone()
-        };
+fn print_audit_entry(entry: &AuditEntry) {
+    let timestamp = entry.timestamp.format("%Y-%m-%d %H:%M:%S");
+    let event_str = format_event_type(entry.event_type);
+    let actor = if entry.actor.len() > 18 {
+        format!("{}...", &entry.actor[..15])
+    } else {
+        entry.actor.clone()
+    };
 
-        let details = if let Some(ref kd) = entry.key_details {
-            format!("key:{}", &kd.fingerprint)
-        } else if let Some(ref reason) = entry.reason {
-            if reason.len() > 30 {
-                format!("{}...", &reason[..27])
-            } else {
-                reason.clone()
-            }
+    let details = if let Some(ref kd) = entry.key_details {
+        format!("key:{}", &kd.fingerprint)
+    } else if let Some(ref reason) = entry.reason {
+        if reason.len() > 30 {
+            format!("{}...", &reason[..27])
         } else {
-            "-".to_string()
-        };
+            reason.clone()
+        }
+    } else {
+        "-".to_string()
+    };
 
-        println!(
-            "{:<20} {:<12} {:<20} {}",
-            timestamp.to_string().dimmed(),
-            event_str,
-            actor,
-            details.dimmed()
-        );
-    }
+    println!(
+        "{:<20} {:<12} {:<20} {}",
+        timestamp.to_string().dimmed(),
+        event_str,
+        actor,
+        details.dimmed()
+    );
+}
 
-    Ok(())
+/// A file operation, in the same columns as audit entries
+fn print_operation(op: &OperationMetadata) {
+    let actor = if op.user.len() > 18 {
+        format!("{}...", &op.user[..15])
+    } else {
+        op.user.clone()
+    };
+    println!(
+        "{:<20} {:<12} {:<20} {}",
+        op.timestamp
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string()
+            .dimmed(),
+        format!("OP:{}", op.op_type.to_string().to_uppercase()).blue(),
+        actor,
+        op.path.display().to_string().dimmed()
+    );
 }
 
 fn cmd_audit_history(km: &mut KeyManager, key_id: Uuid) -> Result<(), Box<dyn std::error::Error>> {
@@ -667,10 +1330,7 @@ fn cmd_audit_verify(km: &mut KeyManager) -> Result<(), Box<dyn std::error::Error
     Ok(())
 }
 
-fn cmd_audit_export(
-    km: &mut KeyManager,
-    output: &PathBuf,
-) -> Result<(), Box<dyn std::error::Error>> {
+fn cmd_audit_export(km: &mut KeyManager, output: &Path) -> Result<(), Box<dyn std::error::Error>> {
     unlock_store(km)?;
 
     if output.exists() {
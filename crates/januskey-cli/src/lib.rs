@@ -7,41 +7,168 @@
 //
 // Core types are provided by reversible-core. This crate adds:
 // - Filesystem operation execution (operations.rs)
+// - Remote storage backends (backend.rs)
+// - Edit scripts for `jk modify` (edit_script.rs)
+// - Octal and symbolic modes for `jk chmod` (mode.rs)
+// - Glob selection with exclusions (select.rs)
+// - Regex renames for `jk rename-bulk` (rename.rs)
+// - Signed history export (export.rs)
+// - History graphs for `jk history --graph` (history_graph.rs)
+// - Envelope and age encryption to X25519 recipients (envelope.rs)
 // - Key management (keys.rs)
+// - Passphrase strength estimation (passphrase.rs)
 // - Audit trail (attestation.rs)
-// - Secure deletion (obliteration.rs, delta.rs)
-// - CLI interface (main.rs, keys_cli.rs)
+// - SIEM forwarding of audit events (audit_sink.rs)
+// - Secure deletion (obliteration.rs)
+// - Legal holds (hold.rs)
+// - History, encryption and confirmation by path (policy.rs)
+// - When batches of changes ask before running (confirm.rs)
+// - Scheduled obliteration of deleted content (retention.rs)
+// - Whole-tree snapshots (snapshot.rs)
+// - Content search over history (search.rs)
+// - Offloading cold blobs to a second backend (tier.rs)
+// - Backup and restore of the whole store (store_backup.rs)
+// - Replication to remote stores (remote.rs)
+// - Several roots sharing one store (workspace.rs)
+// - Environment and store health checks for `jk doctor` (doctor.rs)
+// - Daemon serving the store over a Unix socket (daemon.rs), with API
+//   tokens and roles deciding what clients may ask of it (access.rs)
+// - Read-only JSON over HTTP for dashboards (server.rs)
+// - CLI interface (main.rs, keys_cli.rs; jk_keys.rs is the deprecated
+//   standalone jk-keys)
 
 #![forbid(unsafe_code)]
 
+pub mod access;
 pub mod attestation;
-pub mod delta;
+pub mod audit_sink;
+pub mod backend;
+pub mod confirm;
+#[cfg(unix)]
+pub mod daemon;
+pub mod doctor;
+pub mod edit_script;
+pub mod envelope;
+pub mod export;
+pub mod guard;
+pub mod history_graph;
+pub mod hold;
+#[cfg(target_os = "linux")]
+pub mod intercept;
 pub mod keys;
+pub mod mode;
 pub mod obliteration;
 pub mod operations;
+pub mod passphrase;
+pub mod pkcs11;
+pub mod policy;
+pub mod remote;
+pub mod rename;
+pub mod retention;
+pub mod search;
+pub mod select;
+pub mod server;
+pub mod snapshot;
+pub mod store_backup;
+pub mod tier;
+pub mod tpm;
+pub mod workspace;
 
 // Re-export core types from reversible-core for backward compatibility
-pub use reversible_core::content_store::{self, ContentHash, ContentStore};
+pub use reversible_core::content_store::{self, ContentHash, ContentStore, HashAlgorithm};
+pub use reversible_core::context::{self, ContextCapture, OperationContext};
+pub use reversible_core::delta;
+pub use reversible_core::events::{self, Event, EventBus};
+pub use reversible_core::framing;
 /// Error module — re-exports reversible-core error types with JanusKey naming
 pub mod error {
+    pub use reversible_core::error::ErrorContext;
     pub use reversible_core::error::Result;
     pub use reversible_core::error::ReversibleError as JanusError;
 }
 pub use error::{JanusError, Result};
+pub use reversible_core::actor::{self, Actor, ActorResolver, EnvActorResolver};
+pub use reversible_core::lock::{self, FileLocking, LockMode};
 pub use reversible_core::manifest::{self, ManifestEmitter};
-pub use reversible_core::metadata::{self, MetadataStore, OperationMetadata, OperationType};
-pub use reversible_core::transaction::{self, Transaction, TransactionManager, TransactionPreview};
+pub use reversible_core::metadata::{
+    self, ByteAccounting, DependencyGraph, MetadataStore, OperationMetadata, OperationType,
+    PreservedAttribute, PruneRule,
+};
+pub use reversible_core::ntfs;
+pub use reversible_core::ownership::{self, OwnershipConfig, OwnershipMode};
+pub use reversible_core::refs::{self, References};
+pub use reversible_core::transaction::{
+    self, ContentChange, RollbackStatus, Transaction, TransactionManager, TransactionPreview,
+    TransactionState,
+};
 pub use reversible_core::ReversibleExecutor;
 
+pub use access::{ApiToken, Permission, Role};
 pub use attestation::{AuditEntry, AuditEventType, AuditLog, IntegrityReport, KeyEventDetails};
-pub use keys::{KeyAlgorithm, KeyError, KeyManager, KeyMetadata, KeyPurpose, KeyState};
-pub use operations::{FileOperation, OperationExecutor};
+pub use audit_sink::{AuditFormat, AuditSinkConfig, AuditSinks};
+pub use backend::{
+    ContentStorage, FileBackend, RetryBackend, RetryPolicy, StorageUri, Throttle, TransferPolicy,
+};
+pub use confirm::{
+    ConfirmationConfig, ConfirmationPolicy, ConfirmationReason, ConfirmationRequest, Prompt,
+};
+pub use doctor::{diagnose, Check, CheckStatus, DoctorReport};
+pub use edit_script::EditScript;
+pub use guard::PathGuard;
+pub use history_graph::{HistoryGraph, HistoryLink, LinkKind};
+pub use hold::{LegalHold, LegalHolds};
+pub use keys::{
+    KeyAlgorithm, KeyError, KeyManager, KeyMetadata, KeyPurpose, KeyState, ObliterationProof,
+};
+pub use mode::Mode;
+pub use operations::{FileOperation, OperationExecutor, Simulation};
+pub use policy::{EffectivePolicy, PathPolicies, PathPolicy};
+pub use remote::{Remote, SyncPlan, SyncReport};
+pub use rename::{Collision, RenamePlan, RenameTemplate};
+pub use retention::{PendingObliteration, RetentionRule, RetentionSchedule};
+pub use search::{IndexStatus, Query, SearchHit, SearchIndex};
+pub use select::{select_files, Excludes, Selection};
+pub use snapshot::{Snapshot, SnapshotChange, SnapshotManifest, Snapshots};
+pub use store_backup::{BackupFile, BackupManifest};
+pub use tier::TieringPolicy;
+pub use workspace::Workspaces;
+
+/// Version of the config file layout this build reads and writes
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Upgrades of a config file, each from the version of its index to the
+/// next
+const CONFIG_MIGRATIONS: [fn(&mut serde_json::Map<String, serde_json::Value>);
+    CONFIG_VERSION as usize] = [
+    // 0 to 1: the version is recorded, and the storage path older
+    // releases wrote without it being chosen is dropped
+    |config| {
+        let legacy = config
+            .get("storage_path")
+            .and_then(serde_json::Value::as_str)
+            .is_some_and(|path| std::path::Path::new(path) == legacy_default_storage_path());
+        if legacy {
+            config.insert("storage_path".to_string(), serde_json::Value::Null);
+        }
+    },
+];
 
 /// JanusKey configuration
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Config {
-    /// Path to JanusKey metadata storage
-    pub storage_path: std::path::PathBuf,
+    /// Layout version of the file, [`CONFIG_VERSION`] once loaded; older
+    /// files are migrated
+    #[serde(default)]
+    pub version: u32,
+    /// Where history (content, metadata, transactions) is stored: a path or
+    /// backend URI (`file://`, `ssh://`, `s3://`). `None` keeps it in
+    /// `.januskey/` next to the files.
+    #[serde(default)]
+    pub storage_path: Option<String>,
+    /// ID this root records its operations under in a shared store;
+    /// assigned by `jk init` when `storage_path` is set
+    #[serde(default)]
+    pub workspace: Option<String>,
     /// Enable compression for stored content
     pub compression: bool,
     /// Maximum number of operations to keep in history
@@ -52,44 +179,326 @@ pub struct Config {
     pub dry_run_default: bool,
     /// Enable audit trail
     pub audit_enabled: bool,
+    /// Store modified content as binary deltas against earlier versions
+    #[serde(default = "default_delta_storage")]
+    pub delta_storage: bool,
+    /// Algorithm naming newly stored content (`sha256` or `blake3`).
+    /// Content stored under another algorithm stays readable.
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
+    /// Memory-map large files to hash, store and compare them instead of
+    /// reading them into memory. Off by default: the working files jk
+    /// captures are mapped too, and one truncated or rewritten by another
+    /// process while mapped can crash jk or fail its hash check.
+    #[serde(default)]
+    pub memory_map: bool,
+    /// Largest size in bytes the content store may grow to (no limit if unset)
+    #[serde(default)]
+    pub max_store_bytes: Option<u64>,
+    /// Largest file in bytes whose content one operation may capture
+    #[serde(default)]
+    pub max_capture_bytes: Option<u64>,
+    /// When the store limit would be exceeded, prune history to
+    /// `max_history` operations and collect garbage instead of refusing
+    #[serde(default)]
+    pub gc_on_quota: bool,
+    /// Keep rolling back past an operation that fails to undo, when the
+    /// failure is confined to that operation
+    #[serde(default = "default_rollback_continue_on_error")]
+    pub rollback_continue_on_error: bool,
+    /// Retries for remote storage backends after transient failures
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    /// Chunking, parallelism and bandwidth cap for large remote transfers
+    #[serde(default)]
+    pub transfer: TransferPolicy,
+    /// Where key and obliteration audit events are forwarded, in addition
+    /// to the local chained log
+    #[serde(default)]
+    pub audit_sinks: Vec<AuditSinkConfig>,
+    /// Keep a content index for `jk search`, updated as content is stored
+    /// and deleted, and caught up by `jk gc` and while a daemon is idle
+    #[serde(default)]
+    pub search_index: bool,
+    /// zstd-compress the logs: metadata segments as they are sealed, and
+    /// the transaction and obliteration logs as they are written
+    #[serde(default)]
+    pub compress_logs: bool,
+    /// Offload old or large blobs to a second backend with `jk tier`
+    #[serde(default)]
+    pub tiering: Option<TieringPolicy>,
+    /// Keep content blobs on a backend of their own, such as S3, with a
+    /// bounded local cache; the logs stay at `storage_path`
+    #[serde(default)]
+    pub content_storage: Option<ContentStorage>,
+    /// History, encryption, version limits and confirmation by path; a
+    /// later policy matching a path overrides an earlier one
+    #[serde(default)]
+    pub policies: Vec<PathPolicy>,
+    /// Hex X25519 public keys (`jk keys export-pub`) that content captured
+    /// under an `encrypt_content` policy is encrypted to
+    #[serde(default)]
+    pub content_recipients: Vec<String>,
+    /// When deletes, modifies and restores ask before running: file and
+    /// byte thresholds, and paths that always or never ask
+    #[serde(default)]
+    pub confirmation: ConfirmationConfig,
+    /// Whose files recreated by undo are: the captured IDs, the captured
+    /// names resolved on this machine, or through a remap table
+    #[serde(default)]
+    pub ownership: OwnershipConfig,
+    /// Files and directories, relative to the root unless absolute, that
+    /// operations refuse to change like `.januskey/` itself
+    #[serde(default)]
+    pub protected_paths: Vec<String>,
+    /// Refuse operations on paths outside the root, symlinks followed,
+    /// unless `--allow-outside` is given
+    #[serde(default)]
+    pub confine_to_root: bool,
+    /// What copies carry over from their source besides the content and
+    /// mode, unless `jk cp --preserve` says otherwise
+    #[serde(default)]
+    pub copy_preserve: std::collections::BTreeSet<PreservedAttribute>,
+    /// Lock the files an operation or undo changes against other
+    /// processes, waiting up to `timeout_ms` for them
+    #[serde(default)]
+    pub file_locking: FileLocking,
+    /// What is recorded of the host, process and working directory each
+    /// operation was run from
+    #[serde(default)]
+    pub context: ContextCapture,
+    /// Stores `jk push` and `jk pull` copy the history to and from, by
+    /// name: a path or URI as for `storage_path`
+    #[serde(default)]
+    pub remotes: std::collections::BTreeMap<String, String>,
+    /// API tokens the daemon accepts, each granting a role (`jk token`).
+    /// With none, only the daemon's user can connect and may do anything;
+    /// with some, its group can connect and every request needs a token.
+    #[serde(default)]
+    pub daemon_tokens: Vec<ApiToken>,
+}
+
+fn default_delta_storage() -> bool {
+    true
+}
+
+fn default_rollback_continue_on_error() -> bool {
+    true
+}
+
+/// Storage path written by default before `storage_path` was honoured.
+///
+/// Older configs carry this value without having chosen it, so it is
+/// treated the same as no storage path.
+fn legacy_default_storage_path() -> std::path::PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(|| std::path::PathBuf::from("."))
+        .join("januskey")
 }
 
 impl Default for Config {
     fn default() -> Self {
-        let storage_path = dirs::data_local_dir()
-            .unwrap_or_else(|| std::path::PathBuf::from("."))
-            .join("januskey");
-
         Self {
-            storage_path,
+            version: CONFIG_VERSION,
+            storage_path: None,
+            workspace: None,
             compression: true,
             max_history: 10000,
             auto_confirm: false,
             dry_run_default: false,
             audit_enabled: true,
+            delta_storage: true,
+            hash_algorithm: HashAlgorithm::default(),
+            memory_map: false,
+            max_store_bytes: None,
+            max_capture_bytes: None,
+            gc_on_quota: false,
+            rollback_continue_on_error: true,
+            retry: RetryPolicy::default(),
+            transfer: TransferPolicy::default(),
+            audit_sinks: Vec::new(),
+            search_index: false,
+            compress_logs: false,
+            tiering: None,
+            content_storage: None,
+            policies: Vec::new(),
+            content_recipients: Vec::new(),
+            confirmation: ConfirmationConfig::default(),
+            ownership: OwnershipConfig::default(),
+            protected_paths: Vec::new(),
+            confine_to_root: false,
+            copy_preserve: Default::default(),
+            file_locking: FileLocking::default(),
+            context: ContextCapture::default(),
+            remotes: std::collections::BTreeMap::new(),
+            daemon_tokens: Vec::new(),
         }
     }
 }
 
 impl Config {
-    /// Load config from directory's .januskey/config.json or use defaults
-    pub fn load(dir: &std::path::Path) -> Self {
-        let config_path = dir.join(".januskey").join("config.json");
-        if config_path.exists() {
-            if let Ok(content) = ({
-                use std::io::Read;
-                std::fs::File::open(&config_path).and_then(|mut f| {
-                    let mut buf = String::new();
-                    f.take(10 * 1024 * 1024).read_to_string(&mut buf)?;
-                    Ok(buf)
-                })
-            }) {
-                if let Ok(config) = serde_json::from_str(&content) {
-                    return config;
+    /// Load config from directory's .januskey/config.json, or the defaults
+    /// if there is none. A file that cannot be read or parsed, or that has
+    /// keys which are not settings, is an error rather than a silent fall
+    /// back to the defaults. A file from an older version is migrated, and
+    /// rewritten with the original kept beside it as `config.json.v<N>`.
+    pub fn load(dir: &std::path::Path) -> Result<Self> {
+        let Some(document) = Self::read_document(dir)? else {
+            return Ok(Self::default());
+        };
+        let path = Self::path(dir);
+        let invalid =
+            |message: String| JanusError::InvalidConfig(format!("{}: {}", path.display(), message));
+        let serde_json::Value::Object(mut document) = document else {
+            return Err(invalid("not a JSON object".to_string()));
+        };
+
+        let version = match document.get("version") {
+            None => 0,
+            Some(version) => version
+                .as_u64()
+                .and_then(|version| u32::try_from(version).ok())
+                .ok_or_else(|| invalid(format!("version {} is not a version number", version)))?,
+        };
+        if version > CONFIG_VERSION {
+            return Err(invalid(format!(
+                "version {} is newer than this jk reads ({}); upgrade jk",
+                version, CONFIG_VERSION
+            )));
+        }
+        for migrate in &CONFIG_MIGRATIONS[version as usize..] {
+            migrate(&mut document);
+        }
+        document.insert("version".to_string(), CONFIG_VERSION.into());
+
+        let mut unknown = Vec::new();
+        unknown_keys_in(
+            &serde_json::to_value(Self::default())?,
+            &document,
+            "",
+            &mut unknown,
+        );
+        if !unknown.is_empty() {
+            return Err(invalid(format!("not a setting: {}", unknown.join(", "))));
+        }
+        let config: Config = serde_json::from_value(serde_json::Value::Object(document))
+            .map_err(|e| invalid(e.to_string()))?;
+
+        if version < CONFIG_VERSION {
+            let backup = path.with_extension(format!("json.v{}", version));
+            let rewritten = std::fs::copy(&path, &backup)
+                .map_err(JanusError::from)
+                .and_then(|_| config.save(dir));
+            match rewritten {
+                Ok(()) => tracing::info!(
+                    from = version,
+                    to = CONFIG_VERSION,
+                    backup = %backup.display(),
+                    "migrated config"
+                ),
+                // Still usable as migrated; the next load tries again
+                Err(e) => tracing::warn!("failed to rewrite migrated config: {}", e),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Path of the config file for `dir`
+    pub fn path(dir: &std::path::Path) -> std::path::PathBuf {
+        dir.join(".januskey").join("config.json")
+    }
+
+    /// The config file as JSON, if there is one
+    fn read_document(dir: &std::path::Path) -> Result<Option<serde_json::Value>> {
+        let config_path = Self::path(dir);
+        if !config_path.exists() {
+            return Ok(None);
+        }
+        let content = {
+            use std::io::Read;
+            std::fs::File::open(&config_path).and_then(|f| {
+                let mut buf = String::new();
+                f.take(10 * 1024 * 1024).read_to_string(&mut buf)?;
+                Ok(buf)
+            })
+        }?;
+        serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|e| JanusError::InvalidConfig(format!("{}: {}", config_path.display(), e)))
+    }
+
+    /// The value of setting `key`: a field name, or a dotted path into
+    /// one (`retry.max_attempts`)
+    pub fn get(&self, key: &str) -> Result<serde_json::Value> {
+        let document = serde_json::to_value(self)?;
+        setting(&document, key)
+            .cloned()
+            .ok_or_else(|| JanusError::InvalidConfig(format!("unknown setting '{}'", key)))
+    }
+
+    /// Set `key` (as for [`Config::get`]) to `value` as typed on a command
+    /// line. The value is read as JSON where the setting accepts that
+    /// (numbers, `true`, `null`, lists, objects) and as a string
+    /// otherwise; anything the setting cannot hold is refused.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        if key == "version" {
+            return Err(JanusError::InvalidConfig(
+                "the version is set by migrations, not by hand".to_string(),
+            ));
+        }
+        self.get(key)?;
+        let document = serde_json::to_value(&*self)?;
+        let candidates = serde_json::from_str(value)
+            .ok()
+            .into_iter()
+            .chain(std::iter::once(serde_json::Value::String(
+                value.to_string(),
+            )));
+        let mut first_error = None;
+        for candidate in candidates {
+            let mut edited = document.clone();
+            if let Some(slot) = setting_mut(&mut edited, key) {
+                *slot = candidate;
+            }
+            match serde_json::from_value::<Config>(edited) {
+                Ok(config) => {
+                    *self = config;
+                    return Ok(());
+                }
+                Err(e) => {
+                    first_error.get_or_insert(e);
                 }
             }
         }
-        Self::default()
+        Err(JanusError::InvalidConfig(format!(
+            "{} cannot be set to '{}': {}",
+            key,
+            value,
+            first_error.map(|e| e.to_string()).unwrap_or_default()
+        )))
+    }
+
+    /// Check what parsing alone does not: the storage location, and the
+    /// path policies and confirmation rules against `root`
+    pub fn validate(&self, root: &std::path::Path) -> Result<()> {
+        self.storage_uri()?;
+        let policies = PathPolicies::new(
+            root,
+            &self.policies,
+            &self.content_recipients,
+            self.auto_confirm,
+        )?;
+        ConfirmationPolicy::new(root, &self.confirmation, policies)?;
+        Ok(())
+    }
+
+    /// Parse the configured storage location, if history is not kept locally
+    pub fn storage_uri(&self) -> Result<Option<StorageUri>> {
+        match &self.storage_path {
+            None => Ok(None),
+            Some(path) if std::path::Path::new(path) == legacy_default_storage_path() => Ok(None),
+            Some(path) => StorageUri::parse(path).map(Some),
+        }
     }
 
     /// Save config to directory
@@ -98,46 +507,138 @@ pub fn save(&self, dir: &std::path::Path) -> Result<()> {
         std::fs::create_dir_all(&config_dir)?;
         let config_path = config_dir.join("config.json");
         let content = serde_json::to_string_pretty(self)?;
-        std::fs::write(config_path, content)?;
+        backend::write_atomic(&config_path, content.as_bytes())?;
         Ok(())
     }
 }
 
+/// The value at a dotted `key` in a config document
+fn setting<'a>(document: &'a serde_json::Value, key: &str) -> Option<&'a serde_json::Value> {
+    key.split('.')
+        .try_fold(document, |value, part| value.as_object()?.get(part))
+}
+
+/// The value at a dotted `key` in a config document, to change
+fn setting_mut<'a>(
+    document: &'a mut serde_json::Value,
+    key: &str,
+) -> Option<&'a mut serde_json::Value> {
+    key.split('.')
+        .try_fold(document, |value, part| value.as_object_mut()?.get_mut(part))
+}
+
+/// Describe the keys of `document` that `known` (the defaults) lacks,
+/// with the setting each was probably meant to be. Only objects the
+/// defaults spell out are checked, so free-form maps such as `remotes`
+/// are not.
+fn unknown_keys_in(
+    known: &serde_json::Value,
+    document: &serde_json::Map<String, serde_json::Value>,
+    prefix: &str,
+    unknown: &mut Vec<String>,
+) {
+    let Some(known) = known.as_object().filter(|known| !known.is_empty()) else {
+        return;
+    };
+    for (key, value) in document {
+        let path = format!("{}{}", prefix, key);
+        match (known.get(key), value) {
+            (None, _) => {
+                let closest = known
+                    .keys()
+                    .map(|candidate| (strsim::levenshtein(key, candidate), candidate))
+                    .min()
+                    .filter(|(distance, _)| *distance <= 2);
+                unknown.push(match closest {
+                    Some((_, candidate)) => {
+                        format!("{} (did you mean {}{}?)", path, prefix, candidate)
+                    }
+                    None => path,
+                });
+            }
+            (Some(known), serde_json::Value::Object(nested)) => {
+                unknown_keys_in(known, nested, &format!("{}.", path), unknown)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Format a byte count for people: `1.50 MB`
+pub fn human_bytes(bytes: u64) -> String {
+    const KB: u64 = 1024;
+    const MB: u64 = KB * 1024;
+    const GB: u64 = MB * 1024;
+
+    if bytes >= GB {
+        format!("{:.2} GB", bytes as f64 / GB as f64)
+    } else if bytes >= MB {
+        format!("{:.2} MB", bytes as f64 / MB as f64)
+    } else if bytes >= KB {
+        format!("{:.2} KB", bytes as f64 / KB as f64)
+    } else {
+        format!("{} bytes", bytes)
+    }
+}
+
 /// Main JanusKey instance for a directory
+///
+/// `JanusKey` is `Send + Sync`. Reads take `&self` and operations take
+/// `&mut self`, so an instance shared between threads must be wrapped in
+/// a lock, e.g. `Arc<RwLock<JanusKey>>` (the C ABI handle does this).
+/// Two instances opened on the same directory do not coordinate with
+/// each other; use one instance per directory per process.
 pub struct JanusKey {
     /// Working directory
     pub root: std::path::PathBuf,
     /// Configuration
     pub config: Config,
-    /// Content-addressed storage
-    pub content_store: ContentStore,
+    /// Content-addressed storage, shared with the daemon's interceptor
+    pub content_store: std::sync::Arc<ContentStore>,
     /// Metadata/operation log store
     pub metadata_store: MetadataStore,
     /// Transaction manager
     pub transaction_manager: TransactionManager,
+    /// Who operations are recorded against
+    pub actor: Actor,
+    /// Where operations, undos, stored content and transactions are
+    /// announced; see [`JanusKey::subscribe`]
+    events: EventBus,
+    /// Keys that decrypt content captured encrypted; see
+    /// [`JanusKey::unlock_content`]
+    pub(crate) content_keys: Vec<keys::SecretKey>,
+    /// Paths operations refuse to change; see [`JanusKey::guard`]
+    guard: PathGuard,
 }
 
+// The FFI layer and embedders share JanusKey across threads
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<JanusKey>();
+};
+
 impl JanusKey {
     /// Initialize JanusKey for a directory
     pub fn init(root: &std::path::Path) -> Result<Self> {
+        let config = Config::load(root)?;
+        Self::init_with_config(root, config)
+    }
+
+    /// Initialize JanusKey for a directory with the given configuration
+    ///
+    /// With a shared `storage_path` and no `workspace`, the root is
+    /// registered as a new workspace named after its directory.
+    pub fn init_with_config(root: &std::path::Path, mut config: Config) -> Result<Self> {
         let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
         let jk_dir = root.join(".januskey");
         std::fs::create_dir_all(&jk_dir)?;
 
-        let config = Config::load(&root);
+        if config.workspace.is_none() && config.storage_uri()?.is_some() {
+            let (backend, store_dir) = Self::connect_storage(&root, &config)?;
+            config.workspace = Some(Workspaces::load(&backend, &store_dir)?.new_id(&root));
+        }
         config.save(&root)?;
-
-        let content_store = ContentStore::new(jk_dir.join("content"), config.compression)?;
-        let metadata_store = MetadataStore::new(jk_dir.join("metadata.json"))?;
-        let transaction_manager = TransactionManager::new(jk_dir.join("transactions"))?;
-
-        Ok(Self {
-            root,
-            config,
-            content_store,
-            metadata_store,
-            transaction_manager,
-        })
+        Self::open_stores(root, config)
     }
 
     /// Open existing JanusKey directory
@@ -149,38 +650,2548 @@ pub fn open(root: &std::path::Path) -> Result<Self> {
             return Err(JanusError::NotInitialized(root.display().to_string()));
         }
 
-        let config = Config::load(&root);
-        let content_store = ContentStore::new(jk_dir.join("content"), config.compression)?;
-        let metadata_store = MetadataStore::new(jk_dir.join("metadata.json"))?;
-        let transaction_manager = TransactionManager::new(jk_dir.join("transactions"))?;
+        let config = Config::load(&root)?;
+        Self::open_stores(root, config)
+    }
+
+    /// Connect the configured backend and open the stores on it
+    fn open_stores(root: std::path::PathBuf, config: Config) -> Result<Self> {
+        let (backend, store_dir) = Self::connect_storage(&root, &config)?;
+        let (content_backend, content_dir) =
+            Self::connect_content(&root, &config, &backend, &store_dir)?;
+
+        let events = EventBus::new();
+        let content_store =
+            ContentStore::new_with_backend(content_backend, content_dir, config.compression)?
+                .with_delta(config.delta_storage)
+                .with_algorithm(config.hash_algorithm)
+                .with_mmap(config.memory_map)
+                .with_limits(config.max_store_bytes, config.max_capture_bytes)
+                .with_events(events.clone());
+        let content_store = match &config.tiering {
+            Some(policy) => content_store.with_cold_tier(policy.cold_tier(
+                &root,
+                &config.transfer,
+                &config.retry,
+            )?),
+            None => content_store,
+        };
+        let content_store = if config.search_index {
+            content_store.with_index(std::sync::Arc::new(SearchIndex::open(
+                SearchIndex::path_for(&root),
+            )))
+        } else {
+            content_store
+        };
+        let mut metadata_store =
+            MetadataStore::new_with_backend(backend.clone(), store_dir.join("metadata"))?;
+        if let Some(id) = &config.workspace {
+            let mut workspaces = Workspaces::load(&backend, &store_dir)?;
+            if workspaces.register(id, &root) {
+                workspaces.save(&backend, &store_dir)?;
+            }
+            metadata_store.set_workspace(id);
+        }
+        metadata_store.set_root(&root);
+        metadata_store.set_context(config.context.capture());
+        metadata_store.migrate_paths()?;
+        metadata_store.set_compression(config.compress_logs);
+        let mut transaction_manager =
+            TransactionManager::new_with_backend(backend, store_dir.join("transactions"))?;
+        transaction_manager.set_events(events.clone());
+        transaction_manager.set_compression(config.compress_logs);
 
-        Ok(Self {
+        let jk = Self {
+            guard: PathGuard::for_root(&root, &config),
             root,
             config,
-            content_store,
+            content_store: std::sync::Arc::new(content_store),
             metadata_store,
             transaction_manager,
+            actor: Actor::os(),
+            events,
+            content_keys: Vec::new(),
+        };
+        let mut jk = jk.with_actor_resolver(&EnvActorResolver::default())?;
+        jk.set_refs(refs::from_env()?);
+        Ok(jk)
+    }
+
+    /// The configured backend and the store directory on it
+    fn connect_storage(
+        root: &std::path::Path,
+        config: &Config,
+    ) -> Result<(std::sync::Arc<dyn FileBackend>, std::path::PathBuf)> {
+        match config.storage_uri()? {
+            Some(uri) => Self::connect_uri(root, &uri, config),
+            None => Ok((
+                std::sync::Arc::new(backend::LocalBackend),
+                root.join(".januskey"),
+            )),
+        }
+    }
+
+    /// The backend for `uri` and the directory it names on it
+    fn connect_uri(
+        root: &std::path::Path,
+        uri: &StorageUri,
+        config: &Config,
+    ) -> Result<(std::sync::Arc<dyn FileBackend>, std::path::PathBuf)> {
+        match uri {
+            // Relative local paths are relative to the working directory
+            StorageUri::Local(_) => Ok((uri.connect()?, root.join(uri.root()))),
+            _ => {
+                let retry = config.retry.clone();
+                let backend = retry.run(|| uri.connect_with(&config.transfer, &retry))?;
+                Ok((
+                    std::sync::Arc::new(RetryBackend::new(backend, retry)),
+                    uri.root(),
+                ))
+            }
+        }
+    }
+
+    /// Connect to the remote configured as `name` (see [`remote`])
+    pub fn remote(&self, name: &str) -> Result<Remote> {
+        let uri = self
+            .config
+            .remotes
+            .get(name)
+            .ok_or_else(|| JanusError::RemoteNotFound(name.to_string()))?;
+        let (backend, dir) = Self::connect_uri(&self.root, &StorageUri::parse(uri)?, &self.config)?;
+        let content_store = ContentStore::new_with_backend(
+            backend.clone(),
+            dir.join("content"),
+            self.config.compression,
+        )?;
+        let mut metadata_store = MetadataStore::new_with_backend(backend, dir.join("metadata"))?;
+        metadata_store.set_root(&self.root);
+        Ok(Remote {
+            name: name.to_string(),
+            uri: uri.clone(),
+            content_store,
+            metadata_store,
         })
     }
 
+    /// Copy the operations `remote` lacks, and their content, to it.
+    /// Fails if it has operations this store lacks, newer than its oldest.
+    /// Every workspace's operations are copied.
+    pub fn push(&self, remote: &mut Remote) -> Result<SyncReport> {
+        SyncPlan::new(
+            &self.metadata_store.all_operations(),
+            remote.metadata_store.operations(),
+        )?
+        .apply(
+            &self.content_store,
+            &remote.content_store,
+            &mut remote.metadata_store,
+        )
+    }
+
+    /// Copy the operations this store lacks, and their content, from
+    /// `remote`. Only the history changes; files are left as they are.
+    pub fn pull(&mut self, remote: &Remote) -> Result<SyncReport> {
+        SyncPlan::new(
+            remote.metadata_store.operations(),
+            &self.metadata_store.all_operations(),
+        )?
+        .apply(
+            &remote.content_store,
+            &self.content_store,
+            &mut self.metadata_store,
+        )
+    }
+
+    /// The workspaces registered against this root's store (see
+    /// [`workspace`]); empty unless it is shared
+    pub fn workspaces(&self) -> Result<Workspaces> {
+        let (backend, store_dir) = Self::connect_storage(&self.root, &self.config)?;
+        Workspaces::load(&backend, &store_dir)
+    }
+
+    /// Register this root as workspace `id` of its store (or an ID from
+    /// its directory name), and reopen it there. Operations recorded
+    /// before stay visible to every workspace.
+    pub fn join_workspace(mut self, id: Option<&str>) -> Result<Self> {
+        let id = match (id, &self.config.workspace) {
+            (Some(id), Some(current)) if id == current => return Ok(self),
+            (None, Some(_)) => return Ok(self),
+            (_, Some(current)) => {
+                return Err(JanusError::OperationFailed(format!(
+                    "{} is already workspace {}",
+                    self.root.display(),
+                    current
+                )))
+            }
+            (Some(id), None) => id.to_string(),
+            (None, None) => self.workspaces()?.new_id(&self.root),
+        };
+        self.config.workspace = Some(id);
+        self.config.save(&self.root)?;
+        Self::open_stores(self.root, self.config)
+    }
+
+    /// The operations workspace `id` recorded, oldest first, with paths
+    /// under the root it was last opened from
+    pub fn workspace_history(&self, id: &str) -> Result<Vec<OperationMetadata>> {
+        if self.metadata_store.workspace() == Some(id) {
+            return Ok(self.metadata_store.operations().to_vec());
+        }
+        let workspaces = self.workspaces()?;
+        if !workspaces.roots.contains_key(id) {
+            return Err(JanusError::WorkspaceNotFound(id.to_string()));
+        }
+        Ok(self
+            .metadata_store
+            .other_workspaces()
+            .iter()
+            .filter(|op| op.workspace.as_deref() == Some(id))
+            .map(|op| workspaces.resolve(op))
+            .collect())
+    }
+
+    /// The backend and directory of the content store: its own if
+    /// `content_storage` is set, else beside the logs in `store_dir`
+    fn connect_content(
+        root: &std::path::Path,
+        config: &Config,
+        backend: &std::sync::Arc<dyn FileBackend>,
+        store_dir: &std::path::Path,
+    ) -> Result<(std::sync::Arc<dyn FileBackend>, std::path::PathBuf)> {
+        match &config.content_storage {
+            Some(storage) => storage.connect(root, &config.transfer, &config.retry),
+            None => Ok((backend.clone(), store_dir.join("content"))),
+        }
+    }
+
+    /// Record operations and transactions against the actor `resolver`
+    /// decides on. [`JanusKey::open`] uses [`EnvActorResolver`].
+    pub fn with_actor_resolver(mut self, resolver: &dyn ActorResolver) -> Result<Self> {
+        self.actor = resolver.resolve()?;
+        self.metadata_store.set_actor(&self.actor);
+        self.transaction_manager.set_actor(&self.actor);
+        Ok(self)
+    }
+
+    /// Record `refs` on operations and transactions from now on (see
+    /// [`refs`]). [`JanusKey::open`] takes them from `$JANUSKEY_REFS`.
+    pub fn set_refs(&mut self, refs: References) {
+        self.metadata_store.set_refs(refs.clone());
+        self.transaction_manager.set_refs(refs);
+    }
+
+    /// Paths operations refuse to change: the store, the configured
+    /// `protected_paths`, and with `confine_to_root` those outside the root
+    pub fn guard(&self) -> &PathGuard {
+        &self.guard
+    }
+
+    /// Let operations change protected paths, or not. [`JanusKey::open`]
+    /// allows it if `$JANUSKEY_ALLOW_PROTECTED` is set.
+    pub fn set_allow_protected(&mut self, allowed: bool) {
+        self.guard = self.guard.clone().allowing(allowed);
+    }
+
+    /// Let operations change paths outside the root when `confine_to_root`
+    /// is set, or not. [`JanusKey::open`] allows it if
+    /// `$JANUSKEY_ALLOW_OUTSIDE` is set.
+    pub fn set_allow_outside(&mut self, allowed: bool) {
+        self.guard = self.guard.clone().allowing_outside(allowed);
+    }
+
+    /// Receive an [`Event`] for everything this instance does from now on:
+    /// operations starting, completing and failing, undos, new content in
+    /// the store, and transactions beginning and ending.
+    ///
+    /// Events are sent as they happen and queue until received, so the
+    /// receiver can be moved to another thread; drop it to unsubscribe.
+    pub fn subscribe(&self) -> std::sync::mpsc::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// The obliteration log, `.januskey/obliterations.json`, forwarding
+    /// to the configured audit sinks and refusing content under legal hold
+    pub fn obliteration_manager(&self) -> Result<obliteration::ObliterationManager> {
+        let held = self.legal_holds()?.held_content(
+            &self.root,
+            &self.content_store,
+            &self.metadata_store,
+        )?;
+        Ok(obliteration::ObliterationManager::new(
+            self.root.join(".januskey").join("obliterations.json"),
+        )?
+        .with_sinks(AuditSinks::from_config(&self.config.audit_sinks))
+        .with_holds(held)
+        .with_delta_bases(&self.store_operations())
+        .with_compression(self.config.compress_logs))
+    }
+
+    /// What obliterating `content_hash` would destroy, across every
+    /// workspace sharing the store, with `dependents` deciding the fate of
+    /// deltas stored against it. Changes nothing.
+    pub fn obliteration_impact(
+        &self,
+        content_hash: &ContentHash,
+        dependents: obliteration::DeltaDependents,
+    ) -> Result<obliteration::ObliterationImpact> {
+        obliteration::impact(
+            content_hash,
+            &self.content_store,
+            &self.store_operations(),
+            self.transaction_manager.all(),
+            dependents,
+        )
+    }
+
+    /// The operations of every workspace sharing the content store
+    fn store_operations(&self) -> Vec<OperationMetadata> {
+        self.metadata_store
+            .operations()
+            .iter()
+            .chain(self.metadata_store.other_workspaces())
+            .cloned()
+            .collect()
+    }
+
+    /// The configured path policies
+    pub fn policies(&self) -> Result<PathPolicies> {
+        PathPolicies::new(
+            &self.root,
+            &self.config.policies,
+            &self.config.content_recipients,
+            self.config.auto_confirm,
+        )
+    }
+
+    /// The configured confirmation policy, for embedding applications to
+    /// run their own prompts through (see [`ConfirmationPolicy::confirm`])
+    pub fn confirmation_policy(&self) -> Result<ConfirmationPolicy> {
+        ConfirmationPolicy::new(&self.root, &self.config.confirmation, self.policies()?)
+    }
+
+    /// The confirmation policy configured for `root`, without opening the
+    /// stores
+    pub fn confirmation_policy_for(root: &std::path::Path) -> Result<ConfirmationPolicy> {
+        let config = Config::load(root)?;
+        let policies = PathPolicies::new(
+            root,
+            &config.policies,
+            &config.content_recipients,
+            config.auto_confirm,
+        )?;
+        ConfirmationPolicy::new(root, &config.confirmation, policies)
+    }
+
+    /// An executor over the stores that applies the path policies,
+    /// decrypts with the unlocked content keys and announces on the
+    /// instance's events
+    pub fn executor(&mut self) -> Result<OperationExecutor<'_>> {
+        let policies = self.policies()?;
+        let held = if policies.limits_versions() {
+            self.legal_holds()?
+                .held_operations(&self.root, &self.metadata_store)
+        } else {
+            std::collections::HashSet::new()
+        };
+        Ok(
+            OperationExecutor::new(&self.content_store, &mut self.metadata_store)
+                .with_events(self.events.clone())
+                .with_policies(policies, held)
+                .with_content_keys(&self.content_keys)
+                .with_ownership(self.config.ownership.clone())
+                .with_locking(self.config.file_locking)
+                .with_guard(self.guard.clone()),
+        )
+    }
+
+    /// Unlock the key store with `passphrase` and keep the secret keys of
+    /// the configured `content_recipients` it holds, to decrypt content
+    /// captured under an `encrypt_content` policy. Returns how many were
+    /// found.
+    pub fn unlock_content(&mut self, passphrase: &str) -> Result<usize> {
+        let key_error = |e: KeyError| JanusError::OperationFailed(format!("key store: {}", e));
+        let mut keys = KeyManager::new(&self.root);
+        keys.unlock(passphrase).map_err(key_error)?;
+        self.take_content_keys(&keys)
+    }
+
+    /// [`JanusKey::unlock_content`] with the key store's TPM-sealed KEK in
+    /// place of a passphrase. Returns `None` if the store is not sealed to
+    /// a TPM; an error means the TPM would not unseal it.
+    pub fn unlock_content_with_tpm(&mut self) -> Result<Option<usize>> {
+        let key_error = |e: KeyError| JanusError::OperationFailed(format!("key store: {}", e));
+        let mut keys = KeyManager::new(&self.root);
+        if !keys.unlock_with_tpm().map_err(key_error)? {
+            return Ok(None);
+        }
+        self.take_content_keys(&keys).map(Some)
+    }
+
+    /// Keep the secret keys of the `content_recipients` an unlocked key
+    /// store holds
+    fn take_content_keys(&mut self, keys: &KeyManager) -> Result<usize> {
+        let key_error = |e: KeyError| JanusError::OperationFailed(format!("key store: {}", e));
+        let recipients: Vec<String> = self
+            .config
+            .content_recipients
+            .iter()
+            .map(|key| key.trim().to_ascii_lowercase())
+            .collect();
+        for metadata in keys.list().map_err(key_error)? {
+            if metadata.algorithm != KeyAlgorithm::X25519 {
+                continue;
+            }
+            // Revoked and expired keys cannot be retrieved
+            let Ok(secret) = keys.retrieve(metadata.id) else {
+                continue;
+            };
+            let public_key = hex::encode(keys::x25519_public_key(secret.as_bytes()));
+            if recipients.contains(&public_key) {
+                self.content_keys.push(secret);
+            }
+        }
+        Ok(self.content_keys.len())
+    }
+
+    /// Whether an operation not yet undone holds content captured
+    /// encrypted, which undoing it needs [`JanusKey::unlock_content`] for
+    pub fn has_encrypted_content(&self) -> bool {
+        self.metadata_store
+            .operations()
+            .iter()
+            .any(|op| op.plaintext_hash.is_some() && !op.undone)
+    }
+
+    /// Whether [`JanusKey::unlock_content`] has found keys for encrypted
+    /// content
+    pub fn content_unlocked(&self) -> bool {
+        !self.content_keys.is_empty()
+    }
+
+    /// Content `op` captured before it ran, decrypted if it was stored
+    /// encrypted
+    pub fn captured_content(&self, op: &OperationMetadata) -> Result<Vec<u8>> {
+        policy::captured_content(&self.content_store, op, &self.content_keys)
+    }
+
+    /// [`TransactionPreview`] of `transaction` with each operation's
+    /// content change: what it captured before running, and after, what
+    /// the next operation on the same path captured or else the file as
+    /// it is now. Moves, chmods and the like change no content.
+    pub fn preview_with_content(&self, transaction: &Transaction) -> Result<TransactionPreview> {
+        let mut preview = TransactionPreview::from_transaction(transaction, &self.metadata_store);
+        let current = |path: &std::path::Path| std::fs::read(self.root.join(path)).ok();
+        // Content of a path as the next operation on it found it, filled
+        // in walking back from the newest operation
+        let mut later: std::collections::HashMap<std::path::PathBuf, Option<Vec<u8>>> =
+            std::collections::HashMap::new();
+
+        for entry in preview.operations.iter_mut().rev() {
+            let Some(op) = self.metadata_store.get(&entry.op_id) else {
+                continue;
+            };
+            let after_at = |later: &std::collections::HashMap<_, Option<Vec<u8>>>,
+                            path: &std::path::Path| {
+                later.get(path).cloned().unwrap_or_else(|| current(path))
+            };
+            let captured = || -> Result<Option<Vec<u8>>> {
+                op.content_hash
+                    .as_ref()
+                    .map(|_| self.captured_content(op))
+                    .transpose()
+            };
+            match op.op_type {
+                OperationType::Delete | OperationType::Modify | OperationType::Create => {
+                    let change = ContentChange {
+                        before: captured()?,
+                        after: match op.op_type {
+                            OperationType::Delete => None,
+                            _ => after_at(&later, &op.path),
+                        },
+                    };
+                    later.insert(op.path.clone(), change.before.clone());
+                    entry.change = Some(change);
+                }
+                OperationType::Copy => {
+                    if let Some(dest) = &op.path_secondary {
+                        entry.change = Some(ContentChange {
+                            before: None,
+                            after: after_at(&later, dest),
+                        });
+                        later.insert(dest.clone(), None);
+                    }
+                }
+                OperationType::Move => {
+                    if let Some(dest) = &op.path_secondary {
+                        let moved = after_at(&later, dest);
+                        later.insert(op.path.clone(), moved);
+                        later.insert(dest.clone(), None);
+                    }
+                }
+                OperationType::Chmod | OperationType::Chown => {}
+            }
+        }
+        Ok(preview)
+    }
+
+    /// Legal holds (`jk hold`), forwarding to the configured audit sinks
+    pub fn legal_holds(&self) -> Result<LegalHolds> {
+        Ok(
+            LegalHolds::open(self.root.join(".januskey").join("holds.json"))?
+                .with_sinks(AuditSinks::from_config(&self.config.audit_sinks)),
+        )
+    }
+
+    /// Scheduled obliteration rules (`jk obliterate --schedule`)
+    pub fn retention_schedule(&self) -> Result<RetentionSchedule> {
+        RetentionSchedule::open(self.root.join(".januskey").join("retention.json"))
+    }
+
+    /// Named whole-tree snapshots (`jk snapshot`)
+    pub fn snapshots(&self) -> Result<Snapshots> {
+        Snapshots::open(self.root.join(".januskey").join("snapshots.json"))
+    }
+
+    /// Snapshot every file under the root that `excludes` does not skip,
+    /// incrementally against the snapshot `base` if given
+    pub fn snapshot(
+        &self,
+        name: Option<&str>,
+        excludes: &Excludes,
+        base: Option<&str>,
+    ) -> Result<Snapshot> {
+        self.snapshots()?
+            .create(name, &self.root, &self.content_store, excludes, base)
+    }
+
+    /// The operations [`JanusKey::restore_snapshot`] would execute
+    pub fn restore_snapshot_plan(
+        &self,
+        name: &str,
+        excludes: &Excludes,
+    ) -> Result<Vec<FileOperation>> {
+        let snapshots = self.snapshots()?;
+        snapshots
+            .manifest(snapshots.get(name)?, &self.content_store)?
+            .restore_operations(&self.root, &self.content_store, excludes)
+    }
+
+    /// Bring the tree back to the snapshot `name` with the fewest creates,
+    /// modifies and deletes, executed all-or-nothing (see
+    /// [`JanusKey::execute_batch`]). Files `excludes` skips are left alone.
+    pub fn restore_snapshot(
+        &mut self,
+        name: &str,
+        excludes: &Excludes,
+    ) -> Result<Vec<OperationMetadata>> {
+        let operations = self.restore_snapshot_plan(name, excludes)?;
+        self.execute_batch(operations)
+    }
+
     /// Check if directory is initialized
     pub fn is_initialized(root: &std::path::Path) -> bool {
         root.join(".januskey").exists()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::TempDir;
+    /// Summary of the store for `jk status`
+    pub fn status(&self) -> Result<StoreStatus> {
+        Ok(StoreStatus {
+            root: self.root.clone(),
+            storage: self.config.storage_uri()?.map(|uri| uri.to_string()),
+            operations: self.metadata_store.count(),
+            blobs: self.content_store.count()?,
+            bytes: self.content_store.total_size()?,
+            offloaded: self.content_store.offloaded()?.len(),
+            active_transaction: self.transaction_manager.active().cloned(),
+        })
+    }
 
-    #[test]
-    fn test_init_and_open() {
-        let tmp = TempDir::new().unwrap();
-        let jk = JanusKey::init(tmp.path()).unwrap();
-        assert!(JanusKey::is_initialized(tmp.path()));
+    /// What the recorded operations read and added to the content store,
+    /// for `jk stats`. Operations recorded before byte accounting are
+    /// counted in `unaccounted`.
+    pub fn byte_usage(&self) -> ByteUsage {
+        let mut usage = ByteUsage::default();
+        let mut by_path: std::collections::HashMap<std::path::PathBuf, ByteTotals> =
+            std::collections::HashMap::new();
+        for op in self.metadata_store.operations() {
+            let Some(bytes) = &op.bytes else {
+                if op.content_hash.is_some() {
+                    usage.unaccounted += 1;
+                }
+                continue;
+            };
+            usage.total.add(bytes);
+            usage
+                .by_type
+                .entry(op.op_type.to_string())
+                .or_default()
+                .add(bytes);
+            if op.delta_base.is_some() {
+                usage.delta.add(bytes);
+            }
+            if bytes.stored == 0 {
+                usage.deduplicated += 1;
+            }
+            by_path.entry(op.path.clone()).or_default().add(bytes);
+        }
+        usage.by_path = by_path
+            .into_iter()
+            .map(|(path, totals)| PathUsage { path, totals })
+            .collect();
+        usage.by_path.sort_by(|a, b| {
+            b.totals
+                .stored
+                .cmp(&a.totals.stored)
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        usage
+    }
 
-        let jk2 = JanusKey::open(tmp.path()).unwrap();
-        assert_eq!(jk.root, jk2.root);
+    /// Make sure `bytes` more content fits under `max_store_bytes`. If it
+    /// does not and `gc_on_quota` is set, history is first pruned to
+    /// `max_history` operations and unreferenced blobs collected.
+    pub fn ensure_capacity(&mut self, bytes: u64) -> Result<()> {
+        let fits = |jk: &Self| -> Result<bool> {
+            Ok(jk
+                .content_store
+                .headroom()?
+                .is_none_or(|headroom| bytes <= headroom))
+        };
+        if fits(self)? {
+            return Ok(());
+        }
+        if self.config.gc_on_quota {
+            self.gc(self.config.max_history)?;
+            if fits(self)? {
+                return Ok(());
+            }
+        }
+        Err(JanusError::QuotaExceeded(format!(
+            "capturing {} bytes would take the content store past its {} byte limit",
+            bytes,
+            self.config.max_store_bytes.unwrap_or_default()
+        )))
+    }
+
+    /// Make sure the filesystem holding the tree has `bytes` free, so an
+    /// undo, rollback or restore writing that much cannot stop halfway on
+    /// a full disk
+    pub fn ensure_disk_space(&self, bytes: u64) -> Result<()> {
+        if bytes == 0 {
+            return Ok(());
+        }
+        let available = fs4::available_space(&self.root)?;
+        if bytes > available {
+            return Err(JanusError::InsufficientSpace(format!(
+                "restoring needs {} bytes but only {} are available on {}",
+                bytes,
+                available,
+                self.root.display()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Execute an operation, recording it in the active transaction if any
+    pub fn execute(&mut self, operation: FileOperation) -> Result<OperationMetadata> {
+        self.execute_keyed(operation, None)
+    }
+
+    /// Execute an operation at most once per idempotency `key` (see
+    /// [`OperationExecutor::execute_with_key`]). A retry returns the
+    /// recorded operation and adds nothing to the active transaction.
+    pub fn execute_with_key(
+        &mut self,
+        operation: FileOperation,
+        key: &str,
+    ) -> Result<OperationMetadata> {
+        self.execute_keyed(operation, Some(key))
+    }
+
+    fn execute_keyed(
+        &mut self,
+        operation: FileOperation,
+        key: Option<&str>,
+    ) -> Result<OperationMetadata> {
+        let retry = key.is_some_and(|key| {
+            self.metadata_store
+                .get(&metadata::operation_id_for_key(key))
+                .is_some()
+        });
+        if !retry {
+            self.ensure_capacity(operation.captured_bytes())?;
+        }
+        let transaction = self
+            .transaction_manager
+            .active()
+            .map(|tx| (tx.id.clone(), tx.refs.clone()));
+        let transaction_id = transaction.as_ref().map(|(id, _)| id.clone());
+        let mut executor = self.executor()?;
+        if let Some((tid, refs)) = transaction {
+            executor = executor.with_transaction(tid).with_refs(refs);
+        }
+        let meta = match key {
+            Some(key) => executor.execute_with_key(operation, key)?,
+            None => executor.execute(operation)?,
+        };
+        if transaction_id.is_some() && !retry {
+            self.transaction_manager.add_operation(meta.id.clone())?;
+        }
+        Ok(meta)
+    }
+
+    /// Execute several operations all-or-nothing, recording them with one
+    /// synced log append (see [`OperationExecutor::execute_batch`])
+    pub fn execute_batch(
+        &mut self,
+        operations: Vec<FileOperation>,
+    ) -> Result<Vec<OperationMetadata>> {
+        self.ensure_capacity(operations.iter().map(FileOperation::captured_bytes).sum())?;
+        let transaction = self
+            .transaction_manager
+            .active()
+            .map(|tx| (tx.id.clone(), tx.refs.clone()));
+        let transaction_id = transaction.as_ref().map(|(id, _)| id.clone());
+        let mut executor = self.executor()?;
+        if let Some((tid, refs)) = transaction {
+            executor = executor.with_transaction(tid).with_refs(refs);
+        }
+        let recorded = executor.execute_batch(operations)?;
+        if transaction_id.is_some() {
+            self.transaction_manager
+                .add_operations(recorded.iter().map(|op| op.id.clone()))?;
+        }
+        Ok(recorded)
+    }
+
+    /// Apply an operation without keeping its original content or
+    /// recording it, so it cannot be undone (`--no-history`)
+    pub fn execute_without_history(
+        &mut self,
+        operation: FileOperation,
+    ) -> Result<OperationMetadata> {
+        self.executor()?.without_history().execute(operation)
+    }
+
+    /// Delete a file; relative paths are taken from the root
+    pub fn delete(&mut self, path: impl AsRef<std::path::Path>) -> Result<OperationMetadata> {
+        let path = self.resolve(path);
+        self.execute(FileOperation::Delete { path })
+    }
+
+    /// Replace a file's content
+    pub fn modify(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+        new_content: impl Into<Vec<u8>>,
+    ) -> Result<OperationMetadata> {
+        let path = self.resolve(path);
+        self.execute(FileOperation::Modify {
+            path,
+            new_content: new_content.into(),
+        })
+    }
+
+    /// Move or rename a file
+    pub fn move_file(
+        &mut self,
+        source: impl AsRef<std::path::Path>,
+        destination: impl AsRef<std::path::Path>,
+    ) -> Result<OperationMetadata> {
+        let (source, destination) = (self.resolve(source), self.resolve(destination));
+        self.execute(FileOperation::Move {
+            source,
+            destination,
+        })
+    }
+
+    /// Copy a file, carrying over what `copy_preserve` says
+    pub fn copy(
+        &mut self,
+        source: impl AsRef<std::path::Path>,
+        destination: impl AsRef<std::path::Path>,
+    ) -> Result<OperationMetadata> {
+        let (source, destination) = (self.resolve(source), self.resolve(destination));
+        self.execute(FileOperation::Copy {
+            source,
+            destination,
+            preserve: self.config.copy_preserve.clone(),
+        })
+    }
+
+    /// Create a new file
+    pub fn create(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+        content: impl Into<Vec<u8>>,
+    ) -> Result<OperationMetadata> {
+        let path = self.resolve(path);
+        self.execute(FileOperation::Create {
+            path,
+            content: content.into(),
+            mode: None,
+        })
+    }
+
+    /// Change a file's permission bits
+    #[cfg(unix)]
+    pub fn chmod(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+        new_mode: u32,
+    ) -> Result<OperationMetadata> {
+        let path = self.resolve(path);
+        self.execute(FileOperation::Chmod { path, new_mode })
+    }
+
+    /// Begin a transaction. Operations run through the returned handle
+    /// (or on this `JanusKey` while it is active) join it until it is
+    /// committed or rolled back.
+    pub fn begin(&mut self) -> Result<TransactionHandle<'_>> {
+        self.begin_named(None)
+    }
+
+    /// Begin a transaction with a name shown in `jk tx list`
+    pub fn begin_named(&mut self, name: Option<String>) -> Result<TransactionHandle<'_>> {
+        let id = self.transaction_manager.begin(name)?.id.clone();
+        Ok(TransactionHandle { jk: self, id })
+    }
+
+    /// `path` if absolute, else `path` under the root
+    fn resolve(&self, path: impl AsRef<std::path::Path>) -> std::path::PathBuf {
+        self.root.join(path)
+    }
+
+    /// Attribute each line of the text file at `path` to the operation
+    /// that introduced it, by diffing the stored versions before each
+    /// modify, create and delete in its history.
+    ///
+    /// A line is unattributed if it predates the recorded history or came
+    /// from an edit made outside JanusKey: an operation is only credited
+    /// when the next recorded version is the content it produced.
+    pub fn blame(&self, path: impl AsRef<std::path::Path>) -> Result<Vec<BlameLine>> {
+        let path = self.resolve(path);
+        let current = std::fs::read(&path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => JanusError::FileNotFound(path.display().to_string()),
+            _ => e.into(),
+        })?;
+        if !delta::is_likely_text(&current) {
+            return Err(JanusError::OperationFailed(format!(
+                "{} is not a text file",
+                path.display()
+            )));
+        }
+
+        let changes: Vec<&OperationMetadata> = self
+            .metadata_store
+            .path_history(&path)
+            .into_iter()
+            .filter(|op| {
+                matches!(
+                    op.op_type,
+                    OperationType::Modify | OperationType::Create | OperationType::Delete
+                )
+            })
+            .collect();
+
+        // The content before each change, then the file as it is now
+        let mut versions = Vec::with_capacity(changes.len() + 1);
+        for op in &changes {
+            versions.push(match (&op.op_type, &op.content_hash) {
+                (OperationType::Create, _) | (_, None) => Vec::new(),
+                (_, Some(_)) => self.captured_content(op)?,
+            });
+        }
+        versions.push(current);
+        let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+
+        let mut origins: Vec<Option<String>> = vec![None; text(&versions[0]).lines().count()];
+        for (op, pair) in changes.iter().zip(versions.windows(2)) {
+            let produced = match op.op_type {
+                OperationType::Delete => Some(ContentHash::from_bytes(b"")),
+                _ => op.new_content_hash.clone(),
+            };
+            let author = produced
+                .is_some_and(|hash| hash.verify(&pair[1]))
+                .then(|| op.id.clone());
+            origins = delta::line_alignment(&text(&pair[0]), &text(&pair[1]))
+                .into_iter()
+                .map(|carried| match carried {
+                    Some(line) => origins[line].clone(),
+                    None => author.clone(),
+                })
+                .collect();
+        }
+
+        Ok(text(&versions[changes.len()])
+            .lines()
+            .zip(origins)
+            .map(|(line, operation)| BlameLine {
+                text: line.to_string(),
+                operation,
+            })
+            .collect())
+    }
+
+    /// Undo the most recent operation that has not been undone
+    pub fn undo_last(&mut self) -> Result<OperationMetadata> {
+        let op_id = self
+            .metadata_store
+            .last_undoable()
+            .map(|op| op.id.clone())
+            .ok_or(JanusError::NothingToUndo)?;
+        self.executor()?.undo(&op_id)
+    }
+
+    /// Undo the operation `id`, provided no later operation depends on it.
+    ///
+    /// Fails with [`JanusError::HasDependents`] listing the operations
+    /// that do; undo them first, or use [`JanusKey::undo_cascade`].
+    pub fn undo(&mut self, id: &str) -> Result<OperationMetadata> {
+        let dependents = self.metadata_store.dependency_graph().descendants(id);
+        if !dependents.is_empty() {
+            return Err(JanusError::HasDependents {
+                id: id.to_string(),
+                dependents,
+            });
+        }
+        self.executor()?.undo(id)
+    }
+
+    /// Undo the operation `id` together with every later operation that
+    /// depends on it, newest first. Returns the undos performed, `id`'s last.
+    pub fn undo_cascade(&mut self, id: &str) -> Result<Vec<OperationMetadata>> {
+        let mut ids = self.metadata_store.dependency_graph().descendants(id);
+        ids.insert(0, id.to_string());
+        let mut undone = Vec::new();
+        for op_id in ids.iter().rev() {
+            undone.push(self.executor()?.undo(op_id)?);
+        }
+        Ok(undone)
+    }
+
+    /// Undo the last `count` operations, newest first, carrying on past
+    /// one that fails. Each is paired with its undo or why it failed.
+    pub fn undo_recent(
+        &mut self,
+        count: usize,
+    ) -> Vec<(OperationMetadata, Result<OperationMetadata>)> {
+        let ops: Vec<OperationMetadata> = self
+            .metadata_store
+            .last_n(count)
+            .into_iter()
+            .cloned()
+            .collect();
+        ops.into_iter()
+            .map(|op| {
+                let result = self
+                    .executor()
+                    .and_then(|mut executor| executor.undo(&op.id));
+                (op, result)
+            })
+            .collect()
+    }
+
+    /// The last `count` operations not yet undone that touched a path
+    /// matching `pattern` (a glob relative to the root), newest first,
+    /// whatever happened to other paths in between. Moves and copies
+    /// match on either path. Check [`JanusKey::undo_conflicts`] before
+    /// undoing them.
+    pub fn recent_for_path(&self, pattern: &str, count: usize) -> Result<Vec<OperationMetadata>> {
+        Ok(self
+            .live_matching(Some(pattern))?
+            .take(count)
+            .cloned()
+            .collect())
+    }
+
+    /// Every operation not yet undone recorded at or after `since`,
+    /// newest first, as for [`JanusKey::recent_for_path`]; with a
+    /// `pattern`, only those touching a matching path
+    pub fn recent_since(
+        &self,
+        pattern: Option<&str>,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<OperationMetadata>> {
+        Ok(self
+            .live_matching(pattern)?
+            .filter(|op| op.timestamp >= since)
+            .cloned()
+            .collect())
+    }
+
+    /// Operations not yet undone touching a path matching `pattern` (or
+    /// any path), newest first
+    fn live_matching(
+        &self,
+        pattern: Option<&str>,
+    ) -> Result<impl Iterator<Item = &OperationMetadata> + '_> {
+        let pattern = pattern.map(glob::Pattern::new).transpose()?;
+        let matches = move |path: &std::path::Path| {
+            let relative = path.strip_prefix(&self.root).unwrap_or(path);
+            pattern
+                .as_ref()
+                .is_none_or(|pattern| pattern.matches_path_with(relative, select::MATCH_OPTIONS))
+        };
+        Ok(self
+            .metadata_store
+            .operations()
+            .iter()
+            .rev()
+            .filter(|op| !op.undone)
+            .filter(move |op| {
+                matches(&op.path) || op.path_secondary.as_deref().is_some_and(&matches)
+            }))
+    }
+
+    /// Undo every operation of the active transaction, newest first,
+    /// and mark it rolled back (Theorem 3.4: Sequential Reversibility).
+    ///
+    /// An operation that fails to undo is recorded in the transaction's
+    /// `rollback_status`. With `rollback_continue_on_error` the rollback
+    /// carries on past recoverable failures; either way, if anything is
+    /// left undone the transaction is marked partially rolled back and
+    /// [`JanusError::RollbackIncomplete`] is returned. Finish it with
+    /// [`JanusKey::resume_rollback`].
+    pub fn rollback(&mut self) -> Result<Transaction> {
+        let active_tx = self
+            .transaction_manager
+            .active()
+            .ok_or(JanusError::NoActiveTransaction)?
+            .clone();
+        self.rollback_transaction(&active_tx)
+    }
+
+    /// Retry the operations a partial rollback left undone, for the
+    /// transaction `id` (or unique prefix) or else the most recent one
+    pub fn resume_rollback(&mut self, id: Option<&str>) -> Result<Transaction> {
+        let tx = self.incomplete_rollback(id)?.clone();
+        self.rollback_transaction(&tx)
+    }
+
+    /// The partially rolled back transaction `id`, or the most recent
+    pub fn incomplete_rollback(&self, id: Option<&str>) -> Result<&Transaction> {
+        let tx = match id {
+            Some(id) => self.transaction_manager.find(id)?,
+            None => self
+                .transaction_manager
+                .all()
+                .iter()
+                .rev()
+                .find(|t| t.state == TransactionState::PartiallyRolledBack)
+                .ok_or_else(|| {
+                    JanusError::InvalidTransactionId(
+                        "no partially rolled back transaction".to_string(),
+                    )
+                })?,
+        };
+        if tx.state != TransactionState::PartiallyRolledBack {
+            return Err(JanusError::InvalidTransactionId(format!(
+                "{} is {}, not partially rolled back",
+                &tx.id[..8],
+                tx.state
+            )));
+        }
+        Ok(tx)
+    }
+
+    fn rollback_transaction(&mut self, tx: &Transaction) -> Result<Transaction> {
+        let mut failed = 0;
+        let order = tx.rollback_order(&self.metadata_store.dependency_graph());
+        for op_id in &order {
+            let already_undone = self.metadata_store.get(op_id).is_some_and(|op| op.undone);
+            let mut stop = false;
+            let status = if already_undone {
+                RollbackStatus::Undone
+            } else {
+                match self
+                    .executor()
+                    .and_then(|mut executor| executor.undo(op_id))
+                {
+                    Ok(_) => RollbackStatus::Undone,
+                    Err(e) => {
+                        failed += 1;
+                        stop = !self.config.rollback_continue_on_error || !e.is_recoverable();
+                        RollbackStatus::Failed(e.to_string())
+                    }
+                }
+            };
+            self.transaction_manager
+                .record_rollback_step(&tx.id, op_id, status)?;
+            if stop {
+                break;
+            }
+        }
+
+        if failed == 0 {
+            return self.transaction_manager.mark_rolled_back_by_id(&tx.id);
+        }
+        let tx = self
+            .transaction_manager
+            .mark_partially_rolled_back(&tx.id)?;
+        Err(JanusError::RollbackIncomplete(format!(
+            "{} of {} operations in transaction {} not undone",
+            tx.rollback_remaining().count(),
+            tx.operation_ids.len(),
+            &tx.id[..8]
+        )))
+    }
+
+    /// Simulate `rollback`: the inverse of each operation in the active
+    /// transaction, in rollback order. Nothing is changed.
+    pub fn plan_rollback(&mut self) -> Result<Vec<(OperationMetadata, FileOperation)>> {
+        let active_tx = self
+            .transaction_manager
+            .active()
+            .ok_or(JanusError::NoActiveTransaction)?
+            .clone();
+        self.plan_rollback_of(&active_tx)
+    }
+
+    /// Simulate rolling back `tx`: the inverse of each operation not yet
+    /// undone, in [`Transaction::rollback_order`]. Nothing is changed.
+    pub fn plan_rollback_of(
+        &mut self,
+        tx: &Transaction,
+    ) -> Result<Vec<(OperationMetadata, FileOperation)>> {
+        let originals = tx
+            .rollback_order(&self.metadata_store.dependency_graph())
+            .iter()
+            .filter(|op_id| !self.metadata_store.get(op_id).is_some_and(|op| op.undone))
+            .map(|op_id| {
+                self.metadata_store
+                    .get(op_id)
+                    .cloned()
+                    .ok_or_else(|| JanusError::InvalidOperationId(op_id.clone()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let executor = self.executor()?;
+        let mut sim = Simulation::new();
+        let mut steps = Vec::new();
+        for original in originals {
+            let inverse = executor.simulate_undo(&original.id, &mut sim)?;
+            steps.push((original, inverse));
+        }
+        Ok(steps)
+    }
+
+    /// Operations that block undoing exactly `ids`: for each selected
+    /// operation, every later operation depending on it (see
+    /// [`DependencyGraph`]) that is not selected too. Undoing the selection
+    /// while any of these stand would overwrite their effects. Pairs are
+    /// (selected, blocker).
+    pub fn undo_conflicts(&self, ids: &[String]) -> Vec<(OperationMetadata, OperationMetadata)> {
+        let graph = self.metadata_store.dependency_graph();
+        let mut conflicts = Vec::new();
+        for selected in self.metadata_store.operations() {
+            if !ids.contains(&selected.id) {
+                continue;
+            }
+            for later in graph.descendants(&selected.id) {
+                if ids.contains(&later) {
+                    continue;
+                }
+                if let Some(later) = self.metadata_store.get(&later) {
+                    conflicts.push((selected.clone(), later.clone()));
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// The recorded versions whose content matches `query`, newest
+    /// first; see [`search::search`]. The content index is used if one
+    /// has been built.
+    pub fn search(&self, query: &Query) -> Result<Vec<SearchHit>> {
+        let index = self.search_index();
+        search::search(
+            &self.metadata_store,
+            &self.content_store,
+            index.exists().then_some(&index),
+            query,
+        )
+    }
+
+    /// The content index for [`JanusKey::search`]. With `search_index`
+    /// configured the content store keeps it up to date.
+    pub fn search_index(&self) -> SearchIndex {
+        SearchIndex::open(SearchIndex::path_for(&self.root))
+    }
+
+    /// The blobs [`JanusKey::tier`] would offload, with their stored sizes
+    pub fn tier_plan(&self) -> Result<Vec<(ContentHash, u64)>> {
+        let policy = self.config.tiering.as_ref().ok_or_else(|| {
+            JanusError::OperationFailed("no tiering policy is configured".to_string())
+        })?;
+        tier::plan(&self.content_store, policy)
+    }
+
+    /// Offload the blobs the tiering policy selects to the cold tier,
+    /// leaving pointers in their place. Returns what was moved.
+    pub fn tier(&self) -> Result<Vec<(ContentHash, u64)>> {
+        let plan = self.tier_plan()?;
+        for (hash, _) in &plan {
+            self.content_store.offload(hash)?;
+        }
+        Ok(plan)
+    }
+
+    /// Bring every offloaded blob back from the cold tier. Returns what
+    /// was fetched.
+    pub fn recall(&self) -> Result<Vec<(ContentHash, u64)>> {
+        let offloaded = self.content_store.offloaded()?;
+        for (hash, _) in &offloaded {
+            self.content_store.recall(hash)?;
+        }
+        Ok(offloaded)
+    }
+
+    /// What `gc(keep)` would remove. Nothing is changed.
+    pub fn gc_plan(&self, keep: usize) -> Result<GcPlan> {
+        self.gc_plan_by(&PruneRule::keep(keep))
+    }
+
+    /// What `gc_by(rule)` would remove. Nothing is changed.
+    pub fn gc_plan_by(&self, rule: &PruneRule) -> Result<GcPlan> {
+        let held_ids = self
+            .legal_holds()?
+            .held_operations(&self.root, &self.metadata_store);
+        let operations: Vec<OperationMetadata> = self
+            .metadata_store
+            .prune_candidates_by(rule, &held_ids)
+            .into_iter()
+            .cloned()
+            .collect();
+        let held = self
+            .metadata_store
+            .prune_candidates_by(rule, &std::collections::HashSet::new())
+            .len()
+            - operations.len();
+        let pruned: std::collections::HashSet<&str> =
+            operations.iter().map(|op| op.id.as_str()).collect();
+
+        // Blobs still needed by the remaining operations and the
+        // snapshots, including the bases their deltas are stored against.
+        // Other workspaces sharing the store need theirs too.
+        let mut referenced = std::collections::HashSet::new();
+        let mut pending: Vec<ContentHash> = self
+            .metadata_store
+            .operations()
+            .iter()
+            .chain(self.metadata_store.other_workspaces())
+            .filter(|op| !pruned.contains(op.id.as_str()))
+            .flat_map(|op| {
+                [&op.content_hash, &op.new_content_hash, &op.delta_base]
+                    .into_iter()
+                    .flatten()
+                    .chain(op.stream_hashes())
+            })
+            .cloned()
+            .collect();
+        pending.extend(self.snapshots()?.referenced(&self.content_store));
+        for (id, root) in &self.workspaces()?.roots {
+            if root == &self.root {
+                continue;
+            }
+            match Snapshots::open(root.join(".januskey").join("snapshots.json")) {
+                Ok(snapshots) => pending.extend(snapshots.referenced(&self.content_store)),
+                Err(e) => tracing::warn!(workspace = %id, "cannot read snapshots: {}", e),
+            }
+        }
+        while let Some(hash) = pending.pop() {
+            if let Some(base) = self.content_store.delta_base(&hash)? {
+                pending.push(base);
+            }
+            referenced.insert(hash);
+        }
+
+        let blobs = self
+            .content_store
+            .list()?
+            .into_iter()
+            .filter(|(hash, _)| !referenced.contains(hash))
+            .collect();
+
+        Ok(GcPlan {
+            operations,
+            blobs,
+            held,
+        })
+    }
+
+    /// Prune all but the last `keep` operations (and what they depend on,
+    /// see [`MetadataStore::prune`]), then delete blobs no remaining
+    /// operation needs. Operations under legal hold are kept.
+    pub fn gc(&mut self, keep: usize) -> Result<GcPlan> {
+        self.gc_by(&PruneRule::keep(keep))
+    }
+
+    /// Prune the operations `rule` does not keep (other than what kept
+    /// operations depend on), then delete blobs no remaining operation
+    /// needs. Operations under legal hold are kept.
+    pub fn gc_by(&mut self, rule: &PruneRule) -> Result<GcPlan> {
+        let plan = self.gc_plan_by(rule)?;
+        let held_ids = self
+            .legal_holds()?
+            .held_operations(&self.root, &self.metadata_store);
+        self.metadata_store.prune_by(rule, &held_ids)?;
+        let blobs: Vec<ContentHash> = plan.blobs.iter().map(|(hash, _)| hash.clone()).collect();
+        self.content_store.delete_many(&blobs)?;
+        if self.config.search_index {
+            self.search_index().update(&self.content_store)?;
+        }
+        Ok(plan)
+    }
+
+    /// Rewrite the logs zstd-compressed: every metadata segment but the
+    /// one being appended to, the transaction log and the obliteration
+    /// log. Logs already compressed are left alone.
+    pub fn compress_logs(&mut self) -> Result<framing::Compressed> {
+        let mut compressed = self.metadata_store.compress_segments()?;
+        compressed.merge(self.transaction_manager.compress()?);
+        compressed.merge(self.obliteration_manager()?.compress()?);
+        Ok(compressed)
+    }
+
+    /// Rebuild a damaged history so the directory opens again, keeping
+    /// everything that can still be read.
+    ///
+    /// Unreadable operation log lines are moved to `lost+found/` and the
+    /// log is rewritten without them. An unreadable transaction log is
+    /// moved there too and rebuilt from the transaction IDs recorded on
+    /// the operations. The report lists what could not be recovered:
+    /// operations transactions refer to that are gone, operations whose
+    /// stored content is missing, and blobs no operation refers to. With
+    /// `dry_run` nothing is changed.
+    pub fn heal(root: &std::path::Path, dry_run: bool) -> Result<HealReport> {
+        let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+        if !root.join(".januskey").exists() {
+            return Err(JanusError::NotInitialized(root.display().to_string()));
+        }
+        let config = Config::load(&root)?;
+        let (backend, store_dir) = Self::connect_storage(&root, &config)?;
+        let lost_found = store_dir.join("lost+found");
+        let stamp = chrono::Utc::now().format("%Y%m%dT%H%M%SZ");
+
+        let (mut metadata_store, damaged) =
+            MetadataStore::recover_with_backend(backend.clone(), store_dir.join("metadata"))?;
+        metadata_store.set_root(&root);
+        let (content_backend, content_dir) =
+            Self::connect_content(&root, &config, &backend, &store_dir)?;
+        let content_store =
+            ContentStore::new_with_backend(content_backend, content_dir, config.compression)?
+                .with_delta(config.delta_storage)
+                .with_algorithm(config.hash_algorithm)
+                .with_mmap(config.memory_map);
+        let transactions_path = store_dir.join("transactions");
+        let transactions = match TransactionManager::new_with_backend(
+            backend.clone(),
+            transactions_path.clone(),
+        ) {
+            Ok(manager) => Ok(manager),
+            Err(JanusError::MetadataCorrupted(e)) => Err(e),
+            Err(e) => return Err(e),
+        };
+
+        let mut report = HealReport {
+            operations: metadata_store.count(),
+            damaged,
+            ..HealReport::default()
+        };
+
+        match &transactions {
+            // gc prunes the oldest operations; only those newer than the
+            // oldest survivor can have been lost
+            Ok(manager) => {
+                let oldest = metadata_store.operations().first().map(|op| op.timestamp);
+                for tx in manager.all() {
+                    if oldest.is_some_and(|oldest| tx.started_at < oldest) {
+                        continue;
+                    }
+                    for op_id in &tx.operation_ids {
+                        if metadata_store.get(op_id).is_none() {
+                            report
+                                .missing_operations
+                                .push((tx.id.clone(), op_id.clone()));
+                        }
+                    }
+                }
+            }
+            Err(e) => report.transactions_error = Some(e.clone()),
+        }
+
+        // Undo needs the original content and any delta bases under it;
+        // the new content of a modify is kept only when it was stored.
+        // Snapshot content is not an operation's, but is not orphaned.
+        let snapshotted = Snapshots::open(root.join(".januskey").join("snapshots.json"))
+            .map(|snapshots| snapshots.referenced(&content_store))
+            .unwrap_or_default();
+        let mut referenced = std::collections::HashSet::new();
+        for hash in &snapshotted {
+            let mut next = Some(hash.clone());
+            while let Some(hash) = next.filter(|hash| content_store.exists(hash)) {
+                next = content_store.delta_base(&hash)?;
+                referenced.insert(hash);
+            }
+        }
+        for op in metadata_store.operations() {
+            let needed = [&op.content_hash, &op.delta_base]
+                .into_iter()
+                .flatten()
+                .chain(op.stream_hashes());
+            let kept = op.new_content_hash.iter();
+            for (hash, required) in needed.map(|h| (h, true)).chain(kept.map(|h| (h, false))) {
+                let mut next = Some(hash.clone());
+                while let Some(hash) = next {
+                    if !content_store.exists(&hash) {
+                        if required {
+                            report.missing_content.push((op.id.clone(), hash));
+                        }
+                        break;
+                    }
+                    next = content_store.delta_base(&hash)?;
+                    referenced.insert(hash);
+                }
+            }
+        }
+        report.orphaned_blobs = content_store
+            .list()?
+            .into_iter()
+            .filter(|(hash, _)| !referenced.contains(hash))
+            .collect();
+
+        if transactions.is_err() {
+            let mut grouped: Vec<(&str, Vec<&OperationMetadata>)> = Vec::new();
+            for op in metadata_store.operations() {
+                let Some(tx_id) = op.transaction_id.as_deref() else {
+                    continue;
+                };
+                match grouped.iter_mut().find(|(id, _)| *id == tx_id) {
+                    Some((_, ops)) => ops.push(op),
+                    None => grouped.push((tx_id, vec![op])),
+                }
+            }
+            report.transactions_rebuilt = grouped.len();
+
+            if !dry_run {
+                backend.create_dir_all(&lost_found)?;
+                backend.rename(
+                    &transactions_path,
+                    &lost_found.join(format!("transactions-{}.json", stamp)),
+                )?;
+                TransactionManager::new_with_backend(backend.clone(), transactions_path)?.restore(
+                    grouped
+                        .iter()
+                        .map(|(id, ops)| Transaction::from_operations(id, ops))
+                        .collect(),
+                )?;
+            }
+        }
+
+        if !dry_run && !report.damaged.is_empty() {
+            let lines: String = report
+                .damaged
+                .iter()
+                .map(|record| format!("{}\n", record.text))
+                .collect();
+            backend.create_dir_all(&lost_found)?;
+            backend.write(
+                &lost_found.join(format!("metadata-{}.jsonl", stamp)),
+                lines.as_bytes(),
+            )?;
+            metadata_store.compact()?;
+        }
+
+        Ok(report)
+    }
+}
+
+/// A transaction begun with [`JanusKey::begin`]. Dereferences to the
+/// `JanusKey`, so `tx.delete(path)` records into the transaction.
+///
+/// Dropping the handle leaves the transaction active, as `jk begin` does;
+/// it can still be committed or rolled back later.
+pub struct TransactionHandle<'a> {
+    jk: &'a mut JanusKey,
+    id: String,
+}
+
+impl TransactionHandle<'_> {
+    /// ID of the transaction
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Commit the transaction
+    pub fn commit(self) -> Result<Transaction> {
+        self.jk.transaction_manager.commit()
+    }
+
+    /// Undo the transaction's operations (see [`JanusKey::rollback`])
+    pub fn rollback(self) -> Result<Transaction> {
+        self.jk.rollback()
+    }
+}
+
+impl std::ops::Deref for TransactionHandle<'_> {
+    type Target = JanusKey;
+
+    fn deref(&self) -> &JanusKey {
+        self.jk
+    }
+}
+
+impl std::ops::DerefMut for TransactionHandle<'_> {
+    fn deref_mut(&mut self) -> &mut JanusKey {
+        self.jk
+    }
+}
+
+/// A line of [`JanusKey::blame`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlameLine {
+    /// The line, without its terminator
+    pub text: String,
+    /// ID of the operation that introduced the line, if known
+    pub operation: Option<String>,
+}
+
+/// What [`JanusKey::status`] reports
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct StoreStatus {
+    /// Working directory
+    pub root: std::path::PathBuf,
+    /// Storage URI, if history is not kept in `.januskey/`
+    pub storage: Option<String>,
+    /// Operations in the log
+    pub operations: usize,
+    /// Blobs in the content store
+    pub blobs: usize,
+    /// Stored size of the blobs in bytes, not counting those offloaded
+    pub bytes: u64,
+    /// Blobs offloaded to the cold tier
+    #[serde(default)]
+    pub offloaded: usize,
+    pub active_transaction: Option<Transaction>,
+}
+
+/// Bytes read and stored by some number of operations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct ByteTotals {
+    pub operations: usize,
+    pub read: u64,
+    pub stored: u64,
+}
+
+impl ByteTotals {
+    fn add(&mut self, bytes: &ByteAccounting) {
+        self.operations += 1;
+        self.read += bytes.read;
+        self.stored += bytes.stored;
+    }
+
+    /// `stored` as a fraction of `read`; `None` when nothing was read
+    pub fn ratio(&self) -> Option<f64> {
+        ByteAccounting {
+            read: self.read,
+            stored: self.stored,
+        }
+        .ratio()
+    }
+}
+
+/// [`ByteTotals`] of the operations on one path
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct PathUsage {
+    pub path: std::path::PathBuf,
+    #[serde(flatten)]
+    pub totals: ByteTotals,
+}
+
+/// What [`JanusKey::byte_usage`] reports
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct ByteUsage {
+    /// Every operation that captured content
+    pub total: ByteTotals,
+    /// Keyed by operation type (`DELETE`, `MODIFY`)
+    pub by_type: std::collections::BTreeMap<String, ByteTotals>,
+    /// Operations whose content was stored as a delta
+    pub delta: ByteTotals,
+    /// Operations whose content was already stored
+    pub deduplicated: usize,
+    /// Most stored bytes first
+    pub by_path: Vec<PathUsage>,
+    /// Operations that captured content before bytes were accounted
+    pub unaccounted: usize,
+}
+
+/// What [`JanusKey::heal`] found
+#[derive(Debug, Clone, Default)]
+pub struct HealReport {
+    /// Operations in the recovered log
+    pub operations: usize,
+    /// Operation log lines that could not be read
+    pub damaged: Vec<metadata::DamagedRecord>,
+    /// Why the transaction log could not be read, if it could not
+    pub transactions_error: Option<String>,
+    /// Transactions rebuilt from the operations' transaction IDs
+    pub transactions_rebuilt: usize,
+    /// `(transaction ID, operation ID)` of operations a transaction lists
+    /// but the log no longer has
+    pub missing_operations: Vec<(String, String)>,
+    /// `(operation ID, blob)` of content an operation needs but the
+    /// store does not have; such operations cannot be undone
+    pub missing_content: Vec<(String, ContentHash)>,
+    /// Blobs no recovered operation refers to, with their stored sizes.
+    /// `jk gc` deletes them.
+    pub orphaned_blobs: Vec<(ContentHash, u64)>,
+}
+
+impl HealReport {
+    /// Whether everything was readable and consistent
+    pub fn is_clean(&self) -> bool {
+        self.damaged.is_empty()
+            && self.transactions_error.is_none()
+            && self.missing_operations.is_empty()
+            && self.missing_content.is_empty()
+            && self.orphaned_blobs.is_empty()
+    }
+}
+
+/// Operations and blobs removed by garbage collection
+#[derive(Debug, Clone, Default)]
+pub struct GcPlan {
+    /// Pruned operations, oldest first
+    pub operations: Vec<OperationMetadata>,
+    /// Unreferenced blobs with their stored sizes in bytes
+    pub blobs: Vec<(ContentHash, u64)>,
+    /// Operations kept only because of legal holds: held operations and
+    /// those they depend on
+    pub held: usize,
+}
+
+impl GcPlan {
+    /// Bytes freed by deleting the blobs
+    pub fn blob_bytes(&self) -> u64 {
+        self.blobs.iter().map(|(_, size)| size).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_init_and_open() {
+        let tmp = TempDir::new().unwrap();
+        let jk = JanusKey::init(tmp.path()).unwrap();
+        assert!(JanusKey::is_initialized(tmp.path()));
+
+        let jk2 = JanusKey::open(tmp.path()).unwrap();
+        assert_eq!(jk.root, jk2.root);
+    }
+
+    #[test]
+    fn test_config_settings() {
+        let tmp = TempDir::new().unwrap();
+        let mut config = Config::load(tmp.path()).unwrap();
+        assert_eq!(config.get("max_history").unwrap(), 10000);
+        assert!(!config.memory_map);
+
+        config.set("max_history", "500").unwrap();
+        config.set("hash_algorithm", "blake3").unwrap();
+        config.set("retry.max_attempts", "7").unwrap();
+        config.set("storage_path", "/srv/history").unwrap();
+        assert_eq!(config.max_history, 500);
+        assert_eq!(config.hash_algorithm, HashAlgorithm::Blake3);
+        assert_eq!(config.get("retry.max_attempts").unwrap(), 7);
+        assert_eq!(config.storage_path.as_deref(), Some("/srv/history"));
+        config.set("storage_path", "null").unwrap();
+        assert_eq!(config.storage_path, None);
+
+        assert_eq!(
+            config.set("compression", "yes").unwrap_err().code(),
+            "invalid_config"
+        );
+        assert!(config.set("max_history", "-1").is_err());
+        assert!(config.set("no_such_setting", "1").is_err());
+        assert!(config.set("version", "9").is_err());
+        assert!(config.get("retry.no_such_setting").is_err());
+
+        // A file that does not parse is an error, not the defaults
+        config.save(tmp.path()).unwrap();
+        let mut document: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(Config::path(tmp.path())).unwrap())
+                .unwrap();
+        document["max_histroy"] = serde_json::json!(5);
+        document["retry"]["attempts"] = serde_json::json!(5);
+        std::fs::write(Config::path(tmp.path()), document.to_string()).unwrap();
+        let error = Config::load(tmp.path()).unwrap_err();
+        assert_eq!(error.code(), "invalid_config");
+        assert!(error
+            .to_string()
+            .ends_with("not a setting: max_histroy (did you mean max_history?), retry.attempts"));
+        std::fs::write(Config::path(tmp.path()), r#"{"max_history": "lots"}"#).unwrap();
+        assert_eq!(
+            Config::load(tmp.path()).unwrap_err().code(),
+            "invalid_config"
+        );
+        assert!(JanusKey::open(tmp.path()).is_err());
+    }
+
+    #[test]
+    fn test_shared_storage_path() {
+        let tmp = TempDir::new().unwrap();
+        let shared = tmp.path().join("shared");
+        let config = Config {
+            storage_path: Some(format!("file://{}", shared.display())),
+            ..Config::default()
+        };
+
+        for name in ["alice", "bob"] {
+            let dir = tmp.path().join(name);
+            std::fs::create_dir_all(&dir).unwrap();
+            let mut jk = JanusKey::init_with_config(&dir, config.clone()).unwrap();
+            jk.metadata_store
+                .append(OperationMetadata::new(OperationType::Create, dir.join("f")))
+                .unwrap();
+        }
+
+        assert!(shared.join("metadata").exists());
+        let jk = JanusKey::open(&tmp.path().join("alice")).unwrap();
+        assert_eq!(jk.config.workspace.as_deref(), Some("alice"));
+        assert_eq!(jk.metadata_store.count(), 1);
+        assert_eq!(jk.metadata_store.all_operations().len(), 2);
+        assert_eq!(jk.workspaces().unwrap().roots.len(), 2);
+    }
+
+    #[test]
+    fn test_workspaces_keep_their_own_history() {
+        let tmp = TempDir::new().unwrap();
+        let config = Config {
+            storage_path: Some(tmp.path().join("shared").display().to_string()),
+            ..Config::default()
+        };
+        let mut open = Vec::new();
+        for name in ["web", "api"] {
+            let dir = tmp.path().join(name);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("notes.txt"), name).unwrap();
+            let mut jk = JanusKey::init_with_config(&dir, config.clone()).unwrap();
+            jk.delete("notes.txt").unwrap();
+            open.push(jk);
+        }
+        let (mut web, mut api) = (open.remove(0), open.remove(0));
+        web = JanusKey::open(&web.root).unwrap();
+
+        // The same relative path in each workspace stays apart
+        web.undo_last().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(web.root.join("notes.txt")).unwrap(),
+            "web"
+        );
+        assert!(!api.root.join("notes.txt").exists());
+        let history = web.workspace_history("api").unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].path, api.root.join("notes.txt"));
+        assert!(matches!(
+            web.workspace_history("nope").map_err(|e| e.inner().code()),
+            Err("workspace_not_found")
+        ));
+
+        // gc in one workspace keeps the content the other needs
+        let plan = web.gc(0).unwrap();
+        assert_eq!(plan.operations.len(), 2);
+        api.undo_last().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(api.root.join("notes.txt")).unwrap(),
+            "api"
+        );
+    }
+
+    #[test]
+    fn test_separate_content_storage() {
+        let tmp = TempDir::new().unwrap();
+        let blobs = tmp.path().join("blobs");
+        let root = tmp.path().join("work");
+        std::fs::create_dir(&root).unwrap();
+        let config = Config {
+            content_storage: Some(ContentStorage {
+                storage: blobs.display().to_string(),
+                cache_bytes: 1024,
+            }),
+            ..Config::default()
+        };
+        let mut jk = JanusKey::init_with_config(&root, config).unwrap();
+        std::fs::write(root.join("a.txt"), "one").unwrap();
+        let op = jk.delete("a.txt").unwrap();
+
+        // Content went to its own store; the log stayed local
+        assert!(blobs.join("content").exists());
+        assert!(!root.join(".januskey/content").exists());
+        assert!(root.join(".januskey/metadata").exists());
+        assert!(jk.content_store.exists(op.content_hash.as_ref().unwrap()));
+
+        let mut jk = JanusKey::open(&root).unwrap();
+        jk.undo_last().unwrap();
+        assert_eq!(std::fs::read_to_string(root.join("a.txt")).unwrap(), "one");
+    }
+
+    #[test]
+    fn test_switch_hash_algorithm() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        std::fs::write(root.join("a.txt"), "one").unwrap();
+        let mut jk = JanusKey::init(root).unwrap();
+        let before = jk.modify("a.txt", b"two").unwrap();
+        assert_eq!(before.content_hash.as_ref().unwrap().algorithm(), "sha256");
+
+        jk.config.hash_algorithm = HashAlgorithm::Blake3;
+        jk.config.save(root).unwrap();
+        let mut jk = JanusKey::open(root).unwrap();
+        let after = jk.modify("a.txt", b"three").unwrap();
+        assert_eq!(after.content_hash.as_ref().unwrap().algorithm(), "blake3");
+        assert_eq!(
+            after.new_content_hash.as_ref().unwrap().algorithm(),
+            "blake3"
+        );
+
+        // History from before the switch still undoes
+        jk.undo(&after.id).unwrap();
+        jk.undo(&before.id).unwrap();
+        assert_eq!(std::fs::read_to_string(root.join("a.txt")).unwrap(), "one");
+        assert!(JanusKey::heal(root, true)
+            .unwrap()
+            .missing_content
+            .is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_undo_delete_remaps_owner() {
+        use std::os::unix::fs::MetadataExt;
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        std::fs::write(root.join("a.txt"), "one").unwrap();
+        // Only root may give files away; elsewhere ownership is left alone
+        if std::os::unix::fs::lchown(root.join("a.txt"), Some(4242), Some(4343)).is_err() {
+            return;
+        }
+        let mut jk = JanusKey::init(root).unwrap();
+        let op = jk.delete("a.txt").unwrap();
+        let captured = op.original_metadata.as_ref().unwrap();
+        assert_eq!(
+            (captured.owner.as_str(), captured.group.as_str()),
+            ("4242", "4343")
+        );
+
+        jk.config.ownership = OwnershipConfig {
+            restore: OwnershipMode::Map,
+            users: [("4242".to_string(), "5151".to_string())].into(),
+            groups: Default::default(),
+        };
+        jk.undo(&op.id).unwrap();
+        let restored = std::fs::metadata(root.join("a.txt")).unwrap();
+        assert_eq!((restored.uid(), restored.gid()), (5151, 4343));
+    }
+
+    #[test]
+    fn test_moved_directory_keeps_history() {
+        let tmp = TempDir::new().unwrap();
+        let old_root = tmp.path().join("old");
+        std::fs::create_dir(&old_root).unwrap();
+        std::fs::write(old_root.join("a.txt"), "one").unwrap();
+        let mut jk = JanusKey::init(&old_root).unwrap();
+        jk.delete("a.txt").unwrap();
+        drop(jk);
+
+        let new_root = tmp.path().join("new");
+        std::fs::rename(&old_root, &new_root).unwrap();
+        let mut jk = JanusKey::open(&new_root).unwrap();
+        assert_eq!(
+            jk.metadata_store.operations()[0].path,
+            jk.root.join("a.txt")
+        );
+        jk.undo_last().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(new_root.join("a.txt")).unwrap(),
+            "one"
+        );
+    }
+
+    #[test]
+    fn test_legacy_storage_path_stays_local() {
+        let config = Config {
+            storage_path: Some(legacy_default_storage_path().display().to_string()),
+            ..Config::default()
+        };
+        assert_eq!(config.storage_uri().unwrap(), None);
+    }
+
+    #[test]
+    fn test_config_migration() {
+        let tmp = TempDir::new().unwrap();
+        std::fs::create_dir(tmp.path().join(".januskey")).unwrap();
+        let mut document = serde_json::to_value(Config::default()).unwrap();
+        document.as_object_mut().unwrap().remove("version");
+        document["storage_path"] = legacy_default_storage_path().display().to_string().into();
+        std::fs::write(Config::path(tmp.path()), document.to_string()).unwrap();
+
+        let config = Config::load(tmp.path()).unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.storage_path, None);
+        let rewritten: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(Config::path(tmp.path())).unwrap())
+                .unwrap();
+        assert_eq!(rewritten["version"], CONFIG_VERSION);
+        assert!(tmp.path().join(".januskey/config.json.v0").exists());
+
+        document["version"] = (CONFIG_VERSION + 1).into();
+        std::fs::write(Config::path(tmp.path()), document.to_string()).unwrap();
+        assert!(Config::load(tmp.path())
+            .unwrap_err()
+            .to_string()
+            .contains("upgrade jk"));
+    }
+
+    #[test]
+    fn test_execute_in_transaction_and_rollback() {
+        let tmp = TempDir::new().unwrap();
+        let file = tmp.path().join("a.txt");
+        std::fs::write(&file, "one").unwrap();
+        let mut jk = JanusKey::init(tmp.path()).unwrap();
+        assert!(matches!(jk.undo_last(), Err(JanusError::NothingToUndo)));
+
+        jk.transaction_manager.begin(None).unwrap();
+        jk.execute(FileOperation::Modify {
+            path: file.clone(),
+            new_content: b"two".to_vec(),
+        })
+        .unwrap();
+        jk.execute(FileOperation::Delete { path: file.clone() })
+            .unwrap();
+        assert_eq!(
+            jk.transaction_manager.active().unwrap().operation_ids.len(),
+            2
+        );
+
+        let tx = jk.rollback().unwrap();
+        assert_eq!(tx.operation_ids.len(), 2);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "one");
+        assert!(jk.transaction_manager.active().is_none());
+    }
+
+    #[test]
+    fn test_preview_with_content() {
+        let tmp = TempDir::new().unwrap();
+        std::fs::write(tmp.path().join("a.txt"), "one\ntwo\n").unwrap();
+        std::fs::write(tmp.path().join("b.bin"), [0u8, 1, 2]).unwrap();
+        let mut jk = JanusKey::init(tmp.path()).unwrap();
+
+        jk.transaction_manager.begin(None).unwrap();
+        jk.modify("a.txt", b"one\n2\n".to_vec()).unwrap();
+        jk.modify("a.txt", b"one\n2\nthree\n".to_vec()).unwrap();
+        jk.move_file("a.txt", "c.txt").unwrap();
+        jk.delete("b.bin").unwrap();
+
+        let tx = jk.transaction_manager.active().unwrap().clone();
+        let preview = jk.preview_with_content(&tx).unwrap();
+        let changes: Vec<_> = preview
+            .operations
+            .iter()
+            .map(|op| op.change.clone())
+            .collect();
+        let text = |s: &str| Some(s.as_bytes().to_vec());
+        // Each modify ends where the next begins; the last ends in what
+        // moved to c.txt
+        assert_eq!(changes[0].as_ref().unwrap().before, text("one\ntwo\n"));
+        assert_eq!(changes[0].as_ref().unwrap().after, text("one\n2\n"));
+        assert_eq!(changes[1].as_ref().unwrap().after, text("one\n2\nthree\n"));
+        assert_eq!(changes[1].as_ref().unwrap().line_counts(), Some((1, 0)));
+        assert!(changes[2].is_none());
+        let deleted = changes[3].as_ref().unwrap();
+        assert_eq!(deleted.after, None);
+        assert_eq!(deleted.byte_delta(), -3);
+        assert!(deleted.line_diff().is_none());
+    }
+
+    #[test]
+    fn test_subscribers_see_operations() {
+        let tmp = TempDir::new().unwrap();
+        let file = tmp.path().join("a.txt");
+        std::fs::write(&file, "one").unwrap();
+        let mut jk = JanusKey::init(tmp.path()).unwrap();
+        let events = jk.subscribe();
+
+        jk.transaction_manager.begin(None).unwrap();
+        let op = jk
+            .execute(FileOperation::Modify {
+                path: file.clone(),
+                new_content: b"two".to_vec(),
+            })
+            .unwrap();
+        jk.transaction_manager.commit().unwrap();
+        assert!(jk.delete("missing.txt").is_err());
+        let undo = jk.undo_last().unwrap();
+
+        let received: Vec<Event> = events.try_iter().collect();
+        let stored = received
+            .iter()
+            .filter(|event| matches!(event, Event::ContentStored { bytes: 3, .. }))
+            .count();
+        // "one" before the modify, "two" before its undo
+        assert_eq!(stored, 2);
+        let without_content: Vec<&Event> = received
+            .iter()
+            .filter(|event| !matches!(event, Event::ContentStored { .. }))
+            .collect();
+        assert!(matches!(
+            without_content[..],
+            [
+                Event::TransactionBegun { .. },
+                Event::OperationStarted {
+                    op_type: OperationType::Modify,
+                    ..
+                },
+                Event::OperationCompleted { id, .. },
+                Event::TransactionCommitted { .. },
+                Event::OperationStarted {
+                    op_type: OperationType::Delete,
+                    ..
+                },
+                Event::OperationFailed { .. },
+                Event::OperationStarted { .. },
+                Event::OperationCompleted { .. },
+                Event::UndoPerformed {
+                    operation_id,
+                    undo_operation_id,
+                },
+            ] if *id == op.id && *operation_id == op.id && *undo_operation_id == undo.id
+        ));
+    }
+
+    #[test]
+    fn test_facade_records_into_transaction() {
+        let tmp = TempDir::new().unwrap();
+        std::fs::write(tmp.path().join("a.txt"), "one").unwrap();
+        std::fs::write(tmp.path().join("b.txt"), "bee").unwrap();
+        let mut jk = JanusKey::init(tmp.path()).unwrap();
+
+        let mut tx = jk.begin().unwrap();
+        tx.modify("a.txt", "two").unwrap();
+        tx.move_file("a.txt", "c.txt").unwrap();
+        tx.delete(tmp.path().join("b.txt")).unwrap();
+        let committed = tx.commit().unwrap();
+        assert_eq!(committed.operation_ids.len(), 3);
+        assert_eq!(
+            std::fs::read_to_string(tmp.path().join("c.txt")).unwrap(),
+            "two"
+        );
+        assert!(!tmp.path().join("b.txt").exists());
+
+        let mut tx = jk.begin_named(Some("scratch".to_string())).unwrap();
+        tx.create("d.txt", "dee").unwrap();
+        let rolled_back = tx.rollback().unwrap();
+        assert_eq!(rolled_back.name.as_deref(), Some("scratch"));
+        assert!(!tmp.path().join("d.txt").exists());
+
+        let recorded = jk.metadata_store.operations().len();
+        jk.execute_without_history(FileOperation::Delete {
+            path: tmp.path().join("c.txt"),
+        })
+        .unwrap();
+        assert!(!tmp.path().join("c.txt").exists());
+        assert_eq!(jk.metadata_store.operations().len(), recorded);
+    }
+
+    #[test]
+    fn test_heal_rebuilds_damaged_logs() {
+        let tmp = TempDir::new().unwrap();
+        let a = tmp.path().join("a.txt");
+        std::fs::write(&a, "one").unwrap();
+        let mut jk = JanusKey::init(tmp.path()).unwrap();
+        let mut tx = jk.begin().unwrap();
+        tx.modify(&a, "two").unwrap();
+        tx.modify(&a, "three").unwrap();
+        let tx = tx.commit().unwrap();
+        drop(jk);
+        assert!(JanusKey::heal(tmp.path(), false).unwrap().is_clean());
+
+        let jk_dir = tmp.path().join(".januskey");
+        let segment = jk_dir.join("metadata/00000001.jsonl");
+        let mut log = std::fs::read_to_string(&segment).unwrap();
+        log.insert_str(0, "{garbage\n");
+        std::fs::write(&segment, log).unwrap();
+        std::fs::write(jk_dir.join("transactions"), "{").unwrap();
+        assert!(JanusKey::open(tmp.path()).is_err());
+
+        let report = JanusKey::heal(tmp.path(), true).unwrap();
+        assert_eq!(report.damaged.len(), 1);
+        assert_eq!(report.transactions_rebuilt, 1);
+        assert!(JanusKey::open(tmp.path()).is_err());
+
+        let report = JanusKey::heal(tmp.path(), false).unwrap();
+        assert_eq!(report.operations, 2);
+        assert!(report.missing_content.is_empty());
+        let jk = JanusKey::open(tmp.path()).unwrap();
+        let rebuilt = jk.transaction_manager.get(&tx.id).unwrap();
+        assert_eq!(rebuilt.operation_ids, tx.operation_ids);
+        assert_eq!(rebuilt.state, TransactionState::Committed);
+        assert_eq!(
+            std::fs::read_dir(jk_dir.join("lost+found"))
+                .unwrap()
+                .count(),
+            2
+        );
+        assert!(JanusKey::heal(tmp.path(), false).unwrap().is_clean());
+    }
+
+    #[test]
+    fn test_execute_with_key_is_idempotent() {
+        let tmp = TempDir::new().unwrap();
+        let a = tmp.path().join("a.txt");
+        std::fs::write(&a, "one").unwrap();
+        let mut jk = JanusKey::init(tmp.path()).unwrap();
+        let modify = |content: &[u8]| FileOperation::Modify {
+            path: a.clone(),
+            new_content: content.to_vec(),
+        };
+
+        jk.transaction_manager.begin(None).unwrap();
+        let first = jk.execute_with_key(modify(b"two"), "deploy-42").unwrap();
+        assert_eq!(first.id, metadata::operation_id_for_key("deploy-42"));
+        std::fs::write(&a, "changed since").unwrap();
+
+        // A retry does nothing and returns the recorded operation
+        let retry = jk.execute_with_key(modify(b"two"), "deploy-42").unwrap();
+        assert_eq!(retry.id, first.id);
+        assert_eq!(std::fs::read_to_string(&a).unwrap(), "changed since");
+        assert_eq!(jk.metadata_store.count(), 1);
+        assert_eq!(
+            jk.transaction_manager.active().unwrap().operation_ids,
+            vec![first.id.clone()]
+        );
+
+        assert!(matches!(
+            jk.execute_with_key(modify(b"three"), "deploy-42"),
+            Err(JanusError::IdempotencyConflict(_))
+        ));
+        jk.execute_with_key(modify(b"three"), "deploy-43").unwrap();
+        assert_eq!(jk.metadata_store.count(), 2);
+    }
+
+    #[test]
+    fn test_byte_usage_accounts_each_capture() {
+        let tmp = TempDir::new().unwrap();
+        let original = "x".repeat(10_000);
+        std::fs::write(tmp.path().join("a.txt"), &original).unwrap();
+        std::fs::write(tmp.path().join("b.txt"), &original).unwrap();
+        let mut jk = JanusKey::init(tmp.path()).unwrap();
+
+        // Compressed by default, so far less is stored than read
+        let modify = jk.modify("a.txt", "short").unwrap();
+        let bytes = modify.bytes.unwrap();
+        assert_eq!(bytes.read, 10_000);
+        assert!(bytes.stored > 0 && bytes.stored < bytes.read / 10);
+        assert_eq!(
+            jk.metadata_store.get(&modify.id).unwrap().bytes,
+            Some(bytes)
+        );
+
+        // The same content again is deduplicated
+        let delete = jk.delete("b.txt").unwrap();
+        assert_eq!(delete.bytes.unwrap().stored, 0);
+        jk.move_file("a.txt", "c.txt").unwrap();
+
+        let usage = jk.byte_usage();
+        assert_eq!(usage.total.operations, 2);
+        assert_eq!(usage.total.read, 20_000);
+        assert_eq!(usage.total.stored, bytes.stored);
+        assert_eq!(usage.by_type["MODIFY"].operations, 1);
+        assert_eq!(usage.by_type["DELETE"].stored, 0);
+        assert_eq!(usage.deduplicated, 1);
+        assert_eq!(usage.unaccounted, 0);
+        assert_eq!(usage.by_path[0].path, tmp.path().join("a.txt"));
+        assert_eq!(usage.by_path.len(), 2);
+    }
+
+    #[test]
+    fn test_blame_attributes_lines() {
+        let tmp = TempDir::new().unwrap();
+        let a = tmp.path().join("a.txt");
+        std::fs::write(&a, "base\n").unwrap();
+        let mut jk = JanusKey::init(tmp.path()).unwrap();
+
+        let first = jk.modify("a.txt", "base\nfirst\n").unwrap();
+        let second = jk.modify("a.txt", "top\nbase\nfirst\n").unwrap();
+        // An edit outside JanusKey is not credited to the last operation
+        std::fs::write(&a, "top\nbase\nfirst\nmanual\n").unwrap();
+
+        let blame = jk.blame("a.txt").unwrap();
+        let origins: Vec<_> = blame
+            .iter()
+            .map(|line| (line.text.as_str(), line.operation.as_deref()))
+            .collect();
+        assert_eq!(
+            origins,
+            vec![
+                ("top", None),
+                ("base", None),
+                ("first", Some(first.id.as_str())),
+                ("manual", None),
+            ]
+        );
+        // "top" lost its credit with the outside edit; without it, it is kept
+        std::fs::write(&a, "top\nbase\nfirst\n").unwrap();
+        assert_eq!(
+            jk.blame("a.txt").unwrap()[0].operation.as_deref(),
+            Some(second.id.as_str())
+        );
+
+        std::fs::write(tmp.path().join("bin"), [0u8, 159, 146, 150]).unwrap();
+        assert!(jk.blame("bin").is_err());
+    }
+
+    #[test]
+    fn test_partial_rollback_resumes() {
+        let tmp = TempDir::new().unwrap();
+        let a = tmp.path().join("a.txt");
+        let b = tmp.path().join("b.txt");
+        std::fs::write(&a, "one").unwrap();
+        std::fs::write(&b, "bee").unwrap();
+        let mut jk = JanusKey::init(tmp.path()).unwrap();
+
+        jk.transaction_manager.begin(None).unwrap();
+        jk.execute(FileOperation::Modify {
+            path: a.clone(),
+            new_content: b"two".to_vec(),
+        })
+        .unwrap();
+        jk.execute(FileOperation::Delete { path: b.clone() })
+            .unwrap();
+
+        // Something recreates b, so restoring it fails; a is still restored
+        std::fs::write(&b, "squatter").unwrap();
+        assert!(matches!(
+            jk.rollback(),
+            Err(JanusError::RollbackIncomplete(_))
+        ));
+        assert_eq!(std::fs::read_to_string(&a).unwrap(), "one");
+        assert!(jk.transaction_manager.active().is_none());
+        let tx = jk.incomplete_rollback(None).unwrap().clone();
+        assert_eq!(tx.state, TransactionState::PartiallyRolledBack);
+        assert_eq!(tx.rollback_remaining().count(), 1);
+        assert!(matches!(
+            tx.rollback_status[&tx.operation_ids[1]],
+            RollbackStatus::Failed(_)
+        ));
+
+        std::fs::remove_file(&b).unwrap();
+        assert_eq!(jk.plan_rollback_of(&tx).unwrap().len(), 1);
+        let tx = jk.resume_rollback(Some(&tx.id[..8])).unwrap();
+        assert_eq!(tx.state, TransactionState::RolledBack);
+        assert_eq!(std::fs::read_to_string(&b).unwrap(), "bee");
+        assert!(jk.resume_rollback(None).is_err());
+    }
+
+    #[test]
+    fn test_rollback_undoes_dependent_operations_first() {
+        let tmp = TempDir::new().unwrap();
+        std::fs::write(tmp.path().join("a.txt"), "one").unwrap();
+        let mut jk = JanusKey::init(tmp.path()).unwrap();
+
+        jk.transaction_manager.begin(None).unwrap();
+        jk.move_file("a.txt", "b.txt").unwrap();
+        jk.modify("b.txt", "two").unwrap();
+        jk.create("c.txt", "line 1\n").unwrap();
+        jk.modify("c.txt", "line 1\nline 2\n").unwrap();
+        jk.create("d/e.txt", "nested").unwrap();
+        jk.move_file("d/e.txt", "d/f/e.txt").unwrap();
+
+        // Recorded out of the order they ran, as concurrent clients of a
+        // transaction can leave them
+        let mut tx = jk.transaction_manager.active().unwrap().clone();
+        tx.operation_ids.reverse();
+        let steps = jk.plan_rollback_of(&tx).unwrap();
+        // Newest first would move b.txt back before restoring its content
+        assert!(matches!(
+            &steps[0].1,
+            FileOperation::Modify { path, new_content } if path.ends_with("b.txt") && new_content == b"one"
+        ));
+        let tx = jk.rollback_transaction(&tx).unwrap();
+        assert_eq!(tx.state, TransactionState::RolledBack);
+        assert_eq!(
+            std::fs::read_to_string(tmp.path().join("a.txt")).unwrap(),
+            "one"
+        );
+        for gone in ["b.txt", "c.txt", "d/e.txt", "d/f/e.txt"] {
+            assert!(!tmp.path().join(gone).exists(), "{}", gone);
+        }
+    }
+
+    #[test]
+    fn test_plan_rollback_and_gc_plan_change_nothing() {
+        let tmp = TempDir::new().unwrap();
+        let a = tmp.path().join("a.txt");
+        let b = tmp.path().join("b.txt");
+        std::fs::write(&a, "one").unwrap();
+        let mut jk = JanusKey::init(tmp.path()).unwrap();
+
+        jk.transaction_manager.begin(None).unwrap();
+        jk.execute(FileOperation::Modify {
+            path: a.clone(),
+            new_content: b"two".to_vec(),
+        })
+        .unwrap();
+        jk.execute(FileOperation::Move {
+            source: a.clone(),
+            destination: b.clone(),
+        })
+        .unwrap();
+
+        // The second step only succeeds because the first moved b.txt back
+        let steps = jk.plan_rollback().unwrap();
+        assert_eq!(steps.len(), 2);
+        assert!(matches!(steps[0].1, FileOperation::Move { .. }));
+        assert!(matches!(
+            &steps[1].1,
+            FileOperation::Modify { new_content, .. } if new_content == b"one"
+        ));
+        assert!(!a.exists());
+        assert_eq!(std::fs::read_to_string(&b).unwrap(), "two");
+        jk.transaction_manager.commit().unwrap();
+
+        let blobs_before = jk.content_store.count().unwrap();
+        let plan = jk.gc_plan(0).unwrap();
+        assert_eq!(plan.operations.len(), 2);
+        assert_eq!(plan.blobs.len(), blobs_before);
+        assert_eq!(jk.metadata_store.count(), 2);
+
+        let done = jk.gc(0).unwrap();
+        assert_eq!(done.blobs.len(), plan.blobs.len());
+        assert_eq!(jk.metadata_store.count(), 0);
+        assert_eq!(jk.content_store.count().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_legal_hold_blocks_gc_and_obliteration() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        std::fs::create_dir(root.join("contracts")).unwrap();
+        std::fs::write(root.join("contracts/acme.txt"), "signed").unwrap();
+        std::fs::write(root.join("notes.txt"), "draft").unwrap();
+        let mut jk = JanusKey::init(root).unwrap();
+        let held = jk.delete("contracts/acme.txt").unwrap();
+        jk.delete("notes.txt").unwrap();
+        let hash = held.content_hash.clone().unwrap();
+
+        jk.legal_holds()
+            .unwrap()
+            .add("ACME-123", "contracts/**", None)
+            .unwrap();
+        let plan = jk.gc(0).unwrap();
+        assert_eq!(plan.operations.len(), 1);
+        assert_eq!(plan.held, 1);
+        assert_eq!(jk.metadata_store.count(), 1);
+        assert!(jk.content_store.exists(&hash));
+
+        let mut obliterations = jk.obliteration_manager().unwrap();
+        assert_eq!(obliterations.blocking_hold(&hash), Some("ACME-123"));
+        assert!(matches!(
+            obliterations.obliterate(&jk.content_store, &hash, None, None),
+            Err(JanusError::LegalHold(_))
+        ));
+        assert!(jk.content_store.exists(&hash));
+
+        let record = jk
+            .obliteration_manager()
+            .unwrap()
+            .override_holds("court order 42".to_string())
+            .obliterate(&jk.content_store, &hash, None, None)
+            .unwrap();
+        assert_eq!(
+            record.hold_override.as_deref(),
+            Some("case ACME-123: court order 42")
+        );
+        assert!(!jk.content_store.exists(&hash));
+
+        jk.legal_holds().unwrap().release("ACME-123").unwrap();
+        assert_eq!(jk.gc(0).unwrap().held, 0);
+        assert_eq!(jk.metadata_store.count(), 0);
+    }
+
+    #[test]
+    fn test_undo_conflicts() {
+        let tmp = TempDir::new().unwrap();
+        let a = tmp.path().join("a.txt");
+        let b = tmp.path().join("b.txt");
+        std::fs::write(&a, "one").unwrap();
+        std::fs::write(&b, "one").unwrap();
+        let mut jk = JanusKey::init(tmp.path()).unwrap();
+
+        let modify = |path: &std::path::Path, content: &[u8]| FileOperation::Modify {
+            path: path.to_path_buf(),
+            new_content: content.to_vec(),
+        };
+        let first = jk.execute(modify(&a, b"two")).unwrap();
+        let other = jk.execute(modify(&b, b"two")).unwrap();
+        let second = jk.execute(modify(&a, b"three")).unwrap();
+
+        let conflicts = jk.undo_conflicts(std::slice::from_ref(&first.id));
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].1.id, second.id);
+        assert!(jk
+            .undo_conflicts(&[first.id.clone(), second.id.clone()])
+            .is_empty());
+        assert!(jk
+            .undo_conflicts(std::slice::from_ref(&other.id))
+            .is_empty());
+
+        // Once undone, the later change and its undo cancel out
+        jk.undo_last().unwrap();
+        assert!(jk.undo_conflicts(&[first.id]).is_empty());
+    }
+
+    #[test]
+    fn test_recent_for_path() {
+        let tmp = TempDir::new().unwrap();
+        std::fs::create_dir_all(tmp.path().join("src")).unwrap();
+        for name in ["src/a.rs", "src/b.rs", "notes.txt"] {
+            std::fs::write(tmp.path().join(name), "one").unwrap();
+        }
+        let mut jk = JanusKey::init(tmp.path()).unwrap();
+
+        let a = jk.modify("src/a.rs", "two").unwrap();
+        jk.modify("notes.txt", "two").unwrap();
+        let b = jk.modify("src/b.rs", "two").unwrap();
+        let moved = jk.move_file("notes.txt", "src/notes.txt").unwrap();
+
+        let ids = |ops: Vec<OperationMetadata>| -> Vec<String> {
+            ops.into_iter().map(|op| op.id).collect()
+        };
+        assert_eq!(
+            ids(jk.recent_for_path("src/*.rs", 10).unwrap()),
+            [b.id.clone(), a.id]
+        );
+        assert_eq!(
+            ids(jk.recent_for_path("src/*", 2).unwrap()),
+            [moved.id, b.id.clone()]
+        );
+
+        // Only the undo itself is left to undo
+        let undo = jk.undo(&b.id).unwrap();
+        assert_eq!(ids(jk.recent_for_path("src/b.rs", 10).unwrap()), [undo.id]);
+        assert!(jk.recent_for_path("[", 1).is_err());
+    }
+
+    #[test]
+    fn test_undo_refuses_dependents_or_cascades() {
+        let tmp = TempDir::new().unwrap();
+        let a = tmp.path().join("a.txt");
+        let b = tmp.path().join("b.txt");
+        std::fs::write(&a, "one").unwrap();
+        let mut jk = JanusKey::init(tmp.path()).unwrap();
+
+        let modify = jk
+            .execute(FileOperation::Modify {
+                path: a.clone(),
+                new_content: b"two".to_vec(),
+            })
+            .unwrap();
+        let moved = jk
+            .execute(FileOperation::Move {
+                source: a.clone(),
+                destination: b.clone(),
+            })
+            .unwrap();
+
+        match jk.undo(&modify.id) {
+            Err(JanusError::HasDependents { dependents, .. }) => {
+                assert_eq!(dependents, [moved.id])
+            }
+            other => panic!("expected HasDependents, got {:?}", other),
+        }
+        assert_eq!(std::fs::read(&b).unwrap(), b"two");
+
+        let undone = jk.undo_cascade(&modify.id).unwrap();
+        assert_eq!(undone.len(), 2);
+        assert!(!b.exists());
+        assert_eq!(std::fs::read(&a).unwrap(), b"one");
+    }
+
+    #[test]
+    fn test_declared_actor_is_recorded() {
+        let tmp = TempDir::new().unwrap();
+        let file = tmp.path().join("a.txt");
+        std::fs::write(&file, "one").unwrap();
+        let resolver = actor::FixedActor(Actor::declared("deploy-bot").unwrap());
+        let mut jk = JanusKey::init(tmp.path())
+            .unwrap()
+            .with_actor_resolver(&resolver)
+            .unwrap();
+
+        let tx = jk.transaction_manager.begin(None).unwrap().clone();
+        let meta = jk
+            .execute(FileOperation::Modify {
+                path: file,
+                new_content: b"two".to_vec(),
+            })
+            .unwrap();
+        assert_eq!(tx.actor.as_deref(), Some("deploy-bot"));
+        assert_eq!(meta.actor.as_deref(), Some("deploy-bot"));
+        assert_eq!(meta.user, whoami::username());
+
+        let reopened = JanusKey::open(tmp.path()).unwrap();
+        let stored = reopened.metadata_store.get(&meta.id).unwrap();
+        assert_eq!(stored.actor.as_deref(), Some("deploy-bot"));
+    }
+
+    #[test]
+    fn test_store_quota() {
+        let tmp = TempDir::new().unwrap();
+        let file = tmp.path().join("a.txt");
+        std::fs::write(&file, vec![b'a'; 100]).unwrap();
+        let config = Config {
+            compression: false,
+            max_store_bytes: Some(150),
+            max_history: 0,
+            ..Config::default()
+        };
+        let mut jk = JanusKey::init_with_config(tmp.path(), config).unwrap();
+        let modify = |byte: u8| FileOperation::Modify {
+            path: file.clone(),
+            new_content: vec![byte; 100],
+        };
+
+        jk.execute(modify(b'b')).unwrap();
+        assert!(matches!(
+            jk.execute(modify(b'c')),
+            Err(JanusError::QuotaExceeded(_))
+        ));
+        assert_eq!(std::fs::read(&file).unwrap(), vec![b'b'; 100]);
+
+        // With gc_on_quota, retention (max_history 0) frees the old blob
+        jk.config.gc_on_quota = true;
+        jk.execute(modify(b'c')).unwrap();
+        assert_eq!(jk.metadata_store.count(), 1);
+    }
+
+    #[test]
+    fn test_disk_space_check() {
+        let tmp = TempDir::new().unwrap();
+        let file = tmp.path().join("a.txt");
+        std::fs::write(&file, vec![b'a'; 100]).unwrap();
+        let mut jk = JanusKey::init(tmp.path()).unwrap();
+        let meta = jk.delete(&file).unwrap();
+
+        assert_eq!(meta.restore_bytes(), 100);
+        jk.ensure_disk_space(meta.restore_bytes()).unwrap();
+        assert!(matches!(
+            jk.ensure_disk_space(u64::MAX),
+            Err(JanusError::InsufficientSpace(_))
+        ));
     }
 }
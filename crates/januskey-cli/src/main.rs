@@ -5,18 +5,38 @@
 // JanusKey CLI: Provably Reversible File Operations
 // "Never lose data again"
 
+mod complete;
+mod keys_cli;
+mod shell;
+
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use colored::Colorize;
-use dialoguer::Confirm;
+use dialoguer::{Confirm, MultiSelect, Password, Select};
 use indicatif::{ProgressBar, ProgressStyle};
 use januskey::{
-    operations::{FileOperation, OperationExecutor},
-    transaction::TransactionPreview,
-    JanusKey,
+    access::TOKEN_ENV,
+    delta::{is_likely_text, line_diff, DiffHunk, DiffLine},
+    edit_script::{apply_hunks, EditCommand, EditScript},
+    envelope::{self, SealFormat},
+    export::{ExportEncoding, FileSignature, HistoryExport, SignedExport},
+    human_bytes,
+    keys::KeyManager,
+    metadata::OperationMetadata,
+    obliteration::DeltaDependents,
+    operations::{restored_hash, FileOperation, Simulation},
+    refs::RefQuery,
+    select_files,
+    transaction::{RollbackStatus, Transaction, TransactionPreview, TransactionState},
+    ApiToken, BlameLine, ByteTotals, CheckStatus, Config, ConfirmationRequest, Excludes,
+    HistoryGraph, JanusError, JanusKey, OperationType, PreservedAttribute, PruneRule, Query, Role,
+    SnapshotChange, StorageUri,
 };
-use std::fs;
-use std::path::PathBuf;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+#[cfg(unix)]
+use januskey::daemon;
 
 #[derive(Parser)]
 #[command(
@@ -37,7 +57,7 @@ struct Cli {
     dir: Option<PathBuf>,
 
     /// Repository directory to operate on (alias of --dir; takes precedence)
-    #[arg(short = 'r', long, global = true)]
+    #[arg(long, global = true)]
     repo: Option<PathBuf>,
 
     /// Dry run mode (don't actually make changes)
@@ -47,6 +67,35 @@ struct Cli {
     /// Skip confirmation prompts
     #[arg(short = 'y', long, global = true)]
     yes: bool,
+
+    /// Print machine-readable JSON (commands that support it)
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Delete and modify without keeping the original content (cannot be
+    /// undone; for when the content store is full)
+    #[arg(long, global = true)]
+    no_history: bool,
+
+    /// Who the operations are for, recorded next to the OS user
+    /// (defaults to $JANUSKEY_ACTOR, or $SUDO_USER under sudo)
+    #[arg(long, global = true, value_name = "NAME")]
+    actor: Option<String>,
+
+    /// External reference to record on operations and transactions, such
+    /// as ci:build=1234 (repeatable; adds to $JANUSKEY_REFS)
+    #[arg(long = "ref", global = true, value_name = "KEY=VALUE")]
+    refs: Vec<String>,
+
+    /// Let operations change .januskey/ and the configured protected paths
+    /// (destroys the history undo relies on)
+    #[arg(long, global = true)]
+    allow_protected: bool,
+
+    /// Let operations change paths outside the root when confine_to_root
+    /// is set
+    #[arg(long, global = true)]
+    allow_outside: bool,
 }
 
 #[derive(Subcommand)]
@@ -56,6 +105,11 @@ enum Commands {
         /// Directory to initialize (created if it does not exist).
         /// Overrides --dir / --repo when given.
         path: Option<PathBuf>,
+
+        /// Store history at a path or backend URI (file://, ssh://, s3://)
+        /// instead of .januskey/
+        #[arg(long, value_name = "URI")]
+        storage: Option<String>,
     },
 
     /// Delete files (reversible)
@@ -68,36 +122,93 @@ enum Commands {
         /// Delete recursively (for directories)
         #[arg(short, long)]
         recursive: bool,
+
+        /// Skip paths matching a gitignore-style pattern (repeatable)
+        #[arg(long, value_name = "PATTERN")]
+        exclude: Vec<String>,
     },
 
     /// Modify files with sed-like syntax or a script (reversible)
     Modify {
-        /// Sed-like pattern (s/old/new/g) or script path
-        pattern: String,
-
-        /// Files to modify
-        #[arg(required = true)]
-        paths: Vec<String>,
+        #[command(flatten)]
+        args: ModifyArgs,
     },
 
     /// Move or rename files (reversible)
     #[command(alias = "mv")]
     Move {
-        /// Source file(s)
-        source: String,
+        /// Source files or glob patterns
+        #[arg(required = true, num_args = 1..)]
+        sources: Vec<String>,
 
-        /// Destination
+        /// Destination (a directory when there are several sources)
         destination: PathBuf,
     },
 
     /// Copy files (reversible - the copy can be deleted)
     #[command(alias = "cp")]
     Copy {
-        /// Source file
-        source: PathBuf,
+        /// Source files or glob patterns
+        #[arg(required = true, num_args = 1..)]
+        sources: Vec<String>,
 
-        /// Destination
+        /// Destination (a directory when there are several sources)
         destination: PathBuf,
+
+        /// Carry over timestamps, ownership and extended attributes too,
+        /// or only those listed (mode,timestamps,ownership,xattr)
+        #[arg(
+            long,
+            value_name = "ATTRS",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "all",
+            value_parser = PreservedAttribute::parse_list
+        )]
+        preserve: Option<BTreeSet<PreservedAttribute>>,
+    },
+
+    /// Create a file from --content, --file or standard input (reversible -
+    /// the file can be deleted)
+    #[command(alias = "write")]
+    Create {
+        /// File to create
+        path: PathBuf,
+
+        /// Content to write
+        #[arg(long, conflicts_with = "file")]
+        content: Option<String>,
+
+        /// Write the content of this file
+        #[arg(long, value_name = "FILE")]
+        file: Option<PathBuf>,
+
+        /// Permission bits to give the file, in octal (Unix only)
+        #[arg(long, value_name = "OCTAL")]
+        mode: Option<String>,
+
+        /// Create missing parent directories
+        #[arg(short, long)]
+        parents: bool,
+    },
+
+    /// Change permissions, octal (755) or symbolic (u+x,go-w) (reversible)
+    Chmod {
+        /// Mode to set, as chmod takes it
+        #[arg(allow_hyphen_values = true)]
+        mode: String,
+
+        /// Files, directories or glob patterns
+        #[arg(required = true)]
+        paths: Vec<String>,
+
+        /// Also change everything beneath the directories
+        #[arg(short = 'R', long)]
+        recursive: bool,
+
+        /// Skip paths matching a gitignore-style pattern (repeatable)
+        #[arg(long, value_name = "PATTERN")]
+        exclude: Vec<String>,
     },
 
     /// Rename a file (reversible)
@@ -109,23 +220,79 @@ enum Commands {
         new_name: PathBuf,
     },
 
+    /// Rename many files with a regex over their names, in one transaction
+    /// (reversible)
+    RenameBulk {
+        /// Files, directories or glob patterns
+        #[arg(required = true)]
+        paths: Vec<String>,
+
+        /// Template for the new names: s/regex/replacement/[gi], where the
+        /// replacement refers to groups as $1 or ${name}
+        #[arg(long)]
+        pattern: String,
+
+        /// Also rename files beneath the directories
+        #[arg(short, long)]
+        recursive: bool,
+
+        /// Skip paths matching a gitignore-style pattern (repeatable)
+        #[arg(long, value_name = "PATTERN")]
+        exclude: Vec<String>,
+    },
+
     /// Obliterate a file: securely overwrite then remove it (NOT reversible).
     /// Implements GDPR Article 17 "right to erasure".
     Obliterate {
         /// File(s) to obliterate
-        #[arg(required = true)]
+        #[arg(required_unless_present_any = ["schedule", "run_due", "pending"])]
         paths: Vec<PathBuf>,
+
+        /// Obliterate content deleted under --path this long after its
+        /// deletion (e.g. 30d, 4w, 12h)
+        #[arg(long, value_name = "AGE", requires = "path", conflicts_with_all = ["paths", "run_due", "pending"])]
+        schedule: Option<String>,
+
+        /// Paths the schedule covers, relative to the root (e.g. 'pii/**')
+        #[arg(long, value_name = "GLOB", requires = "schedule")]
+        path: Option<String>,
+
+        /// Reason recorded with scheduled obliterations
+        #[arg(long, requires = "schedule")]
+        reason: Option<String>,
+
+        /// Legal basis recorded with scheduled obliterations
+        #[arg(long, requires = "schedule")]
+        legal_basis: Option<String>,
+
+        /// Obliterate scheduled content that is due
+        #[arg(long, conflicts_with_all = ["paths", "pending"])]
+        run_due: bool,
+
+        /// What to do with deltas stored against obliterated content:
+        /// refuse, materialize them as full content, or cascade to them
+        #[arg(
+            long,
+            value_name = "POLICY",
+            default_value = "refuse",
+            requires = "run_due"
+        )]
+        dependents: DeltaDependents,
+
+        /// List schedules and the content they will obliterate
+        #[arg(long, conflicts_with = "paths")]
+        pending: bool,
+
+        /// Obliterate content under legal hold anyway, recording this
+        /// justification with the hold
+        #[arg(long, value_name = "JUSTIFICATION", conflicts_with_all = ["schedule", "pending"])]
+        override_hold: Option<String>,
     },
 
     /// Undo the last operation(s)
     Undo {
-        /// Number of operations to undo
-        #[arg(short, long, default_value = "1")]
-        count: usize,
-
-        /// Undo a specific operation by ID
-        #[arg(long)]
-        id: Option<String>,
+        #[command(flatten)]
+        args: UndoArgs,
     },
 
     /// Begin a new transaction
@@ -138,86 +305,1068 @@ enum Commands {
     Commit,
 
     /// Rollback the current transaction
-    Rollback,
+    Rollback {
+        /// Retry the operations a failed rollback left undone
+        #[arg(long)]
+        resume: bool,
+
+        /// Transaction to resume (default: the most recent partially
+        /// rolled back one)
+        #[arg(requires = "resume", value_name = "TRANSACTION_ID")]
+        id: Option<String>,
+
+        /// Roll back even when the disk looks too full for the restored
+        /// content
+        #[arg(long)]
+        force: bool,
+    },
 
     /// Preview pending changes in current transaction
-    Preview,
+    Preview {
+        /// Show what each operation changed in the files' content
+        #[arg(long)]
+        diff: bool,
+
+        /// Summarize lines and bytes changed per file
+        #[arg(long)]
+        stat: bool,
+    },
+
+    /// Place, list and release legal holds, which keep content from gc
+    /// and obliteration
+    Hold {
+        #[command(subcommand)]
+        action: HoldAction,
+    },
+
+    /// Inspect committed, rolled back and active transactions
+    Tx {
+        #[command(subcommand)]
+        action: TxAction,
+    },
 
     /// Show operation history
     History {
-        /// Number of entries to show
-        #[arg(short, long, default_value = "20")]
-        limit: usize,
+        #[command(subcommand)]
+        action: Option<HistoryAction>,
+
+        #[command(flatten)]
+        args: HistoryArgs,
+    },
+
+    /// Show one operation in full: its paths and content, and who ran it
+    /// from where
+    Show {
+        /// Operation ID, or a unique prefix of one
+        #[arg(value_name = "OPERATION_ID")]
+        id: String,
+    },
+
+    /// Show every version of a file, following renames
+    Timeline {
+        /// File to show (its current name)
+        path: PathBuf,
+
+        /// Restore the content captured by timeline entry N (reversible)
+        #[arg(long, value_name = "N", conflicts_with = "diff")]
+        restore: Option<usize>,
+
+        /// Show a line diff between the content of entries N and M
+        #[arg(long, num_args = 2, value_names = ["N", "M"])]
+        diff: Option<Vec<usize>>,
+    },
+
+    /// Show the operation that introduced each line of a text file
+    Blame {
+        /// File to annotate
+        path: PathBuf,
+    },
+
+    /// Find which recorded versions of files contain some text, including
+    /// content since deleted or overwritten
+    Search {
+        /// Text to search for (a regular expression with --regex)
+        #[arg(required_unless_present = "reindex")]
+        pattern: Option<String>,
+
+        /// Treat the pattern as a regular expression
+        #[arg(long)]
+        regex: bool,
+
+        /// Match regardless of case
+        #[arg(short = 'i', long)]
+        ignore_case: bool,
+
+        /// Bring the content index up to date, which lets later literal
+        /// searches skip content that cannot match
+        #[arg(long)]
+        reindex: bool,
+    },
+
+    /// Build and inspect the content index that lets `jk search` skip
+    /// content that cannot match
+    Index {
+        #[command(subcommand)]
+        action: IndexAction,
+    },
+
+    /// Record every file under the root as a named restore point.
+    /// Paths in .januskeyignore are skipped.
+    #[command(args_conflicts_with_subcommands = true)]
+    Snapshot {
+        #[command(subcommand)]
+        action: Option<SnapshotAction>,
+
+        /// Snapshot name (defaults to the current time)
+        name: Option<String>,
+
+        /// Skip paths matching a gitignore-style pattern (repeatable)
+        #[arg(long, value_name = "PATTERN")]
+        exclude: Vec<String>,
+
+        /// Store only what changed since the latest snapshot
+        #[arg(short, long)]
+        incremental: bool,
+    },
+
+    /// Bring the tree back to a snapshot (reversible)
+    RestoreSnapshot {
+        /// Snapshot name
+        name: String,
+
+        /// Leave paths matching a gitignore-style pattern alone (repeatable)
+        #[arg(long, value_name = "PATTERN")]
+        exclude: Vec<String>,
+
+        /// Restore even when the disk looks too full for the snapshot
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Back up the whole store (history, content, transactions, keys and
+    /// obliteration log) to one archive with an integrity manifest
+    Backup {
+        /// Archive to write
+        #[arg(short, long, default_value = "store-backup.tar.zst")]
+        output: PathBuf,
+    },
+
+    /// Restore the store from a `jk backup` archive, checking every file
+    /// against its manifest before unpacking. With --dry-run only checks.
+    RestoreStore {
+        /// Archive written by `jk backup`
+        archive: PathBuf,
+
+        /// Replace an existing store
+        #[arg(long)]
+        force: bool,
+    },
 
-        /// Filter by operation type (DELETE, MODIFY, MOVE, COPY)
+    /// Write a detached Ed25519 signature of a file (reversible)
+    Sign {
+        /// File to sign
+        file: PathBuf,
+
+        /// Ed25519 key ID from the key store to sign with
+        #[arg(long, value_name = "KEY_ID")]
+        key: String,
+
+        /// Signature file to write (defaults to FILE.sig)
         #[arg(short, long)]
-        filter: Option<String>,
+        output: Option<PathBuf>,
+    },
+
+    /// Check a file against a detached signature (no repository needed)
+    Verify {
+        /// Signed file
+        file: PathBuf,
+
+        /// Signature written by `jk sign`
+        signature: PathBuf,
+
+        /// Require the signature to be by this hex-encoded Ed25519 public
+        /// key
+        #[arg(long, value_name = "PUBLIC_KEY")]
+        key: Option<String>,
+    },
+
+    /// Manage cryptographic keys (replaces the standalone jk-keys)
+    Keys {
+        #[command(subcommand)]
+        command: keys_cli::KeysCommand,
     },
 
     /// Show current status
     Status,
 
+    /// Show what operations have added to the content store: bytes read
+    /// and stored, by operation type and by path
+    Stats {
+        /// Number of paths to list
+        #[arg(long, default_value = "10")]
+        top: usize,
+    },
+
+    /// Serve the store over a Unix socket until stopped. While it runs,
+    /// delete, modify, undo, begin, commit, rollback and status go
+    /// through it.
+    Daemon {
+        /// Stop the daemon serving this directory
+        #[arg(long)]
+        stop: bool,
+
+        /// Also capture files before other processes change them, and
+        /// record those changes (Linux, needs CAP_SYS_ADMIN)
+        #[arg(long, conflicts_with = "stop")]
+        intercept: bool,
+
+        /// With --intercept, refuse opens whose content cannot be captured
+        #[arg(long, requires = "intercept")]
+        fail_closed: bool,
+    },
+
+    /// Answer read-only HTTP requests for the history, stats,
+    /// transactions, audit log and obliteration log of these directories,
+    /// as JSON for dashboards (needs the `server` feature)
+    Serve {
+        /// Directories to serve, each under its name (default: this one)
+        dirs: Vec<PathBuf>,
+
+        /// Address to listen on
+        #[arg(long, value_name = "HOST:PORT", default_value = januskey::server::DEFAULT_BIND)]
+        bind: String,
+    },
+
     /// Garbage collect old operations
     Gc {
-        /// Keep only the last N operations
+        /// Keep only the last N operations (default: max_history, or
+        /// none beyond the age limit with --older-than)
         #[arg(long)]
         keep: Option<usize>,
 
-        /// Delete operations older than N days
+        /// Prune operations older than this: hours, days, weeks, calendar
+        /// months or years (12h, 30d, 4w, 6m, 1y)
+        #[arg(long, value_name = "AGE")]
+        older_than: Option<String>,
+
+        /// Keep the newest version of every path, however old
+        #[arg(long)]
+        keep_latest: bool,
+
+        /// Also rewrite sealed operation log segments, the transaction log
+        /// and the obliteration log zstd-compressed
+        #[arg(long)]
+        compact: bool,
+    },
+
+    /// Compact the operation log into as few segments as possible and
+    /// pack small content blobs into packfiles
+    Compact,
+
+    /// Offload old or large blobs to the backend configured under
+    /// `tiering`, leaving pointers so that undo still finds them
+    Tier {
+        /// Bring every offloaded blob back instead
+        #[arg(long)]
+        recall: bool,
+    },
+
+    /// Manage the remote stores the history is pushed to and pulled from
+    Remote {
+        #[command(subcommand)]
+        action: RemoteAction,
+    },
+
+    /// Manage the API tokens `jk daemon` accepts, and the role each grants
+    Token {
+        #[command(subcommand)]
+        action: TokenAction,
+    },
+
+    /// Show and change settings in .januskey/config.json
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// List or join the workspaces sharing this directory's store
+    Workspace {
+        #[command(subcommand)]
+        action: WorkspaceAction,
+    },
+
+    /// Copy the operations a remote lacks, and their content, to it
+    Push {
+        /// Remote to push to (default: the only one configured)
+        remote: Option<String>,
+    },
+
+    /// Copy the operations this store lacks, and their content, from a
+    /// remote. Only the history changes; files are left as they are.
+    Pull {
+        /// Remote to pull from (default: the only one configured)
+        remote: Option<String>,
+    },
+
+    /// Rebuild a damaged operation or transaction log, keeping what is
+    /// readable, and report what could not be recovered
+    Heal,
+
+    /// Check the environment and the store for problems that would stop
+    /// undo from working: daemon lock, filesystem, clock, logs, keystore
+    /// permissions and free space
+    Doctor,
+
+    /// Show whether undos reproduced the original bytes
+    VerifyRestore {
+        /// Operation ID (the undone operation or its undo); all if omitted
+        #[arg(value_name = "OPERATION_ID")]
+        id: Option<String>,
+    },
+
+    /// Run jk commands interactively, keeping the store open between
+    /// them; completes paths and operation IDs, and the prompt shows the
+    /// active transaction
+    Shell,
+
+    /// Print a completion script for a shell; bash, zsh and fish also
+    /// complete operation, transaction and key IDs and recorded paths
+    Completions {
+        /// Shell to complete for
+        shell: clap_complete::Shell,
+    },
+
+    /// Values for the word being completed (used by completion scripts)
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        /// The command line after `jk`, up to the word being completed
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        words: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TxAction {
+    /// List transactions, newest first
+    List {
+        /// Number of transactions to show
+        #[arg(short, long, default_value = "20")]
+        limit: usize,
+    },
+
+    /// Show a transaction and its operations
+    Show {
+        /// Transaction ID or unique prefix
+        #[arg(value_name = "TRANSACTION_ID")]
+        id: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum SnapshotAction {
+    /// List snapshots, oldest first
+    List,
+
+    /// List paths added, removed and modified between two snapshots
+    Diff {
+        /// Earlier snapshot
+        from: String,
+
+        /// Later snapshot
+        to: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum HoldAction {
+    /// Hold everything recorded under matching paths
+    Add {
+        /// Paths to hold, relative to the root (e.g. 'contracts/**')
+        #[arg(long, value_name = "GLOB")]
+        path: String,
+
+        /// Case or matter reference
+        #[arg(long)]
+        case: String,
+
+        /// Why the content is held
+        #[arg(long)]
+        reason: Option<String>,
+    },
+
+    /// List active holds
+    List {
+        /// Include released holds and overrides
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// Release the holds for a case (or one hold by ID)
+    Release {
+        /// Case reference, or hold ID or unique prefix
+        case: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum RemoteAction {
+    /// Name a remote store: a path, or an ssh://, s3://, azure:// or gs://
+    /// URI as for `storage_path`
+    Add { name: String, uri: String },
+
+    /// Forget a remote (the remote store itself is left alone)
+    Remove { name: String },
+
+    /// List the remotes
+    List,
+}
+
+#[derive(Subcommand)]
+enum TokenAction {
+    /// Issue a token, shown once: reader, operator or compliance-officer
+    Add {
+        /// Who the token is for; operations done with it are recorded
+        /// against this name
+        name: String,
         #[arg(long)]
-        older_than: Option<u32>,
+        role: Role,
+    },
+
+    /// Revoke a token
+    Remove { name: String },
+
+    /// List the tokens (names and roles; the tokens are not kept)
+    List,
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print a setting: a name, or a dotted path into one
+    /// (retry.max_attempts)
+    Get { key: String },
+
+    /// Change a setting. Numbers, true/false, null, lists and objects are
+    /// read as JSON, anything else as a string; values the setting cannot
+    /// hold are refused.
+    Set { key: String, value: String },
+
+    /// List every setting with its current value
+    List,
+}
+
+#[derive(Subcommand)]
+enum WorkspaceAction {
+    /// List the roots registered against this directory's store
+    List,
+
+    /// Record this directory's operations as a workspace of its shared
+    /// store from now on (default ID: the directory's name)
+    Join { id: Option<String> },
+}
+
+#[derive(Subcommand)]
+enum IndexAction {
+    /// Discard the index and index all stored content afresh
+    Rebuild,
+
+    /// Show how much of the stored content is indexed
+    Status,
+}
+
+/// Which operations `jk undo` undoes and how
+#[derive(Args)]
+struct UndoArgs {
+    /// Number of operations to undo (default 1)
+    #[arg(short, long)]
+    count: Option<usize>,
+
+    /// Undo a specific operation by ID. Refused when later operations
+    /// depend on it, unless --cascade is given
+    #[arg(long, value_name = "OPERATION_ID")]
+    id: Option<String>,
+
+    /// With --id, also undo every later operation depending on it,
+    /// newest first
+    #[arg(long, requires = "id")]
+    cascade: bool,
+
+    /// Choose which of the recent operations to undo (the last 20
+    /// unless --count is given)
+    #[arg(short, long, conflicts_with = "id")]
+    interactive: bool,
+
+    /// Undo the last operation (or --count operations) on paths
+    /// matching this glob, whatever happened elsewhere since. Warns
+    /// when later operations depend on them
+    #[arg(long, value_name = "GLOB", conflicts_with_all = ["id", "interactive"])]
+    path: Option<String>,
+
+    /// Undo every operation recorded since this time, newest first,
+    /// only on paths matching --path if given: an age (12h, 30d),
+    /// `1 hour ago`, a date or an RFC 3339 timestamp
+    #[arg(long, value_name = "WHEN", conflicts_with_all = ["id", "interactive", "count"])]
+    since: Option<String>,
+
+    /// Undo even when the disk looks too full for the restored content
+    #[arg(long)]
+    force: bool,
+}
+
+/// What `jk modify` changes and how
+#[derive(Args)]
+struct ModifyArgs {
+    /// Sed-like command (s/old/new/g, 3d, $a text, /text/i text) or
+    /// script path with one command per line
+    #[arg(required_unless_present = "stdin")]
+    pattern: Option<String>,
+
+    /// Files to modify
+    #[arg(required_unless_present = "stdin")]
+    paths: Vec<String>,
+
+    /// Skip paths matching a gitignore-style pattern (repeatable)
+    #[arg(long, value_name = "PATTERN", conflicts_with = "stdin")]
+    exclude: Vec<String>,
+
+    /// Replace FILE's content with standard input
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["pattern", "paths"])]
+    stdin: Option<PathBuf>,
+
+    /// Show each changed hunk and choose which to apply; only the
+    /// accepted ones are recorded
+    #[arg(short, long, conflicts_with = "stdin")]
+    interactive: bool,
+}
+
+/// What `jk history` lists and how
+#[derive(Args)]
+struct HistoryArgs {
+    /// Number of entries to show
+    #[arg(short, long, default_value = "20")]
+    limit: usize,
+
+    /// Filter by operation type (DELETE, MODIFY, MOVE, COPY)
+    #[arg(short, long)]
+    filter: Option<String>,
+
+    /// Only show operations on paths matching this file or glob
+    #[arg(short, long)]
+    path: Option<String>,
+
+    /// Also show operations recorded under the file's earlier names
+    #[arg(long, requires = "path")]
+    follow: bool,
+
+    /// Only show operations with this external reference, any value or
+    /// the one given (repeatable; all must match)
+    #[arg(long = "has-ref", value_name = "KEY[=VALUE]")]
+    has_ref: Vec<String>,
+
+    /// Draw operations grouped by transaction, with undo/redo links
+    /// and rename chains; `--graph dot` prints Graphviz DOT
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        default_missing_value = "text"
+    )]
+    graph: Option<GraphFormat>,
+
+    /// Show the operations another workspace of a shared store recorded
+    #[arg(long, value_name = "ID", conflicts_with_all = ["path", "graph"])]
+    workspace: Option<String>,
+
+    /// Print whole operation IDs instead of the shortest unique prefix
+    #[arg(long, conflicts_with = "graph")]
+    full_ids: bool,
+
+    /// Add columns for the bytes each operation read and stored, and
+    /// the ratio between them
+    #[arg(long, conflicts_with = "graph")]
+    stat: bool,
+}
+
+#[derive(Subcommand)]
+enum HistoryAction {
+    /// Export operations as a signed bundle for hand-off
+    Export {
+        #[command(flatten)]
+        args: ExportArgs,
+    },
+
+    /// Verify a signed history bundle (no repository needed)
+    VerifyExport {
+        /// Bundle to verify
+        file: PathBuf,
+
+        /// Require a signature by this hex-encoded Ed25519 public key
+        #[arg(long, value_name = "PUBLIC_KEY")]
+        key: Option<String>,
+
+        /// X25519 key ID from the key store to decrypt an encrypted bundle
+        /// with
+        #[arg(long, value_name = "KEY_ID")]
+        identity: Option<String>,
     },
 }
 
-fn main() -> Result<()> {
+/// What `jk history export` writes and how it is signed and encrypted
+#[derive(Args)]
+struct ExportArgs {
+    /// File to write the bundle to
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Ed25519 key ID from the key store to sign with (repeatable)
+    #[arg(long = "sign", value_name = "KEY_ID", required = true)]
+    sign: Vec<String>,
+
+    /// Bundle encoding
+    #[arg(long, value_enum, default_value = "json")]
+    format: BundleFormat,
+
+    /// Encrypt the bundle to this X25519 public key, hex or age1...
+    /// (repeatable; see `jk keys export-pub`)
+    #[arg(long = "recipient", value_name = "PUBLIC_KEY")]
+    recipients: Vec<String>,
+
+    /// Encrypt in the age format, so recipients can use `age -d`
+    #[arg(long, requires = "recipients")]
+    age: bool,
+
+    /// Only export the N most recent matching operations
+    #[arg(short, long)]
+    limit: Option<usize>,
+
+    /// Filter by operation type (DELETE, MODIFY, MOVE, COPY)
+    #[arg(short, long)]
+    filter: Option<String>,
+
+    /// Only export operations on paths matching this file or glob
+    #[arg(short, long)]
+    path: Option<String>,
+
+    /// Also export operations recorded under the file's earlier names
+    #[arg(long, requires = "path")]
+    follow: bool,
+
+    /// Only export operations with this external reference, any value
+    /// or the one given (repeatable; all must match)
+    #[arg(long = "has-ref", value_name = "KEY[=VALUE]")]
+    has_ref: Vec<String>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BundleFormat {
+    Json,
+    Cbor,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum GraphFormat {
+    Text,
+    Dot,
+}
+
+fn main() -> std::process::ExitCode {
     let cli = Cli::parse();
+    let json = cli.json;
+    match run(cli) {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(e) if json => {
+            println!("{}", error_json(&e));
+            std::process::ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            std::process::ExitCode::FAILURE
+        }
+    }
+}
+
+/// A failed command as `--json` reports it: the stable code, path and
+/// operation of the JanusKey error behind it, and the chain of causes
+fn error_json(error: &anyhow::Error) -> serde_json::Value {
+    let janus = error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<JanusError>());
+    serde_json::json!({
+        "error": {
+            "code": janus.map_or("error", JanusError::code),
+            "message": error.to_string(),
+            "path": janus.and_then(JanusError::path),
+            "operation_id": janus.and_then(JanusError::operation_id),
+            "causes": error.chain().skip(1).map(ToString::to_string).collect::<Vec<_>>(),
+        }
+    })
+}
 
+fn run(cli: Cli) -> Result<()> {
     // Determine working directory. --repo takes precedence over --dir; both
     // fall back to the current directory.
     let working_dir = match cli.repo.or(cli.dir) {
         Some(dir) => dir,
         None => std::env::current_dir().context("Failed to get current directory")?,
     };
+    // History records paths relative to the root JanusKey::open resolves,
+    // so file arguments must be joined onto the same canonical form
+    let working_dir = working_dir.canonicalize().unwrap_or(working_dir);
+
+    if let Some(actor) = &cli.actor {
+        januskey::actor::validate_actor(actor)?;
+        // Read by every actor resolution in this process, audit records
+        // included; set before any threads start
+        std::env::set_var(januskey::actor::ACTOR_ENV, actor);
+    }
+    if !cli.refs.is_empty() {
+        let mut refs = januskey::refs::from_env()?;
+        for spec in &cli.refs {
+            let (key, value) = januskey::refs::parse_reference(spec)?;
+            refs.insert(key, value);
+        }
+        // Like the actor, read wherever the store is opened
+        std::env::set_var(
+            januskey::refs::REFS_ENV,
+            januskey::refs::format_references(&refs),
+        );
+    }
+    if cli.allow_protected {
+        std::env::set_var(januskey::guard::ALLOW_PROTECTED_ENV, "1");
+    }
+    if cli.allow_outside {
+        std::env::set_var(januskey::guard::ALLOW_OUTSIDE_ENV, "1");
+    }
+
+    #[cfg(unix)]
+    if !cli.dry_run && bypasses_daemon(&cli.command) && daemon_running(&working_dir)? {
+        anyhow::bail!(
+            "jk daemon is serving {}; stop it with `jk daemon --stop` to run this command",
+            working_dir.display()
+        );
+    }
 
     match cli.command {
         // `jk init <path>` targets the positional path when given; otherwise
         // it initialises the working directory.
-        Commands::Init { path } => cmd_init(&path.unwrap_or(working_dir)),
-        Commands::Delete { paths, recursive } => {
-            cmd_delete(&working_dir, &paths, recursive, cli.dry_run, cli.yes)
-        }
-        Commands::Modify { pattern, paths } => {
-            cmd_modify(&working_dir, &pattern, &paths, cli.dry_run, cli.yes)
-        }
+        Commands::Init { path, storage } => cmd_init(&path.unwrap_or(working_dir), storage),
+        Commands::Delete {
+            paths,
+            recursive,
+            exclude,
+        } => cmd_delete(
+            &working_dir,
+            &paths,
+            recursive,
+            &exclude,
+            cli.dry_run,
+            cli.yes,
+            cli.no_history,
+        ),
+        Commands::Modify { args } => match &args.stdin {
+            Some(path) => cmd_modify_stdin(&working_dir, path, cli.dry_run, cli.no_history),
+            None => cmd_modify(&working_dir, args, cli.dry_run, cli.yes, cli.no_history),
+        },
         Commands::Move {
-            source,
+            sources,
             destination,
-        } => cmd_move(&working_dir, &source, &destination, cli.dry_run),
+        } => cmd_transfer(
+            &working_dir,
+            Transfer::Move,
+            &sources,
+            &destination,
+            None,
+            cli.dry_run,
+        ),
         Commands::Copy {
-            source,
+            sources,
             destination,
-        } => cmd_copy(&working_dir, &source, &destination, cli.dry_run),
-        Commands::Rename { old_name, new_name } => cmd_move(
+            preserve,
+        } => cmd_transfer(
+            &working_dir,
+            Transfer::Copy,
+            &sources,
+            &destination,
+            preserve,
+            cli.dry_run,
+        ),
+        Commands::Create {
+            path,
+            content,
+            file,
+            mode,
+            parents,
+        } => cmd_create(
+            &working_dir,
+            &path,
+            content,
+            file.as_deref(),
+            mode.as_deref(),
+            parents,
+            cli.dry_run,
+        ),
+        Commands::Chmod {
+            mode,
+            paths,
+            recursive,
+            exclude,
+        } => cmd_chmod(
+            &working_dir,
+            &mode,
+            &paths,
+            recursive,
+            &exclude,
+            cli.dry_run,
+        ),
+        Commands::Rename { old_name, new_name } => cmd_transfer(
             &working_dir,
-            &old_name.to_string_lossy(),
+            Transfer::Move,
+            &[glob::Pattern::escape(&old_name.to_string_lossy())],
             &new_name,
+            None,
             cli.dry_run,
         ),
-        Commands::Obliterate { paths } => {
-            cmd_obliterate(&working_dir, &paths, cli.dry_run, cli.yes)
+        Commands::RenameBulk {
+            paths,
+            pattern,
+            recursive,
+            exclude,
+        } => cmd_rename_bulk(
+            &working_dir,
+            &paths,
+            &pattern,
+            recursive,
+            &exclude,
+            cli.dry_run,
+            cli.yes,
+        ),
+        Commands::Obliterate {
+            schedule: Some(age),
+            path: Some(pattern),
+            reason,
+            legal_basis,
+            ..
+        } => cmd_obliterate_schedule(&working_dir, &age, &pattern, reason, legal_basis),
+        Commands::Obliterate {
+            run_due: true,
+            override_hold,
+            dependents,
+            ..
+        } => cmd_obliterate_due(
+            &working_dir,
+            override_hold,
+            dependents,
+            cli.dry_run,
+            cli.yes,
+            cli.json,
+        ),
+        Commands::Obliterate { pending: true, .. } => cmd_obliterate_pending(&working_dir),
+        Commands::Obliterate {
+            paths,
+            override_hold,
+            ..
+        } => cmd_obliterate(&working_dir, &paths, override_hold, cli.dry_run, cli.yes),
+        Commands::Undo { args } if args.interactive => cmd_undo_interactive(
+            &working_dir,
+            args.count.unwrap_or(INTERACTIVE_UNDO_COUNT),
+            args.force,
+            cli.dry_run,
+        ),
+        Commands::Undo { args } if args.path.is_some() || args.since.is_some() => {
+            cmd_undo_path(&working_dir, args, cli.yes, cli.dry_run)
         }
-        Commands::Undo { count, id } => cmd_undo(&working_dir, count, id),
+        Commands::Undo { args } => cmd_undo(
+            &working_dir,
+            args.count.unwrap_or(1),
+            args.id,
+            args.cascade,
+            args.force,
+            cli.dry_run,
+        ),
         Commands::Begin { name } => cmd_begin(&working_dir, name),
         Commands::Commit => cmd_commit(&working_dir),
-        Commands::Rollback => cmd_rollback(&working_dir),
-        Commands::Preview => cmd_preview(&working_dir),
-        Commands::History { limit, filter } => cmd_history(&working_dir, limit, filter),
+        Commands::Rollback { resume, id, force } => {
+            cmd_rollback(&working_dir, resume, id.as_deref(), force, cli.dry_run)
+        }
+        Commands::Preview { diff, stat } => cmd_preview(&working_dir, diff, stat),
+        Commands::Hold {
+            action: HoldAction::Add { path, case, reason },
+        } => cmd_hold_add(&working_dir, &path, &case, reason),
+        Commands::Hold {
+            action: HoldAction::List { all },
+        } => cmd_hold_list(&working_dir, all, cli.json),
+        Commands::Hold {
+            action: HoldAction::Release { case },
+        } => cmd_hold_release(&working_dir, &case),
+        Commands::Index {
+            action: IndexAction::Rebuild,
+        } => cmd_index_rebuild(&working_dir, cli.dry_run),
+        Commands::Index {
+            action: IndexAction::Status,
+        } => cmd_index_status(&working_dir, cli.json),
+        Commands::Tx {
+            action: TxAction::List { limit },
+        } => cmd_tx_list(&working_dir, limit, cli.json),
+        Commands::Tx {
+            action: TxAction::Show { id },
+        } => cmd_tx_show(&working_dir, &id, cli.json),
+        Commands::History {
+            action: Some(HistoryAction::Export { args }),
+            ..
+        } => cmd_history_export(&working_dir, args),
+        Commands::History {
+            action:
+                Some(HistoryAction::VerifyExport {
+                    file,
+                    key,
+                    identity,
+                }),
+            ..
+        } => cmd_history_verify_export(&working_dir, &file, key, identity),
+        Commands::History { action: None, args } => match args.graph {
+            Some(format) => cmd_history_graph(&working_dir, &args, format),
+            None => cmd_history(&working_dir, args),
+        },
+        Commands::Show { id } => cmd_show(&working_dir, &id, cli.json),
+        Commands::Timeline {
+            path,
+            restore,
+            diff,
+        } => cmd_timeline(&working_dir, &path, restore, diff, cli.dry_run),
+        Commands::Blame { path } => cmd_blame(&working_dir, &path, cli.json),
+        Commands::Search {
+            pattern,
+            regex,
+            ignore_case,
+            reindex,
+        } => cmd_search(
+            &working_dir,
+            pattern.as_deref(),
+            regex,
+            ignore_case,
+            reindex,
+            cli.json,
+        ),
+        Commands::Snapshot {
+            action: Some(SnapshotAction::List),
+            ..
+        } => cmd_snapshot_list(&working_dir, cli.json),
+        Commands::Snapshot {
+            action: Some(SnapshotAction::Diff { from, to }),
+            ..
+        } => cmd_snapshot_diff(&working_dir, &from, &to, cli.json),
+        Commands::Snapshot {
+            action: None,
+            name,
+            exclude,
+            incremental,
+        } => cmd_snapshot(
+            &working_dir,
+            name.as_deref(),
+            &exclude,
+            incremental,
+            cli.dry_run,
+        ),
+        Commands::RestoreSnapshot {
+            name,
+            exclude,
+            force,
+        } => cmd_restore_snapshot(&working_dir, &name, &exclude, force, cli.dry_run, cli.yes),
+        Commands::Backup { output } => cmd_backup(&working_dir, &output, cli.dry_run),
+        Commands::RestoreStore { archive, force } => {
+            cmd_restore_store(&working_dir, &archive, force, cli.dry_run)
+        }
+        Commands::Sign { file, key, output } => {
+            cmd_sign(&working_dir, &file, &key, output.as_deref(), cli.dry_run)
+        }
+        Commands::Verify {
+            file,
+            signature,
+            key,
+        } => cmd_verify(&working_dir, &file, &signature, key.as_deref()),
+        Commands::Keys { command } => {
+            keys_cli::run(&working_dir, cli.json, command).map_err(|e| anyhow::anyhow!("{}", e))
+        }
         Commands::Status => cmd_status(&working_dir),
-        Commands::Gc { keep, older_than } => cmd_gc(&working_dir, keep, older_than),
+        Commands::Stats { top } => cmd_stats(&working_dir, top, cli.json),
+        Commands::Daemon {
+            stop,
+            intercept,
+            fail_closed,
+        } => cmd_daemon(&working_dir, stop, intercept.then_some(fail_closed)),
+        Commands::Serve { dirs, bind } => cmd_serve(&working_dir, dirs, &bind),
+        Commands::Gc {
+            keep,
+            older_than,
+            keep_latest,
+            compact,
+        } => cmd_gc(
+            &working_dir,
+            keep,
+            older_than.as_deref(),
+            keep_latest,
+            compact,
+            cli.dry_run,
+        ),
+        Commands::Compact => cmd_compact(&working_dir),
+        Commands::Tier { recall } => cmd_tier(&working_dir, recall, cli.dry_run),
+        Commands::Remote {
+            action: RemoteAction::Add { name, uri },
+        } => cmd_remote_add(&working_dir, &name, &uri),
+        Commands::Remote {
+            action: RemoteAction::Remove { name },
+        } => cmd_remote_remove(&working_dir, &name),
+        Commands::Remote {
+            action: RemoteAction::List,
+        } => cmd_remote_list(&working_dir, cli.json),
+        Commands::Token {
+            action: TokenAction::Add { name, role },
+        } => cmd_token_add(&working_dir, &name, role, cli.json),
+        Commands::Token {
+            action: TokenAction::Remove { name },
+        } => cmd_token_remove(&working_dir, &name),
+        Commands::Token {
+            action: TokenAction::List,
+        } => cmd_token_list(&working_dir, cli.json),
+        Commands::Config {
+            action: ConfigAction::Get { key },
+        } => cmd_config_get(&working_dir, &key, cli.json),
+        Commands::Config {
+            action: ConfigAction::Set { key, value },
+        } => cmd_config_set(&working_dir, &key, &value),
+        Commands::Config {
+            action: ConfigAction::List,
+        } => cmd_config_list(&working_dir, cli.json),
+        Commands::Workspace {
+            action: WorkspaceAction::List,
+        } => cmd_workspace_list(&working_dir, cli.json),
+        Commands::Workspace {
+            action: WorkspaceAction::Join { id },
+        } => cmd_workspace_join(&working_dir, id),
+        Commands::Push { remote } => cmd_sync(
+            &working_dir,
+            remote,
+            SyncDirection::Push,
+            cli.dry_run,
+            cli.json,
+        ),
+        Commands::Pull { remote } => cmd_sync(
+            &working_dir,
+            remote,
+            SyncDirection::Pull,
+            cli.dry_run,
+            cli.json,
+        ),
+        Commands::Heal => cmd_heal(&working_dir, cli.dry_run),
+        Commands::Doctor => cmd_doctor(&working_dir, cli.json),
+        Commands::Shell => shell::cmd_shell(&working_dir),
+        Commands::Completions { shell } => complete::cmd_completions(shell),
+        Commands::Complete { words } => complete::cmd_complete(&working_dir, &words),
+        Commands::VerifyRestore { id } => cmd_verify_restore(&working_dir, id.as_deref()),
     }
 }
 
-fn cmd_init(dir: &PathBuf) -> Result<()> {
+fn cmd_init(dir: &Path, storage: Option<String>) -> Result<()> {
     if JanusKey::is_initialized(dir) {
         println!(
             "{} JanusKey already initialized in {}",
@@ -227,9 +1376,17 @@ fn cmd_init(dir: &PathBuf) -> Result<()> {
         return Ok(());
     }
 
-    JanusKey::init(dir).context("Failed to initialize JanusKey")?;
+    let mut config = Config::load(dir)?;
+    if let Some(storage) = storage {
+        StorageUri::parse(&storage)?;
+        config.storage_path = Some(storage);
+    }
+    let jk = JanusKey::init_with_config(dir, config).context("Failed to initialize JanusKey")?;
     println!("{} JanusKey initialized in {}", "✓".green(), dir.display());
-    println!("  Metadata stored in: {}/.januskey/", dir.display());
+    match jk.config.storage_uri()? {
+        Some(uri) => println!("  Metadata stored in: {}", uri),
+        None => println!("  Metadata stored in: {}/.januskey/", dir.display()),
+    }
     println!("\n  You can now use reversible file operations:");
     println!("    jk delete <files>    - Delete files (reversible)");
     println!("    jk modify <pattern> <files> - Modify files (reversible)");
@@ -239,37 +1396,26 @@ fn cmd_init(dir: &PathBuf) -> Result<()> {
 }
 
 fn cmd_delete(
-    dir: &PathBuf,
+    dir: &Path,
     paths: &[String],
     recursive: bool,
+    exclude: &[String],
     dry_run: bool,
     auto_yes: bool,
+    no_history: bool,
 ) -> Result<()> {
-    let mut jk = JanusKey::open(dir).context("Failed to open JanusKey directory")?;
+    let mut repo = Repo::open(dir)?;
 
     // Expand glob patterns and collect files
-    let mut files_to_delete = Vec::new();
-    for pattern in paths {
-        let full_pattern = dir.join(pattern);
-        let pattern_str = full_pattern.to_string_lossy();
-        for entry in glob::glob(&pattern_str)? {
-            let path = entry?;
-            if path.is_file() {
-                files_to_delete.push(path);
-            } else if path.is_dir() && recursive {
-                // Collect all files in directory
-                for entry in walkdir::WalkDir::new(&path) {
-                    let entry = entry?;
-                    if entry.file_type().is_file() {
-                        files_to_delete.push(entry.path().to_path_buf());
-                    }
-                }
-            }
-        }
-    }
+    // Links are deleted themselves, never what they point to
+    let selection = select_files(dir, paths, recursive, &Excludes::new(exclude)?)?;
+    let files_to_delete: Vec<PathBuf> =
+        selection.files.into_iter().chain(selection.links).collect();
+    refuse_guarded(dir, &files_to_delete, januskey::PathGuard::check_removal)?;
 
     if files_to_delete.is_empty() {
         println!("{} No files matched the pattern(s)", "!".yellow());
+        print_excluded(&selection.excluded);
         return Ok(());
     }
 
@@ -279,33 +1425,27 @@ fn cmd_delete(
         for file in &files_to_delete {
             println!("  - {}", file.display());
         }
+        print_excluded(&selection.excluded);
         return Ok(());
     }
 
-    // Confirm if many files
-    if files_to_delete.len() > 10 && !auto_yes {
-        println!(
-            "{} This will delete {} files:",
-            "⚠".yellow(),
-            files_to_delete.len()
-        );
-        for file in files_to_delete.iter().take(5) {
-            println!("  - {}", file.display());
-        }
-        if files_to_delete.len() > 5 {
-            println!("  ... and {} more", files_to_delete.len() - 5);
-        }
-        if !Confirm::new()
-            .with_prompt("Continue?")
-            .default(false)
-            .interact()?
-        {
-            println!("{}", "Cancelled".red());
-            return Ok(());
-        }
+    // Confirm if the confirmation policy asks for it
+    let changes: Vec<_> = files_to_delete
+        .iter()
+        .map(|file| (OperationType::Delete, file))
+        .collect();
+    let summary = format!("This will delete {} files:", files_to_delete.len());
+    if !auto_yes && !confirm_changes(dir, &changes, &summary)? {
+        println!("{}", "Cancelled".red());
+        return Ok(());
     }
 
-    let transaction_id = jk.transaction_manager.active_id().map(String::from);
+    let size: u64 = files_to_delete
+        .iter()
+        .filter_map(|f| f.symlink_metadata().ok())
+        .map(|m| m.len())
+        .sum();
+    prepare_capture(&mut repo, size, no_history)?;
 
     // Progress bar for multiple files
     let progress = if files_to_delete.len() > 1 {
@@ -323,13 +1463,9 @@ fn cmd_delete(
 
     let mut deleted_count = 0;
     for path in &files_to_delete {
-        let mut executor = OperationExecutor::new(&jk.content_store, &mut jk.metadata_store);
-        if let Some(ref tid) = transaction_id {
-            executor = executor.with_transaction(tid.clone());
-        }
-
-        match executor.execute(FileOperation::Delete { path: path.clone() }) {
-            Ok(meta) => {
+        let operation = FileOperation::Delete { path: path.clone() };
+        match repo.execute(operation, no_history) {
+            Ok(_) => {
                 deleted_count += 1;
                 if let Some(ref pb) = progress {
                     pb.inc(1);
@@ -338,10 +1474,6 @@ fn cmd_delete(
                         path.file_name().unwrap_or_default().to_string_lossy()
                     ));
                 }
-                // Record in transaction if active
-                if transaction_id.is_some() {
-                    jk.transaction_manager.add_operation(meta.id)?;
-                }
             }
             Err(e) => {
                 eprintln!("{} Failed to delete {}: {}", "✗".red(), path.display(), e);
@@ -354,38 +1486,43 @@ fn cmd_delete(
     }
 
     println!("{} Deleted {} file(s)", "✓".green(), deleted_count);
-    println!("  Use {} to restore", "jk undo".cyan());
+    if !no_history {
+        println!("  Use {} to restore", "jk undo".cyan());
+    }
 
     Ok(())
 }
 
 fn cmd_modify(
-    dir: &PathBuf,
-    pattern: &str,
-    paths: &[String],
+    dir: &Path,
+    args: ModifyArgs,
     dry_run: bool,
     auto_yes: bool,
+    no_history: bool,
 ) -> Result<()> {
-    let mut jk = JanusKey::open(dir).context("Failed to open JanusKey directory")?;
+    let ModifyArgs {
+        pattern,
+        paths,
+        exclude,
+        interactive,
+        ..
+    } = args;
+    let Some(pattern) = pattern else {
+        unreachable!("clap requires a pattern without --stdin")
+    };
+    let mut repo = Repo::open(dir)?;
 
-    // Parse sed-like pattern: s/old/new/g
-    let (search, replace, global) = parse_sed_pattern(pattern)?;
+    let script = load_edit_script(&pattern)?;
 
     // Expand glob patterns
-    let mut files = Vec::new();
-    for p in paths {
-        let full_pattern = dir.join(p);
-        let pattern_str = full_pattern.to_string_lossy();
-        for entry in glob::glob(&pattern_str)? {
-            let path = entry?;
-            if path.is_file() {
-                files.push(path);
-            }
-        }
-    }
+    let selection = select_files(dir, &paths, false, &Excludes::new(&exclude)?)?;
+    let files = selection.files;
+    refuse_guarded(dir, &files, januskey::PathGuard::check)?;
 
     if files.is_empty() {
         println!("{} No files matched the pattern(s)", "!".yellow());
+        print_excluded(&selection.excluded);
+        print_links(&selection.links);
         return Ok(());
     }
 
@@ -394,21 +1531,20 @@ fn cmd_modify(
     for file in &files {
         let content = ({
             use std::io::Read;
-            std::fs::File::open(file).and_then(|mut f| {
+            std::fs::File::open(file).and_then(|f| {
                 let mut buf = String::new();
                 f.take(10 * 1024 * 1024).read_to_string(&mut buf)?;
                 Ok(buf)
             })
         })?;
-        let new_content = if global {
-            content.replace(&search, &replace)
-        } else {
-            content.replacen(&search, &replace, 1)
-        };
+        let new_content = script.apply(&content);
         if content != new_content {
-            changes.push((file.clone(), new_content));
+            changes.push((file.clone(), content, new_content));
         }
     }
+    if interactive {
+        changes = choose_hunks(dir, changes)?;
+    }
 
     if changes.is_empty() {
         println!("{} No changes would be made", "!".yellow());
@@ -417,553 +1553,4478 @@ fn cmd_modify(
 
     if dry_run {
         println!("{} Dry run - would modify:", "[DRY RUN]".cyan());
-        for (file, _) in &changes {
+        for (file, _, _) in &changes {
             println!("  - {}", file.display());
         }
+        print_excluded(&selection.excluded);
+        print_links(&selection.links);
         return Ok(());
     }
 
-    // Confirm
-    if changes.len() > 5 && !auto_yes {
-        println!("{} This will modify {} files", "⚠".yellow(), changes.len());
-        if !Confirm::new()
-            .with_prompt("Continue?")
-            .default(false)
-            .interact()?
-        {
-            println!("{}", "Cancelled".red());
-            return Ok(());
-        }
+    // Confirm, unless each hunk was just chosen
+    let planned: Vec<_> = changes
+        .iter()
+        .map(|(path, _, _)| (OperationType::Modify, path))
+        .collect();
+    let summary = format!("This will modify {} files:", changes.len());
+    if !auto_yes && !interactive && !confirm_changes(dir, &planned, &summary)? {
+        println!("{}", "Cancelled".red());
+        return Ok(());
     }
 
-    let transaction_id = jk.transaction_manager.active_id().map(String::from);
-
-    for (file, new_content) in changes {
-        let mut executor = OperationExecutor::new(&jk.content_store, &mut jk.metadata_store);
-        if let Some(ref tid) = transaction_id {
-            executor = executor.with_transaction(tid.clone());
-        }
+    let size: u64 = changes
+        .iter()
+        .filter_map(|(f, _, _)| f.metadata().ok())
+        .map(|m| m.len())
+        .sum();
+    prepare_capture(&mut repo, size, no_history)?;
 
-        match executor.execute(FileOperation::Modify {
+    for (file, _, new_content) in changes {
+        let operation = FileOperation::Modify {
             path: file.clone(),
             new_content: new_content.into_bytes(),
-        }) {
-            Ok(meta) => {
-                println!("  {} {}", "✓".green(), file.display());
-                if transaction_id.is_some() {
-                    jk.transaction_manager.add_operation(meta.id)?;
-                }
-            }
+        };
+        match repo.execute(operation, no_history) {
+            Ok(_) => println!("  {} {}", "✓".green(), file.display()),
             Err(e) => {
                 eprintln!("  {} {}: {}", "✗".red(), file.display(), e);
             }
         }
     }
 
-    println!("  Use {} to restore original content", "jk undo".cyan());
+    if !no_history {
+        println!("  Use {} to restore original content", "jk undo".cyan());
+    }
 
     Ok(())
 }
 
-fn parse_sed_pattern(pattern: &str) -> Result<(String, String, bool)> {
-    // Parse s/old/new/g pattern
-    if !pattern.starts_with("s/") {
-        anyhow::bail!("Pattern must be in format: s/search/replace/[g]");
+/// Ask hunk by hunk which of the planned `(file, content, new content)`
+/// changes to make, like `git add -p`. Returns the changes with only the
+/// accepted hunks applied, dropping files left unchanged.
+fn choose_hunks(
+    dir: &Path,
+    changes: Vec<(PathBuf, String, String)>,
+) -> Result<Vec<(PathBuf, String, String)>> {
+    use std::io::IsTerminal;
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!("--interactive needs a terminal to ask about each hunk");
     }
-
-    let rest = &pattern[2..];
-    let parts: Vec<&str> = rest.split('/').collect();
-
-    if parts.len() < 2 {
-        anyhow::bail!("Invalid pattern format. Use: s/search/replace/[g]");
+    let choices = [
+        "y - apply this hunk",
+        "n - skip this hunk",
+        "a - apply this and the rest of the file's hunks",
+        "d - skip this and the rest of the file's hunks",
+        "q - quit, skipping every remaining hunk",
+    ];
+
+    let mut chosen = Vec::new();
+    let mut quit = false;
+    for (file, content, new_content) in changes {
+        let diff = line_diff(&content, &new_content);
+        let mut accepted = Vec::with_capacity(diff.hunks.len());
+        let mut rest = None;
+        for (i, hunk) in diff.hunks.iter().enumerate() {
+            if let Some(all) = rest.or(quit.then_some(false)) {
+                accepted.push(all);
+                continue;
+            }
+            println!(
+                "{} ({}/{})",
+                file.strip_prefix(dir)
+                    .unwrap_or(&file)
+                    .display()
+                    .to_string()
+                    .bold(),
+                i + 1,
+                diff.hunks.len()
+            );
+            print_hunk(hunk);
+            let choice = Select::new()
+                .with_prompt("Apply this hunk?")
+                .items(&choices)
+                .default(0)
+                .interact()?;
+            match choice {
+                0 | 1 => accepted.push(choice == 0),
+                2 | 3 => {
+                    rest = Some(choice == 2);
+                    accepted.push(choice == 2);
+                }
+                _ => {
+                    quit = true;
+                    accepted.push(false);
+                }
+            }
+        }
+        let new_content = apply_hunks(&content, &new_content, &diff, &accepted);
+        if new_content != content {
+            chosen.push((file, content, new_content));
+        }
     }
+    Ok(chosen)
+}
 
-    let search = parts[0].to_string();
-    let replace = parts[1].to_string();
-    let global = parts.get(2).map_or(false, |f| f.contains('g'));
+/// A script file if `pattern` names one, otherwise a single command
+fn load_edit_script(pattern: &str) -> Result<EditScript> {
+    let script_path = Path::new(pattern);
+    if script_path.is_file() {
+        let script = std::fs::read_to_string(script_path)
+            .with_context(|| format!("Failed to read script {}", pattern))?;
+        let script =
+            EditScript::parse(&script).with_context(|| format!("Invalid script {}", pattern))?;
+        if script.commands.is_empty() {
+            anyhow::bail!("Script {} has no commands", pattern);
+        }
+        return Ok(script);
+    }
 
-    Ok((search, replace, global))
+    match EditCommand::parse(pattern) {
+        Ok(command) => Ok(EditScript {
+            commands: vec![command],
+        }),
+        Err(e) => anyhow::bail!(
+            "Invalid pattern ({}). Use s/search/replace/[g], <addr>d, \
+             <addr>a text, <addr>i text or a script path",
+            e
+        ),
+    }
 }
 
-fn cmd_move(dir: &PathBuf, source: &str, destination: &PathBuf, dry_run: bool) -> Result<()> {
-    let mut jk = JanusKey::open(dir).context("Failed to open JanusKey directory")?;
-
-    let source_path = if PathBuf::from(source).is_absolute() {
-        PathBuf::from(source)
-    } else {
-        dir.join(source)
-    };
+/// Replace a file's content with standard input, as one Modify operation
+fn cmd_modify_stdin(dir: &Path, path: &Path, dry_run: bool, no_history: bool) -> Result<()> {
+    let mut repo = Repo::open(dir)?;
+    let file = dir.join(path);
+    if !file.is_file() {
+        anyhow::bail!("Not a file: {}", file.display());
+    }
 
-    let dest_path = if destination.is_absolute() {
-        destination.clone()
-    } else {
-        dir.join(destination)
-    };
+    let mut new_content = Vec::new();
+    std::io::Read::read_to_end(&mut std::io::stdin(), &mut new_content)
+        .context("Failed to read standard input")?;
+    let old_len = std::fs::metadata(&file)?.len();
+    if std::fs::read(&file)? == new_content {
+        println!("{} No changes would be made", "!".yellow());
+        return Ok(());
+    }
 
     if dry_run {
         println!(
-            "{} Would move {} -> {}",
+            "{} Dry run - would replace {} ({} -> {})",
             "[DRY RUN]".cyan(),
-            source_path.display(),
-            dest_path.display()
+            file.display(),
+            human_bytes(old_len),
+            human_bytes(new_content.len() as u64)
         );
         return Ok(());
     }
 
-    let transaction_id = jk.transaction_manager.active_id().map(String::from);
-    let mut executor = OperationExecutor::new(&jk.content_store, &mut jk.metadata_store);
-    if let Some(ref tid) = transaction_id {
-        executor = executor.with_transaction(tid.clone());
-    }
-
-    let meta = executor.execute(FileOperation::Move {
-        source: source_path.clone(),
-        destination: dest_path.clone(),
-    })?;
+    prepare_capture(&mut repo, old_len, no_history)?;
+    let operation = FileOperation::Modify {
+        path: file.clone(),
+        new_content,
+    };
+    repo.execute(operation, no_history)?;
 
-    if transaction_id.is_some() {
-        jk.transaction_manager.add_operation(meta.id)?;
+    println!("  {} {}", "✓".green(), file.display());
+    if !no_history {
+        println!("  Use {} to restore original content", "jk undo".cyan());
     }
 
-    println!(
-        "{} Moved {} -> {}",
-        "✓".green(),
-        source_path.display(),
-        dest_path.display()
-    );
-    println!("  Use {} to move back", "jk undo".cyan());
-
     Ok(())
 }
 
-fn cmd_copy(dir: &PathBuf, source: &PathBuf, destination: &PathBuf, dry_run: bool) -> Result<()> {
-    let mut jk = JanusKey::open(dir).context("Failed to open JanusKey directory")?;
-
-    let source_path = if source.is_absolute() {
-        source.clone()
-    } else {
-        dir.join(source)
+/// Create a file from `--content`, `--file` or standard input, as one
+/// Create operation
+fn cmd_create(
+    dir: &Path,
+    path: &Path,
+    content: Option<String>,
+    file: Option<&Path>,
+    mode: Option<&str>,
+    parents: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let target = dir.join(path);
+    if std::fs::symlink_metadata(&target).is_ok() {
+        anyhow::bail!("Already exists: {}", target.display());
+    }
+    let parent = target.parent().unwrap_or(dir);
+    if !parents && !parent.is_dir() {
+        anyhow::bail!(
+            "No such directory: {} (use --parents to create it)",
+            parent.display()
+        );
+    }
+    let mode = match mode.map(str::parse).transpose()? {
+        None => None,
+        Some(januskey::Mode::Octal(bits)) => Some(bits),
+        Some(januskey::Mode::Symbolic(_)) => {
+            anyhow::bail!("--mode takes an octal mode, such as 600")
+        }
     };
 
-    let dest_path = if destination.is_absolute() {
-        destination.clone()
-    } else {
-        dir.join(destination)
+    let content = match (content, file) {
+        (Some(content), _) => content.into_bytes(),
+        (None, Some(file)) => std::fs::read(dir.join(file))
+            .with_context(|| format!("Failed to read {}", file.display()))?,
+        (None, None) => {
+            let mut content = Vec::new();
+            std::io::Read::read_to_end(&mut std::io::stdin(), &mut content)
+                .context("Failed to read standard input")?;
+            content
+        }
     };
 
     if dry_run {
         println!(
-            "{} Would copy {} -> {}",
+            "{} Dry run - would create {} ({})",
             "[DRY RUN]".cyan(),
-            source_path.display(),
-            dest_path.display()
+            target.display(),
+            human_bytes(content.len() as u64)
         );
         return Ok(());
     }
 
-    let transaction_id = jk.transaction_manager.active_id().map(String::from);
-    let mut executor = OperationExecutor::new(&jk.content_store, &mut jk.metadata_store);
-    if let Some(ref tid) = transaction_id {
-        executor = executor.with_transaction(tid.clone());
+    let mut repo = Repo::open(dir)?;
+    let operation = FileOperation::Create {
+        path: target.clone(),
+        content,
+        mode,
+    };
+    repo.execute(operation, false)?;
+
+    println!("  {} {}", "✓".green(), target.display());
+    println!("  Use {} to remove it", "jk undo".cyan());
+
+    Ok(())
+}
+
+/// Refuse a selection reaching into `.januskey/` or another protected
+/// path, or outside a confined root, before changing anything, rather
+/// than failing file by file. `check` is [`januskey::PathGuard::check`],
+/// or `check_removal` for files being removed.
+fn refuse_guarded(
+    dir: &Path,
+    files: &[PathBuf],
+    check: fn(&januskey::PathGuard, &Path) -> januskey::Result<()>,
+) -> Result<()> {
+    let guard = januskey::PathGuard::for_root(dir, &Config::load(dir)?);
+    for file in files {
+        check(&guard, file)?;
     }
+    Ok(())
+}
 
-    let meta = executor.execute(FileOperation::Copy {
-        source: source_path.clone(),
-        destination: dest_path.clone(),
-    })?;
+/// List paths that matched but were excluded with --exclude
+fn print_excluded(excluded: &[PathBuf]) {
+    if excluded.is_empty() {
+        return;
+    }
+    println!("  Excluded ({}):", excluded.len());
+    for path in excluded {
+        println!("  {} {}", "-".dimmed(), path.display().to_string().dimmed());
+    }
+}
 
-    if transaction_id.is_some() {
-        jk.transaction_manager.add_operation(meta.id)?;
+/// List symbolic links that matched but were skipped, as changing them
+/// would change what they point to
+fn print_links(links: &[PathBuf]) {
+    if links.is_empty() {
+        return;
     }
+    println!("  Skipped symbolic links ({}):", links.len());
+    for path in links {
+        println!("  {} {}", "-".dimmed(), path.display().to_string().dimmed());
+    }
+}
 
-    println!(
-        "{} Copied {} -> {}",
-        "✓".green(),
-        source_path.display(),
-        dest_path.display()
-    );
-    println!("  Use {} to delete the copy", "jk undo".cyan());
+/// Whether `changes` may go ahead, asking on the terminal if the
+/// confirmation policy says to. `summary` heads the prompt.
+fn confirm_changes<P: AsRef<Path>>(
+    dir: &Path,
+    changes: &[(OperationType, P)],
+    summary: &str,
+) -> Result<bool> {
+    confirm_described(dir, changes, summary, |path| path.display().to_string())
+}
 
-    Ok(())
+/// As [`confirm_changes`], listing each change in the prompt as `describe`
+/// puts it
+fn confirm_described<P: AsRef<Path>>(
+    dir: &Path,
+    changes: &[(OperationType, P)],
+    summary: &str,
+    describe: impl Fn(&Path) -> String,
+) -> Result<bool> {
+    let policy = JanusKey::confirmation_policy_for(dir)?;
+    let mut prompt = |request: &ConfirmationRequest| -> januskey::Result<bool> {
+        println!("{} {}", "⚠".yellow(), summary);
+        for (_, path) in request.changes.iter().take(5) {
+            println!("  - {}", describe(path));
+        }
+        if request.changes.len() > 5 {
+            println!("  ... and {} more", request.changes.len() - 5);
+        }
+        for reason in &request.reasons {
+            println!("  {} {}", "!".yellow(), reason);
+        }
+        Confirm::new()
+            .with_prompt("Continue?")
+            .default(false)
+            .interact()
+            .map_err(|e| JanusError::OperationFailed(format!("confirmation prompt: {}", e)))
+    };
+    Ok(policy.confirm(changes, &mut prompt)?)
 }
 
-fn cmd_obliterate(dir: &PathBuf, paths: &[PathBuf], dry_run: bool, auto_yes: bool) -> Result<()> {
-    use januskey::obliteration::obliterate_file;
+/// Make room in the content store for `bytes` of original content, or
+/// warn that nothing will be kept with --no-history
+fn prepare_capture(repo: &mut Repo, bytes: u64, no_history: bool) -> Result<()> {
+    if no_history {
+        println!(
+            "{} --no-history: original content is not kept; this cannot be undone",
+            "!".yellow()
+        );
+        return Ok(());
+    }
+    repo.ensure_capacity(bytes)
+        .context("Content store is full: run `jk gc`, raise max_store_bytes, or pass --no-history")
+}
 
-    // Resolve each path against the working directory if it is relative.
-    let targets: Vec<PathBuf> = paths
-        .iter()
-        .map(|p| {
-            if p.is_absolute() {
-                p.clone()
-            } else {
-                dir.join(p)
-            }
-        })
-        .collect();
+/// The store a command works on: opened by this process, or reached
+/// through `jk daemon` while it serves the directory
+enum Repo {
+    Local(Box<shell::SessionStore>),
+    #[cfg(unix)]
+    Daemon(daemon::Client),
+}
 
-    if dry_run {
-        println!("{} Dry run - would obliterate:", "[DRY RUN]".cyan());
-        for t in &targets {
-            println!("  - {}", t.display());
+impl Repo {
+    fn open(dir: &Path) -> Result<Self> {
+        #[cfg(unix)]
+        if let Some(client) = daemon::Client::connect(dir)? {
+            return Ok(Self::Daemon(client));
         }
-        return Ok(());
+        let jk = shell::open(dir)?;
+        Ok(Self::Local(Box::new(jk)))
     }
 
-    // Obliteration is irreversible — confirm unless --yes was given. Refuse
-    // outright (rather than silently auto-confirming) when stdin is not a
-    // terminal and no --yes was supplied, so destructive erasure never runs
-    // unattended without explicit consent.
-    if !auto_yes {
-        use std::io::IsTerminal;
-        if !std::io::stdin().is_terminal() {
-            anyhow::bail!(
-                "refusing to obliterate without confirmation in non-interactive mode; \
-                 pass --yes/-y to confirm"
-            );
+    fn execute(
+        &mut self,
+        operation: FileOperation,
+        no_history: bool,
+    ) -> januskey::Result<OperationMetadata> {
+        match self {
+            Self::Local(jk) if no_history => jk.execute_without_history(operation),
+            Self::Local(jk) => jk.execute(operation),
+            #[cfg(unix)]
+            Self::Daemon(client) => client.execute(operation, no_history),
         }
-        println!(
-            "{} Obliteration is {} — content will be unrecoverable:",
-            "⚠".yellow(),
-            "irreversible".red()
-        );
-        for t in &targets {
-            println!("  - {}", t.display());
+    }
+
+    fn ensure_capacity(&mut self, bytes: u64) -> januskey::Result<()> {
+        match self {
+            Self::Local(jk) => jk.ensure_capacity(bytes),
+            #[cfg(unix)]
+            Self::Daemon(client) => client.ensure_capacity(bytes),
         }
-        if !Confirm::new()
-            .with_prompt("Continue?")
-            .default(false)
-            .interact()?
-        {
-            println!("{}", "Cancelled".red());
-            return Ok(());
+    }
+
+    fn status(&mut self) -> januskey::Result<januskey::StoreStatus> {
+        match self {
+            Self::Local(jk) => jk.status(),
+            #[cfg(unix)]
+            Self::Daemon(client) => client.status(),
         }
     }
 
-    let mut obliterated = 0;
-    for t in &targets {
-        match obliterate_file(t) {
-            Ok(proof) => {
-                obliterated += 1;
-                println!(
-                    "{} Obliterated {} ({} passes, proof {})",
-                    "✓".green(),
-                    t.display(),
-                    proof.overwrite_passes,
-                    &proof.id[..8]
-                );
-            }
-            Err(e) => {
-                eprintln!("{} Failed to obliterate {}: {}", "✗".red(), t.display(), e);
-            }
+    fn begin(&mut self, name: Option<String>) -> januskey::Result<Transaction> {
+        match self {
+            Self::Local(jk) => jk.transaction_manager.begin(name).cloned(),
+            #[cfg(unix)]
+            Self::Daemon(client) => client.begin(name),
+        }
+    }
+
+    fn commit(&mut self) -> januskey::Result<Transaction> {
+        match self {
+            Self::Local(jk) => jk.transaction_manager.commit(),
+            #[cfg(unix)]
+            Self::Daemon(client) => client.commit(),
+        }
+    }
+}
+
+/// Whether a daemon is serving `dir` and answering
+#[cfg(unix)]
+fn daemon_running(dir: &Path) -> Result<bool> {
+    Ok(daemon::Client::connect(dir)?.is_some())
+}
+
+/// Whether `command` would change the store without going through a
+/// running daemon, whose history would then be out of date
+#[cfg(unix)]
+fn bypasses_daemon(command: &Commands) -> bool {
+    match command {
+        Commands::Delete { .. }
+        | Commands::Modify { .. }
+        | Commands::Create { .. }
+        | Commands::Begin { .. }
+        | Commands::Commit
+        | Commands::Status
+        | Commands::Daemon { .. } => false,
+        Commands::Undo { args } => args.interactive || args.path.is_some() || args.since.is_some(),
+        Commands::Rollback { resume, .. } => *resume,
+        // Read only
+        Commands::Preview { .. }
+        | Commands::Tx { .. }
+        | Commands::History { .. }
+        | Commands::Show { .. }
+        | Commands::Stats { .. }
+        | Commands::Blame { .. }
+        | Commands::Search { .. }
+        | Commands::Index { .. }
+        | Commands::Backup { .. }
+        | Commands::Verify { .. }
+        | Commands::Keys { .. }
+        | Commands::Remote { .. }
+        | Commands::Token { .. }
+        | Commands::Config { .. }
+        | Commands::Workspace {
+            action: WorkspaceAction::List,
         }
+        | Commands::Push { .. }
+        | Commands::Doctor
+        | Commands::Shell
+        | Commands::Completions { .. }
+        | Commands::Complete { .. }
+        | Commands::Serve { .. }
+        | Commands::VerifyRestore { .. } => false,
+        Commands::Timeline { restore, .. } => restore.is_some(),
+        Commands::Hold { action } => !matches!(action, HoldAction::List { .. }),
+        Commands::Snapshot { action, .. } => action.is_none(),
+        Commands::Obliterate { pending, .. } => !pending,
+        _ => true,
+    }
+}
+
+/// With `intercept` set to whether to fail closed, changes other processes
+/// make are captured too
+#[cfg(unix)]
+fn cmd_daemon(dir: &Path, stop: bool, intercept: Option<bool>) -> Result<()> {
+    if stop {
+        let Some(mut client) = daemon::Client::connect(dir)? else {
+            anyhow::bail!("No daemon is serving {}", dir.display());
+        };
+        client.shutdown()?;
+        println!("{} Stopped the daemon for {}", "✓".green(), dir.display());
+        return Ok(());
     }
 
+    let server = daemon::Daemon::bind(dir).context("Failed to start the daemon")?;
+    // Undos through the daemon decrypt with keys unlocked now; there is no
+    // terminal to prompt on later
+    if let Ok(passphrase) = std::env::var("JANUSKEY_PASSPHRASE") {
+        server.unlock_content(&passphrase)?;
+    }
+    if let Some(fail_closed) = intercept {
+        #[cfg(target_os = "linux")]
+        server
+            .intercept(fail_closed)
+            .context("Failed to start interception")?;
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = fail_closed;
+            anyhow::bail!("jk daemon --intercept needs Linux fanotify");
+        }
+    }
     println!(
-        "{} Obliterated {} file(s) — erasure is permanent",
+        "{} Serving {} on {}",
         "✓".green(),
-        obliterated
+        dir.display(),
+        server.socket().display()
     );
-
+    if intercept.is_some() {
+        println!("  Capturing changes other processes make to it");
+    }
+    println!("  Stop with {}", "jk daemon --stop".cyan());
+    server.serve()?;
     Ok(())
 }
 
-fn cmd_undo(dir: &PathBuf, count: usize, id: Option<String>) -> Result<()> {
-    let mut jk = JanusKey::open(dir).context("Failed to open JanusKey directory")?;
+#[cfg(not(unix))]
+fn cmd_daemon(_dir: &Path, _stop: bool, _intercept: Option<bool>) -> Result<()> {
+    anyhow::bail!("jk daemon needs Unix domain sockets")
+}
 
-    if let Some(op_id) = id {
-        // Undo specific operation
-        let mut executor = OperationExecutor::new(&jk.content_store, &mut jk.metadata_store);
-        let meta = executor.undo(&op_id)?;
-        println!(
-            "{} Undid {} on {}",
-            "✓".green(),
-            meta.op_type,
-            meta.path.display()
-        );
+/// Serve `dirs` (or `dir`) to dashboards over HTTP until stopped
+fn cmd_serve(dir: &Path, dirs: Vec<PathBuf>, bind: &str) -> Result<()> {
+    if !januskey::server::SUPPORTED {
+        anyhow::bail!("jk serve requires januskey built with the `server` feature");
+    }
+    let dirs = if dirs.is_empty() {
+        vec![dir.to_path_buf()]
     } else {
-        // Undo last N operations
-        let ops_to_undo: Vec<_> = jk
-            .metadata_store
-            .last_n(count)
-            .into_iter()
-            .cloned()
-            .collect();
-
-        if ops_to_undo.is_empty() {
-            println!("{} Nothing to undo", "!".yellow());
+        dirs
+    };
+    let dashboard = januskey::server::Dashboard::new(&dirs)?;
+    let names: Vec<String> = dashboard
+        .repositories()
+        .iter()
+        .map(|repo| repo.name.clone())
+        .collect();
+    let listener = dashboard
+        .listen(bind)
+        .context("Failed to start the server")?;
+    println!(
+        "{} Serving {} on http://{}",
+        "✓".green(),
+        names.join(", "),
+        listener.address()
+    );
+    println!(
+        "  Read-only; e.g. {}",
+        format!("GET /repos/{}/history?limit=20", names[0]).cyan()
+    );
+    listener.serve()?;
+    Ok(())
+}
+
+/// Whether `cmd_transfer` moves or copies
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Transfer {
+    Move,
+    Copy,
+}
+
+/// Move or copy files, mv/cp style: with several sources (or an existing
+/// directory as destination) each file goes into the destination directory.
+/// Each file is its own reversible operation; outside a transaction,
+/// several files are grouped into an implicit one that is rolled back if
+/// any of them fails. Copies carry over `preserve`, by default the
+/// configured `copy_preserve`.
+fn cmd_transfer(
+    dir: &Path,
+    kind: Transfer,
+    sources: &[String],
+    destination: &Path,
+    preserve: Option<BTreeSet<PreservedAttribute>>,
+    dry_run: bool,
+) -> Result<()> {
+    let mut jk = shell::open(dir)?;
+    let preserve = preserve.unwrap_or_else(|| jk.config.copy_preserve.clone());
+
+    let mut source_paths = expand_sources(dir, sources)?;
+    if kind == Transfer::Copy {
+        source_paths.retain(|path| {
+            if path.is_dir() {
+                println!("{} Skipping directory {}", "!".yellow(), path.display());
+            }
+            !path.is_dir()
+        });
+    }
+    if source_paths.is_empty() {
+        anyhow::bail!("No files to {}", verb(kind, false).to_lowercase());
+    }
+
+    let dest_path = dir.join(destination);
+    let into_dir = dest_path.is_dir() || source_paths.len() > 1;
+    if into_dir && !dest_path.is_dir() {
+        anyhow::bail!(
+            "Target {} is not a directory (required for {} sources)",
+            dest_path.display(),
+            source_paths.len()
+        );
+    }
+
+    let mut plan = Vec::new();
+    for source in source_paths {
+        let target = if into_dir {
+            let name = source
+                .file_name()
+                .ok_or_else(|| anyhow::anyhow!("Invalid source {}", source.display()))?;
+            dest_path.join(name)
+        } else {
+            dest_path.clone()
+        };
+        plan.push((source, target));
+    }
+
+    if dry_run {
+        for (source, target) in &plan {
+            println!(
+                "{} Would {} {} -> {}",
+                "[DRY RUN]".cyan(),
+                verb(kind, false).to_lowercase(),
+                source.display(),
+                target.display()
+            );
+        }
+        return Ok(());
+    }
+
+    let implicit = plan.len() > 1 && !jk.transaction_manager.has_active();
+    if implicit {
+        let name = format!("{} {} files", verb(kind, false).to_lowercase(), plan.len());
+        jk.transaction_manager.begin(Some(name))?;
+    }
+
+    for (source, target) in &plan {
+        let operation = match kind {
+            Transfer::Move => FileOperation::Move {
+                source: source.clone(),
+                destination: target.clone(),
+            },
+            Transfer::Copy => FileOperation::Copy {
+                source: source.clone(),
+                destination: target.clone(),
+                preserve: preserve.clone(),
+            },
+        };
+
+        if let Err(e) = jk.execute(operation) {
+            eprintln!(
+                "{} Failed to {} {}: {}",
+                "✗".red(),
+                verb(kind, false).to_lowercase(),
+                source.display(),
+                e
+            );
+            if implicit {
+                let tx = jk.rollback()?;
+                println!(
+                    "{} Rolled back {} completed operations",
+                    "!".yellow(),
+                    tx.operation_ids.len()
+                );
+            }
+            anyhow::bail!("{} failed", verb(kind, false));
+        }
+
+        println!(
+            "{} {} {} -> {}",
+            "✓".green(),
+            verb(kind, true),
+            source.display(),
+            target.display()
+        );
+    }
+
+    if implicit {
+        jk.transaction_manager.commit()?;
+    }
+
+    match (kind, plan.len()) {
+        (Transfer::Move, 1) => println!("  Use {} to move back", "jk undo".cyan()),
+        (Transfer::Copy, 1) => println!("  Use {} to delete the copy", "jk undo".cyan()),
+        (_, n) => println!(
+            "  Use {} to undo each of the {} operations",
+            format!("jk undo --count {}", n).cyan(),
+            n
+        ),
+    }
+
+    Ok(())
+}
+
+/// Rename the selected files as `pattern` rewrites their names, each a
+/// reversible Move. Collisions are reported before anything is renamed;
+/// outside a transaction the renames are grouped into an implicit one
+/// that is rolled back if any of them fails.
+fn cmd_rename_bulk(
+    dir: &Path,
+    paths: &[String],
+    pattern: &str,
+    recursive: bool,
+    exclude: &[String],
+    dry_run: bool,
+    auto_yes: bool,
+) -> Result<()> {
+    let template: januskey::RenameTemplate = pattern.parse()?;
+    let selection = select_files(dir, paths, recursive, &Excludes::new(exclude)?)?;
+    let plan = januskey::RenamePlan::new(&selection.files, &template);
+
+    if !plan.collisions.is_empty() {
+        for collision in &plan.collisions {
+            eprintln!("{} {}", "✗".red(), describe_collision(collision));
+        }
+        anyhow::bail!(
+            "{} collision(s); nothing was renamed",
+            plan.collisions.len()
+        );
+    }
+    if plan.renames.is_empty() {
+        println!("{} No file names would change", "!".yellow());
+        print_excluded(&selection.excluded);
+        print_links(&selection.links);
+        return Ok(());
+    }
+    let moved: Vec<PathBuf> = plan
+        .renames
+        .iter()
+        .flat_map(|rename| [rename.source.clone(), rename.destination.clone()])
+        .collect();
+    refuse_guarded(dir, &moved, januskey::PathGuard::check)?;
+
+    let described: HashMap<&Path, String> = plan
+        .renames
+        .iter()
+        .map(|rename| {
+            let line = format!(
+                "{} -> {}",
+                rename.source.display(),
+                file_name(&rename.destination)
+            );
+            (rename.source.as_path(), line)
+        })
+        .collect();
+    let print_renames = || {
+        for rename in &plan.renames {
+            println!("  - {}", described[rename.source.as_path()]);
+        }
+    };
+
+    if dry_run {
+        println!("{} Dry run - would rename:", "[DRY RUN]".cyan());
+        print_renames();
+        print_excluded(&selection.excluded);
+        print_links(&selection.links);
+        return Ok(());
+    }
+
+    if !auto_yes {
+        println!("Renames ({}):", plan.renames.len());
+        print_renames();
+        let changes: Vec<_> = plan
+            .renames
+            .iter()
+            .map(|rename| (OperationType::Move, &rename.source))
+            .collect();
+        let summary = format!("This will rename {} files:", plan.renames.len());
+        let describe = |path: &Path| {
+            described
+                .get(path)
+                .cloned()
+                .unwrap_or_else(|| path.display().to_string())
+        };
+        if !confirm_described(dir, &changes, &summary, describe)? {
+            println!("{}", "Cancelled".red());
             return Ok(());
         }
+    }
 
-        for op in ops_to_undo {
-            let mut executor = OperationExecutor::new(&jk.content_store, &mut jk.metadata_store);
-            match executor.undo(&op.id) {
-                Ok(_) => {
-                    println!(
-                        "{} Undid {} on {}",
-                        "✓".green(),
-                        op.op_type,
-                        op.path.display()
-                    );
-                }
-                Err(e) => {
-                    eprintln!(
-                        "{} Failed to undo {} on {}: {}",
-                        "✗".red(),
-                        op.op_type,
-                        op.path.display(),
-                        e
-                    );
+    let mut jk = shell::open(dir)?;
+    let implicit = plan.renames.len() > 1 && !jk.transaction_manager.has_active();
+    if implicit {
+        let name = format!("rename-bulk {} {} files", pattern, plan.renames.len());
+        jk.transaction_manager.begin(Some(name))?;
+    }
+
+    for rename in &plan.renames {
+        let operation = FileOperation::Move {
+            source: rename.source.clone(),
+            destination: rename.destination.clone(),
+        };
+        if let Err(e) = jk.execute(operation) {
+            eprintln!(
+                "{} Failed to rename {}: {}",
+                "✗".red(),
+                rename.source.display(),
+                e
+            );
+            if implicit {
+                let tx = jk.rollback()?;
+                println!(
+                    "{} Rolled back {} completed operations",
+                    "!".yellow(),
+                    tx.operation_ids.len()
+                );
+            }
+            anyhow::bail!("Rename failed");
+        }
+        println!(
+            "{} Renamed {} -> {}",
+            "✓".green(),
+            rename.source.display(),
+            file_name(&rename.destination)
+        );
+    }
+
+    if implicit {
+        jk.transaction_manager.commit()?;
+    }
+    match plan.renames.len() {
+        1 => println!("  Use {} to rename it back", "jk undo".cyan()),
+        n => println!(
+            "  Use {} to undo each of the {} renames",
+            format!("jk undo --count {}", n).cyan(),
+            n
+        ),
+    }
+    print_excluded(&selection.excluded);
+    print_links(&selection.links);
+
+    Ok(())
+}
+
+/// Why a bulk rename cannot run, for the terminal
+fn describe_collision(collision: &januskey::Collision) -> String {
+    let list = |paths: &[PathBuf]| -> String {
+        paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    match collision {
+        januskey::Collision::Shared {
+            destination,
+            sources,
+        } => format!(
+            "{} would all be renamed to {}",
+            list(sources),
+            destination.display()
+        ),
+        januskey::Collision::Exists {
+            source,
+            destination,
+        } => format!(
+            "{} -> {}: a file that is not being renamed has that name",
+            source.display(),
+            file_name(destination)
+        ),
+        januskey::Collision::InvalidName { source, name } => {
+            format!("{} -> {:?}: not a valid file name", source.display(), name)
+        }
+        januskey::Collision::Cycle { sources } => {
+            format!("{} would take each other's names", list(sources))
+        }
+    }
+}
+
+/// The last component of `path`, for display
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Change permissions of files and directories, each its own reversible
+/// operation. A symbolic mode is applied to each path's current mode.
+/// Files go first, then directories deepest first, so removing access to
+/// a directory does not stop its contents being changed. Outside a
+/// transaction, several paths are grouped into an implicit one that is
+/// rolled back if any of them fails.
+#[cfg(unix)]
+fn cmd_chmod(
+    dir: &Path,
+    mode: &str,
+    paths: &[String],
+    recursive: bool,
+    exclude: &[String],
+    dry_run: bool,
+) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let parsed: januskey::Mode = mode.parse()?;
+    let mut jk = shell::open(dir)?;
+    let selection = select_files(dir, paths, recursive, &Excludes::new(exclude)?)?;
+
+    let mut plan = Vec::new();
+    let targets = selection
+        .files
+        .iter()
+        .chain(selection.directories.iter().rev());
+    for path in targets {
+        let current = std::fs::metadata(path)?.permissions().mode() & 0o7777;
+        let new_mode = parsed.apply(current, path.is_dir());
+        if new_mode != current {
+            plan.push((path.clone(), current, new_mode));
+        }
+    }
+    if plan.is_empty() {
+        println!("{} No permissions would change", "!".yellow());
+        print_excluded(&selection.excluded);
+        print_links(&selection.links);
+        return Ok(());
+    }
+
+    if dry_run {
+        println!("{} Dry run - would change:", "[DRY RUN]".cyan());
+        for (path, current, new_mode) in &plan {
+            println!("  - {} {:04o} -> {:04o}", path.display(), current, new_mode);
+        }
+        print_excluded(&selection.excluded);
+        print_links(&selection.links);
+        return Ok(());
+    }
+
+    let implicit = plan.len() > 1 && !jk.transaction_manager.has_active();
+    if implicit {
+        let name = format!("chmod {} {} paths", mode, plan.len());
+        jk.transaction_manager.begin(Some(name))?;
+    }
+
+    for (path, current, new_mode) in &plan {
+        let operation = FileOperation::Chmod {
+            path: path.clone(),
+            new_mode: *new_mode,
+        };
+        if let Err(e) = jk.execute(operation) {
+            eprintln!("{} Failed to chmod {}: {}", "✗".red(), path.display(), e);
+            if implicit {
+                let tx = jk.rollback()?;
+                println!(
+                    "{} Rolled back {} completed operations",
+                    "!".yellow(),
+                    tx.operation_ids.len()
+                );
+            }
+            anyhow::bail!("Chmod failed");
+        }
+        println!(
+            "{} {} {:04o} -> {:04o}",
+            "✓".green(),
+            path.display(),
+            current,
+            new_mode
+        );
+    }
+
+    if implicit {
+        jk.transaction_manager.commit()?;
+    }
+
+    match plan.len() {
+        1 => println!("  Use {} to restore the permissions", "jk undo".cyan()),
+        n => println!(
+            "  Use {} to undo each of the {} operations",
+            format!("jk undo --count {}", n).cyan(),
+            n
+        ),
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn cmd_chmod(
+    _dir: &Path,
+    _mode: &str,
+    _paths: &[String],
+    _recursive: bool,
+    _exclude: &[String],
+    _dry_run: bool,
+) -> Result<()> {
+    anyhow::bail!("jk chmod needs Unix permissions")
+}
+
+/// "Move"/"Moved" or "Copy"/"Copied"
+fn verb(kind: Transfer, past: bool) -> &'static str {
+    match (kind, past) {
+        (Transfer::Move, false) => "Move",
+        (Transfer::Move, true) => "Moved",
+        (Transfer::Copy, false) => "Copy",
+        (Transfer::Copy, true) => "Copied",
+    }
+}
+
+/// Resolve source arguments against `dir`, expanding glob patterns.
+/// Literal paths are kept as given (missing files are reported when the
+/// operation runs); a pattern that matches nothing is an error.
+fn expand_sources(dir: &Path, sources: &[String]) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for source in sources {
+        let full = dir.join(source);
+        if !source.contains(['*', '?', '[']) {
+            paths.push(full);
+            continue;
+        }
+
+        let matches =
+            glob::glob(&full.to_string_lossy())?.collect::<std::result::Result<Vec<_>, _>>()?;
+        if matches.is_empty() {
+            anyhow::bail!("No files match {}", source);
+        }
+        paths.extend(matches);
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    paths.retain(|path| seen.insert(path.clone()));
+    Ok(paths)
+}
+
+fn cmd_obliterate(
+    dir: &Path,
+    paths: &[PathBuf],
+    override_hold: Option<String>,
+    dry_run: bool,
+    auto_yes: bool,
+) -> Result<()> {
+    use januskey::obliteration::obliterate_file;
+
+    // Resolve each path against the working directory if it is relative.
+    let targets: Vec<PathBuf> = paths
+        .iter()
+        .map(|p| {
+            if p.is_absolute() {
+                p.clone()
+            } else {
+                dir.join(p)
+            }
+        })
+        .collect();
+
+    // Files under legal hold are refused unless the hold is overridden
+    let jk = JanusKey::open(dir).ok();
+    let mut holds = jk.as_ref().map(|jk| jk.legal_holds()).transpose()?;
+    let mut held = std::collections::HashMap::new();
+    if let (Some(jk), Some(holds)) = (&jk, &holds) {
+        for t in &targets {
+            let canonical = t.canonicalize().unwrap_or_else(|_| t.clone());
+            if let Some(hold) = holds.hold_on_path(&jk.root, &canonical) {
+                held.insert(t.clone(), hold.clone());
+            }
+        }
+    }
+    if !held.is_empty() && override_hold.is_none() {
+        for (t, hold) in &held {
+            eprintln!(
+                "{} {} is under legal hold for case {}",
+                "✗".red(),
+                t.display(),
+                hold.case
+            );
+        }
+        anyhow::bail!(
+            "refusing to obliterate files under legal hold; \
+             pass --override-hold <JUSTIFICATION> to proceed"
+        );
+    }
+
+    if dry_run {
+        println!("{} Dry run - would obliterate:", "[DRY RUN]".cyan());
+        for t in &targets {
+            let size = std::fs::metadata(t).map(|m| m.len()).unwrap_or(0);
+            match held.get(t) {
+                Some(hold) => println!(
+                    "  - {} ({}, overriding legal hold for case {})",
+                    t.display(),
+                    human_bytes(size),
+                    hold.case
+                ),
+                None => println!("  - {} ({})", t.display(), human_bytes(size)),
+            }
+            // Only the file is wiped: say what stays in the store
+            let (Some(jk), Ok(content)) = (&jk, std::fs::read(t)) else {
+                continue;
+            };
+            let hash = jk.content_store.hash(&content);
+            let mut stored: Vec<januskey::ContentHash> = Vec::new();
+            for op in jk.metadata_store.operations() {
+                if let Some(content_hash) = &op.content_hash {
+                    let matches =
+                        *content_hash == hash || op.plaintext_hash.as_ref() == Some(&hash);
+                    if matches && !stored.contains(content_hash) {
+                        stored.push(content_hash.clone());
+                    }
                 }
             }
+            if jk.content_store.exists(&hash) && !stored.contains(&hash) {
+                stored.push(hash);
+            }
+            for content_hash in &stored {
+                let impact = jk.obliteration_impact(content_hash, DeltaDependents::default())?;
+                println!(
+                    "    {} a copy stays in the content store ({}, {}, {} operation(s))",
+                    "!".yellow(),
+                    &content_hash.raw_hash()[..12],
+                    human_bytes(impact.stored_bytes),
+                    impact.operations.len()
+                );
+            }
+        }
+        return Ok(());
+    }
+
+    // Obliteration is irreversible — confirm unless --yes was given. Refuse
+    // outright (rather than silently auto-confirming) when stdin is not a
+    // terminal and no --yes was supplied, so destructive erasure never runs
+    // unattended without explicit consent.
+    if !auto_yes {
+        use std::io::IsTerminal;
+        if !std::io::stdin().is_terminal() {
+            anyhow::bail!(
+                "refusing to obliterate without confirmation in non-interactive mode; \
+                 pass --yes/-y to confirm"
+            );
+        }
+        println!(
+            "{} Obliteration is {} — content will be unrecoverable:",
+            "⚠".yellow(),
+            "irreversible".red()
+        );
+        for t in &targets {
+            println!("  - {}", t.display());
+        }
+        if !Confirm::new()
+            .with_prompt("Continue?")
+            .default(false)
+            .interact()?
+        {
+            println!("{}", "Cancelled".red());
+            return Ok(());
         }
     }
 
+    let mut obliterated = 0;
+    for t in &targets {
+        if let (Some(hold), Some(holds), Some(justification)) =
+            (held.get(t), holds.as_mut(), override_hold.as_deref())
+        {
+            holds.record_override(hold, &t.display().to_string(), justification)?;
+        }
+        match obliterate_file(t) {
+            Ok(proof) => {
+                obliterated += 1;
+                println!(
+                    "{} Obliterated {} ({} passes, proof {})",
+                    "✓".green(),
+                    t.display(),
+                    proof.overwrite_passes,
+                    &proof.id[..8]
+                );
+            }
+            Err(e) => {
+                eprintln!("{} Failed to obliterate {}: {}", "✗".red(), t.display(), e);
+            }
+        }
+    }
+
+    println!(
+        "{} Obliterated {} file(s) — erasure is permanent",
+        "✓".green(),
+        obliterated
+    );
+
+    Ok(())
+}
+
+fn cmd_obliterate_schedule(
+    dir: &Path,
+    age: &str,
+    pattern: &str,
+    reason: Option<String>,
+    legal_basis: Option<String>,
+) -> Result<()> {
+    let jk = shell::open(dir)?;
+    let after = januskey::retention::parse_retention(age)?;
+    let rule = jk
+        .retention_schedule()?
+        .schedule(pattern, after, reason, legal_basis)?;
+
+    println!(
+        "{} Content deleted under {} will be obliterated {} after deletion (schedule {})",
+        "✓".green(),
+        rule.pattern.cyan(),
+        age,
+        &rule.id[..8]
+    );
+    println!(
+        "  Run {} regularly (e.g. from cron) to carry it out",
+        "jk obliterate --run-due --yes".cyan()
+    );
+    cmd_obliterate_pending(dir)
+}
+
+fn cmd_obliterate_pending(dir: &Path) -> Result<()> {
+    let jk = shell::open(dir)?;
+    let schedule = jk.retention_schedule()?;
+    if schedule.rules().is_empty() {
+        println!("No obliteration schedules");
+        return Ok(());
+    }
+
+    println!("{}", "Schedules:".bold());
+    for rule in schedule.rules() {
+        println!(
+            "  {} {} after {} (by {})",
+            &rule.id[..8],
+            rule.pattern.cyan(),
+            januskey::retention::format_retention(rule.after()),
+            rule.created_by
+        );
+    }
+
+    let pending = schedule.pending(&jk.root, &jk.content_store, &jk.metadata_store)?;
+    println!("{}", "─".repeat(70));
+    if pending.is_empty() {
+        println!("Nothing pending");
+        return Ok(());
+    }
+    let now = chrono::Utc::now();
+    for item in &pending {
+        let due = item.due.format("%Y-%m-%d %H:%M").to_string();
+        println!(
+            "{} | {} | {}",
+            if item.due <= now {
+                due.red()
+            } else {
+                due.normal()
+            },
+            &item.content_hash.raw_hash()[..12],
+            item.path.strip_prefix(dir).unwrap_or(&item.path).display()
+        );
+    }
+    let due = pending.iter().filter(|item| item.due <= now).count();
+    println!("{} pending, {} due", pending.len(), due);
+    Ok(())
+}
+
+fn cmd_obliterate_due(
+    dir: &Path,
+    override_hold: Option<String>,
+    dependents: DeltaDependents,
+    dry_run: bool,
+    auto_yes: bool,
+    json: bool,
+) -> Result<()> {
+    let jk = shell::open(dir)?;
+    let schedule = jk.retention_schedule()?;
+    let now = chrono::Utc::now();
+    let due: Vec<_> = schedule
+        .pending(&jk.root, &jk.content_store, &jk.metadata_store)?
+        .into_iter()
+        .filter(|item| item.due <= now)
+        .collect();
+    if due.is_empty() {
+        println!("{} Nothing due for obliteration", "✓".green());
+        return Ok(());
+    }
+
+    if dry_run {
+        let impacts = due
+            .iter()
+            .map(|item| jk.obliteration_impact(&item.content_hash, dependents))
+            .collect::<januskey::Result<Vec<_>>>()?;
+        if json {
+            println!("{}", serde_json::to_string_pretty(&impacts)?);
+            return Ok(());
+        }
+        println!("{} Dry run - would obliterate:", "[DRY RUN]".cyan());
+        for (item, impact) in due.iter().zip(&impacts) {
+            print_obliteration_impact(
+                &item
+                    .path
+                    .strip_prefix(dir)
+                    .unwrap_or(&item.path)
+                    .display()
+                    .to_string(),
+                item.deleted_at,
+                impact,
+            );
+        }
+        let bytes: u64 = impacts.iter().map(|impact| impact.stored_bytes).sum();
+        let undos: usize = impacts.iter().map(|impact| impact.lost_undos.len()).sum();
+        println!(
+            "{} item(s), {} wiped, {} operation(s) could no longer be undone",
+            impacts.len(),
+            human_bytes(bytes),
+            undos
+        );
+        return Ok(());
+    }
+
+    if !auto_yes {
+        use std::io::IsTerminal;
+        if !std::io::stdin().is_terminal() {
+            anyhow::bail!(
+                "refusing to obliterate without confirmation in non-interactive mode; \
+                 pass --yes/-y to confirm"
+            );
+        }
+        if !Confirm::new()
+            .with_prompt(format!(
+                "Irreversibly obliterate {} scheduled item(s)?",
+                due.len()
+            ))
+            .default(false)
+            .interact()?
+        {
+            println!("{}", "Cancelled".red());
+            return Ok(());
+        }
+    }
+
+    let mut obliterations = match override_hold {
+        Some(justification) => jk.obliteration_manager()?.override_holds(justification),
+        None => jk.obliteration_manager()?,
+    }
+    .on_dependents(dependents);
+    for item in &due {
+        if let Some(case) = obliterations.blocking_hold(&item.content_hash) {
+            println!(
+                "{} {} is under legal hold for case {}, skipped",
+                "!".yellow(),
+                item.path.strip_prefix(dir).unwrap_or(&item.path).display(),
+                case
+            );
+        }
+    }
+    let result = schedule.run_due(
+        &jk.root,
+        &jk.content_store,
+        &jk.metadata_store,
+        &mut obliterations,
+        now,
+    )?;
+    for record in &result.successful {
+        println!(
+            "{} Obliterated {} (proof {})",
+            "✓".green(),
+            &record.content_hash.raw_hash()[..12],
+            &record.proof.id[..8]
+        );
+    }
+    for (hash, e) in &result.failed {
+        eprintln!(
+            "{} Failed to obliterate {}: {}",
+            "✗".red(),
+            &hash.raw_hash()[..12],
+            e
+        );
+    }
+    if !result.failed.is_empty() {
+        anyhow::bail!("{} scheduled obliterations failed", result.failed.len());
+    }
+    Ok(())
+}
+
+fn print_obliteration_impact(
+    path: &str,
+    deleted_at: chrono::DateTime<chrono::Utc>,
+    impact: &januskey::obliteration::ObliterationImpact,
+) {
+    println!(
+        "  - {} {} (deleted {}, {})",
+        &impact.content_hash.raw_hash()[..12],
+        path,
+        deleted_at.format("%Y-%m-%d"),
+        human_bytes(impact.stored_bytes)
+    );
+    if !impact.operations.is_empty() {
+        let ids: Vec<&str> = impact.operations.iter().map(|id| &id[..8]).collect();
+        println!("      referenced by operations {}", ids.join(", "));
+    }
+    if !impact.dependent_deltas.is_empty() {
+        let hashes: Vec<&str> = impact
+            .dependent_deltas
+            .iter()
+            .map(|hash| &hash.raw_hash()[..12])
+            .collect();
+        let fate = match impact.dependents {
+            DeltaDependents::Refuse => "block it (see --dependents)",
+            DeltaDependents::Materialize => "are rewritten as full content",
+            DeltaDependents::Cascade => "are obliterated too",
+        };
+        println!(
+            "      {} deltas stored against it {}: {}",
+            "!".yellow(),
+            fate,
+            hashes.join(", ")
+        );
+    }
+    if !impact.lost_undos.is_empty() {
+        let ids: Vec<&str> = impact.lost_undos.iter().map(|id| &id[..8]).collect();
+        println!(
+            "      {} no longer undoable: {}",
+            "!".yellow(),
+            ids.join(", ")
+        );
+    }
+    if !impact.transactions.is_empty() {
+        let ids: Vec<&str> = impact.transactions.iter().map(|id| &id[..8]).collect();
+        println!(
+            "      {} transactions no longer fully rollbackable: {}",
+            "!".yellow(),
+            ids.join(", ")
+        );
+    }
+}
+
+fn cmd_hold_add(dir: &Path, path: &str, case: &str, reason: Option<String>) -> Result<()> {
+    let jk = shell::open(dir)?;
+    let mut holds = jk.legal_holds()?;
+    let hold = holds.add(case, path, reason)?;
+    let held = holds.held_operations(&jk.root, &jk.metadata_store).len();
+    println!(
+        "{} Placed legal hold {} on {} for case {} ({} operations held)",
+        "✓".green(),
+        &hold.id[..8],
+        hold.pattern.cyan(),
+        hold.case.bold(),
+        held
+    );
+    Ok(())
+}
+
+fn cmd_hold_list(dir: &Path, all: bool, json: bool) -> Result<()> {
+    let jk = shell::open(dir)?;
+    let holds = jk.legal_holds()?;
+    let shown: Vec<_> = holds
+        .all()
+        .iter()
+        .filter(|hold| all || hold.is_active())
+        .collect();
+
+    if json {
+        let overrides = if all { holds.overrides() } else { &[] };
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({
+                "holds": shown,
+                "overrides": overrides,
+            }))?
+        );
+        return Ok(());
+    }
+
+    if shown.is_empty() {
+        println!("No legal holds");
+        return Ok(());
+    }
+    for hold in &shown {
+        let status = match (&hold.released_at, &hold.released_by) {
+            (Some(at), Some(by)) => format!("released {} by {}", at.format("%Y-%m-%d"), by)
+                .dimmed()
+                .to_string(),
+            _ => "active".green().to_string(),
+        };
+        println!(
+            "{} | {} | {} | {} | {}",
+            &hold.id[..8],
+            hold.case.bold(),
+            hold.pattern.cyan(),
+            hold.created_at.format("%Y-%m-%d"),
+            status
+        );
+        if let Some(reason) = &hold.reason {
+            println!("    {}", reason);
+        }
+    }
+    if all && !holds.overrides().is_empty() {
+        println!("{}", "─".repeat(70));
+        println!("{}", "Overrides:".bold());
+        for o in holds.overrides() {
+            println!(
+                "  {} | case {} | {} | {}: {}",
+                o.timestamp.format("%Y-%m-%d %H:%M"),
+                o.case,
+                o.target,
+                o.user,
+                o.justification
+            );
+        }
+    }
+    Ok(())
+}
+
+fn cmd_hold_release(dir: &Path, case: &str) -> Result<()> {
+    let jk = shell::open(dir)?;
+    let mut holds = jk.legal_holds()?;
+    for hold in holds.release(case)? {
+        println!(
+            "{} Released legal hold {} on {} for case {}",
+            "✓".green(),
+            &hold.id[..8],
+            hold.pattern.cyan(),
+            hold.case.bold()
+        );
+    }
+    Ok(())
+}
+
+fn cmd_undo(
+    dir: &Path,
+    count: usize,
+    id: Option<String>,
+    cascade: bool,
+    force: bool,
+    dry_run: bool,
+) -> Result<()> {
+    if dry_run {
+        let mut jk = shell::open(dir)?;
+        unlock_content(&mut jk)?;
+        let id = resolve_operation_id(&jk, id)?;
+        let op_ids: Vec<String> = match id {
+            Some(op_id) if cascade => {
+                let mut ids = jk.metadata_store.dependency_graph().descendants(&op_id);
+                ids.insert(0, op_id);
+                ids.reverse();
+                ids
+            }
+            Some(op_id) => vec![op_id],
+            None => jk
+                .metadata_store
+                .last_n(count)
+                .into_iter()
+                .map(|op| op.id.clone())
+                .collect(),
+        };
+        if op_ids.is_empty() {
+            println!("{} Nothing to undo", "!".yellow());
+            return Ok(());
+        }
+
+        println!("{} Dry run - undo would:", "[DRY RUN]".cyan());
+        let executor = jk.executor()?;
+        let mut sim = Simulation::new();
+        for op_id in &op_ids {
+            let inverse = executor.simulate_undo(op_id, &mut sim)?;
+            println!("  - {}", describe_undo(&inverse));
+        }
+        return Ok(());
+    }
+
+    #[cfg(unix)]
+    if let Some(mut client) = daemon::Client::connect(dir)? {
+        let by_id = id.is_some();
+        let outcomes = client.undo(id, count, cascade, force)?;
+        if outcomes.is_empty() {
+            println!("{} Nothing to undo", "!".yellow());
+        }
+        for outcome in outcomes {
+            let op = &outcome.operation;
+            match outcome.result {
+                Ok(meta) => {
+                    // As below: by ID the undo is described, otherwise
+                    // the operation it reversed
+                    let shown = if by_id { &meta } else { op };
+                    println!(
+                        "{} Undid {} on {}",
+                        "✓".green(),
+                        shown.op_type,
+                        shown.path.display()
+                    );
+                    print_verified(&meta);
+                }
+                Err(e) => eprintln!(
+                    "{} Failed to undo {} on {}: {}",
+                    "✗".red(),
+                    op.op_type,
+                    op.path.display(),
+                    e
+                ),
+            }
+        }
+        return Ok(());
+    }
+
+    let mut jk = shell::open(dir)?;
+    unlock_content(&mut jk)?;
+    if let Some(op_id) = resolve_operation_id(&jk, id)? {
+        // Undo specific operation, and with --cascade what depends on it
+        let mut op_ids = vec![op_id.clone()];
+        if cascade {
+            op_ids.extend(jk.metadata_store.dependency_graph().descendants(&op_id));
+        }
+        let bytes = op_ids
+            .iter()
+            .filter_map(|id| jk.metadata_store.get(id))
+            .filter(|op| !op.undone)
+            .map(|op| op.restore_bytes())
+            .sum();
+        check_disk_space(&jk, bytes, force)?;
+
+        let undone = if cascade {
+            jk.undo_cascade(&op_id)?
+        } else {
+            match jk.undo(&op_id) {
+                Ok(meta) => vec![meta],
+                Err(e) => {
+                    if let JanusError::HasDependents { dependents, .. } = e.inner() {
+                        eprintln!("{} Later operations depend on {}:", "✗".red(), op_id);
+                        for dependent in dependents.iter().rev() {
+                            if let Some(op) = jk.metadata_store.get(dependent) {
+                                eprintln!("  {} {} {}", &op.id[..8], op.op_type, op.path.display());
+                            }
+                        }
+                        anyhow::bail!("Undo them first, or pass --cascade to undo them too");
+                    }
+                    return Err(e.into());
+                }
+            }
+        };
+        for meta in &undone {
+            println!(
+                "{} Undid {} on {}",
+                "✓".green(),
+                meta.op_type,
+                meta.path.display()
+            );
+            print_verified(meta);
+        }
+    } else {
+        // Undo last N operations
+        let ops_to_undo: Vec<_> = jk
+            .metadata_store
+            .last_n(count)
+            .into_iter()
+            .cloned()
+            .collect();
+
+        if ops_to_undo.is_empty() {
+            println!("{} Nothing to undo", "!".yellow());
+            return Ok(());
+        }
+        let bytes = ops_to_undo.iter().map(|op| op.restore_bytes()).sum();
+        check_disk_space(&jk, bytes, force)?;
+
+        for (op, result) in jk.undo_recent(count) {
+            match result {
+                Ok(meta) => {
+                    println!(
+                        "{} Undid {} on {}",
+                        "✓".green(),
+                        op.op_type,
+                        op.path.display()
+                    );
+                    print_verified(&meta);
+                }
+                Err(e) => {
+                    eprintln!(
+                        "{} Failed to undo {} on {}: {}",
+                        "✗".red(),
+                        op.op_type,
+                        op.path.display(),
+                        e
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The full ID of the operation `id` names, which may be a unique prefix
+fn resolve_operation_id(jk: &JanusKey, id: Option<String>) -> Result<Option<String>> {
+    Ok(match id {
+        Some(id) => Some(jk.metadata_store.find(&id)?.id.clone()),
+        None => None,
+    })
+}
+
+/// Refuse to start writing `bytes` of restored content that will not fit
+/// on the disk, so it cannot fail halfway; with `force` only warn
+fn check_disk_space(jk: &JanusKey, bytes: u64, force: bool) -> Result<()> {
+    match jk.ensure_disk_space(bytes) {
+        Err(JanusError::InsufficientSpace(reason)) if force => {
+            eprintln!("{} {}; continuing (--force)", "⚠".yellow(), reason);
+            Ok(())
+        }
+        Err(e @ JanusError::InsufficientSpace(_)) => {
+            Err(e).context("Free up space, or pass --force to try anyway")
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Operations offered by `jk undo --interactive` without --count
+const INTERACTIVE_UNDO_COUNT: usize = 20;
+
+fn cmd_undo_interactive(dir: &Path, count: usize, force: bool, dry_run: bool) -> Result<()> {
+    let mut jk = shell::open(dir)?;
+    unlock_content(&mut jk)?;
+
+    // Newest first, the order they must be undone in
+    let candidates: Vec<OperationMetadata> = jk
+        .metadata_store
+        .last_n(count)
+        .into_iter()
+        .cloned()
+        .collect();
+    if candidates.is_empty() {
+        println!("{} Nothing to undo", "!".yellow());
+        return Ok(());
+    }
+
+    println!("{}", "Recent operations:".bold());
+    let executor = jk.executor()?;
+    let mut items = Vec::new();
+    for (i, op) in candidates.iter().enumerate() {
+        let timestamp = op.timestamp.format("%Y-%m-%d %H:%M:%S");
+        println!(
+            "{} {} {} {}",
+            format!("[{}]", i + 1).dimmed(),
+            timestamp,
+            op.op_type.to_string().yellow(),
+            op.path.display()
+        );
+        // Each preview stands alone: what undoing only this one would do
+        match executor.simulate_undo(&op.id, &mut Simulation::new()) {
+            Ok(inverse) => {
+                println!("  would {}", describe_undo(&inverse));
+                if let FileOperation::Modify { path, new_content } = &inverse {
+                    let current = std::fs::read(path).unwrap_or_default();
+                    print_diff(&current, new_content);
+                }
+            }
+            Err(e) => println!("  {} {}", "cannot undo:".red(), e),
+        }
+        items.push(format!(
+            "{} {} {}",
+            &op.id[..8],
+            op.op_type,
+            op.path.strip_prefix(dir).unwrap_or(&op.path).display()
+        ));
+    }
+    println!();
+
+    let chosen = MultiSelect::new()
+        .with_prompt("Select operations to undo (space to toggle, enter to confirm)")
+        .items(&items)
+        .interact()?;
+    if chosen.is_empty() {
+        println!("{}", "Cancelled".red());
+        return Ok(());
+    }
+    let selected: Vec<&OperationMetadata> = chosen.iter().map(|&i| &candidates[i]).collect();
+    let ids: Vec<String> = selected.iter().map(|op| op.id.clone()).collect();
+
+    let conflicts = jk.undo_conflicts(&ids);
+    if !conflicts.is_empty() {
+        eprintln!("{} Selection conflicts with later operations:", "✗".red());
+        for (op, later) in &conflicts {
+            eprintln!(
+                "  {} {} {} is followed by {} {} {}",
+                &op.id[..8],
+                op.op_type,
+                op.path.display(),
+                &later.id[..8],
+                later.op_type,
+                later.path.display()
+            );
+        }
+        anyhow::bail!("Select the later operations too, or undo them first");
+    }
+
+    // Check the whole selection applies before touching anything
+    let executor = jk.executor()?;
+    let mut sim = Simulation::new();
+    let mut plan = Vec::new();
+    for op in &selected {
+        plan.push(executor.simulate_undo(&op.id, &mut sim)?);
+    }
+
+    if dry_run {
+        println!("{} Dry run - undo would:", "[DRY RUN]".cyan());
+        for inverse in &plan {
+            println!("  - {}", describe_undo(inverse));
+        }
+        return Ok(());
+    }
+
+    let bytes = selected.iter().map(|op| op.restore_bytes()).sum();
+    check_disk_space(&jk, bytes, force)?;
+    for op in selected {
+        let mut executor = jk.executor()?;
+        let meta = executor
+            .undo(&op.id)
+            .with_context(|| format!("Failed to undo {} on {}", op.op_type, op.path.display()))?;
+        println!(
+            "{} Undid {} on {}",
+            "✓".green(),
+            op.op_type,
+            op.path.display()
+        );
+        print_verified(&meta);
+    }
+
+    Ok(())
+}
+
+/// Undo the last `--count` operations on paths matching `--path`, or
+/// every operation since `--since` (on those paths, if given), newest
+/// first. Later operations left out that depend on them are listed and
+/// must be confirmed past, since undoing would overwrite their effects.
+fn cmd_undo_path(dir: &Path, args: UndoArgs, auto_yes: bool, dry_run: bool) -> Result<()> {
+    let UndoArgs {
+        count,
+        path: pattern,
+        since,
+        force,
+        ..
+    } = args;
+    let (pattern, since, count) = (pattern.as_deref(), since.as_deref(), count.unwrap_or(1));
+    let mut jk = shell::open(dir)?;
+    unlock_content(&mut jk)?;
+
+    let (selected, scope) = match (since, pattern) {
+        (Some(since), pattern) => {
+            let since = januskey::retention::parse_since(since, chrono::Utc::now())?;
+            let scope = format!("since {}", since.format("%Y-%m-%d %H:%M:%S"));
+            let scope = match pattern {
+                Some(pattern) => format!("{} {}", pattern, scope),
+                None => scope,
+            };
+            (jk.recent_since(pattern, since)?, scope)
+        }
+        (None, Some(pattern)) => (jk.recent_for_path(pattern, count)?, pattern.to_string()),
+        (None, None) => unreachable!("dispatched only with --path or --since"),
+    };
+    if selected.is_empty() {
+        println!("{} Nothing to undo on {}", "!".yellow(), scope);
+        return Ok(());
+    }
+    let ids: Vec<String> = selected.iter().map(|op| op.id.clone()).collect();
+
+    let conflicts = jk.undo_conflicts(&ids);
+    if !conflicts.is_empty() {
+        println!(
+            "{} Later operations depend on these and would be overwritten:",
+            "⚠".yellow()
+        );
+        for (op, later) in &conflicts {
+            println!(
+                "  {} {} {} is followed by {} {} {}",
+                &op.id[..8],
+                op.op_type,
+                op.path.strip_prefix(dir).unwrap_or(&op.path).display(),
+                &later.id[..8],
+                later.op_type,
+                later
+                    .path
+                    .strip_prefix(dir)
+                    .unwrap_or(&later.path)
+                    .display()
+            );
+        }
+    }
+
+    // Check the whole selection applies before touching anything
+    let executor = jk.executor()?;
+    let mut sim = Simulation::new();
+    let mut plan = Vec::new();
+    for op in &selected {
+        plan.push(executor.simulate_undo(&op.id, &mut sim)?);
+    }
+
+    if dry_run {
+        println!("{} Dry run - undo would:", "[DRY RUN]".cyan());
+        for inverse in &plan {
+            println!("  - {}", describe_undo(inverse));
+        }
+        return Ok(());
+    }
+
+    if !conflicts.is_empty() && !auto_yes {
+        use std::io::IsTerminal;
+        if !std::io::stdin().is_terminal() {
+            anyhow::bail!(
+                "refusing to overwrite later operations without confirmation in \
+                 non-interactive mode; pass --yes/-y to undo anyway"
+            );
+        }
+        if !Confirm::new()
+            .with_prompt("Undo anyway?")
+            .default(false)
+            .interact()?
+        {
+            println!("{}", "Cancelled".red());
+            return Ok(());
+        }
+    }
+
+    let bytes = selected.iter().map(|op| op.restore_bytes()).sum();
+    check_disk_space(&jk, bytes, force)?;
+    for op in &selected {
+        let meta = jk
+            .executor()?
+            .undo(&op.id)
+            .with_context(|| format!("Failed to undo {} on {}", op.op_type, op.path.display()))?;
+        println!(
+            "{} Undid {} on {}",
+            "✓".green(),
+            op.op_type,
+            op.path.display()
+        );
+        print_verified(&meta);
+    }
+
+    Ok(())
+}
+
+fn cmd_begin(dir: &Path, name: Option<String>) -> Result<()> {
+    let tx = Repo::open(dir)?.begin(name.clone())?;
+    let display_name = name.unwrap_or_else(|| tx.id[..8].to_string());
+    println!(
+        "{} Started transaction: {}",
+        "✓".green(),
+        display_name.cyan()
+    );
+    println!(
+        "  Run operations, then use {} or {}",
+        "jk commit".cyan(),
+        "jk rollback".cyan()
+    );
+
+    Ok(())
+}
+
+fn cmd_commit(dir: &Path) -> Result<()> {
+    let tx = Repo::open(dir)?.commit()?;
+    let display_name = tx.name.unwrap_or_else(|| tx.id[..8].to_string());
+    println!(
+        "{} Committed transaction: {} ({} operations)",
+        "✓".green(),
+        display_name.cyan(),
+        tx.operation_ids.len()
+    );
+
+    Ok(())
+}
+
+/// Unlock the keys that decrypt content captured under an
+/// `encrypt_content` policy, if anything still to undo was. The passphrase
+/// comes from JANUSKEY_PASSPHRASE (a token's PIN from JANUSKEY_PIN), or a
+/// prompt on a terminal; without either, undoing encrypted content fails
+/// and says why.
+fn unlock_content(jk: &mut JanusKey) -> Result<()> {
+    if !jk.has_encrypted_content() || jk.content_unlocked() {
+        return Ok(());
+    }
+    let unsealed = match jk.unlock_content_with_tpm() {
+        Ok(unsealed) => unsealed,
+        Err(e) => {
+            eprintln!(
+                "{} TPM unlock failed ({}); falling back to the passphrase",
+                "!".yellow(),
+                e
+            );
+            None
+        }
+    };
+    let found = match unsealed {
+        Some(found) => found,
+        None => match content_passphrase(&jk.root)? {
+            Some(passphrase) => jk.unlock_content(&passphrase)?,
+            None => return Ok(()),
+        },
+    };
+    if found == 0 {
+        println!(
+            "{} No key in the key store matches content_recipients",
+            "!".yellow()
+        );
+    }
+    Ok(())
+}
+
+/// The passphrase (or token PIN) for the key store, from the environment
+/// or a prompt; `None` if neither is available
+fn content_passphrase(root: &Path) -> Result<Option<String>> {
+    use std::io::IsTerminal;
+    let (env, prompt) = if KeyManager::new(root).uses_token() {
+        (
+            januskey::pkcs11::PIN_ENV,
+            "Token PIN to decrypt captured content",
+        )
+    } else {
+        (
+            "JANUSKEY_PASSPHRASE",
+            "Passphrase to decrypt captured content",
+        )
+    };
+    Ok(match std::env::var(env) {
+        Ok(passphrase) => Some(passphrase),
+        Err(_) if std::io::stdin().is_terminal() => {
+            Some(Password::new().with_prompt(prompt).interact()?)
+        }
+        Err(_) => None,
+    })
+}
+
+fn cmd_rollback(
+    dir: &Path,
+    resume: bool,
+    id: Option<&str>,
+    force: bool,
+    dry_run: bool,
+) -> Result<()> {
+    if dry_run {
+        let mut jk = shell::open(dir)?;
+        unlock_content(&mut jk)?;
+        let steps = if resume {
+            let tx = jk.incomplete_rollback(id)?.clone();
+            jk.plan_rollback_of(&tx)?
+        } else {
+            jk.plan_rollback()?
+        };
+        println!(
+            "{} Dry run - rollback would undo {} operations:",
+            "[DRY RUN]".cyan(),
+            steps.len()
+        );
+        for (_, inverse) in &steps {
+            println!("  - {}", describe_undo(inverse));
+        }
+        return Ok(());
+    }
+
+    #[cfg(unix)]
+    if !resume {
+        if let Some(mut client) = daemon::Client::connect(dir)? {
+            let tx = client.rollback(force)?;
+            print_rolled_back(tx);
+            return Ok(());
+        }
+    }
+
+    let mut jk = shell::open(dir)?;
+    unlock_content(&mut jk)?;
+    let tx = if resume {
+        jk.incomplete_rollback(id)?
+    } else {
+        jk.transaction_manager
+            .active()
+            .ok_or(JanusError::NoActiveTransaction)?
+    };
+    let bytes = tx
+        .rollback_remaining()
+        .filter_map(|op_id| jk.metadata_store.get(op_id))
+        .filter(|op| !op.undone)
+        .map(|op| op.restore_bytes())
+        .sum();
+    check_disk_space(&jk, bytes, force)?;
+
+    let result = if resume {
+        jk.resume_rollback(id)
+    } else {
+        jk.rollback()
+    };
+    let tx = match result {
+        Ok(tx) => tx,
+        Err(e @ JanusError::RollbackIncomplete(_)) => {
+            if let Ok(tx) = jk.incomplete_rollback(None) {
+                for op_id in tx.rollback_remaining() {
+                    let error = match tx.rollback_status.get(op_id) {
+                        Some(RollbackStatus::Failed(error)) => error.as_str(),
+                        _ => "not attempted",
+                    };
+                    eprintln!("{} {}: {}", "✗".red(), &op_id[..8], error);
+                }
+            }
+            return Err(e).context("Fix the failures above and run `jk rollback --resume`");
+        }
+        Err(e) => return Err(e.into()),
+    };
+    print_rolled_back(tx);
+
+    Ok(())
+}
+
+fn print_rolled_back(tx: Transaction) {
+    let display_name = tx.name.unwrap_or_else(|| tx.id[..8].to_string());
+    println!(
+        "{} Rolled back transaction: {} ({} operations undone)",
+        "✓".green(),
+        display_name.cyan(),
+        tx.operation_ids.len()
+    );
+}
+
+fn cmd_tx_list(dir: &Path, limit: usize, json: bool) -> Result<()> {
+    let jk = shell::open(dir)?;
+    let transactions: Vec<&Transaction> = jk
+        .transaction_manager
+        .all()
+        .iter()
+        .rev()
+        .take(limit)
+        .collect();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&transactions)?);
+        return Ok(());
+    }
+    if transactions.is_empty() {
+        println!("{} No transactions recorded", "!".yellow());
+        return Ok(());
+    }
+
+    println!("{}", "Transactions:".bold());
+    println!("{}", "─".repeat(70));
+    for tx in &transactions {
+        println!(
+            "{} | {} | {:11} | {:>3} ops | {:>7} | {} {}",
+            &tx.id[..8],
+            tx.started_at.format("%Y-%m-%d %H:%M:%S"),
+            colored_state(tx.state),
+            tx.operation_ids.len(),
+            tx_duration(tx),
+            tx_started_by(tx).dimmed(),
+            tx.name.as_deref().unwrap_or_default().cyan()
+        );
+    }
+    println!("{}", "─".repeat(70));
+    println!(
+        "Showing {} of {} transactions. Use {} for details.",
+        transactions.len(),
+        jk.transaction_manager.all().len(),
+        "jk tx show <id>".cyan()
+    );
+
+    Ok(())
+}
+
+fn cmd_tx_show(dir: &Path, id: &str, json: bool) -> Result<()> {
+    let jk = shell::open(dir)?;
+    let tx = jk.transaction_manager.find(id)?;
+    // Operations pruned by gc are None
+    let operations: Vec<Option<&OperationMetadata>> = tx
+        .operation_ids
+        .iter()
+        .map(|op_id| jk.metadata_store.get(op_id))
+        .collect();
+
+    if json {
+        let shown = serde_json::json!({ "transaction": tx, "operations": operations });
+        println!("{}", serde_json::to_string_pretty(&shown)?);
+        return Ok(());
+    }
+
+    println!("{} {}", "Transaction".bold(), tx.id);
+    if let Some(name) = &tx.name {
+        println!("  Name:      {}", name.cyan());
+    }
+    println!("  State:     {}", colored_state(tx.state));
+    println!("  User:      {}", tx_started_by(tx));
+    println!("  Started:   {}", tx.started_at.format("%Y-%m-%d %H:%M:%S"));
+    if let Some(completed) = tx.completed_at {
+        println!("  Completed: {}", completed.format("%Y-%m-%d %H:%M:%S"));
+    }
+    println!("  Duration:  {}", tx_duration(tx));
+    if !tx.refs.is_empty() {
+        println!(
+            "  Refs:      {}",
+            januskey::refs::format_references(&tx.refs)
+        );
+    }
+    println!();
+
+    println!("{} ({})", "Operations:".bold(), operations.len());
+    for (i, (op_id, op)) in tx.operation_ids.iter().zip(&operations).enumerate() {
+        let Some(op) = op else {
+            println!("  {:>3}. {} {}", i + 1, &op_id[..8], "(pruned)".dimmed());
+            continue;
+        };
+        let target = match &op.path_secondary {
+            Some(secondary) => format!("{} -> {}", op.path.display(), secondary.display()),
+            None => op.path.display().to_string(),
+        };
+        let status = match tx.rollback_status.get(op_id) {
+            Some(RollbackStatus::Failed(error)) if !op.undone => {
+                format!(" (rollback failed: {})", error).red().to_string()
+            }
+            _ if op.undone => " (undone)".dimmed().to_string(),
+            _ => String::new(),
+        };
+        println!(
+            "  {:>3}. {} {:8} {}{}",
+            i + 1,
+            &op.id[..8],
+            op.op_type.to_string(),
+            target,
+            status
+        );
+    }
+
+    Ok(())
+}
+
+fn colored_state(state: TransactionState) -> colored::ColoredString {
+    match state {
+        TransactionState::Active => state.to_string().yellow(),
+        TransactionState::Committed => state.to_string().green(),
+        TransactionState::RolledBack => state.to_string().red(),
+        TransactionState::PartiallyRolledBack => state.to_string().magenta(),
+    }
+}
+
+/// The user that began a transaction, and the actor it declared
+fn tx_started_by(tx: &Transaction) -> String {
+    match &tx.actor {
+        Some(actor) => format!("{} as {}", tx.user, actor),
+        None => tx.user.clone(),
+    }
+}
+
+/// How long a transaction took, or has been open for
+fn tx_duration(tx: &Transaction) -> String {
+    let end = tx.completed_at.unwrap_or_else(chrono::Utc::now);
+    let secs = (end - tx.started_at).num_seconds().max(0);
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+fn cmd_preview(dir: &Path, diff: bool, stat: bool) -> Result<()> {
+    let jk = shell::open(dir)?;
+
+    let tx = jk
+        .transaction_manager
+        .active()
+        .ok_or_else(|| anyhow::anyhow!("No active transaction"))?;
+
+    let preview = if diff || stat {
+        jk.preview_with_content(tx)
+            .context("Failed to read the transaction's content")?
+    } else {
+        TransactionPreview::from_transaction(tx, &jk.metadata_store)
+    };
+
+    let name = preview
+        .transaction_name
+        .unwrap_or_else(|| tx.id[..8].to_string());
+    println!("📋 Transaction: {}", name.cyan());
+    println!("Operations pending: {}", preview.operations.len());
+    println!();
+
+    for op in &preview.operations {
+        let arrow = if op.secondary_path.is_some() {
+            " → "
+        } else {
+            ""
+        };
+        let secondary = op
+            .secondary_path
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_default();
+
+        println!(
+            "  {} {} {}{}{}",
+            match op.op_type.as_str() {
+                "DELETE" => "🗑".to_string(),
+                "MODIFY" => "✏".to_string(),
+                "MOVE" => "📦".to_string(),
+                "COPY" => "📄".to_string(),
+                _ => "•".to_string(),
+            },
+            op.op_type.yellow(),
+            op.path.display(),
+            arrow,
+            secondary
+        );
+        if let (true, Some(change)) = (diff, &op.change) {
+            match change.line_diff() {
+                Some(line_diff) => line_diff.hunks.iter().for_each(print_hunk),
+                None => println!(
+                    "    Binary content: {} → {}",
+                    human_bytes(change.before.as_ref().map_or(0, |c| c.len() as u64)),
+                    human_bytes(change.after.as_ref().map_or(0, |c| c.len() as u64))
+                ),
+            }
+        }
+    }
+
+    if stat {
+        println!();
+        print_preview_stat(&preview.operations);
+    }
+
+    println!();
+    println!("Total files affected: {}", preview.total_files_affected);
+    println!();
+    println!(
+        "Use {} to apply or {} to cancel",
+        "jk commit".cyan(),
+        "jk rollback".cyan()
+    );
+
+    Ok(())
+}
+
+/// `git diff --stat`-style summary of the content changes in `operations`
+fn print_preview_stat(operations: &[januskey::transaction::OperationPreview]) {
+    const BAR_WIDTH: usize = 40;
+    let changes: Vec<_> = operations
+        .iter()
+        .filter_map(|op| {
+            let path = op.secondary_path.as_ref().filter(|_| op.op_type == "COPY");
+            let change = op.change.as_ref()?;
+            Some((path.unwrap_or(&op.path).display().to_string(), change))
+        })
+        .collect();
+    let width = changes
+        .iter()
+        .map(|(path, _)| path.len())
+        .max()
+        .unwrap_or(0);
+    let most = changes
+        .iter()
+        .filter_map(|(_, change)| change.line_counts())
+        .map(|(added, removed)| added + removed)
+        .max()
+        .unwrap_or(0);
+
+    let (mut insertions, mut deletions, mut bytes) = (0, 0, 0);
+    for (path, change) in &changes {
+        bytes += change.byte_delta();
+        match change.line_counts() {
+            Some((added, removed)) => {
+                insertions += added;
+                deletions += removed;
+                // Scale the bar down when the largest change would not fit
+                let scale = |n: usize| match most > BAR_WIDTH {
+                    true => (n * BAR_WIDTH).div_ceil(most),
+                    false => n,
+                };
+                println!(
+                    " {:<width$} | {:>5} {}{}",
+                    path,
+                    added + removed,
+                    "+".repeat(scale(added)).green(),
+                    "-".repeat(scale(removed)).red(),
+                );
+            }
+            None => println!(
+                " {:<width$} | Bin {} -> {} bytes",
+                path,
+                change.before.as_ref().map_or(0, Vec::len),
+                change.after.as_ref().map_or(0, Vec::len),
+            ),
+        }
+    }
+    println!(
+        " {} files changed, {} insertions(+), {} deletions(-), {}{} bytes",
+        changes.len(),
+        insertions,
+        deletions,
+        if bytes >= 0 { "+" } else { "-" },
+        bytes.unsigned_abs()
+    );
+}
+
+fn cmd_history(dir: &Path, args: HistoryArgs) -> Result<()> {
+    let HistoryArgs {
+        limit,
+        filter,
+        path,
+        follow,
+        has_ref,
+        workspace,
+        full_ids,
+        stat,
+        ..
+    } = args;
+    let has_ref = parse_ref_queries(&has_ref)?;
+    let jk = shell::open(dir)?;
+    let id_len = if full_ids {
+        usize::MAX
+    } else {
+        jk.metadata_store.short_id_len()
+    };
+
+    let foreign;
+    let (selected, total) = match &workspace {
+        Some(id) => {
+            foreign = jk.workspace_history(id)?;
+            (
+                filter_operations(foreign.iter().collect(), filter, &has_ref),
+                foreign.len(),
+            )
+        }
+        None => (
+            select_history(&jk, dir, filter, path, follow, &has_ref)?,
+            jk.metadata_store.count(),
+        ),
+    };
+    let ops: Vec<_> = selected.into_iter().rev().take(limit).collect();
+
+    if ops.is_empty() {
+        println!("{} No operations in history", "!".yellow());
+        return Ok(());
+    }
+
+    println!("{}", "Operation History".bold());
+    println!("{}", "─".repeat(70));
+
+    for op in ops {
+        let status = if op.undone {
+            "[UNDONE]".dimmed()
+        } else {
+            "".normal()
+        };
+
+        let time = op.timestamp.format("%Y-%m-%d %H:%M:%S");
+        let op_type = match op.op_type.to_string().as_str() {
+            "DELETE" => "DELETE".red(),
+            "MODIFY" => "MODIFY".yellow(),
+            "MOVE" => "MOVE".blue(),
+            "COPY" => "COPY".cyan(),
+            "CREATE" => "CREATE".green(),
+            other => other.normal(),
+        };
+
+        let bytes = if stat {
+            format!("{} | ", byte_columns(op))
+        } else {
+            String::new()
+        };
+
+        println!(
+            "{} | {} | {:8} | {}{} | {} {}",
+            &op.id[..id_len.min(op.id.len())],
+            time,
+            op_type,
+            bytes,
+            op.path.display(),
+            recorded_by(op).dimmed(),
+            status
+        );
+    }
+
+    println!("{}", "─".repeat(70));
+    println!("Total: {} operations", total);
+
+    Ok(())
+}
+
+/// `read | stored | ratio` of an operation for `history --stat`, blank
+/// where it captured no content. A delta is marked after its ratio.
+fn byte_columns(op: &OperationMetadata) -> String {
+    match &op.bytes {
+        Some(bytes) => format!(
+            "{:>10} | {:>10} | {:>7}",
+            human_bytes(bytes.read),
+            human_bytes(bytes.stored),
+            format_ratio(bytes.ratio(), op.delta_base.is_some())
+        ),
+        None => format!("{:>10} | {:>10} | {:>7}", "-", "-", ""),
+    }
+}
+
+/// A stored/read ratio as a percentage, `Δ` marking a delta
+fn format_ratio(ratio: Option<f64>, delta: bool) -> String {
+    match ratio {
+        Some(ratio) if delta => format!("{:.0}% Δ", ratio * 100.0),
+        Some(ratio) => format!("{:.0}%", ratio * 100.0),
+        None => "-".to_string(),
+    }
+}
+
+fn cmd_show(dir: &Path, id: &str, json: bool) -> Result<()> {
+    let jk = shell::open(dir)?;
+    let op = jk.metadata_store.find(id)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(op)?);
+        return Ok(());
+    }
+
+    println!("{} {}", "Operation".bold(), op.id);
+    println!("  Type:        {}", op.op_type);
+    println!(
+        "  Time:        {}",
+        op.timestamp.format("%Y-%m-%d %H:%M:%S")
+    );
+    println!("  User:        {}", recorded_by(op));
+    println!("  Path:        {}", op.path.display());
+    if let Some(secondary) = &op.path_secondary {
+        println!("  To:          {}", secondary.display());
+    }
+    if let Some(tx) = &op.transaction_id {
+        println!("  Transaction: {}", tx);
+    }
+    if !op.refs.is_empty() {
+        println!(
+            "  References:  {}",
+            januskey::refs::format_references(&op.refs)
+        );
+    }
+    if !op.preserved.is_empty() {
+        let preserved: Vec<String> = op.preserved.iter().map(ToString::to_string).collect();
+        println!("  Preserved:   {}", preserved.join(", "));
+    }
+    if let Some(hash) = &op.content_hash {
+        println!("  Original:    {}", hash);
+    }
+    if let Some(hash) = &op.new_content_hash {
+        println!("  New:         {}", hash);
+    }
+    if let Some(bytes) = &op.bytes {
+        println!(
+            "  Stored:      {} of {} read ({})",
+            human_bytes(bytes.stored),
+            human_bytes(bytes.read),
+            format_ratio(bytes.ratio(), op.delta_base.is_some())
+        );
+    }
+    match &op.undo_operation_id {
+        Some(undo) => println!("  Undone by:   {}", undo),
+        None if op.undone => println!("  Undone:      yes"),
+        None => {}
+    }
+
+    let Some(context) = &op.context else {
+        println!("  {}", "No context recorded".dimmed());
+        return Ok(());
+    };
+    if let Some(hostname) = &context.hostname {
+        println!("  Host:        {}", hostname);
+    }
+    if let Some(pid) = context.pid {
+        println!("  Process:     {}", pid);
+    }
+    if let Some(command) = &context.parent_command {
+        println!("  Run from:    {}", command);
+    }
+    if let Some(cwd) = &context.cwd {
+        println!("  Directory:   {}", cwd.display());
+    }
+    if let Some(environment) = &context.environment {
+        println!("  Environment: {}", environment);
+    }
+    Ok(())
+}
+
+fn cmd_history_graph(dir: &Path, args: &HistoryArgs, format: GraphFormat) -> Result<()> {
+    let has_ref = parse_ref_queries(&args.has_ref)?;
+    let jk = shell::open(dir)?;
+
+    let mut ops: Vec<_> = select_history(
+        &jk,
+        dir,
+        args.filter.clone(),
+        args.path.clone(),
+        args.follow,
+        &has_ref,
+    )?
+    .into_iter()
+    .rev()
+    .take(args.limit)
+    .collect();
+    ops.reverse();
+
+    let graph = HistoryGraph::build(
+        jk.metadata_store.operations(),
+        ops,
+        jk.transaction_manager.all(),
+    );
+    match format {
+        GraphFormat::Text => print!("{}", graph.render_text(&jk.root)),
+        GraphFormat::Dot => print!("{}", graph.to_dot(&jk.root)),
+    }
+    Ok(())
+}
+
+/// Operations matching the history filters, oldest first
+fn select_history<'a>(
+    jk: &'a JanusKey,
+    dir: &Path,
+    filter: Option<String>,
+    path: Option<String>,
+    follow: bool,
+    has_ref: &[RefQuery],
+) -> Result<Vec<&'a OperationMetadata>> {
+    let candidates: Vec<_> = match path {
+        Some(path) => {
+            let target = dir.join(&path);
+            if follow {
+                jk.metadata_store.path_history(&target)
+            } else {
+                jk.metadata_store
+                    .filter_by_path(&target.to_string_lossy())?
+            }
+        }
+        None => jk.metadata_store.operations().iter().collect(),
+    };
+    Ok(filter_operations(candidates, filter, has_ref))
+}
+
+/// The operations of the type `filter` names, if given, that have every
+/// reference in `has_ref`
+fn filter_operations<'a>(
+    candidates: Vec<&'a OperationMetadata>,
+    filter: Option<String>,
+    has_ref: &[RefQuery],
+) -> Vec<&'a OperationMetadata> {
+    let filter_upper = filter.map(|f| f.to_uppercase());
+    candidates
+        .into_iter()
+        .filter(|op| {
+            filter_upper
+                .as_ref()
+                .is_none_or(|f| op.op_type.to_string() == *f)
+        })
+        .filter(|op| has_ref.iter().all(|query| query.matches(&op.refs)))
+        .collect()
+}
+
+/// Parse `--has-ref` arguments
+fn parse_ref_queries(specs: &[String]) -> Result<Vec<RefQuery>> {
+    Ok(specs
+        .iter()
+        .map(|spec| RefQuery::parse(spec))
+        .collect::<std::result::Result<_, _>>()?)
+}
+
+fn cmd_history_export(dir: &Path, args: ExportArgs) -> Result<()> {
+    let ExportArgs {
+        output,
+        sign,
+        format,
+        recipients,
+        age,
+        limit,
+        filter,
+        path,
+        follow,
+        has_ref,
+    } = args;
+    let has_ref = parse_ref_queries(&has_ref)?;
+    let jk = shell::open(dir)?;
+
+    let mut ops = select_history(&jk, dir, filter, path, follow, &has_ref)?;
+    if let Some(limit) = limit {
+        ops.drain(..ops.len().saturating_sub(limit));
+    }
+    if ops.is_empty() {
+        anyhow::bail!("No operations match; nothing to export");
+    }
+
+    let key_ids = sign
+        .iter()
+        .map(|id| parse_key_id(id))
+        .collect::<Result<Vec<_>>>()?;
+    let recipients = recipients
+        .iter()
+        .map(|key| envelope::parse_public_key(key))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    let km = unlock_key_store(dir)?;
+
+    let export = HistoryExport::new(
+        jk.root.display().to_string(),
+        ops.into_iter().cloned().collect(),
+    );
+    let mut signed = SignedExport::new(&export)?;
+    for key_id in &key_ids {
+        signed.sign_with(&km, *key_id)?;
+    }
+
+    let encoding = match format {
+        BundleFormat::Json => ExportEncoding::Json,
+        BundleFormat::Cbor => ExportEncoding::Cbor,
+    };
+    let mut bytes = signed.to_bytes(encoding)?;
+    if !recipients.is_empty() {
+        let format = if age {
+            SealFormat::Age
+        } else {
+            SealFormat::Envelope
+        };
+        bytes = envelope::seal(&bytes, &recipients, format)?;
+    }
+    let output = dir.join(output);
+    januskey::backend::write_atomic(&output, &bytes)
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+
+    println!(
+        "{} Exported {} operations to {}",
+        "✓".green(),
+        export.operations.len(),
+        output.display()
+    );
+    for signature in &signed.signatures {
+        println!(
+            "  Signed by {} (fingerprint {})",
+            signature.key_id.to_string().cyan(),
+            signature.fingerprint
+        );
+        println!("  Public key: {}", signature.public_key.dimmed());
+    }
+    if age {
+        println!(
+            "  Encrypted to {} recipient(s) as an age file",
+            recipients.len()
+        );
+    } else if !recipients.is_empty() {
+        println!("  Encrypted to {} recipient(s)", recipients.len());
+    }
+
+    Ok(())
+}
+
+fn parse_key_id(id: &str) -> Result<uuid::Uuid> {
+    uuid::Uuid::parse_str(id).with_context(|| format!("Invalid key ID: {}", id))
+}
+
+/// Open and unlock the key store in `dir`, with the passphrase from
+/// $JANUSKEY_PASSPHRASE (a token's PIN from $JANUSKEY_PIN) or a prompt
+fn unlock_key_store(dir: &Path) -> Result<KeyManager> {
+    let mut km = KeyManager::new(dir);
+    if !km.is_initialized() {
+        anyhow::bail!("Key store not initialized. Run 'jk keys init' first.");
+    }
+    match km.unlock_with_tpm() {
+        Ok(true) => return Ok(km),
+        Ok(false) => {}
+        Err(e) => eprintln!(
+            "{} TPM unlock failed ({}); falling back to the passphrase",
+            "!".yellow(),
+            e
+        ),
+    }
+    let (env, prompt) = if km.uses_token() {
+        (januskey::pkcs11::PIN_ENV, "Enter token PIN")
+    } else {
+        ("JANUSKEY_PASSPHRASE", "Enter key store passphrase")
+    };
+    let passphrase = match std::env::var(env) {
+        Ok(passphrase) => passphrase,
+        Err(_) => dialoguer::Password::new().with_prompt(prompt).interact()?,
+    };
+    km.unlock(&passphrase)?;
+    Ok(km)
+}
+
+fn cmd_history_verify_export(
+    dir: &Path,
+    file: &Path,
+    key: Option<String>,
+    identity: Option<String>,
+) -> Result<()> {
+    let file = dir.join(file);
+    let mut bytes =
+        std::fs::read(&file).with_context(|| format!("Failed to read {}", file.display()))?;
+    if envelope::is_sealed(&bytes) {
+        let Some(identity) = identity else {
+            anyhow::bail!(
+                "{} is encrypted; pass --identity <KEY_ID> with an X25519 key it was encrypted to",
+                file.display()
+            );
+        };
+        let km = unlock_key_store(dir)?;
+        bytes = envelope::open_with(&bytes, &km, parse_key_id(&identity)?)?;
+        println!("{} Decrypted with key {}", "✓".green(), identity.cyan());
+    }
+    let signed = SignedExport::from_bytes(&bytes)?;
+
+    let export = match signed.verify() {
+        Ok(export) => export,
+        Err(e) => {
+            println!("{} {}", "✗".red(), e);
+            anyhow::bail!("Export verification failed");
+        }
+    };
+
+    if let Some(key) = key {
+        if !signed
+            .signatures
+            .iter()
+            .any(|s| s.public_key.eq_ignore_ascii_case(&key))
+        {
+            println!("{} Not signed by {}", "✗".red(), key);
+            anyhow::bail!("Export verification failed");
+        }
+    }
+
+    println!("{}", "History Export".bold());
+    println!("{}", "─".repeat(70));
+    println!("  Source:      {}", export.source);
+    println!(
+        "  Exported at: {}",
+        export.exported_at.format("%Y-%m-%d %H:%M:%S")
+    );
+    for signature in &signed.signatures {
+        println!(
+            "  {} Signature by {} (fingerprint {})",
+            "✓".green(),
+            signature.key_id.to_string().cyan(),
+            signature.fingerprint
+        );
+        println!("    Public key: {}", signature.public_key.dimmed());
+    }
+    println!("{}", "─".repeat(70));
+
+    for op in &export.operations {
+        println!(
+            "{} | {:8} | {} | {}",
+            op.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            op.op_type.to_string(),
+            op.path.display(),
+            recorded_by(op).dimmed()
+        );
+    }
+
+    println!("{}", "─".repeat(70));
+    println!(
+        "{} {} operations, all signatures valid",
+        "✓".green(),
+        export.operations.len()
+    );
+
+    Ok(())
+}
+
+fn cmd_sign(
+    dir: &Path,
+    file: &Path,
+    key: &str,
+    output: Option<&Path>,
+    dry_run: bool,
+) -> Result<()> {
+    let mut jk = shell::open(dir)?;
+
+    let file = dir.join(file);
+    let output = match output {
+        Some(output) => dir.join(output),
+        None => {
+            let mut name = file.clone().into_os_string();
+            name.push(".sig");
+            PathBuf::from(name)
+        }
+    };
+    let key_id = parse_key_id(key)?;
+    let content =
+        std::fs::read(&file).with_context(|| format!("Failed to read {}", file.display()))?;
+
+    if dry_run {
+        println!(
+            "{} Would sign {} with key {} into {}",
+            "[DRY RUN]".cyan(),
+            file.display(),
+            key_id.to_string().cyan(),
+            output.display()
+        );
+        return Ok(());
+    }
+
+    let km = unlock_key_store(dir)?;
+    let signature = FileSignature::sign_with(&km, key_id, &content)?;
+    let bytes = signature.to_bytes()?;
+
+    // Record the signature file so `jk undo` removes (or restores) it
+    let operation = if output.exists() {
+        FileOperation::Modify {
+            path: output.clone(),
+            new_content: bytes,
+        }
+    } else {
+        FileOperation::Create {
+            path: output.clone(),
+            content: bytes,
+            mode: None,
+        }
+    };
+    jk.execute(operation)?;
+
+    println!(
+        "{} Signed {} into {}",
+        "✓".green(),
+        file.display(),
+        output.display()
+    );
+    println!(
+        "  Key {} (fingerprint {})",
+        key_id.to_string().cyan(),
+        signature.signature.fingerprint
+    );
+    println!("  Public key: {}", signature.signature.public_key.dimmed());
+
+    Ok(())
+}
+
+fn cmd_verify(dir: &Path, file: &Path, signature: &Path, key: Option<&str>) -> Result<()> {
+    let (file, signature_path) = (dir.join(file), dir.join(signature));
+    let content =
+        std::fs::read(&file).with_context(|| format!("Failed to read {}", file.display()))?;
+    let bytes = std::fs::read(&signature_path)
+        .with_context(|| format!("Failed to read {}", signature_path.display()))?;
+    let signature = FileSignature::from_bytes(&bytes)
+        .with_context(|| format!("{} is not a signature file", signature_path.display()))?;
+
+    if let Err(e) = signature.verify(&content) {
+        println!("{} {}", "✗".red(), e);
+        anyhow::bail!("Signature verification failed");
+    }
+    if let Some(key) = key {
+        if !signature.signature.public_key.eq_ignore_ascii_case(key) {
+            println!("{} Not signed by {}", "✗".red(), key);
+            anyhow::bail!("Signature verification failed");
+        }
+    }
+
+    println!(
+        "{} Good signature on {} by {} (fingerprint {})",
+        "✓".green(),
+        file.display(),
+        signature.signature.key_id.to_string().cyan(),
+        signature.signature.fingerprint
+    );
+    println!(
+        "  Signed at:  {}",
+        signature.signed_at.format("%Y-%m-%d %H:%M:%S")
+    );
+    println!("  Public key: {}", signature.signature.public_key.dimmed());
+
+    Ok(())
+}
+
+fn cmd_timeline(
+    dir: &Path,
+    path: &Path,
+    restore: Option<usize>,
+    diff: Option<Vec<usize>>,
+    dry_run: bool,
+) -> Result<()> {
+    let mut jk = shell::open(dir)?;
+    let target = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        dir.join(path)
+    };
+    if restore.is_some() || diff.is_some() {
+        unlock_content(&mut jk)?;
+    }
+
+    let entries: Vec<_> = jk
+        .metadata_store
+        .path_history(&target)
+        .into_iter()
+        .cloned()
+        .collect();
+    if entries.is_empty() {
+        println!("{} No history for {}", "!".yellow(), path.display());
+        return Ok(());
+    }
+
+    // Content captured before entry N (1-based)
+    let snapshot = |n: usize| -> Result<Vec<u8>> {
+        let entry = entries
+            .get(n.wrapping_sub(1))
+            .ok_or_else(|| anyhow::anyhow!("No timeline entry {} (1-{})", n, entries.len()))?;
+        if entry.content_hash.is_none() {
+            anyhow::bail!("Entry {} ({}) has no stored content", n, entry.op_type);
+        }
+        Ok(jk.captured_content(entry)?)
+    };
+
+    if let Some(n) = restore {
+        let content = snapshot(n)?;
+        if dry_run {
+            println!(
+                "{} Would restore {} to entry {} ({})",
+                "[DRY RUN]".cyan(),
+                path.display(),
+                n,
+                human_bytes(content.len() as u64)
+            );
+            return Ok(());
+        }
+
+        let op = if target.exists() {
+            FileOperation::Modify {
+                path: target.clone(),
+                new_content: content,
+            }
+        } else {
+            FileOperation::Create {
+                path: target.clone(),
+                content,
+                mode: None,
+            }
+        };
+
+        jk.execute(op)?;
+
+        println!("{} Restored {} to entry {}", "✓".green(), path.display(), n);
+        println!("  Use {} to revert", "jk undo".cyan());
+        return Ok(());
+    }
+
+    if let Some(pair) = diff {
+        let (old, new) = (snapshot(pair[0])?, snapshot(pair[1])?);
+        if old == new {
+            println!(
+                "{} Entries {} and {} are identical",
+                "✓".green(),
+                pair[0],
+                pair[1]
+            );
+        } else {
+            print_diff(&old, &new);
+        }
+        return Ok(());
+    }
+
+    println!("{} {}", "Timeline:".bold(), path.display());
+    let previous = jk.metadata_store.previous_names(&target);
+    if !previous.is_empty() {
+        let names: Vec<_> = previous
+            .iter()
+            .map(|p| p.strip_prefix(dir).unwrap_or(p).display().to_string())
+            .collect();
+        println!("  previously: {}", names.join(", ").dimmed());
+    }
+    println!("{}", "─".repeat(70));
+
+    for (i, op) in entries.iter().enumerate() {
+        let size = op
+            .original_metadata
+            .as_ref()
+            .map(|m| human_bytes(m.size))
+            .unwrap_or_else(|| "-".to_string());
+        let hash = op
+            .content_hash
+            .as_ref()
+            .map(|h| h.raw_hash()[..12.min(h.raw_hash().len())].to_string())
+            .unwrap_or_else(|| "-".to_string());
+        let shown = match &op.path_secondary {
+            Some(dest) => format!(
+                "{} -> {}",
+                op.path.strip_prefix(dir).unwrap_or(&op.path).display(),
+                dest.strip_prefix(dir).unwrap_or(dest).display()
+            ),
+            None => op
+                .path
+                .strip_prefix(dir)
+                .unwrap_or(&op.path)
+                .display()
+                .to_string(),
+        };
+        let status = if op.undone {
+            "[UNDONE]".dimmed()
+        } else {
+            "".normal()
+        };
+
+        println!(
+            "{:>3} | {} | {:8} | {:>12} | {:12} | {} {}",
+            i + 1,
+            op.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            op.op_type.to_string(),
+            size,
+            hash,
+            shown,
+            status
+        );
+    }
+
+    println!("{}", "─".repeat(70));
+    println!(
+        "Restore with {} or compare with {}",
+        "--restore N".cyan(),
+        "--diff N M".cyan()
+    );
+
+    Ok(())
+}
+
+fn cmd_status(dir: &Path) -> Result<()> {
+    let mut repo = Repo::open(dir)?;
+    let status = repo.status()?;
+
+    println!("{}", "JanusKey Status".bold());
+    println!("{}", "─".repeat(40));
+    println!("Directory: {}", dir.display());
+    if let Some(uri) = &status.storage {
+        println!("Storage: {}", uri);
+    }
+    println!("Operations logged: {}", status.operations);
+    println!(
+        "Content store: {} blobs ({} bytes)",
+        status.blobs,
+        human_bytes(status.bytes)
+    );
+    if status.offloaded > 0 {
+        println!("Offloaded: {} blobs on the cold tier", status.offloaded);
+    }
+    #[cfg(unix)]
+    if let Repo::Daemon(_) = repo {
+        println!("Served by: jk daemon");
+    }
+
+    if let Some(tx) = &status.active_transaction {
+        let name = tx.name.clone().unwrap_or_else(|| tx.id[..8].to_string());
+        println!();
+        println!("📝 Active transaction: {}", name.cyan());
+        println!("  Started: {}", tx.started_at.format("%Y-%m-%d %H:%M:%S"));
+        println!("  Operations: {}", tx.operation_ids.len());
+    } else {
+        println!();
+        println!("No active transaction");
+    }
+
+    Ok(())
+}
+
+fn cmd_stats(dir: &Path, top: usize, json: bool) -> Result<()> {
+    let jk = shell::open(dir)?;
+    let mut usage = jk.byte_usage();
+    usage.by_path.truncate(top);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&usage)?);
+        return Ok(());
+    }
+
+    let row = |label: &str, totals: &ByteTotals| {
+        println!(
+            "  {:14} {:>6} ops {:>10} read {:>10} stored {:>5}",
+            label,
+            totals.operations,
+            human_bytes(totals.read),
+            human_bytes(totals.stored),
+            format_ratio(totals.ratio(), false)
+        );
+    };
+
+    println!("{}", "Content Store Usage".bold());
+    println!("{}", "─".repeat(70));
+    row("Total", &usage.total);
+    for (op_type, totals) in &usage.by_type {
+        row(op_type, totals);
+    }
+    if usage.delta.operations > 0 {
+        row("As deltas", &usage.delta);
+    }
+    if usage.deduplicated > 0 {
+        println!(
+            "  {:14} {:>6} ops already stored",
+            "Deduplicated", usage.deduplicated
+        );
+    }
+
+    if !usage.by_path.is_empty() {
+        println!();
+        println!("{}", "Largest by stored bytes".bold());
+        for entry in &usage.by_path {
+            println!(
+                "  {:>10} stored {:>10} read {:>4} ops  {}",
+                human_bytes(entry.totals.stored),
+                human_bytes(entry.totals.read),
+                entry.totals.operations,
+                entry.path.display()
+            );
+        }
+    }
+
+    println!("{}", "─".repeat(70));
+    println!(
+        "Content store: {} on disk",
+        human_bytes(jk.content_store.total_size()?)
+    );
+    if usage.unaccounted > 0 {
+        println!(
+            "{} {} operations predate byte accounting and are not counted",
+            "!".yellow(),
+            usage.unaccounted
+        );
+    }
+
+    Ok(())
+}
+
+fn cmd_remote_add(dir: &Path, name: &str, uri: &str) -> Result<()> {
+    let mut jk = shell::open(dir)?;
+    if jk.config.remotes.contains_key(name) {
+        anyhow::bail!("Remote {} already exists", name);
+    }
+    StorageUri::parse(uri)?;
+    jk.config.remotes.insert(name.to_string(), uri.to_string());
+    jk.config.save(&jk.root)?;
+    println!("{} Added remote {} at {}", "✓".green(), name.bold(), uri);
+    Ok(())
+}
+
+fn cmd_remote_remove(dir: &Path, name: &str) -> Result<()> {
+    let mut jk = shell::open(dir)?;
+    if jk.config.remotes.remove(name).is_none() {
+        return Err(JanusError::RemoteNotFound(name.to_string()).into());
+    }
+    jk.config.save(&jk.root)?;
+    println!("{} Removed remote {}", "✓".green(), name.bold());
+    Ok(())
+}
+
+fn cmd_remote_list(dir: &Path, json: bool) -> Result<()> {
+    let jk = shell::open(dir)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&jk.config.remotes)?);
+        return Ok(());
+    }
+    if jk.config.remotes.is_empty() {
+        println!("No remotes");
+    }
+    for (name, uri) in &jk.config.remotes {
+        println!("{} | {}", name.bold(), uri);
+    }
+    Ok(())
+}
+
+fn cmd_token_add(dir: &Path, name: &str, role: Role, json: bool) -> Result<()> {
+    let mut jk = shell::open(dir)?;
+    if jk
+        .config
+        .daemon_tokens
+        .iter()
+        .any(|token| token.name == name)
+    {
+        anyhow::bail!("Token {} already exists", name);
+    }
+    let (record, token) = ApiToken::issue(name, role)?;
+    jk.config.daemon_tokens.push(record);
+    jk.config.save(&jk.root)?;
+
+    if json {
+        let issued = serde_json::json!({ "name": name, "role": role, "token": token });
+        println!("{}", serde_json::to_string_pretty(&issued)?);
+        return Ok(());
+    }
+    println!("{} Issued {} token {}", "✓".green(), role, name.bold());
+    println!("  {}", token);
+    println!(
+        "  Shown once; clients set {} to it. A running daemon accepts it once restarted.",
+        TOKEN_ENV.cyan()
+    );
+    Ok(())
+}
+
+fn cmd_token_remove(dir: &Path, name: &str) -> Result<()> {
+    let mut jk = shell::open(dir)?;
+    let before = jk.config.daemon_tokens.len();
+    jk.config.daemon_tokens.retain(|token| token.name != name);
+    if jk.config.daemon_tokens.len() == before {
+        anyhow::bail!("No token named {}", name);
+    }
+    jk.config.save(&jk.root)?;
+    println!(
+        "{} Revoked token {}; a running daemon accepts it until restarted",
+        "✓".green(),
+        name.bold()
+    );
+    Ok(())
+}
+
+fn cmd_token_list(dir: &Path, json: bool) -> Result<()> {
+    let jk = shell::open(dir)?;
+    let tokens = &jk.config.daemon_tokens;
+    if json {
+        let rows: Vec<_> = tokens
+            .iter()
+            .map(|token| {
+                serde_json::json!({
+                    "name": token.name,
+                    "role": token.role,
+                    "created_at": token.created_at,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+    if tokens.is_empty() {
+        println!("No tokens; the daemon serves only its own user");
+    }
+    for token in tokens {
+        println!(
+            "{} | {} | issued {}",
+            token.name.bold(),
+            token.role,
+            token.created_at.format("%Y-%m-%d %H:%M:%S")
+        );
+    }
+    Ok(())
+}
+
+/// The config of the JanusKey directory `dir`, without opening its stores
+/// (so a setting that stops them opening can still be fixed)
+fn load_config(dir: &Path) -> Result<Config> {
+    if !dir.join(".januskey").exists() {
+        return Err(JanusError::NotInitialized(dir.display().to_string()).into());
+    }
+    Ok(Config::load(dir)?)
+}
+
+/// Print a setting's value: strings bare, anything else as JSON
+fn config_value(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn cmd_config_get(dir: &Path, key: &str, json: bool) -> Result<()> {
+    let value = load_config(dir)?.get(key)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&value)?);
+    } else {
+        println!("{}", config_value(&value));
+    }
+    Ok(())
+}
+
+fn cmd_config_set(dir: &Path, key: &str, value: &str) -> Result<()> {
+    if key == "daemon_tokens" || key.starts_with("daemon_tokens.") {
+        anyhow::bail!("daemon tokens are managed with `jk token`");
+    }
+    let mut config = load_config(dir)?;
+    let before = config.get(key)?;
+    config.set(key, value)?;
+    config.validate(dir)?;
+    config.save(dir)?;
+
+    let after = config.get(key)?;
+    println!(
+        "{} {} = {} (was {})",
+        "✓".green(),
+        key.bold(),
+        config_value(&after),
+        config_value(&before)
+    );
+    if ["storage_path", "workspace", "content_storage"]
+        .iter()
+        .any(|moved| key.split('.').next() == Some(*moved))
+    {
+        println!(
+            "  {} History already recorded stays where it was",
+            "!".yellow()
+        );
+    }
+    Ok(())
+}
+
+fn cmd_config_list(dir: &Path, json: bool) -> Result<()> {
+    let config = load_config(dir)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&config)?);
+        return Ok(());
+    }
+
+    // Settings in nested objects are listed by their dotted paths
+    fn list(prefix: &str, value: &serde_json::Value) {
+        match value {
+            serde_json::Value::Object(settings) if !settings.is_empty() => {
+                for (key, value) in settings {
+                    list(&format!("{}{}.", prefix, key), value);
+                }
+            }
+            value => println!(
+                "{} = {}",
+                prefix.trim_end_matches('.').bold(),
+                config_value(value)
+            ),
+        }
+    }
+    list("", &serde_json::to_value(&config)?);
+    Ok(())
+}
+
+fn cmd_workspace_list(dir: &Path, json: bool) -> Result<()> {
+    let jk = shell::open(dir)?;
+    let workspaces = jk.workspaces()?;
+    let mut counts = std::collections::HashMap::new();
+    for op in jk.metadata_store.other_workspaces() {
+        *counts.entry(op.workspace.as_deref()).or_insert(0) += 1;
+    }
+    let current = jk.metadata_store.workspace();
+    let rows: Vec<_> = workspaces
+        .roots
+        .iter()
+        .map(|(id, root)| {
+            let is_current = Some(id.as_str()) == current;
+            let operations = if is_current {
+                jk.metadata_store.count()
+            } else {
+                counts.get(&Some(id.as_str())).copied().unwrap_or(0)
+            };
+            (id, root, operations, is_current)
+        })
+        .collect();
+
+    if json {
+        let rows: Vec<_> = rows
+            .iter()
+            .map(|(id, root, operations, current)| {
+                serde_json::json!({
+                    "id": id,
+                    "root": root,
+                    "operations": operations,
+                    "current": current,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+    if rows.is_empty() {
+        println!(
+            "No workspaces; set storage_path and run {} to share a store",
+            "jk workspace join".cyan()
+        );
+    }
+    for (id, root, operations, current) in rows {
+        let marker = if current { "*" } else { " " };
+        println!(
+            "{} {} | {} | {} operations",
+            marker,
+            id.bold(),
+            root.display(),
+            operations
+        );
+    }
+    Ok(())
+}
+
+fn cmd_workspace_join(dir: &Path, id: Option<String>) -> Result<()> {
+    let jk = JanusKey::open(dir).context("Failed to open JanusKey directory")?;
+    if jk.config.storage_uri()?.is_none() {
+        println!(
+            "{} History is kept in .januskey/; set storage_path to share a store",
+            "!".yellow()
+        );
+    }
+    let jk = jk.join_workspace(id.as_deref())?;
+    println!(
+        "{} {} is workspace {}",
+        "✓".green(),
+        jk.root.display(),
+        jk.config.workspace.as_deref().unwrap_or_default().bold()
+    );
+    Ok(())
+}
+
+/// Direction `cmd_sync` copies the history in
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SyncDirection {
+    Push,
+    Pull,
+}
+
+fn cmd_sync(
+    dir: &Path,
+    remote: Option<String>,
+    direction: SyncDirection,
+    dry_run: bool,
+    json: bool,
+) -> Result<()> {
+    let mut jk = shell::open(dir)?;
+    let name = match remote {
+        Some(name) => name,
+        None => match jk.config.remotes.keys().collect::<Vec<_>>().as_slice() {
+            [name] => name.to_string(),
+            [] => anyhow::bail!("No remotes; add one with `jk remote add <name> <uri>`"),
+            _ => anyhow::bail!("Several remotes are configured; name one"),
+        },
+    };
+    let mut remote = jk.remote(&name)?;
+    let (verb, preposition) = match direction {
+        SyncDirection::Push => ("push", "to"),
+        SyncDirection::Pull => ("pull", "from"),
+    };
+
+    if dry_run {
+        let plan = match direction {
+            SyncDirection::Push => januskey::SyncPlan::new(
+                jk.metadata_store.operations(),
+                remote.metadata_store.operations(),
+            )?,
+            SyncDirection::Pull => januskey::SyncPlan::new(
+                remote.metadata_store.operations(),
+                jk.metadata_store.operations(),
+            )?,
+        };
+        println!(
+            "{} Would {} {} operations and {} undos {} {}",
+            "[DRY RUN]".cyan(),
+            verb,
+            plan.operations.len(),
+            plan.undone.len(),
+            preposition,
+            name.bold()
+        );
+        return Ok(());
+    }
+
+    let report = match direction {
+        SyncDirection::Push => jk.push(&mut remote)?,
+        SyncDirection::Pull => jk.pull(&remote)?,
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+    if report.operations == 0 && report.undone == 0 {
+        println!("{} Nothing to {}", "✓".green(), verb);
+    } else {
+        println!(
+            "{} {} {} operations and {} undos {} {} ({} blobs, {})",
+            "✓".green(),
+            match direction {
+                SyncDirection::Push => "Pushed",
+                SyncDirection::Pull => "Pulled",
+            },
+            report.operations,
+            report.undone,
+            preposition,
+            name.bold(),
+            report.blobs,
+            human_bytes(report.bytes)
+        );
+    }
+    if report.behind > 0 {
+        let (side, hint) = match direction {
+            SyncDirection::Push => (name.as_str(), "jk pull"),
+            SyncDirection::Pull => ("This store", "jk push"),
+        };
+        println!(
+            "{} {} has {} operations the other side lacks; copy them with {}",
+            "!".yellow(),
+            side,
+            report.behind,
+            hint.cyan()
+        );
+    }
+    Ok(())
+}
+
+fn cmd_tier(dir: &Path, recall: bool, dry_run: bool) -> Result<()> {
+    let jk = shell::open(dir)?;
+    let storage = jk
+        .config
+        .tiering
+        .as_ref()
+        .map(|policy| policy.storage.clone())
+        .context("No tiering policy is configured; set `tiering` in .januskey/config.json")?;
+    let total = |blobs: &[(januskey::ContentHash, u64)]| blobs.iter().map(|(_, size)| size).sum();
+
+    if recall {
+        if dry_run {
+            let blobs = jk.content_store.offloaded()?;
+            println!(
+                "{} Would recall {} blobs ({}) from {}",
+                "[DRY RUN]".cyan(),
+                blobs.len(),
+                human_bytes(total(&blobs)),
+                storage
+            );
+            return Ok(());
+        }
+        let blobs = jk.recall()?;
+        println!(
+            "{} Recalled {} blobs ({}) from {}",
+            "✓".green(),
+            blobs.len(),
+            human_bytes(total(&blobs)),
+            storage
+        );
+        return Ok(());
+    }
+
+    if dry_run {
+        let blobs = jk.tier_plan()?;
+        println!(
+            "{} Would offload {} blobs ({}) to {}:",
+            "[DRY RUN]".cyan(),
+            blobs.len(),
+            human_bytes(total(&blobs)),
+            storage
+        );
+        for (hash, size) in &blobs {
+            println!("  - {} ({})", hash, human_bytes(*size));
+        }
+        return Ok(());
+    }
+
+    let blobs = jk.tier()?;
+    if blobs.is_empty() {
+        println!("{} Nothing to offload", "✓".green());
+    } else {
+        println!(
+            "{} Offloaded {} blobs ({}) to {}",
+            "✓".green(),
+            blobs.len(),
+            human_bytes(total(&blobs)),
+            storage
+        );
+    }
+    Ok(())
+}
+
+fn cmd_gc(
+    dir: &Path,
+    keep: Option<usize>,
+    older_than: Option<&str>,
+    keep_latest: bool,
+    compact: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let mut jk = shell::open(dir)?;
+
+    let newer_than = older_than
+        .map(|age| januskey::retention::cutoff_before(age, chrono::Utc::now()))
+        .transpose()?;
+    // An age limit alone decides what goes, unless --keep is given too
+    let default_keep = if newer_than.is_some() {
+        0
+    } else {
+        jk.config.max_history
+    };
+    let rule = PruneRule {
+        keep: keep.unwrap_or(default_keep),
+        newer_than,
+        latest_per_path: keep_latest,
+    };
+    let kept = describe_prune_rule(&rule);
+
+    if dry_run {
+        if compact {
+            println!(
+                "{} Dry run - would rewrite the logs compressed",
+                "[DRY RUN]".cyan()
+            );
+        }
+        let plan = jk.gc_plan_by(&rule)?;
+        print_held(plan.held);
+        if plan.operations.is_empty() && plan.blobs.is_empty() {
+            println!("{} Nothing to prune", "✓".green());
+            return Ok(());
+        }
+
+        println!(
+            "{} Dry run - would prune {} operations ({}):",
+            "[DRY RUN]".cyan(),
+            plan.operations.len(),
+            kept
+        );
+        for op in &plan.operations {
+            println!(
+                "  - {} {:8} {}",
+                op.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                op.op_type.to_string(),
+                op.path.display()
+            );
+        }
+        println!(
+            "{} Dry run - would delete {} unreferenced blobs ({}):",
+            "[DRY RUN]".cyan(),
+            plan.blobs.len(),
+            human_bytes(plan.blob_bytes())
+        );
+        for (hash, size) in &plan.blobs {
+            println!("  - {} ({})", hash, human_bytes(*size));
+        }
+        return Ok(());
+    }
+
+    let plan = jk.gc_by(&rule)?;
+    print_held(plan.held);
+
+    if plan.operations.is_empty() && plan.blobs.is_empty() {
+        println!("{} Nothing to prune", "✓".green());
+    } else {
+        println!(
+            "{} Pruned {} old operations ({})",
+            "✓".green(),
+            plan.operations.len(),
+            kept
+        );
+        println!(
+            "{} Deleted {} unreferenced blobs ({})",
+            "✓".green(),
+            plan.blobs.len(),
+            human_bytes(plan.blob_bytes())
+        );
+    }
+
+    if compact {
+        let compressed = jk.compress_logs()?;
+        if compressed.files == 0 {
+            println!("{} Logs already compressed", "✓".green());
+        } else {
+            println!(
+                "{} Compressed {} log files ({} -> {})",
+                "✓".green(),
+                compressed.files,
+                human_bytes(compressed.before),
+                human_bytes(compressed.after)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// What a prune rule keeps, for gc's summary
+fn describe_prune_rule(rule: &PruneRule) -> String {
+    let mut kept = Vec::new();
+    if rule.keep > 0 || rule.newer_than.is_none() {
+        kept.push(format!("the last {}", rule.keep));
+    }
+    if let Some(since) = rule.newer_than {
+        kept.push(format!("those since {}", since.format("%Y-%m-%d %H:%M")));
+    }
+    if rule.latest_per_path {
+        kept.push("the latest version of each path".to_string());
+    }
+    format!("keeping {}", kept.join(" and "))
+}
+
+/// Note operations gc keeps for legal holds
+fn print_held(held: usize) {
+    if held > 0 {
+        println!(
+            "{} Keeping {} operations under legal hold (see {})",
+            "!".yellow(),
+            held,
+            "jk hold list".cyan()
+        );
+    }
+}
+
+fn cmd_compact(dir: &Path) -> Result<()> {
+    let mut jk = shell::open(dir)?;
+
+    let (before, after) = jk.metadata_store.compact()?;
+    println!(
+        "{} Compacted operation log: {} segments -> {} ({} operations)",
+        "✓".green(),
+        before,
+        after,
+        jk.metadata_store.count()
+    );
+
+    let backend = jk.content_store.backend();
+    if !backend.capabilities().range_reads {
+        println!(
+            "{} Left blobs unpacked: the {} backend cannot read part of a pack",
+            "!".yellow(),
+            backend.name()
+        );
+        return Ok(());
+    }
+    let packed = jk
+        .content_store
+        .pack(januskey::content_store::PACK_LOOSE_MAX_BYTES)?;
+    println!(
+        "{} Packed {} small blobs ({} blobs stored)",
+        "✓".green(),
+        packed,
+        jk.content_store.count()?
+    );
+
+    Ok(())
+}
+
+fn cmd_doctor(dir: &Path, json: bool) -> Result<()> {
+    let report = januskey::diagnose(dir);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    for check in &report.checks {
+        let mark = match check.status {
+            CheckStatus::Ok => "✓".green(),
+            CheckStatus::Warn => "!".yellow(),
+            CheckStatus::Fail => "✗".red(),
+        };
+        println!("{} {:<12} {}", mark, check.name, check.message);
+    }
+    let count = |status| report.checks.iter().filter(|c| c.status == status).count();
+    match report.status {
+        CheckStatus::Ok => println!("\n{} No problems found", "✓".green()),
+        CheckStatus::Warn => println!("\n{} {} warnings", "!".yellow(), count(CheckStatus::Warn)),
+        CheckStatus::Fail => anyhow::bail!(
+            "{} checks failed, {} warnings",
+            count(CheckStatus::Fail),
+            count(CheckStatus::Warn)
+        ),
+    }
+    Ok(())
+}
+
+fn cmd_heal(dir: &Path, dry_run: bool) -> Result<()> {
+    let report = JanusKey::heal(dir, dry_run).context("Failed to heal JanusKey directory")?;
+    let prefix = if dry_run {
+        format!("{} ", "[DRY RUN]".cyan())
+    } else {
+        String::new()
+    };
+
+    if report.is_clean() {
+        println!(
+            "{} History is intact ({} operations)",
+            "✓".green(),
+            report.operations
+        );
+        return Ok(());
+    }
+
+    if !report.damaged.is_empty() {
+        println!(
+            "{}{} {} unreadable log lines {}:",
+            prefix,
+            "!".yellow(),
+            report.damaged.len(),
+            if dry_run {
+                "would be moved to lost+found"
+            } else {
+                "moved to lost+found"
+            }
+        );
+        for record in &report.damaged {
+            println!(
+                "  - segment {} line {}: {}",
+                record.segment, record.line, record.error
+            );
+        }
+    }
+    if let Some(error) = &report.transactions_error {
+        println!(
+            "{}{} Transaction log unreadable ({}); {} {} transactions from operations",
+            prefix,
+            "!".yellow(),
+            error,
+            if dry_run { "would rebuild" } else { "rebuilt" },
+            report.transactions_rebuilt
+        );
+    }
+    if !report.missing_operations.is_empty() {
+        println!(
+            "{} {} operations are lost (listed by a transaction, not in the log):",
+            "✗".red(),
+            report.missing_operations.len()
+        );
+        for (tx_id, op_id) in &report.missing_operations {
+            println!("  - {} in transaction {}", &op_id[..8], &tx_id[..8]);
+        }
+    }
+    if !report.missing_content.is_empty() {
+        println!(
+            "{} {} operations cannot be undone (stored content missing):",
+            "✗".red(),
+            report.missing_content.len()
+        );
+        for (op_id, hash) in &report.missing_content {
+            println!("  - {} needs {}", &op_id[..8], hash);
+        }
+    }
+    if !report.orphaned_blobs.is_empty() {
+        let bytes: u64 = report.orphaned_blobs.iter().map(|(_, size)| size).sum();
+        println!(
+            "{} {} blobs ({}) belong to no operation; `jk gc` deletes them",
+            "!".yellow(),
+            report.orphaned_blobs.len(),
+            human_bytes(bytes)
+        );
+    }
+    println!(
+        "{}{} {} operations recovered",
+        prefix,
+        "✓".green(),
+        report.operations
+    );
+
+    Ok(())
+}
+
+fn cmd_blame(dir: &Path, path: &Path, json: bool) -> Result<()> {
+    let mut jk = shell::open(dir)?;
+    unlock_content(&mut jk)?;
+    let blame = jk.blame(path)?;
+    let origin = |line: &BlameLine| {
+        line.operation
+            .as_deref()
+            .and_then(|id| jk.metadata_store.get(id))
+    };
+
+    if json {
+        let lines: Vec<_> = blame
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                serde_json::json!({
+                    "line": i + 1,
+                    "text": line.text,
+                    "operation": origin(line),
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&lines)?);
+        return Ok(());
+    }
+
+    let width = blame.len().to_string().len();
+    for (i, line) in blame.iter().enumerate() {
+        let annotation = match origin(line) {
+            Some(op) => format!(
+                "{} {} {:12}",
+                op.id[..8].yellow(),
+                op.timestamp.format("%Y-%m-%d %H:%M"),
+                recorded_by(op)
+            ),
+            // Predates the history or was edited outside JanusKey
+            None => format!("{} {:16} {:12}", "--------".dimmed(), "", ""),
+        };
+        println!("{} {:>width$}) {}", annotation, i + 1, line.text);
+    }
+
+    Ok(())
+}
+
+fn cmd_search(
+    dir: &Path,
+    pattern: Option<&str>,
+    regex: bool,
+    ignore_case: bool,
+    reindex: bool,
+    json: bool,
+) -> Result<()> {
+    let jk = shell::open(dir)?;
+
+    if reindex {
+        let added = jk.search_index().update(&jk.content_store)?;
+        if !json {
+            println!("{} Indexed {} new blobs", "✓".green(), added);
+        }
+    }
+    let Some(pattern) = pattern else {
+        return Ok(());
+    };
+
+    let query = if regex {
+        Query::regex(pattern, ignore_case)?
+    } else {
+        Query::literal(pattern, ignore_case)?
+    };
+    let hits = jk.search(&query)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&hits)?);
+        return Ok(());
+    }
+
+    if hits.is_empty() {
+        println!("No recorded version contains {:?}", pattern);
+        return Ok(());
+    }
+    for hit in &hits {
+        let op = &hit.operation;
+        println!(
+            "{} {} {:8} {} ({}){}",
+            op.id[..8].yellow(),
+            op.timestamp.format("%Y-%m-%d %H:%M"),
+            op.op_type.to_string(),
+            op.path.display(),
+            hit.version,
+            if hit.binary { " [binary]" } else { "" }
+        );
+        for line in &hit.lines {
+            println!("  {:>5}: {}", line.line, line.text);
+        }
+    }
+    println!("{} {} matching versions", "✓".green(), hits.len());
+
+    Ok(())
+}
+
+fn cmd_index_rebuild(dir: &Path, dry_run: bool) -> Result<()> {
+    let jk = shell::open(dir)?;
+
+    if dry_run {
+        let blobs = jk.content_store.count()?;
+        println!(
+            "{} Would discard the index and index {} stored blobs",
+            "[DRY RUN]".cyan(),
+            blobs
+        );
+        return Ok(());
+    }
+
+    let indexed = jk.search_index().rebuild(&jk.content_store)?;
+    println!("{} Indexed {} stored blobs", "✓".green(), indexed);
+    if !jk.config.search_index {
+        println!(
+            "{} search_index is off in the configuration, so content stored from now on is not indexed",
+            "⚠".yellow()
+        );
+    }
     Ok(())
 }
 
-fn cmd_begin(dir: &PathBuf, name: Option<String>) -> Result<()> {
-    let mut jk = JanusKey::open(dir).context("Failed to open JanusKey directory")?;
+fn cmd_index_status(dir: &Path, json: bool) -> Result<()> {
+    let jk = shell::open(dir)?;
+    let index = jk.search_index();
+    let status = index.status(&jk.content_store)?;
 
-    let tx = jk.transaction_manager.begin(name.clone())?;
-    let display_name = name.unwrap_or_else(|| tx.id[..8].to_string());
+    if json {
+        let mut value = serde_json::to_value(&status)?;
+        value["built"] = index.exists().into();
+        value["maintained"] = jk.config.search_index.into();
+        println!("{}", serde_json::to_string_pretty(&value)?);
+        return Ok(());
+    }
+
+    if !index.exists() {
+        println!("No content index; build one with `jk index rebuild`");
+        return Ok(());
+    }
+    println!("Indexed:    {} blobs", status.indexed);
     println!(
-        "{} Started transaction: {}",
-        "✓".green(),
-        display_name.cyan()
+        "Unindexed:  {} blobs (always read by searches)",
+        status.missing
     );
+    if status.stale > 0 {
+        println!("Stale:      {} entries for deleted blobs", status.stale);
+    }
+    println!("Size:       {}", human_bytes(status.bytes));
     println!(
-        "  Run operations, then use {} or {}",
-        "jk commit".cyan(),
-        "jk rollback".cyan()
+        "Maintained: {}",
+        if jk.config.search_index {
+            "yes, as content is stored and deleted"
+        } else {
+            "no (search_index is off)"
+        }
     );
-
     Ok(())
 }
 
-fn cmd_commit(dir: &PathBuf) -> Result<()> {
-    let mut jk = JanusKey::open(dir).context("Failed to open JanusKey directory")?;
+fn cmd_snapshot(
+    dir: &Path,
+    name: Option<&str>,
+    exclude: &[String],
+    incremental: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let jk = shell::open(dir)?;
+    let excludes = Excludes::from_ignore_file(&jk.root, exclude)?;
+    let base = match jk.snapshots()?.latest() {
+        Some(latest) if incremental => Some(latest.name.clone()),
+        None if incremental => {
+            println!("{} No earlier snapshot; taking a full one", "!".yellow());
+            None
+        }
+        _ => None,
+    };
+    if dry_run {
+        println!(
+            "{} Would snapshot {} as {}",
+            "[DRY RUN]".cyan(),
+            jk.root.display(),
+            name.unwrap_or("the current time")
+        );
+        return Ok(());
+    }
 
-    let tx = jk.transaction_manager.commit()?;
-    let display_name = tx.name.unwrap_or_else(|| tx.id[..8].to_string());
+    let snapshot = jk.snapshot(name, &excludes, base.as_deref())?;
+    match &snapshot.base {
+        Some(base) => println!(
+            "{} Snapshot {} ({} files, {}; {} changed since {})",
+            "✓".green(),
+            snapshot.name.bold(),
+            snapshot.files,
+            human_bytes(snapshot.bytes),
+            snapshot.changed,
+            base
+        ),
+        None => println!(
+            "{} Snapshot {} ({} files, {})",
+            "✓".green(),
+            snapshot.name.bold(),
+            snapshot.files,
+            human_bytes(snapshot.bytes)
+        ),
+    }
     println!(
-        "{} Committed transaction: {} ({} operations)",
-        "✓".green(),
-        display_name.cyan(),
-        tx.operation_ids.len()
+        "  Use {} to bring the tree back to it",
+        format!("jk restore-snapshot {}", snapshot.name).cyan()
     );
-
     Ok(())
 }
 
-fn cmd_rollback(dir: &PathBuf) -> Result<()> {
-    let mut jk = JanusKey::open(dir).context("Failed to open JanusKey directory")?;
-
-    // Get the active transaction's operation IDs before modifying state
-    let active_tx = jk
-        .transaction_manager
-        .active()
-        .ok_or_else(|| anyhow::anyhow!("No active transaction"))?
-        .clone();
+fn cmd_snapshot_list(dir: &Path, json: bool) -> Result<()> {
+    let jk = shell::open(dir)?;
+    let snapshots = jk.snapshots()?;
 
-    // Undo operations in reverse order (Theorem 3.4: Sequential Reversibility)
-    for op_id in active_tx.operation_ids.iter().rev() {
-        let mut executor = OperationExecutor::new(&jk.content_store, &mut jk.metadata_store);
-        executor.undo(op_id)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(snapshots.all())?);
+        return Ok(());
     }
 
-    // Mark transaction as rolled back
-    let tx = jk.transaction_manager.mark_rolled_back()?;
-    let display_name = tx.name.unwrap_or_else(|| tx.id[..8].to_string());
-    println!(
-        "{} Rolled back transaction: {} ({} operations undone)",
-        "✓".green(),
-        display_name.cyan(),
-        tx.operation_ids.len()
-    );
-
+    if snapshots.all().is_empty() {
+        println!("No snapshots");
+        return Ok(());
+    }
+    for snapshot in snapshots.all() {
+        let kind = match &snapshot.base {
+            Some(base) => format!("{} changed since {}", snapshot.changed, base),
+            None => "full".to_string(),
+        };
+        println!(
+            "{} | {} | {} files | {} | {} | {}",
+            snapshot.name.bold(),
+            snapshot.created_at.format("%Y-%m-%d %H:%M:%S"),
+            snapshot.files,
+            human_bytes(snapshot.bytes),
+            kind.dimmed(),
+            snapshot.created_by
+        );
+    }
     Ok(())
 }
 
-fn cmd_preview(dir: &PathBuf) -> Result<()> {
-    let jk = JanusKey::open(dir).context("Failed to open JanusKey directory")?;
-
-    let tx = jk
-        .transaction_manager
-        .active()
-        .ok_or_else(|| anyhow::anyhow!("No active transaction"))?;
-
-    let preview = TransactionPreview::from_transaction(tx, &jk.metadata_store);
-
-    let name = preview
-        .transaction_name
-        .unwrap_or_else(|| tx.id[..8].to_string());
-    println!("{} Transaction: {}", "📋".to_string(), name.cyan());
-    println!("Operations pending: {}", preview.operations.len());
-    println!();
-
-    for op in &preview.operations {
-        let arrow = if op.secondary_path.is_some() {
-            " → "
-        } else {
-            ""
-        };
-        let secondary = op
-            .secondary_path
-            .as_ref()
-            .map(|p| p.display().to_string())
-            .unwrap_or_default();
+fn cmd_snapshot_diff(dir: &Path, from: &str, to: &str, json: bool) -> Result<()> {
+    let jk = shell::open(dir)?;
+    let snapshots = jk.snapshots()?;
+    let old = snapshots.manifest(snapshots.get(from)?, &jk.content_store)?;
+    let new = snapshots.manifest(snapshots.get(to)?, &jk.content_store)?;
+    let changes = old.diff(&new);
 
-        println!(
-            "  {} {} {}{}{}",
-            match op.op_type.as_str() {
-                "DELETE" => "🗑".to_string(),
-                "MODIFY" => "✏".to_string(),
-                "MOVE" => "📦".to_string(),
-                "COPY" => "📄".to_string(),
-                _ => "•".to_string(),
-            },
-            op.op_type.yellow(),
-            op.path.display(),
-            arrow,
-            secondary
-        );
+    if json {
+        println!("{}", serde_json::to_string_pretty(&changes)?);
+        return Ok(());
     }
 
-    println!();
-    println!("Total files affected: {}", preview.total_files_affected);
-    println!();
+    if changes.is_empty() {
+        println!("No differences between {} and {}", from, to);
+        return Ok(());
+    }
+    let (mut added, mut removed, mut modified) = (0, 0, 0);
+    for change in &changes {
+        match change {
+            SnapshotChange::Added { path, size } => {
+                added += 1;
+                println!(
+                    "{} {} ({})",
+                    "+".green(),
+                    path.display(),
+                    human_bytes(*size)
+                );
+            }
+            SnapshotChange::Removed { path, size } => {
+                removed += 1;
+                println!("{} {} ({})", "-".red(), path.display(), human_bytes(*size));
+            }
+            SnapshotChange::Modified {
+                path,
+                old_size,
+                new_size,
+            } => {
+                modified += 1;
+                println!(
+                    "{} {} ({} -> {})",
+                    "~".yellow(),
+                    path.display(),
+                    human_bytes(*old_size),
+                    human_bytes(*new_size)
+                );
+            }
+        }
+    }
+    println!("{}", "─".repeat(70));
     println!(
-        "Use {} to apply or {} to cancel",
-        "jk commit".cyan(),
-        "jk rollback".cyan()
+        "{} added, {} removed, {} modified",
+        added, removed, modified
     );
-
     Ok(())
 }
 
-fn cmd_history(dir: &PathBuf, limit: usize, filter: Option<String>) -> Result<()> {
-    let jk = JanusKey::open(dir).context("Failed to open JanusKey directory")?;
+/// Restore a snapshot with the fewest creates, modifies and deletes, all or
+/// nothing. Outside a transaction, several operations are grouped into an
+/// implicit one so they can be inspected and undone together.
+fn cmd_restore_snapshot(
+    dir: &Path,
+    name: &str,
+    exclude: &[String],
+    force: bool,
+    dry_run: bool,
+    auto_yes: bool,
+) -> Result<()> {
+    let mut jk = shell::open(dir)?;
+    let excludes = Excludes::from_ignore_file(&jk.root, exclude)?;
+    let operations = jk.restore_snapshot_plan(name, &excludes)?;
 
-    let ops: Vec<_> = if let Some(ref filter_str) = filter {
-        let filter_upper = filter_str.to_uppercase();
-        jk.metadata_store
-            .operations()
-            .iter()
-            .filter(|op| op.op_type.to_string() == filter_upper)
-            .rev()
-            .take(limit)
-            .collect()
-    } else {
-        jk.metadata_store
-            .operations()
-            .iter()
-            .rev()
-            .take(limit)
-            .collect()
-    };
+    if operations.is_empty() {
+        println!("{} The tree already matches snapshot {}", "✓".green(), name);
+        return Ok(());
+    }
 
-    if ops.is_empty() {
-        println!("{} No operations in history", "!".yellow());
+    if dry_run {
+        println!("{} Dry run - would restore {}:", "[DRY RUN]".cyan(), name);
+    }
+    for operation in &operations {
+        println!(
+            "  {:<6} {}",
+            operation.op_type().to_string().to_lowercase(),
+            operation.path().display()
+        );
+    }
+    if dry_run {
         return Ok(());
     }
 
-    println!("{}", "Operation History".bold());
-    println!("{}", "─".repeat(70));
+    let changes: Vec<_> = operations
+        .iter()
+        .map(|operation| (operation.op_type(), operation.path()))
+        .collect();
+    let summary = format!("This will change {} files:", operations.len());
+    if !auto_yes && !confirm_changes(dir, &changes, &summary)? {
+        println!("{}", "Cancelled".red());
+        return Ok(());
+    }
 
-    for op in ops {
-        let status = if op.undone {
-            "[UNDONE]".dimmed()
-        } else {
-            "".normal()
-        };
+    let bytes = operations.iter().map(FileOperation::written_bytes).sum();
+    check_disk_space(&jk, bytes, force)?;
 
-        let time = op.timestamp.format("%Y-%m-%d %H:%M:%S");
-        let op_type = match op.op_type.to_string().as_str() {
-            "DELETE" => "DELETE".red(),
-            "MODIFY" => "MODIFY".yellow(),
-            "MOVE" => "MOVE".blue(),
-            "COPY" => "COPY".cyan(),
-            "CREATE" => "CREATE".green(),
-            other => other.normal(),
-        };
+    let count = operations.len();
+    let implicit = count > 1 && !jk.transaction_manager.has_active();
+    if implicit {
+        jk.transaction_manager
+            .begin(Some(format!("restore snapshot {}", name)))?;
+    }
+    if let Err(e) = jk.execute_batch(operations) {
+        if implicit {
+            jk.rollback()?;
+        }
+        return Err(e).with_context(|| format!("Failed to restore snapshot {}", name));
+    }
+    if implicit {
+        jk.transaction_manager.commit()?;
+    }
 
-        println!(
-            "{} | {:8} | {} | {} {}",
-            time,
-            op_type,
-            op.path.display(),
-            op.user.dimmed(),
-            status
-        );
+    println!("{} Restored snapshot {}", "✓".green(), name.bold());
+    match count {
+        1 => println!("  Use {} to reverse it", "jk undo".cyan()),
+        n => println!(
+            "  Use {} to undo each of the {} operations",
+            format!("jk undo --count {}", n).cyan(),
+            n
+        ),
     }
+    Ok(())
+}
 
-    println!("{}", "─".repeat(70));
-    println!("Total: {} operations", jk.metadata_store.count());
+/// The OS user an operation ran as, and the actor it declared
+fn recorded_by(op: &OperationMetadata) -> String {
+    match &op.actor {
+        Some(actor) => format!("{} as {}", op.user, actor),
+        None => op.user.clone(),
+    }
+}
 
-    Ok(())
+/// Note an undo's content verification under its "Undid" line
+fn print_verified(undo: &OperationMetadata) {
+    if let Some(hash) = &undo.verified_hash {
+        println!("  {} {}", "restored content verified:".dimmed(), hash);
+    }
 }
 
-fn cmd_status(dir: &PathBuf) -> Result<()> {
-    let jk = JanusKey::open(dir).context("Failed to open JanusKey directory")?;
+fn cmd_verify_restore(dir: &Path, id: Option<&str>) -> Result<()> {
+    let jk = shell::open(dir)?;
+    let store = &jk.metadata_store;
+    let id = id
+        .map(|id| store.find(id).map(|op| op.id.as_str()))
+        .transpose()?;
 
-    println!("{}", "JanusKey Status".bold());
-    println!("{}", "─".repeat(40));
-    println!("Directory: {}", dir.display());
-    println!("Operations logged: {}", jk.metadata_store.count());
-    println!(
-        "Content store: {} blobs ({} bytes)",
-        jk.content_store.count()?,
-        human_bytes(jk.content_store.total_size()?)
-    );
+    // Undone operations whose undo restored content, with that undo
+    let restores: Vec<(&OperationMetadata, &OperationMetadata)> = store
+        .operations()
+        .iter()
+        .filter(|op| restored_hash(op).is_some())
+        .filter_map(|op| {
+            let undo = store.get(op.undo_operation_id.as_deref()?)?;
+            Some((op, undo))
+        })
+        .filter(|(op, undo)| id.is_none_or(|id| op.id == id || undo.id == id))
+        .collect();
 
-    if let Some(tx) = jk.transaction_manager.active() {
-        let name = tx.name.clone().unwrap_or_else(|| tx.id[..8].to_string());
-        println!();
-        println!("{} Active transaction: {}", "📝".to_string(), name.cyan());
-        println!("  Started: {}", tx.started_at.format("%Y-%m-%d %H:%M:%S"));
-        println!("  Operations: {}", tx.operation_ids.len());
-    } else {
-        println!();
-        println!("No active transaction");
+    if restores.is_empty() {
+        match id {
+            Some(id) => anyhow::bail!("No undone delete or modify matches {}", id),
+            None => println!("{} No undos have restored content yet", "!".yellow()),
+        }
+        return Ok(());
+    }
+
+    let mut unverified = 0;
+    for (op, undo) in &restores {
+        let expected = restored_hash(op).expect("filtered above");
+        match &undo.verified_hash {
+            Some(hash) if hash == expected => println!(
+                "{} {} {} {} restored {}",
+                "✓".green(),
+                op.id[..8].dimmed(),
+                op.op_type,
+                op.path.display(),
+                hash
+            ),
+            _ => {
+                unverified += 1;
+                println!(
+                    "{} {} {} {} not verified (undone before restores were checked)",
+                    "!".yellow(),
+                    op.id[..8].dimmed(),
+                    op.op_type,
+                    op.path.display()
+                );
+            }
+        }
     }
 
+    println!();
+    println!(
+        "{} of {} restoring undos verified",
+        restores.len() - unverified,
+        restores.len()
+    );
     Ok(())
 }
 
-fn cmd_gc(dir: &PathBuf, keep: Option<usize>, _older_than: Option<u32>) -> Result<()> {
-    let mut jk = JanusKey::open(dir).context("Failed to open JanusKey directory")?;
+/// Print a coloured line diff of `old` -> `new`, or a size summary for
+/// binary content
+fn print_diff(old: &[u8], new: &[u8]) {
+    if is_likely_text(old) && is_likely_text(new) {
+        let diff = line_diff(&String::from_utf8_lossy(old), &String::from_utf8_lossy(new));
+        for hunk in &diff.hunks {
+            print_hunk(hunk);
+        }
+    } else {
+        println!(
+            "Binary content differs ({} -> {})",
+            human_bytes(old.len() as u64),
+            human_bytes(new.len() as u64)
+        );
+    }
+}
 
-    let keep_count = keep.unwrap_or(jk.config.max_history);
+/// Print one hunk of a line diff, coloured
+fn print_hunk(hunk: &DiffHunk) {
+    println!(
+        "{}",
+        format!(
+            "@@ -{},{} +{},{} @@",
+            hunk.original_start + 1,
+            hunk.original_count,
+            hunk.new_start + 1,
+            hunk.new_count
+        )
+        .cyan()
+    );
+    for line in &hunk.lines {
+        match line {
+            DiffLine::Context(l) => println!(" {}", l),
+            DiffLine::Removed(l) => println!("{}", format!("-{}", l).red()),
+            DiffLine::Added(l) => println!("{}", format!("+{}", l).green()),
+        }
+    }
+}
 
-    let pruned = jk.metadata_store.prune(keep_count)?;
+/// What undoing an operation does, given the inverse it executes
+fn describe_undo(inverse: &FileOperation) -> String {
+    match inverse {
+        FileOperation::Create { path, content, .. } => format!(
+            "restore {} ({})",
+            path.display(),
+            human_bytes(content.len() as u64)
+        ),
+        FileOperation::Modify { path, new_content } => format!(
+            "restore previous content of {} ({})",
+            path.display(),
+            human_bytes(new_content.len() as u64)
+        ),
+        FileOperation::Move {
+            source,
+            destination,
+        } => format!(
+            "move {} back to {}",
+            source.display(),
+            destination.display()
+        ),
+        FileOperation::Copy {
+            source,
+            destination,
+            ..
+        } => format!("copy {} to {}", source.display(), destination.display()),
+        FileOperation::Delete { path } => format!("remove {}", path.display()),
+        #[cfg(unix)]
+        FileOperation::Chmod { path, new_mode } => format!(
+            "restore permissions of {} to {:o}",
+            path.display(),
+            new_mode & 0o7777
+        ),
+        #[cfg(unix)]
+        FileOperation::Symlink { path, target } => {
+            format!("restore link {} -> {}", path.display(), target.display())
+        }
+    }
+}
 
-    if pruned > 0 {
+fn cmd_backup(dir: &Path, output: &Path, dry_run: bool) -> Result<()> {
+    if !JanusKey::is_initialized(dir) {
+        anyhow::bail!("JanusKey not initialized in {}", dir.display());
+    }
+    if dry_run {
         println!(
-            "{} Pruned {} old operations (keeping last {})",
-            "✓".green(),
-            pruned,
-            keep_count
+            "{} Would back up the store to {}",
+            "[DRY RUN]".cyan(),
+            output.display()
         );
-    } else {
-        println!("{} Nothing to prune", "✓".green());
+        return Ok(());
     }
 
+    let manifest = januskey::store_backup::create(dir, output)
+        .with_context(|| format!("Failed to back up the store to {}", output.display()))?;
+    println!(
+        "{} Backed up {} files ({}) to {}",
+        "✓".green(),
+        manifest.files.len(),
+        human_bytes(manifest.bytes()),
+        output.display()
+    );
+    println!(
+        "  Restore with {}",
+        format!("jk restore-store {}", output.display()).cyan()
+    );
     Ok(())
 }
 
-fn human_bytes(bytes: u64) -> String {
-    const KB: u64 = 1024;
-    const MB: u64 = KB * 1024;
-    const GB: u64 = MB * 1024;
-
-    if bytes >= GB {
-        format!("{:.2} GB", bytes as f64 / GB as f64)
-    } else if bytes >= MB {
-        format!("{:.2} MB", bytes as f64 / MB as f64)
-    } else if bytes >= KB {
-        format!("{:.2} KB", bytes as f64 / KB as f64)
-    } else {
-        format!("{} bytes", bytes)
+fn cmd_restore_store(dir: &Path, archive: &Path, force: bool, dry_run: bool) -> Result<()> {
+    if dry_run {
+        let manifest = januskey::store_backup::verify(archive)?;
+        println!(
+            "{} {} verified: {} files ({}) from {}, backed up {}",
+            "[DRY RUN]".cyan(),
+            archive.display(),
+            manifest.files.len(),
+            human_bytes(manifest.bytes()),
+            manifest.source,
+            manifest.created_at.format("%Y-%m-%d %H:%M:%S")
+        );
+        return Ok(());
     }
+
+    let manifest = match januskey::store_backup::restore(archive, dir, force) {
+        Err(JanusError::PathExists(path)) => {
+            anyhow::bail!(
+                "A store already exists at {}; pass --force to replace it",
+                path
+            )
+        }
+        result => result?,
+    };
+    println!(
+        "{} Restored {} files ({}) backed up from {} at {}",
+        "✓".green(),
+        manifest.files.len(),
+        human_bytes(manifest.bytes()),
+        manifest.source,
+        manifest.created_at.format("%Y-%m-%d %H:%M:%S")
+    );
+    Ok(())
 }
@@ -0,0 +1,176 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// File Modes: octal (755) and symbolic (u+x,go-w) modes for `jk chmod`
+// A symbolic mode is worked out against each file's current mode
+
+use crate::error::{JanusError, Result};
+use std::str::FromStr;
+
+/// Permission bits a mode can change: the nine rwx bits and setuid,
+/// setgid and sticky
+const MODE_BITS: u32 = 0o7777;
+
+/// A mode to give files, as `chmod` takes it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mode {
+    /// Absolute mode (`755`)
+    Octal(u32),
+    /// Comma-separated clauses (`u+x,go-w`), applied in order
+    Symbolic(Vec<Clause>),
+}
+
+/// One symbolic clause: whose bits, then one or more changes to them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clause {
+    /// Bits of the classes named (`ugoa`; none means all)
+    who: u32,
+    actions: Vec<(char, Perms)>,
+}
+
+/// What a change adds, removes or sets
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Perms {
+    /// Letters from `rwxXst`
+    Letters(String),
+    /// Another class's current bits (`g=u`)
+    Copy(char),
+}
+
+impl FromStr for Mode {
+    type Err = JanusError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || JanusError::InvalidMode(s.to_string());
+        if !s.is_empty() && s.chars().all(|c| c.is_digit(8)) {
+            let mode = u32::from_str_radix(s, 8).map_err(|_| invalid())?;
+            if mode > MODE_BITS {
+                return Err(invalid());
+            }
+            return Ok(Self::Octal(mode));
+        }
+        s.split(',')
+            .map(|clause| parse_clause(clause).ok_or_else(invalid))
+            .collect::<Result<_>>()
+            .map(Self::Symbolic)
+    }
+}
+
+fn parse_clause(clause: &str) -> Option<Clause> {
+    let ops = clause.find(['+', '-', '='])?;
+    let (classes, mut rest) = clause.split_at(ops);
+    let mut who = 0;
+    for class in classes.chars() {
+        who |= class_bits(class)?;
+    }
+    if classes.is_empty() {
+        who = MODE_BITS;
+    }
+
+    let mut actions = Vec::new();
+    while let Some(op) = rest.chars().next() {
+        let end = rest[1..]
+            .find(['+', '-', '='])
+            .map_or(rest.len(), |i| i + 1);
+        let perms = &rest[1..end];
+        let perms = match perms {
+            "u" | "g" | "o" => Perms::Copy(perms.chars().next()?),
+            _ if perms.chars().all(|c| "rwxXst".contains(c)) => Perms::Letters(perms.to_string()),
+            _ => return None,
+        };
+        actions.push((op, perms));
+        rest = &rest[end..];
+    }
+    Some(Clause { who, actions })
+}
+
+/// Bits belonging to a class, including its special bit
+fn class_bits(class: char) -> Option<u32> {
+    match class {
+        'u' => Some(0o4700),
+        'g' => Some(0o2070),
+        'o' => Some(0o1007),
+        'a' => Some(MODE_BITS),
+        _ => None,
+    }
+}
+
+impl Mode {
+    /// The mode a file with `current` mode gets. `is_dir` decides `X`.
+    /// File type bits in `current` are kept.
+    pub fn apply(&self, current: u32, is_dir: bool) -> u32 {
+        let clauses = match self {
+            Self::Octal(mode) => return (current & !MODE_BITS) | mode,
+            Self::Symbolic(clauses) => clauses,
+        };
+        let mut mode = current;
+        for clause in clauses {
+            for (op, perms) in &clause.actions {
+                let bits = match perms {
+                    Perms::Letters(letters) => letters.chars().fold(0, |bits, letter| {
+                        bits | match letter {
+                            'r' => 0o444,
+                            'w' => 0o222,
+                            'x' => 0o111,
+                            'X' if is_dir || mode & 0o111 != 0 => 0o111,
+                            's' => 0o6000,
+                            't' => 0o1000,
+                            _ => 0,
+                        }
+                    }),
+                    Perms::Copy(class) => {
+                        let shift = match class {
+                            'u' => 6,
+                            'g' => 3,
+                            _ => 0,
+                        };
+                        ((mode >> shift) & 0o7) * 0o111
+                    }
+                } & clause.who;
+                mode = match op {
+                    '+' => mode | bits,
+                    '-' => mode & !bits,
+                    _ => (mode & !clause.who) | bits,
+                };
+            }
+        }
+        mode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(mode: &str, current: u32) -> u32 {
+        mode.parse::<Mode>().unwrap().apply(current, false)
+    }
+
+    #[test]
+    fn test_octal_mode() {
+        assert_eq!(apply("755", 0o100644), 0o100755);
+        assert_eq!(apply("0600", 0o644), 0o600);
+        assert!("8".parse::<Mode>().is_err());
+        assert!("17777".parse::<Mode>().is_err());
+    }
+
+    #[test]
+    fn test_symbolic_mode() {
+        assert_eq!(apply("u+x", 0o644), 0o744);
+        assert_eq!(apply("go-w", 0o666), 0o644);
+        assert_eq!(apply("a=r", 0o755), 0o444);
+        assert_eq!(apply("+x", 0o644), 0o755);
+        assert_eq!(apply("u=rwx,g=u-w,o=", 0o644), 0o750);
+        assert_eq!(apply("u+s,+t", 0o755), 0o5755);
+
+        // X only where something is executable already, or on directories
+        assert_eq!(apply("a+X", 0o644), 0o644);
+        assert_eq!(apply("a+X", 0o744), 0o755);
+        assert_eq!("a+X".parse::<Mode>().unwrap().apply(0o700, true), 0o711);
+
+        for invalid in ["", "u", "z+x", "u+q", "u+x,", "g=uo"] {
+            assert!(invalid.parse::<Mode>().is_err(), "{}", invalid);
+        }
+    }
+}
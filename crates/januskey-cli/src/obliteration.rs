@@ -10,12 +10,19 @@
 // 2. A proof of non-existence is generated
 // 3. The fact of obliteration is logged (without content)
 
-use crate::content_store::{ContentHash, ContentStore};
+use crate::actor::Actor;
+use crate::audit_sink::{AuditEvent, AuditSinks};
+use crate::backend::{require, write_atomic, Capabilities};
+use crate::content_store::{BlobLocation, ContentHash, ContentStore};
 use crate::error::{JanusError, Result};
+use crate::framing::{self, Compressed, LogReader};
+use crate::metadata::OperationMetadata;
+use crate::transaction::{Transaction, TransactionState};
 use chrono::{DateTime, Utc};
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::fs::{self, File, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
@@ -58,13 +65,13 @@ pub fn generate(content_hash: &ContentHash, passes: usize) -> Self {
 
         // Generate random nonce
         let mut nonce_bytes = [0u8; 32];
-        rand::thread_rng().fill_bytes(&mut nonce_bytes);
+        rand::rng().fill_bytes(&mut nonce_bytes);
         let nonce = hex::encode(nonce_bytes);
 
         // Generate commitment: H(content_hash || nonce || timestamp)
         let mut hasher = Sha256::new();
         hasher.update(content_hash.raw_hash().as_bytes());
-        hasher.update(&nonce_bytes);
+        hasher.update(nonce_bytes);
         hasher.update(timestamp.to_rfc3339().as_bytes());
         let commitment = hex::encode(hasher.finalize());
 
@@ -97,6 +104,46 @@ pub fn verify_commitment(&self) -> bool {
     }
 }
 
+/// What obliterating content does to deltas stored against it, which
+/// cannot be reconstructed without it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeltaDependents {
+    /// Refuse to obliterate content deltas depend on
+    #[default]
+    Refuse,
+    /// Rewrite the dependent deltas as full content first
+    Materialize,
+    /// Obliterate the dependent deltas too
+    Cascade,
+}
+
+impl std::fmt::Display for DeltaDependents {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeltaDependents::Refuse => write!(f, "refuse"),
+            DeltaDependents::Materialize => write!(f, "materialize"),
+            DeltaDependents::Cascade => write!(f, "cascade"),
+        }
+    }
+}
+
+impl std::str::FromStr for DeltaDependents {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "refuse" => Ok(DeltaDependents::Refuse),
+            "materialize" => Ok(DeltaDependents::Materialize),
+            "cascade" => Ok(DeltaDependents::Cascade),
+            other => Err(format!(
+                "unknown choice '{}' (expected refuse, materialize or cascade)",
+                other
+            )),
+        }
+    }
+}
+
 /// Record of an obliteration event (stored in audit log)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ObliterationRecord {
@@ -106,6 +153,9 @@ pub struct ObliterationRecord {
     pub timestamp: DateTime<Utc>,
     /// User who performed obliteration
     pub user: String,
+    /// Actor `user` declared it was acting for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
     /// Hash of obliterated content (not the content itself)
     pub content_hash: ContentHash,
     /// Reason for obliteration (optional, for compliance)
@@ -116,6 +166,18 @@ pub struct ObliterationRecord {
     pub proof: ObliterationProof,
     /// Related operation IDs that were cleaned up
     pub cleaned_operation_ids: Vec<String>,
+    /// The legal hold the content was under and why it was obliterated
+    /// anyway, if it was held
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hold_override: Option<String>,
+    /// Deltas stored against the content that were rewritten as full
+    /// content first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub materialized: Vec<ContentHash>,
+    /// Deltas stored against the content that were obliterated with it,
+    /// each with its own record
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cascaded: Vec<ContentHash>,
 }
 
 /// Obliteration log for audit trail
@@ -140,27 +202,129 @@ pub struct ObliterationManager {
     log_path: PathBuf,
     /// Obliteration log
     log: ObliterationLog,
+    /// Where obliteration records are forwarded after being logged
+    sinks: AuditSinks,
+    /// Content under legal hold, with the case holding it
+    holds: HashMap<ContentHash, String>,
+    /// Justification for obliterating held content anyway
+    hold_override: Option<String>,
+    /// Deltas recorded against each base, from the operation log
+    delta_bases: HashMap<ContentHash, Vec<ContentHash>>,
+    /// What to do with deltas stored against obliterated content
+    dependents: DeltaDependents,
+    /// Write the log zstd-compressed
+    compress: bool,
 }
 
 impl ObliterationManager {
     /// Create or open an obliteration manager
     pub fn new(log_path: PathBuf) -> Result<Self> {
-        let log = if log_path.exists() {
-            let content = ({
-                use std::io::Read;
-                std::fs::File::open(&log_path).and_then(|mut f| {
-                    let mut buf = String::new();
-                    f.take(10 * 1024 * 1024).read_to_string(&mut buf)?;
-                    Ok(buf)
-                })
-            })?;
-            serde_json::from_str(&content)
-                .map_err(|e| JanusError::MetadataCorrupted(e.to_string()))?
+        let (log, compress) = if log_path.exists() {
+            let reader = LogReader::new(File::open(&log_path)?)?;
+            let compress = reader.is_compressed();
+            let log = serde_json::from_reader(reader.take(10 * 1024 * 1024))
+                .map_err(|e| JanusError::MetadataCorrupted(e.to_string()))?;
+            (log, compress)
         } else {
-            ObliterationLog::new()
+            (ObliterationLog::new(), false)
         };
 
-        Ok(Self { log_path, log })
+        Ok(Self {
+            log_path,
+            log,
+            sinks: AuditSinks::new(),
+            holds: HashMap::new(),
+            hold_override: None,
+            delta_bases: HashMap::new(),
+            dependents: DeltaDependents::default(),
+            compress,
+        })
+    }
+
+    /// Write the log compressed from now on (from `Config.compress_logs`).
+    /// A log read compressed stays compressed.
+    pub fn with_compression(mut self, compress: bool) -> Self {
+        self.compress |= compress;
+        self
+    }
+
+    /// Rewrite the log compressed, if it is not already
+    pub fn compress(&mut self) -> Result<Compressed> {
+        let mut compressed = Compressed::default();
+        if self.compress || !self.log_path.exists() {
+            return Ok(compressed);
+        }
+        let before = fs::metadata(&self.log_path)?.len();
+        self.compress = true;
+        self.save()?;
+        compressed.add(before, fs::metadata(&self.log_path)?.len());
+        Ok(compressed)
+    }
+
+    /// Forward obliteration records to `sinks` (from `Config.audit_sinks`)
+    pub fn with_sinks(mut self, sinks: AuditSinks) -> Self {
+        self.sinks = sinks;
+        self
+    }
+
+    /// Refuse to obliterate `holds` (content hash to case, from
+    /// [`LegalHolds::held_content`](crate::hold::LegalHolds::held_content))
+    pub fn with_holds(mut self, holds: HashMap<ContentHash, String>) -> Self {
+        self.holds = holds;
+        self
+    }
+
+    /// Obliterate held content anyway. Each record of held content keeps
+    /// the case and `justification`, and is forwarded at top severity.
+    pub fn override_holds(mut self, justification: String) -> Self {
+        self.hold_override = Some(justification);
+        self
+    }
+
+    /// Know which deltas `operations` stored against which base, so
+    /// obliterating a base does not silently break them
+    pub fn with_delta_bases(mut self, operations: &[OperationMetadata]) -> Self {
+        self.delta_bases.clear();
+        for op in operations {
+            if let (Some(delta), Some(base)) = (&op.content_hash, &op.delta_base) {
+                let deltas = self.delta_bases.entry(base.clone()).or_default();
+                if !deltas.contains(delta) {
+                    deltas.push(delta.clone());
+                }
+            }
+        }
+        self
+    }
+
+    /// Handle deltas stored against obliterated content as `dependents`
+    /// says, rather than refusing
+    pub fn on_dependents(mut self, dependents: DeltaDependents) -> Self {
+        self.dependents = dependents;
+        self
+    }
+
+    /// Deltas in `content_store` that still need `content_hash` to be
+    /// reconstructed
+    pub fn dependent_deltas(
+        &self,
+        content_store: &ContentStore,
+        content_hash: &ContentHash,
+    ) -> Result<Vec<ContentHash>> {
+        let mut dependents = Vec::new();
+        for delta in self.delta_bases.get(content_hash).into_iter().flatten() {
+            if content_store.delta_base(delta)?.as_ref() == Some(content_hash) {
+                dependents.push(delta.clone());
+            }
+        }
+        Ok(dependents)
+    }
+
+    /// The case holding `content_hash`, unless holds are overridden
+    pub fn blocking_hold(&self, content_hash: &ContentHash) -> Option<&str> {
+        match self.hold_override {
+            Some(_) => None,
+            None => self.holds.get(content_hash).map(String::as_str),
+        }
     }
 
     /// Save log to disk
@@ -168,8 +332,8 @@ fn save(&self) -> Result<()> {
         if let Some(parent) = self.log_path.parent() {
             fs::create_dir_all(parent)?;
         }
-        let content = serde_json::to_string_pretty(&self.log)?;
-        fs::write(&self.log_path, content)?;
+        let content = framing::encode(serde_json::to_vec_pretty(&self.log)?, self.compress)?;
+        write_atomic(&self.log_path, &content)?;
         Ok(())
     }
 
@@ -182,41 +346,133 @@ pub fn obliterate(
         reason: Option<String>,
         legal_basis: Option<String>,
     ) -> Result<ObliterationRecord> {
-        // Get the content path
-        let content_path = content_store.content_path(content_hash);
-
-        if !content_path.exists() {
-            return Err(JanusError::FileNotFound(format!(
-                "Content {} not found in store",
-                content_hash
+        // Secure overwrite rewrites the blob in place, with direct access
+        // to it on disk
+        let backend = content_store.backend();
+        let truncate = Capabilities {
+            truncate: true,
+            ..Capabilities::NONE
+        };
+        require(backend.as_ref(), &truncate, "secure obliteration")?;
+        if !backend.is_local() {
+            return Err(JanusError::Unsupported(format!(
+                "secure obliteration needs the content store on the local disk, not the {} backend",
+                backend.name()
             )));
         }
 
-        // Perform secure overwrite
-        let passes = secure_overwrite(&content_path)?;
+        if let Some(case) = self.blocking_hold(content_hash) {
+            return Err(JanusError::LegalHold(format!(
+                "content {} is held for case {}",
+                content_hash, case
+            )));
+        }
+        let hold_override = self.holds.get(content_hash).map(|case| {
+            format!(
+                "case {}: {}",
+                case,
+                self.hold_override.as_deref().unwrap_or_default()
+            )
+        });
+
+        // Deltas stored against the content cannot be reconstructed
+        // without it: deal with them before anything is overwritten
+        let dependents = self.dependent_deltas(content_store, content_hash)?;
+        let (mut materialized, mut cascaded) = (Vec::new(), Vec::new());
+        if !dependents.is_empty() {
+            match self.dependents {
+                DeltaDependents::Refuse => {
+                    return Err(JanusError::DeltaDependents {
+                        hash: content_hash.to_string(),
+                        deltas: dependents.iter().map(ToString::to_string).collect(),
+                    })
+                }
+                DeltaDependents::Materialize => {
+                    for delta in dependents {
+                        content_store.materialize(&delta)?;
+                        materialized.push(delta);
+                    }
+                }
+                DeltaDependents::Cascade => {
+                    if let Some((delta, case)) = dependents
+                        .iter()
+                        .find_map(|delta| Some((delta, self.blocking_hold(delta)?)))
+                    {
+                        return Err(JanusError::LegalHold(format!(
+                            "delta {} of content {} is held for case {}",
+                            delta, content_hash, case
+                        )));
+                    }
+                    for delta in dependents {
+                        self.obliterate(
+                            content_store,
+                            &delta,
+                            reason.clone(),
+                            legal_basis.clone(),
+                        )?;
+                        cascaded.push(delta);
+                    }
+                }
+            }
+        }
 
-        // Remove the file
-        fs::remove_file(&content_path)?;
+        // Overwrite the blob where it lies: its own file (full blob or
+        // delta object), or its byte range of a pack, which is then
+        // rewritten without it
+        let passes = match content_store.locate(content_hash)? {
+            Some(BlobLocation::Loose(path)) => {
+                let passes = secure_overwrite(&path)?;
+                fs::remove_file(&path)?;
+                // Lets the store drop what it indexed about the blob
+                content_store.delete(content_hash)?;
+                passes
+            }
+            Some(BlobLocation::Packed { pack, offset, len }) => {
+                let passes = secure_overwrite_range(&pack, offset, len)?;
+                content_store.delete(content_hash)?;
+                passes
+            }
+            Some(BlobLocation::Cold { .. }) => {
+                return Err(JanusError::Backend(format!(
+                    "content {} is on the cold tier, where it cannot be securely overwritten; bring it back with `jk tier --recall` first",
+                    content_hash
+                )))
+            }
+            None => {
+                return Err(JanusError::FileNotFound(format!(
+                    "Content {} not found in store",
+                    content_hash
+                )))
+            }
+        };
 
         // Generate obliteration proof
         let proof = ObliterationProof::generate(content_hash, passes);
 
         // Create record
+        let actor = Actor::from_env();
         let record = ObliterationRecord {
             id: Uuid::new_v4().to_string(),
             timestamp: Utc::now(),
-            user: whoami::username(),
+            user: actor.os_user,
+            actor: actor.declared,
             content_hash: content_hash.clone(),
             reason,
             legal_basis,
             proof,
             cleaned_operation_ids: Vec::new(),
+            hold_override,
+            materialized,
+            cascaded,
         };
 
         // Log the obliteration
         self.log.records.push(record.clone());
         self.save()?;
 
+        // Forwarding is best effort: the local log is the record of truth
+        let _ = self.sinks.emit(&AuditEvent::from(&record));
+
         Ok(record)
     }
 
@@ -285,7 +541,13 @@ pub fn count(&self) -> usize {
 /// Uses multiple passes with different patterns to ensure data is unrecoverable
 fn secure_overwrite(path: &Path) -> Result<usize> {
     let metadata = fs::metadata(path)?;
-    let file_size = metadata.len() as usize;
+    secure_overwrite_range(path, 0, metadata.len())
+}
+
+/// Overwrite `len` bytes of a file from `offset` in place, leaving the rest
+/// of it intact (a blob inside a pack)
+fn secure_overwrite_range(path: &Path, offset: u64, len: u64) -> Result<usize> {
+    let file_size = len as usize;
 
     if file_size == 0 {
         return Ok(OVERWRITE_PASSES);
@@ -296,14 +558,14 @@ fn secure_overwrite(path: &Path) -> Result<usize> {
 
     // Perform overwrite passes
     for (pass, &pattern) in PATTERNS.iter().enumerate() {
-        // Seek to beginning
-        file.seek(SeekFrom::Start(0))?;
+        // Seek to the start of the range
+        file.seek(SeekFrom::Start(offset))?;
 
         // Create pattern buffer
         let buffer = if pass == OVERWRITE_PASSES - 1 {
             // Final pass: random data
             let mut random_buffer = vec![0u8; file_size.min(8192)];
-            rand::thread_rng().fill_bytes(&mut random_buffer);
+            rand::rng().fill_bytes(&mut random_buffer);
             random_buffer
         } else {
             // Fixed pattern
@@ -325,6 +587,101 @@ fn secure_overwrite(path: &Path) -> Result<usize> {
     Ok(OVERWRITE_PASSES)
 }
 
+/// What obliterating a blob from the store would destroy (see [`impact`])
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObliterationImpact {
+    pub content_hash: ContentHash,
+    /// Bytes overwritten and removed: the blob's own file or its range of
+    /// a pack. Zero if it is not in the local store.
+    pub stored_bytes: u64,
+    /// Operations that recorded the content, before or after they ran
+    pub operations: Vec<String>,
+    /// Blobs stored as deltas against it, directly or through another
+    /// delta, which cannot be read without it
+    pub dependent_deltas: Vec<ContentHash>,
+    /// What is done with those deltas
+    pub dependents: DeltaDependents,
+    /// Operations not yet undone whose undo needs the content or one of
+    /// the dependent deltas, and so can no longer be undone
+    pub lost_undos: Vec<String>,
+    /// Transactions, not rolled back, holding such operations: they can
+    /// no longer be rolled back in full
+    pub transactions: Vec<String>,
+}
+
+/// What obliterating `content_hash` from `content_store` would destroy,
+/// given every recorded operation and transaction and what is to be done
+/// with the deltas stored against it. Nothing is changed.
+pub fn impact(
+    content_hash: &ContentHash,
+    content_store: &ContentStore,
+    operations: &[OperationMetadata],
+    transactions: &[Transaction],
+    dependents: DeltaDependents,
+) -> Result<ObliterationImpact> {
+    let stored_bytes = match content_store.locate(content_hash)? {
+        Some(BlobLocation::Loose(path)) => fs::metadata(path)?.len(),
+        Some(BlobLocation::Packed { len, .. }) => len,
+        Some(BlobLocation::Cold { .. }) | None => 0,
+    };
+
+    // Deltas chain: anything still stored against a lost blob is lost too
+    let mut lost = vec![content_hash.clone()];
+    let mut dependent_deltas = Vec::new();
+    while let Some(base) = lost.pop() {
+        for op in operations {
+            if let (Some(delta), Some(op_base)) = (&op.content_hash, &op.delta_base) {
+                if *op_base == base
+                    && !dependent_deltas.contains(delta)
+                    && content_store.delta_base(delta)?.as_ref() == Some(&base)
+                {
+                    dependent_deltas.push(delta.clone());
+                    lost.push(delta.clone());
+                }
+            }
+        }
+    }
+
+    let refers = |hash: &Option<ContentHash>| hash.as_ref() == Some(content_hash);
+    let operations_referring = operations
+        .iter()
+        .filter(|op| refers(&op.content_hash) || refers(&op.new_content_hash))
+        .map(|op| op.id.clone())
+        .collect();
+    // Materialized deltas survive. Refusing destroys nothing, so what is
+    // reported is the cost of going ahead with the deltas unreadable.
+    let lost_deltas: &[ContentHash] = match dependents {
+        DeltaDependents::Materialize => &[],
+        DeltaDependents::Refuse | DeltaDependents::Cascade => &dependent_deltas,
+    };
+    let lost_undos: Vec<String> = operations
+        .iter()
+        .filter(|op| !op.undone)
+        .filter(|op| {
+            op.content_hash
+                .as_ref()
+                .is_some_and(|hash| hash == content_hash || lost_deltas.contains(hash))
+        })
+        .map(|op| op.id.clone())
+        .collect();
+    let transactions = transactions
+        .iter()
+        .filter(|tx| tx.state != TransactionState::RolledBack)
+        .filter(|tx| tx.operation_ids.iter().any(|id| lost_undos.contains(id)))
+        .map(|tx| tx.id.clone())
+        .collect();
+
+    Ok(ObliterationImpact {
+        content_hash: content_hash.clone(),
+        stored_bytes,
+        operations: operations_referring,
+        dependent_deltas,
+        dependents,
+        lost_undos,
+        transactions,
+    })
+}
+
 /// Obliterate an arbitrary file on disk (not necessarily in the content
 /// store): hash its current content, securely overwrite it with
 /// [`OVERWRITE_PASSES`] passes, remove it, and return a proof of erasure.
@@ -368,8 +725,7 @@ pub fn verify_obliteration(path: &Path, original_hash: &ContentHash) -> Result<b
     file.read_to_end(&mut content)?;
 
     // Verify it doesn't match original
-    let current_hash = ContentHash::from_bytes(&content);
-    Ok(current_hash != *original_hash)
+    Ok(!original_hash.verify(&content))
 }
 
 /// Batch obliteration request
@@ -519,6 +875,34 @@ fn test_secure_overwrite() {
         assert_ne!(remaining, original.to_vec());
     }
 
+    #[test]
+    fn test_obliterate_packed_content() {
+        let (tmp, content_store, mut obliteration_manager) = setup();
+        let secret = content_store.store(b"packed secret").unwrap();
+        let kept = content_store.store(b"packed neighbour").unwrap();
+        content_store.pack(1024).unwrap();
+        let Some(BlobLocation::Packed { pack, .. }) = content_store.locate(&secret).unwrap() else {
+            panic!("content was not packed");
+        };
+
+        obliteration_manager
+            .obliterate(&content_store, &secret, None, None)
+            .expect("failed to obliterate packed content");
+        assert!(!content_store.exists(&secret));
+        assert_eq!(content_store.retrieve(&kept).unwrap(), b"packed neighbour");
+
+        // The old pack is gone and no pack holds the bytes
+        assert!(!pack.exists());
+        for entry in walkdir::WalkDir::new(tmp.path()).into_iter().flatten() {
+            if entry.file_type().is_file() {
+                let bytes = fs::read(entry.path()).unwrap();
+                assert!(!bytes
+                    .windows(b"packed secret".len())
+                    .any(|w| w == b"packed secret"));
+            }
+        }
+    }
+
     #[test]
     fn test_batch_obliteration() {
         let (_tmp, content_store, mut obliteration_manager) = setup();
@@ -560,4 +944,105 @@ fn test_obliterate_nonexistent() {
 
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_impact() {
+        use crate::metadata::OperationType;
+
+        let (_tmp, content_store, _) = setup();
+        let content_store = content_store.with_delta(true);
+        let personal = b"personal data\n".repeat(500);
+        let base = content_store.store(&personal).unwrap();
+        let mut modified = personal.clone();
+        modified[100] = b'X';
+        let (delta, _) = content_store.store_delta(&modified, &base).unwrap();
+        let other = content_store.store(b"unrelated").unwrap();
+
+        let delete = OperationMetadata::new(OperationType::Delete, "a.txt".into())
+            .with_content_hash(base.clone());
+        let modify = OperationMetadata::new(OperationType::Modify, "b.txt".into())
+            .with_content_hash(delta.clone())
+            .with_delta_base(base.clone());
+        let mut undone = OperationMetadata::new(OperationType::Delete, "c.txt".into())
+            .with_content_hash(base.clone());
+        undone.undone = true;
+        let unrelated =
+            OperationMetadata::new(OperationType::Delete, "d.txt".into()).with_content_hash(other);
+
+        let mut committed = Transaction::new(None);
+        committed.operation_ids = vec![modify.id.clone()];
+        committed.state = TransactionState::Committed;
+        let mut rolled_back = Transaction::new(None);
+        rolled_back.operation_ids = vec![delete.id.clone()];
+        rolled_back.state = TransactionState::RolledBack;
+
+        let operations = [delete.clone(), modify.clone(), undone.clone(), unrelated];
+        let impact = impact(
+            &base,
+            &content_store,
+            &operations,
+            &[committed.clone(), rolled_back],
+            DeltaDependents::Cascade,
+        )
+        .unwrap();
+
+        assert_eq!(impact.stored_bytes, personal.len() as u64);
+        assert_eq!(impact.operations, vec![delete.id.clone(), undone.id]);
+        assert_eq!(impact.dependent_deltas, vec![delta]);
+        assert_eq!(impact.lost_undos, vec![delete.id, modify.id]);
+        assert_eq!(impact.transactions, vec![committed.id]);
+
+        let impact = super::impact(
+            &base,
+            &content_store,
+            &operations,
+            &[],
+            DeltaDependents::Materialize,
+        )
+        .unwrap();
+        assert_eq!(impact.lost_undos, vec![operations[0].id.clone()]);
+    }
+
+    #[test]
+    fn test_delta_dependents() {
+        use crate::metadata::OperationType;
+
+        let (_tmp, content_store, manager) = setup();
+        let content_store = content_store.with_delta(true);
+        let store_pair = |text: &[u8]| {
+            let original = text.repeat(500);
+            let base = content_store.store(&original).unwrap();
+            let mut modified = original;
+            modified[100] = b'X';
+            let (delta, _) = content_store.store_delta(&modified, &base).unwrap();
+            let op = OperationMetadata::new(OperationType::Modify, "a.txt".into())
+                .with_content_hash(delta.clone())
+                .with_delta_base(base.clone());
+            (base, delta, modified, op)
+        };
+        let (base, delta, modified, op) = store_pair(b"personal data\n");
+        let (other_base, other_delta, _, other_op) = store_pair(b"other records\n");
+        let mut manager = manager.with_delta_bases(&[op, other_op]);
+
+        let error = manager
+            .obliterate(&content_store, &base, None, None)
+            .unwrap_err();
+        assert_eq!(error.code(), "delta_dependents");
+        assert!(content_store.exists(&base));
+
+        let mut manager = manager.on_dependents(DeltaDependents::Materialize);
+        let record = manager
+            .obliterate(&content_store, &base, None, None)
+            .unwrap();
+        assert_eq!(record.materialized, vec![delta.clone()]);
+        assert_eq!(content_store.retrieve(&delta).unwrap(), modified);
+
+        let mut manager = manager.on_dependents(DeltaDependents::Cascade);
+        let record = manager
+            .obliterate(&content_store, &other_base, None, None)
+            .unwrap();
+        assert_eq!(record.cascaded, vec![other_delta.clone()]);
+        assert!(!content_store.exists(&other_delta));
+        assert_eq!(manager.get_by_hash(&other_delta).len(), 1);
+    }
 }
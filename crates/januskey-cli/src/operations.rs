@@ -5,19 +5,39 @@
 // Reversible File Operations
 // Each operation stores sufficient metadata for perfect inversion
 
+use crate::backend::{clone_file, move_file};
 use crate::content_store::{ContentHash, ContentStore};
 use crate::error::{JanusError, Result};
-use crate::metadata::{FileMetadata, MetadataStore, OperationMetadata, OperationType};
+use crate::events::{Event, EventBus};
+use crate::guard::PathGuard;
+use crate::keys::SecretKey;
+use crate::lock::{FileLock, FileLocking, LockMode};
+use crate::metadata::{
+    operation_id_for_key, ByteAccounting, FileMetadata, MetadataStore, OperationMetadata,
+    OperationType, PreservedAttribute,
+};
+use crate::ntfs;
+use crate::ownership::OwnershipConfig;
+use crate::policy::{captured_content, PathPolicies};
+use crate::refs::{self, References};
+use crate::select::{select_files, Excludes};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 
 /// A file operation that can be executed and reversed
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
 pub enum FileOperation {
     /// Delete a file (reversible: restore from stored content)
     Delete { path: PathBuf },
     /// Modify a file (reversible: restore original content)
-    Modify { path: PathBuf, new_content: Vec<u8> },
+    Modify {
+        path: PathBuf,
+        #[serde(with = "base64_content")]
+        new_content: Vec<u8>,
+    },
     /// Move/rename a file (reversible: move back)
     Move {
         source: PathBuf,
@@ -27,12 +47,41 @@ pub enum FileOperation {
     Copy {
         source: PathBuf,
         destination: PathBuf,
+        /// What to carry over besides the content and mode
+        #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+        preserve: BTreeSet<PreservedAttribute>,
     },
     /// Change permissions (reversible: restore original perms)
     #[cfg(unix)]
     Chmod { path: PathBuf, new_mode: u32 },
     /// Create a new file (reversible: delete)
-    Create { path: PathBuf, content: Vec<u8> },
+    Create {
+        path: PathBuf,
+        #[serde(with = "base64_content")]
+        content: Vec<u8>,
+        /// Permission bits to give it instead of the umask's (Unix only)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mode: Option<u32>,
+    },
+    /// Create a symbolic link to `target` (reversible: delete the link)
+    #[cfg(unix)]
+    Symlink { path: PathBuf, target: PathBuf },
+}
+
+/// File content as base64 when an operation is serialized (e.g. sent to
+/// `jk daemon`)
+mod base64_content {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(content: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(content))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
 }
 
 impl FileOperation {
@@ -46,6 +95,8 @@ pub fn op_type(&self) -> OperationType {
             #[cfg(unix)]
             Self::Chmod { .. } => OperationType::Chmod,
             Self::Create { .. } => OperationType::Create,
+            #[cfg(unix)]
+            Self::Symlink { .. } => OperationType::Create,
         }
     }
 
@@ -59,7 +110,90 @@ pub fn path(&self) -> &Path {
             #[cfg(unix)]
             Self::Chmod { path, .. } => path,
             Self::Create { path, .. } => path,
+            #[cfg(unix)]
+            Self::Symlink { path, .. } => path,
+        }
+    }
+
+    /// Bytes of original content executing this would capture (an upper
+    /// bound: stored content may be compressed or deduplicated)
+    pub fn captured_bytes(&self) -> u64 {
+        match self {
+            Self::Delete { path } | Self::Modify { path, .. } => {
+                fs::symlink_metadata(path).map(|m| m.len()).unwrap_or(0)
+            }
+            _ => 0,
+        }
+    }
+
+    /// Bytes executing this writes into the tree
+    pub fn written_bytes(&self) -> u64 {
+        match self {
+            Self::Modify { new_content, .. } => new_content.len() as u64,
+            Self::Create { content, .. } => content.len() as u64,
+            Self::Copy { source, .. } => fs::metadata(source).map(|m| m.len()).unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    /// Hash of the operation's type and parameters, content included
+    pub fn fingerprint(&self) -> String {
+        use sha2::{Digest, Sha256};
+
+        let mut hasher = Sha256::new();
+        hasher.update(self.op_type().to_string());
+        let mut field = |bytes: &[u8]| {
+            hasher.update((bytes.len() as u64).to_le_bytes());
+            hasher.update(bytes);
+        };
+        match self {
+            Self::Delete { path } => field(path.as_os_str().as_encoded_bytes()),
+            Self::Modify { path, new_content } => {
+                field(path.as_os_str().as_encoded_bytes());
+                field(new_content);
+            }
+            Self::Move {
+                source,
+                destination,
+            } => {
+                field(source.as_os_str().as_encoded_bytes());
+                field(destination.as_os_str().as_encoded_bytes());
+            }
+            Self::Copy {
+                source,
+                destination,
+                preserve,
+            } => {
+                field(source.as_os_str().as_encoded_bytes());
+                field(destination.as_os_str().as_encoded_bytes());
+                // Left out when empty, as before it could be given
+                for attribute in preserve {
+                    field(attribute.to_string().as_bytes());
+                }
+            }
+            #[cfg(unix)]
+            Self::Chmod { path, new_mode } => {
+                field(path.as_os_str().as_encoded_bytes());
+                field(&new_mode.to_le_bytes());
+            }
+            Self::Create {
+                path,
+                content,
+                mode,
+            } => {
+                field(path.as_os_str().as_encoded_bytes());
+                field(content);
+                if let Some(mode) = mode {
+                    field(&mode.to_le_bytes());
+                }
+            }
+            #[cfg(unix)]
+            Self::Symlink { path, target } => {
+                field(path.as_os_str().as_encoded_bytes());
+                field(target.as_os_str().as_encoded_bytes());
+            }
         }
+        hex::encode(hasher.finalize())
     }
 }
 
@@ -68,6 +202,29 @@ pub struct OperationExecutor<'a> {
     content_store: &'a ContentStore,
     metadata_store: &'a mut MetadataStore,
     transaction_id: Option<String>,
+    /// References of the transaction, inherited by its operations
+    refs: References,
+    /// Whether delete and modify capture and record original content
+    history: bool,
+    /// Idempotency key and fingerprint for the operation being executed
+    idempotency: Option<(String, String)>,
+    /// Operations held back from the log while a batch executes
+    deferred: Option<Vec<OperationMetadata>>,
+    /// Where operations and undos are announced
+    events: EventBus,
+    /// History, encryption and version limits by path
+    policies: PathPolicies,
+    /// Operations trimming versions must keep: those under legal hold,
+    /// and those being undone until they are marked so
+    pinned: HashSet<String>,
+    /// Keys to decrypt content captured encrypted
+    content_keys: &'a [SecretKey],
+    /// Whose files recreated by undo are
+    ownership: OwnershipConfig,
+    /// Whether and how long to wait to lock the files operations change
+    locking: FileLocking,
+    /// Paths operations refuse to change
+    guard: PathGuard,
 }
 
 impl<'a> OperationExecutor<'a> {
@@ -76,16 +233,166 @@ pub fn new(content_store: &'a ContentStore, metadata_store: &'a mut MetadataStor
             content_store,
             metadata_store,
             transaction_id: None,
+            refs: References::new(),
+            history: true,
+            idempotency: None,
+            deferred: None,
+            events: EventBus::default(),
+            policies: PathPolicies::default(),
+            pinned: HashSet::new(),
+            content_keys: &[],
+            ownership: OwnershipConfig::default(),
+            locking: FileLocking::default(),
+            guard: PathGuard::default(),
         }
     }
 
+    /// Publish operations starting, completing and failing, and undos, on
+    /// `events`
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
     pub fn with_transaction(mut self, transaction_id: String) -> Self {
         self.transaction_id = Some(transaction_id);
         self
     }
 
+    /// Record `refs` on operations beside the metadata store's own, which
+    /// win where both have a key
+    pub fn with_refs(mut self, refs: References) -> Self {
+        self.refs = refs;
+        self
+    }
+
+    /// Apply `policies` to each operation by its path, keeping the
+    /// `pinned` operations when trimming versions
+    pub fn with_policies(mut self, policies: PathPolicies, pinned: HashSet<String>) -> Self {
+        self.policies = policies;
+        self.pinned = pinned;
+        self
+    }
+
+    /// Decrypt content captured encrypted with one of `keys` when undoing
+    pub fn with_content_keys(mut self, keys: &'a [SecretKey]) -> Self {
+        self.content_keys = keys;
+        self
+    }
+
+    /// Give files recreated by undo their captured owner and group as
+    /// `ownership` says
+    pub fn with_ownership(mut self, ownership: OwnershipConfig) -> Self {
+        self.ownership = ownership;
+        self
+    }
+
+    /// Lock the files each operation changes (and the source it copies)
+    /// as `locking` says, from capturing their content until the change
+    pub fn with_locking(mut self, locking: FileLocking) -> Self {
+        self.locking = locking;
+        self
+    }
+
+    /// Refuse operations that change a path `guard` protects, before
+    /// touching anything
+    pub fn with_guard(mut self, guard: PathGuard) -> Self {
+        self.guard = guard;
+        self
+    }
+
+    /// Run deletes and modifies without capturing the original content.
+    ///
+    /// They are not recorded and cannot be undone; the returned metadata
+    /// only describes what was done. Other operations store no content
+    /// and are recorded as usual.
+    pub fn without_history(mut self) -> Self {
+        self.history = false;
+        self
+    }
+
     /// Execute an operation and record metadata for reversal
     pub fn execute(&mut self, operation: FileOperation) -> Result<OperationMetadata> {
+        self.guard
+            .check_operation(&operation)
+            .map_err(|e| e.with_path(operation.path()))?;
+        let _locks = self.lock_targets(&operation)?;
+        self.execute_locked(operation)
+    }
+
+    /// Lock the existing files `operation` reads or changes
+    fn lock_targets(&self, operation: &FileOperation) -> Result<Vec<FileLock>> {
+        let targets = match operation {
+            FileOperation::Delete { path } | FileOperation::Modify { path, .. } => {
+                vec![(path.as_path(), LockMode::Exclusive)]
+            }
+            #[cfg(unix)]
+            FileOperation::Chmod { path, .. } => vec![(path.as_path(), LockMode::Exclusive)],
+            FileOperation::Move { source, .. } => vec![(source.as_path(), LockMode::Exclusive)],
+            FileOperation::Copy { source, .. } => vec![(source.as_path(), LockMode::Shared)],
+            FileOperation::Create { .. } => Vec::new(),
+            #[cfg(unix)]
+            FileOperation::Symlink { .. } => Vec::new(),
+        };
+        self.locking
+            .lock_all(&targets)
+            .map_err(|e| e.with_path(operation.path()))
+    }
+
+    /// [`OperationExecutor::execute`] once the operation's files are locked
+    fn execute_locked(&mut self, operation: FileOperation) -> Result<OperationMetadata> {
+        let (op_type, path) = (operation.op_type(), operation.path().to_path_buf());
+        let _span =
+            tracing::debug_span!("execute", op = %op_type, path = %path.display()).entered();
+        self.events.publish(Event::OperationStarted {
+            op_type,
+            path: path.clone(),
+        });
+
+        let result = self.run(operation).map_err(|e| e.with_path(&path));
+        match &result {
+            Ok(metadata) => {
+                tracing::debug!(id = %metadata.id, "operation completed");
+                self.events.publish(Event::OperationCompleted {
+                    id: metadata.id.clone(),
+                    op_type,
+                    path,
+                });
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "operation failed");
+                self.events.publish(Event::OperationFailed {
+                    op_type,
+                    path,
+                    error: e.to_string(),
+                });
+            }
+        }
+        result
+    }
+
+    /// Perform an operation and record it
+    fn run(&mut self, operation: FileOperation) -> Result<OperationMetadata> {
+        if !self.history || !self.policies.resolve(operation.path()).history {
+            match &operation {
+                FileOperation::Delete { path } if !present(path) => {
+                    return Err(JanusError::FileNotFound(path.display().to_string()));
+                }
+                FileOperation::Modify { path, .. } if !path.exists() => {
+                    return Err(JanusError::FileNotFound(path.display().to_string()));
+                }
+                FileOperation::Delete { path } => {
+                    fs::remove_file(path)?;
+                    return Ok(OperationMetadata::new(OperationType::Delete, path.clone()));
+                }
+                FileOperation::Modify { path, new_content } => {
+                    fs::write(path, new_content)?;
+                    return Ok(OperationMetadata::new(OperationType::Modify, path.clone()));
+                }
+                _ => {}
+            }
+        }
+
         match operation {
             FileOperation::Delete { path } => self.execute_delete(&path),
             FileOperation::Modify { path, new_content } => self.execute_modify(&path, &new_content),
@@ -96,28 +403,193 @@ pub fn execute(&mut self, operation: FileOperation) -> Result<OperationMetadata>
             FileOperation::Copy {
                 source,
                 destination,
-            } => self.execute_copy(&source, &destination),
+                preserve,
+            } => self.execute_copy(&source, &destination, &preserve),
             #[cfg(unix)]
             FileOperation::Chmod { path, new_mode } => self.execute_chmod(&path, new_mode),
-            FileOperation::Create { path, content } => self.execute_create(&path, &content),
+            FileOperation::Create {
+                path,
+                content,
+                mode,
+            } => self.execute_create(&path, &content, mode),
+            #[cfg(unix)]
+            FileOperation::Symlink { path, target } => self.execute_symlink(&path, &target),
         }
     }
 
+    /// Execute an operation at most once per idempotency `key`.
+    ///
+    /// The operation is recorded under the ID [`operation_id_for_key`]
+    /// gives the key. If that ID is already recorded for the same
+    /// operation (same type, paths and content), the earlier metadata is
+    /// returned and nothing is done, so automation can retry safely; for
+    /// a different operation this fails with `IdempotencyConflict`.
+    /// Keys are forgotten once `gc` prunes their operation, and have no
+    /// effect [`without_history`](Self::without_history).
+    pub fn execute_with_key(
+        &mut self,
+        operation: FileOperation,
+        key: &str,
+    ) -> Result<OperationMetadata> {
+        if key.is_empty() {
+            return Err(JanusError::IdempotencyConflict(
+                "empty idempotency key".to_string(),
+            ));
+        }
+        let fingerprint = operation.fingerprint();
+        if let Some(existing) = self.metadata_store.get(&operation_id_for_key(key)) {
+            return if existing.request_fingerprint.as_deref() == Some(fingerprint.as_str()) {
+                Ok(existing.clone())
+            } else {
+                Err(JanusError::IdempotencyConflict(key.to_string()))
+            };
+        }
+
+        self.idempotency = Some((key.to_string(), fingerprint));
+        let result = self.execute(operation);
+        self.idempotency = None;
+        result
+    }
+
+    /// Execute `operations` in order, then record them all with one
+    /// synced log append: either every operation is applied and recorded,
+    /// or none is.
+    ///
+    /// If an operation fails, or the log cannot be written, those already
+    /// applied are reversed, newest first, and the error is returned; if
+    /// reversing also fails, the error is `RollbackIncomplete`. Content
+    /// captured for reversed operations is left for `gc`. Deletes and
+    /// modifies run [`without_history`](Self::without_history) cannot be
+    /// reversed.
+    pub fn execute_batch(
+        &mut self,
+        operations: Vec<FileOperation>,
+    ) -> Result<Vec<OperationMetadata>> {
+        self.deferred = Some(Vec::new());
+        let mut applied = Vec::with_capacity(operations.len());
+        let mut failure = None;
+        for operation in operations {
+            match self.execute(operation) {
+                Ok(metadata) => applied.push(metadata),
+                Err(e) => {
+                    failure = Some(e);
+                    break;
+                }
+            }
+        }
+        let recorded = self.deferred.take().unwrap_or_default();
+        let failure = match failure {
+            Some(e) => e,
+            None => match self.metadata_store.append_batch(recorded) {
+                Ok(()) => {
+                    for metadata in &applied {
+                        self.trim_versions(&metadata.path)?;
+                    }
+                    return Ok(applied);
+                }
+                Err(e) => e,
+            },
+        };
+
+        // Reverse without recording anything
+        self.deferred = Some(Vec::new());
+        let unreversed = applied
+            .iter()
+            .rev()
+            .filter(|metadata| self.reverse(metadata).is_err())
+            .count();
+        self.deferred = None;
+        if unreversed > 0 {
+            return Err(JanusError::RollbackIncomplete(format!(
+                "batch failed ({}) and {} applied operations could not be reversed",
+                failure, unreversed
+            )));
+        }
+        Err(failure)
+    }
+
+    /// Reverse an operation of a failed batch
+    fn reverse(&mut self, metadata: &OperationMetadata) -> Result<()> {
+        let inverse = self.inverse(metadata)?;
+        let parent_missing = parent_missing(metadata);
+        self.execute(inverse)?;
+        self.restore_file_metadata(metadata, parent_missing)
+    }
+
+    /// After undoing a delete or modify, restore the file's captured
+    /// streams, then for a delete its times and owner, then its
+    /// permissions or attributes. With `parent_created`, the undo had to
+    /// recreate the directory holding it, which gets its captured
+    /// metadata too.
+    fn restore_file_metadata(&self, op: &OperationMetadata, parent_created: bool) -> Result<()> {
+        if !matches!(op.op_type, OperationType::Delete | OperationType::Modify) {
+            return Ok(());
+        }
+        let Some(ref file_meta) = op.original_metadata else {
+            return Ok(());
+        };
+        // Permissions and owner would apply to the link's target
+        if !file_meta.is_symlink {
+            ntfs::restore_streams(self.content_store, &op.path, &file_meta.streams)?;
+            // A modify leaves Unix permissions and times alone, but
+            // rewriting the file resets its Windows attributes
+            if op.op_type == OperationType::Delete {
+                self.restore_entry(file_meta, &op.path)?;
+            } else if file_meta.attributes.is_some() {
+                file_meta.apply(&op.path)?;
+            }
+        }
+        if let (true, Some(parent), Some(dir)) = (
+            parent_created && op.op_type == OperationType::Delete,
+            file_meta.parent.as_deref(),
+            op.path.parent(),
+        ) {
+            self.restore_entry(parent, dir)?;
+        }
+        Ok(())
+    }
+
+    /// Give a recreated file or directory its captured times, owner and
+    /// permissions, in that order: only the owner may set the times, and
+    /// changing owner clears setuid and setgid
+    fn restore_entry(&self, meta: &FileMetadata, path: &Path) -> Result<()> {
+        meta.restore_times(path)?;
+        self.ownership.restore(meta, path)?;
+        meta.apply(path)
+    }
+
     /// Execute delete operation
     fn execute_delete(&mut self, path: &Path) -> Result<OperationMetadata> {
-        if !path.exists() {
+        if !present(path) {
             return Err(JanusError::FileNotFound(path.display().to_string()));
         }
 
-        // Capture original content and metadata
-        let content = fs::read(path)?;
-        let file_metadata = FileMetadata::from_path(path)?;
-        let content_hash = self.content_store.store(&content)?;
+        // Capture original content and metadata; a link's content is its
+        // target, never what it points to
+        let written = self.content_store.bytes_written();
+        let mut file_metadata = self.capture_file_metadata(path)?;
+        file_metadata.parent = path
+            .parent()
+            .and_then(|parent| FileMetadata::from_path(parent).ok())
+            .map(Box::new);
+        let (content_hash, plaintext_hash) = if file_metadata.is_symlink {
+            (self.content_store.store(&link_content(path)?)?, None)
+        } else if self.policies.resolve(path).encrypt_content {
+            self.store_sealed(&self.content_store.read_file(path)?)?
+        } else {
+            (self.content_store.store_file(path)?, None)
+        };
+        let bytes = self.accounting(file_metadata.size, written);
 
         // Create operation metadata
         let mut metadata = OperationMetadata::new(OperationType::Delete, path.to_path_buf())
             .with_content_hash(content_hash)
-            .with_original_metadata(file_metadata);
+            .with_original_metadata(file_metadata)
+            .with_bytes(bytes);
+
+        if let Some(hash) = plaintext_hash {
+            metadata = metadata.with_plaintext_hash(hash);
+        }
 
         if let Some(ref tid) = self.transaction_id {
             metadata = metadata.with_transaction_id(tid.clone());
@@ -126,9 +598,7 @@ fn execute_delete(&mut self, path: &Path) -> Result<OperationMetadata> {
         // Perform the delete
         fs::remove_file(path)?;
 
-        // Record and return
-        self.metadata_store.append(metadata.clone())?;
-        Ok(metadata)
+        self.record(metadata)
     }
 
     /// Execute modify operation
@@ -138,29 +608,139 @@ fn execute_modify(&mut self, path: &Path, new_content: &[u8]) -> Result<Operatio
         }
 
         // Capture original content
-        let original_content = fs::read(path)?;
-        let file_metadata = FileMetadata::from_path(path)?;
-        let original_hash = self.content_store.store(&original_content)?;
-        let new_hash = ContentHash::from_bytes(new_content);
+        let written = self.content_store.bytes_written();
+        let original_content = self.content_store.read_file(path)?;
+        let file_metadata = self.capture_file_metadata(path)?;
+        let (original_hash, plaintext_hash, delta_base) =
+            if self.policies.resolve(path).encrypt_content {
+                let (hash, plaintext_hash) = self.store_sealed(&original_content)?;
+                (hash, plaintext_hash, None)
+            } else {
+                let (hash, delta_base) = match self.delta_base_for(path) {
+                    Some(base) => self.content_store.store_delta(&original_content, &base)?,
+                    None => (self.content_store.store(&original_content)?, None),
+                };
+                (hash, None, delta_base)
+            };
+        let new_hash = self.content_store.hash(new_content);
+        let bytes = self.accounting(original_content.len() as u64, written);
 
         // Create operation metadata
         let mut metadata = OperationMetadata::new(OperationType::Modify, path.to_path_buf())
             .with_content_hash(original_hash)
             .with_new_content_hash(new_hash)
-            .with_original_metadata(file_metadata);
+            .with_original_metadata(file_metadata)
+            .with_bytes(bytes);
 
+        if let Some(base) = delta_base {
+            metadata = metadata.with_delta_base(base);
+        }
+        if let Some(hash) = plaintext_hash {
+            metadata = metadata.with_plaintext_hash(hash);
+        }
         if let Some(ref tid) = self.transaction_id {
             metadata = metadata.with_transaction_id(tid.clone());
         }
 
-        // Perform the modify
+        // Perform the modify, once the original is no longer mapped:
+        // truncating a mapped file faults (and fails outright on Windows)
+        drop(original_content);
         fs::write(path, new_content)?;
 
-        // Record and return
-        self.metadata_store.append(metadata.clone())?;
+        self.record(metadata)
+    }
+
+    /// Metadata of a file about to be deleted or modified, with its
+    /// alternate data streams stored. Streams are not captured where a
+    /// policy encrypts the content, so they are never stored in the clear.
+    fn capture_file_metadata(&self, path: &Path) -> Result<FileMetadata> {
+        let mut file_metadata = FileMetadata::from_path(path)?;
+        if !file_metadata.is_symlink && !self.policies.resolve(path).encrypt_content {
+            file_metadata.streams = ntfs::capture_streams(self.content_store, path)?;
+        }
+        Ok(file_metadata)
+    }
+
+    /// Accounting for a capture that read `read` bytes of content, started
+    /// when the content store had written `written_before`. Includes any
+    /// alternate data streams stored with it.
+    fn accounting(&self, read: u64, written_before: u64) -> ByteAccounting {
+        ByteAccounting {
+            read,
+            stored: self.content_store.bytes_written() - written_before,
+        }
+    }
+
+    /// Append an operation to the log and return it as recorded
+    fn record(&mut self, mut metadata: OperationMetadata) -> Result<OperationMetadata> {
+        metadata.actor = self.metadata_store.actor().map(String::from);
+        metadata.refs = self.metadata_store.refs().clone();
+        refs::inherit(&mut metadata.refs, &self.refs);
+        if let Some((key, fingerprint)) = self.idempotency.take() {
+            metadata = metadata.with_idempotency_key(&key, fingerprint);
+        }
+        match &mut self.deferred {
+            Some(deferred) => deferred.push(metadata.clone()),
+            None => {
+                self.metadata_store.append(metadata.clone())?;
+                self.trim_versions(&metadata.path)?;
+            }
+        }
         Ok(metadata)
     }
 
+    /// Store `content` encrypted, returning the hashes of the envelope
+    /// and of the content itself
+    fn store_sealed(&self, content: &[u8]) -> Result<(ContentHash, Option<ContentHash>)> {
+        let envelope = self.policies.seal(content)?;
+        Ok((
+            self.content_store.store(&envelope)?,
+            Some(self.content_store.hash(content)),
+        ))
+    }
+
+    /// Drop the oldest operations that captured content of `path` beyond
+    /// the `max_versions` its policy keeps. As with `gc`, what the kept
+    /// operations depend on stays, and the content is left for `gc`.
+    fn trim_versions(&mut self, path: &Path) -> Result<()> {
+        let Some(max_versions) = self.policies.resolve(path).max_versions else {
+            return Ok(());
+        };
+        let operations = self.metadata_store.operations();
+        let versions: Vec<&str> = operations
+            .iter()
+            .filter(|op| op.path == path && op.content_hash.is_some())
+            .map(|op| op.id.as_str())
+            .collect();
+        let Some(excess) = versions.len().checked_sub(max_versions).filter(|&n| n > 0) else {
+            return Ok(());
+        };
+        let dropped: HashSet<&str> = versions[..excess].iter().copied().collect();
+        let mut kept: HashSet<String> = operations
+            .iter()
+            .filter(|op| !dropped.contains(op.id.as_str()))
+            .map(|op| op.id.clone())
+            .collect();
+        kept.extend(self.pinned.iter().cloned());
+        self.metadata_store.prune_pinned(0, &kept)?;
+        Ok(())
+    }
+
+    /// Most recent full blob stored for a path, used as the delta base
+    fn delta_base_for(&self, path: &Path) -> Option<ContentHash> {
+        if !self.content_store.delta_enabled() {
+            return None;
+        }
+        self.metadata_store
+            .operations()
+            .iter()
+            .rev()
+            .filter(|op| op.path == path && op.plaintext_hash.is_none())
+            .filter_map(|op| op.content_hash.as_ref())
+            .find(|hash| self.content_store.is_full(hash))
+            .cloned()
+    }
+
     /// Execute move operation
     fn execute_move(&mut self, source: &Path, destination: &Path) -> Result<OperationMetadata> {
         if !source.exists() {
@@ -187,22 +767,30 @@ fn execute_move(&mut self, source: &Path, destination: &Path) -> Result<Operatio
             metadata = metadata.with_transaction_id(tid.clone());
         }
 
-        // Perform the move
-        fs::rename(source, destination)?;
+        // Perform the move; across filesystems it is a verified copy, and
+        // the moved content's hash is kept with the operation
+        if let Some(hash) = move_file(source, destination)? {
+            metadata = metadata.with_new_content_hash(hash);
+        }
 
-        // Record and return
-        self.metadata_store.append(metadata.clone())?;
-        Ok(metadata)
+        self.record(metadata)
     }
 
     /// Execute copy operation
-    fn execute_copy(&mut self, source: &Path, destination: &Path) -> Result<OperationMetadata> {
+    fn execute_copy(
+        &mut self,
+        source: &Path,
+        destination: &Path,
+        preserve: &BTreeSet<PreservedAttribute>,
+    ) -> Result<OperationMetadata> {
         if !source.exists() {
             return Err(JanusError::FileNotFound(source.display().to_string()));
         }
         if destination.exists() {
             return Err(JanusError::PathExists(destination.display().to_string()));
         }
+        // Before copying, which can change the access time
+        let source_metadata = FileMetadata::from_path(source)?;
 
         // Create parent directory if needed
         if let Some(parent) = destination.parent() {
@@ -217,12 +805,12 @@ fn execute_copy(&mut self, source: &Path, destination: &Path) -> Result<Operatio
             metadata = metadata.with_transaction_id(tid.clone());
         }
 
-        // Perform the copy
-        fs::copy(source, destination)?;
+        // Perform the copy, as a reflink where the filesystem allows
+        clone_file(source, destination)?;
+        metadata.preserved = preserve_attributes(source, destination, &source_metadata, preserve)?;
+        metadata.new_metadata = Some(FileMetadata::from_path(destination)?);
 
-        // Record and return
-        self.metadata_store.append(metadata.clone())?;
-        Ok(metadata)
+        self.record(metadata)
     }
 
     /// Execute chmod operation
@@ -254,16 +842,25 @@ fn execute_chmod(&mut self, path: &Path, new_mode: u32) -> Result<OperationMetad
         let perms = fs::Permissions::from_mode(new_mode);
         fs::set_permissions(path, perms)?;
 
-        // Record and return
-        self.metadata_store.append(metadata.clone())?;
-        Ok(metadata)
+        self.record(metadata)
     }
 
     /// Execute create operation
-    fn execute_create(&mut self, path: &Path, content: &[u8]) -> Result<OperationMetadata> {
-        if path.exists() {
+    fn execute_create(
+        &mut self,
+        path: &Path,
+        content: &[u8],
+        mode: Option<u32>,
+    ) -> Result<OperationMetadata> {
+        // A dangling link would be written through
+        if present(path) {
             return Err(JanusError::PathExists(path.display().to_string()));
         }
+        if cfg!(not(unix)) && mode.is_some() {
+            return Err(JanusError::OperationFailed(
+                "File modes not supported on this platform".to_string(),
+            ));
+        }
 
         // Create parent directory if needed
         if let Some(parent) = path.parent() {
@@ -271,7 +868,7 @@ fn execute_create(&mut self, path: &Path, content: &[u8]) -> Result<OperationMet
         }
 
         // Create operation metadata
-        let content_hash = ContentHash::from_bytes(content);
+        let content_hash = self.content_store.hash(content);
         let mut metadata = OperationMetadata::new(OperationType::Create, path.to_path_buf())
             .with_new_content_hash(content_hash);
 
@@ -281,14 +878,126 @@ fn execute_create(&mut self, path: &Path, content: &[u8]) -> Result<OperationMet
 
         // Perform the create
         fs::write(path, content)?;
+        #[cfg(unix)]
+        if let Some(mode) = mode {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+            metadata.new_metadata = Some(FileMetadata::from_path(path)?);
+        }
 
-        // Record and return
-        self.metadata_store.append(metadata.clone())?;
-        Ok(metadata)
+        self.record(metadata)
+    }
+
+    /// Execute symlink operation
+    #[cfg(unix)]
+    fn execute_symlink(&mut self, path: &Path, target: &Path) -> Result<OperationMetadata> {
+        if present(path) {
+            return Err(JanusError::PathExists(path.display().to_string()));
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let content_hash = self
+            .content_store
+            .hash(target.as_os_str().as_encoded_bytes());
+        let mut metadata = OperationMetadata::new(OperationType::Create, path.to_path_buf())
+            .with_new_content_hash(content_hash);
+        if let Some(ref tid) = self.transaction_id {
+            metadata = metadata.with_transaction_id(tid.clone());
+        }
+
+        std::os::unix::fs::symlink(target, path)?;
+
+        self.record(metadata)
     }
 
-    /// Undo an operation using its metadata
+    /// Undo an operation using its metadata.
+    ///
+    /// When the undo restores content (of a delete or modify), the file is
+    /// re-read and hashed afterwards. A match is recorded as the undo's
+    /// `verified_hash`; a mismatch reverts the undo and fails with
+    /// [`JanusError::ContentIntegrityError`].
     pub fn undo(&mut self, operation_id: &str) -> Result<OperationMetadata> {
+        let _span = tracing::debug_span!("undo", operation = operation_id).entered();
+        self.undo_operation(operation_id)
+            .map_err(|e| e.with_operation(operation_id))
+    }
+
+    fn undo_operation(&mut self, operation_id: &str) -> Result<OperationMetadata> {
+        let original_op = self.undoable(operation_id)?;
+        let inverse = self.inverse(&original_op)?;
+        // Held through verification, and a revert if that fails
+        let _locks = self.lock_targets(&inverse)?;
+        self.pinned.insert(operation_id.to_string());
+        let parent_missing = parent_missing(&original_op);
+        let mut undo_metadata = self.execute_locked(inverse)?;
+        if original_op.op_type == OperationType::Delete {
+            undo_metadata.op_type = OperationType::Create;
+        }
+
+        if let Some(expected) = restored_hash(&original_op) {
+            let actual = if is_link(&original_op.path) {
+                expected.rehash(&link_content(&original_op.path)?)
+            } else {
+                expected.rehash(&self.content_store.read_file(&original_op.path)?)
+            };
+            if actual != *expected {
+                tracing::warn!(%expected, %actual, "restored content does not match, reverting undo");
+                self.revert(&undo_metadata.id)?;
+                return Err(JanusError::ContentIntegrityError {
+                    expected: expected.0.clone(),
+                    actual: actual.0,
+                });
+            }
+            self.metadata_store
+                .mark_verified(&undo_metadata.id, actual.clone())?;
+            undo_metadata.verified_hash = Some(actual);
+        }
+
+        // Restore original metadata (streams, times, owner, permissions,
+        // attributes) once verified, so reading it back does not touch
+        // the access time and a restored mode cannot refuse the read
+        self.restore_file_metadata(&original_op, parent_missing)?;
+
+        // Mark original operation as undone
+        self.metadata_store
+            .mark_undone(operation_id, &undo_metadata.id)?;
+        tracing::info!(undo = %undo_metadata.id, "operation undone");
+        self.events.publish(Event::UndoPerformed {
+            operation_id: operation_id.to_string(),
+            undo_operation_id: undo_metadata.id.clone(),
+        });
+
+        Ok(undo_metadata)
+    }
+
+    /// Reverse an undo whose restored content failed verification; the
+    /// undo holds the locks
+    fn revert(&mut self, undo_id: &str) -> Result<()> {
+        let undo_op = self.undoable(undo_id)?;
+        let inverse = self.inverse(&undo_op)?;
+        self.pinned.insert(undo_id.to_string());
+        let revert_metadata = self.execute_locked(inverse)?;
+        self.metadata_store
+            .mark_undone(undo_id, &revert_metadata.id)
+    }
+
+    /// Work out what undoing an operation would do, without touching the
+    /// filesystem or the operation log.
+    ///
+    /// Returns the inverse operation `undo` would execute. Its preconditions
+    /// are checked against `sim`, which then records its effects so that
+    /// several undos (a rollback) can be simulated in sequence.
+    pub fn simulate_undo(&self, operation_id: &str, sim: &mut Simulation) -> Result<FileOperation> {
+        let original_op = self.undoable(operation_id)?;
+        let inverse = self.inverse(&original_op)?;
+        sim.apply(&inverse)?;
+        Ok(inverse)
+    }
+
+    /// Look up an operation that has not been undone yet
+    fn undoable(&self, operation_id: &str) -> Result<OperationMetadata> {
         let original_op = self
             .metadata_store
             .get(operation_id)
@@ -302,155 +1011,308 @@ pub fn undo(&mut self, operation_id: &str) -> Result<OperationMetadata> {
             )));
         }
 
-        let undo_metadata = match original_op.op_type {
-            OperationType::Delete => self.undo_delete(&original_op)?,
-            OperationType::Modify => self.undo_modify(&original_op)?,
-            OperationType::Move => self.undo_move(&original_op)?,
-            OperationType::Copy => self.undo_copy(&original_op)?,
+        Ok(original_op)
+    }
+
+    /// The operation that reverses `original`
+    fn inverse(&self, original: &OperationMetadata) -> Result<FileOperation> {
+        match original.op_type {
+            // Undo delete of a link: recreate the link
+            OperationType::Delete
+                if original
+                    .original_metadata
+                    .as_ref()
+                    .is_some_and(|meta| meta.is_symlink) =>
+            {
+                #[cfg(unix)]
+                {
+                    use std::os::unix::ffi::OsStringExt;
+                    Ok(FileOperation::Symlink {
+                        path: original.path.clone(),
+                        target: PathBuf::from(std::ffi::OsString::from_vec(
+                            self.original_content(original)?,
+                        )),
+                    })
+                }
+                #[cfg(not(unix))]
+                {
+                    Err(JanusError::OperationFailed(
+                        "Symlinks not supported on this platform".to_string(),
+                    ))
+                }
+            }
+            // Undo delete: restore file from content store
+            OperationType::Delete => Ok(FileOperation::Create {
+                path: original.path.clone(),
+                content: self.original_content(original)?,
+                mode: None,
+            }),
+            // Undo modify: restore original content
+            OperationType::Modify => Ok(FileOperation::Modify {
+                path: original.path.clone(),
+                new_content: self.original_content(original)?,
+            }),
+            // Undo move: move back to original location
+            OperationType::Move => Ok(FileOperation::Move {
+                source: secondary_path(original)?,
+                destination: original.path.clone(),
+            }),
+            // Undo copy: delete the copy
+            OperationType::Copy => Ok(FileOperation::Delete {
+                path: secondary_path(original)?,
+            }),
+            // Undo chmod: restore original permissions
             OperationType::Chmod => {
                 #[cfg(unix)]
                 {
-                    self.undo_chmod(&original_op)?
+                    let file_meta = original.original_metadata.as_ref().ok_or_else(|| {
+                        JanusError::MetadataCorrupted("Missing original metadata".to_string())
+                    })?;
+                    Ok(FileOperation::Chmod {
+                        path: original.path.clone(),
+                        new_mode: file_meta.permissions,
+                    })
                 }
                 #[cfg(not(unix))]
                 {
-                    return Err(JanusError::OperationFailed(
+                    Err(JanusError::OperationFailed(
                         "Chmod not supported on this platform".to_string(),
-                    ));
+                    ))
                 }
             }
-            OperationType::Create => self.undo_create(&original_op)?,
-            OperationType::Chown => {
-                return Err(JanusError::OperationFailed(
-                    "Chown undo not yet implemented".to_string(),
-                ))
-            }
-        };
-
-        // Mark original operation as undone
-        self.metadata_store
-            .mark_undone(operation_id, &undo_metadata.id)?;
+            // Undo create: delete the created file
+            OperationType::Create => Ok(FileOperation::Delete {
+                path: original.path.clone(),
+            }),
+            OperationType::Chown => Err(JanusError::OperationFailed(
+                "Chown undo not yet implemented".to_string(),
+            )),
+        }
+    }
 
-        Ok(undo_metadata)
+    /// Content captured before the operation ran
+    fn original_content(&self, original: &OperationMetadata) -> Result<Vec<u8>> {
+        captured_content(self.content_store, original, self.content_keys)
     }
+}
 
-    /// Undo delete: restore file from content store
-    fn undo_delete(&mut self, original: &OperationMetadata) -> Result<OperationMetadata> {
-        let content_hash = original
-            .content_hash
+/// Hash an undo of `original` must reproduce on disk, if it restores content
+pub fn restored_hash(original: &OperationMetadata) -> Option<&ContentHash> {
+    match original.op_type {
+        OperationType::Delete | OperationType::Modify => original
+            .plaintext_hash
             .as_ref()
-            .ok_or_else(|| JanusError::MetadataCorrupted("Missing content hash".to_string()))?;
-
-        // Retrieve original content
-        let content = self.content_store.retrieve(content_hash)?;
-
-        // Create (restore) the file
-        let create_op = FileOperation::Create {
-            path: original.path.clone(),
-            content,
-        };
+            .or(original.content_hash.as_ref()),
+        _ => None,
+    }
+}
 
-        let mut metadata = self.execute(create_op)?;
+/// Carry `preserve` over from `source`, whose metadata is `meta`, to its
+/// new copy `destination`. Returns what was carried over: the mode, which
+/// always is, and the rest as far as the platform and privileges allow.
+fn preserve_attributes(
+    source: &Path,
+    destination: &Path,
+    meta: &FileMetadata,
+    preserve: &BTreeSet<PreservedAttribute>,
+) -> Result<BTreeSet<PreservedAttribute>> {
+    let mut preserved = BTreeSet::from([PreservedAttribute::Mode]);
+    if preserve.contains(&PreservedAttribute::Xattr) && copy_xattrs(source, destination) {
+        preserved.insert(PreservedAttribute::Xattr);
+    }
+    // Before the owner, as only the owner may set them
+    if preserve.contains(&PreservedAttribute::Timestamps) {
+        meta.restore_times(destination)?;
+        preserved.insert(PreservedAttribute::Timestamps);
+    }
+    if preserve.contains(&PreservedAttribute::Ownership) && copy_ownership(meta, destination)? {
+        preserved.insert(PreservedAttribute::Ownership);
+        // Changing owner clears setuid and setgid
+        meta.apply(destination)?;
+    }
+    Ok(preserved)
+}
 
-        // Restore original metadata (permissions, etc.)
-        if let Some(ref file_meta) = original.original_metadata {
-            file_meta.apply(&original.path)?;
+/// Copy every extended attribute of `source` to `destination`; whether
+/// all of them were copied.
+#[cfg(unix)]
+fn copy_xattrs(source: &Path, destination: &Path) -> bool {
+    let names = match xattr::list(source) {
+        Ok(names) => names,
+        Err(e) => {
+            tracing::warn!(
+                "{}: cannot list extended attributes: {}",
+                source.display(),
+                e
+            );
+            return false;
+        }
+    };
+    let mut complete = true;
+    for name in names {
+        let copied = xattr::get(source, &name)
+            .and_then(|value| value.map_or(Ok(()), |value| xattr::set(destination, &name, &value)));
+        if let Err(e) = copied {
+            tracing::warn!(
+                "{}: cannot copy extended attribute {:?}: {}",
+                destination.display(),
+                name,
+                e
+            );
+            complete = false;
         }
-
-        metadata.op_type = OperationType::Create;
-        Ok(metadata)
     }
+    complete
+}
 
-    /// Undo modify: restore original content
-    fn undo_modify(&mut self, original: &OperationMetadata) -> Result<OperationMetadata> {
-        let content_hash = original
-            .content_hash
-            .as_ref()
-            .ok_or_else(|| JanusError::MetadataCorrupted("Missing content hash".to_string()))?;
+#[cfg(not(unix))]
+fn copy_xattrs(_source: &Path, _destination: &Path) -> bool {
+    false
+}
 
-        // Retrieve original content
-        let content = self.content_store.retrieve(content_hash)?;
+/// Give `path` the owner and group IDs `meta` captured; whether it could.
+/// Only root may give files away, so a refusal is logged, not failed.
+#[cfg(unix)]
+fn copy_ownership(meta: &FileMetadata, path: &Path) -> Result<bool> {
+    match std::os::unix::fs::lchown(path, meta.owner.parse().ok(), meta.group.parse().ok()) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            tracing::warn!("{}: cannot copy owner: {}", path.display(), e);
+            Ok(false)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
 
-        // Modify back to original
-        let modify_op = FileOperation::Modify {
-            path: original.path.clone(),
-            new_content: content,
-        };
+#[cfg(not(unix))]
+fn copy_ownership(_meta: &FileMetadata, _path: &Path) -> Result<bool> {
+    Ok(false)
+}
 
-        self.execute(modify_op)
-    }
+/// Whether undoing the delete `op` has to recreate the directory that
+/// held the file
+fn parent_missing(op: &OperationMetadata) -> bool {
+    op.op_type == OperationType::Delete
+        && op
+            .path
+            .parent()
+            .is_some_and(|parent| !parent.as_os_str().is_empty() && !parent.exists())
+}
 
-    /// Undo move: move back to original location
-    fn undo_move(&mut self, original: &OperationMetadata) -> Result<OperationMetadata> {
-        let destination = original
-            .path_secondary
-            .as_ref()
-            .ok_or_else(|| JanusError::MetadataCorrupted("Missing secondary path".to_string()))?;
+/// Whether `path` exists, as a link even if dangling
+fn present(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok()
+}
 
-        let move_op = FileOperation::Move {
-            source: destination.clone(),
-            destination: original.path.clone(),
-        };
+/// Whether `path` is a symbolic link
+fn is_link(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_symlink())
+}
 
-        self.execute(move_op)
-    }
+/// What is stored for a link: its target
+fn link_content(path: &Path) -> Result<Vec<u8>> {
+    Ok(fs::read_link(path)?.into_os_string().into_encoded_bytes())
+}
 
-    /// Undo copy: delete the copy
-    fn undo_copy(&mut self, original: &OperationMetadata) -> Result<OperationMetadata> {
-        let destination = original
-            .path_secondary
-            .as_ref()
-            .ok_or_else(|| JanusError::MetadataCorrupted("Missing secondary path".to_string()))?;
+/// Second path of a move or copy (its destination)
+fn secondary_path(original: &OperationMetadata) -> Result<PathBuf> {
+    original
+        .path_secondary
+        .clone()
+        .ok_or_else(|| JanusError::MetadataCorrupted("Missing secondary path".to_string()))
+}
 
-        let delete_op = FileOperation::Delete {
-            path: destination.clone(),
-        };
+/// Filesystem view for dry runs: paths created or removed by simulated
+/// operations, over the real filesystem (which is only ever read)
+#[derive(Debug, Default)]
+pub struct Simulation {
+    overlay: HashMap<PathBuf, bool>,
+}
 
-        self.execute(delete_op)
+impl Simulation {
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    /// Undo chmod: restore original permissions
-    #[cfg(unix)]
-    fn undo_chmod(&mut self, original: &OperationMetadata) -> Result<OperationMetadata> {
-        let file_meta = original.original_metadata.as_ref().ok_or_else(|| {
-            JanusError::MetadataCorrupted("Missing original metadata".to_string())
-        })?;
-
-        let chmod_op = FileOperation::Chmod {
-            path: original.path.clone(),
-            new_mode: file_meta.permissions,
-        };
-
-        self.execute(chmod_op)
+    /// Whether `path` exists once the simulated operations have run
+    pub fn exists(&self, path: &Path) -> bool {
+        self.overlay
+            .get(path)
+            .copied()
+            .unwrap_or_else(|| present(path))
     }
 
-    /// Undo create: delete the created file
-    fn undo_create(&mut self, original: &OperationMetadata) -> Result<OperationMetadata> {
-        let delete_op = FileOperation::Delete {
-            path: original.path.clone(),
+    /// Check an operation's preconditions the way `execute` does, then
+    /// record its effect
+    pub fn apply(&mut self, operation: &FileOperation) -> Result<()> {
+        let require = |path: &Path| {
+            if self.exists(path) {
+                Ok(())
+            } else {
+                Err(JanusError::FileNotFound(path.display().to_string()))
+            }
+        };
+        let forbid = |path: &Path| {
+            if self.exists(path) {
+                Err(JanusError::PathExists(path.display().to_string()))
+            } else {
+                Ok(())
+            }
         };
 
-        self.execute(delete_op)
+        match operation {
+            FileOperation::Delete { path } => {
+                require(path)?;
+                self.overlay.insert(path.clone(), false);
+            }
+            FileOperation::Modify { path, .. } => require(path)?,
+            #[cfg(unix)]
+            FileOperation::Chmod { path, .. } => require(path)?,
+            FileOperation::Move {
+                source,
+                destination,
+            } => {
+                require(source)?;
+                forbid(destination)?;
+                self.overlay.insert(source.clone(), false);
+                self.overlay.insert(destination.clone(), true);
+            }
+            FileOperation::Copy {
+                source,
+                destination,
+                ..
+            } => {
+                require(source)?;
+                forbid(destination)?;
+                self.overlay.insert(destination.clone(), true);
+            }
+            FileOperation::Create { path, .. } => {
+                forbid(path)?;
+                self.overlay.insert(path.clone(), true);
+            }
+            #[cfg(unix)]
+            FileOperation::Symlink { path, .. } => {
+                forbid(path)?;
+                self.overlay.insert(path.clone(), true);
+            }
+        }
+        Ok(())
     }
 }
 
-/// Delete files matching a glob pattern
+/// Delete files matching a glob pattern, skipping any `excludes` matches
 pub fn delete_glob(
     pattern: &str,
     base_dir: &Path,
+    excludes: &Excludes,
     content_store: &ContentStore,
     metadata_store: &mut MetadataStore,
     transaction_id: Option<String>,
 ) -> Result<Vec<OperationMetadata>> {
-    let full_pattern = base_dir.join(pattern);
-    let pattern_str = full_pattern.to_string_lossy();
-    let paths: Vec<PathBuf> = glob::glob(&pattern_str)?
-        .filter_map(|r| r.ok())
-        .filter(|p| p.is_file())
-        .collect();
+    let selection = select_files(base_dir, &[pattern], false, excludes)?;
 
     let mut results = Vec::new();
-    for path in paths {
+    for path in selection.files.into_iter().chain(selection.links) {
         let mut executor = OperationExecutor::new(content_store, metadata_store);
         if let Some(ref tid) = transaction_id {
             executor = executor.with_transaction(tid.clone());
@@ -474,6 +1336,115 @@ fn setup() -> (TempDir, ContentStore, MetadataStore) {
         (tmp, content_store, metadata_store)
     }
 
+    #[test]
+    fn test_delete_glob_respects_excludes() {
+        let (tmp, content_store, mut metadata_store) = setup();
+        let base = tmp.path().join("work");
+        fs::create_dir(&base).unwrap();
+        for name in ["a.log", "b.log", "keep.log"] {
+            fs::write(base.join(name), name).unwrap();
+        }
+
+        let excludes = Excludes::new(&["keep.*"]).unwrap();
+        let deleted = delete_glob(
+            "*.log",
+            &base,
+            &excludes,
+            &content_store,
+            &mut metadata_store,
+            None,
+        )
+        .unwrap();
+        assert_eq!(deleted.len(), 2);
+        assert!(!base.join("a.log").exists());
+        assert!(base.join("keep.log").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_operations_wait_for_locked_files() {
+        let (tmp, content_store, mut metadata_store) = setup();
+        let test_file = tmp.path().join("locked.txt");
+        fs::write(&test_file, "original").unwrap();
+        let locking = FileLocking {
+            enabled: true,
+            timeout_ms: 50,
+        };
+        let mut executor =
+            OperationExecutor::new(&content_store, &mut metadata_store).with_locking(locking);
+
+        // Another holder keeps the modify out, and nothing is captured
+        let held = locking.lock(&test_file, LockMode::Shared).unwrap();
+        let error = executor
+            .execute(FileOperation::Modify {
+                path: test_file.clone(),
+                new_content: b"changed".to_vec(),
+            })
+            .unwrap_err();
+        assert_eq!(error.code(), "locked");
+        assert_eq!(error.path(), Some(test_file.as_path()));
+        assert_eq!(fs::read_to_string(&test_file).unwrap(), "original");
+        drop(held);
+        assert_eq!(executor.metadata_store.count(), 0);
+
+        let modify = executor
+            .execute(FileOperation::Modify {
+                path: test_file.clone(),
+                new_content: b"changed".to_vec(),
+            })
+            .unwrap();
+
+        // Undo takes the same lock, and releases it when done
+        let held = locking.lock(&test_file, LockMode::Exclusive).unwrap();
+        assert_eq!(executor.undo(&modify.id).unwrap_err().code(), "locked");
+        drop(held);
+        executor.undo(&modify.id).unwrap();
+        assert_eq!(fs::read_to_string(&test_file).unwrap(), "original");
+        assert!(locking
+            .lock(&test_file, LockMode::Exclusive)
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_repeated_modify_uses_delta_and_undoes() {
+        let tmp = TempDir::new().unwrap();
+        let content_store = ContentStore::new(tmp.path().join("content"), true)
+            .unwrap()
+            .with_delta(true);
+        let mut metadata_store = MetadataStore::new(tmp.path().join("metadata.json")).unwrap();
+
+        let test_file = tmp.path().join("big.txt");
+        let v0 = b"a line of text in a large file\n".repeat(400);
+        let mut v1 = v0.clone();
+        v1[100] = b'X';
+        let mut v2 = v1.clone();
+        v2[5000] = b'Y';
+        fs::write(&test_file, &v0).unwrap();
+
+        let mut executor = OperationExecutor::new(&content_store, &mut metadata_store);
+        let first = executor
+            .execute(FileOperation::Modify {
+                path: test_file.clone(),
+                new_content: v1.clone(),
+            })
+            .unwrap();
+        let second = executor
+            .execute(FileOperation::Modify {
+                path: test_file.clone(),
+                new_content: v2,
+            })
+            .unwrap();
+
+        assert!(first.delta_base.is_none());
+        assert_eq!(second.delta_base, first.content_hash);
+
+        executor.undo(&second.id).unwrap();
+        assert_eq!(fs::read(&test_file).unwrap(), v1);
+        executor.undo(&first.id).unwrap();
+        assert_eq!(fs::read(&test_file).unwrap(), v0);
+    }
+
     #[test]
     fn test_delete_and_undo() {
         let (tmp, content_store, mut metadata_store) = setup();
@@ -500,7 +1471,7 @@ fn test_delete_and_undo() {
         assert_eq!(
             ({
                 use std::io::Read;
-                std::fs::File::open(&test_file).and_then(|mut f| {
+                std::fs::File::open(&test_file).and_then(|f| {
                     let mut buf = String::new();
                     f.take(10 * 1024 * 1024).read_to_string(&mut buf)?;
                     Ok(buf)
@@ -511,6 +1482,196 @@ fn test_delete_and_undo() {
         );
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_undo_delete_restores_times_and_recreated_parent() {
+        use std::os::unix::fs::PermissionsExt;
+        let (tmp, content_store, mut metadata_store) = setup();
+        let dir = tmp.path().join("docs");
+        let test_file = dir.join("old.txt");
+        fs::create_dir(&dir).unwrap();
+        fs::write(&test_file, "from last year").unwrap();
+        let modified =
+            std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        let accessed = modified + std::time::Duration::from_secs(3600);
+        let times = fs::FileTimes::new()
+            .set_modified(modified)
+            .set_accessed(accessed);
+        fs::File::open(&test_file)
+            .unwrap()
+            .set_times(times)
+            .unwrap();
+        fs::set_permissions(&test_file, fs::Permissions::from_mode(0o400)).unwrap();
+        fs::File::open(&dir).unwrap().set_times(times).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o750)).unwrap();
+
+        let mut executor = OperationExecutor::new(&content_store, &mut metadata_store);
+        let delete_meta = executor
+            .execute(FileOperation::Delete {
+                path: test_file.clone(),
+            })
+            .unwrap();
+        fs::remove_dir(&dir).unwrap();
+
+        executor.undo(&delete_meta.id).unwrap();
+        let restored = fs::metadata(&test_file).unwrap();
+        assert_eq!(restored.modified().unwrap(), modified);
+        assert_eq!(restored.accessed().unwrap(), accessed);
+        assert_eq!(restored.permissions().mode() & 0o7777, 0o400);
+        let parent = fs::metadata(&dir).unwrap();
+        assert_eq!(parent.permissions().mode() & 0o7777, 0o750);
+        assert_eq!(parent.modified().unwrap(), modified);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_preserve_records_what_was_preserved() {
+        let (tmp, content_store, mut metadata_store) = setup();
+        let source = tmp.path().join("source.txt");
+        fs::write(&source, "keep my dates").unwrap();
+        let modified =
+            std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        fs::File::open(&source)
+            .unwrap()
+            .set_times(fs::FileTimes::new().set_modified(modified))
+            .unwrap();
+        let has_xattrs = xattr::set(&source, "user.januskey.test", b"1").is_ok();
+
+        let mut executor = OperationExecutor::new(&content_store, &mut metadata_store);
+        let plain = executor
+            .execute(FileOperation::Copy {
+                source: source.clone(),
+                destination: tmp.path().join("plain.txt"),
+                preserve: Default::default(),
+            })
+            .unwrap();
+        assert_eq!(plain.preserved, BTreeSet::from([PreservedAttribute::Mode]));
+        assert_ne!(
+            fs::metadata(tmp.path().join("plain.txt"))
+                .unwrap()
+                .modified()
+                .unwrap(),
+            modified
+        );
+
+        let dest = tmp.path().join("kept.txt");
+        let kept = executor
+            .execute(FileOperation::Copy {
+                source: source.clone(),
+                destination: dest.clone(),
+                preserve: BTreeSet::from([
+                    PreservedAttribute::Timestamps,
+                    PreservedAttribute::Xattr,
+                ]),
+            })
+            .unwrap();
+        assert!(kept.preserved.contains(&PreservedAttribute::Timestamps));
+        assert_eq!(
+            kept.preserved.contains(&PreservedAttribute::Xattr),
+            has_xattrs
+        );
+        assert_eq!(fs::metadata(&dest).unwrap().modified().unwrap(), modified);
+        assert_eq!(
+            kept.new_metadata.unwrap().modified.timestamp(),
+            1_700_000_000
+        );
+        if has_xattrs {
+            assert_eq!(
+                xattr::get(&dest, "user.januskey.test").unwrap(),
+                Some(b"1".to_vec())
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_create_with_mode_and_undo() {
+        use std::os::unix::fs::PermissionsExt;
+        let (tmp, content_store, mut metadata_store) = setup();
+        let path = tmp.path().join("secret.txt");
+
+        let mut executor = OperationExecutor::new(&content_store, &mut metadata_store);
+        let create_meta = executor
+            .execute(FileOperation::Create {
+                path: path.clone(),
+                content: b"token".to_vec(),
+                mode: Some(0o600),
+            })
+            .unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"token");
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o7777,
+            0o600
+        );
+        assert_eq!(
+            create_meta.new_metadata.unwrap().permissions & 0o7777,
+            0o600
+        );
+
+        executor.undo(&create_meta.id).unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_undo_verifies_restored_content() {
+        let (tmp, content_store, mut metadata_store) = setup();
+        let test_file = tmp.path().join("test.txt");
+        fs::write(&test_file, "original content").unwrap();
+
+        let mut executor = OperationExecutor::new(&content_store, &mut metadata_store);
+        let modify_meta = executor
+            .execute(FileOperation::Modify {
+                path: test_file.clone(),
+                new_content: b"modified content".to_vec(),
+            })
+            .unwrap();
+        let undo_meta = executor.undo(&modify_meta.id).unwrap();
+        assert_eq!(undo_meta.verified_hash, modify_meta.content_hash);
+
+        // Undos that restore nothing are not verified
+        let copy_meta = executor
+            .execute(FileOperation::Copy {
+                source: test_file.clone(),
+                destination: tmp.path().join("copy.txt"),
+                preserve: Default::default(),
+            })
+            .unwrap();
+        assert!(executor
+            .undo(&copy_meta.id)
+            .unwrap()
+            .verified_hash
+            .is_none());
+
+        let reopened = MetadataStore::new(tmp.path().join("metadata.json")).unwrap();
+        assert_eq!(
+            reopened.get(&undo_meta.id).unwrap().verified_hash,
+            modify_meta.content_hash
+        );
+    }
+
+    #[test]
+    fn test_modify_and_undo_mapped_file() {
+        let (tmp, content_store, mut metadata_store) = setup();
+        let content_store = content_store.with_mmap(true);
+        let test_file = tmp.path().join("big.bin");
+        let original = b"large file line\n".repeat(70_000);
+        fs::write(&test_file, &original).unwrap();
+        assert!(content_store.read_file(&test_file).unwrap().is_mapped());
+
+        let mut executor = OperationExecutor::new(&content_store, &mut metadata_store);
+        let modify_meta = executor
+            .execute(FileOperation::Modify {
+                path: test_file.clone(),
+                new_content: b"small now".to_vec(),
+            })
+            .unwrap();
+        assert_eq!(fs::read(&test_file).unwrap(), b"small now");
+
+        let undo_meta = executor.undo(&modify_meta.id).unwrap();
+        assert_eq!(undo_meta.verified_hash, modify_meta.content_hash);
+        assert_eq!(fs::read(&test_file).unwrap(), original);
+    }
+
     #[test]
     fn test_modify_and_undo() {
         let (tmp, content_store, mut metadata_store) = setup();
@@ -531,7 +1692,7 @@ fn test_modify_and_undo() {
         assert_eq!(
             ({
                 use std::io::Read;
-                std::fs::File::open(&test_file).and_then(|mut f| {
+                std::fs::File::open(&test_file).and_then(|f| {
                     let mut buf = String::new();
                     f.take(10 * 1024 * 1024).read_to_string(&mut buf)?;
                     Ok(buf)
@@ -548,7 +1709,7 @@ fn test_modify_and_undo() {
         assert_eq!(
             ({
                 use std::io::Read;
-                std::fs::File::open(&test_file).and_then(|mut f| {
+                std::fs::File::open(&test_file).and_then(|f| {
                     let mut buf = String::new();
                     f.take(10 * 1024 * 1024).read_to_string(&mut buf)?;
                     Ok(buf)
@@ -559,6 +1720,32 @@ fn test_modify_and_undo() {
         );
     }
 
+    #[test]
+    fn test_undo_append_after_later_change_restores_original() {
+        let (tmp, content_store, mut metadata_store) = setup();
+        let test_file = tmp.path().join("log.txt");
+        fs::write(&test_file, "one\n").unwrap();
+
+        // An append is recorded as a modify, capturing the whole original
+        let mut executor = OperationExecutor::new(&content_store, &mut metadata_store);
+        let append = executor
+            .execute(FileOperation::Modify {
+                path: test_file.clone(),
+                new_content: b"one\ntwo\n".to_vec(),
+            })
+            .unwrap();
+        assert!(append.content_hash.is_some());
+
+        // Changed again outside JanusKey, shorter than before the append
+        fs::write(&test_file, "x").unwrap();
+
+        // Undo restores the captured content rather than truncating
+        let mut executor = OperationExecutor::new(&content_store, &mut metadata_store);
+        let undo = executor.undo(&append.id).unwrap();
+        assert_eq!(fs::read_to_string(&test_file).unwrap(), "one\n");
+        assert_eq!(undo.verified_hash, append.content_hash);
+    }
+
     #[test]
     fn test_move_and_undo() {
         let (tmp, content_store, mut metadata_store) = setup();
@@ -603,6 +1790,7 @@ fn test_copy_and_undo() {
             .execute(FileOperation::Copy {
                 source: source.clone(),
                 destination: dest.clone(),
+                preserve: Default::default(),
             })
             .unwrap();
 
@@ -616,4 +1804,53 @@ fn test_copy_and_undo() {
         assert!(source.exists());
         assert!(!dest.exists());
     }
+
+    #[test]
+    fn test_batch_is_all_or_nothing() {
+        let (tmp, content_store, mut metadata_store) = setup();
+        let a = tmp.path().join("a.txt");
+        let b = tmp.path().join("b.txt");
+        fs::write(&a, "one").unwrap();
+        fs::write(&b, "bee").unwrap();
+
+        // The move onto an existing file fails, so the delete and modify
+        // before it are reversed and nothing is recorded
+        let mut executor = OperationExecutor::new(&content_store, &mut metadata_store);
+        let result = executor.execute_batch(vec![
+            FileOperation::Modify {
+                path: a.clone(),
+                new_content: b"two".to_vec(),
+            },
+            FileOperation::Delete { path: b.clone() },
+            FileOperation::Move {
+                source: a.clone(),
+                destination: tmp.path().join("content"),
+            },
+        ]);
+        assert!(matches!(
+            result.as_ref().map_err(JanusError::inner),
+            Err(JanusError::PathExists(_))
+        ));
+        assert_eq!(fs::read_to_string(&a).unwrap(), "one");
+        assert_eq!(fs::read_to_string(&b).unwrap(), "bee");
+        assert_eq!(metadata_store.count(), 0);
+
+        let mut executor = OperationExecutor::new(&content_store, &mut metadata_store);
+        let recorded = executor
+            .execute_batch(vec![
+                FileOperation::Modify {
+                    path: a.clone(),
+                    new_content: b"two".to_vec(),
+                },
+                FileOperation::Delete { path: b.clone() },
+            ])
+            .unwrap();
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(metadata_store.count(), 2);
+        assert_eq!(metadata_store.segment_count(), 1);
+
+        let mut executor = OperationExecutor::new(&content_store, &mut metadata_store);
+        executor.undo(&recorded[1].id).unwrap();
+        assert_eq!(fs::read_to_string(&b).unwrap(), "bee");
+    }
 }
@@ -0,0 +1,290 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Passphrase Strength: a zxcvbn-style estimate of the guesses to crack one
+// Common passwords, words, repeats, sequences and keyboard runs count cheap
+
+/// Frequently used passwords and words, most common first. A match costs
+/// its rank in guesses.
+const COMMON: &[&str] = &[
+    "password",
+    "123456",
+    "12345678",
+    "qwerty",
+    "abc123",
+    "letmein",
+    "monkey",
+    "dragon",
+    "iloveyou",
+    "admin",
+    "welcome",
+    "login",
+    "master",
+    "sunshine",
+    "princess",
+    "football",
+    "baseball",
+    "shadow",
+    "superman",
+    "trustno",
+    "secret",
+    "freedom",
+    "whatever",
+    "starwars",
+    "passphrase",
+    "changeme",
+    "hello",
+    "charlie",
+    "michael",
+    "jennifer",
+    "jordan",
+    "hunter",
+    "ranger",
+    "buster",
+    "soccer",
+    "hockey",
+    "killer",
+    "pepper",
+    "ginger",
+    "summer",
+    "winter",
+    "spring",
+    "autumn",
+    "love",
+    "god",
+    "money",
+    "computer",
+    "internet",
+    "default",
+    "root",
+    "test",
+    "guest",
+    "januskey",
+    "keystore",
+];
+
+/// Keyboard rows; runs of four or more along one are cheap to guess
+const KEYBOARD_ROWS: &[&str] = &["qwertyuiop", "asdfghjkl", "zxcvbnm"];
+
+/// How hard a passphrase is to guess
+#[derive(Debug, Clone, PartialEq)]
+pub struct Strength {
+    /// 0 (guessable in a few thousand tries) to 4 (very hard), as in zxcvbn
+    pub score: u8,
+    /// Estimated guesses needed, as a power of ten
+    pub guesses_log10: f64,
+    /// Why the passphrase is weak, if it is
+    pub warning: Option<&'static str>,
+}
+
+impl Strength {
+    /// Human-readable name of the score
+    pub fn label(&self) -> &'static str {
+        match self.score {
+            0 => "very weak",
+            1 => "weak",
+            2 => "fair",
+            3 => "strong",
+            _ => "very strong",
+        }
+    }
+}
+
+/// A way of guessing part of a passphrase
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pattern {
+    BruteForce,
+    Common,
+    Repeat,
+    Sequence,
+    Keyboard,
+    Year,
+}
+
+impl Pattern {
+    fn warning(self) -> &'static str {
+        match self {
+            Pattern::BruteForce => "Short passphrases are easy to guess",
+            Pattern::Common => "Common passwords and words are easy to guess",
+            Pattern::Repeat => "Repeats like \"aaa\" are easy to guess",
+            Pattern::Sequence => "Sequences like \"abc\" or \"6543\" are easy to guess",
+            Pattern::Keyboard => "Keyboard runs like \"qwerty\" are easy to guess",
+            Pattern::Year => "Years are easy to guess",
+        }
+    }
+}
+
+/// Estimate how many guesses an attacker who knows common patterns needs.
+///
+/// The passphrase is split into the cheapest sequence of patterns, each
+/// costing the guesses to enumerate it; characters matching no pattern
+/// cost the size of their character class.
+pub fn estimate(passphrase: &str) -> Strength {
+    let chars: Vec<char> = passphrase.chars().collect();
+    let lower: Vec<char> = chars.iter().map(|c| c.to_ascii_lowercase()).collect();
+    let unleeted: Vec<char> = chars.iter().map(|&c| unleet(c)).collect();
+    let n = chars.len();
+
+    // best[j]: cheapest cost of chars[..j], and the pattern ending there
+    let mut best = vec![(f64::INFINITY, 0, Pattern::BruteForce); n + 1];
+    best[0].0 = 0.0;
+    for i in 0..n {
+        let base = best[i].0;
+        let mut consider = |j: usize, cost: f64, pattern: Pattern| {
+            if base + cost < best[j].0 {
+                best[j] = (base + cost, i, pattern);
+            }
+        };
+
+        consider(i + 1, class_size(chars[i]).log10(), Pattern::BruteForce);
+        for j in i + 3..=n {
+            let segment = &chars[i..j];
+            let len = segment.len() as f64;
+            let word: String = lower[i..j].iter().collect();
+            let leet: String = unleeted[i..j].iter().collect();
+            if let Some((rank, substituted)) = COMMON
+                .iter()
+                .position(|w| *w == word)
+                .map(|rank| (rank, false))
+                .or_else(|| COMMON.iter().position(|w| *w == leet).map(|r| (r, true)))
+            {
+                // Capitals and l33t substitutions add a little
+                let variations = 1.0
+                    + segment.iter().any(|c| c.is_uppercase()) as u8 as f64
+                    + substituted as u8 as f64;
+                consider(j, ((rank + 1) as f64 * variations).log10(), Pattern::Common);
+            }
+            if segment.iter().all(|&c| c == segment[0]) {
+                consider(j, (class_size(segment[0]) * len).log10(), Pattern::Repeat);
+            }
+            if let Some(descending) = sequence(segment) {
+                let directions = if descending { 2.0 } else { 1.0 };
+                consider(
+                    j,
+                    (class_size(segment[0]) * len * directions).log10(),
+                    Pattern::Sequence,
+                );
+            }
+            if segment.len() >= 4 && KEYBOARD_ROWS.iter().any(|row| row.contains(&word)) {
+                consider(j, (40.0 * len).log10(), Pattern::Keyboard);
+            }
+            if segment.len() == 4 {
+                let year: String = segment.iter().collect();
+                if year
+                    .parse::<u32>()
+                    .is_ok_and(|y| (1900..=2039).contains(&y))
+                {
+                    consider(j, 140f64.log10(), Pattern::Year);
+                }
+            }
+        }
+    }
+
+    let guesses_log10 = best[n].0;
+    let score = match guesses_log10 {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    };
+
+    // Blame the pattern covering the most characters
+    let mut covered: Vec<(Pattern, usize)> = Vec::new();
+    let mut j = n;
+    while j > 0 {
+        let (_, i, pattern) = best[j];
+        match covered.iter_mut().find(|(p, _)| *p == pattern) {
+            Some((_, count)) => *count += j - i,
+            None => covered.push((pattern, j - i)),
+        }
+        j = i;
+    }
+    let warning = covered
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .filter(|_| score < 3)
+        .map(|(pattern, _)| pattern.warning());
+
+    Strength {
+        score,
+        guesses_log10,
+        warning,
+    }
+}
+
+/// Guesses per character of brute force for `c`'s character class
+fn class_size(c: char) -> f64 {
+    if c.is_ascii_lowercase() || c.is_ascii_uppercase() {
+        26.0
+    } else if c.is_ascii_digit() {
+        10.0
+    } else if c.is_ascii() {
+        33.0
+    } else {
+        100.0
+    }
+}
+
+/// Lowercase `c`, undoing common l33t substitutions
+fn unleet(c: char) -> char {
+    match c {
+        '0' => 'o',
+        '1' | '!' => 'i',
+        '3' => 'e',
+        '4' | '@' => 'a',
+        '5' | '$' => 's',
+        '7' => 't',
+        _ => c.to_ascii_lowercase(),
+    }
+}
+
+/// Whether `chars` step by one code point throughout, and if so whether
+/// downwards
+fn sequence(chars: &[char]) -> Option<bool> {
+    let step = chars[1] as i64 - chars[0] as i64;
+    if step.abs() != 1 || !chars.iter().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    chars
+        .windows(2)
+        .all(|w| w[1] as i64 - w[0] as i64 == step)
+        .then_some(step < 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_common_patterns_score_low() {
+        for weak in [
+            "123456",
+            "password",
+            "P@ssw0rd",
+            "qwertyuiop",
+            "aaaaaaaaaaaa",
+            "abcdefgh",
+            "98765432",
+        ] {
+            let strength = estimate(weak);
+            assert!(strength.score <= 1, "{} scored {:?}", weak, strength);
+            assert!(strength.warning.is_some());
+        }
+        assert_eq!(
+            estimate("password2024").warning,
+            Some(Pattern::Common.warning())
+        );
+        assert_eq!(estimate("").score, 0);
+    }
+
+    #[test]
+    fn test_long_random_passphrases_score_high() {
+        for strong in ["correct horse battery staple", "v8#Lq2!mZr9&tW"] {
+            let strength = estimate(strong);
+            assert_eq!(strength.score, 4, "{} scored {:?}", strong, strength);
+            assert_eq!(strength.warning, None);
+        }
+    }
+}
@@ -0,0 +1,269 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Hardware Token KEK: the key store's key-encryption key wrapped by a
+// PKCS#11 token (a YubiKey, smart card or HSM)
+// The KEK is random and is encrypted to an RSA key pair whose private half
+// never leaves the token. Unlocking logs in with the token's PIN and has
+// the token decrypt it; the token must stay present to retrieve keys.
+// Needs the `pkcs11` feature; other builds read the configuration but
+// cannot open a token.
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::keys::{KeyError, Result, SecretKey};
+
+/// Label of the key pair generated on the token when none is named
+pub const DEFAULT_KEY_LABEL: &str = "januskey-kek";
+
+/// Environment variable holding a token's PIN, read in place of
+/// `JANUSKEY_PASSPHRASE` for a token-backed store
+pub const PIN_ENV: &str = "JANUSKEY_PIN";
+
+/// Whether this build can use PKCS#11 tokens
+pub const SUPPORTED: bool = cfg!(feature = "pkcs11");
+
+/// A token-held KEK (`keys/token.json`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenConfig {
+    /// PKCS#11 module (shared library) for the token
+    pub module: PathBuf,
+    /// Label of the token; `None` uses the first token present
+    pub token_label: Option<String>,
+    /// Label of the RSA key pair the KEK is encrypted to
+    pub key_label: String,
+    /// The KEK encrypted with RSA-OAEP, hex
+    pub wrapped_kek: String,
+}
+
+/// A logged-in session on the token holding a store's KEK
+pub struct TokenSession {
+    #[cfg(feature = "pkcs11")]
+    inner: imp::Session,
+}
+
+impl TokenSession {
+    /// Load `module`, find the token and log in with `pin`. An incorrect
+    /// PIN fails with [`KeyError::InvalidPassphrase`].
+    pub fn open(module: &std::path::Path, token_label: Option<&str>, pin: &str) -> Result<Self> {
+        #[cfg(feature = "pkcs11")]
+        {
+            Ok(Self {
+                inner: imp::Session::open(module, token_label, pin)?,
+            })
+        }
+        #[cfg(not(feature = "pkcs11"))]
+        {
+            let _ = (module, token_label, pin);
+            Err(unsupported())
+        }
+    }
+
+    /// Encrypt `kek` to the key pair labelled `key_label`, generating a
+    /// 2048-bit pair on the token if there is none
+    pub fn wrap(&self, key_label: &str, kek: &SecretKey) -> Result<Vec<u8>> {
+        #[cfg(feature = "pkcs11")]
+        {
+            self.inner.wrap(key_label, kek.as_bytes())
+        }
+        #[cfg(not(feature = "pkcs11"))]
+        {
+            let _ = (key_label, kek);
+            Err(unsupported())
+        }
+    }
+
+    /// Have the token decrypt a KEK [`wrap`](Self::wrap) encrypted
+    pub fn unwrap(&self, key_label: &str, wrapped: &[u8]) -> Result<SecretKey> {
+        #[cfg(feature = "pkcs11")]
+        {
+            let plaintext = self.inner.unwrap(key_label, wrapped)?;
+            let bytes = plaintext
+                .as_slice()
+                .try_into()
+                .map_err(|_| KeyError::Token("unwrapped KEK has the wrong length".to_string()))?;
+            Ok(SecretKey::new(bytes))
+        }
+        #[cfg(not(feature = "pkcs11"))]
+        {
+            let _ = (key_label, wrapped);
+            Err(unsupported())
+        }
+    }
+
+    /// Fail with [`KeyError::TokenAbsent`] if the token has been removed
+    pub fn ensure_present(&self) -> Result<()> {
+        #[cfg(feature = "pkcs11")]
+        {
+            self.inner.ensure_present()
+        }
+        #[cfg(not(feature = "pkcs11"))]
+        {
+            Err(unsupported())
+        }
+    }
+}
+
+#[cfg(not(feature = "pkcs11"))]
+fn unsupported() -> KeyError {
+    KeyError::Token("hardware tokens require januskey built with the `pkcs11` feature".to_string())
+}
+
+#[cfg(feature = "pkcs11")]
+mod imp {
+    use cryptoki::context::{CInitializeArgs, Pkcs11};
+    use cryptoki::error::{Error, RvError};
+    use cryptoki::mechanism::rsa::{PkcsMgfType, PkcsOaepParams, PkcsOaepSource};
+    use cryptoki::mechanism::{Mechanism, MechanismType};
+    use cryptoki::object::{Attribute, KeyType, ObjectClass, ObjectHandle};
+    use cryptoki::session::UserType;
+    use cryptoki::slot::Slot;
+    use cryptoki::types::AuthPin;
+
+    use crate::keys::{KeyError, Result};
+
+    pub(super) struct Session {
+        // Kept so the library stays loaded for the session
+        context: Pkcs11,
+        slot: Slot,
+        session: cryptoki::session::Session,
+    }
+
+    fn token_error(e: Error) -> KeyError {
+        match e {
+            Error::Pkcs11(RvError::PinIncorrect, _) => KeyError::InvalidPassphrase,
+            Error::Pkcs11(RvError::TokenNotPresent | RvError::DeviceRemoved, _) => {
+                KeyError::TokenAbsent
+            }
+            e => KeyError::Token(e.to_string()),
+        }
+    }
+
+    fn oaep() -> Mechanism<'static> {
+        Mechanism::RsaPkcsOaep(PkcsOaepParams::new(
+            MechanismType::SHA256,
+            PkcsMgfType::MGF1_SHA256,
+            PkcsOaepSource::empty(),
+        ))
+    }
+
+    impl Session {
+        pub(super) fn open(
+            module: &std::path::Path,
+            token_label: Option<&str>,
+            pin: &str,
+        ) -> Result<Self> {
+            let context = Pkcs11::new(module).map_err(token_error)?;
+            context
+                .initialize(CInitializeArgs::OsThreads)
+                .map_err(token_error)?;
+            let mut slot = None;
+            for candidate in context.get_slots_with_token().map_err(token_error)? {
+                let info = context.get_token_info(candidate).map_err(token_error)?;
+                if token_label.is_none_or(|label| info.label().trim_end() == label) {
+                    slot = Some(candidate);
+                    break;
+                }
+            }
+            let slot = slot.ok_or(KeyError::TokenAbsent)?;
+            let session = context.open_rw_session(slot).map_err(token_error)?;
+            session
+                .login(UserType::User, Some(&AuthPin::new(pin.to_string())))
+                .map_err(token_error)?;
+            Ok(Self {
+                context,
+                slot,
+                session,
+            })
+        }
+
+        fn find(&self, class: ObjectClass, label: &str) -> Result<Option<ObjectHandle>> {
+            let template = [
+                Attribute::Class(class),
+                Attribute::KeyType(KeyType::RSA),
+                Attribute::Label(label.as_bytes().to_vec()),
+            ];
+            Ok(self
+                .session
+                .find_objects(&template)
+                .map_err(token_error)?
+                .into_iter()
+                .next())
+        }
+
+        pub(super) fn wrap(&self, label: &str, kek: &[u8]) -> Result<Vec<u8>> {
+            let public = match self.find(ObjectClass::PUBLIC_KEY, label)? {
+                Some(public) => public,
+                None => {
+                    let label = label.as_bytes().to_vec();
+                    let public_template = [
+                        Attribute::Token(true),
+                        Attribute::Encrypt(true),
+                        Attribute::ModulusBits(2048.into()),
+                        Attribute::PublicExponent(vec![0x01, 0x00, 0x01]),
+                        Attribute::Label(label.clone()),
+                    ];
+                    let private_template = [
+                        Attribute::Token(true),
+                        Attribute::Private(true),
+                        Attribute::Sensitive(true),
+                        Attribute::Extractable(false),
+                        Attribute::Decrypt(true),
+                        Attribute::Label(label),
+                    ];
+                    self.session
+                        .generate_key_pair(
+                            &Mechanism::RsaPkcsKeyPairGen,
+                            &public_template,
+                            &private_template,
+                        )
+                        .map_err(token_error)?
+                        .0
+                }
+            };
+            self.session
+                .encrypt(&oaep(), public, kek)
+                .map_err(token_error)
+        }
+
+        pub(super) fn unwrap(&self, label: &str, wrapped: &[u8]) -> Result<Vec<u8>> {
+            let private = self
+                .find(ObjectClass::PRIVATE_KEY, label)?
+                .ok_or_else(|| KeyError::Token(format!("no private key labelled {}", label)))?;
+            self.session
+                .decrypt(&oaep(), private, wrapped)
+                .map_err(token_error)
+        }
+
+        pub(super) fn ensure_present(&self) -> Result<()> {
+            let present = self
+                .context
+                .get_slot_info(self.slot)
+                .map_err(token_error)?
+                .token_present();
+            if !present {
+                return Err(KeyError::TokenAbsent);
+            }
+            // A token removed and reinserted has dropped our login
+            self.session.get_session_info().map_err(token_error)?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opening_a_token_needs_the_feature_or_a_module() {
+        let missing = std::path::Path::new("/nonexistent/libpkcs11.so");
+        let error = TokenSession::open(missing, None, "123456").err().unwrap();
+        assert!(matches!(error, KeyError::Token(_)));
+        if !SUPPORTED {
+            assert!(error.to_string().contains("`pkcs11` feature"));
+        }
+    }
+}
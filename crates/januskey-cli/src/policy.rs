@@ -0,0 +1,282 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Path Policies: history, encryption and confirmation by path
+// Evaluated by the executor, so the library and CLI treat paths alike
+
+use crate::content_store::ContentStore;
+use crate::envelope::{parse_public_key, Envelope};
+use crate::error::{JanusError, Result};
+use crate::keys::SecretKey;
+use crate::metadata::OperationMetadata;
+use crate::select::MATCH_OPTIONS;
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Settings for the paths matching `pattern`. Unset fields leave what
+/// earlier policies (or the defaults) decided.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathPolicy {
+    /// Glob over paths relative to the JanusKey root (`secrets/**`)
+    pub pattern: String,
+    /// Capture and record deletes and modifies; `false` runs them as with
+    /// `--no-history`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history: Option<bool>,
+    /// Store captured content encrypted to `content_recipients`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypt_content: Option<bool>,
+    /// Keep only the newest this many captured versions of each file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_versions: Option<usize>,
+    /// Skip confirmation prompts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_confirm: Option<bool>,
+}
+
+/// What the policies decide for one path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EffectivePolicy {
+    pub history: bool,
+    pub encrypt_content: bool,
+    pub max_versions: Option<usize>,
+    pub auto_confirm: bool,
+}
+
+/// The configured policies, ready to evaluate against paths under `root`
+#[derive(Debug, Clone, Default)]
+pub struct PathPolicies {
+    root: PathBuf,
+    policies: Vec<(Pattern, PathPolicy)>,
+    recipients: Vec<[u8; 32]>,
+    auto_confirm: bool,
+}
+
+impl PathPolicies {
+    /// Compile `policies` for paths under `root`. `recipients` are the
+    /// hex X25519 public keys content is encrypted to; `auto_confirm` is
+    /// the default where no policy sets it.
+    pub fn new(
+        root: &Path,
+        policies: &[PathPolicy],
+        recipients: &[String],
+        auto_confirm: bool,
+    ) -> Result<Self> {
+        let policies = policies
+            .iter()
+            .map(|policy| {
+                Pattern::new(&policy.pattern)
+                    .map(|pattern| (pattern, policy.clone()))
+                    .map_err(|e| {
+                        JanusError::InvalidPattern(format!("policy {}: {}", policy.pattern, e))
+                    })
+            })
+            .collect::<Result<_>>()?;
+        let recipients = recipients
+            .iter()
+            .map(|key| {
+                parse_public_key(key)
+                    .map_err(|e| JanusError::OperationFailed(format!("content recipient: {}", e)))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            policies,
+            recipients,
+            auto_confirm,
+        })
+    }
+
+    /// The policy for `path`, relative to the root or absolute. Policies
+    /// are applied in order, so a later match overrides an earlier one.
+    pub fn resolve(&self, path: &Path) -> EffectivePolicy {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        let mut effective = EffectivePolicy {
+            history: true,
+            encrypt_content: false,
+            max_versions: None,
+            auto_confirm: self.auto_confirm,
+        };
+        for (pattern, policy) in &self.policies {
+            if !pattern.matches_path_with(relative, MATCH_OPTIONS) {
+                continue;
+            }
+            effective.history = policy.history.unwrap_or(effective.history);
+            effective.encrypt_content = policy.encrypt_content.unwrap_or(effective.encrypt_content);
+            effective.max_versions = policy.max_versions.or(effective.max_versions);
+            effective.auto_confirm = policy.auto_confirm.unwrap_or(effective.auto_confirm);
+        }
+        effective
+    }
+
+    /// Whether every one of `paths` may skip confirmation
+    pub fn auto_confirms<P: AsRef<Path>>(&self, paths: &[P]) -> bool {
+        paths
+            .iter()
+            .all(|path| self.resolve(path.as_ref()).auto_confirm)
+    }
+
+    /// Whether any policy limits the versions kept
+    pub fn limits_versions(&self) -> bool {
+        self.policies
+            .iter()
+            .any(|(_, policy)| policy.max_versions.is_some())
+    }
+
+    /// Encrypt captured content to the configured recipients
+    pub fn seal(&self, content: &[u8]) -> Result<Vec<u8>> {
+        if self.recipients.is_empty() {
+            return Err(JanusError::OperationFailed(
+                "a policy encrypts this path but no content_recipients are configured".to_string(),
+            ));
+        }
+        Envelope::seal(content, &self.recipients)
+            .and_then(|envelope| envelope.to_bytes())
+            .map_err(|e| JanusError::OperationFailed(format!("encrypting content: {}", e)))
+    }
+}
+
+/// Content `op` captured before it ran, decrypted with one of `keys` if
+/// it was stored encrypted
+pub fn captured_content(
+    content_store: &ContentStore,
+    op: &OperationMetadata,
+    keys: &[SecretKey],
+) -> Result<Vec<u8>> {
+    let content_hash = op
+        .content_hash
+        .as_ref()
+        .ok_or_else(|| JanusError::MetadataCorrupted("Missing content hash".to_string()))?;
+    let stored = content_store.retrieve(content_hash)?;
+    if op.plaintext_hash.is_none() {
+        return Ok(stored);
+    }
+
+    let envelope = Envelope::from_bytes(&stored).ok_or_else(|| {
+        JanusError::MetadataCorrupted(format!("content of {} is not an envelope", op.id))
+    })?;
+    keys.iter()
+        .find_map(|key| envelope.open(key).ok())
+        .ok_or_else(|| {
+            JanusError::OperationFailed(format!(
+                "content of operation {} is encrypted: unlock a recipient key \
+                 (set JANUSKEY_PASSPHRASE) to restore it",
+                op.id
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::x25519_public_key;
+    use crate::{Config, JanusKey};
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn policy(pattern: &str) -> PathPolicy {
+        PathPolicy {
+            pattern: pattern.to_string(),
+            history: None,
+            encrypt_content: None,
+            max_versions: None,
+            auto_confirm: None,
+        }
+    }
+
+    #[test]
+    fn test_later_policies_override_earlier() {
+        let root = Path::new("/work");
+        let policies = PathPolicies::new(
+            root,
+            &[
+                PathPolicy {
+                    max_versions: Some(10),
+                    auto_confirm: Some(true),
+                    ..policy("**")
+                },
+                PathPolicy {
+                    encrypt_content: Some(true),
+                    max_versions: Some(3),
+                    ..policy("secrets/**")
+                },
+                PathPolicy {
+                    history: Some(false),
+                    ..policy("build/**")
+                },
+            ],
+            &[],
+            false,
+        )
+        .unwrap();
+
+        let secret = policies.resolve(&root.join("secrets/api.key"));
+        assert!(secret.history && secret.encrypt_content && secret.auto_confirm);
+        assert_eq!(secret.max_versions, Some(3));
+
+        let build = policies.resolve(Path::new("build/out.o"));
+        assert!(!build.history && !build.encrypt_content);
+        assert_eq!(build.max_versions, Some(10));
+
+        assert!(policies.auto_confirms(&["a.txt", "build/x"]));
+        assert!(PathPolicies::default().resolve(Path::new("a.txt")).history);
+        assert!(PathPolicies::new(root, &[policy("[")], &[], false).is_err());
+    }
+
+    #[test]
+    fn test_policies_apply_to_operations() {
+        let temp = TempDir::new().unwrap();
+        let secret = SecretKey::new([7u8; 32]);
+        let config = Config {
+            policies: vec![
+                PathPolicy {
+                    encrypt_content: Some(true),
+                    max_versions: Some(2),
+                    ..policy("secrets/**")
+                },
+                PathPolicy {
+                    history: Some(false),
+                    ..policy("build/**")
+                },
+            ],
+            content_recipients: vec![hex::encode(x25519_public_key(secret.as_bytes()))],
+            ..Config::default()
+        };
+        let mut jk = JanusKey::init_with_config(temp.path(), config).unwrap();
+        fs::create_dir_all(temp.path().join("secrets")).unwrap();
+        fs::create_dir_all(temp.path().join("build")).unwrap();
+        fs::write(temp.path().join("secrets/token"), "v1").unwrap();
+        fs::write(temp.path().join("build/out.o"), "object").unwrap();
+
+        // Not captured, not recorded
+        jk.delete("build/out.o").unwrap();
+        assert_eq!(jk.metadata_store.count(), 0);
+
+        jk.modify("secrets/token", "v2").unwrap();
+        jk.modify("secrets/token", "v3").unwrap();
+        let last = jk.modify("secrets/token", "v4").unwrap();
+        assert_eq!(jk.metadata_store.count(), 2);
+        let stored = jk
+            .content_store
+            .retrieve(last.content_hash.as_ref().unwrap())
+            .unwrap();
+        assert!(!String::from_utf8_lossy(&stored).contains("v3"));
+
+        // Locked: the undo fails and changes nothing
+        assert!(jk.undo(&last.id).is_err());
+        assert_eq!(
+            fs::read_to_string(temp.path().join("secrets/token")).unwrap(),
+            "v4"
+        );
+
+        jk.content_keys.push(secret);
+        let undo = jk.undo(&last.id).unwrap();
+        assert_eq!(undo.verified_hash, last.plaintext_hash);
+        assert_eq!(
+            fs::read_to_string(temp.path().join("secrets/token")).unwrap(),
+            "v3"
+        );
+    }
+}
@@ -0,0 +1,217 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Remotes: copies of the history on another machine or service
+// A remote holds `metadata/` and `content/` as a `storage_path` does.
+// Push and pull copy the operations one side lacks, with the content they
+// refer to, as long as one side's history extends the other's.
+
+use crate::content_store::{ContentHash, ContentStore};
+use crate::error::{JanusError, Result};
+use crate::metadata::{MetadataStore, OperationMetadata};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// What one side of a push or pull must copy from the other
+#[derive(Debug, Clone, Default)]
+pub struct SyncPlan {
+    /// Operations the destination lacks, oldest first
+    pub operations: Vec<OperationMetadata>,
+    /// Operations the destination has, undone since: (operation, its undo)
+    pub undone: Vec<(String, String)>,
+    /// Operations only the destination has: the source is behind it
+    pub behind: usize,
+}
+
+impl SyncPlan {
+    /// Work out what copying `source` to `destination` involves.
+    ///
+    /// Fails with [`JanusError::Diverged`] if each has operations the other
+    /// lacks. Operations older than all of the source's are taken to have
+    /// been pruned from it by `gc`, not to be missing.
+    pub fn new(source: &[OperationMetadata], destination: &[OperationMetadata]) -> Result<Self> {
+        let in_source: HashMap<&str, usize> = source
+            .iter()
+            .enumerate()
+            .map(|(i, op)| (op.id.as_str(), i))
+            .collect();
+        let in_destination: HashMap<&str, &OperationMetadata> =
+            destination.iter().map(|op| (op.id.as_str(), op)).collect();
+
+        let operations: Vec<OperationMetadata> = source
+            .iter()
+            .filter(|op| !in_destination.contains_key(op.id.as_str()))
+            .cloned()
+            .collect();
+        let start = source.first().map(|op| op.timestamp);
+        let only_destination: Vec<&OperationMetadata> = destination
+            .iter()
+            .filter(|op| !in_source.contains_key(op.id.as_str()))
+            .filter(|op| start.is_some_and(|start| op.timestamp >= start))
+            .collect();
+        if let (Some(first), Some(theirs)) = (operations.first(), only_destination.first()) {
+            return Err(JanusError::Diverged(format!(
+                "{} and {} were each recorded without the other",
+                first.id, theirs.id
+            )));
+        }
+        // What the source adds must come after everything in common
+        let last_common = destination
+            .iter()
+            .filter_map(|op| in_source.get(op.id.as_str()))
+            .max();
+        if let (Some(first), Some(&last_common)) = (operations.first(), last_common) {
+            if in_source[first.id.as_str()] < last_common {
+                return Err(JanusError::Diverged(format!(
+                    "{} is missing from before operations both sides have",
+                    first.id
+                )));
+            }
+        }
+
+        let undone = source
+            .iter()
+            .filter(|op| op.undone)
+            .filter(|op| {
+                in_destination
+                    .get(op.id.as_str())
+                    .is_some_and(|theirs| !theirs.undone)
+            })
+            .filter_map(|op| Some((op.id.clone(), op.undo_operation_id.clone()?)))
+            .collect();
+
+        Ok(Self {
+            operations,
+            undone,
+            behind: only_destination.len(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty() && self.undone.is_empty()
+    }
+
+    /// Copy the content the planned operations refer to from `from` to
+    /// `to`, then record them in `metadata`, so the destination never
+    /// refers to content it lacks. Content `from` does not hold (never
+    /// captured, or obliterated) is skipped.
+    pub fn apply(
+        &self,
+        from: &ContentStore,
+        to: &ContentStore,
+        metadata: &mut MetadataStore,
+    ) -> Result<SyncReport> {
+        let mut report = SyncReport {
+            operations: self.operations.len(),
+            undone: self.undone.len(),
+            behind: self.behind,
+            ..SyncReport::default()
+        };
+        let mut seen = HashSet::new();
+        let mut pending: Vec<ContentHash> = self
+            .operations
+            .iter()
+            .flat_map(|op| {
+                [&op.content_hash, &op.new_content_hash, &op.delta_base]
+                    .into_iter()
+                    .flatten()
+                    .chain(op.stream_hashes())
+            })
+            .cloned()
+            .collect();
+        while let Some(hash) = pending.pop() {
+            if !seen.insert(hash.clone()) || to.exists(&hash) {
+                continue;
+            }
+            if !from.exists(&hash) {
+                tracing::warn!(%hash, "content to copy is missing; skipping it");
+                continue;
+            }
+            let content = from.retrieve(&hash)?;
+            to.import(&hash, &content)?;
+            report.blobs += 1;
+            report.bytes += content.len() as u64;
+        }
+
+        metadata.append_batch(self.operations.clone())?;
+        for (id, undo_id) in &self.undone {
+            metadata.mark_undone(id, undo_id)?;
+        }
+        Ok(report)
+    }
+}
+
+/// What a push or pull copied
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SyncReport {
+    pub operations: usize,
+    pub undone: usize,
+    pub blobs: usize,
+    pub bytes: u64,
+    /// Operations only the destination has, left alone
+    pub behind: usize,
+}
+
+/// A remote store, opened with [`crate::JanusKey::remote`]
+pub struct Remote {
+    pub name: String,
+    /// Path or URI, as configured
+    pub uri: String,
+    pub content_store: ContentStore,
+    pub metadata_store: MetadataStore,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::OperationType;
+    use std::path::PathBuf;
+
+    fn ops(n: usize) -> Vec<OperationMetadata> {
+        (0..n)
+            .map(|i| {
+                let mut op =
+                    OperationMetadata::new(OperationType::Create, PathBuf::from(format!("{}", i)));
+                op.timestamp += chrono::TimeDelta::seconds(i as i64);
+                op
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sync_plan() {
+        let history = ops(4);
+        // Fast-forward: the destination has a prefix
+        let plan = SyncPlan::new(&history, &history[..2]).unwrap();
+        assert_eq!(plan.operations.len(), 2);
+        assert_eq!(plan.behind, 0);
+
+        // The destination is ahead: nothing to copy
+        let plan = SyncPlan::new(&history[..2], &history).unwrap();
+        assert!(plan.is_empty());
+        assert_eq!(plan.behind, 2);
+
+        // The source pruned its oldest operations
+        let plan = SyncPlan::new(&history[1..], &history[..2]).unwrap();
+        assert_eq!(plan.operations.len(), 2);
+
+        // Each side recorded something the other lacks
+        let mut other = history[..2].to_vec();
+        other.extend(ops(1));
+        assert!(matches!(
+            SyncPlan::new(&history, &other),
+            Err(JanusError::Diverged(_))
+        ));
+
+        // Undos of operations both have are copied
+        let mut undone = history.clone();
+        undone[0].undone = true;
+        undone[0].undo_operation_id = Some(history[3].id.clone());
+        let plan = SyncPlan::new(&undone, &history).unwrap();
+        assert_eq!(
+            plan.undone,
+            vec![(history[0].id.clone(), history[3].id.clone())]
+        );
+    }
+}
@@ -0,0 +1,313 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Bulk Renames: `s/regex/replacement/` templates for `jk rename-bulk`
+// A template rewrites file names; the plan orders the renames so each new
+// name is free when its turn comes, and reports the ones that collide
+
+use crate::error::{JanusError, Result};
+use regex::{Regex, RegexBuilder};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// A rewrite of file names, as `s/regex/replacement/[gi]` takes it: the
+/// replacement refers to groups as `$1` or `${name}`, and `\/` is a
+/// literal slash in either part
+#[derive(Debug, Clone)]
+pub struct RenameTemplate {
+    regex: Regex,
+    replacement: String,
+    /// Replace every match rather than the first (`g`)
+    global: bool,
+}
+
+impl FromStr for RenameTemplate {
+    type Err = JanusError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = |message: &str| JanusError::InvalidPattern(format!("{}: {}", s, message));
+        let rest = s
+            .strip_prefix("s/")
+            .ok_or_else(|| invalid("expected s/regex/replacement/[gi]"))?;
+        let parts = split_unescaped(rest);
+        if !(2..=3).contains(&parts.len()) {
+            return Err(invalid("expected s/regex/replacement/[gi]"));
+        }
+        if parts[0].is_empty() {
+            return Err(invalid("empty regex"));
+        }
+        let flags = parts.get(2).map_or("", String::as_str);
+        if let Some(flag) = flags.chars().find(|c| !matches!(c, 'g' | 'i')) {
+            return Err(invalid(&format!("unknown flag {}", flag)));
+        }
+        let regex = RegexBuilder::new(&parts[0])
+            .case_insensitive(flags.contains('i'))
+            .build()
+            .map_err(|e| invalid(&e.to_string()))?;
+        Ok(Self {
+            regex,
+            replacement: parts[1].clone(),
+            global: flags.contains('g'),
+        })
+    }
+}
+
+/// Split on `/` not preceded by a backslash, unescaping `\/`
+fn split_unescaped(s: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'/') => {
+                parts.last_mut().unwrap().push(chars.next().unwrap());
+            }
+            '/' => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+    // A trailing slash without flags ends the template
+    if parts.len() == 3 && parts[2].is_empty() {
+        parts.pop();
+    }
+    parts
+}
+
+impl RenameTemplate {
+    /// The new name for the file name `name`, or `None` when the regex
+    /// does not match it
+    pub fn apply(&self, name: &str) -> Option<String> {
+        if !self.regex.is_match(name) {
+            return None;
+        }
+        let limit = if self.global { 0 } else { 1 };
+        Some(
+            self.regex
+                .replacen(name, limit, self.replacement.as_str())
+                .into_owned(),
+        )
+    }
+}
+
+/// One file to rename, within its directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rename {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+}
+
+/// Why some of the planned renames cannot run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Collision {
+    /// Several files would get the same name
+    Shared {
+        destination: PathBuf,
+        sources: Vec<PathBuf>,
+    },
+    /// The new name is taken by a file that is not being renamed
+    Exists {
+        source: PathBuf,
+        destination: PathBuf,
+    },
+    /// The new name is empty, `.`, `..` or has a path separator in it
+    InvalidName { source: PathBuf, name: String },
+    /// The files take each other's names, so none of them can go first
+    Cycle { sources: Vec<PathBuf> },
+}
+
+/// What a template does to a set of files
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenamePlan {
+    /// Renames in an order that frees each destination before it is used
+    pub renames: Vec<Rename>,
+    /// Files the regex did not match, or whose name it leaves as it is
+    pub unchanged: Vec<PathBuf>,
+    /// When not empty, nothing should be renamed
+    pub collisions: Vec<Collision>,
+}
+
+impl RenamePlan {
+    /// Apply `template` to the name of each of `files`
+    pub fn new(files: &[PathBuf], template: &RenameTemplate) -> Self {
+        let mut plan = Self::default();
+        let mut wanted = Vec::new();
+        for file in files {
+            let name = file.file_name().map(|name| name.to_string_lossy());
+            let new_name = name.as_deref().and_then(|name| {
+                template
+                    .apply(name)
+                    .filter(|new_name| new_name.as_str() != name)
+            });
+            match new_name {
+                None => plan.unchanged.push(file.clone()),
+                Some(name) if !valid_name(&name) => plan.collisions.push(Collision::InvalidName {
+                    source: file.clone(),
+                    name,
+                }),
+                Some(name) => wanted.push(Rename {
+                    source: file.clone(),
+                    destination: file.with_file_name(name),
+                }),
+            }
+        }
+
+        let mut by_destination: BTreeMap<&Path, Vec<&Path>> = BTreeMap::new();
+        for rename in &wanted {
+            by_destination
+                .entry(&rename.destination)
+                .or_default()
+                .push(&rename.source);
+        }
+        let sources: HashSet<&Path> = wanted.iter().map(|r| r.source.as_path()).collect();
+        let mut pending = Vec::new();
+        for rename in &wanted {
+            let sharing = &by_destination[rename.destination.as_path()];
+            if sharing.len() > 1 {
+                if sharing[0] == rename.source {
+                    plan.collisions.push(Collision::Shared {
+                        destination: rename.destination.clone(),
+                        sources: sharing.iter().map(|p| p.to_path_buf()).collect(),
+                    });
+                }
+            } else if present(&rename.destination)
+                && !sources.contains(rename.destination.as_path())
+            {
+                plan.collisions.push(Collision::Exists {
+                    source: rename.source.clone(),
+                    destination: rename.destination.clone(),
+                });
+            } else {
+                pending.push(rename.clone());
+            }
+        }
+
+        // A rename can go once no pending rename still has its new name
+        while !pending.is_empty() {
+            let still_there: HashSet<PathBuf> = pending.iter().map(|r| r.source.clone()).collect();
+            let (ready, blocked): (Vec<_>, Vec<_>) = pending
+                .into_iter()
+                .partition(|r| !still_there.contains(&r.destination));
+            if ready.is_empty() {
+                plan.collisions.push(Collision::Cycle {
+                    sources: blocked.into_iter().map(|r| r.source).collect(),
+                });
+                break;
+            }
+            plan.renames.extend(ready);
+            pending = blocked;
+        }
+        plan
+    }
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.contains('/')
+        && !name.contains(std::path::MAIN_SEPARATOR)
+}
+
+/// Whether anything, a dangling link included, is at `path`
+fn present(path: &Path) -> bool {
+    path.symlink_metadata().is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn template(s: &str) -> RenameTemplate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_apply() {
+        let photos = template(r"s/IMG_(\d+)/photo_$1/");
+        assert_eq!(photos.apply("IMG_0042.jpg").unwrap(), "photo_0042.jpg");
+        assert_eq!(photos.apply("notes.txt"), None);
+
+        assert_eq!(template("s/-/_/g").apply("a-b-c").unwrap(), "a_b_c");
+        assert_eq!(template("s/-/_/").apply("a-b-c").unwrap(), "a_b-c");
+        assert_eq!(template("s/jpeg$/jpg/i").apply("A.JPEG").unwrap(), "A.jpg");
+        assert_eq!(
+            template(r"s/(?P<y>\d{4})-(?P<m>\d\d)/${m}.${y}/")
+                .apply("2026-10.log")
+                .unwrap(),
+            "10.2026.log"
+        );
+        assert_eq!(template(r"s/a\/b/c/").regex.as_str(), "a/b");
+
+        for bad in ["IMG/photo", "s/IMG", "s//x/", "s/(/x/", "s/a/b/q"] {
+            assert!(bad.parse::<RenameTemplate>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_plan_orders_chains_and_reports_collisions() {
+        let tmp = TempDir::new().unwrap();
+        let path = |name: &str| tmp.path().join(name);
+        let files: Vec<PathBuf> = ["a.txt", "aa.txt", "ab.txt", "ba.txt", "1.txt", "2.txt"]
+            .iter()
+            .map(|name| path(name))
+            .collect();
+        for file in &files {
+            fs::write(file, "x").unwrap();
+        }
+        fs::write(path("keep.md"), "x").unwrap();
+
+        // aa.txt has to become aaa.txt before a.txt can become aa.txt
+        let plan = RenamePlan::new(&files[..2], &template("s/^a/aa/"));
+        assert!(plan.collisions.is_empty());
+        assert_eq!(
+            plan.renames,
+            vec![
+                Rename {
+                    source: path("aa.txt"),
+                    destination: path("aaa.txt"),
+                },
+                Rename {
+                    source: path("a.txt"),
+                    destination: path("aa.txt"),
+                },
+            ]
+        );
+
+        let swap = RenamePlan::new(&files[2..4], &template(r"s/^(\w)(\w)\./$2$1./"));
+        assert_eq!(
+            swap.collisions,
+            vec![Collision::Cycle {
+                sources: vec![path("ab.txt"), path("ba.txt")],
+            }]
+        );
+
+        let taken = RenamePlan::new(&files, &template(r"s/^a\.txt$/keep.md/"));
+        assert_eq!(
+            taken.collisions,
+            vec![Collision::Exists {
+                source: path("a.txt"),
+                destination: path("keep.md"),
+            }]
+        );
+        assert_eq!(taken.unchanged.len(), 5);
+
+        let shared = RenamePlan::new(&files[4..], &template(r"s/\d/n/"));
+        assert_eq!(
+            shared.collisions,
+            vec![Collision::Shared {
+                destination: path("n.txt"),
+                sources: vec![path("1.txt"), path("2.txt")],
+            }]
+        );
+        assert!(shared.renames.is_empty());
+
+        let emptied = RenamePlan::new(&files[..1], &template("s/.*//"));
+        assert!(matches!(
+            &emptied.collisions[..],
+            [Collision::InvalidName { name, .. }] if name.is_empty()
+        ));
+    }
+}
@@ -0,0 +1,458 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Retention: scheduled obliteration of deleted content
+// Content deleted under a scheduled path is obliterated once it is due
+
+use crate::actor::Actor;
+use crate::backend::write_atomic;
+use crate::content_store::{ContentHash, ContentStore};
+use crate::error::{JanusError, Result};
+use crate::metadata::{MetadataStore, OperationType};
+use crate::obliteration::{BatchObliterationResult, ObliterationManager};
+use crate::select::MATCH_OPTIONS;
+use chrono::{DateTime, Duration, Months, NaiveDate, NaiveTime, Utc};
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Parse a retention period: a whole number of hours, days or weeks
+/// (`12h`, `30d`, `4w`)
+pub fn parse_retention(spec: &str) -> Result<Duration> {
+    let invalid =
+        || JanusError::InvalidRetention(format!("{:?} (expected e.g. 30d, 4w, 12h)", spec));
+    let unit = spec.chars().last().ok_or_else(invalid)?;
+    let count: i64 = spec[..spec.len() - unit.len_utf8()]
+        .parse()
+        .map_err(|_| invalid())?;
+    if count < 0 {
+        return Err(invalid());
+    }
+    match unit {
+        'h' => Duration::try_hours(count),
+        'd' => Duration::try_days(count),
+        'w' => Duration::try_weeks(count),
+        _ => None,
+    }
+    .ok_or_else(invalid)
+}
+
+/// The time `spec` before `now`: a whole number of hours, days, weeks,
+/// months or years (`12h`, `30d`, `4w`, `6m`, `1y`). Months and years are
+/// calendar ones, so `1m` before 31 March is the end of February.
+pub fn cutoff_before(spec: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let invalid =
+        || JanusError::InvalidRetention(format!("{:?} (expected e.g. 30d, 4w, 6m, 1y)", spec));
+    let months = match spec.chars().last() {
+        Some(unit @ ('m' | 'y')) => {
+            let count: u32 = spec[..spec.len() - 1].parse().map_err(|_| invalid())?;
+            let per_unit = if unit == 'y' { 12 } else { 1 };
+            Some(count.checked_mul(per_unit).ok_or_else(invalid)?)
+        }
+        _ => None,
+    };
+    match months {
+        Some(months) => now.checked_sub_months(Months::new(months)),
+        None => now.checked_sub_signed(parse_retention(spec).map_err(|_| invalid())?),
+    }
+    .ok_or_else(invalid)
+}
+
+/// The time `spec` names: an RFC 3339 timestamp, a date (its start, in
+/// UTC), an age as [`cutoff_before`] takes it, or one spelled out with an
+/// optional `ago` (`1 hour ago`, `90 minutes ago`, `2 weeks`)
+pub fn parse_since(spec: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let spec = spec.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(spec) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(spec, "%Y-%m-%d") {
+        return Ok(date.and_time(NaiveTime::MIN).and_utc());
+    }
+    let invalid = || {
+        JanusError::InvalidRetention(format!(
+            "{:?} (expected e.g. 30d, \"1 hour ago\" or 2026-01-31)",
+            spec
+        ))
+    };
+    let age = spec.strip_suffix("ago").unwrap_or(spec).trim_end();
+    let Some((count, unit)) = age.split_once(char::is_whitespace) else {
+        return cutoff_before(age, now).map_err(|_| invalid());
+    };
+    let unit = match unit.trim_start().trim_end_matches('s') {
+        "minute" | "min" => {
+            let count: i64 = count.parse().map_err(|_| invalid())?;
+            return Duration::try_minutes(count)
+                .filter(|_| count >= 0)
+                .and_then(|age| now.checked_sub_signed(age))
+                .ok_or_else(invalid);
+        }
+        "hour" => 'h',
+        "day" => 'd',
+        "week" => 'w',
+        "month" => 'm',
+        "year" => 'y',
+        _ => return Err(invalid()),
+    };
+    cutoff_before(&format!("{}{}", count, unit), now).map_err(|_| invalid())
+}
+
+/// Format a retention period as [`parse_retention`] accepts it
+pub fn format_retention(period: Duration) -> String {
+    match period.num_hours() {
+        hours if hours % (24 * 7) == 0 && hours > 0 => format!("{}w", hours / (24 * 7)),
+        hours if hours % 24 == 0 => format!("{}d", hours / 24),
+        hours => format!("{}h", hours),
+    }
+}
+
+/// Obliterate content deleted from paths matching `pattern` once it has
+/// been deleted for `after_secs`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionRule {
+    pub id: String,
+    /// Glob over paths relative to the JanusKey root (`pii/**`)
+    pub pattern: String,
+    /// Seconds between a delete and the obliteration of its content
+    pub after_secs: i64,
+    pub created_at: DateTime<Utc>,
+    /// Who scheduled it, as recorded for operations
+    pub created_by: String,
+    /// Passed on to the obliteration records
+    pub reason: Option<String>,
+    pub legal_basis: Option<String>,
+}
+
+impl RetentionRule {
+    /// How long deleted content is kept
+    pub fn after(&self) -> Duration {
+        Duration::seconds(self.after_secs)
+    }
+}
+
+/// Deleted content a rule will obliterate
+#[derive(Debug, Clone)]
+pub struct PendingObliteration {
+    pub content_hash: ContentHash,
+    /// The rule that first makes it due
+    pub rule_id: String,
+    /// Path of the earliest matching delete
+    pub path: PathBuf,
+    pub deleted_at: DateTime<Utc>,
+    pub due: DateTime<Utc>,
+    /// Every operation whose stored content this is; none can be undone
+    /// afterwards
+    pub operation_ids: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ScheduleFile {
+    rules: Vec<RetentionRule>,
+}
+
+/// Retention rules, kept in `.januskey/retention.json`
+pub struct RetentionSchedule {
+    path: PathBuf,
+    rules: Vec<RetentionRule>,
+}
+
+impl RetentionSchedule {
+    /// Open the schedule at `path`, empty if it does not exist
+    pub fn open(path: PathBuf) -> Result<Self> {
+        let rules = if path.exists() {
+            serde_json::from_str::<ScheduleFile>(&fs::read_to_string(&path)?)
+                .map_err(|e| JanusError::MetadataCorrupted(e.to_string()))?
+                .rules
+        } else {
+            Vec::new()
+        };
+        Ok(Self { path, rules })
+    }
+
+    fn save(&self) -> Result<()> {
+        let file = ScheduleFile {
+            rules: self.rules.clone(),
+        };
+        write_atomic(&self.path, &serde_json::to_vec_pretty(&file)?)?;
+        Ok(())
+    }
+
+    /// Scheduled rules, oldest first
+    pub fn rules(&self) -> &[RetentionRule] {
+        &self.rules
+    }
+
+    /// Schedule obliteration of content deleted under `pattern`, `after`
+    /// its deletion. Applies to earlier deletes as well as later ones.
+    pub fn schedule(
+        &mut self,
+        pattern: &str,
+        after: Duration,
+        reason: Option<String>,
+        legal_basis: Option<String>,
+    ) -> Result<RetentionRule> {
+        Pattern::new(pattern)?;
+        let rule = RetentionRule {
+            id: Uuid::new_v4().to_string(),
+            pattern: pattern.to_string(),
+            after_secs: after.num_seconds(),
+            created_at: Utc::now(),
+            created_by: Actor::from_env().to_string(),
+            reason,
+            legal_basis,
+        };
+        self.rules.push(rule.clone());
+        self.save()?;
+        Ok(rule)
+    }
+
+    /// Deleted content under a rule that is still in the store, soonest
+    /// due first. A delete that was undone does not count.
+    pub fn pending(
+        &self,
+        root: &Path,
+        content_store: &ContentStore,
+        metadata_store: &MetadataStore,
+    ) -> Result<Vec<PendingObliteration>> {
+        let patterns = self
+            .rules
+            .iter()
+            .map(|rule| Ok((rule, Pattern::new(&rule.pattern)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut pending: HashMap<&ContentHash, PendingObliteration> = HashMap::new();
+        for op in metadata_store.operations() {
+            let Some(hash) = op.content_hash.as_ref() else {
+                continue;
+            };
+            if op.op_type != OperationType::Delete || op.undone || !content_store.exists(hash) {
+                continue;
+            }
+            let relative = op.path.strip_prefix(root).unwrap_or(&op.path);
+            let Some((rule, due)) = patterns
+                .iter()
+                .filter(|(_, pattern)| pattern.matches_path_with(relative, MATCH_OPTIONS))
+                .map(|(rule, _)| (rule, op.timestamp + rule.after()))
+                .min_by_key(|(_, due)| *due)
+            else {
+                continue;
+            };
+            let entry = pending.entry(hash).or_insert_with(|| PendingObliteration {
+                content_hash: hash.clone(),
+                rule_id: rule.id.clone(),
+                path: op.path.clone(),
+                deleted_at: op.timestamp,
+                due,
+                operation_ids: Vec::new(),
+            });
+            if due < entry.due {
+                entry.rule_id = rule.id.clone();
+                entry.due = due;
+            }
+        }
+
+        for op in metadata_store.operations() {
+            if let Some(entry) = op.content_hash.as_ref().and_then(|h| pending.get_mut(h)) {
+                entry.operation_ids.push(op.id.clone());
+            }
+        }
+
+        let mut pending: Vec<_> = pending.into_values().collect();
+        pending.sort_by_key(|p| p.due);
+        Ok(pending)
+    }
+
+    /// Obliterate the pending content due by `now`, recording a proof for
+    /// each and forwarding it to `obliterations`' audit sinks. Content
+    /// `obliterations` holds is skipped.
+    pub fn run_due(
+        &self,
+        root: &Path,
+        content_store: &ContentStore,
+        metadata_store: &MetadataStore,
+        obliterations: &mut ObliterationManager,
+        now: DateTime<Utc>,
+    ) -> Result<BatchObliterationResult> {
+        let mut result = BatchObliterationResult {
+            successful: Vec::new(),
+            failed: Vec::new(),
+        };
+        for due in self.pending(root, content_store, metadata_store)? {
+            if due.due > now {
+                break;
+            }
+            // Held content stays pending until the hold is released
+            if obliterations.blocking_hold(&due.content_hash).is_some() {
+                continue;
+            }
+            let Some(rule) = self.rules.iter().find(|rule| rule.id == due.rule_id) else {
+                continue;
+            };
+            let reason = rule
+                .reason
+                .clone()
+                .unwrap_or_else(|| format!("retention: deleted under {}", rule.pattern));
+            match obliterations.obliterate_with_cleanup(
+                content_store,
+                &due.content_hash,
+                due.operation_ids,
+                Some(reason),
+                rule.legal_basis.clone(),
+            ) {
+                Ok(record) => result.successful.push(record),
+                Err(e) => result.failed.push((due.content_hash, e)),
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operations::{FileOperation, OperationExecutor};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_retention() {
+        assert_eq!(parse_retention("30d").unwrap(), Duration::days(30));
+        assert_eq!(parse_retention("2w").unwrap(), Duration::days(14));
+        assert_eq!(parse_retention("12h").unwrap(), Duration::hours(12));
+        assert_eq!(format_retention(Duration::days(30)), "30d");
+        assert_eq!(format_retention(Duration::days(14)), "2w");
+        assert_eq!(format_retention(Duration::hours(36)), "36h");
+        for bad in ["", "d", "30", "-1d", "1.5d", "30y"] {
+            assert!(parse_retention(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_cutoff_before() {
+        let now: DateTime<Utc> = "2026-03-31T12:00:00Z".parse().unwrap();
+        let at = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(
+            cutoff_before("30d", now).unwrap(),
+            at("2026-03-01T12:00:00Z")
+        );
+        assert_eq!(
+            cutoff_before("12h", now).unwrap(),
+            at("2026-03-31T00:00:00Z")
+        );
+        assert_eq!(
+            cutoff_before("1m", now).unwrap(),
+            at("2026-02-28T12:00:00Z")
+        );
+        assert_eq!(
+            cutoff_before("6m", now).unwrap(),
+            at("2025-09-30T12:00:00Z")
+        );
+        assert_eq!(
+            cutoff_before("1y", now).unwrap(),
+            at("2025-03-31T12:00:00Z")
+        );
+        for bad in ["", "m", "-1m", "1.5y", "30x", "99999999999y"] {
+            assert!(cutoff_before(bad, now).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_parse_since() {
+        let now: DateTime<Utc> = "2026-03-31T12:00:00Z".parse().unwrap();
+        let at = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+        for (spec, expected) in [
+            ("1 hour ago", "2026-03-31T11:00:00Z"),
+            ("90 minutes ago", "2026-03-31T10:30:00Z"),
+            ("2 days", "2026-03-29T12:00:00Z"),
+            ("1 month ago", "2026-02-28T12:00:00Z"),
+            ("12h", "2026-03-31T00:00:00Z"),
+            ("4w ago", "2026-03-03T12:00:00Z"),
+            ("2026-03-30", "2026-03-30T00:00:00Z"),
+            ("2026-03-30T08:00:00+02:00", "2026-03-30T06:00:00Z"),
+        ] {
+            assert_eq!(parse_since(spec, now).unwrap(), at(expected), "{}", spec);
+        }
+        for bad in [
+            "",
+            "ago",
+            "an hour ago",
+            "-1 minutes ago",
+            "3 fortnights ago",
+        ] {
+            assert!(parse_since(bad, now).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_run_due_obliterates_deleted_content() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        let content_store = ContentStore::new(root.join("store/content"), false).unwrap();
+        let mut metadata_store = MetadataStore::new(root.join("store/metadata")).unwrap();
+        fs::create_dir(root.join("pii")).unwrap();
+        fs::write(root.join("pii/alice.txt"), "alice's address").unwrap();
+        fs::write(root.join("notes.txt"), "shopping list").unwrap();
+
+        let mut executor = OperationExecutor::new(&content_store, &mut metadata_store);
+        let pii = executor
+            .execute(FileOperation::Delete {
+                path: root.join("pii/alice.txt"),
+            })
+            .unwrap();
+        let notes = executor
+            .execute(FileOperation::Delete {
+                path: root.join("notes.txt"),
+            })
+            .unwrap();
+
+        let mut schedule = RetentionSchedule::open(root.join("retention.json")).unwrap();
+        schedule
+            .schedule("pii/**", Duration::days(30), None, None)
+            .unwrap();
+        let schedule = RetentionSchedule::open(root.join("retention.json")).unwrap();
+        let pending = schedule
+            .pending(root, &content_store, &metadata_store)
+            .unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].operation_ids, vec![pii.id.clone()]);
+        assert_eq!(pending[0].due, pii.timestamp + Duration::days(30));
+
+        let mut obliterations = ObliterationManager::new(root.join("obliterations.json")).unwrap();
+        let not_yet = schedule
+            .run_due(
+                root,
+                &content_store,
+                &metadata_store,
+                &mut obliterations,
+                Utc::now(),
+            )
+            .unwrap();
+        assert!(not_yet.successful.is_empty());
+
+        let later = Utc::now() + Duration::days(31);
+        let run = schedule
+            .run_due(
+                root,
+                &content_store,
+                &metadata_store,
+                &mut obliterations,
+                later,
+            )
+            .unwrap();
+        assert_eq!(run.successful.len(), 1);
+        assert!(run.successful[0].proof.verify_commitment());
+        assert!(!content_store.exists(pii.content_hash.as_ref().unwrap()));
+        assert!(content_store.exists(notes.content_hash.as_ref().unwrap()));
+
+        // Nothing is left to do, and the delete can no longer be undone
+        assert!(schedule
+            .pending(root, &content_store, &metadata_store)
+            .unwrap()
+            .is_empty());
+        let mut executor = OperationExecutor::new(&content_store, &mut metadata_store);
+        assert!(executor.undo(&pii.id).is_err());
+    }
+}
@@ -0,0 +1,502 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// History Search: which recorded versions contain a pattern
+// Scans the stored content of every operation; an optional trigram index,
+// maintained as blobs are stored and deleted, lets literal searches skip
+// blobs that cannot match
+
+use crate::backend::write_atomic;
+use crate::content_store::{ContentHash, ContentIndex, ContentStore};
+use crate::delta;
+use crate::error::{JanusError, Result};
+use crate::metadata::{MetadataStore, OperationMetadata};
+use regex::bytes::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory of the index, under `.januskey/`
+pub const INDEX_DIR: &str = "search-index";
+
+/// Single-file index kept by earlier versions, under `.januskey/`
+const LEGACY_INDEX: &str = "search-index.json";
+
+/// Size of each blob's trigram filter. Larger blobs saturate it and are
+/// always scanned, which is slower but never misses a match.
+const FILTER_BYTES: usize = 2048;
+
+/// Bits set per trigram
+const FILTER_PROBES: u32 = 3;
+
+/// Which of an operation's versions of the file a match is in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Version {
+    /// The content before the operation, e.g. what a delete removed
+    Before,
+    /// The content the operation produced
+    After,
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Version::Before => write!(f, "before"),
+            Version::After => write!(f, "after"),
+        }
+    }
+}
+
+/// A line of a matching version
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LineMatch {
+    /// 1-based
+    pub line: usize,
+    pub text: String,
+}
+
+/// A recorded version that contains the pattern
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub operation: OperationMetadata,
+    pub version: Version,
+    pub hash: ContentHash,
+    /// Whether the content is binary, in which case no lines are listed
+    pub binary: bool,
+    /// Lines containing a match. Empty for binary content, and for a
+    /// pattern that only matches across lines.
+    pub lines: Vec<LineMatch>,
+}
+
+/// What to search for
+#[derive(Debug, Clone)]
+pub struct Query {
+    regex: Regex,
+    /// The text of a literal search, lowercased, for the index
+    literal: Option<Vec<u8>>,
+}
+
+impl Query {
+    /// Search for `text` as written
+    pub fn literal(text: &str, ignore_case: bool) -> Result<Self> {
+        if ignore_case && !text.is_ascii() {
+            // Unicode case folding is beyond what the index records
+            return Self::regex(&regex::escape(text), true);
+        }
+        // ASCII-only folding, so that the index never rules out a match
+        let regex = RegexBuilder::new(&regex::escape(text))
+            .case_insensitive(ignore_case)
+            .unicode(false)
+            .build()
+            .map_err(|e| JanusError::InvalidPattern(e.to_string()))?;
+        Ok(Self {
+            regex,
+            literal: Some(text.to_ascii_lowercase().into_bytes()),
+        })
+    }
+
+    /// Search for a regular expression
+    pub fn regex(pattern: &str, ignore_case: bool) -> Result<Self> {
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(ignore_case)
+            .build()
+            .map_err(|e| JanusError::InvalidPattern(e.to_string()))?;
+        Ok(Self {
+            regex,
+            literal: None,
+        })
+    }
+
+    /// Whether `content` contains a match
+    pub fn is_match(&self, content: &[u8]) -> bool {
+        self.regex.is_match(content)
+    }
+}
+
+/// Find the versions recorded by `metadata_store`, newest first, whose
+/// content matches `query`. Each blob is read once however many
+/// operations refer to it; blobs that have been garbage-collected or
+/// obliterated are skipped, as is content captured encrypted. With an
+/// `index`, literal searches skip the blobs it rules out.
+pub fn search(
+    metadata_store: &MetadataStore,
+    content_store: &ContentStore,
+    index: Option<&SearchIndex>,
+    query: &Query,
+) -> Result<Vec<SearchHit>> {
+    let mut scanned: HashMap<ContentHash, Option<(bool, Vec<LineMatch>)>> = HashMap::new();
+    let mut hits = Vec::new();
+    for op in metadata_store.operations().iter().rev() {
+        // Content captured encrypted is not searched
+        let before = op
+            .content_hash
+            .as_ref()
+            .filter(|_| op.plaintext_hash.is_none());
+        let versions = [
+            (Version::Before, before),
+            (Version::After, op.new_content_hash.as_ref()),
+        ];
+        for (version, hash) in versions {
+            let Some(hash) = hash else { continue };
+            if !scanned.contains_key(hash) {
+                let found = scan(content_store, index, query, hash)?;
+                scanned.insert(hash.clone(), found);
+            }
+            if let Some((binary, lines)) = &scanned[hash] {
+                hits.push(SearchHit {
+                    operation: op.clone(),
+                    version,
+                    hash: hash.clone(),
+                    binary: *binary,
+                    lines: lines.clone(),
+                });
+            }
+        }
+    }
+    Ok(hits)
+}
+
+/// Whether the blob `hash` matches, and the matching lines if so
+fn scan(
+    content_store: &ContentStore,
+    index: Option<&SearchIndex>,
+    query: &Query,
+    hash: &ContentHash,
+) -> Result<Option<(bool, Vec<LineMatch>)>> {
+    if let (Some(index), Some(literal)) = (index, &query.literal) {
+        if !index.may_contain(hash, literal) {
+            return Ok(None);
+        }
+    }
+    let content = match content_store.retrieve(hash) {
+        Ok(content) => content,
+        Err(JanusError::FileNotFound(_)) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if !query.is_match(&content) {
+        return Ok(None);
+    }
+    if !delta::is_likely_text(&content) {
+        return Ok(Some((true, Vec::new())));
+    }
+    let lines = content
+        .split(|&b| b == b'\n')
+        .enumerate()
+        .filter(|(_, line)| query.is_match(line))
+        .map(|(i, line)| LineMatch {
+            line: i + 1,
+            text: String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(line)).into_owned(),
+        })
+        .collect();
+    Ok(Some((false, lines)))
+}
+
+/// What the index covers compared with the store
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct IndexStatus {
+    /// Stored blobs with an entry
+    pub indexed: usize,
+    /// Stored blobs without one, which searches always read
+    pub missing: usize,
+    /// Entries for blobs no longer stored
+    pub stale: usize,
+    /// Size of the index on disk
+    pub bytes: u64,
+}
+
+/// A trigram filter per stored blob, one file each under
+/// `.januskey/search-index/`.
+///
+/// Blobs never change once stored, so an entry stays valid until the blob
+/// is deleted. Attached to the content store (see
+/// [`ContentStore::with_index`]), the index gains an entry for every blob
+/// stored and loses those deleted by gc or obliteration. Blobs without an
+/// entry are always scanned, and the filter can report false positives
+/// but not false negatives, so the index only ever saves work.
+pub struct SearchIndex {
+    dir: PathBuf,
+}
+
+impl SearchIndex {
+    /// The index in `dir`, empty if it does not exist yet
+    pub fn open(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Where the index for the JanusKey root `root` is kept
+    pub fn path_for(root: &Path) -> PathBuf {
+        root.join(".januskey").join(INDEX_DIR)
+    }
+
+    /// Whether the index has been built
+    pub fn exists(&self) -> bool {
+        self.dir.is_dir()
+    }
+
+    /// The entry for `hash`, sharded like the content store
+    fn entry(&self, hash: &ContentHash) -> PathBuf {
+        let raw = hash.raw_hash();
+        let (shard, file) = raw.split_at(2.min(raw.len()));
+        self.dir.join(shard).join(file)
+    }
+
+    /// Raw hashes with an entry, and the entry sizes
+    fn entries(&self) -> Result<HashMap<String, u64>> {
+        let mut entries = HashMap::new();
+        if !self.exists() {
+            return Ok(entries);
+        }
+        for shard in fs::read_dir(&self.dir)? {
+            let shard = shard?;
+            if !shard.file_type()?.is_dir() {
+                continue;
+            }
+            let prefix = shard.file_name().to_string_lossy().into_owned();
+            for entry in fs::read_dir(shard.path())? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                // Skip interrupted writes
+                if name.starts_with('.') {
+                    continue;
+                }
+                entries.insert(format!("{}{}", prefix, name), entry.metadata()?.len());
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Index the blobs in `content_store` that have no entry, and drop the
+    /// entries of blobs it no longer has. Returns how many were added.
+    pub fn update(&self, content_store: &ContentStore) -> Result<usize> {
+        let mut entries = self.entries()?;
+        let mut added = 0;
+        for (hash, _) in content_store.list()? {
+            if entries.remove(hash.raw_hash()).is_some() {
+                continue;
+            }
+            let content = match content_store.retrieve(&hash) {
+                Ok(content) => content,
+                Err(JanusError::FileNotFound(_)) => continue,
+                Err(e) => return Err(e),
+            };
+            self.insert(&hash, &content)?;
+            added += 1;
+        }
+        let stale: Vec<ContentHash> = entries
+            .into_keys()
+            .map(|raw| ContentHash::from_string(&raw))
+            .collect();
+        self.remove(&stale)?;
+        Ok(added)
+    }
+
+    /// Discard the index and index every blob in `content_store` afresh.
+    /// Returns how many were indexed.
+    pub fn rebuild(&self, content_store: &ContentStore) -> Result<usize> {
+        if self.exists() {
+            fs::remove_dir_all(&self.dir)?;
+        }
+        // Written by earlier versions, which kept the index in one file
+        if let Some(legacy) = self.dir.parent().map(|dir| dir.join(LEGACY_INDEX)) {
+            if legacy.exists() {
+                fs::remove_file(legacy)?;
+            }
+        }
+        fs::create_dir_all(&self.dir)?;
+        self.update(content_store)
+    }
+
+    /// How the index compares with `content_store`
+    pub fn status(&self, content_store: &ContentStore) -> Result<IndexStatus> {
+        let mut entries = self.entries()?;
+        let mut status = IndexStatus {
+            bytes: entries.values().sum(),
+            ..IndexStatus::default()
+        };
+        for (hash, _) in content_store.list()? {
+            match entries.remove(hash.raw_hash()) {
+                Some(_) => status.indexed += 1,
+                None => status.missing += 1,
+            }
+        }
+        status.stale = entries.len();
+        Ok(status)
+    }
+
+    /// Whether the blob `hash` may contain `needle` (lowercased). Blobs
+    /// without an entry, and needles too short to have a trigram, may.
+    fn may_contain(&self, hash: &ContentHash, needle: &[u8]) -> bool {
+        let Some(bits) = fs::read(self.entry(hash))
+            .ok()
+            .filter(|bits| bits.len() == FILTER_BYTES)
+        else {
+            return true;
+        };
+        needle
+            .windows(3)
+            .all(|trigram| probes(trigram).all(|bit| bits[bit / 8] & (1 << (bit % 8)) != 0))
+    }
+}
+
+impl ContentIndex for SearchIndex {
+    fn insert(&self, hash: &ContentHash, content: &[u8]) -> Result<()> {
+        let entry = self.entry(hash);
+        if let Some(shard) = entry.parent() {
+            fs::create_dir_all(shard)?;
+        }
+        write_atomic(&entry, &filter(content))?;
+        Ok(())
+    }
+
+    fn remove(&self, hashes: &[ContentHash]) -> Result<()> {
+        for hash in hashes {
+            match fs::remove_file(self.entry(hash)) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The trigram filter of `content`, case-folded so that it also serves
+/// case-insensitive searches
+fn filter(content: &[u8]) -> Vec<u8> {
+    let mut bits = vec![0u8; FILTER_BYTES];
+    let folded = content.to_ascii_lowercase();
+    for trigram in folded.windows(3) {
+        for bit in probes(trigram) {
+            bits[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+    bits
+}
+
+/// The filter bits for `trigram`
+fn probes(trigram: &[u8]) -> impl Iterator<Item = usize> {
+    let key = u64::from(trigram[0]) | u64::from(trigram[1]) << 8 | u64::from(trigram[2]) << 16;
+    let mixed = key.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    (0..FILTER_PROBES)
+        .map(move |i| (mixed.rotate_left(i * 21) % (FILTER_BYTES as u64 * 8)) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::JanusKey;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_search_finds_deleted_content() {
+        let temp = TempDir::new().unwrap();
+        let mut jk = JanusKey::init(temp.path()).unwrap();
+        fs::write(
+            temp.path().join("secrets.env"),
+            "USER=me\nAPI_KEY=sk-12345\n",
+        )
+        .unwrap();
+        fs::write(temp.path().join("notes.txt"), "nothing here\n").unwrap();
+        jk.delete("secrets.env").unwrap();
+        jk.delete("notes.txt").unwrap();
+
+        let query = Query::literal("sk-12345", false).unwrap();
+        let hits = search(&jk.metadata_store, &jk.content_store, None, &query).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].version, Version::Before);
+        assert!(hits[0].operation.path.ends_with("secrets.env"));
+        assert_eq!(
+            hits[0].lines,
+            vec![LineMatch {
+                line: 2,
+                text: "API_KEY=sk-12345".to_string()
+            }]
+        );
+
+        let query = Query::regex(r"api_key=sk-\d+", true).unwrap();
+        let hits = search(&jk.metadata_store, &jk.content_store, None, &query).unwrap();
+        assert_eq!(hits.len(), 1);
+    }
+
+    #[test]
+    fn test_index_rules_out_blobs() {
+        let temp = TempDir::new().unwrap();
+        let mut jk = JanusKey::init(temp.path()).unwrap();
+        fs::write(temp.path().join("a.txt"), "alpha\n").unwrap();
+        fs::write(temp.path().join("b.txt"), "bravo\n").unwrap();
+        jk.delete("a.txt").unwrap();
+        jk.delete("b.txt").unwrap();
+
+        let index = SearchIndex::open(temp.path().join("index"));
+        assert_eq!(index.update(&jk.content_store).unwrap(), 2);
+        assert_eq!(index.update(&jk.content_store).unwrap(), 0);
+
+        let alpha = ContentHash::from_bytes(b"alpha\n");
+        let bravo = ContentHash::from_bytes(b"bravo\n");
+        assert!(index.may_contain(&alpha, b"alph"));
+        assert!(!index.may_contain(&bravo, b"alph"));
+        // Unknown blobs are always scanned
+        assert!(index.may_contain(&ContentHash::from_bytes(b"x"), b"alph"));
+
+        let query = Query::literal("ALPHA", true).unwrap();
+        let hits = search(&jk.metadata_store, &jk.content_store, Some(&index), &query).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].operation.path.ends_with("a.txt"));
+    }
+
+    #[test]
+    fn test_index_maintained_by_store() {
+        let temp = TempDir::new().unwrap();
+        let config = crate::Config {
+            search_index: true,
+            ..crate::Config::default()
+        };
+        let mut jk = JanusKey::init_with_config(temp.path(), config).unwrap();
+        fs::write(temp.path().join("a.txt"), "alpha\n").unwrap();
+        jk.delete("a.txt").unwrap();
+
+        let index = jk.search_index();
+        let status = index.status(&jk.content_store).unwrap();
+        assert_eq!((status.indexed, status.missing, status.stale), (1, 0, 0));
+
+        let hash = ContentHash::from_bytes(b"alpha\n");
+        let mut obliteration = jk.obliteration_manager().unwrap();
+        obliteration
+            .obliterate(&jk.content_store, &hash, None, None)
+            .unwrap();
+        let status = index.status(&jk.content_store).unwrap();
+        assert_eq!((status.indexed, status.stale), (0, 0));
+        assert!(!index.entry(&hash).exists());
+    }
+
+    #[test]
+    fn test_rebuild_drops_stale_entries() {
+        let temp = TempDir::new().unwrap();
+        let mut jk = JanusKey::init(temp.path()).unwrap();
+        fs::write(temp.path().join("a.txt"), "alpha\n").unwrap();
+        jk.delete("a.txt").unwrap();
+
+        let index = jk.search_index();
+        assert!(!index.exists());
+        index
+            .insert(&ContentHash::from_bytes(b"gone"), b"gone")
+            .unwrap();
+        assert_eq!(index.status(&jk.content_store).unwrap().stale, 1);
+
+        assert_eq!(index.rebuild(&jk.content_store).unwrap(), 1);
+        let status = index.status(&jk.content_store).unwrap();
+        assert_eq!((status.indexed, status.missing, status.stale), (1, 0, 0));
+        assert!(status.bytes > 0);
+    }
+
+    #[test]
+    fn test_invalid_regex() {
+        assert!(matches!(
+            Query::regex("(unclosed", false),
+            Err(JanusError::InvalidPattern(_))
+        ));
+    }
+}
@@ -0,0 +1,315 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// File Selection: glob expansion with gitignore-style exclusions
+// Shared by the bulk CLI commands and `delete_glob`
+
+use crate::error::{JanusError, Result};
+use glob::{MatchOptions, Pattern};
+use std::path::{Path, PathBuf};
+
+/// `*` stays within a path component; `**` crosses them; dotfiles match
+pub(crate) const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// File at the root listing paths whole-tree commands (`jk snapshot`)
+/// skip: one [`Excludes`] pattern per line, blank lines and `#` comments
+/// ignored
+pub const IGNORE_FILE: &str = ".januskeyignore";
+
+/// Gitignore-style exclusion patterns, matched against paths relative to
+/// the directory being selected from.
+///
+/// - A pattern without `/` matches a name at any depth (`*.log`)
+/// - A leading `/` anchors to the base directory (`/build`)
+/// - A trailing `/` is ignored; excluding a directory excludes its contents
+/// - A leading `!` re-includes what an earlier pattern excluded, except
+///   inside an excluded directory
+///
+/// The last pattern matching a path decides.
+#[derive(Debug, Clone, Default)]
+pub struct Excludes {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    pattern: Pattern,
+    negated: bool,
+}
+
+impl Excludes {
+    /// Compile exclusion patterns
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Result<Self> {
+        let rules = patterns
+            .iter()
+            .map(|p| Rule::parse(p.as_ref()))
+            .collect::<Result<_>>()?;
+        Ok(Self { rules })
+    }
+
+    /// The patterns in `root`'s [`IGNORE_FILE`], if it has one, followed
+    /// by `extra`
+    pub fn from_ignore_file<S: AsRef<str>>(root: &Path, extra: &[S]) -> Result<Self> {
+        let path = root.join(IGNORE_FILE);
+        let content = if path.exists() {
+            std::fs::read_to_string(path)?
+        } else {
+            String::new()
+        };
+        let patterns: Vec<&str> = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .chain(extra.iter().map(AsRef::as_ref))
+            .collect();
+        Self::new(&patterns)
+    }
+
+    /// Whether there are no patterns
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Whether `relative` or a directory above it is excluded
+    pub fn is_excluded(&self, relative: &Path) -> bool {
+        let mut ancestors: Vec<&Path> = relative
+            .ancestors()
+            .filter(|a| !a.as_os_str().is_empty())
+            .collect();
+        ancestors.reverse();
+        ancestors.into_iter().any(|path| {
+            self.rules
+                .iter()
+                .rev()
+                .find(|rule| rule.pattern.matches_path_with(path, MATCH_OPTIONS))
+                .is_some_and(|rule| !rule.negated)
+        })
+    }
+}
+
+impl Rule {
+    fn parse(pattern: &str) -> Result<Self> {
+        let (negated, pattern) = match pattern.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let pattern = pattern.trim_end_matches('/');
+        let pattern = match pattern.strip_prefix('/') {
+            Some(anchored) => anchored.to_string(),
+            None if !pattern.contains('/') => format!("**/{}", pattern),
+            None => pattern.to_string(),
+        };
+        if pattern.is_empty() || pattern == "**/" {
+            return Err(JanusError::InvalidPattern(
+                "empty exclude pattern".to_string(),
+            ));
+        }
+        Ok(Self {
+            pattern: Pattern::new(&pattern)?,
+            negated,
+        })
+    }
+}
+
+/// Files picked by [`select_files`]
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    /// Files to operate on, in match order and without duplicates
+    pub files: Vec<PathBuf>,
+    /// Directories that matched, and with `recursive` those beneath them
+    pub directories: Vec<PathBuf>,
+    /// Files and directories that matched but were excluded
+    pub excluded: Vec<PathBuf>,
+    /// Symbolic links that matched or were met descending, never followed
+    pub links: Vec<PathBuf>,
+}
+
+/// Expand glob `patterns` under `base` into files. With `recursive`, a
+/// matched directory contributes every file beneath it. Anything
+/// `excludes` matches is set aside in [`Selection::excluded`]; excluded
+/// directories are not descended into. Symbolic links are set aside in
+/// [`Selection::links`] and never descended into, so nothing outside the
+/// tree is selected through one.
+pub fn select_files<S: AsRef<str>>(
+    base: &Path,
+    patterns: &[S],
+    recursive: bool,
+    excludes: &Excludes,
+) -> Result<Selection> {
+    let mut selection = Selection::default();
+    let excluded = |path: &Path| {
+        !excludes.is_empty() && excludes.is_excluded(path.strip_prefix(base).unwrap_or(path))
+    };
+
+    for pattern in patterns {
+        let full_pattern = base.join(pattern.as_ref());
+        let literal = literal_prefix(&full_pattern);
+        for entry in glob::glob(&full_pattern.to_string_lossy())? {
+            let path = entry.map_err(|e| e.into_error())?;
+            if through_link(&literal, &path) {
+                continue;
+            }
+            if excluded(&path) {
+                selection.excluded.push(path);
+            } else if is_link(&path) {
+                selection.links.push(path);
+            } else if path.is_file() {
+                selection.files.push(path);
+            } else if path.is_dir() && recursive {
+                let mut walker = walkdir::WalkDir::new(&path).min_depth(1).into_iter();
+                selection.directories.push(path);
+                while let Some(entry) = walker.next() {
+                    let entry = entry.map_err(std::io::Error::from)?;
+                    if excluded(entry.path()) {
+                        if entry.file_type().is_dir() {
+                            walker.skip_current_dir();
+                        }
+                        selection.excluded.push(entry.into_path());
+                    } else if entry.file_type().is_symlink() {
+                        selection.links.push(entry.into_path());
+                    } else if entry.file_type().is_file() {
+                        selection.files.push(entry.into_path());
+                    } else if entry.file_type().is_dir() {
+                        selection.directories.push(entry.into_path());
+                    }
+                }
+            } else if path.is_dir() {
+                selection.directories.push(path);
+            }
+        }
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    selection.files.retain(|path| seen.insert(path.clone()));
+    let mut seen = std::collections::HashSet::new();
+    selection
+        .directories
+        .retain(|path| seen.insert(path.clone()));
+    let mut seen = std::collections::HashSet::new();
+    selection.excluded.retain(|path| seen.insert(path.clone()));
+    let mut seen = std::collections::HashSet::new();
+    selection.links.retain(|path| seen.insert(path.clone()));
+    Ok(selection)
+}
+
+fn is_link(path: &Path) -> bool {
+    std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_symlink())
+}
+
+/// The leading components of `pattern` without wildcards, which name
+/// their path literally
+fn literal_prefix(pattern: &Path) -> PathBuf {
+    pattern
+        .components()
+        .take_while(|component| {
+            !component
+                .as_os_str()
+                .to_string_lossy()
+                .contains(['*', '?', '['])
+        })
+        .collect()
+}
+
+/// Whether a directory a wildcard matched between `literal` and `path` is
+/// a symbolic link, as `**` and `*/` follow them
+fn through_link(literal: &Path, path: &Path) -> bool {
+    let Ok(relative) = path.strip_prefix(literal) else {
+        return false;
+    };
+    let mut current = literal.to_path_buf();
+    let mut components = relative.components().peekable();
+    while let Some(component) = components.next() {
+        if components.peek().is_none() {
+            return false;
+        }
+        current.push(component);
+        if is_link(&current) {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_exclude_patterns() {
+        let excludes = Excludes::new(&["*.rs", "/build/", ".git/**", "!keep.rs"]).unwrap();
+        let excluded = |p: &str| excludes.is_excluded(Path::new(p));
+
+        assert!(excluded("main.rs"));
+        assert!(excluded("src/deep/lib.rs"));
+        assert!(!excluded("keep.rs"));
+        assert!(excluded("build/out.txt"));
+        assert!(!excluded("src/build/out.txt"));
+        assert!(excluded(".git/config"));
+        assert!(!excluded("src/notes.txt"));
+
+        // Re-including does not reach into an excluded directory
+        let excludes = Excludes::new(&["vendor", "!vendor/keep.txt"]).unwrap();
+        assert!(excludes.is_excluded(Path::new("vendor/keep.txt")));
+
+        assert!(Excludes::new(&["/"]).is_err());
+    }
+
+    #[test]
+    fn test_select_files_recursive() {
+        let tmp = TempDir::new().unwrap();
+        let base = tmp.path();
+        std::fs::create_dir_all(base.join("src/gen")).unwrap();
+        for file in ["src/a.rs", "src/b.txt", "src/gen/c.txt", "top.txt"] {
+            std::fs::write(base.join(file), "x").unwrap();
+        }
+
+        let excludes = Excludes::new(&["*.rs", "src/gen"]).unwrap();
+        let selection = select_files(base, &["src", "*.txt"], true, &excludes).unwrap();
+        assert_eq!(
+            selection.files,
+            vec![base.join("src/b.txt"), base.join("top.txt")]
+        );
+        assert_eq!(selection.directories, vec![base.join("src")]);
+        let mut excluded = selection.excluded;
+        excluded.sort();
+        assert_eq!(excluded, vec![base.join("src/a.rs"), base.join("src/gen")]);
+
+        let selection = select_files(base, &["src"], false, &Excludes::default()).unwrap();
+        assert!(selection.files.is_empty());
+        assert_eq!(selection.directories, vec![base.join("src")]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_select_files_never_follows_links() {
+        let tmp = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        let base = tmp.path();
+        std::fs::create_dir_all(base.join("src")).unwrap();
+        std::fs::write(base.join("src/a.txt"), "x").unwrap();
+        std::fs::write(outside.path().join("far.txt"), "x").unwrap();
+        std::os::unix::fs::symlink(outside.path(), base.join("src/out")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), base.join("top")).unwrap();
+
+        let selection = select_files(base, &["src", "top"], true, &Excludes::default()).unwrap();
+        assert_eq!(selection.files, vec![base.join("src/a.txt")]);
+        assert_eq!(selection.directories, vec![base.join("src")]);
+        assert_eq!(
+            selection.links,
+            vec![base.join("src/out"), base.join("top")]
+        );
+
+        let selection = select_files(base, &["**/*.txt"], false, &Excludes::default()).unwrap();
+        assert_eq!(selection.files, vec![base.join("src/a.txt")]);
+
+        // Naming a path through a link is up to the caller
+        let selection = select_files(base, &["top/far.txt"], false, &Excludes::default()).unwrap();
+        assert_eq!(selection.files, vec![base.join("top/far.txt")]);
+    }
+}
@@ -0,0 +1,553 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Dashboard Server: read-only JSON over HTTP for many repositories
+// `jk serve` answers GET requests for the history, stats, transactions,
+// key audit log and obliteration log of each repository it is given, so
+// a dashboard can chart activity without shelling out to `jk`. Lists come
+// newest first in pages, and every reply carries an ETag of its body, so
+// polling an unchanged repository costs a 304. A repository with API
+// tokens configured answers only requests bearing one (see access.rs).
+// Needs the `server` feature; other builds route requests but cannot
+// listen.
+
+use crate::access::{authenticate, Permission};
+use crate::attestation::AuditLog;
+use crate::error::{JanusError, Result};
+use crate::{ByteUsage, Config, JanusKey, StoreStatus};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+
+/// Address `jk serve` listens on unless told otherwise
+pub const DEFAULT_BIND: &str = "127.0.0.1:8470";
+
+/// Items in a page when the request does not ask for a number
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// Most items one page holds
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+/// Paths `/stats` lists when the request does not ask for a number
+const DEFAULT_TOP_PATHS: usize = 10;
+
+/// Whether this build can listen for HTTP requests
+pub const SUPPORTED: bool = cfg!(feature = "server");
+
+/// Requests answered at once
+#[cfg(feature = "server")]
+const WORKERS: usize = 4;
+
+/// A repository served, under `/repos/<name>/`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Repository {
+    /// Name of its directory
+    pub name: String,
+    pub root: PathBuf,
+}
+
+/// An answer to a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+    /// HTTP status code
+    pub status: u16,
+    /// JSON body; empty for a 304
+    pub body: Vec<u8>,
+    /// Quoted entity tag of the body, on successful replies
+    pub etag: Option<String>,
+}
+
+impl Reply {
+    fn failure(failure: Failure) -> Self {
+        let body = serde_json::json!({
+            "error": failure.code,
+            "message": failure.message,
+        });
+        Self {
+            status: failure.status,
+            body: body.to_string().into_bytes(),
+            etag: None,
+        }
+    }
+}
+
+/// One page of a list, newest first
+#[derive(Serialize)]
+struct Page<T> {
+    /// Items in the whole list
+    total: usize,
+    offset: usize,
+    limit: usize,
+    items: Vec<T>,
+}
+
+/// What `/stats` answers
+#[derive(Serialize)]
+struct Stats {
+    status: StoreStatus,
+    usage: ByteUsage,
+}
+
+/// Why a request failed
+struct Failure {
+    status: u16,
+    code: &'static str,
+    message: String,
+}
+
+impl Failure {
+    fn new(status: u16, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn not_found(what: &str) -> Self {
+        Self::new(404, "not_found", format!("no such {}", what))
+    }
+}
+
+impl From<JanusError> for Failure {
+    fn from(error: JanusError) -> Self {
+        let status = match error.inner() {
+            JanusError::InvalidTransactionId(_) => 404,
+            JanusError::AccessDenied(_) => 403,
+            _ => 500,
+        };
+        Self::new(status, error.code(), error.to_string())
+    }
+}
+
+impl From<std::io::Error> for Failure {
+    fn from(error: std::io::Error) -> Self {
+        JanusError::from(error).into()
+    }
+}
+
+/// The query string of a request
+struct Query<'a>(Vec<(&'a str, &'a str)>);
+
+impl<'a> Query<'a> {
+    fn parse(query: &'a str) -> Self {
+        Self(
+            query
+                .split('&')
+                .filter(|pair| !pair.is_empty())
+                .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+                .collect(),
+        )
+    }
+
+    /// The whole number given for `key`, or `default`
+    fn number(&self, key: &str, default: usize) -> std::result::Result<usize, Failure> {
+        match self.0.iter().rev().find(|(name, _)| *name == key) {
+            Some((_, value)) => value.parse().map_err(|_| {
+                Failure::new(
+                    400,
+                    "invalid_request",
+                    format!("{} must be a whole number, not {:?}", key, value),
+                )
+            }),
+            None => Ok(default),
+        }
+    }
+
+    /// The page of `items` (oldest first) asked for with `offset` and
+    /// `limit`, newest first
+    fn page<'i, T>(&self, items: &'i [T]) -> std::result::Result<Page<&'i T>, Failure> {
+        let offset = self.number("offset", 0)?;
+        let limit = self.number("limit", DEFAULT_PAGE_SIZE)?.min(MAX_PAGE_SIZE);
+        Ok(Page {
+            total: items.len(),
+            offset,
+            limit,
+            items: items.iter().rev().skip(offset).take(limit).collect(),
+        })
+    }
+}
+
+/// Refuse `token` unless `config` has no API tokens, or it is one of them
+/// and its role may read
+fn authorize(config: &Config, token: Option<&str>) -> std::result::Result<(), Failure> {
+    if config.daemon_tokens.is_empty() {
+        return Ok(());
+    }
+    let Some(presented) = token else {
+        return Err(Failure::new(
+            401,
+            "access_denied",
+            "this repository requires an API token",
+        ));
+    };
+    match authenticate(&config.daemon_tokens, presented) {
+        None => Err(Failure::new(403, "access_denied", "unknown API token")),
+        Some(token) if !token.role.permits(Permission::Read) => Err(Failure::new(
+            403,
+            "access_denied",
+            format!("{} is a {} and may not read", token.name, token.role),
+        )),
+        Some(_) => Ok(()),
+    }
+}
+
+/// Whether an If-None-Match header lists `etag`
+fn etag_matches(header: &str, etag: &str) -> bool {
+    header
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+fn json<T: Serialize>(value: &T) -> std::result::Result<Vec<u8>, Failure> {
+    serde_json::to_vec(value).map_err(|e| JanusError::from(e).into())
+}
+
+/// The repositories `jk serve` answers for
+pub struct Dashboard {
+    repositories: Vec<Repository>,
+}
+
+impl Dashboard {
+    /// Serve each of `roots`, named after its directory. Each must be
+    /// initialized, and no two may share a name.
+    pub fn new(roots: &[PathBuf]) -> Result<Self> {
+        let mut repositories: Vec<Repository> = Vec::new();
+        for root in roots {
+            let root = root.canonicalize().unwrap_or_else(|_| root.clone());
+            if !JanusKey::is_initialized(&root) {
+                return Err(JanusError::NotInitialized(root.display().to_string()));
+            }
+            let name = root.file_name().map_or_else(
+                || "root".to_string(),
+                |name| name.to_string_lossy().into_owned(),
+            );
+            if repositories.iter().any(|repo| repo.name == name) {
+                return Err(JanusError::OperationFailed(format!(
+                    "two repositories are named {}; serve them from separate processes",
+                    name
+                )));
+            }
+            repositories.push(Repository { name, root });
+        }
+        Ok(Self { repositories })
+    }
+
+    /// The repositories served
+    pub fn repositories(&self) -> &[Repository] {
+        &self.repositories
+    }
+
+    /// Answer a GET of `target`, a path and query string. `token` is the
+    /// bearer token presented and `if_none_match` the If-None-Match
+    /// header; a reply whose ETag it lists is a 304 with no body.
+    ///
+    /// - `/repos`: the repositories `token` may read
+    /// - `/repos/<name>/history`: operations
+    /// - `/repos/<name>/stats`: status and byte usage, with the `top`
+    ///   paths by stored bytes
+    /// - `/repos/<name>/transactions`, `/repos/<name>/transactions/<id>`
+    /// - `/repos/<name>/audit`: the key audit log
+    /// - `/repos/<name>/obliterations`: the obliteration log
+    ///
+    /// Lists are pages, newest first, chosen with `offset` and `limit`.
+    pub fn get(&self, target: &str, token: Option<&str>, if_none_match: Option<&str>) -> Reply {
+        let body = match self.route(target, token) {
+            Ok(body) => body,
+            Err(failure) => return Reply::failure(failure),
+        };
+        let etag = format!("\"{}\"", &hex::encode(Sha256::digest(&body))[..32]);
+        if if_none_match.is_some_and(|header| etag_matches(header, &etag)) {
+            return Reply {
+                status: 304,
+                body: Vec::new(),
+                etag: Some(etag),
+            };
+        }
+        Reply {
+            status: 200,
+            body,
+            etag: Some(etag),
+        }
+    }
+
+    fn route(&self, target: &str, token: Option<&str>) -> std::result::Result<Vec<u8>, Failure> {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let query = Query::parse(query);
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let (repo, rest) = match segments.as_slice() {
+            ["repos"] => {
+                let readable: Vec<&Repository> = self
+                    .repositories
+                    .iter()
+                    .filter(|repo| {
+                        Config::load(&repo.root)
+                            .is_ok_and(|config| authorize(&config, token).is_ok())
+                    })
+                    .collect();
+                return json(&readable);
+            }
+            ["repos", name, rest @ ..] => (
+                self.repositories
+                    .iter()
+                    .find(|repo| repo.name == *name)
+                    .ok_or_else(|| Failure::not_found("repository"))?,
+                rest,
+            ),
+            _ => return Err(Failure::not_found("endpoint")),
+        };
+        authorize(&Config::load(&repo.root)?, token)?;
+
+        match rest {
+            ["audit"] => json(&query.page(&AuditLog::new(&repo.root).read_all()?)?),
+            ["history" | "stats" | "obliterations" | "transactions"] | ["transactions", _] => {
+                let jk = JanusKey::open(&repo.root)?;
+                match rest {
+                    ["history"] => json(&query.page(jk.metadata_store.operations())?),
+                    ["stats"] => {
+                        let mut usage = jk.byte_usage();
+                        usage
+                            .by_path
+                            .truncate(query.number("top", DEFAULT_TOP_PATHS)?);
+                        json(&Stats {
+                            status: jk.status()?,
+                            usage,
+                        })
+                    }
+                    ["obliterations"] => json(&query.page(jk.obliteration_manager()?.records())?),
+                    ["transactions"] => json(&query.page(jk.transaction_manager.all())?),
+                    ["transactions", id] => json(jk.transaction_manager.find(id)?),
+                    _ => unreachable!("matched above"),
+                }
+            }
+            _ => Err(Failure::not_found("endpoint")),
+        }
+    }
+
+    /// Listen on `bind` (`host:port`; port 0 picks a free port)
+    pub fn listen(self, bind: &str) -> Result<Listener> {
+        #[cfg(feature = "server")]
+        {
+            let http = tiny_http::Server::http(bind).map_err(|e| {
+                JanusError::OperationFailed(format!("cannot listen on {}: {}", bind, e))
+            })?;
+            Ok(Listener {
+                dashboard: self,
+                http,
+            })
+        }
+        #[cfg(not(feature = "server"))]
+        {
+            let _ = bind;
+            Err(unsupported())
+        }
+    }
+}
+
+/// A [`Dashboard`] listening for HTTP requests
+pub struct Listener {
+    #[cfg(feature = "server")]
+    dashboard: Dashboard,
+    #[cfg(feature = "server")]
+    http: tiny_http::Server,
+}
+
+impl Listener {
+    /// The address listened on
+    pub fn address(&self) -> String {
+        #[cfg(feature = "server")]
+        {
+            self.http.server_addr().to_string()
+        }
+        #[cfg(not(feature = "server"))]
+        {
+            String::new()
+        }
+    }
+
+    /// Answer requests until the process is stopped. Anything but GET and
+    /// HEAD is refused.
+    pub fn serve(&self) -> Result<()> {
+        #[cfg(feature = "server")]
+        {
+            std::thread::scope(|scope| {
+                for _ in 0..WORKERS {
+                    scope.spawn(|| loop {
+                        match self.http.recv() {
+                            Ok(request) => self.respond(request),
+                            Err(e) => {
+                                tracing::warn!("stopped accepting requests: {}", e);
+                                break;
+                            }
+                        }
+                    });
+                }
+            });
+            Ok(())
+        }
+        #[cfg(not(feature = "server"))]
+        {
+            Err(unsupported())
+        }
+    }
+
+    #[cfg(feature = "server")]
+    fn respond(&self, request: tiny_http::Request) {
+        use tiny_http::{Header, Method, Response};
+
+        let header = |name: &str| {
+            request
+                .headers()
+                .iter()
+                .find(|header| header.field.as_str().as_str().eq_ignore_ascii_case(name))
+                .map(|header| header.value.as_str().to_string())
+        };
+        let reply = match request.method() {
+            Method::Get | Method::Head => {
+                let authorization = header("Authorization");
+                let token = authorization
+                    .as_deref()
+                    .and_then(|value| value.strip_prefix("Bearer "));
+                self.dashboard.get(
+                    request.url(),
+                    token.map(str::trim),
+                    header("If-None-Match").as_deref(),
+                )
+            }
+            method => Reply::failure(Failure::new(
+                405,
+                "method_not_allowed",
+                format!("{} is not allowed; the API is read-only", method),
+            )),
+        };
+        tracing::debug!(url = request.url(), status = reply.status, "request");
+
+        let mut headers = vec![
+            ("Content-Type", "application/json".to_string()),
+            ("Cache-Control", "no-cache".to_string()),
+        ];
+        match reply.status {
+            401 => headers.push(("WWW-Authenticate", "Bearer".to_string())),
+            405 => headers.push(("Allow", "GET, HEAD".to_string())),
+            _ => {}
+        }
+        if let Some(etag) = reply.etag {
+            headers.push(("ETag", etag));
+        }
+        let mut response = Response::from_data(reply.body).with_status_code(reply.status);
+        for (name, value) in headers {
+            if let Ok(header) = Header::from_bytes(name, value) {
+                response.add_header(header);
+            }
+        }
+        if let Err(e) = request.respond(response) {
+            tracing::debug!("failed to send a reply: {}", e);
+        }
+    }
+}
+
+#[cfg(not(feature = "server"))]
+fn unsupported() -> JanusError {
+    JanusError::Unsupported(
+        "serving over HTTP requires januskey built with the `server` feature".to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::access::{ApiToken, Role};
+    use tempfile::TempDir;
+
+    fn body(reply: &Reply) -> serde_json::Value {
+        serde_json::from_slice(&reply.body).unwrap()
+    }
+
+    #[test]
+    fn test_pages_and_etags() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("site");
+        std::fs::create_dir(&root).unwrap();
+        let mut jk = JanusKey::init(&root).unwrap();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            jk.create(root.join(name), b"x".to_vec()).unwrap();
+        }
+        let tx = jk.begin().unwrap();
+        let tx_id = tx.id().to_string();
+        tx.commit().unwrap();
+        drop(jk);
+        let dashboard = Dashboard::new(std::slice::from_ref(&root)).unwrap();
+
+        let repos = dashboard.get("/repos", None, None);
+        assert_eq!(body(&repos)[0]["name"], "site");
+
+        let page = dashboard.get("/repos/site/history?offset=1&limit=1", None, None);
+        assert_eq!(page.status, 200);
+        let history = body(&page);
+        assert_eq!(history["total"], 3);
+        assert_eq!(history["items"].as_array().unwrap().len(), 1);
+        assert!(history["items"][0]["path"]
+            .as_str()
+            .unwrap()
+            .ends_with("b.txt"));
+
+        // Unchanged since: not sent again
+        let etag = page.etag.unwrap();
+        let again = dashboard.get("/repos/site/history?offset=1&limit=1", None, Some(&etag));
+        assert_eq!((again.status, again.body.len()), (304, 0));
+        let all = dashboard.get("/repos/site/history", None, Some(&etag));
+        assert_eq!(all.status, 200);
+        assert_eq!(body(&all)["limit"], DEFAULT_PAGE_SIZE);
+
+        let stats = body(&dashboard.get("/repos/site/stats", None, None));
+        assert_eq!(stats["status"]["operations"], 3);
+        let tx = dashboard.get(&format!("/repos/site/transactions/{}", tx_id), None, None);
+        assert_eq!(body(&tx)["id"], tx_id.as_str());
+        for target in ["/repos/site/audit", "/repos/site/obliterations"] {
+            assert_eq!(body(&dashboard.get(target, None, None))["total"], 0);
+        }
+
+        for (target, status) in [
+            ("/repos/site/history?limit=all", 400),
+            ("/repos/site/transactions/missing", 404),
+            ("/repos/elsewhere/history", 404),
+            ("/repos/site/keys", 404),
+            ("/", 404),
+        ] {
+            let reply = dashboard.get(target, None, None);
+            assert_eq!(reply.status, status, "{}", target);
+            assert!(body(&reply)["error"].is_string());
+        }
+
+        assert!(Dashboard::new(&[root.clone(), root]).is_err());
+        assert!(Dashboard::new(&[tmp.path().to_path_buf()]).is_err());
+    }
+
+    #[test]
+    fn test_tokens() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("private");
+        std::fs::create_dir(&root).unwrap();
+        let mut jk = JanusKey::init(&root).unwrap();
+        let (record, token) = ApiToken::issue("dashboard", Role::Reader).unwrap();
+        jk.config.daemon_tokens.push(record);
+        jk.config.save(&root).unwrap();
+        drop(jk);
+        let dashboard = Dashboard::new(&[root]).unwrap();
+
+        assert_eq!(
+            body(&dashboard.get("/repos", None, None)),
+            serde_json::json!([])
+        );
+        let history = "/repos/private/history";
+        assert_eq!(dashboard.get(history, None, None).status, 401);
+        assert_eq!(dashboard.get(history, Some("jkt_wrong"), None).status, 403);
+        assert_eq!(dashboard.get(history, Some(&token), None).status, 200);
+        assert_eq!(
+            body(&dashboard.get("/repos", Some(&token), None))[0]["name"],
+            "private"
+        );
+    }
+}
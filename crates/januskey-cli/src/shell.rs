@@ -0,0 +1,279 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// JanusKey Shell: the `jk shell` REPL
+// Keeps the store open between commands and completes against it
+
+use anyhow::{Context as _, Result};
+use clap::{CommandFactory, Parser};
+use colored::Colorize;
+use januskey::JanusKey;
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use std::cell::RefCell;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+
+use crate::{complete, run, Cli, Commands};
+
+/// Lines entered in `jk shell`, kept across sessions
+const HISTORY_FILE: &str = ".januskey/shell_history";
+
+thread_local! {
+    /// The store a running `jk shell` keeps open; `None` outside a shell
+    static SESSION: RefCell<Option<Session>> = const { RefCell::new(None) };
+}
+
+#[derive(Default)]
+struct Session {
+    /// Returned here by [`SessionStore`] when a command is done with it
+    store: Option<JanusKey>,
+}
+
+/// A store opened by [`open`]: inside `jk shell` it goes back to the
+/// session when dropped, so the next command need not reopen it
+pub struct SessionStore(Option<JanusKey>);
+
+impl Deref for SessionStore {
+    type Target = JanusKey;
+
+    fn deref(&self) -> &JanusKey {
+        self.0.as_ref().expect("taken only on drop")
+    }
+}
+
+impl DerefMut for SessionStore {
+    fn deref_mut(&mut self) -> &mut JanusKey {
+        self.0.as_mut().expect("taken only on drop")
+    }
+}
+
+impl Drop for SessionStore {
+    fn drop(&mut self) {
+        let jk = self.0.take();
+        SESSION.with_borrow_mut(|session| {
+            if let Some(session) = session {
+                session.store = jk;
+            }
+        });
+    }
+}
+
+/// Open the store for `dir`, or take the one the shell session holds
+pub fn open(dir: &Path) -> Result<SessionStore> {
+    let root = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let held = SESSION.with_borrow_mut(|session| {
+        session
+            .as_mut()
+            .and_then(|session| session.store.take_if(|jk| jk.root == root))
+    });
+    let jk = match held {
+        Some(jk) => jk,
+        None => JanusKey::open(dir).context("Failed to open JanusKey directory")?,
+    };
+    Ok(SessionStore(Some(jk)))
+}
+
+/// Drop the store the session holds, so the next command reads it afresh
+fn forget() {
+    SESSION.with_borrow_mut(|session| {
+        if let Some(session) = session {
+            session.store = None;
+        }
+    });
+}
+
+/// Whether `command` changes the store other than through the
+/// [`JanusKey`] the session holds, leaving that out of date
+fn invalidates_store(command: &Commands) -> bool {
+    matches!(
+        command,
+        Commands::Init { .. }
+            | Commands::Config { .. }
+            | Commands::Heal
+            | Commands::RestoreStore { .. }
+            | Commands::Workspace { .. }
+            | Commands::Pull { .. }
+            | Commands::Keys { .. }
+    )
+}
+
+/// Read commands until `exit` or end of input, running each as `jk`
+/// would in `dir`
+pub fn cmd_shell(dir: &Path) -> Result<()> {
+    std::env::set_current_dir(dir).with_context(|| format!("Failed to enter {}", dir.display()))?;
+    SESSION.with_borrow_mut(|session| *session = Some(Session::default()));
+
+    let mut editor: Editor<ShellHelper, FileHistory> =
+        Editor::new().context("Failed to start the line editor")?;
+    editor.set_helper(Some(ShellHelper::default()));
+    let history = dir.join(HISTORY_FILE);
+    // Missing until the first session in this directory ends
+    let _ = editor.load_history(&history);
+
+    loop {
+        let line = match editor.readline(&prompt(dir)) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e).context("Failed to read a command"),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line);
+        if matches!(line, "exit" | "quit") {
+            break;
+        }
+        let words = match shell_words::split(line) {
+            Ok(words) => words,
+            Err(e) => {
+                eprintln!("{} {}", "✗".red(), e);
+                continue;
+            }
+        };
+        let cli = match Cli::try_parse_from(std::iter::once("jk".to_string()).chain(words)) {
+            Ok(cli) => cli,
+            Err(e) => {
+                let _ = e.print();
+                continue;
+            }
+        };
+        if matches!(cli.command, Commands::Shell) {
+            eprintln!("{} Already in jk shell", "!".yellow());
+            continue;
+        }
+        #[cfg(unix)]
+        if crate::daemon_running(dir).unwrap_or(false) {
+            // The daemon changes the store; what the session holds goes stale
+            forget();
+        }
+
+        let invalidates = invalidates_store(&cli.command);
+        let json = cli.json;
+        if let Err(e) = run(cli) {
+            // A command that failed partway may have left the held store
+            // out of step with disk
+            forget();
+            if json {
+                println!("{}", crate::error_json(&e));
+            } else {
+                eprintln!("Error: {:?}", e);
+            }
+        } else if invalidates {
+            forget();
+        }
+    }
+
+    if JanusKey::is_initialized(dir) {
+        let _ = editor.save_history(&history);
+    }
+    SESSION.with_borrow_mut(|session| *session = None);
+    Ok(())
+}
+
+/// `jk> `, naming the active transaction when there is one
+fn prompt(dir: &Path) -> String {
+    if !JanusKey::is_initialized(dir) {
+        return "jk> ".to_string();
+    }
+    let Ok(jk) = open(dir) else {
+        return "jk> ".to_string();
+    };
+    match jk.transaction_manager.active() {
+        Some(tx) => {
+            let name = tx.name.clone().unwrap_or_else(|| tx.id[..8].to_string());
+            format!("jk ({})> ", name)
+        }
+        None => "jk> ".to_string(),
+    }
+}
+
+/// Completes subcommands, their flags, store values (see
+/// [`complete::value_kind`]) from the held store, and otherwise paths
+#[derive(Default)]
+struct ShellHelper {
+    files: FilenameCompleter,
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos].rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let word = &line[start..pos];
+        let before: Vec<&str> = line[..start].split_whitespace().collect();
+        let pairs = |names: Vec<String>| {
+            names
+                .into_iter()
+                .map(|name| Pair {
+                    display: name.clone(),
+                    replacement: name,
+                })
+                .collect()
+        };
+
+        let cli = Cli::command();
+        let Some(name) = before.iter().find(|word| !word.starts_with('-')) else {
+            let mut names: Vec<String> = cli
+                .get_subcommands()
+                .filter(|sub| !sub.is_hide_set())
+                .flat_map(|sub| std::iter::once(sub.get_name()).chain(sub.get_all_aliases()))
+                .chain(["exit", "quit"])
+                .filter(|name| name.starts_with(word))
+                .map(str::to_string)
+                .collect();
+            names.sort();
+            return Ok((start, pairs(names)));
+        };
+        let Some(sub) = cli.find_subcommand(name) else {
+            return self.files.complete(line, pos, ctx);
+        };
+
+        if word.starts_with('-') {
+            let names = sub
+                .get_arguments()
+                .chain(cli.get_arguments())
+                .filter_map(|arg| arg.get_long())
+                .map(|long| format!("--{}", long))
+                .filter(|long| long.starts_with(word))
+                .collect();
+            return Ok((start, pairs(names)));
+        }
+        let words: Vec<&str> = before.iter().copied().chain([word]).collect();
+        let values = complete::value_kind(&words)
+            .and_then(|kind| {
+                SESSION.with_borrow(|session| {
+                    let jk = session.as_ref()?.store.as_ref()?;
+                    Some(complete::candidates(jk, kind, word))
+                })
+            })
+            .unwrap_or_default();
+        if values.is_empty() {
+            return self.files.complete(line, pos, ctx);
+        }
+        Ok((start, pairs(values)))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
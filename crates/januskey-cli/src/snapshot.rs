@@ -0,0 +1,667 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Snapshots: whole-tree restore points
+// A manifest of every file's content, kept in the content store; an
+// incremental snapshot stores only what changed since its base
+
+use crate::actor::Actor;
+use crate::backend::write_atomic;
+use crate::content_store::{ContentHash, ContentStore};
+use crate::error::{JanusError, Result};
+use crate::operations::FileOperation;
+use crate::select::Excludes;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A file as a snapshot recorded it. A symbolic link is recorded as
+/// itself, its content being its target.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotFile {
+    pub hash: ContentHash,
+    pub size: u64,
+    /// Whether it is a symbolic link
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub link: bool,
+    /// Modification time when recorded, which lets an incremental
+    /// snapshot skip reading the file if it is unchanged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<DateTime<Utc>>,
+}
+
+/// Every file under the root, by path relative to it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub files: BTreeMap<PathBuf, SnapshotFile>,
+}
+
+impl SnapshotManifest {
+    /// Record the files under `root` that `excludes` does not skip,
+    /// storing their content in `content_store`. A file whose size and
+    /// modification time match its entry in `previous` is taken from
+    /// there without being read.
+    pub fn capture(
+        root: &Path,
+        content_store: &ContentStore,
+        excludes: &Excludes,
+        previous: Option<&SnapshotManifest>,
+    ) -> Result<Self> {
+        let mut files = BTreeMap::new();
+        for relative in walk(root, excludes)? {
+            let path = root.join(&relative);
+            let metadata = fs::symlink_metadata(&path)?;
+            let link = metadata.file_type().is_symlink();
+            let modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+            let unchanged = previous
+                .and_then(|previous| previous.files.get(&relative))
+                .filter(|file| {
+                    file.link == link
+                        && file.size == metadata.len()
+                        && modified.is_some()
+                        && file.modified == modified
+                });
+            let file = match unchanged {
+                Some(file) => file.clone(),
+                None if link => {
+                    let target = link_target(&path)?;
+                    SnapshotFile {
+                        hash: content_store.store(&target)?,
+                        size: target.len() as u64,
+                        link,
+                        modified,
+                    }
+                }
+                None => SnapshotFile {
+                    hash: content_store.store_file(&path)?,
+                    size: metadata.len(),
+                    link,
+                    modified,
+                },
+            };
+            files.insert(relative, file);
+        }
+        Ok(Self { files })
+    }
+
+    /// Paths added, removed and modified going from `self` to `other`,
+    /// by path
+    pub fn diff(&self, other: &SnapshotManifest) -> Vec<SnapshotChange> {
+        let mut changes = Vec::new();
+        for (path, file) in &self.files {
+            match other.files.get(path) {
+                None => changes.push(SnapshotChange::Removed {
+                    path: path.clone(),
+                    size: file.size,
+                }),
+                Some(new) if new.hash != file.hash || new.link != file.link => {
+                    changes.push(SnapshotChange::Modified {
+                        path: path.clone(),
+                        old_size: file.size,
+                        new_size: new.size,
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        for (path, file) in &other.files {
+            if !self.files.contains_key(path) {
+                changes.push(SnapshotChange::Added {
+                    path: path.clone(),
+                    size: file.size,
+                });
+            }
+        }
+        changes.sort_by(|a, b| a.path().cmp(b.path()));
+        changes
+    }
+
+    /// Total size of the recorded files in bytes
+    pub fn bytes(&self) -> u64 {
+        self.files.values().map(|file| file.size).sum()
+    }
+
+    /// The operations that bring the tree under `root` back to this
+    /// manifest: a create or modify for each recorded file that is missing
+    /// or differs, and a delete for each file it does not record. A link
+    /// that differs, or a file recorded as a link or the other way round,
+    /// is deleted and recreated; links are never followed. Files
+    /// `excludes` skips are left alone.
+    pub fn restore_operations(
+        &self,
+        root: &Path,
+        content_store: &ContentStore,
+        excludes: &Excludes,
+    ) -> Result<Vec<FileOperation>> {
+        let mut operations = Vec::new();
+        for (relative, file) in &self.files {
+            let path = root.join(relative);
+            let current = fs::symlink_metadata(&path).ok();
+            let is_link = current
+                .as_ref()
+                .is_some_and(|metadata| metadata.file_type().is_symlink());
+            let restore = |path: PathBuf| -> Result<FileOperation> {
+                let content = content_store.retrieve(&file.hash)?;
+                if file.link {
+                    link_operation(path, content)
+                } else {
+                    Ok(FileOperation::Create {
+                        path,
+                        content,
+                        mode: None,
+                    })
+                }
+            };
+            if current.is_none() {
+                operations.push(restore(path)?);
+            } else if is_link || file.link {
+                let same =
+                    is_link && file.link && file.hash.rehash(&link_target(&path)?) == file.hash;
+                if !same {
+                    operations.push(FileOperation::Delete { path: path.clone() });
+                    operations.push(restore(path)?);
+                }
+            } else if file.hash.rehash_file(&path)? != file.hash {
+                operations.push(FileOperation::Modify {
+                    path,
+                    new_content: content_store.retrieve(&file.hash)?,
+                });
+            }
+        }
+        for relative in walk(root, excludes)? {
+            if !self.files.contains_key(&relative) {
+                operations.push(FileOperation::Delete {
+                    path: root.join(relative),
+                });
+            }
+        }
+        Ok(operations)
+    }
+}
+
+/// A difference between two snapshots
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum SnapshotChange {
+    Added {
+        path: PathBuf,
+        size: u64,
+    },
+    Removed {
+        path: PathBuf,
+        size: u64,
+    },
+    Modified {
+        path: PathBuf,
+        old_size: u64,
+        new_size: u64,
+    },
+}
+
+impl SnapshotChange {
+    /// The path that changed, relative to the root
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Added { path, .. } | Self::Removed { path, .. } | Self::Modified { path, .. } => {
+                path
+            }
+        }
+    }
+}
+
+/// A manifest as kept in the content store: in full, or as the entries
+/// that differ from the `parent` manifest it is stored against
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoredManifest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parent: Option<ContentHash>,
+    files: BTreeMap<PathBuf, SnapshotFile>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    removed: BTreeSet<PathBuf>,
+}
+
+/// What is recorded for a symbolic link: its target
+fn link_target(path: &Path) -> Result<Vec<u8>> {
+    Ok(fs::read_link(path)?.into_os_string().into_encoded_bytes())
+}
+
+/// The operation creating a link at `path` to the recorded `target`
+fn link_operation(path: PathBuf, target: Vec<u8>) -> Result<FileOperation> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        Ok(FileOperation::Symlink {
+            path,
+            target: PathBuf::from(std::ffi::OsString::from_vec(target)),
+        })
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = target;
+        Err(JanusError::OperationFailed(format!(
+            "cannot restore symbolic link {} on this platform",
+            path.display()
+        )))
+    }
+}
+
+/// Files and symbolic links under `root` that `excludes` does not skip,
+/// relative to it and sorted. `.januskey/` is always skipped, and links
+/// are listed themselves, never followed.
+fn walk(root: &Path, excludes: &Excludes) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut walker = walkdir::WalkDir::new(root)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter();
+    while let Some(entry) = walker.next() {
+        let entry = entry.map_err(std::io::Error::from)?;
+        let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        if relative == Path::new(".januskey") || excludes.is_excluded(relative) {
+            if entry.file_type().is_dir() {
+                walker.skip_current_dir();
+            }
+            continue;
+        }
+        if entry.file_type().is_file() || entry.file_type().is_symlink() {
+            files.push(relative.to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+/// A named snapshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub name: String,
+    /// The [`SnapshotManifest`], stored as JSON in the content store
+    pub manifest: ContentHash,
+    pub created_at: DateTime<Utc>,
+    /// Who took it, as recorded for operations
+    pub created_by: String,
+    /// Number of files recorded
+    pub files: usize,
+    /// Total size of the files recorded
+    pub bytes: u64,
+    /// The snapshot an incremental snapshot was stored against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+    /// Paths added, modified or removed since `base`; every file for a
+    /// full snapshot
+    #[serde(default)]
+    pub changed: usize,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SnapshotsFile {
+    snapshots: Vec<Snapshot>,
+}
+
+/// Named snapshots, kept in `.januskey/snapshots.json`
+pub struct Snapshots {
+    path: PathBuf,
+    snapshots: Vec<Snapshot>,
+}
+
+impl Snapshots {
+    /// Open the snapshots at `path`, none if it does not exist
+    pub fn open(path: PathBuf) -> Result<Self> {
+        let snapshots = if path.exists() {
+            serde_json::from_str::<SnapshotsFile>(&fs::read_to_string(&path)?)
+                .map_err(|e| JanusError::MetadataCorrupted(e.to_string()))?
+                .snapshots
+        } else {
+            Vec::new()
+        };
+        Ok(Self { path, snapshots })
+    }
+
+    fn save(&self) -> Result<()> {
+        let file = SnapshotsFile {
+            snapshots: self.snapshots.clone(),
+        };
+        write_atomic(&self.path, &serde_json::to_vec_pretty(&file)?)?;
+        Ok(())
+    }
+
+    /// Every snapshot, oldest first
+    pub fn all(&self) -> &[Snapshot] {
+        &self.snapshots
+    }
+
+    /// The snapshot called `name`
+    pub fn get(&self, name: &str) -> Result<&Snapshot> {
+        self.snapshots
+            .iter()
+            .find(|snapshot| snapshot.name == name)
+            .ok_or_else(|| JanusError::SnapshotNotFound(name.to_string()))
+    }
+
+    /// The most recent snapshot
+    pub fn latest(&self) -> Option<&Snapshot> {
+        self.snapshots.last()
+    }
+
+    /// Snapshot the tree under `root` as `name` (by default, the time).
+    /// With a `base` snapshot, only what changed since it is stored, and
+    /// files that look unchanged (same size and modification time) are
+    /// not read again.
+    pub fn create(
+        &mut self,
+        name: Option<&str>,
+        root: &Path,
+        content_store: &ContentStore,
+        excludes: &Excludes,
+        base: Option<&str>,
+    ) -> Result<Snapshot> {
+        let created_at = Utc::now();
+        let name = match name {
+            Some(name) if name.trim().is_empty() => {
+                return Err(JanusError::OperationFailed(
+                    "a snapshot name cannot be empty".to_string(),
+                ))
+            }
+            Some(name) if self.get(name).is_ok() => {
+                return Err(JanusError::OperationFailed(format!(
+                    "snapshot {} already exists",
+                    name
+                )))
+            }
+            Some(name) => name.to_string(),
+            None => {
+                let stamp = created_at.format("%Y%m%d-%H%M%S").to_string();
+                (1..)
+                    .map(|n| match n {
+                        1 => stamp.clone(),
+                        n => format!("{}-{}", stamp, n),
+                    })
+                    .find(|name| self.get(name).is_err())
+                    .expect("unbounded")
+            }
+        };
+
+        let base = match base {
+            Some(base) => {
+                let snapshot = self.get(base)?;
+                Some((snapshot, self.manifest(snapshot, content_store)?))
+            }
+            None => None,
+        };
+        let manifest =
+            SnapshotManifest::capture(root, content_store, excludes, base.as_ref().map(|b| &b.1))?;
+        let stored = match &base {
+            Some((snapshot, previous)) => StoredManifest {
+                parent: Some(snapshot.manifest.clone()),
+                files: manifest
+                    .files
+                    .iter()
+                    .filter(|(path, file)| previous.files.get(*path) != Some(*file))
+                    .map(|(path, file)| (path.clone(), file.clone()))
+                    .collect(),
+                removed: previous
+                    .files
+                    .keys()
+                    .filter(|path| !manifest.files.contains_key(*path))
+                    .cloned()
+                    .collect(),
+            },
+            None => StoredManifest {
+                files: manifest.files.clone(),
+                ..StoredManifest::default()
+            },
+        };
+        let changed = match &base {
+            Some((_, previous)) => previous.diff(&manifest).len(),
+            None => manifest.files.len(),
+        };
+        let snapshot = Snapshot {
+            name,
+            manifest: content_store.store(&serde_json::to_vec(&stored)?)?,
+            created_at,
+            created_by: Actor::from_env().to_string(),
+            files: manifest.files.len(),
+            bytes: manifest.bytes(),
+            base: base.map(|(snapshot, _)| snapshot.name.clone()),
+            changed,
+        };
+        self.snapshots.push(snapshot.clone());
+        self.save()?;
+        Ok(snapshot)
+    }
+
+    /// Read `snapshot`'s manifest from the content store, applying an
+    /// incremental one to the manifests it was stored against
+    pub fn manifest(
+        &self,
+        snapshot: &Snapshot,
+        content_store: &ContentStore,
+    ) -> Result<SnapshotManifest> {
+        let mut chain = Vec::new();
+        let mut next = Some(snapshot.manifest.clone());
+        while let Some(hash) = next {
+            let stored: StoredManifest = serde_json::from_slice(&content_store.retrieve(&hash)?)
+                .map_err(|e| JanusError::MetadataCorrupted(e.to_string()))?;
+            next = stored.parent.clone();
+            chain.push(stored);
+        }
+
+        let mut manifest = SnapshotManifest::default();
+        for stored in chain.into_iter().rev() {
+            for path in &stored.removed {
+                manifest.files.remove(path);
+            }
+            manifest.files.extend(stored.files);
+        }
+        Ok(manifest)
+    }
+
+    /// The manifests `snapshot`'s is stored against, and its own, newest
+    /// first. Stops at the first that cannot be read.
+    fn manifest_chain(
+        &self,
+        snapshot: &Snapshot,
+        content_store: &ContentStore,
+    ) -> Vec<ContentHash> {
+        let mut chain = vec![snapshot.manifest.clone()];
+        while let Some(parent) = content_store
+            .retrieve(chain.last().expect("non-empty"))
+            .ok()
+            .and_then(|content| serde_json::from_slice::<StoredManifest>(&content).ok())
+            .and_then(|stored| stored.parent)
+        {
+            chain.push(parent);
+        }
+        chain
+    }
+
+    /// Blobs the snapshots need: their manifests (with those incremental
+    /// ones are stored against) and every file in them. A manifest that
+    /// cannot be read contributes only itself.
+    pub fn referenced(&self, content_store: &ContentStore) -> HashSet<ContentHash> {
+        let mut referenced = HashSet::new();
+        for snapshot in &self.snapshots {
+            referenced.extend(self.manifest_chain(snapshot, content_store));
+            match self.manifest(snapshot, content_store) {
+                Ok(manifest) => {
+                    referenced.extend(manifest.files.into_values().map(|file| file.hash))
+                }
+                Err(e) => {
+                    tracing::warn!(snapshot = %snapshot.name, error = %e, "unreadable snapshot manifest")
+                }
+            }
+        }
+        referenced
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_restore_operations() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("tree");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join(".januskey")).unwrap();
+        fs::write(root.join("src/a.txt"), "one").unwrap();
+        fs::write(root.join("b.txt"), "two").unwrap();
+        fs::write(root.join("build.log"), "noise").unwrap();
+        fs::write(root.join(".januskey/config.json"), "{}").unwrap();
+        let content_store = ContentStore::new(tmp.path().join("content"), true).unwrap();
+        let excludes = Excludes::new(&["*.log"]).unwrap();
+
+        let mut snapshots = Snapshots::open(tmp.path().join("snapshots.json")).unwrap();
+        let snapshot = snapshots
+            .create(Some("base"), &root, &content_store, &excludes, None)
+            .unwrap();
+        assert_eq!((snapshot.files, snapshot.bytes), (2, 6));
+        assert!(snapshots
+            .create(Some("base"), &root, &content_store, &excludes, None)
+            .is_err());
+
+        let snapshots = Snapshots::open(tmp.path().join("snapshots.json")).unwrap();
+        let manifest = snapshots
+            .manifest(snapshots.get("base").unwrap(), &content_store)
+            .unwrap();
+        assert!(manifest
+            .restore_operations(&root, &content_store, &excludes)
+            .unwrap()
+            .is_empty());
+        assert!(matches!(
+            snapshots.get("other"),
+            Err(JanusError::SnapshotNotFound(_))
+        ));
+
+        fs::write(root.join("src/a.txt"), "changed").unwrap();
+        fs::remove_file(root.join("b.txt")).unwrap();
+        fs::write(root.join("new.txt"), "new").unwrap();
+        let operations = manifest
+            .restore_operations(&root, &content_store, &excludes)
+            .unwrap();
+        assert_eq!(operations.len(), 3);
+        assert!(matches!(
+            &operations[0],
+            FileOperation::Create { path, content, .. } if *path == root.join("b.txt") && content == b"two"
+        ));
+        assert!(matches!(
+            &operations[1],
+            FileOperation::Modify { path, new_content } if *path == root.join("src/a.txt") && new_content == b"one"
+        ));
+        assert!(matches!(
+            &operations[2],
+            FileOperation::Delete { path } if *path == root.join("new.txt")
+        ));
+
+        let referenced = snapshots.referenced(&content_store);
+        assert!(referenced.contains(&snapshot.manifest));
+        assert!(referenced.contains(&ContentHash::from_string("two")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_links_recorded_not_followed() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("tree");
+        let outside = tmp.path().join("outside");
+        fs::create_dir_all(&root).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("far.txt"), "far away").unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("out")).unwrap();
+        let content_store = ContentStore::new(tmp.path().join("content"), false).unwrap();
+        let excludes = Excludes::default();
+
+        let manifest = SnapshotManifest::capture(&root, &content_store, &excludes, None).unwrap();
+        let file = &manifest.files[Path::new("out")];
+        assert!(file.link);
+        assert_eq!(
+            content_store.retrieve(&file.hash).unwrap(),
+            outside.as_os_str().as_encoded_bytes()
+        );
+        assert_eq!(manifest.files.len(), 1);
+        assert!(manifest
+            .restore_operations(&root, &content_store, &excludes)
+            .unwrap()
+            .is_empty());
+
+        fs::remove_file(root.join("out")).unwrap();
+        fs::write(root.join("out"), "a file now").unwrap();
+        let operations = manifest
+            .restore_operations(&root, &content_store, &excludes)
+            .unwrap();
+        assert!(matches!(
+            &operations[..],
+            [FileOperation::Delete { path }, FileOperation::Symlink { target, .. }]
+                if *path == root.join("out") && *target == outside
+        ));
+    }
+
+    #[test]
+    fn test_incremental_snapshot_and_diff() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("tree");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("same.txt"), "same").unwrap();
+        fs::write(root.join("changed.txt"), "old").unwrap();
+        fs::write(root.join("removed.txt"), "gone soon").unwrap();
+        let content_store = ContentStore::new(tmp.path().join("content"), false).unwrap();
+        let excludes = Excludes::new::<&str>(&[]).unwrap();
+        let mut snapshots = Snapshots::open(tmp.path().join("snapshots.json")).unwrap();
+        let full = snapshots
+            .create(Some("full"), &root, &content_store, &excludes, None)
+            .unwrap();
+        assert_eq!((full.base.as_deref(), full.changed), (None, 3));
+
+        fs::write(root.join("changed.txt"), "newer").unwrap();
+        fs::remove_file(root.join("removed.txt")).unwrap();
+        fs::write(root.join("added.txt"), "hi").unwrap();
+        let incremental = snapshots
+            .create(Some("inc"), &root, &content_store, &excludes, Some("full"))
+            .unwrap();
+        assert_eq!(incremental.base.as_deref(), Some("full"));
+        assert_eq!((incremental.files, incremental.changed), (3, 3));
+
+        // Only the differences are stored, but the manifest resolves in full
+        let stored: StoredManifest =
+            serde_json::from_slice(&content_store.retrieve(&incremental.manifest).unwrap())
+                .unwrap();
+        assert_eq!(stored.parent.as_ref(), Some(&full.manifest));
+        assert!(!stored.files.contains_key(Path::new("same.txt")));
+        assert!(stored.removed.contains(Path::new("removed.txt")));
+        let old = snapshots.manifest(&full, &content_store).unwrap();
+        let new = snapshots.manifest(&incremental, &content_store).unwrap();
+        assert_eq!(
+            new.files.keys().collect::<Vec<_>>(),
+            ["added.txt", "changed.txt", "same.txt"]
+                .iter()
+                .map(Path::new)
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(
+            old.diff(&new),
+            vec![
+                SnapshotChange::Added {
+                    path: "added.txt".into(),
+                    size: 2
+                },
+                SnapshotChange::Modified {
+                    path: "changed.txt".into(),
+                    old_size: 3,
+                    new_size: 5
+                },
+                SnapshotChange::Removed {
+                    path: "removed.txt".into(),
+                    size: 9
+                },
+            ]
+        );
+        assert!(snapshots
+            .referenced(&content_store)
+            .is_superset(&[full.manifest, incremental.manifest].into_iter().collect()));
+    }
+}
@@ -0,0 +1,397 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Store Backup: the whole store in one zstd-compressed tar archive
+// Metadata, content, transactions, keys and the obliteration log, with a
+// manifest of every file's SHA-256 checked before anything is unpacked
+
+use crate::backend::StorageUri;
+use crate::error::{JanusError, Result};
+use crate::Config;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+/// Format identifier recorded in every backup manifest
+pub const BACKUP_FORMAT: &str = "januskey-store-backup/1";
+
+/// Name of the manifest entry, the last in the archive
+const MANIFEST_ENTRY: &str = "MANIFEST.json";
+
+/// Archive prefix for `.januskey/`
+const STORE_PREFIX: &str = "januskey";
+
+/// Archive prefix for a local `storage_path` outside `.januskey/`
+const STORAGE_PREFIX: &str = "storage";
+
+/// zstd compression level for backups
+const ZSTD_LEVEL: i32 = 3;
+
+/// A file in the backup
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupFile {
+    pub size: u64,
+    /// Hex-encoded SHA-256 of the file
+    pub sha256: String,
+}
+
+/// What a backup holds: every file by archive path
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub format: String,
+    pub created_at: DateTime<Utc>,
+    /// Working directory the store belonged to
+    pub source: String,
+    pub files: BTreeMap<String, BackupFile>,
+}
+
+impl BackupManifest {
+    /// Total size of the files in bytes
+    pub fn bytes(&self) -> u64 {
+        self.files.values().map(|file| file.size).sum()
+    }
+}
+
+/// Write a backup of the store for `root` to `output`. History kept in a
+/// local `storage_path` is included; remote storage is refused, since it
+/// is backed up with the backend's own tools.
+pub fn create(root: &Path, output: &Path) -> Result<BackupManifest> {
+    let dirs = store_dirs(root, &Config::load(root)?)?;
+    let output_abs = output
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .and_then(|parent| parent.canonicalize().ok())
+        .and_then(|parent| Some(parent.join(output.file_name()?)));
+
+    let encoder = zstd::Encoder::new(File::create(output)?, ZSTD_LEVEL)?;
+    let mut builder = tar::Builder::new(encoder);
+    let mut files = BTreeMap::new();
+    for (prefix, dir) in &dirs {
+        if !dir.exists() {
+            continue;
+        }
+        for entry in walkdir::WalkDir::new(dir).sort_by_file_name() {
+            let entry = entry.map_err(|e| JanusError::Io(e.into()))?;
+            if !entry.file_type().is_file() || output_abs.as_deref() == Some(entry.path()) {
+                continue;
+            }
+            let relative = entry.path().strip_prefix(dir).unwrap_or(entry.path());
+            let name = archive_name(prefix, relative);
+
+            let mut header = tar::Header::new_gnu();
+            header.set_metadata(&entry.metadata().map_err(|e| JanusError::Io(e.into()))?);
+            let mut reader = HashingReader::new(File::open(entry.path())?);
+            builder.append_data(&mut header, &name, &mut reader)?;
+            files.insert(
+                name,
+                BackupFile {
+                    size: reader.bytes,
+                    sha256: hex::encode(reader.hasher.finalize()),
+                },
+            );
+        }
+    }
+
+    let manifest = BackupManifest {
+        format: BACKUP_FORMAT.to_string(),
+        created_at: Utc::now(),
+        source: root.display().to_string(),
+        files,
+    };
+    let json = serde_json::to_vec_pretty(&manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(json.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(manifest.created_at.timestamp().max(0) as u64);
+    builder.append_data(&mut header, MANIFEST_ENTRY, json.as_slice())?;
+    builder.into_inner()?.finish()?.sync_all()?;
+    Ok(manifest)
+}
+
+/// Check every file in the backup `archive` against its manifest without
+/// unpacking anything
+pub fn verify(archive: &Path) -> Result<BackupManifest> {
+    verify_entries(archive).map(|(manifest, _)| manifest)
+}
+
+/// Unpack the backup `archive` into `root` once every file has matched
+/// the manifest. An existing store is refused unless `replace` is set, in
+/// which case it is removed first.
+pub fn restore(archive: &Path, root: &Path, replace: bool) -> Result<BackupManifest> {
+    let (manifest, config) = verify_entries(archive)?;
+    let config: Config = match config {
+        Some(json) => serde_json::from_slice(&json)?,
+        None => Config::default(),
+    };
+    let dirs: BTreeMap<&str, PathBuf> = store_dirs(root, &config)?.into_iter().collect();
+
+    for dir in dirs.values() {
+        if dir.exists() {
+            if !replace {
+                return Err(JanusError::PathExists(dir.display().to_string()));
+            }
+            fs::remove_dir_all(dir)?;
+        }
+    }
+
+    let mut tar = open_archive(archive)?;
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        if name == MANIFEST_ENTRY {
+            continue;
+        }
+        let (prefix, relative) = split_name(&name)?;
+        let dir = dirs.get(prefix).ok_or_else(|| {
+            JanusError::InvalidBackup(format!("{} has no local storage to restore to", name))
+        })?;
+        let path = dir.join(relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        entry.unpack(&path)?;
+    }
+    Ok(manifest)
+}
+
+/// Hash every entry of `archive` and compare with its manifest, returning
+/// the manifest and the archived `config.json`
+fn verify_entries(archive: &Path) -> Result<(BackupManifest, Option<Vec<u8>>)> {
+    let mut found = BTreeMap::new();
+    let mut manifest = None;
+    let mut config = None;
+    let config_name = archive_name(STORE_PREFIX, Path::new("config.json"));
+
+    let mut tar = open_archive(archive)?;
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            return Err(JanusError::InvalidBackup(format!(
+                "{} is not a regular file",
+                entry.path()?.display()
+            )));
+        }
+        let name = entry.path()?.to_string_lossy().into_owned();
+        if name == MANIFEST_ENTRY {
+            let mut json = Vec::new();
+            entry.read_to_end(&mut json)?;
+            manifest = Some(serde_json::from_slice::<BackupManifest>(&json)?);
+            continue;
+        }
+        split_name(&name)?;
+
+        let mut reader = HashingReader::new(&mut entry);
+        if name == config_name {
+            let mut json = Vec::new();
+            reader.read_to_end(&mut json)?;
+            config = Some(json);
+        } else {
+            io::copy(&mut reader, &mut io::sink())?;
+        }
+        let file = BackupFile {
+            size: reader.bytes,
+            sha256: hex::encode(reader.hasher.finalize()),
+        };
+        if found.insert(name.clone(), file).is_some() {
+            return Err(JanusError::InvalidBackup(format!("{} appears twice", name)));
+        }
+    }
+
+    let manifest =
+        manifest.ok_or_else(|| JanusError::InvalidBackup(format!("no {}", MANIFEST_ENTRY)))?;
+    if manifest.format != BACKUP_FORMAT {
+        return Err(JanusError::InvalidBackup(format!(
+            "unsupported format {}",
+            manifest.format
+        )));
+    }
+    for (name, expected) in &manifest.files {
+        match found.remove(name) {
+            Some(actual) if actual == *expected => {}
+            Some(actual) => {
+                return Err(JanusError::InvalidBackup(format!(
+                    "{} has SHA-256 {}, expected {}",
+                    name, actual.sha256, expected.sha256
+                )))
+            }
+            None => return Err(JanusError::InvalidBackup(format!("{} is missing", name))),
+        }
+    }
+    if let Some(name) = found.keys().next() {
+        return Err(JanusError::InvalidBackup(format!(
+            "{} is not in the manifest",
+            name
+        )));
+    }
+    Ok((manifest, config))
+}
+
+/// The directories making up the store for `root` under `config`, by
+/// archive prefix
+fn store_dirs(root: &Path, config: &Config) -> Result<Vec<(&'static str, PathBuf)>> {
+    let store = root.join(".januskey");
+    let mut dirs = vec![(STORE_PREFIX, store.clone())];
+    match config.storage_uri()? {
+        None => {}
+        Some(uri @ StorageUri::Local(_)) => {
+            let storage = root.join(uri.root());
+            if !storage.starts_with(&store) {
+                dirs.push((STORAGE_PREFIX, storage));
+            }
+        }
+        Some(uri) => {
+            return Err(JanusError::OperationFailed(format!(
+                "history is kept in {}; back it up with the backend's own tools",
+                uri
+            )))
+        }
+    }
+    Ok(dirs)
+}
+
+/// Archive path of `relative` under `prefix`, always with `/` separators
+fn archive_name(prefix: &str, relative: &Path) -> String {
+    let mut name = prefix.to_string();
+    for component in relative.components() {
+        name.push('/');
+        name.push_str(&component.as_os_str().to_string_lossy());
+    }
+    name
+}
+
+/// Split an archive path into its prefix and a relative path that cannot
+/// escape the directory it is unpacked into
+fn split_name(name: &str) -> Result<(&str, &Path)> {
+    let invalid = || JanusError::InvalidBackup(format!("unexpected entry {}", name));
+    let (prefix, relative) = name.split_once('/').ok_or_else(invalid)?;
+    let relative = Path::new(relative);
+    let safe = relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if ![STORE_PREFIX, STORAGE_PREFIX].contains(&prefix) || !safe {
+        return Err(invalid());
+    }
+    Ok((prefix, relative))
+}
+
+fn open_archive(
+    archive: &Path,
+) -> Result<tar::Archive<zstd::Decoder<'static, io::BufReader<File>>>> {
+    Ok(tar::Archive::new(zstd::Decoder::new(File::open(archive)?)?))
+}
+
+/// Reader that hashes and counts what passes through it
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+    bytes: u64,
+}
+
+impl<R> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            bytes: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.bytes += n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::JanusKey;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_backup_and_restore() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("tree");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.txt"), "hello").unwrap();
+        let mut jk = JanusKey::init(&root).unwrap();
+        jk.delete(root.join("a.txt")).unwrap();
+
+        let archive = tmp.path().join("store.tar.zst");
+        let manifest = create(&root, &archive).unwrap();
+        assert!(manifest.files.contains_key("januskey/config.json"));
+        assert_eq!(verify(&archive).unwrap().files, manifest.files);
+
+        let target = tmp.path().join("restored");
+        restore(&archive, &target, false).unwrap();
+        let mut jk = JanusKey::open(&target).unwrap();
+        assert_eq!(jk.metadata_store.count(), 1);
+        assert!(matches!(
+            restore(&archive, &target, false),
+            Err(JanusError::PathExists(_))
+        ));
+
+        // The restored history undoes into the restored tree
+        let op = jk.metadata_store.last_n(1)[0].id.clone();
+        jk.undo(&op).unwrap();
+        assert_eq!(fs::read(target.join("a.txt")).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_tampered_backup_is_refused() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("tree");
+        fs::create_dir_all(&root).unwrap();
+        JanusKey::init(&root).unwrap();
+        let archive = tmp.path().join("store.tar.zst");
+        let mut manifest = create(&root, &archive).unwrap();
+
+        // Rewrite the archive with a manifest claiming other content
+        let file = manifest.files.get_mut("januskey/config.json").unwrap();
+        file.sha256 = hex::encode(Sha256::digest(b"other"));
+        let mut builder = tar::Builder::new(
+            zstd::Encoder::new(File::create(&archive).unwrap(), ZSTD_LEVEL).unwrap(),
+        );
+        let config = fs::read(root.join(".januskey/config.json")).unwrap();
+        let json = serde_json::to_vec(&manifest).unwrap();
+        for (name, data) in [("januskey/config.json", &config), (MANIFEST_ENTRY, &json)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, name, data.as_slice())
+                .unwrap();
+        }
+        builder
+            .into_inner()
+            .unwrap()
+            .finish()
+            .unwrap()
+            .flush()
+            .unwrap();
+
+        let target = tmp.path().join("restored");
+        assert!(matches!(
+            restore(&archive, &target, false),
+            Err(JanusError::InvalidBackup(_))
+        ));
+        assert!(!target.join(".januskey").exists());
+    }
+
+    #[test]
+    fn test_entry_names_cannot_escape() {
+        assert!(split_name("januskey/content/ab").is_ok());
+        assert!(split_name("januskey/../../etc/passwd").is_err());
+        assert!(split_name("elsewhere/file").is_err());
+    }
+}
@@ -0,0 +1,154 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Tiering: keep the local content store small
+// Old or large blobs move to a secondary backend with a pointer left in
+// their place; reading one (to undo, say) fetches it transparently
+
+use crate::backend::{RetryBackend, RetryPolicy, StorageUri, TransferPolicy};
+use crate::content_store::{BlobLocation, ColdTier, ContentHash, ContentStore};
+use crate::error::{JanusError, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Which blobs `jk tier` offloads, and where to. A blob is offloaded if
+/// either limit selects it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TieringPolicy {
+    /// Path or backend URI to offload to, as for `storage_path`
+    pub storage: String,
+    /// Offload blobs stored more than this many days ago
+    #[serde(default)]
+    pub older_than_days: Option<u32>,
+    /// Offload blobs of more than this many stored bytes
+    #[serde(default)]
+    pub larger_than_bytes: Option<u64>,
+}
+
+impl TieringPolicy {
+    /// The cold tier on `storage`, connected on first use with `transfer`
+    /// and `retry`. A relative local path is relative to `root`.
+    pub fn cold_tier(
+        &self,
+        root: &Path,
+        transfer: &TransferPolicy,
+        retry: &RetryPolicy,
+    ) -> Result<ColdTier> {
+        let uri = StorageUri::parse(&self.storage)?;
+        Ok(match uri {
+            StorageUri::Local(ref path) => {
+                ColdTier::new(root.join(path).join("content"), move || uri.connect())
+            }
+            _ => {
+                let (transfer, retry) = (transfer.clone(), retry.clone());
+                ColdTier::new(uri.root().join("content"), move || {
+                    let backend = retry.run(|| uri.connect_with(&transfer, &retry))?;
+                    Ok(Arc::new(RetryBackend::new(backend, retry.clone())))
+                })
+            }
+        })
+    }
+
+    /// Whether a blob of `bytes` stored at `stored` is cold at `now`
+    pub fn is_cold(&self, bytes: u64, stored: SystemTime, now: SystemTime) -> bool {
+        let large = self.larger_than_bytes.is_some_and(|max| bytes > max);
+        let old = self.older_than_days.is_some_and(|days| {
+            now.duration_since(stored)
+                .is_ok_and(|age| age > Duration::from_secs(u64::from(days) * 86_400))
+        });
+        large || old
+    }
+}
+
+/// The loose blobs in `content_store` that `policy` selects, with their
+/// stored sizes. Packed blobs are small and stay local.
+pub fn plan(
+    content_store: &ContentStore,
+    policy: &TieringPolicy,
+) -> Result<Vec<(ContentHash, u64)>> {
+    if !content_store.backend().is_local() {
+        return Err(JanusError::OperationFailed(format!(
+            "tiering needs the content store on the local disk, not the {} backend",
+            content_store.backend().name()
+        )));
+    }
+    let now = SystemTime::now();
+    let mut cold = Vec::new();
+    for (hash, bytes) in content_store.list()? {
+        let Some(BlobLocation::Loose(path)) = content_store.locate(&hash)? else {
+            continue;
+        };
+        let stored = std::fs::metadata(&path)?.modified()?;
+        if policy.is_cold(bytes, stored, now) {
+            cold.push((hash, bytes));
+        }
+    }
+    Ok(cold)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, JanusKey};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_is_cold() {
+        let policy = TieringPolicy {
+            storage: "cold".to_string(),
+            older_than_days: Some(30),
+            larger_than_bytes: Some(1024),
+        };
+        let now = SystemTime::now();
+        let days = |n: u64| now - Duration::from_secs(n * 86_400);
+        assert!(!policy.is_cold(10, days(1), now));
+        assert!(policy.is_cold(2048, days(1), now));
+        assert!(policy.is_cold(10, days(31), now));
+
+        let none = TieringPolicy {
+            older_than_days: None,
+            larger_than_bytes: None,
+            ..policy
+        };
+        assert!(!none.is_cold(u64::MAX, days(10_000), now));
+    }
+
+    #[test]
+    fn test_undo_fetches_offloaded_content() {
+        let temp = TempDir::new().unwrap();
+        let cold = TempDir::new().unwrap();
+        let config = Config {
+            tiering: Some(TieringPolicy {
+                storage: cold.path().display().to_string(),
+                older_than_days: None,
+                larger_than_bytes: Some(16),
+            }),
+            compression: false,
+            ..Config::default()
+        };
+        let mut jk = JanusKey::init_with_config(temp.path(), config).unwrap();
+        fs::write(temp.path().join("big.txt"), "large enough to offload\n").unwrap();
+        fs::write(temp.path().join("small.txt"), "tiny\n").unwrap();
+        jk.delete("big.txt").unwrap();
+        jk.delete("small.txt").unwrap();
+
+        let offloaded = jk.tier().unwrap();
+        assert_eq!(offloaded.len(), 1);
+        assert_eq!(jk.status().unwrap().offloaded, 1);
+        assert!(jk.tier_plan().unwrap().is_empty());
+
+        jk.undo_recent(2);
+        assert_eq!(
+            fs::read_to_string(temp.path().join("big.txt")).unwrap(),
+            "large enough to offload\n"
+        );
+        assert!(temp.path().join("small.txt").exists());
+
+        assert_eq!(jk.recall().unwrap().len(), 1);
+        assert_eq!(jk.status().unwrap().offloaded, 0);
+    }
+}
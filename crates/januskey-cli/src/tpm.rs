@@ -0,0 +1,213 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// TPM Sealing: the key store's KEK sealed to this machine's TPM 2.0
+// The KEK is sealed under the owner hierarchy's primary key with a policy
+// on PCR values, so it unseals only on this TPM (the primary is derived
+// from its owner seed) and only while the measured boot state matches the
+// state it was sealed in. Driven through tpm2-tools, so nothing is linked
+// against the TSS libraries; Linux only.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::keys::{KeyError, Result, SecretKey};
+
+/// PCRs sealed to when none are named: the Secure Boot policy and the
+/// keys that signed what booted
+pub const DEFAULT_PCRS: &str = "sha256:7";
+
+/// A KEK sealed to the TPM (`keys/tpm.json`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TpmSeal {
+    /// PCR selection the policy covers, as tpm2-tools writes it
+    /// (`sha256:0,7`)
+    pub pcrs: String,
+    /// Public and private parts of the sealed object, hex
+    pub public: String,
+    pub private: String,
+}
+
+/// Check a PCR selection: a bank (`sha1`, `sha256`, `sha384`), a colon,
+/// then PCR indexes 0 to 23 separated by commas
+pub fn parse_pcrs(selection: &str) -> Result<String> {
+    let invalid = || KeyError::Tpm(format!("invalid PCR selection {:?}", selection));
+    let (bank, indexes) = selection.split_once(':').ok_or_else(invalid)?;
+    if !matches!(bank, "sha1" | "sha256" | "sha384") {
+        return Err(invalid());
+    }
+    let mut pcrs = Vec::new();
+    for index in indexes.split(',') {
+        let pcr: u8 = index.trim().parse().map_err(|_| invalid())?;
+        if pcr > 23 {
+            return Err(invalid());
+        }
+        pcrs.push(pcr);
+    }
+    pcrs.sort_unstable();
+    pcrs.dedup();
+    let pcrs: Vec<String> = pcrs.iter().map(u8::to_string).collect();
+    Ok(format!("{}:{}", bank, pcrs.join(",")))
+}
+
+/// Seal `kek` to the PCR values `pcrs` (see [`parse_pcrs`]) hold now.
+/// The tools' working files go in a directory under `store_dir`.
+pub fn seal(kek: &SecretKey, pcrs: &str, store_dir: &Path) -> Result<TpmSeal> {
+    let pcrs = parse_pcrs(pcrs)?;
+    let dir = Scratch::new(store_dir)?;
+    let path = |name: &str| dir.path().join(name);
+    create_primary(dir.path())?;
+    run(
+        Command::new("tpm2_createpolicy")
+            .arg("--policy-pcr")
+            .args(["-l", &pcrs])
+            .arg("-L")
+            .arg(path("pcr.policy")),
+        None,
+    )?;
+    // Without userwithauth, only the PCR policy can unseal it
+    run(
+        Command::new("tpm2_create")
+            .arg("-C")
+            .arg(path("primary.ctx"))
+            .arg("-L")
+            .arg(path("pcr.policy"))
+            .args(["-a", "fixedtpm|fixedparent", "-i", "-"])
+            .arg("-u")
+            .arg(path("seal.pub"))
+            .arg("-r")
+            .arg(path("seal.priv")),
+        Some(kek.as_bytes()),
+    )?;
+    Ok(TpmSeal {
+        pcrs,
+        public: hex::encode(std::fs::read(path("seal.pub"))?),
+        private: hex::encode(std::fs::read(path("seal.priv"))?),
+    })
+}
+
+/// Unseal the KEK, which fails on another machine or once the PCRs no
+/// longer hold the values it was sealed to
+pub fn unseal(seal: &TpmSeal, store_dir: &Path) -> Result<SecretKey> {
+    let dir = Scratch::new(store_dir)?;
+    let path = |name: &str| dir.path().join(name);
+    let decode =
+        |part: &str| hex::decode(part).map_err(|e| KeyError::Tpm(format!("tpm.json: {}", e)));
+    std::fs::write(path("seal.pub"), decode(&seal.public)?)?;
+    std::fs::write(path("seal.priv"), decode(&seal.private)?)?;
+    create_primary(dir.path())?;
+    run(
+        Command::new("tpm2_load")
+            .arg("-C")
+            .arg(path("primary.ctx"))
+            .arg("-u")
+            .arg(path("seal.pub"))
+            .arg("-r")
+            .arg(path("seal.priv"))
+            .arg("-c")
+            .arg(path("seal.ctx")),
+        None,
+    )?;
+    let kek = run(
+        Command::new("tpm2_unseal")
+            .arg("-c")
+            .arg(path("seal.ctx"))
+            .args(["-p", &format!("pcr:{}", seal.pcrs)]),
+        None,
+    )?;
+    let bytes = kek
+        .as_slice()
+        .try_into()
+        .map_err(|_| KeyError::Tpm("unsealed KEK has the wrong length".to_string()))?;
+    Ok(SecretKey::new(bytes))
+}
+
+/// The owner hierarchy's primary key, which the TPM re-derives the same
+/// from its seed each time
+fn create_primary(dir: &Path) -> Result<()> {
+    run(
+        Command::new("tpm2_createprimary")
+            .args(["-C", "o", "-g", "sha256", "-G", "ecc", "-c"])
+            .arg(dir.join("primary.ctx")),
+        None,
+    )
+    .map(|_| ())
+}
+
+/// Directory for the tools' context files, removed when dropped. It is
+/// made in the key store's directory, which only its owner can read.
+struct Scratch(std::path::PathBuf);
+
+impl Scratch {
+    fn new(store_dir: &Path) -> Result<Self> {
+        if !cfg!(target_os = "linux") {
+            return Err(KeyError::Tpm(
+                "TPM sealing is supported on Linux only".to_string(),
+            ));
+        }
+        let dir = store_dir.join(format!(".tpm-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self(dir))
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Run a tpm2-tools command, feeding it `input`, and return its output
+fn run(command: &mut Command, input: Option<&[u8]>) -> Result<Vec<u8>> {
+    use std::io::Write;
+
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => {
+                KeyError::Tpm(format!("{} not found; install tpm2-tools", program))
+            }
+            _ => KeyError::Tpm(format!("{}: {}", program, e)),
+        })?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input)?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(KeyError::Tpm(format!(
+            "{} failed: {}",
+            program,
+            stderr.lines().last().unwrap_or("no output").trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pcrs() {
+        assert_eq!(parse_pcrs("sha256:7").unwrap(), "sha256:7");
+        assert_eq!(parse_pcrs("sha1:7, 0,7").unwrap(), "sha1:0,7");
+        for bad in ["7", "md5:7", "sha256:24", "sha256:", "sha256:a"] {
+            assert!(matches!(parse_pcrs(bad), Err(KeyError::Tpm(_))), "{}", bad);
+        }
+    }
+}
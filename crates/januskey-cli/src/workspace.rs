@@ -0,0 +1,114 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Workspaces: several roots sharing one store
+// Each root registered against a shared `storage_path` records its
+// operations under a workspace ID, and sees and prunes only those.
+// The registry beside the logs maps each ID to the root it was last
+// opened from, so other workspaces' history can be shown with real paths.
+
+use crate::backend::FileBackend;
+use crate::error::Result;
+use crate::metadata::OperationMetadata;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Registered workspaces of a store, by ID
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Workspaces {
+    pub roots: BTreeMap<String, PathBuf>,
+}
+
+impl Workspaces {
+    /// The registry file in `store_dir`
+    pub fn path_for(store_dir: &Path) -> PathBuf {
+        store_dir.join("workspaces.json")
+    }
+
+    /// Read the registry from `store_dir`, empty if there is none
+    pub fn load(backend: &Arc<dyn FileBackend>, store_dir: &Path) -> Result<Self> {
+        let path = Self::path_for(store_dir);
+        if !backend.exists(&path) {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_slice(&backend.read(&path)?)?)
+    }
+
+    pub fn save(&self, backend: &Arc<dyn FileBackend>, store_dir: &Path) -> Result<()> {
+        backend.write(
+            &Self::path_for(store_dir),
+            &serde_json::to_vec_pretty(self)?,
+        )
+    }
+
+    /// An unused ID for `root`: its directory name, numbered if taken
+    pub fn new_id(&self, root: &Path) -> String {
+        let name = root
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| "workspace".to_string());
+        (1..)
+            .map(|n| match n {
+                1 => name.clone(),
+                n => format!("{}-{}", name, n),
+            })
+            .find(|id| !self.roots.contains_key(id))
+            .expect("some numbered ID is free")
+    }
+
+    /// Record that workspace `id` is at `root`. Returns whether the
+    /// registry changed (a new workspace, or one opened from a new place).
+    pub fn register(&mut self, id: &str, root: &Path) -> bool {
+        match self.roots.insert(id.to_string(), root.to_path_buf()) {
+            Some(previous) if previous == root => false,
+            Some(previous) => {
+                tracing::info!(
+                    workspace = id,
+                    from = %previous.display(),
+                    to = %root.display(),
+                    "workspace moved"
+                );
+                true
+            }
+            None => true,
+        }
+    }
+
+    /// `op` from another workspace, with paths resolved against its root
+    pub fn resolve(&self, op: &OperationMetadata) -> OperationMetadata {
+        let mut op = op.clone();
+        if let Some(root) = op.workspace.as_ref().and_then(|id| self.roots.get(id)) {
+            op.path = root.join(&op.path);
+            if let Some(secondary) = &op.path_secondary {
+                op.path_secondary = Some(root.join(secondary));
+            }
+        }
+        op
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::OperationType;
+
+    #[test]
+    fn test_new_id_and_resolve() {
+        let mut workspaces = Workspaces::default();
+        assert_eq!(workspaces.new_id(Path::new("/src/web")), "web");
+        assert!(workspaces.register("web", Path::new("/src/web")));
+        assert!(!workspaces.register("web", Path::new("/src/web")));
+        assert_eq!(workspaces.new_id(Path::new("/other/web")), "web-2");
+
+        let mut op = OperationMetadata::new(OperationType::Move, PathBuf::from("a.txt"))
+            .with_secondary_path(PathBuf::from("b/a.txt"));
+        op.workspace = Some("web".to_string());
+        let op = workspaces.resolve(&op);
+        assert_eq!(op.path, PathBuf::from("/src/web/a.txt"));
+        assert_eq!(op.path_secondary, Some(PathBuf::from("/src/web/b/a.txt")));
+    }
+}
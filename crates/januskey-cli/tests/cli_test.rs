@@ -0,0 +1,2585 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+//
+// CLI tests: drive the `jk` binary end to end
+// Each test initializes a fresh repository in a temp directory
+
+use assert_cmd::Command;
+use predicates::prelude::*;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Helper: `jk` command running in `dir`
+fn jk(dir: &Path) -> Command {
+    let mut cmd = Command::cargo_bin("jk").expect("jk binary");
+    cmd.current_dir(dir);
+    cmd
+}
+
+/// Helper: initialized repository
+fn repo() -> TempDir {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    jk(dir.path()).arg("init").assert().success();
+    dir
+}
+
+#[test]
+fn timeline_follows_rename_and_restores() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one\ntwo\n").unwrap();
+
+    jk(base)
+        .args(["modify", "s/two/three/", "a.txt"])
+        .assert()
+        .success();
+    jk(base).args(["mv", "a.txt", "b.txt"]).assert().success();
+    jk(base)
+        .args(["modify", "s/one/uno/", "b.txt"])
+        .assert()
+        .success();
+
+    jk(base)
+        .args(["timeline", "b.txt"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("previously: a.txt"))
+        .stdout(predicate::str::contains("a.txt -> b.txt"));
+
+    jk(base)
+        .args(["timeline", "b.txt", "--diff", "1", "3"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("-two"))
+        .stdout(predicate::str::contains("+three"));
+
+    jk(base)
+        .args(["timeline", "b.txt", "--restore", "1"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(base.join("b.txt")).unwrap(),
+        "one\ntwo\n"
+    );
+
+    // Restoring is itself reversible
+    jk(base).arg("undo").assert().success();
+    assert_eq!(
+        fs::read_to_string(base.join("b.txt")).unwrap(),
+        "uno\nthree\n"
+    );
+}
+
+#[test]
+fn timeline_rejects_missing_entry() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "x").unwrap();
+    jk(base)
+        .args(["modify", "s/x/y/", "a.txt"])
+        .assert()
+        .success();
+
+    jk(base)
+        .args(["timeline", "a.txt", "--restore", "5"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No timeline entry 5"));
+}
+
+#[test]
+fn blame_credits_modify_across_rename() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one\ntwo\n").unwrap();
+    jk(base)
+        .args(["modify", "s/two/three/", "a.txt"])
+        .assert()
+        .success();
+    jk(base).args(["mv", "a.txt", "b.txt"]).assert().success();
+
+    let output = jk(base)
+        .args(["--json", "blame", "b.txt"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let lines: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(lines[0]["text"], "one");
+    assert!(lines[0]["operation"].is_null());
+    assert_eq!(lines[1]["text"], "three");
+    assert_eq!(lines[1]["operation"]["op_type"], "MODIFY");
+
+    jk(base)
+        .args(["blame", "b.txt"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2) three"));
+}
+
+#[test]
+fn history_follow_includes_prior_names() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("old.txt"), "v1").unwrap();
+
+    jk(base)
+        .args(["modify", "s/v1/v2/", "old.txt"])
+        .assert()
+        .success();
+    jk(base)
+        .args(["mv", "old.txt", "new.txt"])
+        .assert()
+        .success();
+
+    jk(base)
+        .args(["history", "--path", "new.txt"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("MODIFY").not());
+
+    jk(base)
+        .args(["history", "--path", "new.txt", "--follow"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("MODIFY"))
+        .stdout(predicate::str::contains("old.txt"));
+}
+
+#[test]
+fn history_export_signs_and_verifies_offline() {
+    use januskey::keys::{KeyAlgorithm, KeyManager, KeyPurpose};
+
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "evidence").unwrap();
+    jk(base)
+        .args(["modify", "s/evidence/redacted/", "a.txt"])
+        .assert()
+        .success();
+
+    let mut km = KeyManager::new(base);
+    km.init("correct horse battery").unwrap();
+    let key_id = km
+        .generate(KeyAlgorithm::Ed25519, KeyPurpose::Signing, None, None)
+        .unwrap();
+
+    jk(base)
+        .env("JANUSKEY_PASSPHRASE", "correct horse battery")
+        .args(["history", "export", "-o", "bundle.cbor", "--format", "cbor"])
+        .args(["--sign", &key_id.to_string()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Exported 1 operations"));
+
+    // Verification needs only the bundle
+    let elsewhere = tempfile::tempdir().unwrap();
+    let bundle = elsewhere.path().join("bundle.cbor");
+    fs::copy(base.join("bundle.cbor"), &bundle).unwrap();
+    jk(elsewhere.path())
+        .args(["history", "verify-export", "bundle.cbor"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("all signatures valid"))
+        .stdout(predicate::str::contains("MODIFY"));
+
+    // Any change to the signed content is detected
+    let mut bytes = fs::read(&bundle).unwrap();
+    let pos = bytes.windows(5).position(|w| w == b"a.txt").unwrap();
+    bytes[pos] = b'b';
+    fs::write(&bundle, bytes).unwrap();
+    jk(elsewhere.path())
+        .args(["history", "verify-export", "bundle.cbor"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn history_export_encrypts_to_recipients() {
+    use januskey::keys::{KeyAlgorithm, KeyManager, KeyPurpose};
+
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "evidence").unwrap();
+    jk(base)
+        .args(["modify", "s/evidence/redacted/", "a.txt"])
+        .assert()
+        .success();
+
+    let mut km = KeyManager::new(base);
+    km.init("correct horse battery").unwrap();
+    let signing = km
+        .generate(KeyAlgorithm::Ed25519, KeyPurpose::Signing, None, None)
+        .unwrap();
+    let agreement = km
+        .generate(KeyAlgorithm::X25519, KeyPurpose::Encryption, None, None)
+        .unwrap();
+    let recipient = hex::encode(km.public_key(agreement).unwrap());
+
+    jk(base)
+        .env("JANUSKEY_PASSPHRASE", "correct horse battery")
+        .args(["history", "export", "-o", "bundle.json"])
+        .args(["--sign", &signing.to_string(), "--recipient", &recipient])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Encrypted to 1 recipient(s)"));
+    let sealed = fs::read_to_string(base.join("bundle.json")).unwrap();
+    assert!(sealed.contains("januskey-envelope/1"));
+    assert!(!sealed.contains("a.txt"));
+
+    jk(base)
+        .args(["history", "verify-export", "bundle.json"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--identity"));
+    jk(base)
+        .env("JANUSKEY_PASSPHRASE", "correct horse battery")
+        .args(["history", "verify-export", "bundle.json"])
+        .args(["--identity", &agreement.to_string()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("all signatures valid"));
+    jk(base)
+        .env("JANUSKEY_PASSPHRASE", "correct horse battery")
+        .args(["history", "verify-export", "bundle.json"])
+        .args(["--identity", &signing.to_string()])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("not an X25519 key"));
+}
+
+#[test]
+fn age_exports_open_with_standard_age_identities() {
+    use januskey::keys::{KeyAlgorithm, KeyManager, KeyPurpose};
+    use std::str::FromStr;
+
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "evidence").unwrap();
+    jk(base)
+        .args(["modify", "s/evidence/redacted/", "a.txt"])
+        .assert()
+        .success();
+
+    let mut km = KeyManager::new(base);
+    km.init("correct horse battery").unwrap();
+    let signing = km
+        .generate(KeyAlgorithm::Ed25519, KeyPurpose::Signing, None, None)
+        .unwrap();
+    let agreement = km
+        .generate(KeyAlgorithm::X25519, KeyPurpose::Encryption, None, None)
+        .unwrap();
+
+    let output = jk(base)
+        .env("JANUSKEY_PASSPHRASE", "correct horse battery")
+        .args(["keys", "export-pub", &agreement.to_string(), "--age"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let recipient = String::from_utf8(output.stdout).unwrap().trim().to_string();
+    assert!(recipient.starts_with("age1"));
+    // An external age recipient, as from age-keygen
+    let outsider = age::x25519::Identity::generate();
+
+    jk(base)
+        .env("JANUSKEY_PASSPHRASE", "correct horse battery")
+        .args(["history", "export", "-o", "bundle.age", "--age"])
+        .args(["--sign", &signing.to_string(), "--recipient", &recipient])
+        .args(["--recipient", &outsider.to_public().to_string()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("as an age file"));
+    let sealed = fs::read(base.join("bundle.age")).unwrap();
+    assert!(sealed.starts_with(b"age-encryption.org/v1\n"));
+    let bundle = age::decrypt(&outsider, &sealed).unwrap();
+    assert!(String::from_utf8(bundle).unwrap().contains("a.txt"));
+    jk(base)
+        .env("JANUSKEY_PASSPHRASE", "correct horse battery")
+        .args(["history", "verify-export", "bundle.age"])
+        .args(["--identity", &agreement.to_string()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("all signatures valid"));
+
+    jk(base)
+        .env("JANUSKEY_PASSPHRASE", "correct horse battery")
+        .args(["keys", "backup", "-o", "keys.age", "--age"])
+        .args(["--recipient", &recipient])
+        .assert()
+        .success();
+    jk(base)
+        .env("JANUSKEY_PASSPHRASE", "correct horse battery")
+        .args(["keys", "export-identity", &agreement.to_string()])
+        .args(["-o", "identity.txt"])
+        .assert()
+        .success();
+    let identity_file = fs::read_to_string(base.join("identity.txt")).unwrap();
+    assert!(identity_file.contains(&format!("# public key: {}", recipient)));
+    let identity = identity_file
+        .lines()
+        .find(|line| line.starts_with("AGE-SECRET-KEY-1"))
+        .unwrap();
+    let identity = age::x25519::Identity::from_str(identity).unwrap();
+    let backup = age::decrypt(&identity, &fs::read(base.join("keys.age")).unwrap()).unwrap();
+    assert_eq!(
+        backup,
+        fs::read(base.join(".januskey/keys/keystore.jks")).unwrap()
+    );
+
+    jk(base)
+        .args(["keys", "backup", "-o", "plain.age", "--age"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--recipient"));
+    jk(base)
+        .env("JANUSKEY_PASSPHRASE", "correct horse battery")
+        .args(["keys", "export-pub", &signing.to_string(), "--age"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("not an X25519 key"));
+}
+
+#[test]
+fn sign_and_verify_detached_signature() {
+    use januskey::keys::{KeyAlgorithm, KeyManager, KeyPurpose};
+
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("release.tar"), "payload").unwrap();
+
+    let mut km = KeyManager::new(base);
+    km.init("correct horse battery").unwrap();
+    let signing = km
+        .generate(KeyAlgorithm::Ed25519, KeyPurpose::Signing, None, None)
+        .unwrap();
+    let public_key = hex::encode(km.public_key(signing).unwrap());
+
+    jk(base)
+        .env("JANUSKEY_PASSPHRASE", "correct horse battery")
+        .args(["sign", "release.tar", "--key", &signing.to_string()])
+        .assert()
+        .success();
+    assert!(base.join("release.tar.sig").exists());
+
+    jk(base)
+        .args([
+            "verify",
+            "release.tar",
+            "release.tar.sig",
+            "--key",
+            &public_key,
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Good signature"));
+
+    fs::write(base.join("release.tar"), "tampered").unwrap();
+    jk(base)
+        .args(["verify", "release.tar", "release.tar.sig"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("does not match"));
+
+    // Signing is recorded, so undo removes the signature file
+    jk(base).arg("undo").assert().success();
+    assert!(!base.join("release.tar.sig").exists());
+}
+
+#[test]
+fn undo_and_rollback_dry_run_change_nothing() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one").unwrap();
+
+    jk(base).arg("begin").assert().success();
+    jk(base)
+        .args(["modify", "s/one/two/", "a.txt"])
+        .assert()
+        .success();
+    jk(base).args(["mv", "a.txt", "b.txt"]).assert().success();
+
+    jk(base)
+        .args(["--dry-run", "undo"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("back to"));
+    jk(base)
+        .args(["--dry-run", "rollback"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("would undo 2 operations"))
+        .stdout(predicate::str::contains("restore previous content"));
+    assert!(!base.join("a.txt").exists());
+    assert_eq!(fs::read_to_string(base.join("b.txt")).unwrap(), "two");
+
+    // The transaction is still active and rolls back for real
+    jk(base).arg("rollback").assert().success();
+    assert_eq!(fs::read_to_string(base.join("a.txt")).unwrap(), "one");
+}
+
+#[test]
+fn gc_dry_run_lists_operations_and_blobs() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one").unwrap();
+    jk(base)
+        .args(["modify", "s/one/two/", "a.txt"])
+        .assert()
+        .success();
+
+    jk(base)
+        .args(["--dry-run", "gc", "--keep", "0"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("would prune 1 operations"))
+        .stdout(predicate::str::contains(
+            "would delete 1 unreferenced blobs",
+        ));
+    jk(base)
+        .arg("history")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("MODIFY"));
+}
+
+#[test]
+fn gc_compact_compresses_logs() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one").unwrap();
+    jk(base).arg("begin").assert().success();
+    jk(base)
+        .args(["modify", "s/one/two/", "a.txt"])
+        .assert()
+        .success();
+    jk(base).arg("commit").assert().success();
+
+    jk(base)
+        .args(["gc", "--compact"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Compressed 1 log files"));
+    let log = fs::read(base.join(".januskey").join("transactions")).unwrap();
+    assert!(log.starts_with(&januskey::framing::ZSTD_MAGIC));
+    jk(base)
+        .args(["gc", "--compact"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Logs already compressed"));
+    jk(base).arg("undo").assert().success();
+    assert_eq!(fs::read_to_string(base.join("a.txt")).unwrap(), "one");
+}
+
+#[test]
+fn gc_older_than_prunes_by_age() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one").unwrap();
+    jk(base)
+        .args(["modify", "s/one/two/", "a.txt"])
+        .assert()
+        .success();
+
+    jk(base)
+        .args(["--dry-run", "gc", "--older-than", "30d"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Nothing to prune"));
+    jk(base)
+        .args(["--dry-run", "gc", "--older-than", "0d"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("would prune 1 operations"));
+    jk(base)
+        .args(["gc", "--older-than", "0d", "--keep-latest"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Nothing to prune"));
+    jk(base)
+        .args(["gc", "--older-than", "soon"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("expected e.g. 30d"));
+}
+
+#[test]
+fn compact_keeps_history() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one").unwrap();
+    jk(base)
+        .args(["modify", "s/one/two/", "a.txt"])
+        .assert()
+        .success();
+    jk(base).arg("undo").assert().success();
+
+    jk(base)
+        .arg("compact")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("(2 operations)"));
+    jk(base)
+        .arg("history")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[UNDONE]"));
+}
+
+#[test]
+fn compact_packs_blobs_and_undo_reads_them() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one").unwrap();
+    jk(base)
+        .args(["modify", "s/one/two/", "a.txt"])
+        .assert()
+        .success();
+
+    jk(base)
+        .arg("compact")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Packed 1 small blobs"));
+    assert!(base.join(".januskey/content/pack").is_dir());
+
+    jk(base).arg("undo").assert().success();
+    assert_eq!(fs::read_to_string(base.join("a.txt")).unwrap(), "one");
+}
+
+#[test]
+fn move_glob_sources_into_directory() {
+    let dir = repo();
+    let base = dir.path();
+    fs::create_dir(base.join("logs")).unwrap();
+    for name in ["a.log", "b.log", "keep.txt"] {
+        fs::write(base.join(name), name).unwrap();
+    }
+
+    jk(base)
+        .args(["mv", "*.log", "logs"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("jk undo --count 2"));
+    assert_eq!(
+        fs::read_to_string(base.join("logs/a.log")).unwrap(),
+        "a.log"
+    );
+    assert!(base.join("logs/b.log").exists());
+    assert!(base.join("keep.txt").exists());
+
+    jk(base)
+        .args(["cp", "keep.txt", "logs/a.log", "missing-dir"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is not a directory"));
+}
+
+#[test]
+fn copy_partial_failure_rolls_back() {
+    let dir = repo();
+    let base = dir.path();
+    fs::create_dir(base.join("out")).unwrap();
+    fs::write(base.join("a.txt"), "a").unwrap();
+    fs::write(base.join("b.txt"), "b").unwrap();
+    fs::write(base.join("out/b.txt"), "already here").unwrap();
+
+    jk(base)
+        .args(["cp", "a.txt", "b.txt", "out"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "Rolled back 1 completed operations",
+        ));
+    assert!(!base.join("out/a.txt").exists());
+    assert_eq!(
+        fs::read_to_string(base.join("out/b.txt")).unwrap(),
+        "already here"
+    );
+    jk(base).arg("status").assert().success().stdout(
+        predicate::str::contains("No active transaction").or(predicate::str::contains("None")),
+    );
+}
+
+#[test]
+fn verify_restore_reports_checked_undos() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "original").unwrap();
+
+    jk(base)
+        .args(["verify-restore"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No undos have restored content"));
+
+    jk(base)
+        .args(["modify", "s/original/changed/", "a.txt"])
+        .assert()
+        .success();
+    jk(base)
+        .arg("undo")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "restored content verified: sha256:",
+        ));
+    assert_eq!(fs::read_to_string(base.join("a.txt")).unwrap(), "original");
+
+    jk(base)
+        .args(["verify-restore"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 of 1 restoring undos verified"));
+    jk(base)
+        .args(["verify-restore", "no-such-id"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn modify_runs_script_file() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one\ntwo\nTODO three\n").unwrap();
+    fs::write(
+        base.join("edit.jk"),
+        "# tidy up\ns/one/uno/\n/TODO/d\n$a tres\n1i header\n",
+    )
+    .unwrap();
+
+    jk(base)
+        .args(["modify", "edit.jk", "a.txt"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(base.join("a.txt")).unwrap(),
+        "header\nuno\ntwo\ntres\n"
+    );
+
+    // The whole script is one operation
+    jk(base).arg("undo").assert().success();
+    assert_eq!(
+        fs::read_to_string(base.join("a.txt")).unwrap(),
+        "one\ntwo\nTODO three\n"
+    );
+
+    fs::write(base.join("bad.jk"), "s/a/b/\n2x\n").unwrap();
+    jk(base)
+        .args(["modify", "bad.jk", "a.txt"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("line 2"));
+}
+
+#[test]
+fn modify_stdin_replaces_content() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "old\n").unwrap();
+
+    jk(base)
+        .args(["modify", "--stdin", "a.txt"])
+        .write_stdin("new\n")
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(base.join("a.txt")).unwrap(), "new\n");
+
+    jk(base).arg("undo").assert().success();
+    assert_eq!(fs::read_to_string(base.join("a.txt")).unwrap(), "old\n");
+}
+
+#[test]
+fn full_store_refuses_unless_no_history() {
+    let dir = repo();
+    let base = dir.path();
+    let config_path = base.join(".januskey/config.json");
+    let mut config: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&config_path).unwrap()).unwrap();
+    config["max_store_bytes"] = 10.into();
+    fs::write(&config_path, config.to_string()).unwrap();
+    fs::write(base.join("a.txt"), "one\n".repeat(100)).unwrap();
+
+    jk(base)
+        .args(["modify", "s/one/two/g", "a.txt"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--no-history"));
+    assert!(fs::read_to_string(base.join("a.txt"))
+        .unwrap()
+        .starts_with("one"));
+
+    jk(base)
+        .args(["--no-history", "modify", "s/one/two/g", "a.txt"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("cannot be undone"));
+    assert!(fs::read_to_string(base.join("a.txt"))
+        .unwrap()
+        .starts_with("two"));
+    jk(base)
+        .arg("undo")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Nothing to undo"));
+}
+
+#[test]
+fn tx_list_and_show() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one\n").unwrap();
+
+    jk(base).args(["begin", "release"]).assert().success();
+    jk(base)
+        .args(["modify", "s/one/two/", "a.txt"])
+        .assert()
+        .success();
+    jk(base).arg("commit").assert().success();
+
+    jk(base)
+        .args(["tx", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("committed"))
+        .stdout(predicate::str::contains("release"));
+
+    let output = jk(base).args(["--json", "tx", "list"]).output().unwrap();
+    let listed: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let id = listed[0]["id"].as_str().unwrap().to_string();
+    assert_eq!(listed[0]["state"], "Committed");
+
+    jk(base)
+        .args(["tx", "show", &id[..8]])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("MODIFY"))
+        .stdout(predicate::str::contains("a.txt"));
+
+    let output = jk(base)
+        .args(["--json", "tx", "show", &id])
+        .output()
+        .unwrap();
+    let shown: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(shown["operations"].as_array().unwrap().len(), 1);
+
+    jk(base).args(["tx", "show", "zzzz"]).assert().failure();
+}
+
+#[test]
+fn rollback_resume_after_failure() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one\n").unwrap();
+    fs::write(base.join("b.txt"), "bee\n").unwrap();
+
+    jk(base).arg("begin").assert().success();
+    jk(base)
+        .args(["modify", "s/one/two/", "a.txt"])
+        .assert()
+        .success();
+    jk(base).args(["mv", "b.txt", "c.txt"]).assert().success();
+
+    // b.txt reappears, so the move cannot be undone
+    fs::write(base.join("b.txt"), "squatter\n").unwrap();
+    jk(base)
+        .arg("rollback")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("jk rollback --resume"));
+    assert_eq!(fs::read_to_string(base.join("a.txt")).unwrap(), "one\n");
+    jk(base)
+        .args(["tx", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("partially rolled back"));
+
+    fs::remove_file(base.join("b.txt")).unwrap();
+    jk(base).args(["rollback", "--resume"]).assert().success();
+    assert_eq!(fs::read_to_string(base.join("b.txt")).unwrap(), "bee\n");
+    assert!(!base.join("c.txt").exists());
+}
+
+#[test]
+fn modify_skips_excluded_paths() {
+    let dir = repo();
+    let base = dir.path();
+    fs::create_dir(base.join("vendor")).unwrap();
+    fs::write(base.join("a.txt"), "one\n").unwrap();
+    fs::write(base.join("b.txt"), "one\n").unwrap();
+    fs::write(base.join("vendor/c.txt"), "one\n").unwrap();
+
+    jk(base)
+        .args([
+            "--dry-run",
+            "modify",
+            "s/one/two/",
+            "**/*.txt",
+            "--exclude",
+            "b.txt",
+            "--exclude",
+            "vendor/",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("a.txt"))
+        .stdout(predicate::str::contains("Excluded (2)"));
+
+    jk(base)
+        .args(["modify", "s/one/two/", "**/*.txt", "--exclude", "b.txt"])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(base.join("a.txt")).unwrap(), "two\n");
+    assert_eq!(fs::read_to_string(base.join("b.txt")).unwrap(), "one\n");
+    assert_eq!(
+        fs::read_to_string(base.join("vendor/c.txt")).unwrap(),
+        "two\n"
+    );
+}
+
+#[test]
+fn heal_recovers_damaged_log() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one\n").unwrap();
+    jk(base)
+        .args(["modify", "s/one/two/", "a.txt"])
+        .assert()
+        .success();
+
+    let segment = base.join(".januskey/metadata/00000001.jsonl");
+    let mut log = fs::read_to_string(&segment).unwrap();
+    log.push_str("{garbage\n");
+    fs::write(&segment, log).unwrap();
+    jk(base).arg("history").assert().failure();
+
+    jk(base)
+        .arg("heal")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 unreadable log lines"))
+        .stdout(predicate::str::contains("1 operations recovered"));
+    jk(base).arg("history").assert().success();
+    jk(base)
+        .arg("heal")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("History is intact"));
+}
+
+#[test]
+fn scheduled_obliteration_runs_when_due() {
+    let dir = repo();
+    let base = dir.path();
+    fs::create_dir(base.join("pii")).unwrap();
+    fs::write(base.join("pii/alice.txt"), "alice's address").unwrap();
+    januskey::JanusKey::open(base)
+        .unwrap()
+        .delete("pii/alice.txt")
+        .unwrap();
+
+    jk(base)
+        .args(["obliterate", "--schedule", "0d", "--path", "pii/**"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 pending, 1 due"));
+    jk(base)
+        .args(["--dry-run", "obliterate", "--run-due"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("pii/alice.txt"))
+        .stdout(predicate::str::contains("no longer undoable"))
+        .stdout(predicate::str::contains(
+            "1 operation(s) could no longer be undone",
+        ));
+    let output = jk(base)
+        .args(["--json", "--dry-run", "obliterate", "--run-due"])
+        .output()
+        .unwrap();
+    let impacts: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(impacts[0]["lost_undos"].as_array().unwrap().len(), 1);
+    assert!(impacts[0]["stored_bytes"].as_u64().unwrap() > 0);
+    jk(base)
+        .args(["obliterate", "--run-due"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("refusing to obliterate"));
+
+    jk(base)
+        .args(["-y", "obliterate", "--run-due"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Obliterated"));
+    assert!(base.join(".januskey/obliterations.json").exists());
+    jk(base)
+        .args(["obliterate", "--pending"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Nothing pending"));
+    jk(base)
+        .arg("undo")
+        .assert()
+        .stderr(predicate::str::contains("Failed to undo"));
+    assert!(!base.join("pii/alice.txt").exists());
+}
+
+#[test]
+fn obliteration_keeps_deltas_stored_against_the_content() {
+    let dir = repo();
+    let base = dir.path();
+    fs::create_dir(base.join("pii")).unwrap();
+    let original = "a line of personal data\n".repeat(400);
+    let mut edited = original.clone();
+    edited.replace_range(0..1, "A");
+    fs::write(base.join("pii/alice.txt"), &original).unwrap();
+    let mut store = januskey::JanusKey::open(base).unwrap();
+    store.delete("pii/alice.txt").unwrap();
+    fs::write(base.join("pii/alice.txt"), &edited).unwrap();
+    let modify = store.modify("pii/alice.txt", "redacted").unwrap();
+    assert!(modify.delta_base.is_some());
+    drop(store);
+
+    jk(base)
+        .args(["obliterate", "--schedule", "0d", "--path", "pii/**"])
+        .assert()
+        .success();
+    jk(base)
+        .args(["--dry-run", "obliterate", "--run-due"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("block it (see --dependents)"));
+    jk(base)
+        .args(["-y", "obliterate", "--run-due"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Deltas stored against"));
+
+    jk(base)
+        .args([
+            "-y",
+            "obliterate",
+            "--run-due",
+            "--dependents",
+            "materialize",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Obliterated"));
+    jk(base).arg("undo").assert().success();
+    assert_eq!(
+        fs::read_to_string(base.join("pii/alice.txt")).unwrap(),
+        edited
+    );
+}
+
+#[test]
+fn legal_hold_blocks_gc_and_obliterate() {
+    let dir = repo();
+    let base = dir.path();
+    fs::create_dir(base.join("contracts")).unwrap();
+    fs::write(base.join("contracts/acme.txt"), "one").unwrap();
+    jk(base)
+        .args(["modify", "s/one/two/", "contracts/acme.txt"])
+        .assert()
+        .success();
+
+    jk(base)
+        .args([
+            "hold",
+            "add",
+            "--path",
+            "contracts/**",
+            "--case",
+            "ACME-123",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 operations held"));
+    jk(base)
+        .args(["--dry-run", "gc", "--keep", "0"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("under legal hold"));
+    jk(base)
+        .args(["-y", "obliterate", "contracts/acme.txt"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("legal hold for case ACME-123"));
+    assert!(base.join("contracts/acme.txt").exists());
+
+    jk(base)
+        .args(["hold", "release", "ACME-123"])
+        .assert()
+        .success();
+    jk(base)
+        .args(["hold", "list", "--all"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("released"));
+}
+
+#[test]
+fn snapshot_restores_tree_and_survives_gc() {
+    let dir = repo();
+    let base = dir.path();
+    fs::create_dir(base.join("src")).unwrap();
+    fs::write(base.join("src/main.rs"), "fn main() {}").unwrap();
+    fs::write(base.join("notes.txt"), "keep me").unwrap();
+    fs::write(base.join("build.log"), "noise").unwrap();
+    fs::write(base.join(".januskeyignore"), "# build output\n*.log\n").unwrap();
+
+    jk(base)
+        .args(["snapshot", "base"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("3 files"));
+    jk(base)
+        .args(["snapshot", "base"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already exists"));
+
+    fs::write(base.join("src/main.rs"), "fn main() { panic!() }").unwrap();
+    fs::write(base.join("extra.txt"), "new").unwrap();
+    januskey::JanusKey::open(base)
+        .unwrap()
+        .delete("notes.txt")
+        .unwrap();
+    fs::write(base.join("build.log"), "more noise").unwrap();
+    jk(base)
+        .args(["-y", "gc", "--keep", "0"])
+        .assert()
+        .success();
+
+    jk(base)
+        .args(["restore-snapshot", "base"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("jk undo --count 3"));
+    assert_eq!(
+        fs::read_to_string(base.join("src/main.rs")).unwrap(),
+        "fn main() {}"
+    );
+    assert_eq!(
+        fs::read_to_string(base.join("notes.txt")).unwrap(),
+        "keep me"
+    );
+    assert!(!base.join("extra.txt").exists());
+    // Ignored files are left alone
+    assert_eq!(
+        fs::read_to_string(base.join("build.log")).unwrap(),
+        "more noise"
+    );
+    jk(base)
+        .args(["restore-snapshot", "base"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("already matches"));
+
+    jk(base).args(["undo", "--count", "3"]).assert().success();
+    assert!(base.join("extra.txt").exists());
+    assert!(!base.join("notes.txt").exists());
+    jk(base)
+        .args(["snapshot", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("base"));
+    jk(base)
+        .args(["restore-snapshot", "missing"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Snapshot not found"));
+}
+
+#[test]
+fn incremental_snapshot_diff() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one").unwrap();
+    fs::write(base.join("b.txt"), "two").unwrap();
+    jk(base).args(["snapshot", "v1"]).assert().success();
+
+    fs::write(base.join("a.txt"), "one more").unwrap();
+    fs::remove_file(base.join("b.txt")).unwrap();
+    fs::write(base.join("c.txt"), "three").unwrap();
+    jk(base)
+        .args(["snapshot", "--incremental", "v2"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("3 changed since v1"));
+
+    jk(base)
+        .args(["snapshot", "diff", "v1", "v2"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("+ c.txt (5 bytes)"))
+        .stdout(predicate::str::contains("- b.txt (3 bytes)"))
+        .stdout(predicate::str::contains("~ a.txt (3 bytes -> 8 bytes)"))
+        .stdout(predicate::str::contains("1 added, 1 removed, 1 modified"));
+
+    jk(base).args(["restore-snapshot", "v1"]).assert().success();
+    assert_eq!(fs::read_to_string(base.join("b.txt")).unwrap(), "two");
+    assert!(!base.join("c.txt").exists());
+}
+
+#[test]
+fn undo_by_id_refuses_dependents_unless_cascading() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one\n").unwrap();
+
+    jk(base)
+        .args(["modify", "s/one/two/", "a.txt"])
+        .assert()
+        .success();
+    jk(base).args(["mv", "a.txt", "b.txt"]).assert().success();
+
+    let log = fs::read_to_string(base.join(".januskey/metadata/00000001.jsonl")).unwrap();
+    let first: serde_json::Value = serde_json::from_str(log.lines().next().unwrap()).unwrap();
+    let modify = first["append"]["id"].as_str().unwrap().to_string();
+
+    jk(base)
+        .args(["undo", "--id", &modify])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Later operations depend on"))
+        .stderr(predicate::str::contains("MOVE"));
+    assert_eq!(fs::read_to_string(base.join("b.txt")).unwrap(), "two\n");
+
+    jk(base)
+        .args(["undo", "--id", &modify, "--cascade"])
+        .assert()
+        .success();
+    assert!(!base.join("b.txt").exists());
+    assert_eq!(fs::read_to_string(base.join("a.txt")).unwrap(), "one\n");
+}
+
+#[test]
+fn history_shows_short_ids_that_undo_accepts() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one\n").unwrap();
+    jk(base)
+        .args(["modify", "s/one/two/", "a.txt"])
+        .assert()
+        .success();
+
+    let log = fs::read_to_string(base.join(".januskey/metadata/00000001.jsonl")).unwrap();
+    let first: serde_json::Value = serde_json::from_str(log.lines().next().unwrap()).unwrap();
+    let id = first["append"]["id"].as_str().unwrap().to_string();
+
+    let output = jk(base).arg("history").output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(&format!("{} |", &id[..8])));
+    assert!(!stdout.contains(&id));
+    jk(base)
+        .args(["history", "--full-ids"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(&id));
+
+    jk(base).args(["undo", "--id", &id[..6]]).assert().success();
+    assert_eq!(fs::read_to_string(base.join("a.txt")).unwrap(), "one\n");
+    jk(base)
+        .args(["verify-restore", &id[..6]])
+        .assert()
+        .success();
+}
+
+#[test]
+fn history_stat_and_stats_account_stored_bytes() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one\n".repeat(1000)).unwrap();
+    jk(base).args(["delete", "a.txt"]).assert().success();
+
+    jk(base)
+        .args(["history", "--stat"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("3.91 KB"))
+        .stdout(predicate::str::contains("%"));
+
+    let output = jk(base).args(["--json", "stats"]).output().unwrap();
+    let stats: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(stats["total"]["operations"], 1);
+    assert_eq!(stats["total"]["read"], 4000);
+    assert_eq!(stats["by_type"]["DELETE"]["operations"], 1);
+    assert!(stats["by_path"][0]["path"]
+        .as_str()
+        .unwrap()
+        .ends_with("a.txt"));
+
+    jk(base)
+        .arg("stats")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Largest by stored bytes"));
+}
+
+#[test]
+fn show_prints_the_context_an_operation_ran_in() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one\n").unwrap();
+    jk(base)
+        .args(["config", "set", "context.environment", "true"])
+        .assert()
+        .success();
+    jk(base)
+        .args(["modify", "s/one/two/", "a.txt"])
+        .assert()
+        .success();
+
+    let log = fs::read_to_string(base.join(".januskey/metadata/00000001.jsonl")).unwrap();
+    let first: serde_json::Value = serde_json::from_str(log.lines().next().unwrap()).unwrap();
+    let id = first["append"]["id"].as_str().unwrap().to_string();
+
+    let output = jk(base)
+        .args(["--json", "show", &id[..8]])
+        .output()
+        .unwrap();
+    let shown: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let context = &shown["context"];
+    assert!(context["pid"].as_u64().is_some());
+    assert_eq!(context["environment"].as_str().unwrap().len(), 64);
+    assert_eq!(
+        Path::new(context["cwd"].as_str().unwrap())
+            .canonicalize()
+            .unwrap(),
+        base.canonicalize().unwrap()
+    );
+
+    jk(base)
+        .args(["show", &id[..8]])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Process:"))
+        .stdout(predicate::str::contains("Directory:"));
+
+    // With capture off, nothing is recorded
+    for part in ["hostname", "process", "cwd", "environment"] {
+        jk(base)
+            .args(["config", "set", &format!("context.{}", part), "false"])
+            .assert()
+            .success();
+    }
+    jk(base).arg("undo").assert().success();
+    let log = fs::read_to_string(base.join(".januskey/metadata/00000001.jsonl")).unwrap();
+    let undo: serde_json::Value = serde_json::from_str(log.lines().nth(1).unwrap()).unwrap();
+    assert!(undo["append"].get("context").is_none());
+}
+
+#[test]
+fn history_graph_shows_transactions_undos_and_renames() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one\n").unwrap();
+
+    jk(base).args(["begin", "tidy"]).assert().success();
+    jk(base).args(["mv", "a.txt", "b.txt"]).assert().success();
+    jk(base).args(["mv", "b.txt", "c.txt"]).assert().success();
+    jk(base).arg("commit").assert().success();
+    jk(base)
+        .args(["modify", "s/one/two/", "c.txt"])
+        .assert()
+        .success();
+    jk(base).arg("undo").assert().success();
+
+    jk(base)
+        .args(["history", "--graph"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("┌─ transaction"))
+        .stdout(predicate::str::contains("\"tidy\" (committed)"))
+        .stdout(predicate::str::contains("renamed after"))
+        .stdout(predicate::str::contains("undone by"));
+
+    jk(base)
+        .args(["history", "--graph", "dot"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("digraph history {"))
+        .stdout(predicate::str::contains("subgraph \"cluster_"))
+        .stdout(predicate::str::contains("label=\"undo\""))
+        .stdout(predicate::str::contains("label=\"rename\""));
+}
+
+#[test]
+fn keys_subcommand_shares_dir_and_json_flags() {
+    use januskey::keys::{KeyAlgorithm, KeyManager, KeyPurpose};
+
+    let dir = repo();
+    let base = dir.path();
+    let mut km = KeyManager::new(base);
+    km.init("correct horse battery").unwrap();
+    let key_id = km
+        .generate(KeyAlgorithm::Ed25519, KeyPurpose::Signing, None, None)
+        .unwrap();
+
+    let elsewhere = tempfile::tempdir().unwrap();
+    let output = jk(elsewhere.path())
+        .env("JANUSKEY_PASSPHRASE", "correct horse battery")
+        .args(["-C", base.to_str().unwrap(), "--json", "keys", "list"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let keys: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(keys[0]["id"], key_id.to_string());
+
+    jk(base)
+        .args(["keys", "status", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"initialized\": true"));
+
+    // The standalone binary still works, with a deprecation warning
+    Command::cargo_bin("jk-keys")
+        .unwrap()
+        .current_dir(base)
+        .arg("status")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Initialized"))
+        .stderr(predicate::str::contains("use `jk keys`"));
+}
+
+#[test]
+fn keys_init_on_a_token_fails_cleanly_without_one() {
+    let dir = repo();
+    let base = dir.path();
+
+    // Either this build lacks the `pkcs11` feature or the module is missing;
+    // both fail before a store is written
+    jk(base)
+        .env("JANUSKEY_PIN", "123456")
+        .args(["keys", "init", "--pkcs11", "/nonexistent/libpkcs11.so"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("pkcs11").or(predicate::str::contains("token")));
+    assert!(!base.join(".januskey/keys/keystore.jks").exists());
+    assert!(!base.join(".januskey/keys/token.json").exists());
+
+    jk(base)
+        .args(["keys", "init", "--token", "YubiKey"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--pkcs11"));
+}
+
+#[test]
+fn keys_fall_back_to_the_passphrase_when_the_tpm_seal_fails() {
+    use januskey::keys::KeyManager;
+
+    let dir = repo();
+    let base = dir.path();
+    KeyManager::new(base).init("correct horse battery").unwrap();
+
+    jk(base)
+        .env("JANUSKEY_PASSPHRASE", "correct horse battery")
+        .args(["keys", "tpm", "seal", "--pcrs", "sha256:24"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid PCR selection"));
+    assert!(!base.join(".januskey/keys/tpm.json").exists());
+
+    // A seal that cannot be unsealed here, as on another machine
+    fs::write(
+        base.join(".januskey/keys/tpm.json"),
+        r#"{"pcrs": "sha256:7", "public": "00", "private": "00"}"#,
+    )
+    .unwrap();
+    jk(base)
+        .env("JANUSKEY_PASSPHRASE", "correct horse battery")
+        .args(["keys", "list"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("falling back to the passphrase"));
+
+    jk(base)
+        .args(["keys", "tpm", "remove"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Removed the TPM seal"));
+    assert!(!base.join(".januskey/keys/tpm.json").exists());
+}
+
+#[test]
+fn backup_restores_store_into_another_directory() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "keep me\n").unwrap();
+    jk(base)
+        .args(["modify", "s/keep/lose/", "a.txt"])
+        .assert()
+        .success();
+
+    let out = tempfile::tempdir().unwrap();
+    let archive = out.path().join("store.tar.zst");
+    jk(base)
+        .args(["backup", "--output", archive.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Backed up"));
+
+    let target = tempfile::tempdir().unwrap();
+    jk(target.path())
+        .args(["--dry-run", "restore-store", archive.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("verified"));
+    assert!(!target.path().join(".januskey").exists());
+
+    jk(target.path())
+        .args(["restore-store", archive.to_str().unwrap()])
+        .assert()
+        .success();
+    jk(target.path())
+        .args(["restore-store", archive.to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--force"));
+
+    fs::write(target.path().join("a.txt"), "lose me\n").unwrap();
+    jk(target.path()).arg("undo").assert().success();
+    assert_eq!(
+        fs::read_to_string(target.path().join("a.txt")).unwrap(),
+        "keep me\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn daemon_serves_cli_commands_and_refuses_direct_writes() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one\n").unwrap();
+
+    let mut daemon = std::process::Command::new(assert_cmd::cargo::cargo_bin("jk"))
+        .current_dir(base)
+        .arg("daemon")
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let socket = base.join(".januskey").join("daemon.sock");
+    for _ in 0..100 {
+        if socket.exists() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+
+    jk(base)
+        .args(["modify", "s/one/two/", "a.txt"])
+        .assert()
+        .success();
+    jk(base)
+        .arg("status")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Served by: jk daemon"))
+        .stdout(predicate::str::contains("Operations logged: 1"));
+    jk(base)
+        .arg("snapshot")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("jk daemon --stop"));
+    jk(base).arg("undo").assert().success();
+    assert_eq!(fs::read_to_string(base.join("a.txt")).unwrap(), "one\n");
+
+    jk(base).args(["daemon", "--stop"]).assert().success();
+    assert!(daemon.wait().unwrap().success());
+    jk(base)
+        .arg("status")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Served by").not());
+}
+
+#[test]
+fn search_finds_overwritten_content() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join(".env"), "USER=app\nAPI_KEY=sk-live-4f9a\n").unwrap();
+    fs::write(base.join("notes.txt"), "nothing to see\n").unwrap();
+    jk(base)
+        .args(["modify", "s/sk-live-4f9a/REDACTED/", ".env"])
+        .assert()
+        .success();
+    jk(base)
+        .args(["modify", "s/nothing/something/", "notes.txt"])
+        .assert()
+        .success();
+
+    jk(base)
+        .args(["search", "sk-live-4f9a"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(".env (before)"))
+        .stdout(predicate::str::contains("2: API_KEY=sk-live-4f9a"))
+        .stdout(predicate::str::contains("notes.txt").not());
+
+    jk(base)
+        .args(["search", "--reindex", "--regex", "-i", r"api_key=sk-\w+"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Indexed 2 new blobs"))
+        .stdout(predicate::str::contains("1 matching versions"));
+    assert!(base.join(".januskey/search-index").is_dir());
+
+    let output = jk(base)
+        .args(["--json", "search", "nothing"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let hits: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(hits.as_array().unwrap().len(), 1);
+    assert_eq!(hits[0]["version"], "before");
+    assert_eq!(hits[0]["lines"][0]["line"], 1);
+}
+
+#[test]
+fn index_rebuild_and_status() {
+    let dir = repo();
+    let base = dir.path();
+    jk(base)
+        .args(["index", "status"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No content index"));
+
+    fs::write(base.join("a.txt"), "alpha\n").unwrap();
+    jk(base)
+        .args(["modify", "s/alpha/bravo/", "a.txt"])
+        .assert()
+        .success();
+    jk(base)
+        .args(["index", "rebuild"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Indexed 1 stored blobs"))
+        .stdout(predicate::str::contains("search_index is off"));
+
+    let output = jk(base)
+        .args(["--json", "index", "status"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let status: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(status["indexed"], 1);
+    assert_eq!(status["missing"], 0);
+    assert_eq!(status["built"], true);
+    assert_eq!(status["maintained"], false);
+}
+
+#[test]
+fn tier_offloads_and_undo_fetches_back() {
+    let dir = repo();
+    let base = dir.path();
+    let cold = tempfile::tempdir().unwrap();
+    let config_path = base.join(".januskey/config.json");
+    let mut config: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&config_path).unwrap()).unwrap();
+    config["tiering"] = serde_json::json!({
+        "storage": cold.path(),
+        "larger_than_bytes": 0,
+    });
+    fs::write(&config_path, config.to_string()).unwrap();
+    fs::write(base.join("a.txt"), "original\n").unwrap();
+    jk(base)
+        .args(["modify", "s/original/changed/", "a.txt"])
+        .assert()
+        .success();
+
+    jk(base)
+        .args(["--dry-run", "tier"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Would offload 1 blobs"));
+    jk(base)
+        .arg("tier")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Offloaded 1 blobs"));
+    jk(base)
+        .arg("status")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Offloaded: 1 blobs"));
+
+    jk(base).args(["undo", "-y"]).assert().success();
+    assert_eq!(
+        fs::read_to_string(base.join("a.txt")).unwrap(),
+        "original\n"
+    );
+
+    jk(base)
+        .args(["tier", "--recall"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Recalled 1 blobs"));
+}
+
+#[test]
+fn path_policies_skip_confirmation_and_history() {
+    let dir = repo();
+    let base = dir.path();
+    let config_path = base.join(".januskey/config.json");
+    let mut config: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&config_path).unwrap()).unwrap();
+    config["policies"] = serde_json::json!([
+        { "pattern": "docs/**", "auto_confirm": true },
+        { "pattern": "build/**", "history": false },
+    ]);
+    fs::write(&config_path, config.to_string()).unwrap();
+    for dir in ["docs", "build"] {
+        fs::create_dir_all(base.join(dir)).unwrap();
+        for i in 0..6 {
+            fs::write(base.join(format!("{}/{}.txt", dir, i)), "draft\n").unwrap();
+        }
+    }
+
+    // Six files would prompt, and there is no terminal to answer on
+    jk(base)
+        .args(["modify", "s/draft/final/", "build/*.txt"])
+        .assert()
+        .failure();
+    jk(base)
+        .args(["modify", "s/draft/final/", "docs/*.txt"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(base.join("docs/5.txt")).unwrap(),
+        "final\n"
+    );
+
+    jk(base)
+        .args(["-y", "modify", "s/draft/final/", "build/*.txt"])
+        .assert()
+        .success();
+    jk(base)
+        .arg("history")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("docs/0.txt"))
+        .stdout(predicate::str::contains("build/").not());
+}
+
+#[test]
+fn confirmation_policy_thresholds_and_lists() {
+    let dir = repo();
+    let base = dir.path();
+    let config_path = base.join(".januskey/config.json");
+    let mut config: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&config_path).unwrap()).unwrap();
+    config["confirmation"] = serde_json::json!({
+        "max_files": { "MODIFY": 1 },
+        "always": ["keep.txt"],
+        "never": ["scratch/**"],
+    });
+    fs::write(&config_path, config.to_string()).unwrap();
+    fs::create_dir_all(base.join("scratch")).unwrap();
+    for name in [
+        "a.txt",
+        "b.txt",
+        "keep.txt",
+        "scratch/1.txt",
+        "scratch/2.txt",
+    ] {
+        fs::write(base.join(name), "draft\n").unwrap();
+    }
+
+    // Each would prompt, and there is no terminal to answer on
+    for path in ["*.txt", "keep.txt"] {
+        jk(base)
+            .args(["modify", "s/draft/final/", path])
+            .assert()
+            .failure();
+    }
+    assert_eq!(fs::read_to_string(base.join("a.txt")).unwrap(), "draft\n");
+
+    jk(base)
+        .args(["modify", "s/draft/final/", "a.txt", "scratch/*.txt"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(base.join("scratch/2.txt")).unwrap(),
+        "final\n"
+    );
+}
+
+#[test]
+fn undo_path_skips_other_paths_and_warns_on_conflicts() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "a1\n").unwrap();
+    fs::write(base.join("b.txt"), "b1\n").unwrap();
+    jk(base)
+        .args(["modify", "s/a1/a2/", "a.txt"])
+        .assert()
+        .success();
+    jk(base)
+        .args(["modify", "s/b1/b2/", "b.txt"])
+        .assert()
+        .success();
+
+    jk(base)
+        .args(["undo", "--path", "a.*"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Undid MODIFY"));
+    assert_eq!(fs::read_to_string(base.join("a.txt")).unwrap(), "a1\n");
+    assert_eq!(fs::read_to_string(base.join("b.txt")).unwrap(), "b2\n");
+
+    // The copy's destination changed since: undoing it would delete that
+    jk(base).args(["cp", "a.txt", "c.txt"]).assert().success();
+    jk(base)
+        .args(["modify", "s/a1/c2/", "c.txt"])
+        .assert()
+        .success();
+    jk(base)
+        .args(["undo", "--path", "a.txt"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("is followed by"))
+        .stderr(predicate::str::contains("--yes"));
+    assert!(base.join("c.txt").exists());
+    jk(base)
+        .args(["-y", "undo", "--path", "a.txt"])
+        .assert()
+        .success();
+    assert!(!base.join("c.txt").exists());
+}
+
+#[test]
+fn undo_since_undoes_a_subtree_in_the_window() {
+    let dir = repo();
+    let base = dir.path();
+    fs::create_dir_all(base.join("src")).unwrap();
+    fs::write(base.join("src/a.txt"), "a1\n").unwrap();
+    fs::write(base.join("src/b.txt"), "b1\n").unwrap();
+    fs::write(base.join("notes.txt"), "n1\n").unwrap();
+    for (expr, file) in [
+        ("s/a1/a2/", "src/a.txt"),
+        ("s/n1/n2/", "notes.txt"),
+        ("s/b1/b2/", "src/b.txt"),
+        ("s/a2/a3/", "src/a.txt"),
+    ] {
+        jk(base).args(["modify", expr, file]).assert().success();
+    }
+
+    jk(base)
+        .args(["undo", "--path", "src/**", "--since", "tomorrow"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("1 hour ago"));
+    jk(base)
+        .args(["undo", "--path", "src/**", "--since", "2999-01-01"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Nothing to undo on src/** since"));
+
+    jk(base)
+        .args(["undo", "--path", "src/**", "--since", "1 hour ago"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Undid MODIFY").count(3));
+    assert_eq!(fs::read_to_string(base.join("src/a.txt")).unwrap(), "a1\n");
+    assert_eq!(fs::read_to_string(base.join("src/b.txt")).unwrap(), "b1\n");
+    assert_eq!(fs::read_to_string(base.join("notes.txt")).unwrap(), "n2\n");
+
+    // A skipped operation on a path a selected one touched
+    jk(base)
+        .args(["mv", "src/a.txt", "a.txt"])
+        .assert()
+        .success();
+    jk(base)
+        .args(["modify", "s/a1/a4/", "a.txt"])
+        .assert()
+        .success();
+    jk(base)
+        .args(["--dry-run", "undo", "--path", "src/**", "--since", "1h"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("is followed by"));
+}
+
+#[cfg(unix)]
+#[test]
+fn chmod_recursive_is_one_implicit_transaction() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = repo();
+    let base = dir.path();
+    fs::create_dir_all(base.join("bin/sub")).unwrap();
+    for file in ["bin/run", "bin/sub/tool"] {
+        fs::write(base.join(file), "#!/bin/sh\n").unwrap();
+        fs::set_permissions(base.join(file), fs::Permissions::from_mode(0o644)).unwrap();
+    }
+    let mode = |path: &str| fs::metadata(base.join(path)).unwrap().permissions().mode() & 0o7777;
+
+    jk(base)
+        .args(["chmod", "-R", "u+x,go-w", "bin"])
+        .assert()
+        .success();
+    assert_eq!(mode("bin/run"), 0o744);
+    assert_eq!(mode("bin/sub/tool"), 0o744);
+
+    jk(base)
+        .args(["chmod", "600", "bin/run"])
+        .assert()
+        .success();
+    assert_eq!(mode("bin/run"), 0o600);
+    jk(base).args(["undo"]).assert().success();
+    assert_eq!(mode("bin/run"), 0o744);
+
+    jk(base)
+        .args(["tx", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("chmod u+x,go-w"));
+    jk(base).args(["undo", "--count", "10"]).assert().success();
+    assert_eq!(mode("bin/run"), 0o644);
+    assert_eq!(mode("bin/sub/tool"), 0o644);
+
+    jk(base)
+        .args(["chmod", "u+q", "bin/run"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid file mode"));
+}
+
+#[test]
+fn json_errors_carry_code_and_context() {
+    let dir = repo();
+    let base = dir.path();
+
+    let output = jk(base)
+        .args(["--json", "undo", "--id", "no-such-op"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let error: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(error["error"]["code"], "invalid_operation_id");
+    assert_eq!(error["error"]["operation_id"], "no-such-op");
+    assert!(error["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("no-such-op"));
+
+    jk(base)
+        .args(["undo", "--id", "no-such-op"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Error:"));
+}
+
+#[test]
+fn push_and_pull_replicate_history() {
+    let remote = tempfile::tempdir().unwrap();
+    let remote_uri = remote.path().join("store");
+    let remote_uri = remote_uri.to_str().unwrap();
+
+    let first = repo();
+    fs::write(first.path().join("a.txt"), "one").unwrap();
+    jk(first.path())
+        .args(["modify", "s/one/two/", "a.txt"])
+        .assert()
+        .success();
+    jk(first.path())
+        .args(["remote", "add", "origin", remote_uri])
+        .assert()
+        .success();
+    jk(first.path())
+        .arg("push")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Pushed 1 operations"));
+
+    // Another copy of the tree picks the history up and undoes from it
+    let second = repo();
+    fs::write(second.path().join("a.txt"), "two").unwrap();
+    jk(second.path())
+        .args(["remote", "add", "origin", remote_uri])
+        .assert()
+        .success();
+    jk(second.path())
+        .args(["pull", "origin"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Pulled 1 operations"));
+    jk(second.path()).arg("undo").assert().success();
+    assert_eq!(
+        fs::read_to_string(second.path().join("a.txt")).unwrap(),
+        "one"
+    );
+
+    // Both sides now have operations the other lacks
+    jk(first.path())
+        .args(["modify", "s/two/three/", "a.txt"])
+        .assert()
+        .success();
+    jk(second.path()).arg("push").assert().success();
+    jk(first.path())
+        .args(["--json", "push"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("\"diverged\""));
+}
+
+#[test]
+fn workspaces_share_a_store() {
+    let base = tempfile::tempdir().unwrap();
+    let shared = base.path().join("shared");
+    for name in ["web", "api"] {
+        let dir = base.path().join(name);
+        fs::create_dir(&dir).unwrap();
+        jk(&dir).arg("init").assert().success();
+        let config_path = dir.join(".januskey/config.json");
+        let mut config: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&config_path).unwrap()).unwrap();
+        config["storage_path"] = shared.display().to_string().into();
+        fs::write(&config_path, config.to_string()).unwrap();
+        jk(&dir)
+            .args(["workspace", "join"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(format!("is workspace {}", name)));
+        fs::write(dir.join("notes.txt"), "one").unwrap();
+        jk(&dir)
+            .args(["modify", "s/one/two/", "notes.txt"])
+            .assert()
+            .success();
+    }
+
+    let web = base.path().join("web");
+    jk(&web)
+        .args(["workspace", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("* web"))
+        .stdout(predicate::str::contains("api"));
+    jk(&web)
+        .arg("history")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Total: 1 operations"));
+    jk(&web)
+        .args(["history", "--workspace", "api"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            base.path().join("api/notes.txt").display().to_string(),
+        ));
+    jk(&web)
+        .args(["--json", "history", "--workspace", "nope"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("\"workspace_not_found\""));
+}
+
+#[test]
+fn modify_interactive_needs_a_terminal() {
+    let dir = repo();
+    fs::write(dir.path().join("a.txt"), "one\n").unwrap();
+    jk(dir.path())
+        .args(["modify", "--interactive", "s/one/two/", "a.txt"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("needs a terminal"));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.txt")).unwrap(),
+        "one\n"
+    );
+}
+
+#[test]
+fn doctor_reports_each_check() {
+    let dir = repo();
+    let output = jk(dir.path()).args(["--json", "doctor"]).output().unwrap();
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let checks = report["checks"].as_array().unwrap();
+    assert!(checks.iter().any(|c| c["name"] == "clock"));
+    assert!(checks.iter().all(|c| c["status"] != "fail"));
+
+    jk(dir.path())
+        .arg("doctor")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("journal"));
+
+    let empty = TempDir::new().unwrap();
+    jk(empty.path()).arg("doctor").assert().failure();
+}
+
+#[test]
+fn config_settings_are_validated() {
+    let dir = repo();
+    let base = dir.path();
+    jk(base)
+        .args(["config", "set", "max_history", "500"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("max_history = 500 (was 10000)"));
+    jk(base)
+        .args(["config", "get", "max_history"])
+        .assert()
+        .success()
+        .stdout("500\n");
+    jk(base)
+        .args(["config", "set", "compression", "yes"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("expected a boolean"));
+    jk(base)
+        .args(["config", "set", "max_histroy", "5"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown setting 'max_histroy'"));
+    jk(base)
+        .args(["config", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("retry.max_attempts = 4"));
+
+    fs::write(base.join(".januskey/config.json"), "{").unwrap();
+    jk(base)
+        .args(["config", "list"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid configuration"));
+}
+
+#[test]
+fn tokens_are_issued_once_and_revoked() {
+    let dir = repo();
+    let output = jk(dir.path())
+        .args(["--json", "token", "add", "ci", "--role", "operator"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let issued: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let token = issued["token"].as_str().unwrap();
+    assert!(
+        !fs::read_to_string(dir.path().join(".januskey/config.json"))
+            .unwrap()
+            .contains(token)
+    );
+
+    jk(dir.path())
+        .args(["token", "add", "ci", "--role", "reader"])
+        .assert()
+        .failure();
+    jk(dir.path())
+        .args(["token", "add", "dpo", "--role", "admin"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("compliance-officer"));
+    jk(dir.path())
+        .args(["token", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ci | operator"));
+    jk(dir.path())
+        .args(["token", "remove", "ci"])
+        .assert()
+        .success();
+    jk(dir.path())
+        .args(["token", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No tokens"));
+}
+
+#[test]
+fn preview_shows_content_diff_and_stat() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one\ntwo\n").unwrap();
+    jk(base).args(["begin", "edit"]).assert().success();
+    jk(base)
+        .args(["modify", "s/two/three/", "a.txt"])
+        .assert()
+        .success();
+
+    jk(base)
+        .args(["preview", "--diff"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("-two"))
+        .stdout(predicate::str::contains("+three"));
+    jk(base)
+        .args(["preview", "--stat"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("a.txt |     2"))
+        .stdout(predicate::str::contains(
+            "1 files changed, 1 insertions(+), 1 deletions(-), +2 bytes",
+        ));
+}
+
+#[test]
+fn shell_runs_a_transaction_recipe() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one\n").unwrap();
+
+    jk(base)
+        .arg("shell")
+        .write_stdin("begin recipe\nmodify s/one/two/ a.txt\npreview\nnot-a-command\ncommit\nhistory\nexit\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Started transaction: recipe"))
+        .stdout(predicate::str::contains("Committed transaction: recipe"))
+        .stdout(predicate::str::contains("MODIFY"))
+        .stderr(predicate::str::contains("unrecognized subcommand"));
+    assert_eq!(fs::read_to_string(base.join("a.txt")).unwrap(), "two\n");
+    assert!(base.join(".januskey/shell_history").exists());
+}
+
+#[test]
+fn completions_offer_store_values() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one\n").unwrap();
+    jk(base).args(["begin", "cleanup"]).assert().success();
+    jk(base)
+        .args(["modify", "s/one/two/", "a.txt"])
+        .assert()
+        .success();
+    jk(base).arg("commit").assert().success();
+
+    let complete = |words: &[&str]| {
+        let output = jk(base)
+            .args(["__complete", "--"])
+            .args(words)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let ids = complete(&["undo", "--id", ""]);
+    assert_eq!(ids.lines().count(), 1);
+    assert_eq!(ids.trim().len(), 8);
+    assert_eq!(complete(&["--dry-run", "verify-restore", &ids[..2]]), ids);
+    assert_eq!(complete(&["history", "--path", "a"]), "a.txt\n");
+    assert_eq!(complete(&["timeline", ""]), "a.txt\n");
+    let tx = complete(&["tx", "show", "clean"]);
+    assert_eq!(tx.trim().len(), 36);
+    jk(base)
+        .args(["tx", "show", tx.trim()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("cleanup"));
+    assert_eq!(complete(&["undo", "--count", ""]), "");
+    assert_eq!(complete(&["rm", ""]), "");
+
+    jk(base)
+        .args(["completions", "bash"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("complete -F _jk_dynamic"));
+    jk(base)
+        .args(["completions", "powershell"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("jk __complete --").not());
+}
+
+#[cfg(not(feature = "server"))]
+#[test]
+fn serve_needs_the_server_feature() {
+    let dir = repo();
+    jk(dir.path())
+        .arg("serve")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("`server` feature"));
+}
+
+#[test]
+fn refs_are_recorded_inherited_and_filtered() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one\n").unwrap();
+    fs::write(base.join("b.txt"), "two\n").unwrap();
+    fs::write(base.join("c.txt"), "three\n").unwrap();
+
+    jk(base)
+        .args(["--ref", "ci:build=1234", "delete", "a.txt"])
+        .assert()
+        .success();
+    jk(base)
+        .args(["begin", "deploy", "--ref", "ticket=JIRA-99"])
+        .assert()
+        .success();
+    jk(base)
+        .args(["--ref", "ci:build=1235", "delete", "b.txt"])
+        .assert()
+        .success();
+    jk(base).args(["delete", "c.txt"]).assert().success();
+    jk(base).arg("commit").assert().success();
+
+    jk(base)
+        .args(["history", "--has-ref", "ci:build"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("a.txt"))
+        .stdout(predicate::str::contains("b.txt"))
+        .stdout(predicate::str::contains("c.txt").not());
+    jk(base)
+        .args([
+            "history",
+            "--has-ref",
+            "ticket=JIRA-99",
+            "--has-ref",
+            "ci:build",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("b.txt"))
+        .stdout(predicate::str::contains("a.txt").not())
+        .stdout(predicate::str::contains("c.txt").not());
+    jk(base)
+        .args(["history", "--has-ref", "ci build"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid reference"));
+    jk(base)
+        .args(["--ref", "nokey", "history"])
+        .assert()
+        .failure();
+
+    let output = jk(base)
+        .args(["history", "--full-ids", "--path", "b.txt"])
+        .output()
+        .unwrap();
+    let listed = String::from_utf8(output.stdout).unwrap();
+    let id = listed
+        .lines()
+        .find(|line| line.contains("b.txt"))
+        .and_then(|line| line.split(" | ").next())
+        .unwrap()
+        .to_string();
+    jk(base)
+        .args(["show", &id])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "References:  ci:build=1235,ticket=JIRA-99",
+        ));
+}
+
+#[test]
+fn protected_paths_refuse_unless_allowed() {
+    let dir = repo();
+    let base = dir.path();
+    let config_path = base.join(".januskey/config.json");
+    let mut config: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&config_path).unwrap()).unwrap();
+    config["protected_paths"] = serde_json::json!(["keys"]);
+    fs::write(&config_path, config.to_string()).unwrap();
+    fs::create_dir(base.join("keys")).unwrap();
+    fs::write(base.join("keys/id.pem"), "secret\n").unwrap();
+    fs::write(base.join("a.txt"), "one\n").unwrap();
+
+    jk(base)
+        .args(["delete", "-r", "-y", "."])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Protected path"));
+    assert!(base.join("a.txt").exists());
+    assert!(config_path.exists());
+    jk(base)
+        .args(["mv", "a.txt", ".januskey/a.txt"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Protected path"));
+    jk(base).args(["delete", "keys/id.pem"]).assert().failure();
+    assert!(base.join("keys/id.pem").exists());
+
+    jk(base)
+        .args(["--allow-protected", "delete", "keys/id.pem"])
+        .assert()
+        .success();
+    assert!(!base.join("keys/id.pem").exists());
+    jk(base).args(["delete", "a.txt"]).assert().success();
+}
+
+#[cfg(unix)]
+#[test]
+fn confine_to_root_refuses_outside_paths() {
+    let dir = repo();
+    let base = dir.path();
+    let outside = tempfile::tempdir().unwrap();
+    let hosts = outside.path().join("hosts");
+    fs::write(&hosts, "127.0.0.1 localhost\n").unwrap();
+    std::os::unix::fs::symlink(outside.path(), base.join("etc")).unwrap();
+    fs::write(base.join("a.txt"), "one\n").unwrap();
+
+    // Off by default
+    jk(base)
+        .args(["cp", "a.txt"])
+        .arg(outside.path().join("a.txt"))
+        .assert()
+        .success();
+
+    let config_path = base.join(".januskey/config.json");
+    let mut config: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&config_path).unwrap()).unwrap();
+    config["confine_to_root"] = true.into();
+    fs::write(&config_path, config.to_string()).unwrap();
+
+    jk(base)
+        .args(["modify", "s/localhost/evil/", "-y"])
+        .arg(&hosts)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Outside the root"));
+    jk(base)
+        .args(["delete", "-y", "etc/hosts"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Outside the root"));
+    jk(base)
+        .args(["mv", "a.txt"])
+        .arg(outside.path().join("b.txt"))
+        .assert()
+        .failure();
+    assert_eq!(fs::read_to_string(&hosts).unwrap(), "127.0.0.1 localhost\n");
+    assert!(base.join("a.txt").exists());
+
+    jk(base)
+        .args(["--allow-outside", "delete", "-y", "etc/hosts"])
+        .assert()
+        .success();
+    assert!(!hosts.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn confine_to_root_lets_a_link_to_outside_be_deleted() {
+    let dir = repo();
+    let base = dir.path();
+    let outside = tempfile::tempdir().unwrap();
+    fs::write(outside.path().join("hosts"), "127.0.0.1 localhost\n").unwrap();
+    std::os::unix::fs::symlink(outside.path(), base.join("etc")).unwrap();
+    let config_path = base.join(".januskey/config.json");
+    let mut config: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&config_path).unwrap()).unwrap();
+    config["confine_to_root"] = true.into();
+    fs::write(&config_path, config.to_string()).unwrap();
+
+    // The link is in the root; only its target is not
+    jk(base).args(["delete", "-y", "etc"]).assert().success();
+    assert!(fs::symlink_metadata(base.join("etc")).is_err());
+    assert!(outside.path().join("hosts").exists());
+
+    jk(base).arg("undo").assert().success();
+    assert_eq!(fs::read_link(base.join("etc")).unwrap(), outside.path());
+}
+
+#[cfg(unix)]
+#[test]
+fn recursive_delete_removes_links_not_what_they_point_to() {
+    let dir = repo();
+    let base = dir.path();
+    let outside = tempfile::tempdir().unwrap();
+    fs::write(outside.path().join("far.txt"), "far away\n").unwrap();
+    fs::create_dir_all(base.join("build")).unwrap();
+    fs::write(base.join("build/out.txt"), "out\n").unwrap();
+    std::os::unix::fs::symlink(outside.path(), base.join("build/cache")).unwrap();
+
+    jk(base)
+        .args(["delete", "-r", "-y", "build"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Deleted 2 file(s)"));
+    assert!(fs::symlink_metadata(base.join("build/cache")).is_err());
+    assert_eq!(
+        fs::read_to_string(outside.path().join("far.txt")).unwrap(),
+        "far away\n"
+    );
+
+    jk(base).args(["undo", "--count", "2"]).assert().success();
+    assert_eq!(
+        fs::read_link(base.join("build/cache")).unwrap(),
+        outside.path()
+    );
+    assert_eq!(
+        fs::read_to_string(base.join("build/out.txt")).unwrap(),
+        "out\n"
+    );
+}
+
+#[test]
+fn copy_preserve_keeps_timestamps_and_records_them() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "dated\n").unwrap();
+    let modified =
+        std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+    fs::File::options()
+        .write(true)
+        .open(base.join("a.txt"))
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+
+    jk(base)
+        .args(["cp", "a.txt", "plain.txt"])
+        .assert()
+        .success();
+    assert_ne!(
+        fs::metadata(base.join("plain.txt"))
+            .unwrap()
+            .modified()
+            .unwrap(),
+        modified
+    );
+    jk(base)
+        .args(["cp", "--preserve=timestamps", "a.txt", "kept.txt"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::metadata(base.join("kept.txt"))
+            .unwrap()
+            .modified()
+            .unwrap(),
+        modified
+    );
+
+    let output = jk(base)
+        .args(["history", "--limit", "1", "--full-ids"])
+        .output()
+        .unwrap();
+    let history = String::from_utf8(output.stdout).unwrap();
+    let id = history
+        .lines()
+        .find_map(|line| line.split_once(" | ").map(|(id, _)| id.to_string()))
+        .unwrap();
+    jk(base)
+        .args(["show", &id])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Preserved:   mode, timestamps"));
+
+    jk(base)
+        .args(["cp", "--preserve=colour", "a.txt", "c.txt"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn create_writes_content_and_undo_removes_it() {
+    let dir = repo();
+    let base = dir.path();
+    fs::write(base.join("template.txt"), "from a file\n").unwrap();
+
+    jk(base)
+        .args(["create", "a.txt", "--content", "inline\n"])
+        .assert()
+        .success();
+    jk(base)
+        .args(["write", "b.txt"])
+        .write_stdin("piped\n")
+        .assert()
+        .success();
+    jk(base)
+        .args(["create", "c.txt", "--file", "template.txt"])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(base.join("a.txt")).unwrap(), "inline\n");
+    assert_eq!(fs::read_to_string(base.join("b.txt")).unwrap(), "piped\n");
+    assert_eq!(
+        fs::read_to_string(base.join("c.txt")).unwrap(),
+        "from a file\n"
+    );
+
+    jk(base)
+        .args(["create", "a.txt", "--content", "again"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Already exists"));
+    jk(base)
+        .args(["create", "deep/d.txt", "--content", "x"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--parents"));
+    jk(base)
+        .args(["create", "-p", "deep/d.txt", "--content", "x"])
+        .assert()
+        .success();
+    assert!(base.join("deep/d.txt").is_file());
+
+    jk(base).args(["undo", "--count", "4"]).assert().success();
+    for name in ["a.txt", "b.txt", "c.txt", "deep/d.txt"] {
+        assert!(!base.join(name).exists(), "{}", name);
+    }
+}
+
+#[test]
+fn rename_bulk_renames_in_a_transaction_and_refuses_collisions() {
+    let dir = repo();
+    let base = dir.path();
+    for name in ["IMG_001.jpg", "IMG_002.jpg", "notes.txt"] {
+        fs::write(base.join(name), name).unwrap();
+    }
+
+    jk(base)
+        .args([
+            "--dry-run",
+            "rename-bulk",
+            "*.jpg",
+            "--pattern",
+            r"s/IMG_(\d+)/photo_$1/",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("IMG_002.jpg -> photo_002.jpg"));
+    assert!(base.join("IMG_001.jpg").exists());
+
+    jk(base)
+        .args(["rename-bulk", "*", "--pattern", r"s/^\w+/same/"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("would all be renamed to"));
+    jk(base)
+        .args([
+            "rename-bulk",
+            "notes.txt",
+            "--pattern",
+            r"s/notes\.txt/IMG_001.jpg/",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("not being renamed"));
+    assert!(base.join("notes.txt").exists());
+
+    jk(base)
+        .args([
+            "rename-bulk",
+            "*.jpg",
+            "--pattern",
+            r"s/IMG_(\d+)/photo_$1/",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "Renames (2):\n  - {} -> photo_001.jpg",
+            base.join("IMG_001.jpg").display()
+        )));
+    assert_eq!(
+        fs::read_to_string(base.join("photo_001.jpg")).unwrap(),
+        "IMG_001.jpg"
+    );
+    assert!(!base.join("IMG_002.jpg").exists());
+    jk(base)
+        .args(["tx", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("rename-bulk"));
+
+    jk(base).args(["undo", "--count", "2"]).assert().success();
+    assert!(base.join("IMG_001.jpg").exists());
+    assert!(base.join("IMG_002.jpg").exists());
+}
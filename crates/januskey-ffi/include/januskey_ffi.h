@@ -0,0 +1,210 @@
+/* SPDX-License-Identifier: MPL-2.0 */
+/* Copyright (c) 2026 Jonathan D.A. Jewell (hyperpolymath) */
+/* JanusKey C ABI — generated by cbindgen from crates/januskey-ffi; do not edit */
+
+#ifndef JANUSKEY_FFI_H
+#define JANUSKEY_FFI_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+// Success
+#define JK_OK 0
+
+// Directory is not a JanusKey repository, or the handle is null
+#define JK_ERR_NOT_INITIALIZED 1
+
+// Path is null, not UTF-8, missing, or already exists
+#define JK_ERR_INVALID_PATH 2
+
+// I/O, storage or internal error
+#define JK_ERR_IO 3
+
+// Cryptographic failure
+#define JK_ERR_CRYPTO 4
+
+// No transaction is active
+#define JK_ERR_TX_NOT_ACTIVE 5
+
+// A transaction is already active
+#define JK_ERR_TX_CONFLICT 6
+
+// Key not found
+#define JK_ERR_KEY_NOT_FOUND 7
+
+// Key has been revoked
+#define JK_ERR_KEY_REVOKED 8
+
+// Obliteration failed
+#define JK_ERR_OBLITERATION 9
+
+// Attestation failed
+#define JK_ERR_ATTESTATION 10
+
+// Output buffer too small
+#define JK_ERR_BUFFER_TOO_SMALL 11
+
+// There is no operation left to undo
+#define JK_ERR_NOTHING_TO_UNDO 12
+
+// Opaque handle to an open JanusKey repository.
+//
+// Thread-safe: every call locks the repository for its duration.
+typedef struct JanusKeyHandle JanusKeyHandle;
+
+// Opaque token for the transaction begun by `jk_tx_begin`
+typedef struct JanusKeyTransaction JanusKeyTransaction;
+
+// Proof of an obliteration, laid out as `jk_oblit_proof_t`
+typedef struct JanusKeyObliterationProof {
+  // Hash of the obliterated content
+  uint8_t content_hash[32];
+  // Random nonce mixed into the commitment
+  uint8_t nonce[32];
+  // H(content_hash || nonce || timestamp)
+  uint8_t commitment[32];
+  // Number of overwrite passes performed
+  uint64_t overwrite_passes;
+  // 1 when the storage no longer holds the content
+  uint8_t passes_valid;
+} JanusKeyObliterationProof;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Initialize JanusKey in `path` and return a handle in `*out_handle`.
+//
+// # Safety
+// `path` must be a NUL-terminated string and `out_handle` writable.
+// The handle must be released with `jk_close`.
+int jk_init(const char *path, struct JanusKeyHandle **out_handle);
+
+// Open an initialized JanusKey directory and return a handle in `*out_handle`.
+//
+// # Safety
+// Same as `jk_init`.
+int jk_open(const char *path, struct JanusKeyHandle **out_handle);
+
+// Release a handle. Null is ignored.
+//
+// # Safety
+// `handle` must be null or a handle not yet closed, and no other thread
+// may be using it.
+void jk_close(struct JanusKeyHandle *handle);
+
+// Delete a file, keeping its content for undo.
+//
+// # Safety
+// `handle` must be a live handle and `path` a NUL-terminated string.
+int jk_delete(struct JanusKeyHandle *handle, const char *path);
+
+// Replace the content of a file with `len` bytes from `data`.
+//
+// # Safety
+// `handle` must be a live handle, `path` a NUL-terminated string and
+// `data` readable for `len` bytes (it may be null when `len` is 0).
+int jk_modify(struct JanusKeyHandle *handle, const char *path, const uint8_t *data, uintptr_t len);
+
+// Move or rename `src` to `dst`.
+//
+// # Safety
+// `handle` must be a live handle, `src` and `dst` NUL-terminated strings.
+int jk_move(struct JanusKeyHandle *handle, const char *src, const char *dst);
+
+// Copy `src` to `dst`.
+//
+// # Safety
+// Same as `jk_move`.
+int jk_copy(struct JanusKeyHandle *handle, const char *src, const char *dst);
+
+// Undo the most recent operation.
+//
+// # Safety
+// `handle` must be a live handle.
+int jk_undo(struct JanusKeyHandle *handle);
+
+// Begin a transaction, storing its token in `*out_tx`.
+//
+// The token is released by `jk_tx_commit` or `jk_tx_rollback` when they
+// return `JK_OK` or `JK_ERR_TX_NOT_ACTIVE`.
+//
+// # Safety
+// `handle` must be a live handle and `out_tx` writable.
+int jk_tx_begin(struct JanusKeyHandle *handle, struct JanusKeyTransaction **out_tx);
+
+// Begin a transaction called `name` (null for none), storing its token in
+// `*out_tx`.
+//
+// # Safety
+// As `jk_tx_begin`; `name` must be null or a NUL-terminated string.
+int jk_tx_begin_named(struct JanusKeyHandle *handle,
+                      const char *name,
+                      struct JanusKeyTransaction **out_tx);
+
+// Commit the transaction `tx`.
+//
+// # Safety
+// `handle` must be a live handle and `tx` null or a token from
+// `jk_tx_begin` not yet released.
+int jk_tx_commit(struct JanusKeyHandle *handle, struct JanusKeyTransaction *tx);
+
+// Undo every operation of the transaction `tx` and end it.
+//
+// # Safety
+// Same as `jk_tx_commit`.
+int jk_tx_rollback(struct JanusKeyHandle *handle, struct JanusKeyTransaction *tx);
+
+// Overwrite and remove `path` beyond recovery, filling `*out_proof` (when
+// not null). Files under legal hold are refused.
+//
+// # Safety
+// `handle` must be a live handle, `path` a NUL-terminated string and
+// `out_proof` null or writable.
+int jk_obliterate(struct JanusKeyHandle *handle,
+                  const char *path,
+                  struct JanusKeyObliterationProof *out_proof);
+
+// Write the repository status as NUL-terminated JSON into `buf`.
+//
+// `*out_len` receives the size needed, NUL included; when `buf` is null
+// or `len` is smaller, `JK_ERR_BUFFER_TOO_SMALL` is returned.
+//
+// # Safety
+// `handle` must be a live handle, `buf` null or writable for `len` bytes
+// and `out_len` null or writable.
+int jk_status(struct JanusKeyHandle *handle, char *buf, uintptr_t len, uintptr_t *out_len);
+
+// Write the last `limit` operations (all when 0), newest first, as a
+// NUL-terminated JSON array into `buf`.
+//
+// # Safety
+// Same as `jk_status`.
+int jk_history(struct JanusKeyHandle *handle,
+               uintptr_t limit,
+               char *buf,
+               uintptr_t len,
+               uintptr_t *out_len);
+
+// Message describing the last failed call on this thread, or null.
+//
+// The string is owned by the library and stays valid until the next
+// failing call on the same thread.
+const char *jk_last_error_message(void);
+
+// Stable code naming the last failed call's error on this thread
+// (`"file_not_found"`, `"nothing_to_undo"`, ...), or null.
+//
+// Codes do not change between releases, so callers can branch on them
+// where the `JK_ERR_*` status is too coarse. The string is owned by the
+// library and stays valid until the next failing call on the same thread.
+const char *jk_last_error_code(void);
+
+// Library version as a static NUL-terminated string
+const char *jk_version(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* JANUSKEY_FFI_H */
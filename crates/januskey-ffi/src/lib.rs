@@ -0,0 +1,684 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// JanusKey C ABI: reversible file operations for C, Ada, Zig and friends
+// The header include/januskey_ffi.h is generated from this file by build.rs
+//
+// Conventions:
+// - Every fallible function returns a JK_* status code (JK_OK on success)
+// - On failure, jk_last_error_message() describes the error and
+//   jk_last_error_code() names it with a stable code
+// - Relative paths are resolved against the directory the handle was opened on
+// - A handle may be used from several threads at once; calls on the same
+//   handle are serialized by a lock. Only jk_close must not race other calls
+// - Functions named in the spec'd ABI (ffi/zig/include/januskey.h) take its
+//   signatures; its jk_execute and key functions are not provided here
+
+#![deny(unsafe_op_in_unsafe_fn)]
+
+use januskey::obliteration::obliterate_file;
+use januskey::{FileOperation, JanusError, JanusKey};
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::{Mutex, MutexGuard};
+
+/// Success
+pub const JK_OK: c_int = 0;
+/// Directory is not a JanusKey repository, or the handle is null
+pub const JK_ERR_NOT_INITIALIZED: c_int = 1;
+/// Path is null, not UTF-8, missing, or already exists
+pub const JK_ERR_INVALID_PATH: c_int = 2;
+/// I/O, storage or internal error
+pub const JK_ERR_IO: c_int = 3;
+/// Cryptographic failure
+pub const JK_ERR_CRYPTO: c_int = 4;
+/// No transaction is active
+pub const JK_ERR_TX_NOT_ACTIVE: c_int = 5;
+/// A transaction is already active
+pub const JK_ERR_TX_CONFLICT: c_int = 6;
+/// Key not found
+pub const JK_ERR_KEY_NOT_FOUND: c_int = 7;
+/// Key has been revoked
+pub const JK_ERR_KEY_REVOKED: c_int = 8;
+/// Obliteration failed
+pub const JK_ERR_OBLITERATION: c_int = 9;
+/// Attestation failed
+pub const JK_ERR_ATTESTATION: c_int = 10;
+/// Output buffer too small
+pub const JK_ERR_BUFFER_TOO_SMALL: c_int = 11;
+/// There is no operation left to undo
+pub const JK_ERR_NOTHING_TO_UNDO: c_int = 12;
+
+/// Opaque handle to an open JanusKey repository.
+///
+/// Thread-safe: every call locks the repository for its duration.
+pub struct JanusKeyHandle {
+    jk: Mutex<JanusKey>,
+}
+
+/// Opaque token for the transaction begun by `jk_tx_begin`
+pub struct JanusKeyTransaction {
+    id: String,
+}
+
+/// Proof of an obliteration, laid out as `jk_oblit_proof_t`
+#[repr(C)]
+pub struct JanusKeyObliterationProof {
+    /// Hash of the obliterated content
+    pub content_hash: [u8; 32],
+    /// Random nonce mixed into the commitment
+    pub nonce: [u8; 32],
+    /// H(content_hash || nonce || timestamp)
+    pub commitment: [u8; 32],
+    /// Number of overwrite passes performed
+    pub overwrite_passes: u64,
+    /// 1 when the storage no longer holds the content
+    pub passes_valid: u8,
+}
+
+// Handles are shared between threads by C callers
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<JanusKeyHandle>();
+};
+
+thread_local! {
+    /// Message for the last error on this thread
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+    /// Stable code for the last error on this thread
+    static LAST_ERROR_CODE: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(code: &str, message: impl Into<String>) {
+    let message = message.into().replace('\0', " ");
+    let message = CString::new(message).expect("interior NULs removed");
+    let code = CString::new(code).expect("codes have no NULs");
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    LAST_ERROR_CODE.with(|last| *last.borrow_mut() = Some(code));
+}
+
+/// Status code for a JanusKey error
+fn error_code(err: &JanusError) -> c_int {
+    match err.inner() {
+        JanusError::NotInitialized(_) => JK_ERR_NOT_INITIALIZED,
+        JanusError::FileNotFound(_)
+        | JanusError::DirectoryNotFound(_)
+        | JanusError::PathExists(_)
+        | JanusError::InvalidPattern(_)
+        | JanusError::Glob(_) => JK_ERR_INVALID_PATH,
+        JanusError::NoActiveTransaction => JK_ERR_TX_NOT_ACTIVE,
+        JanusError::TransactionActive(_) => JK_ERR_TX_CONFLICT,
+        JanusError::NothingToUndo => JK_ERR_NOTHING_TO_UNDO,
+        JanusError::LegalHold(_) => JK_ERR_OBLITERATION,
+        _ => JK_ERR_IO,
+    }
+}
+
+/// Failure carrying a status code, a stable error code (see
+/// [`JanusError::code`]) and a message
+struct Failure(c_int, &'static str, String);
+
+/// Code for an argument the caller passed wrongly
+const INVALID_ARGUMENT: &str = "invalid_argument";
+
+impl From<JanusError> for Failure {
+    fn from(err: JanusError) -> Self {
+        Failure(error_code(&err), err.code(), err.to_string())
+    }
+}
+
+/// Run `f`, converting errors and panics into a status code
+fn guard(f: impl FnOnce() -> Result<(), Failure>) -> c_int {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => JK_OK,
+        Ok(Err(Failure(status, code, message))) => {
+            set_last_error(code, message);
+            status
+        }
+        Err(_) => {
+            set_last_error("internal", "internal error: panic in januskey");
+            JK_ERR_IO
+        }
+    }
+}
+
+/// Borrow a C string argument as UTF-8
+///
+/// # Safety
+/// `ptr` must be null or point to a NUL-terminated string.
+unsafe fn str_arg<'a>(ptr: *const c_char, what: &str) -> Result<&'a str, Failure> {
+    if ptr.is_null() {
+        return Err(Failure(
+            JK_ERR_INVALID_PATH,
+            INVALID_ARGUMENT,
+            format!("{} is null", what),
+        ));
+    }
+    // SAFETY: non-null and NUL-terminated per the caller's contract
+    unsafe { CStr::from_ptr(ptr) }.to_str().map_err(|_| {
+        Failure(
+            JK_ERR_INVALID_PATH,
+            INVALID_ARGUMENT,
+            format!("{} is not valid UTF-8", what),
+        )
+    })
+}
+
+/// Lock the repository behind a handle
+///
+/// # Safety
+/// `handle` must be null or a live handle from `jk_init`/`jk_open`.
+unsafe fn lock<'a>(handle: *mut JanusKeyHandle) -> Result<MutexGuard<'a, JanusKey>, Failure> {
+    // SAFETY: null or live per the caller's contract; only shared access is taken
+    let handle = unsafe { handle.as_ref() }.ok_or_else(|| {
+        Failure(
+            JK_ERR_NOT_INITIALIZED,
+            "not_initialized",
+            "handle is null".to_string(),
+        )
+    })?;
+    handle.jk.lock().map_err(|_| {
+        Failure(
+            JK_ERR_IO,
+            "internal",
+            "handle is unusable after an earlier internal error".to_string(),
+        )
+    })
+}
+
+/// Resolve a path argument against the repository root
+///
+/// # Safety
+/// See [`str_arg`].
+unsafe fn path_arg(jk: &JanusKey, ptr: *const c_char, what: &str) -> Result<PathBuf, Failure> {
+    // SAFETY: forwarded from the caller
+    let path = unsafe { str_arg(ptr, what) }?;
+    Ok(jk.root.join(path))
+}
+
+/// Failure for an out-parameter the caller passed as null
+fn null_out(what: &str) -> Failure {
+    Failure(JK_ERR_IO, INVALID_ARGUMENT, format!("{} is null", what))
+}
+
+/// Failure for a transaction token that is null or not the active one
+fn tx_not_active() -> Failure {
+    JanusError::NoActiveTransaction.into()
+}
+
+/// Decode a 32-byte hex digest from a proof
+fn digest(hex_digest: &str) -> Result<[u8; 32], Failure> {
+    hex::decode(hex_digest)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| {
+            Failure(
+                JK_ERR_OBLITERATION,
+                "internal",
+                format!("{} is not a 32-byte digest", hex_digest),
+            )
+        })
+}
+
+/// Copy `text` NUL-terminated into `buf` (`len` bytes), storing the size it
+/// needs, NUL included, in `*out_len`
+///
+/// # Safety
+/// `buf` must be null or writable for `len` bytes; `out_len` must be null
+/// or writable.
+unsafe fn write_text(
+    text: &str,
+    buf: *mut c_char,
+    len: usize,
+    out_len: *mut usize,
+) -> Result<(), Failure> {
+    let needed = text.len() + 1;
+    // SAFETY: null or writable per the caller's contract
+    if let Some(out_len) = unsafe { out_len.as_mut() } {
+        *out_len = needed;
+    }
+    if buf.is_null() || len < needed {
+        return Err(Failure(
+            JK_ERR_BUFFER_TOO_SMALL,
+            "buffer_too_small",
+            format!("{} bytes are needed", needed),
+        ));
+    }
+    // SAFETY: buf is writable for len >= needed bytes
+    unsafe {
+        ptr::copy_nonoverlapping(text.as_ptr().cast(), buf, text.len());
+        *buf.add(text.len()) = 0;
+    }
+    Ok(())
+}
+
+fn to_json(value: &impl serde::Serialize) -> Result<String, Failure> {
+    serde_json::to_string(value).map_err(|e| Failure(JK_ERR_IO, "internal", e.to_string()))
+}
+
+fn execute(jk: &mut JanusKey, operation: FileOperation) -> Result<(), Failure> {
+    jk.execute(operation)?;
+    Ok(())
+}
+
+/// Open (`init == false`) or initialize a repository into `*out_handle`
+///
+/// # Safety
+/// See [`jk_init`].
+unsafe fn open_handle(
+    path: *const c_char,
+    out_handle: *mut *mut JanusKeyHandle,
+    init: bool,
+) -> c_int {
+    guard(|| {
+        if out_handle.is_null() {
+            return Err(Failure(
+                JK_ERR_IO,
+                INVALID_ARGUMENT,
+                "out_handle is null".to_string(),
+            ));
+        }
+        // SAFETY: forwarded from the caller
+        let path = PathBuf::from(unsafe { str_arg(path, "path") }?);
+        let jk = if init {
+            JanusKey::init(&path)?
+        } else {
+            JanusKey::open(&path)?
+        };
+        let handle = Box::into_raw(Box::new(JanusKeyHandle { jk: Mutex::new(jk) }));
+        // SAFETY: out_handle is non-null and writable per the caller's contract
+        unsafe { *out_handle = handle };
+        Ok(())
+    })
+}
+
+/// Initialize JanusKey in `path` and return a handle in `*out_handle`.
+///
+/// # Safety
+/// `path` must be a NUL-terminated string and `out_handle` writable.
+/// The handle must be released with `jk_close`.
+#[no_mangle]
+pub unsafe extern "C" fn jk_init(
+    path: *const c_char,
+    out_handle: *mut *mut JanusKeyHandle,
+) -> c_int {
+    // SAFETY: forwarded from the caller
+    unsafe { open_handle(path, out_handle, true) }
+}
+
+/// Open an initialized JanusKey directory and return a handle in `*out_handle`.
+///
+/// # Safety
+/// Same as `jk_init`.
+#[no_mangle]
+pub unsafe extern "C" fn jk_open(
+    path: *const c_char,
+    out_handle: *mut *mut JanusKeyHandle,
+) -> c_int {
+    // SAFETY: forwarded from the caller
+    unsafe { open_handle(path, out_handle, false) }
+}
+
+/// Release a handle. Null is ignored.
+///
+/// # Safety
+/// `handle` must be null or a handle not yet closed, and no other thread
+/// may be using it.
+#[no_mangle]
+pub unsafe extern "C" fn jk_close(handle: *mut JanusKeyHandle) {
+    if !handle.is_null() {
+        // SAFETY: created by Box::into_raw in open_handle and not yet freed
+        drop(unsafe { Box::from_raw(handle) });
+    }
+}
+
+/// Delete a file, keeping its content for undo.
+///
+/// # Safety
+/// `handle` must be a live handle and `path` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn jk_delete(handle: *mut JanusKeyHandle, path: *const c_char) -> c_int {
+    guard(|| {
+        // SAFETY: forwarded from the caller
+        let mut jk = unsafe { lock(handle) }?;
+        let path = unsafe { path_arg(&jk, path, "path") }?;
+        execute(&mut jk, FileOperation::Delete { path })
+    })
+}
+
+/// Replace the content of a file with `len` bytes from `data`.
+///
+/// # Safety
+/// `handle` must be a live handle, `path` a NUL-terminated string and
+/// `data` readable for `len` bytes (it may be null when `len` is 0).
+#[no_mangle]
+pub unsafe extern "C" fn jk_modify(
+    handle: *mut JanusKeyHandle,
+    path: *const c_char,
+    data: *const u8,
+    len: usize,
+) -> c_int {
+    guard(|| {
+        // SAFETY: forwarded from the caller
+        let mut jk = unsafe { lock(handle) }?;
+        let path = unsafe { path_arg(&jk, path, "path") }?;
+        let new_content = if len == 0 {
+            Vec::new()
+        } else if data.is_null() {
+            return Err(Failure(
+                JK_ERR_IO,
+                INVALID_ARGUMENT,
+                "data is null".to_string(),
+            ));
+        } else {
+            // SAFETY: data is readable for len bytes per the caller's contract
+            unsafe { std::slice::from_raw_parts(data, len) }.to_vec()
+        };
+        execute(&mut jk, FileOperation::Modify { path, new_content })
+    })
+}
+
+/// Move or rename `src` to `dst`.
+///
+/// # Safety
+/// `handle` must be a live handle, `src` and `dst` NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn jk_move(
+    handle: *mut JanusKeyHandle,
+    src: *const c_char,
+    dst: *const c_char,
+) -> c_int {
+    guard(|| {
+        // SAFETY: forwarded from the caller
+        let mut jk = unsafe { lock(handle) }?;
+        let source = unsafe { path_arg(&jk, src, "src") }?;
+        let destination = unsafe { path_arg(&jk, dst, "dst") }?;
+        execute(
+            &mut jk,
+            FileOperation::Move {
+                source,
+                destination,
+            },
+        )
+    })
+}
+
+/// Copy `src` to `dst`.
+///
+/// # Safety
+/// Same as `jk_move`.
+#[no_mangle]
+pub unsafe extern "C" fn jk_copy(
+    handle: *mut JanusKeyHandle,
+    src: *const c_char,
+    dst: *const c_char,
+) -> c_int {
+    guard(|| {
+        // SAFETY: forwarded from the caller
+        let mut jk = unsafe { lock(handle) }?;
+        let source = unsafe { path_arg(&jk, src, "src") }?;
+        let destination = unsafe { path_arg(&jk, dst, "dst") }?;
+        let preserve = jk.config.copy_preserve.clone();
+        execute(
+            &mut jk,
+            FileOperation::Copy {
+                source,
+                destination,
+                preserve,
+            },
+        )
+    })
+}
+
+/// Undo the most recent operation.
+///
+/// # Safety
+/// `handle` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn jk_undo(handle: *mut JanusKeyHandle) -> c_int {
+    guard(|| {
+        // SAFETY: forwarded from the caller
+        let mut jk = unsafe { lock(handle) }?;
+        jk.undo_last()?;
+        Ok(())
+    })
+}
+
+/// Begin a transaction, storing its token in `*out_tx`.
+///
+/// The token is released by `jk_tx_commit` or `jk_tx_rollback` when they
+/// return `JK_OK` or `JK_ERR_TX_NOT_ACTIVE`.
+///
+/// # Safety
+/// `handle` must be a live handle and `out_tx` writable.
+#[no_mangle]
+pub unsafe extern "C" fn jk_tx_begin(
+    handle: *mut JanusKeyHandle,
+    out_tx: *mut *mut JanusKeyTransaction,
+) -> c_int {
+    // SAFETY: forwarded from the caller
+    unsafe { jk_tx_begin_named(handle, ptr::null(), out_tx) }
+}
+
+/// Begin a transaction called `name` (null for none), storing its token in
+/// `*out_tx`.
+///
+/// # Safety
+/// As `jk_tx_begin`; `name` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn jk_tx_begin_named(
+    handle: *mut JanusKeyHandle,
+    name: *const c_char,
+    out_tx: *mut *mut JanusKeyTransaction,
+) -> c_int {
+    guard(|| {
+        if out_tx.is_null() {
+            return Err(null_out("out_tx"));
+        }
+        // SAFETY: forwarded from the caller
+        let mut jk = unsafe { lock(handle) }?;
+        let name = if name.is_null() {
+            None
+        } else {
+            Some(unsafe { str_arg(name, "name") }?.to_string())
+        };
+        let id = jk.transaction_manager.begin(name)?.id.clone();
+        let tx = Box::into_raw(Box::new(JanusKeyTransaction { id }));
+        // SAFETY: out_tx is non-null and writable per the caller's contract
+        unsafe { *out_tx = tx };
+        Ok(())
+    })
+}
+
+/// Check that `tx` is the active transaction, then run `end` and release
+/// the token unless `end` failed with the transaction still active
+///
+/// # Safety
+/// `handle` must be a live handle and `tx` null or a token not yet released.
+unsafe fn end_transaction(
+    handle: *mut JanusKeyHandle,
+    tx: *mut JanusKeyTransaction,
+    end: impl FnOnce(&mut JanusKey) -> januskey::Result<()>,
+) -> c_int {
+    guard(|| {
+        // SAFETY: forwarded from the caller
+        let mut jk = unsafe { lock(handle) }?;
+        // SAFETY: null or a live token per the caller's contract
+        let Some(token) = (unsafe { tx.as_ref() }) else {
+            return Err(tx_not_active());
+        };
+        let active = jk.transaction_manager.active().map(|t| t.id.as_str());
+        let result = if active == Some(token.id.as_str()) {
+            end(&mut jk).map_err(Failure::from)
+        } else {
+            Err(tx_not_active())
+        };
+        if matches!(&result, Ok(()) | Err(Failure(JK_ERR_TX_NOT_ACTIVE, ..))) {
+            // SAFETY: the token came from Box::into_raw in jk_tx_begin_named
+            drop(unsafe { Box::from_raw(tx) });
+        }
+        result
+    })
+}
+
+/// Commit the transaction `tx`.
+///
+/// # Safety
+/// `handle` must be a live handle and `tx` null or a token from
+/// `jk_tx_begin` not yet released.
+#[no_mangle]
+pub unsafe extern "C" fn jk_tx_commit(
+    handle: *mut JanusKeyHandle,
+    tx: *mut JanusKeyTransaction,
+) -> c_int {
+    // SAFETY: forwarded from the caller
+    unsafe {
+        end_transaction(handle, tx, |jk| {
+            jk.transaction_manager.commit()?;
+            Ok(())
+        })
+    }
+}
+
+/// Undo every operation of the transaction `tx` and end it.
+///
+/// # Safety
+/// Same as `jk_tx_commit`.
+#[no_mangle]
+pub unsafe extern "C" fn jk_tx_rollback(
+    handle: *mut JanusKeyHandle,
+    tx: *mut JanusKeyTransaction,
+) -> c_int {
+    // SAFETY: forwarded from the caller
+    unsafe {
+        end_transaction(handle, tx, |jk| {
+            jk.rollback()?;
+            Ok(())
+        })
+    }
+}
+
+/// Overwrite and remove `path` beyond recovery, filling `*out_proof` (when
+/// not null). Files under legal hold are refused.
+///
+/// # Safety
+/// `handle` must be a live handle, `path` a NUL-terminated string and
+/// `out_proof` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn jk_obliterate(
+    handle: *mut JanusKeyHandle,
+    path: *const c_char,
+    out_proof: *mut JanusKeyObliterationProof,
+) -> c_int {
+    guard(|| {
+        // SAFETY: forwarded from the caller
+        let jk = unsafe { lock(handle) }?;
+        let path = unsafe { path_arg(&jk, path, "path") }?;
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if let Some(hold) = jk.legal_holds()?.hold_on_path(&jk.root, &canonical) {
+            return Err(JanusError::LegalHold(format!(
+                "{} is held for case {}",
+                path.display(),
+                hold.case
+            ))
+            .into());
+        }
+        let proof = obliterate_file(&path)?;
+        // SAFETY: null or writable per the caller's contract
+        if let Some(out) = unsafe { out_proof.as_mut() } {
+            *out = JanusKeyObliterationProof {
+                content_hash: digest(proof.content_hash.raw_hash())?,
+                nonce: digest(&proof.nonce)?,
+                commitment: digest(&proof.commitment)?,
+                overwrite_passes: proof.overwrite_passes as u64,
+                passes_valid: u8::from(proof.storage_cleared),
+            };
+        }
+        Ok(())
+    })
+}
+
+/// Write the repository status as NUL-terminated JSON into `buf`.
+///
+/// `*out_len` receives the size needed, NUL included; when `buf` is null
+/// or `len` is smaller, `JK_ERR_BUFFER_TOO_SMALL` is returned.
+///
+/// # Safety
+/// `handle` must be a live handle, `buf` null or writable for `len` bytes
+/// and `out_len` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn jk_status(
+    handle: *mut JanusKeyHandle,
+    buf: *mut c_char,
+    len: usize,
+    out_len: *mut usize,
+) -> c_int {
+    guard(|| {
+        // SAFETY: forwarded from the caller
+        let jk = unsafe { lock(handle) }?;
+        let json = to_json(&jk.status()?)?;
+        unsafe { write_text(&json, buf, len, out_len) }
+    })
+}
+
+/// Write the last `limit` operations (all when 0), newest first, as a
+/// NUL-terminated JSON array into `buf`.
+///
+/// # Safety
+/// Same as `jk_status`.
+#[no_mangle]
+pub unsafe extern "C" fn jk_history(
+    handle: *mut JanusKeyHandle,
+    limit: usize,
+    buf: *mut c_char,
+    len: usize,
+    out_len: *mut usize,
+) -> c_int {
+    guard(|| {
+        // SAFETY: forwarded from the caller
+        let jk = unsafe { lock(handle) }?;
+        let operations = jk.metadata_store.operations();
+        let limit = if limit == 0 { operations.len() } else { limit };
+        let recent: Vec<_> = operations.iter().rev().take(limit).collect();
+        let json = to_json(&recent)?;
+        unsafe { write_text(&json, buf, len, out_len) }
+    })
+}
+
+/// Message describing the last failed call on this thread, or null.
+///
+/// The string is owned by the library and stays valid until the next
+/// failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn jk_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Stable code naming the last failed call's error on this thread
+/// (`"file_not_found"`, `"nothing_to_undo"`, ...), or null.
+///
+/// Codes do not change between releases, so callers can branch on them
+/// where the `JK_ERR_*` status is too coarse. The string is owned by the
+/// library and stays valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn jk_last_error_code() -> *const c_char {
+    LAST_ERROR_CODE.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |code| code.as_ptr())
+    })
+}
+
+/// Library version as a static NUL-terminated string
+#[no_mangle]
+pub extern "C" fn jk_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast()
+}
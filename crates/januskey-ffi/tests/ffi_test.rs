@@ -0,0 +1,275 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+//
+// FFI tests: drive the C ABI as a C caller would
+
+use januskey_ffi::*;
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::ptr;
+
+fn c(s: &str) -> CString {
+    CString::new(s).unwrap()
+}
+
+fn last_error() -> String {
+    let msg = jk_last_error_message();
+    assert!(!msg.is_null());
+    unsafe { CStr::from_ptr(msg) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn last_error_code() -> String {
+    let code = jk_last_error_code();
+    assert!(!code.is_null());
+    unsafe { CStr::from_ptr(code) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Helper: initialized repository and its handle
+fn init() -> (tempfile::TempDir, *mut JanusKeyHandle) {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let mut handle = ptr::null_mut();
+    let path = c(dir.path().to_str().unwrap());
+    assert_eq!(unsafe { jk_init(path.as_ptr(), &mut handle) }, JK_OK);
+    assert!(!handle.is_null());
+    (dir, handle)
+}
+
+#[test]
+fn operations_and_undo() {
+    let (dir, handle) = init();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "one").unwrap();
+
+    unsafe {
+        let data = b"two";
+        assert_eq!(
+            jk_modify(handle, c("a.txt").as_ptr(), data.as_ptr(), data.len()),
+            JK_OK
+        );
+        assert_eq!(
+            jk_copy(handle, c("a.txt").as_ptr(), c("b.txt").as_ptr()),
+            JK_OK
+        );
+        assert_eq!(
+            jk_move(handle, c("b.txt").as_ptr(), c("c.txt").as_ptr()),
+            JK_OK
+        );
+        assert_eq!(jk_delete(handle, c("a.txt").as_ptr()), JK_OK);
+    }
+    assert!(!base.join("a.txt").exists());
+    assert_eq!(fs::read_to_string(base.join("c.txt")).unwrap(), "two");
+
+    unsafe {
+        assert_eq!(jk_undo(handle), JK_OK);
+        jk_close(handle);
+    }
+    assert_eq!(fs::read_to_string(base.join("a.txt")).unwrap(), "two");
+}
+
+#[test]
+fn undo_with_empty_history() {
+    let (_dir, handle) = init();
+    unsafe {
+        assert_eq!(jk_undo(handle), JK_ERR_NOTHING_TO_UNDO);
+        assert_eq!(last_error(), "Nothing to undo");
+        assert_eq!(last_error_code(), "nothing_to_undo");
+        jk_close(handle);
+    }
+}
+
+#[test]
+fn transaction_rollback() {
+    let (dir, handle) = init();
+    let base = dir.path();
+    fs::write(base.join("a.txt"), "keep").unwrap();
+
+    unsafe {
+        assert_eq!(
+            jk_tx_rollback(handle, ptr::null_mut()),
+            JK_ERR_TX_NOT_ACTIVE
+        );
+        let mut tx = ptr::null_mut();
+        assert_eq!(
+            jk_tx_begin_named(handle, c("cleanup").as_ptr(), &mut tx),
+            JK_OK
+        );
+        assert!(!tx.is_null());
+        let mut second = ptr::null_mut();
+        assert_eq!(jk_tx_begin(handle, &mut second), JK_ERR_TX_CONFLICT);
+        assert!(second.is_null());
+        assert_eq!(jk_delete(handle, c("a.txt").as_ptr()), JK_OK);
+        assert_eq!(jk_tx_rollback(handle, tx), JK_OK);
+        jk_close(handle);
+    }
+    assert_eq!(fs::read_to_string(base.join("a.txt")).unwrap(), "keep");
+}
+
+#[test]
+fn transaction_commit_checks_the_token() {
+    let (dir, handle) = init();
+    fs::write(dir.path().join("a.txt"), "gone").unwrap();
+
+    unsafe {
+        let mut stale = ptr::null_mut();
+        assert_eq!(jk_tx_begin(handle, &mut stale), JK_OK);
+        assert_eq!(jk_tx_commit(handle, stale), JK_OK);
+
+        let mut tx = ptr::null_mut();
+        assert_eq!(jk_tx_begin(handle, &mut tx), JK_OK);
+        assert_eq!(jk_delete(handle, c("a.txt").as_ptr()), JK_OK);
+        let mut other = ptr::null_mut();
+        assert_eq!(jk_tx_begin(handle, &mut other), JK_ERR_TX_CONFLICT);
+        assert_eq!(jk_tx_commit(handle, ptr::null_mut()), JK_ERR_TX_NOT_ACTIVE);
+        assert_eq!(jk_tx_commit(handle, tx), JK_OK);
+        assert_eq!(jk_tx_begin(handle, ptr::null_mut()), JK_ERR_IO);
+        assert_eq!(last_error_code(), "invalid_argument");
+        jk_close(handle);
+    }
+    assert!(!dir.path().join("a.txt").exists());
+}
+
+#[test]
+fn obliterate_fills_the_proof() {
+    let (dir, handle) = init();
+    fs::write(dir.path().join("secret.txt"), "secret").unwrap();
+
+    let mut proof = JanusKeyObliterationProof {
+        content_hash: [0; 32],
+        nonce: [0; 32],
+        commitment: [0; 32],
+        overwrite_passes: 0,
+        passes_valid: 0,
+    };
+    unsafe {
+        assert_eq!(
+            jk_obliterate(handle, c("secret.txt").as_ptr(), &mut proof),
+            JK_OK
+        );
+        assert_eq!(
+            jk_obliterate(handle, c("secret.txt").as_ptr(), ptr::null_mut()),
+            JK_ERR_INVALID_PATH
+        );
+        jk_close(handle);
+    }
+    assert!(!dir.path().join("secret.txt").exists());
+    assert_eq!(
+        hex::encode(proof.content_hash),
+        januskey::ContentHash::from_bytes(b"secret").raw_hash()
+    );
+    assert!(proof.overwrite_passes > 0);
+    assert_eq!(proof.passes_valid, 1);
+    assert_eq!(std::mem::size_of::<JanusKeyObliterationProof>(), 112);
+}
+
+#[test]
+fn status_and_history_fill_the_buffer() {
+    let (dir, handle) = init();
+    fs::write(dir.path().join("a.txt"), "a").unwrap();
+    fs::write(dir.path().join("b.txt"), "b").unwrap();
+
+    unsafe {
+        assert_eq!(jk_delete(handle, c("a.txt").as_ptr()), JK_OK);
+        assert_eq!(jk_delete(handle, c("b.txt").as_ptr()), JK_OK);
+
+        let mut needed = 0;
+        assert_eq!(
+            jk_status(handle, ptr::null_mut(), 0, &mut needed),
+            JK_ERR_BUFFER_TOO_SMALL
+        );
+        assert_eq!(last_error_code(), "buffer_too_small");
+        let mut buf = vec![0 as std::ffi::c_char; needed];
+        assert_eq!(
+            jk_status(handle, buf.as_mut_ptr(), buf.len(), &mut needed),
+            JK_OK
+        );
+        let status: serde_json::Value =
+            serde_json::from_slice(CStr::from_ptr(buf.as_ptr()).to_bytes()).unwrap();
+        assert_eq!(status["operations"], 2);
+
+        let mut buf = vec![0 as std::ffi::c_char; 4096];
+        assert_eq!(
+            jk_history(handle, 1, buf.as_mut_ptr(), buf.len(), ptr::null_mut()),
+            JK_OK
+        );
+        let history: serde_json::Value =
+            serde_json::from_slice(CStr::from_ptr(buf.as_ptr()).to_bytes()).unwrap();
+        let history = history.as_array().unwrap();
+        assert_eq!(history.len(), 1);
+        assert!(history[0]["path"].as_str().unwrap().ends_with("b.txt"));
+        jk_close(handle);
+    }
+}
+
+#[test]
+fn errors_are_reported() {
+    let (_dir, handle) = init();
+
+    unsafe {
+        assert_eq!(
+            jk_delete(handle, c("missing.txt").as_ptr()),
+            JK_ERR_INVALID_PATH
+        );
+        assert!(last_error().contains("missing.txt"));
+        assert_eq!(last_error_code(), "file_not_found");
+
+        assert_eq!(jk_delete(handle, ptr::null()), JK_ERR_INVALID_PATH);
+        assert_eq!(last_error(), "path is null");
+        assert_eq!(last_error_code(), "invalid_argument");
+
+        assert_eq!(
+            jk_delete(ptr::null_mut(), c("a.txt").as_ptr()),
+            JK_ERR_NOT_INITIALIZED
+        );
+        assert_eq!(last_error_code(), "not_initialized");
+        jk_close(handle);
+    }
+
+    let other = tempfile::tempdir().unwrap();
+    let mut handle = ptr::null_mut();
+    let path = c(other.path().to_str().unwrap());
+    assert_eq!(
+        unsafe { jk_open(path.as_ptr(), &mut handle) },
+        JK_ERR_NOT_INITIALIZED
+    );
+    assert!(handle.is_null());
+}
+
+#[test]
+fn handle_is_shared_between_threads() {
+    let (dir, handle) = init();
+    let base = dir.path();
+    for i in 0..8 {
+        fs::write(base.join(format!("{}.txt", i)), "old").unwrap();
+    }
+
+    // Raw pointers are not Send; C callers share the address freely
+    let addr = handle as usize;
+    let threads: Vec<_> = (0..8)
+        .map(|i| {
+            std::thread::spawn(move || {
+                let handle = addr as *mut JanusKeyHandle;
+                let path = c(&format!("{}.txt", i));
+                let data = b"new";
+                unsafe { jk_modify(handle, path.as_ptr(), data.as_ptr(), data.len()) }
+            })
+        })
+        .collect();
+    for thread in threads {
+        assert_eq!(thread.join().unwrap(), JK_OK);
+    }
+    unsafe { jk_close(handle) };
+
+    // Every operation made it into the log
+    let jk = januskey::JanusKey::open(base).unwrap();
+    assert_eq!(jk.metadata_store.count(), 8);
+    for i in 0..8 {
+        assert_eq!(
+            fs::read_to_string(base.join(format!("{}.txt", i))).unwrap(),
+            "new"
+        );
+    }
+}
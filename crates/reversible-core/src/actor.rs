@@ -0,0 +1,171 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Actors: who an operation is recorded against
+// The OS user is always recorded; a declared actor is recorded beside it
+
+use crate::error::{Result, ReversibleError};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Environment variable naming the declared actor
+pub const ACTOR_ENV: &str = "JANUSKEY_ACTOR";
+
+/// Longest accepted declared actor name
+const MAX_ACTOR_LEN: usize = 128;
+
+/// The OS account running the process and, for service accounts and
+/// sudo, the person or system it acts for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Actor {
+    /// User the process runs as, from the OS
+    pub os_user: String,
+    /// Actor declared by the caller, if any
+    pub declared: Option<String>,
+}
+
+impl Actor {
+    /// The current OS user with no declared actor
+    pub fn os() -> Self {
+        Self {
+            os_user: whoami::username(),
+            declared: None,
+        }
+    }
+
+    /// Resolve with [`EnvActorResolver`], keeping only the OS user if the
+    /// declared actor is invalid. For records that must not fail.
+    pub fn from_env() -> Self {
+        EnvActorResolver::default()
+            .resolve()
+            .unwrap_or_else(|_| Self::os())
+    }
+
+    /// The current OS user acting for `declared`, which must be a valid
+    /// actor name (see [`validate_actor`])
+    pub fn declared(declared: &str) -> Result<Self> {
+        validate_actor(declared)?;
+        Ok(Self {
+            declared: Some(declared.to_string()),
+            ..Self::os()
+        })
+    }
+}
+
+impl fmt::Display for Actor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.declared {
+            Some(declared) if *declared != self.os_user => {
+                write!(f, "{} as {}", self.os_user, declared)
+            }
+            _ => write!(f, "{}", self.os_user),
+        }
+    }
+}
+
+/// Check a declared actor name: non-empty, at most 128 characters, and no
+/// whitespace or control characters that could forge log fields
+pub fn validate_actor(name: &str) -> Result<()> {
+    if name.is_empty() {
+        return Err(ReversibleError::InvalidActor("empty name".to_string()));
+    }
+    if name.chars().count() > MAX_ACTOR_LEN {
+        return Err(ReversibleError::InvalidActor(format!(
+            "longer than {} characters",
+            MAX_ACTOR_LEN
+        )));
+    }
+    if name.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err(ReversibleError::InvalidActor(format!(
+            "{:?} contains whitespace or control characters",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Decides the actor operations are recorded against
+pub trait ActorResolver: Send + Sync {
+    fn resolve(&self) -> Result<Actor>;
+}
+
+/// Default resolver: an explicit name, else `JANUSKEY_ACTOR`, else
+/// `SUDO_USER` when running as root.
+///
+/// `SUDO_USER` is only trusted from root, since any other user can set
+/// it; an explicit name or `JANUSKEY_ACTOR` is a claim and is recorded as
+/// such, next to the OS user, never in place of it.
+#[derive(Debug, Clone, Default)]
+pub struct EnvActorResolver {
+    /// Takes precedence over the environment (e.g. from `--actor`)
+    pub explicit: Option<String>,
+}
+
+impl EnvActorResolver {
+    pub fn new(explicit: Option<String>) -> Self {
+        Self { explicit }
+    }
+}
+
+impl ActorResolver for EnvActorResolver {
+    fn resolve(&self) -> Result<Actor> {
+        if let Some(name) = &self.explicit {
+            return Actor::declared(name);
+        }
+        if let Ok(name) = std::env::var(ACTOR_ENV) {
+            return Actor::declared(&name);
+        }
+
+        let os = Actor::os();
+        match std::env::var("SUDO_USER") {
+            Ok(name) if os.os_user == "root" && validate_actor(&name).is_ok() => Ok(Actor {
+                declared: Some(name),
+                ..os
+            }),
+            _ => Ok(os),
+        }
+    }
+}
+
+/// Resolver that always returns the same actor
+#[derive(Debug, Clone)]
+pub struct FixedActor(pub Actor);
+
+impl ActorResolver for FixedActor {
+    fn resolve(&self) -> Result<Actor> {
+        Ok(self.0.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_actor() {
+        assert!(validate_actor("alice@example.com").is_ok());
+        assert!(validate_actor("ci-bot").is_ok());
+        assert!(validate_actor("").is_err());
+        assert!(validate_actor("alice\nbob").is_err());
+        assert!(validate_actor("alice bob").is_err());
+        assert!(validate_actor(&"a".repeat(129)).is_err());
+    }
+
+    #[test]
+    fn test_explicit_actor_keeps_os_user() {
+        let actor = EnvActorResolver::new(Some("deploy-bot".to_string()))
+            .resolve()
+            .unwrap();
+        assert_eq!(actor.os_user, whoami::username());
+        assert_eq!(actor.declared.as_deref(), Some("deploy-bot"));
+        assert_eq!(
+            actor.to_string(),
+            format!("{} as deploy-bot", whoami::username())
+        );
+
+        assert!(EnvActorResolver::new(Some("bad name".to_string()))
+            .resolve()
+            .is_err());
+    }
+}
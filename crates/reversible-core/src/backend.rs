@@ -0,0 +1,1082 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Storage Backends
+// Where content blobs, the operation log and the transaction log persist
+
+use crate::content_store::ContentHash;
+use crate::error::{Result, ReversibleError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read, Seek, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// What a backend can do natively rather than by emulation.
+///
+/// The stores consult these before choosing how to work: an emulated
+/// append rewrites the whole file, so the operation log keeps shorter
+/// segments; without range reads a packed blob costs a read of its whole
+/// pack, so blobs stay loose; and features that cannot work at all (secure
+/// obliteration without in-place truncation) are refused up front with
+/// [`ReversibleError::Unsupported`] instead of failing partway.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// [`FileBackend::append`] adds to the file instead of rewriting it
+    pub append: bool,
+    /// [`FileBackend::rename`] replaces the destination atomically
+    pub atomic_rename: bool,
+    /// [`FileBackend::read_range`] reads only the bytes asked for
+    pub range_reads: bool,
+    /// Files can be overwritten and truncated in place
+    pub truncate: bool,
+    /// Files keep Unix permissions
+    pub permissions: bool,
+    /// Files can be copied without passing their data through this host
+    pub server_side_copy: bool,
+}
+
+impl Capabilities {
+    /// Nothing beyond whole-file reads and writes
+    pub const NONE: Self = Self {
+        append: false,
+        atomic_rename: false,
+        range_reads: false,
+        truncate: false,
+        permissions: false,
+        server_side_copy: false,
+    };
+
+    /// Everything, as the local filesystem does
+    pub const ALL: Self = Self {
+        append: true,
+        atomic_rename: true,
+        range_reads: true,
+        truncate: true,
+        permissions: true,
+        server_side_copy: true,
+    };
+
+    /// Each capability by name, with whether it is present
+    pub fn list(&self) -> [(&'static str, bool); 6] {
+        [
+            ("append", self.append),
+            ("atomic_rename", self.atomic_rename),
+            ("range_reads", self.range_reads),
+            ("truncate", self.truncate),
+            ("permissions", self.permissions),
+            ("server_side_copy", self.server_side_copy),
+        ]
+    }
+
+    /// Names of the capabilities in `needed` that `self` lacks
+    pub fn missing(&self, needed: &Capabilities) -> Vec<&'static str> {
+        self.list()
+            .into_iter()
+            .zip(needed.list())
+            .filter(|((_, has), (_, need))| *need && !has)
+            .map(|((name, _), _)| name)
+            .collect()
+    }
+}
+
+/// Refuse `feature` unless `backend` has every capability in `needed`
+pub fn require(backend: &dyn FileBackend, needed: &Capabilities, feature: &str) -> Result<()> {
+    let missing = backend.capabilities().missing(needed);
+    if missing.is_empty() {
+        return Ok(());
+    }
+    Err(ReversibleError::Unsupported(format!(
+        "{} needs {} from the storage backend, which the {} backend lacks",
+        feature,
+        missing.join(" and "),
+        backend.name()
+    )))
+}
+
+/// A file-like storage backend.
+///
+/// Paths are interpreted in the backend's own namespace: local paths for
+/// [`LocalBackend`], remote paths or object keys for remote backends.
+/// The stores in this crate only need whole-file reads and writes, so
+/// backends can be implemented over SFTP or object storage without
+/// seeking or partial writes.
+pub trait FileBackend: Send + Sync {
+    /// Short backend name for messages ("local", "ssh", "s3", ...)
+    fn name(&self) -> &'static str;
+
+    /// Read a whole file
+    fn read(&self, path: &Path) -> Result<Vec<u8>>;
+
+    /// Write a whole file, creating parent directories as needed
+    fn write(&self, path: &Path, content: &[u8]) -> Result<()>;
+
+    /// Append to a file, creating it (and parent directories) as needed.
+    ///
+    /// The default rewrites the whole file; backends with a native append
+    /// override it so appends are O(1) and never rewrite earlier bytes.
+    fn append(&self, path: &Path, content: &[u8]) -> Result<()> {
+        let mut existing = if self.exists(path) {
+            self.read(path)?
+        } else {
+            Vec::new()
+        };
+        existing.extend_from_slice(content);
+        self.write(path, &existing)
+    }
+
+    /// Flush a file to stable storage.
+    ///
+    /// Remote backends are durable once a write returns, so the default
+    /// does nothing; the local backend fsyncs.
+    fn sync(&self, _path: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    /// Store the local file `source` at `path`.
+    ///
+    /// The default reads it and calls [`FileBackend::write`]; the local
+    /// backend clones it instead (see [`clone_file`]).
+    fn copy_from_local(&self, source: &Path, path: &Path) -> Result<()> {
+        self.write(path, &fs::read(source)?)
+    }
+
+    /// Check whether a file exists
+    fn exists(&self, path: &Path) -> bool;
+
+    /// Remove a file
+    fn remove_file(&self, path: &Path) -> Result<()>;
+
+    /// Create a directory and all its parents
+    fn create_dir_all(&self, path: &Path) -> Result<()>;
+
+    /// Rename a file, replacing the destination
+    fn rename(&self, from: &Path, to: &Path) -> Result<()>;
+
+    /// Recursively list files under `dir` with their sizes in bytes
+    fn list_files(&self, dir: &Path) -> Result<Vec<(PathBuf, u64)>>;
+
+    /// Whether paths refer to the local filesystem
+    fn is_local(&self) -> bool {
+        false
+    }
+
+    /// What the backend does natively; see [`Capabilities`].
+    ///
+    /// The default claims nothing, matching the emulating defaults of
+    /// [`FileBackend::append`] and [`FileBackend::read_range`].
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::NONE
+    }
+
+    /// Open a file for reading as a stream.
+    ///
+    /// The default reads the whole file first; the local backend streams
+    /// it from disk.
+    fn open_read(&self, path: &Path) -> Result<Box<dyn Read + Send>> {
+        Ok(Box::new(std::io::Cursor::new(self.read(path)?)))
+    }
+
+    /// Read `len` bytes of a file starting at `offset`
+    fn read_range(&self, path: &Path, offset: u64, len: u64) -> Result<Vec<u8>> {
+        let content = self.read(path)?;
+        let start = usize::try_from(offset).unwrap_or(usize::MAX);
+        let end = usize::try_from(offset.saturating_add(len)).unwrap_or(usize::MAX);
+        content.get(start..end).map(<[u8]>::to_vec).ok_or_else(|| {
+            std::io::Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("{} is shorter than {} bytes", path.display(), offset + len),
+            )
+            .into()
+        })
+    }
+
+    /// Read a file as UTF-8 text, reading at most `limit` bytes
+    fn read_to_string(&self, path: &Path, limit: u64) -> Result<String> {
+        let mut content = self.read(path)?;
+        content.truncate(limit.try_into().unwrap_or(usize::MAX));
+        String::from_utf8(content).map_err(|e| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, e.utf8_error()).into()
+        })
+    }
+}
+
+/// Copy `from` to the new file `to`, cloning the data instead where the
+/// filesystem supports it (a reflink on Btrfs, XFS, APFS and ReFS) and
+/// falling back to an ordinary copy. Permissions are copied either way.
+pub fn clone_file(from: &Path, to: &Path) -> Result<()> {
+    if reflink_copy::reflink_or_copy(from, to)?.is_none() {
+        // Cloned rather than copied: carry permissions over as fs::copy does
+        fs::set_permissions(to, fs::metadata(from)?.permissions())?;
+    }
+    Ok(())
+}
+
+/// Whether files in the directory `dir` can be cloned instead of copied
+/// (see [`clone_file`]), found by cloning a scratch file there
+pub fn supports_reflink(dir: &Path) -> Result<bool> {
+    let id = uuid::Uuid::new_v4().simple();
+    let probe = dir.join(format!(".reflink-probe.{}", id));
+    let clone = dir.join(format!(".reflink-probe.{}.clone", id));
+    fs::write(&probe, b"januskey")?;
+    let cloned = reflink_copy::reflink(&probe, &clone).is_ok();
+    let _ = fs::remove_file(&clone);
+    fs::remove_file(&probe)?;
+    Ok(cloned)
+}
+
+/// Replace `path` with `content` so that a crash or full disk leaves
+/// either the old file or the new one, never a truncated mix.
+///
+/// The content goes to a temporary file beside `path`, which is synced,
+/// given the old file's permissions and renamed over it; the directory is
+/// then synced so the rename itself survives a crash. Returns a plain I/O
+/// error so stores with their own error types can use it.
+pub fn write_atomic(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path.file_name().ok_or_else(|| {
+        std::io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("cannot write {}: no file name", path.display()),
+        )
+    })?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(name);
+    temp_name.push(format!(".{}.tmp", uuid::Uuid::new_v4().simple()));
+    let temp = dir.join(temp_name);
+
+    let written = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)?;
+        file.write_all(content)?;
+        if let Ok(metadata) = fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.sync_all()?;
+        fs::rename(&temp, path)
+    })();
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+
+    // Directories cannot be opened for syncing on Windows; renames there
+    // are already durable once they return
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Move `from` to `to`, replacing it.
+///
+/// Where the two are on different filesystems (`EXDEV`), a regular file
+/// is copied, the copy read back and compared with the source, and only
+/// then is the source removed; the hash of the moved content is returned.
+/// Permissions and the modification time are carried over. Directories
+/// and symlinks cannot be moved across filesystems.
+pub fn move_file(from: &Path, to: &Path) -> Result<Option<ContentHash>> {
+    match fs::rename(from, to) {
+        Ok(()) => Ok(None),
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            tracing::debug!(from = %from.display(), to = %to.display(), "moving across filesystems by copy");
+            if !fs::symlink_metadata(from)?.is_file() {
+                return Err(ReversibleError::OperationFailed(format!(
+                    "cannot move {} to another filesystem: not a regular file",
+                    from.display()
+                )));
+            }
+            copy_verified(from, to).map(Some)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Copy `from` to `to`, then remove `from` once the copy reads back the
+/// same. A copy that does not is removed again.
+fn copy_verified(from: &Path, to: &Path) -> Result<ContentHash> {
+    let expected = ContentHash::from_file(from)?;
+    let modified = fs::metadata(from)?.modified()?;
+    fs::copy(from, to)?;
+    let actual = ContentHash::from_file(to)?;
+    if actual != expected {
+        let _ = fs::remove_file(to);
+        return Err(ReversibleError::ContentIntegrityError {
+            expected: expected.0,
+            actual: actual.0,
+        });
+    }
+    File::options()
+        .write(true)
+        .open(to)?
+        .set_modified(modified)?;
+    fs::remove_file(from)?;
+    Ok(expected)
+}
+
+/// Backend for the local filesystem
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalBackend;
+
+impl FileBackend for LocalBackend {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        Ok(fs::read(path)?)
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(write_atomic(path, content)?)
+    }
+
+    fn append(&self, path: &Path, content: &[u8]) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // A single O_APPEND write, so concurrent appenders never interleave
+        // within a record
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(content)?;
+        Ok(())
+    }
+
+    fn sync(&self, path: &Path) -> Result<()> {
+        File::open(path)?.sync_all()?;
+        Ok(())
+    }
+
+    fn copy_from_local(&self, source: &Path, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        clone_file(source, path)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        fs::create_dir_all(path)?;
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        move_file(from, to)?;
+        Ok(())
+    }
+
+    fn list_files(&self, dir: &Path) -> Result<Vec<(PathBuf, u64)>> {
+        let mut files = Vec::new();
+        for entry in walkdir::WalkDir::new(dir).into_iter().flatten() {
+            if entry.file_type().is_file() {
+                let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+                files.push((entry.into_path(), size));
+            }
+        }
+        Ok(files)
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::ALL
+    }
+
+    fn open_read(&self, path: &Path) -> Result<Box<dyn Read + Send>> {
+        Ok(Box::new(File::open(path)?))
+    }
+
+    fn read_range(&self, path: &Path, offset: u64, len: u64) -> Result<Vec<u8>> {
+        let mut file = File::open(path)?;
+        file.seek(std::io::SeekFrom::Start(offset))?;
+        let mut buf = vec![0u8; usize::try_from(len).unwrap_or(usize::MAX)];
+        file.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn read_to_string(&self, path: &Path, limit: u64) -> Result<String> {
+        let mut buf = String::new();
+        File::open(path)?.take(limit).read_to_string(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+/// How remote backend calls are retried after transient failures.
+///
+/// Delays double from `initial_backoff_ms` up to `max_backoff_ms`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Attempts per call, including the first (1 disables retries)
+    pub max_attempts: u32,
+    /// Delay before the first retry, in milliseconds
+    pub initial_backoff_ms: u64,
+    /// Upper bound on the delay between retries, in milliseconds
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_backoff_ms: 200,
+            max_backoff_ms: 5000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Delay before retry number `retry` (0 for the first retry)
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let ms = self
+            .initial_backoff_ms
+            .saturating_mul(1u64.checked_shl(retry).unwrap_or(u64::MAX))
+            .min(self.max_backoff_ms);
+        Duration::from_millis(ms)
+    }
+
+    /// Run `f` until it succeeds, fails permanently, or attempts run out.
+    ///
+    /// `f` must be idempotent: it may run again after a failure that
+    /// happened part-way through.
+    pub fn run<T>(&self, mut f: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut retry = 0;
+        loop {
+            match f() {
+                Err(e) if retry + 1 < self.max_attempts && is_transient(&e) => {
+                    let delay = self.backoff(retry);
+                    tracing::warn!(attempt = retry + 1, error = %e, ?delay, "retrying backend call");
+                    std::thread::sleep(delay);
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Whether an error may go away if the call is repeated.
+///
+/// Backend errors are reported by remote backends for failed sessions and
+/// requests, so they count as transient along with network I/O errors.
+pub fn is_transient(error: &ReversibleError) -> bool {
+    match error {
+        ReversibleError::Backend(_) => true,
+        ReversibleError::Io(e) => matches!(
+            e.kind(),
+            ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::ConnectionRefused
+                | ErrorKind::NotConnected
+                | ErrorKind::BrokenPipe
+                | ErrorKind::TimedOut
+                | ErrorKind::Interrupted
+                | ErrorKind::UnexpectedEof
+                | ErrorKind::WouldBlock
+        ),
+        _ => false,
+    }
+}
+
+/// Wraps a backend, retrying idempotent calls under a [`RetryPolicy`].
+///
+/// Reads, whole-file writes, removals, directory creation and listings are
+/// retried. Appends and renames are not: a failure may hide a success, and
+/// repeating them would duplicate a record or fail on the moved source.
+pub struct RetryBackend {
+    inner: Arc<dyn FileBackend>,
+    policy: RetryPolicy,
+}
+
+impl RetryBackend {
+    /// Retry calls to `inner` according to `policy`
+    pub fn new(inner: Arc<dyn FileBackend>, policy: RetryPolicy) -> Self {
+        Self { inner, policy }
+    }
+}
+
+impl FileBackend for RetryBackend {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        self.policy.run(|| self.inner.read(path))
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+        self.policy.run(|| self.inner.write(path, content))
+    }
+
+    fn append(&self, path: &Path, content: &[u8]) -> Result<()> {
+        self.inner.append(path, content)
+    }
+
+    fn sync(&self, path: &Path) -> Result<()> {
+        self.inner.sync(path)
+    }
+
+    fn copy_from_local(&self, source: &Path, path: &Path) -> Result<()> {
+        self.policy.run(|| self.inner.copy_from_local(source, path))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.inner.exists(path)
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        let mut first = true;
+        self.policy.run(|| {
+            let result = self.inner.remove_file(path);
+            let retried = !std::mem::replace(&mut first, false);
+            match result {
+                // An earlier attempt removed it before its reply was lost
+                Err(ReversibleError::Io(e)) if retried && e.kind() == ErrorKind::NotFound => Ok(()),
+                result => result,
+            }
+        })
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        self.policy.run(|| self.inner.create_dir_all(path))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.inner.rename(from, to)
+    }
+
+    fn list_files(&self, dir: &Path) -> Result<Vec<(PathBuf, u64)>> {
+        self.policy.run(|| self.inner.list_files(dir))
+    }
+
+    fn is_local(&self) -> bool {
+        self.inner.is_local()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    fn read_range(&self, path: &Path, offset: u64, len: u64) -> Result<Vec<u8>> {
+        self.policy.run(|| self.inner.read_range(path, offset, len))
+    }
+
+    fn read_to_string(&self, path: &Path, limit: u64) -> Result<String> {
+        self.policy.run(|| self.inner.read_to_string(path, limit))
+    }
+}
+
+/// Wraps a remote backend with a bounded local cache of whole files, so
+/// a content store whose blobs live remotely reads recently used ones
+/// without a round trip.
+///
+/// Files read or written are kept under `cache_dir`, and the least
+/// recently used are evicted to stay within `max_bytes`; recency survives
+/// restarts as the cached files' modification times. Only immutable files
+/// belong behind it, as content-addressed blobs are: a cached copy is never
+/// checked against the remote. Writes reach the remote before the cache,
+/// and failing to cache never fails a call.
+pub struct CachingBackend {
+    inner: Arc<dyn FileBackend>,
+    cache_dir: PathBuf,
+    max_bytes: u64,
+    /// What is cached, read from `cache_dir` on first use
+    index: Mutex<Option<CacheIndex>>,
+}
+
+/// The files in a [`CachingBackend`]'s cache, least recently used first
+#[derive(Default)]
+struct CacheIndex {
+    /// Cached file to its size and the tick it was last used at
+    files: HashMap<PathBuf, (u64, u64)>,
+    bytes: u64,
+    clock: u64,
+}
+
+impl CacheIndex {
+    /// Index the files under `dir`, oldest modification first, removing
+    /// temporaries an interrupted write left
+    fn load(dir: &Path) -> Self {
+        let mut found: Vec<(SystemTime, PathBuf, u64)> = Vec::new();
+        for entry in walkdir::WalkDir::new(dir).into_iter().flatten() {
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy();
+            if name.starts_with('.') && name.ends_with(".tmp") {
+                let _ = fs::remove_file(entry.path());
+                continue;
+            }
+            if let Ok(metadata) = entry.metadata() {
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                found.push((modified, entry.into_path(), metadata.len()));
+            }
+        }
+        found.sort();
+        let mut index = Self::default();
+        for (_, path, len) in found {
+            index.touch(&path, len);
+        }
+        index
+    }
+
+    fn touch(&mut self, path: &Path, len: u64) {
+        self.clock += 1;
+        if let Some((old, _)) = self.files.insert(path.to_path_buf(), (len, self.clock)) {
+            self.bytes -= old;
+        }
+        self.bytes += len;
+    }
+
+    fn remove(&mut self, path: &Path) {
+        if let Some((len, _)) = self.files.remove(path) {
+            self.bytes -= len;
+        }
+    }
+
+    /// Drop the least recently used files until at most `max_bytes` are
+    /// cached, returning them for removal
+    fn evict(&mut self, max_bytes: u64) -> Vec<PathBuf> {
+        let mut evicted = Vec::new();
+        while self.bytes > max_bytes {
+            let Some(oldest) = self
+                .files
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(path, _)| path.clone())
+            else {
+                break;
+            };
+            self.remove(&oldest);
+            evicted.push(oldest);
+        }
+        evicted
+    }
+}
+
+impl CachingBackend {
+    /// Cache up to `max_bytes` of the files read from or written to
+    /// `inner` in the local directory `cache_dir`
+    pub fn new(inner: Arc<dyn FileBackend>, cache_dir: PathBuf, max_bytes: u64) -> Self {
+        Self {
+            inner,
+            cache_dir,
+            max_bytes,
+            index: Mutex::new(None),
+        }
+    }
+
+    /// Bytes currently cached
+    pub fn cached_bytes(&self) -> u64 {
+        self.with_index(|index| index.bytes)
+    }
+
+    fn with_index<T>(&self, f: impl FnOnce(&mut CacheIndex) -> T) -> T {
+        let mut index = self.index.lock().unwrap_or_else(|e| e.into_inner());
+        f(index.get_or_insert_with(|| CacheIndex::load(&self.cache_dir)))
+    }
+
+    /// Where the cached copy of the backend's `path` lives
+    fn cache_path(&self, path: &Path) -> PathBuf {
+        path.components()
+            .filter_map(|part| match part {
+                Component::Normal(part) => Some(part),
+                _ => None,
+            })
+            .fold(self.cache_dir.clone(), |dir, part| dir.join(part))
+    }
+
+    /// The cached copy of `path`, marking it used
+    fn cached(&self, path: &Path) -> Option<Vec<u8>> {
+        let cached = self.cache_path(path);
+        let content = fs::read(&cached).ok()?;
+        self.with_index(|index| index.touch(&cached, content.len() as u64));
+        // Best effort: recency only orders eviction
+        let _ = File::options()
+            .write(true)
+            .open(&cached)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        Some(content)
+    }
+
+    /// Cache `content` as `path`, evicting what no longer fits
+    fn keep(&self, path: &Path, content: &[u8]) {
+        let len = content.len() as u64;
+        if len > self.max_bytes {
+            return;
+        }
+        let cached = self.cache_path(path);
+        let written = match cached.parent() {
+            Some(dir) => fs::create_dir_all(dir),
+            None => Ok(()),
+        }
+        .and_then(|()| write_atomic(&cached, content));
+        if let Err(e) = written {
+            tracing::warn!("failed to cache {}: {}", path.display(), e);
+            return;
+        }
+        let evicted = self.with_index(|index| {
+            index.touch(&cached, len);
+            index.evict(self.max_bytes)
+        });
+        for path in evicted {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// Drop any cached copy of `path`
+    fn forget(&self, path: &Path) {
+        let cached = self.cache_path(path);
+        self.with_index(|index| index.remove(&cached));
+        if cached.exists() {
+            let _ = fs::remove_file(&cached);
+        }
+    }
+}
+
+impl FileBackend for CachingBackend {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        if let Some(content) = self.cached(path) {
+            return Ok(content);
+        }
+        let content = self.inner.read(path)?;
+        self.keep(path, &content);
+        Ok(content)
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+        self.inner.write(path, content)?;
+        self.keep(path, content);
+        Ok(())
+    }
+
+    fn append(&self, path: &Path, content: &[u8]) -> Result<()> {
+        self.forget(path);
+        self.inner.append(path, content)
+    }
+
+    fn sync(&self, path: &Path) -> Result<()> {
+        self.inner.sync(path)
+    }
+
+    fn copy_from_local(&self, source: &Path, path: &Path) -> Result<()> {
+        self.write(path, &fs::read(source)?)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.cache_path(path).is_file() || self.inner.exists(path)
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        self.forget(path);
+        self.inner.remove_file(path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        self.inner.create_dir_all(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.forget(from);
+        self.forget(to);
+        self.inner.rename(from, to)
+    }
+
+    fn list_files(&self, dir: &Path) -> Result<Vec<(PathBuf, u64)>> {
+        self.inner.list_files(dir)
+    }
+
+    fn is_local(&self) -> bool {
+        self.inner.is_local()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    fn read_range(&self, path: &Path, offset: u64, len: u64) -> Result<Vec<u8>> {
+        self.inner.read_range(path, offset, len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tempfile::TempDir;
+
+    /// Local backend whose calls fail with a reset connection `failures` times
+    struct Flaky {
+        failures: AtomicU32,
+    }
+
+    impl Flaky {
+        fn new(failures: u32) -> Arc<Self> {
+            Arc::new(Self {
+                failures: AtomicU32::new(failures),
+            })
+        }
+
+        fn fail(&self) -> Result<()> {
+            match self
+                .failures
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            {
+                Ok(_) => Err(std::io::Error::from(ErrorKind::ConnectionReset).into()),
+                Err(_) => Ok(()),
+            }
+        }
+    }
+
+    impl FileBackend for Flaky {
+        fn name(&self) -> &'static str {
+            "flaky"
+        }
+        fn read(&self, path: &Path) -> Result<Vec<u8>> {
+            self.fail()?;
+            LocalBackend.read(path)
+        }
+        fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+            self.fail()?;
+            LocalBackend.write(path, content)
+        }
+        fn append(&self, path: &Path, content: &[u8]) -> Result<()> {
+            self.fail()?;
+            LocalBackend.append(path, content)
+        }
+        fn exists(&self, path: &Path) -> bool {
+            LocalBackend.exists(path)
+        }
+        fn remove_file(&self, path: &Path) -> Result<()> {
+            self.fail()?;
+            LocalBackend.remove_file(path)
+        }
+        fn create_dir_all(&self, path: &Path) -> Result<()> {
+            LocalBackend.create_dir_all(path)
+        }
+        fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+            LocalBackend.rename(from, to)
+        }
+        fn list_files(&self, dir: &Path) -> Result<Vec<(PathBuf, u64)>> {
+            LocalBackend.list_files(dir)
+        }
+    }
+
+    fn quick(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 2,
+        }
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_and_keeps_permissions() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("store.json");
+        write_atomic(&path, b"first").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        }
+
+        write_atomic(&path, b"second").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // No temporary files are left behind
+        let names: Vec<_> = fs::read_dir(tmp.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec![std::ffi::OsString::from("store.json")]);
+
+        // A failed write leaves the old file alone
+        assert!(write_atomic(&tmp.path().join("missing/store.json"), b"x").is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+    }
+
+    #[test]
+    fn test_local_backend_roundtrip() {
+        let tmp = TempDir::new().unwrap();
+        let backend = LocalBackend;
+        let path = tmp.path().join("a/b/file.txt");
+
+        backend.write(&path, b"hel").unwrap();
+        backend.append(&path, b"lo").unwrap();
+        assert!(backend.exists(&path));
+        assert_eq!(backend.read(&path).unwrap(), b"hello");
+        assert_eq!(backend.read_to_string(&path, 3).unwrap(), "hel");
+
+        let renamed = tmp.path().join("a/renamed.txt");
+        backend.rename(&path, &renamed).unwrap();
+        assert!(!backend.exists(&path));
+
+        let files = backend.list_files(tmp.path()).unwrap();
+        assert_eq!(files, vec![(renamed.clone(), 5)]);
+
+        backend.remove_file(&renamed).unwrap();
+        assert!(!backend.exists(&renamed));
+    }
+
+    #[test]
+    fn test_capabilities_and_require() {
+        let truncate = Capabilities {
+            truncate: true,
+            ..Capabilities::NONE
+        };
+        assert_eq!(LocalBackend.capabilities(), Capabilities::ALL);
+        assert!(require(&LocalBackend, &truncate, "secure obliteration").is_ok());
+
+        // Wrappers report what they wrap; a backend overriding nothing
+        // claims nothing
+        let retrying = RetryBackend::new(Arc::new(LocalBackend), quick(1));
+        assert_eq!(retrying.capabilities(), Capabilities::ALL);
+        let flaky = Flaky::new(0);
+        assert_eq!(flaky.capabilities(), Capabilities::NONE);
+        assert_eq!(
+            Capabilities::NONE.missing(&Capabilities::ALL).len(),
+            Capabilities::ALL.list().len()
+        );
+
+        let error = require(flaky.as_ref(), &truncate, "secure obliteration").unwrap_err();
+        assert_eq!(error.code(), "unsupported");
+        assert_eq!(
+            error.to_string(),
+            "Not supported: secure obliteration needs truncate from the storage backend, \
+             which the flaky backend lacks"
+        );
+    }
+
+    #[test]
+    fn test_move_file_copy_fallback() {
+        let tmp = TempDir::new().unwrap();
+        let from = tmp.path().join("from.txt");
+        let to = tmp.path().join("to.txt");
+        fs::write(&from, "moved").unwrap();
+        let modified = std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        File::options()
+            .write(true)
+            .open(&from)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+
+        // What a move across filesystems falls back to
+        let hash = copy_verified(&from, &to).unwrap();
+        assert_eq!(hash, ContentHash::from_bytes(b"moved"));
+        assert!(!from.exists());
+        assert_eq!(fs::read_to_string(&to).unwrap(), "moved");
+        assert_eq!(fs::metadata(&to).unwrap().modified().unwrap(), modified);
+
+        // A move on one filesystem is a rename
+        assert_eq!(move_file(&to, &from).unwrap(), None);
+        assert!(from.exists() && !to.exists());
+    }
+
+    #[test]
+    fn test_retry_backoff_doubles_up_to_cap() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::from_millis(200));
+        assert_eq!(policy.backoff(2), Duration::from_millis(800));
+        assert_eq!(policy.backoff(10), Duration::from_millis(5000));
+        assert_eq!(policy.backoff(200), Duration::from_millis(5000));
+    }
+
+    #[test]
+    fn test_retry_backend_retries_idempotent_calls() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("file.txt");
+
+        let flaky = Flaky::new(2);
+        let backend = RetryBackend::new(flaky.clone(), quick(3));
+        backend.write(&path, b"data").unwrap();
+        assert_eq!(flaky.failures.load(Ordering::SeqCst), 0);
+
+        // Appends are never repeated
+        flaky.failures.store(1, Ordering::SeqCst);
+        assert!(backend.append(&path, b"more").is_err());
+        assert_eq!(backend.read(&path).unwrap(), b"data");
+
+        // Attempts run out
+        flaky.failures.store(3, Ordering::SeqCst);
+        assert!(backend.read(&path).is_err());
+        assert!(!is_transient(
+            &backend.read(&tmp.path().join("missing")).unwrap_err()
+        ));
+    }
+
+    #[test]
+    fn test_caching_backend_evicts_least_recently_used() {
+        let remote = TempDir::new().unwrap();
+        let cache = TempDir::new().unwrap();
+        let backend = CachingBackend::new(Arc::new(LocalBackend), cache.path().to_path_buf(), 10);
+        let path = |name: &str| remote.path().join(name);
+        let cached = |name: &str| backend.cache_path(&path(name)).exists();
+
+        backend.write(&path("a"), b"aaaa").unwrap();
+        backend.write(&path("b"), b"bbbb").unwrap();
+        assert_eq!(backend.read(&path("a")).unwrap(), b"aaaa");
+        backend.write(&path("c"), b"cccc").unwrap();
+        assert!(cached("a") && !cached("b") && cached("c"));
+        assert_eq!(backend.cached_bytes(), 8);
+
+        // Served from the cache, or fetched and cached
+        fs::remove_file(path("a")).unwrap();
+        assert_eq!(backend.read(&path("a")).unwrap(), b"aaaa");
+        assert_eq!(backend.read(&path("b")).unwrap(), b"bbbb");
+        assert!(cached("a") && cached("b") && !cached("c"));
+
+        // Too large to cache at all
+        backend.write(&path("big"), &[0u8; 11]).unwrap();
+        assert!(!cached("big") && path("big").exists());
+
+        backend.remove_file(&path("b")).unwrap();
+        assert!(!cached("b") && !backend.exists(&path("b")));
+
+        // A new instance picks up what is cached
+        let reopened = CachingBackend::new(Arc::new(LocalBackend), cache.path().to_path_buf(), 10);
+        assert_eq!(reopened.cached_bytes(), 4);
+    }
+}
@@ -2,33 +2,118 @@
 // Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
 // SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
 //
-// Content-Addressed Storage with SHA256 hashing
+// Content-Addressed Storage with SHA256 or BLAKE3 hashing
 // Provides deduplication and integrity verification
 
+use crate::backend::{FileBackend, LocalBackend};
+use crate::delta::Delta;
 use crate::error::{Result, ReversibleError};
+use crate::events::{Event, EventBus};
+use crate::mapped::FileContent;
+use crate::pack::{self, ObjectKind, PackEntry, PackTable, PACK_DIR, PACK_MAX_BYTES};
 use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
 use flate2::Compression;
 use sha2::{Digest, Sha256};
-use std::fs::{self, File};
+use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
 
-/// SHA256 content hash for content-addressed storage.
+/// Loose blobs up to this size are folded into packs by
+/// [`ContentStore::pack`] unless told otherwise
+pub const PACK_LOOSE_MAX_BYTES: u64 = 64 * 1024;
+
+/// Suffix of the pointer left in place of a loose object offloaded to the
+/// cold tier; it holds the object's size
+pub const COLD_POINTER_SUFFIX: &str = ".cold";
+
+/// Hash algorithm naming content in a store. Each [`ContentHash`] carries
+/// its algorithm, so a store may hold blobs of both while migrating.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    /// SHA-256, the default and the only algorithm of older stores
+    #[default]
+    Sha256,
+    /// BLAKE3, several times faster on large content
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// Name used as the hash prefix (`sha256`, `blake3`)
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Blake3 => "blake3",
+        }
+    }
+
+    /// The algorithm named `name`, if it is known
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sha256" => Some(Self::Sha256),
+            "blake3" => Some(Self::Blake3),
+            _ => None,
+        }
+    }
+
+    /// Hash `content`
+    pub fn hash(self, content: &[u8]) -> ContentHash {
+        let raw = match self {
+            Self::Sha256 => hex::encode(Sha256::digest(content)),
+            Self::Blake3 => blake3::hash(content).to_hex().to_string(),
+        };
+        ContentHash(format!("{}:{}", self.name(), raw))
+    }
+
+    /// Hash a file's content in a streaming pass
+    pub fn hash_file(self, path: &Path) -> Result<ContentHash> {
+        let mut file = fs::File::open(path)?;
+        let raw = match self {
+            Self::Sha256 => {
+                let mut hasher = Sha256::new();
+                std::io::copy(&mut file, &mut hasher)?;
+                hex::encode(hasher.finalize())
+            }
+            Self::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                std::io::copy(&mut file, &mut hasher)?;
+                hasher.finalize().to_hex().to_string()
+            }
+        };
+        Ok(ContentHash(format!("{}:{}", self.name(), raw)))
+    }
+}
+
+impl std::fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Content hash for content-addressed storage.
 ///
-/// Format: `sha256:<hex-encoded-hash>`
+/// Format: `<algorithm>:<hex-encoded-hash>`, e.g. `sha256:...` or
+/// `blake3:...`
 ///
-/// Corresponds to ochrance's `Hash` type with `algorithm = SHA256`.
+/// Corresponds to ochrance's `Hash` type.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub struct ContentHash(pub String);
 
 impl ContentHash {
-    /// Create hash from content bytes
+    /// Create a SHA-256 hash from content bytes
     pub fn from_bytes(content: &[u8]) -> Self {
-        let mut hasher = Sha256::new();
-        hasher.update(content);
-        let hash = hasher.finalize();
-        Self(format!("sha256:{}", hex::encode(hash)))
+        HashAlgorithm::Sha256.hash(content)
+    }
+
+    /// SHA-256 hash of a file's content, in a streaming pass
+    pub fn from_file(path: &Path) -> Result<Self> {
+        HashAlgorithm::Sha256.hash_file(path)
     }
 
     /// Create hash from string content
@@ -38,18 +123,39 @@ pub fn from_string(s: &str) -> Self {
 
     /// Get the raw hash portion (without algorithm prefix)
     pub fn raw_hash(&self) -> &str {
-        self.0.strip_prefix("sha256:").unwrap_or(&self.0)
+        match self.0.split_once(':') {
+            Some((name, raw)) if HashAlgorithm::from_name(name).is_some() => raw,
+            _ => &self.0,
+        }
     }
 
-    /// Get the algorithm name
+    /// Get the algorithm name; hashes without a prefix are SHA-256
     pub fn algorithm(&self) -> &str {
-        "sha256"
+        match self.0.split_once(':') {
+            Some((name, _)) => name,
+            None => HashAlgorithm::Sha256.name(),
+        }
+    }
+
+    /// The algorithm, if it is one this build knows
+    pub fn hash_algorithm(&self) -> Option<HashAlgorithm> {
+        HashAlgorithm::from_name(self.algorithm())
+    }
+
+    /// Hash `content` with this hash's algorithm, so the two compare
+    pub fn rehash(&self, content: &[u8]) -> Self {
+        self.hash_algorithm().unwrap_or_default().hash(content)
+    }
+
+    /// Hash a file with this hash's algorithm
+    pub fn rehash_file(&self, path: &Path) -> Result<Self> {
+        self.hash_algorithm().unwrap_or_default().hash_file(path)
     }
 
     /// Verify content matches this hash
     pub fn verify(&self, content: &[u8]) -> bool {
-        let computed = Self::from_bytes(content);
-        self.0 == computed.0
+        self.hash_algorithm()
+            .is_some_and(|algorithm| algorithm.hash(content) == *self)
     }
 }
 
@@ -59,25 +165,282 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     }
 }
 
+/// Where a stored object lives
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlobLocation {
+    /// A file of its own
+    Loose(PathBuf),
+    /// A byte range of a pack file
+    Packed {
+        pack: PathBuf,
+        offset: u64,
+        len: u64,
+    },
+    /// Offloaded to the cold tier, with a pointer left in the store
+    Cold {
+        pointer: PathBuf,
+        /// Path on the cold tier's backend
+        path: PathBuf,
+    },
+}
+
+/// A second backend that loose objects are offloaded to (see
+/// [`ContentStore::offload`]), mirroring the store's layout under its own
+/// root. It is connected on first use, so a store whose reads never reach
+/// the cold tier works offline.
+pub struct ColdTier {
+    root: PathBuf,
+    connect: Box<dyn Fn() -> Result<Arc<dyn FileBackend>> + Send + Sync>,
+    backend: OnceLock<Arc<dyn FileBackend>>,
+}
+
+impl ColdTier {
+    /// A cold tier rooted at `root` on the backend `connect` returns
+    pub fn new(
+        root: PathBuf,
+        connect: impl Fn() -> Result<Arc<dyn FileBackend>> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            root,
+            connect: Box::new(connect),
+            backend: OnceLock::new(),
+        }
+    }
+
+    /// Root of the offloaded objects on the backend
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The backend, connecting to it if this is its first use
+    pub fn backend(&self) -> Result<&Arc<dyn FileBackend>> {
+        if let Some(backend) = self.backend.get() {
+            return Ok(backend);
+        }
+        let backend = (self.connect)()?;
+        Ok(self.backend.get_or_init(|| backend))
+    }
+}
+
+/// An index over stored content, such as a search index, kept up to date
+/// by a [`ContentStore`] it is attached to with
+/// [`ContentStore::with_index`]. Failures are logged, not returned to
+/// whoever stored or deleted the blob, so an index must tolerate missing
+/// entries.
+pub trait ContentIndex: Send + Sync {
+    /// `content`, stored as `hash`, is new to the store
+    fn insert(&self, hash: &ContentHash, content: &[u8]) -> Result<()>;
+
+    /// `hashes` have been deleted from the store
+    fn remove(&self, hashes: &[ContentHash]) -> Result<()>;
+}
+
 /// Content-addressed storage for file content.
 ///
 /// Stores content by SHA256 hash with optional gzip compression.
 /// Automatic deduplication: identical content is stored once.
 ///
+/// With delta storage enabled, [`ContentStore::store_delta`] keeps a
+/// binary delta against an existing full blob instead of a second full
+/// copy. Delta objects live next to full blobs with a `.delta` suffix and
+/// are reconstructed transparently by [`ContentStore::retrieve`].
+///
+/// Blobs are read and written through a [`FileBackend`], so the store can
+/// live on the local disk or on a remote backend.
+///
+/// New blobs are written loose, one file each; [`ContentStore::pack`]
+/// moves small ones into packfiles under `pack/` (see [`crate::pack`]).
+/// Lookups find a blob either way, through [`ContentStore::locate`].
+///
 /// This is the shared storage backend that both januskey-cli and
 /// valence-shell use for reversible operation data.
 pub struct ContentStore {
+    /// Backend holding the blobs
+    backend: Arc<dyn FileBackend>,
     /// Root directory for content storage
     root: PathBuf,
     /// Whether to compress stored content
     compression: bool,
+    /// Whether `store_delta` may store deltas instead of full content
+    delta: bool,
+    /// Algorithm naming new blobs
+    algorithm: HashAlgorithm,
+    /// Whether large files are memory-mapped rather than read
+    mmap: bool,
+    /// Refuse new blobs that would take the store past this many bytes
+    max_store_bytes: Option<u64>,
+    /// Refuse to store content larger than this many bytes
+    max_blob_bytes: Option<u64>,
+    /// Where new blobs are announced
+    events: EventBus,
+    /// Told about every blob stored and deleted
+    index: Option<Arc<dyn ContentIndex>>,
+    /// Where loose objects are offloaded to
+    cold: Option<ColdTier>,
+    /// Pack indexes, read on first use
+    packs: Mutex<Option<Arc<PackTable>>>,
+    /// Bytes of new objects written since the store was opened
+    written: AtomicU64,
 }
 
 impl ContentStore {
     /// Create or open a content store at the given path
     pub fn new(root: PathBuf, compression: bool) -> Result<Self> {
-        fs::create_dir_all(&root)?;
-        Ok(Self { root, compression })
+        Self::new_with_backend(Arc::new(LocalBackend), root, compression)
+    }
+
+    /// Create or open a content store at `root` on the given backend
+    pub fn new_with_backend(
+        backend: Arc<dyn FileBackend>,
+        root: PathBuf,
+        compression: bool,
+    ) -> Result<Self> {
+        backend.create_dir_all(&root)?;
+        Ok(Self {
+            backend,
+            root,
+            compression,
+            delta: false,
+            algorithm: HashAlgorithm::default(),
+            mmap: false,
+            max_store_bytes: None,
+            max_blob_bytes: None,
+            events: EventBus::default(),
+            index: None,
+            cold: None,
+            packs: Mutex::new(None),
+            written: AtomicU64::new(0),
+        })
+    }
+
+    /// Publish [`Event::ContentStored`] for each new blob on `events`
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Keep `index` up to date with the blobs this store writes and
+    /// deletes
+    pub fn with_index(mut self, index: Arc<dyn ContentIndex>) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    /// Offload loose objects to `cold` with [`ContentStore::offload`], and
+    /// read them back from there when needed
+    pub fn with_cold_tier(mut self, cold: ColdTier) -> Self {
+        self.cold = Some(cold);
+        self
+    }
+
+    /// The cold tier, if one is configured
+    pub fn cold_tier(&self) -> Option<&ColdTier> {
+        self.cold.as_ref()
+    }
+
+    /// Limit the total stored bytes and the size of any one blob
+    pub fn with_limits(
+        mut self,
+        max_store_bytes: Option<u64>,
+        max_blob_bytes: Option<u64>,
+    ) -> Self {
+        self.max_store_bytes = max_store_bytes;
+        self.max_blob_bytes = max_blob_bytes;
+        self
+    }
+
+    /// Bytes that can still be stored under the store limit, if there is one
+    pub fn headroom(&self) -> Result<Option<u64>> {
+        match self.max_store_bytes {
+            Some(max) => Ok(Some(max.saturating_sub(self.total_size()?))),
+            None => Ok(None),
+        }
+    }
+
+    /// Fail unless `content_len` bytes of content, written as `stored`
+    /// bytes, fit within the limits
+    fn check_limits(&self, content_len: u64, stored: u64) -> Result<()> {
+        if let Some(max) = self.max_blob_bytes {
+            if content_len > max {
+                return Err(ReversibleError::QuotaExceeded(format!(
+                    "content of {} bytes is over the {} byte limit per operation",
+                    content_len, max
+                )));
+            }
+        }
+        if let Some(headroom) = self.headroom()? {
+            if stored > headroom {
+                return Err(ReversibleError::QuotaExceeded(format!(
+                    "storing {} bytes would exceed the {} byte store limit ({} bytes free)",
+                    stored,
+                    self.max_store_bytes.unwrap_or_default(),
+                    headroom
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Enable or disable delta storage
+    pub fn with_delta(mut self, enabled: bool) -> Self {
+        self.delta = enabled;
+        self
+    }
+
+    /// Whether delta storage is enabled
+    pub fn delta_enabled(&self) -> bool {
+        self.delta
+    }
+
+    /// Name new blobs with `algorithm`. Blobs already stored keep their
+    /// hashes and stay readable.
+    pub fn with_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Algorithm naming new blobs
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Hash `content` as this store would name it
+    pub fn hash(&self, content: &[u8]) -> ContentHash {
+        self.algorithm.hash(content)
+    }
+
+    /// Memory-map large files instead of reading them (see
+    /// [`FileContent`]). Off by default; keep it off for network
+    /// filesystems.
+    pub fn with_mmap(mut self, enabled: bool) -> Self {
+        self.mmap = enabled;
+        self
+    }
+
+    /// Whether large files are memory-mapped
+    pub fn mmap_enabled(&self) -> bool {
+        self.mmap
+    }
+
+    /// Read a file to hash, store or compare, mapping it if it is large
+    /// and mapping is enabled
+    pub fn read_file(&self, path: &Path) -> Result<FileContent> {
+        FileContent::open(path, self.mmap)
+    }
+
+    /// Hash a file as this store would name its content: from a map if
+    /// mapping is enabled, else in a streaming pass
+    pub fn hash_file(&self, path: &Path) -> Result<ContentHash> {
+        if self.mmap {
+            Ok(self.hash(&self.read_file(path)?))
+        } else {
+            self.algorithm.hash_file(path)
+        }
+    }
+
+    /// Get the backend holding this store's blobs
+    pub fn backend(&self) -> &Arc<dyn FileBackend> {
+        &self.backend
     }
 
     /// Get the root path of this content store
@@ -85,20 +448,136 @@ pub fn root(&self) -> &Path {
         &self.root
     }
 
+    /// Directory holding the objects of `hash`'s algorithm and the file
+    /// name left after its 2-char prefix directory. SHA-256 objects sit
+    /// directly under the root, as they always have; others under a
+    /// directory named after their algorithm.
+    fn object_dir<'a>(&self, hash: &'a ContentHash) -> (PathBuf, &'a str) {
+        let raw = hash.raw_hash();
+        let (dir, file) = raw.split_at(2.min(raw.len()));
+        let root = match hash.hash_algorithm() {
+            Some(HashAlgorithm::Sha256) | None => self.root.clone(),
+            Some(algorithm) => self.root.join(algorithm.name()),
+        };
+        (root.join(dir), file)
+    }
+
     /// Get filesystem path for a content hash.
     ///
     /// Uses a 2-char prefix directory for distribution (git-style layout).
     pub fn content_path(&self, hash: &ContentHash) -> PathBuf {
-        let raw = hash.raw_hash();
-        // Use first 2 chars as directory for distribution
-        let (dir, file) = raw.split_at(2.min(raw.len()));
-        let mut path = self.root.join(dir);
+        let (dir, file) = self.object_dir(hash);
         if self.compression {
-            path = path.join(format!("{}.gz", file));
+            dir.join(format!("{}.gz", file))
+        } else {
+            dir.join(file)
+        }
+    }
+
+    /// Get filesystem path for the delta object of a content hash
+    pub fn delta_path(&self, hash: &ContentHash) -> PathBuf {
+        let (dir, file) = self.object_dir(hash);
+        dir.join(format!("{}.delta", file))
+    }
+
+    /// Get the path of a hash's loose file, whichever form it is stored
+    /// in; `None` if it is packed or absent (see [`Self::locate`])
+    pub fn stored_path(&self, hash: &ContentHash) -> Option<PathBuf> {
+        [self.content_path(hash), self.delta_path(hash)]
+            .into_iter()
+            .find(|p| self.backend.exists(p))
+    }
+
+    /// Find where a hash is stored: its loose file or its range of a pack
+    pub fn locate(&self, hash: &ContentHash) -> Result<Option<BlobLocation>> {
+        if let Some(path) = self.stored_path(hash) {
+            return Ok(Some(BlobLocation::Loose(path)));
+        }
+        if let Some((pointer, loose)) = self.offloaded_object(hash) {
+            let path = self.cold_path(&loose);
+            return Ok(Some(BlobLocation::Cold { pointer, path }));
+        }
+        Ok(self
+            .packed(hash)?
+            .map(|(pack, entry)| BlobLocation::Packed {
+                pack,
+                offset: entry.offset,
+                len: entry.len,
+            }))
+    }
+
+    /// Check whether a hash is stored as full content (not as a delta)
+    pub fn is_full(&self, hash: &ContentHash) -> bool {
+        self.backend.exists(&self.content_path(hash))
+            || self.backend.exists(&cold_pointer(&self.content_path(hash)))
+            || matches!(self.packed(hash), Ok(Some((_, entry))) if entry.kind != ObjectKind::Delta)
+    }
+
+    /// Get the base hash of a delta-stored blob, if it is stored as a delta
+    pub fn delta_base(&self, hash: &ContentHash) -> Result<Option<ContentHash>> {
+        let path = self.delta_path(hash);
+        let object = if self.backend.exists(&path) {
+            self.backend.read(&path)?
+        } else if self.backend.exists(&cold_pointer(&path)) {
+            self.read_cold(&path)?
         } else {
-            path = path.join(file);
+            match self.packed(hash)? {
+                Some((pack, entry)) if entry.kind == ObjectKind::Delta => {
+                    self.backend.read_range(&pack, entry.offset, entry.len)?
+                }
+                _ => return Ok(None),
+            }
+        };
+        let (base, _) = parse_delta_object(hash, &object)?;
+        Ok(Some(base))
+    }
+
+    /// The pack table, reading the indexes if this is its first use
+    fn pack_table(&self) -> Result<Arc<PackTable>> {
+        let mut packs = self.packs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(table) = packs.as_ref() {
+            return Ok(table.clone());
         }
-        path
+        let table = Arc::new(PackTable::load(
+            self.backend.as_ref(),
+            &self.root.join(PACK_DIR),
+        )?);
+        *packs = Some(table.clone());
+        Ok(table)
+    }
+
+    /// Forget the pack table after packs changed
+    fn invalidate_packs(&self) {
+        *self.packs.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    /// The pack holding `hash` and its entry there, if it is packed
+    fn packed(&self, hash: &ContentHash) -> Result<Option<(PathBuf, PackEntry)>> {
+        Ok(self.pack_table()?.get(hash).cloned())
+    }
+
+    /// A loose object's hash and encoding, from its path
+    fn loose_object(&self, path: &Path) -> Option<(ContentHash, ObjectKind)> {
+        let file = path.file_name()?.to_str()?;
+        let parent = path.parent()?;
+        let dir = parent.file_name()?.to_str()?;
+        let algorithm = match parent.parent() {
+            Some(grandparent) if grandparent != self.root => {
+                let name = grandparent.file_name()?.to_str()?;
+                HashAlgorithm::from_name(name).filter(|&a| a != HashAlgorithm::Sha256)?
+            }
+            _ => HashAlgorithm::Sha256,
+        };
+        let (rest, kind) = if let Some(rest) = file.strip_suffix(".gz") {
+            (rest, ObjectKind::Gzip)
+        } else if let Some(rest) = file.strip_suffix(".delta") {
+            (rest, ObjectKind::Delta)
+        } else {
+            (file, ObjectKind::Plain)
+        };
+        let raw = format!("{}{}", dir, rest);
+        (dir.len() == 2 && raw.chars().all(|c| c.is_ascii_hexdigit()))
+            .then(|| (ContentHash(format!("{}:{}", algorithm, raw)), kind))
     }
 
     /// Store content and return its hash.
@@ -106,59 +585,252 @@ pub fn content_path(&self, hash: &ContentHash) -> PathBuf {
     /// If content with the same hash already exists, this is a no-op
     /// (deduplication).
     pub fn store(&self, content: &[u8]) -> Result<ContentHash> {
-        let hash = ContentHash::from_bytes(content);
+        let hash = self.hash(content);
         let path = self.content_path(&hash);
 
         // Skip if already stored (deduplication)
-        if path.exists() {
+        if self.is_full(&hash) {
             return Ok(hash);
         }
+        self.write_full(&hash, &path, content)?;
+        Ok(hash)
+    }
 
-        // Create parent directory
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
+    /// Store content under the hash another store gave it, which may name
+    /// a different algorithm from this store's. Fails if the content does
+    /// not match the hash.
+    pub fn import(&self, hash: &ContentHash, content: &[u8]) -> Result<()> {
+        if !hash.verify(content) {
+            return Err(ReversibleError::ContentIntegrityError {
+                expected: hash.0.clone(),
+                actual: hash.rehash(content).0,
+            });
+        }
+        if !self.is_full(hash) {
+            self.write_full(hash, &self.content_path(hash), content)?;
         }
+        Ok(())
+    }
 
-        // Write content (optionally compressed)
+    /// Write `content` as the full blob for `hash` at `path`
+    fn write_full(&self, hash: &ContentHash, path: &Path, content: &[u8]) -> Result<()> {
         if self.compression {
-            let file = File::create(&path)?;
-            let mut encoder = GzEncoder::new(file, Compression::default());
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
             encoder.write_all(content)?;
-            encoder.finish()?;
+            let compressed = encoder.finish()?;
+            self.check_limits(content.len() as u64, compressed.len() as u64)?;
+            self.backend.write(path, &compressed)?;
+            self.stored(hash, content.len() as u64, compressed.len() as u64);
         } else {
-            fs::write(&path, content)?;
+            self.check_limits(content.len() as u64, content.len() as u64)?;
+            self.backend.write(path, content)?;
+            self.stored(hash, content.len() as u64, content.len() as u64);
+        }
+        self.index(hash, content);
+        Ok(())
+    }
+
+    /// Bytes of new objects this store has written since it was opened.
+    /// Deduplicated content adds nothing, so the difference across a
+    /// store call is what that call cost.
+    pub fn bytes_written(&self) -> u64 {
+        self.written.load(Ordering::Relaxed)
+    }
+
+    /// Announce a blob just written
+    fn stored(&self, hash: &ContentHash, bytes: u64, stored_bytes: u64) {
+        tracing::debug!(%hash, bytes, stored_bytes, backend = self.backend.name(), "content stored");
+        self.written.fetch_add(stored_bytes, Ordering::Relaxed);
+        self.events.publish(Event::ContentStored {
+            hash: hash.clone(),
+            bytes,
+            stored_bytes,
+        });
+    }
+
+    /// Add a stored blob to the index, if there is one. The index only
+    /// speeds up searches, so failing to update it does not fail the store.
+    fn index(&self, hash: &ContentHash, content: &[u8]) {
+        if let Some(index) = &self.index {
+            if let Err(e) = index.insert(hash, content) {
+                tracing::warn!(%hash, "failed to index content: {}", e);
+            }
         }
+    }
 
-        Ok(hash)
+    /// Store content as a delta against `base` when that is smaller.
+    ///
+    /// Returns the content hash and, if a delta object was written (or
+    /// already existed), the hash of its base. Falls back to full storage
+    /// when delta storage is disabled, `base` is not a full blob, or the
+    /// delta would not be worthwhile. Delta chains are never built: the
+    /// base is always full content.
+    pub fn store_delta(
+        &self,
+        content: &[u8],
+        base: &ContentHash,
+    ) -> Result<(ContentHash, Option<ContentHash>)> {
+        let hash = self.hash(content);
+
+        if self.is_full(&hash) {
+            return Ok((hash, None));
+        }
+        if let Some(existing) = self.delta_base(&hash)? {
+            return Ok((hash, Some(existing)));
+        }
+        if !self.delta || &hash == base || !self.is_full(base) {
+            return self.store(content).map(|h| (h, None));
+        }
+
+        let original = self.retrieve_full(base)?;
+        let delta = Delta::compute(&original, content);
+        if delta.is_full() {
+            return self.store(content).map(|h| (h, None));
+        }
+
+        let path = self.delta_path(&hash);
+        let mut object = Vec::with_capacity(base.0.len() + 1 + delta.data.len());
+        object.extend_from_slice(base.0.as_bytes());
+        object.push(b'\n');
+        object.extend_from_slice(&delta.into_bytes());
+        self.check_limits(content.len() as u64, object.len() as u64)?;
+        self.backend.write(&path, &object)?;
+        self.stored(&hash, content.len() as u64, object.len() as u64);
+        self.index(&hash, content);
+
+        Ok((hash, Some(base.clone())))
     }
 
-    /// Store content from a file path
+    /// Store content from a file path.
+    ///
+    /// An uncompressed local store snapshots the file with the backend's
+    /// [`FileBackend::copy_from_local`] (a reflink where the filesystem
+    /// supports it) and hashes the snapshot, so large files are captured
+    /// without reading them into memory or writing their data twice. The
+    /// snapshot is renamed into place, so other backends, and any without
+    /// an atomic rename, read the file and store its content instead.
     pub fn store_file(&self, file_path: &Path) -> Result<ContentHash> {
-        let content = fs::read(file_path)?;
-        self.store(&content)
+        if self.compression
+            || !self.backend.is_local()
+            || !self.backend.capabilities().atomic_rename
+        {
+            let content = self.read_file(file_path)?;
+            return self.store(&content);
+        }
+
+        // Checked before the snapshot exists, so deduplicated content is
+        // counted too
+        let len = fs::metadata(file_path)?.len();
+        self.check_limits(len, len)?;
+
+        // Hash the snapshot rather than the file, which may still change
+        let snapshot = self
+            .root
+            .join(format!(".incoming-{}", uuid::Uuid::new_v4()));
+        self.backend.copy_from_local(file_path, &snapshot)?;
+        let stored = self.hash_file(&snapshot).and_then(|hash| {
+            let path = self.content_path(&hash);
+            if self.is_full(&hash) {
+                self.backend.remove_file(&snapshot)?;
+            } else {
+                if let Some(parent) = path.parent() {
+                    self.backend.create_dir_all(parent)?;
+                }
+                self.backend.rename(&snapshot, &path)?;
+                self.stored(&hash, len, len);
+                // The content was never read into memory
+                if self.index.is_some() {
+                    self.index(&hash, &self.retrieve(&hash)?);
+                }
+            }
+            Ok(hash)
+        });
+        if stored.is_err() && self.backend.exists(&snapshot) {
+            let _ = self.backend.remove_file(&snapshot);
+        }
+        stored
     }
 
-    /// Retrieve content by hash, verifying integrity on read
-    pub fn retrieve(&self, hash: &ContentHash) -> Result<Vec<u8>> {
+    /// Read the stored object for a hash, loose or packed, and how it is
+    /// encoded
+    fn read_object(&self, hash: &ContentHash) -> Result<(ObjectKind, Vec<u8>)> {
         let path = self.content_path(hash);
+        if self.backend.exists(&path) {
+            let kind = if self.compression {
+                ObjectKind::Gzip
+            } else {
+                ObjectKind::Plain
+            };
+            return Ok((kind, self.backend.read(&path)?));
+        }
+        let delta_path = self.delta_path(hash);
+        if self.backend.exists(&delta_path) {
+            return Ok((ObjectKind::Delta, self.backend.read(&delta_path)?));
+        }
+        if let Some((_, loose)) = self.offloaded_object(hash) {
+            let kind = if loose == delta_path {
+                ObjectKind::Delta
+            } else if self.compression {
+                ObjectKind::Gzip
+            } else {
+                ObjectKind::Plain
+            };
+            return Ok((kind, self.read_cold(&loose)?));
+        }
+        match self.packed(hash)? {
+            Some((pack, entry)) => Ok((
+                entry.kind,
+                self.backend.read_range(&pack, entry.offset, entry.len)?,
+            )),
+            None => Err(ReversibleError::FileNotFound(hash.to_string())),
+        }
+    }
 
-        if !path.exists() {
-            return Err(ReversibleError::FileNotFound(hash.to_string()));
+    /// Retrieve full content by hash, mapping its loose object when it is
+    /// stored uncompressed on a local backend and mapping is enabled
+    fn retrieve_full(&self, hash: &ContentHash) -> Result<FileContent> {
+        let path = self.content_path(hash);
+        if self.mmap && !self.compression && self.backend.is_local() && path.is_file() {
+            let content = FileContent::open(&path, true)?;
+            if !hash.verify(&content) {
+                return Err(ReversibleError::ContentIntegrityError {
+                    expected: hash.to_string(),
+                    actual: hash.rehash(&content).to_string(),
+                });
+            }
+            return Ok(content);
         }
+        self.retrieve(hash).map(FileContent::Read)
+    }
 
-        let content = if self.compression {
-            let file = File::open(&path)?;
-            let mut decoder = GzDecoder::new(file);
-            let mut content = Vec::new();
-            decoder.read_to_end(&mut content)?;
-            content
-        } else {
-            fs::read(&path)?
+    /// Retrieve content by hash, verifying integrity on read
+    pub fn retrieve(&self, hash: &ContentHash) -> Result<Vec<u8>> {
+        let (kind, stored) = self.read_object(hash)?;
+        let content = match kind {
+            ObjectKind::Plain => stored,
+            ObjectKind::Gzip => {
+                let mut decoder = GzDecoder::new(stored.as_slice());
+                let mut content = Vec::new();
+                decoder.read_to_end(&mut content)?;
+                content
+            }
+            // Reconstruct delta-stored content from its base
+            ObjectKind::Delta => {
+                let (base, delta) = parse_delta_object(hash, &stored)?;
+                let original = self.retrieve(&base)?;
+                delta.apply(&original).ok_or_else(|| {
+                    ReversibleError::MetadataCorrupted(format!(
+                        "delta for {} does not apply to {}",
+                        hash, base
+                    ))
+                })?
+            }
         };
 
         // Verify integrity
         if !hash.verify(&content) {
-            let actual = ContentHash::from_bytes(&content);
+            let actual = hash.rehash(&content);
+            tracing::error!(expected = %hash, %actual, "stored content is corrupted");
             return Err(ReversibleError::ContentIntegrityError {
                 expected: hash.to_string(),
                 actual: actual.to_string(),
@@ -168,50 +840,613 @@ pub fn retrieve(&self, hash: &ContentHash) -> Result<Vec<u8>> {
         Ok(content)
     }
 
-    /// Check if content exists in the store
+    /// Rewrite a delta-stored blob as full content, so it no longer needs
+    /// its base. Returns whether it was stored as a delta. The full blob
+    /// is written before the delta is dropped, so the content is never
+    /// absent.
+    pub fn materialize(&self, hash: &ContentHash) -> Result<bool> {
+        if self.delta_base(hash)?.is_none() {
+            return Ok(false);
+        }
+        let delta_path = self.delta_path(hash);
+        if self.backend.exists(&cold_pointer(&delta_path)) {
+            return Err(ReversibleError::Backend(format!(
+                "delta {} is on the cold tier; recall it first",
+                hash
+            )));
+        }
+        let content = self.retrieve(hash)?;
+        self.write_full(hash, &self.content_path(hash), &content)?;
+        if self.backend.exists(&delta_path) {
+            self.backend.remove_file(&delta_path)?;
+        } else if let Some((pack, _)) = self.packed(hash)? {
+            pack::remove_from_pack(self.backend.as_ref(), &pack, &HashSet::from([hash.clone()]))?;
+            self.invalidate_packs();
+        }
+        Ok(true)
+    }
+
+    /// Check if content exists in the store (full or delta)
     pub fn exists(&self, hash: &ContentHash) -> bool {
-        self.content_path(hash).exists()
+        self.stored_path(hash).is_some()
+            || self.offloaded_object(hash).is_some()
+            || matches!(self.packed(hash), Ok(Some(_)))
     }
 
     /// Delete content by hash (for garbage collection)
     pub fn delete(&self, hash: &ContentHash) -> Result<()> {
-        let path = self.content_path(hash);
-        if path.exists() {
-            fs::remove_file(path)?;
+        self.delete_many(std::slice::from_ref(hash))
+    }
+
+    /// Delete several blobs, rewriting each pack that holds some of them
+    /// once
+    pub fn delete_many(&self, hashes: &[ContentHash]) -> Result<()> {
+        let table = self.pack_table()?;
+        let mut by_pack: HashMap<&Path, HashSet<ContentHash>> = HashMap::new();
+        for hash in hashes {
+            tracing::debug!(%hash, "deleting content");
+            for path in [self.content_path(hash), self.delta_path(hash)] {
+                if self.backend.exists(&path) {
+                    self.backend.remove_file(&path)?;
+                }
+                let pointer = cold_pointer(&path);
+                if self.backend.exists(&pointer) {
+                    let cold = self.require_cold(hash)?;
+                    let remote = self.cold_path(&path);
+                    let backend = cold.backend()?;
+                    if backend.exists(&remote) {
+                        backend.remove_file(&remote)?;
+                    }
+                    self.backend.remove_file(&pointer)?;
+                }
+            }
+            if let Some((pack, _)) = table.get(hash) {
+                by_pack.entry(pack).or_default().insert(hash.clone());
+            }
+        }
+
+        if !by_pack.is_empty() {
+            for (pack, hashes) in by_pack {
+                pack::remove_from_pack(self.backend.as_ref(), pack, &hashes)?;
+            }
+            self.invalidate_packs();
+        }
+        if let Some(index) = &self.index {
+            if let Err(e) = index.remove(hashes) {
+                tracing::warn!("failed to remove deleted content from the index: {}", e);
+            }
         }
         Ok(())
     }
 
-    /// Get total size of content store in bytes
-    pub fn total_size(&self) -> Result<u64> {
-        let mut size = 0;
-        for entry in walkdir::WalkDir::new(&self.root).into_iter().flatten() {
-            if entry.file_type().is_file() {
-                if let Ok(metadata) = entry.metadata() {
-                    size += metadata.len();
+    /// Move loose blobs of at most `max_object_bytes` stored bytes into
+    /// packfiles, saving a file (and inode) per blob. Returns how many
+    /// blobs were packed.
+    ///
+    /// On a backend without range reads, reading a packed blob would read
+    /// its whole pack, so blobs are left loose and none are packed.
+    pub fn pack(&self, max_object_bytes: u64) -> Result<usize> {
+        if !self.backend.capabilities().range_reads {
+            tracing::info!(
+                backend = self.backend.name(),
+                "backend has no range reads; leaving blobs unpacked"
+            );
+            return Ok(0);
+        }
+        let table = self.pack_table()?;
+        let dir = self.root.join(PACK_DIR);
+        let mut batch: Vec<(ContentHash, ObjectKind, Vec<u8>)> = Vec::new();
+        let mut batch_bytes = 0;
+        let mut packed = 0;
+        let mut loose = Vec::new();
+
+        for (path, size) in self.backend.list_files(&self.root)? {
+            let Some((hash, kind)) = self.loose_object(&path) else {
+                continue;
+            };
+            if size > max_object_bytes {
+                continue;
+            }
+            // Already packed by a run that stopped before removing it
+            if table.get(&hash).is_none() {
+                if batch_bytes + size > PACK_MAX_BYTES && !batch.is_empty() {
+                    pack::write_pack(self.backend.as_ref(), &dir, &batch)?;
+                    batch.clear();
+                    batch_bytes = 0;
                 }
+                batch.push((hash, kind, self.backend.read(&path)?));
+                batch_bytes += size;
+                packed += 1;
             }
+            loose.push(path);
+        }
+        if !batch.is_empty() {
+            pack::write_pack(self.backend.as_ref(), &dir, &batch)?;
         }
-        Ok(size)
+        self.invalidate_packs();
+
+        // Loose copies go only once their packs are indexed
+        for path in loose {
+            self.backend.remove_file(&path)?;
+        }
+        Ok(packed)
+    }
+
+    /// Get total size of content store in bytes
+    pub fn total_size(&self) -> Result<u64> {
+        Ok(self
+            .backend
+            .list_files(&self.root)?
+            .iter()
+            .map(|(_, size)| size)
+            .sum())
     }
 
-    /// Count number of stored content blobs
+    /// Count number of stored content blobs: loose files plus packed
+    /// objects
     pub fn count(&self) -> Result<usize> {
-        let mut count = 0;
-        for entry in walkdir::WalkDir::new(&self.root).into_iter().flatten() {
-            if entry.file_type().is_file() {
-                count += 1;
+        let pack_dir = self.root.join(PACK_DIR);
+        let loose = self
+            .backend
+            .list_files(&self.root)?
+            .iter()
+            .filter(|(path, _)| !path.starts_with(&pack_dir))
+            .count();
+        Ok(loose + self.pack_table()?.entries().count())
+    }
+
+    /// List stored blobs (full or delta, loose or packed) with their
+    /// stored sizes in bytes
+    pub fn list(&self) -> Result<Vec<(ContentHash, u64)>> {
+        let mut blobs: Vec<(ContentHash, u64)> = self
+            .backend
+            .list_files(&self.root)?
+            .into_iter()
+            .filter_map(|(path, size)| Some((self.loose_object(&path)?.0, size)))
+            .collect();
+        blobs.extend(self.offloaded()?);
+        let loose: HashSet<ContentHash> = blobs.iter().map(|(hash, _)| hash.clone()).collect();
+        blobs.extend(
+            self.pack_table()?
+                .entries()
+                .filter(|entry| !loose.contains(&entry.hash))
+                .map(|entry| (entry.hash.clone(), entry.len)),
+        );
+        Ok(blobs)
+    }
+
+    /// Move the loose object of `hash` to the cold tier, leaving a pointer
+    /// in its place. Returns the bytes moved, 0 if it was already there.
+    /// Packed objects are small and stay where they are.
+    pub fn offload(&self, hash: &ContentHash) -> Result<u64> {
+        let cold = self.require_cold(hash)?;
+        let Some(loose) = self.stored_path(hash) else {
+            if self.offloaded_object(hash).is_some() {
+                return Ok(0);
             }
+            return Err(ReversibleError::FileNotFound(format!(
+                "no loose object for {}",
+                hash
+            )));
+        };
+        let object = self.backend.read(&loose)?;
+        cold.backend()?.write(&self.cold_path(&loose), &object)?;
+        // The pointer is written before the local copy goes, so the object
+        // can always be found
+        self.backend
+            .write(&cold_pointer(&loose), object.len().to_string().as_bytes())?;
+        self.backend.remove_file(&loose)?;
+        tracing::debug!(%hash, bytes = object.len(), "content offloaded to the cold tier");
+        Ok(object.len() as u64)
+    }
+
+    /// Bring the object of `hash` back from the cold tier and delete it
+    /// there. Returns the bytes fetched, 0 if it was not offloaded.
+    pub fn recall(&self, hash: &ContentHash) -> Result<u64> {
+        let Some((pointer, loose)) = self.offloaded_object(hash) else {
+            return Ok(0);
+        };
+        let object = self.read_cold(&loose)?;
+        self.backend.write(&loose, &object)?;
+        self.backend.remove_file(&pointer)?;
+        self.require_cold(hash)?
+            .backend()?
+            .remove_file(&self.cold_path(&loose))?;
+        tracing::debug!(%hash, bytes = object.len(), "content recalled from the cold tier");
+        Ok(object.len() as u64)
+    }
+
+    /// Blobs offloaded to the cold tier, with their stored sizes there
+    pub fn offloaded(&self) -> Result<Vec<(ContentHash, u64)>> {
+        let mut blobs = Vec::new();
+        for (path, _) in self.backend.list_files(&self.root)? {
+            let Some(loose) = path
+                .to_str()
+                .and_then(|path| path.strip_suffix(COLD_POINTER_SUFFIX))
+            else {
+                continue;
+            };
+            let Some((hash, _)) = self.loose_object(Path::new(loose)) else {
+                continue;
+            };
+            let size = String::from_utf8_lossy(&self.backend.read(&path)?)
+                .trim()
+                .parse()
+                .unwrap_or(0);
+            blobs.push((hash, size));
+        }
+        Ok(blobs)
+    }
+
+    /// The pointer for `hash` and the loose path it stands in for, if the
+    /// object has been offloaded
+    fn offloaded_object(&self, hash: &ContentHash) -> Option<(PathBuf, PathBuf)> {
+        [self.content_path(hash), self.delta_path(hash)]
+            .into_iter()
+            .map(|loose| (cold_pointer(&loose), loose))
+            .find(|(pointer, _)| self.backend.exists(pointer))
+    }
+
+    /// The cold tier, which `hash` needs
+    fn require_cold(&self, hash: &ContentHash) -> Result<&ColdTier> {
+        self.cold.as_ref().ok_or_else(|| {
+            ReversibleError::Backend(format!(
+                "{} needs the cold tier, but none is configured",
+                hash
+            ))
+        })
+    }
+
+    /// Where the loose object at `loose` lives on the cold tier
+    fn cold_path(&self, loose: &Path) -> PathBuf {
+        let relative = loose.strip_prefix(&self.root).unwrap_or(loose);
+        match &self.cold {
+            Some(cold) => cold.root.join(relative),
+            None => relative.to_path_buf(),
         }
-        Ok(count)
+    }
+
+    /// Read the offloaded object that was at `loose`
+    fn read_cold(&self, loose: &Path) -> Result<Vec<u8>> {
+        let cold = self.cold.as_ref().ok_or_else(|| {
+            ReversibleError::Backend(format!(
+                "{} is on the cold tier, but none is configured",
+                loose.display()
+            ))
+        })?;
+        cold.backend()?.read(&self.cold_path(loose))
     }
 }
 
+/// The pointer left in place of the loose object at `loose` once it is
+/// offloaded
+fn cold_pointer(loose: &Path) -> PathBuf {
+    let mut pointer = loose.as_os_str().to_owned();
+    pointer.push(COLD_POINTER_SUFFIX);
+    PathBuf::from(pointer)
+}
+
+/// Parse a delta object: `<base hash>\n<serialized delta>`
+fn parse_delta_object(hash: &ContentHash, object: &[u8]) -> Result<(ContentHash, Delta)> {
+    let corrupted = || ReversibleError::MetadataCorrupted(format!("bad delta object for {}", hash));
+
+    let split = object
+        .iter()
+        .position(|&b| b == b'\n')
+        .ok_or_else(corrupted)?;
+    let base = std::str::from_utf8(&object[..split]).map_err(|_| corrupted())?;
+    let delta = Delta::from_bytes(&object[split + 1..]).ok_or_else(corrupted)?;
+
+    Ok((ContentHash(base.to_string()), delta))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::backend::Capabilities;
     use tempfile::TempDir;
 
+    #[test]
+    fn test_list() {
+        let tmp = TempDir::new().unwrap();
+        let store = ContentStore::new(tmp.path().join("content"), true)
+            .unwrap()
+            .with_delta(true);
+
+        let original = b"line of text\n".repeat(1000);
+        let mut modified = original.clone();
+        modified[10] = b'X';
+        let base = store.store(&original).unwrap();
+        let (delta, _) = store.store_delta(&modified, &base).unwrap();
+
+        let mut hashes: Vec<_> = store.list().unwrap().into_iter().map(|(h, _)| h).collect();
+        hashes.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut expected = vec![base, delta];
+        expected.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(hashes, expected);
+    }
+
+    #[test]
+    fn test_index_follows_stores_and_deletes() {
+        #[derive(Default)]
+        struct Recorder(Mutex<HashMap<ContentHash, Vec<u8>>>);
+        impl ContentIndex for Recorder {
+            fn insert(&self, hash: &ContentHash, content: &[u8]) -> Result<()> {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .insert(hash.clone(), content.to_vec());
+                Ok(())
+            }
+            fn remove(&self, hashes: &[ContentHash]) -> Result<()> {
+                let mut indexed = self.0.lock().unwrap();
+                for hash in hashes {
+                    indexed.remove(hash);
+                }
+                Ok(())
+            }
+        }
+
+        let tmp = TempDir::new().unwrap();
+        let recorder = Arc::new(Recorder::default());
+        let store = ContentStore::new(tmp.path().join("content"), false)
+            .unwrap()
+            .with_index(recorder.clone());
+
+        let stored = store.store(b"in memory").unwrap();
+        let file = tmp.path().join("file.txt");
+        fs::write(&file, b"from a file").unwrap();
+        let snapshot = store.store_file(&file).unwrap();
+        assert_eq!(recorder.0.lock().unwrap()[&snapshot], b"from a file");
+        assert_eq!(recorder.0.lock().unwrap().len(), 2);
+
+        store.delete(&stored).unwrap();
+        assert!(!recorder.0.lock().unwrap().contains_key(&stored));
+    }
+
+    #[test]
+    fn test_cold_tier() {
+        let tmp = TempDir::new().unwrap();
+        let cold_root = tmp.path().join("cold");
+        let store = ContentStore::new(tmp.path().join("content"), true)
+            .unwrap()
+            .with_delta(true)
+            .with_cold_tier(ColdTier::new(cold_root.clone(), || {
+                Ok(Arc::new(LocalBackend))
+            }));
+
+        let original = b"line of text\n".repeat(1000);
+        let mut modified = original.clone();
+        modified[10] = b'X';
+        let base = store.store(&original).unwrap();
+        let (delta, _) = store.store_delta(&modified, &base).unwrap();
+
+        assert!(store.offload(&base).unwrap() > 0);
+        assert!(store.offload(&delta).unwrap() > 0);
+        assert_eq!(store.offload(&base).unwrap(), 0);
+        assert!(store.stored_path(&base).is_none());
+        assert!(matches!(
+            store.locate(&base).unwrap(),
+            Some(BlobLocation::Cold { path, .. }) if path.starts_with(&cold_root)
+        ));
+        assert!(store.is_full(&base));
+        assert_eq!(store.delta_base(&delta).unwrap(), Some(base.clone()));
+        assert_eq!(store.retrieve(&delta).unwrap(), modified);
+        assert_eq!(store.offloaded().unwrap().len(), 2);
+        assert_eq!(store.list().unwrap().len(), 2);
+        // Only the pointers are left locally
+        assert!(store.total_size().unwrap() < 32);
+
+        assert!(store.recall(&base).unwrap() > 0);
+        assert!(store.stored_path(&base).is_some());
+        assert_eq!(store.retrieve(&base).unwrap(), original);
+
+        store.delete(&delta).unwrap();
+        assert!(!store.exists(&delta));
+        assert!(store.offloaded().unwrap().is_empty());
+        assert!(LocalBackend.list_files(&cold_root).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_cold_object_without_tier() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("content");
+        let tiered = ContentStore::new(root.clone(), false)
+            .unwrap()
+            .with_cold_tier(ColdTier::new(tmp.path().join("cold"), || {
+                Ok(Arc::new(LocalBackend))
+            }));
+        let hash = tiered.store(b"cold content").unwrap();
+        tiered.offload(&hash).unwrap();
+
+        let plain = ContentStore::new(root, false).unwrap();
+        assert!(plain.exists(&hash));
+        assert!(matches!(
+            plain.retrieve(&hash),
+            Err(ReversibleError::Backend(_))
+        ));
+    }
+
+    #[test]
+    fn test_pack_loose_blobs() {
+        for compression in [false, true] {
+            let tmp = TempDir::new().unwrap();
+            let root = tmp.path().join("content");
+            let store = ContentStore::new(root.clone(), compression)
+                .unwrap()
+                .with_delta(true);
+
+            let original = b"line of text\n".repeat(1000);
+            let mut modified = original.clone();
+            modified[10] = b'X';
+            let base = store.store(&original).unwrap();
+            let (delta, _) = store.store_delta(&modified, &base).unwrap();
+            let small: Vec<_> = (0..20)
+                .map(|i| store.store(format!("blob {}", i).as_bytes()).unwrap())
+                .collect();
+            // Incompressible, so too big to pack either way
+            let noise: Vec<u8> = (0..1000u32)
+                .flat_map(|i| Sha256::digest(i.to_le_bytes()))
+                .collect();
+            let big = store.store(&noise).unwrap();
+
+            let packed = store.pack(16 * 1024).unwrap();
+            assert_eq!(packed, 22);
+            assert_eq!(store.count().unwrap(), 23);
+            assert!(matches!(
+                store.locate(&small[0]).unwrap(),
+                Some(BlobLocation::Packed { .. })
+            ));
+            assert!(matches!(
+                store.locate(&big).unwrap(),
+                Some(BlobLocation::Loose(_))
+            ));
+
+            // Packed blobs read, deduplicate and report as before
+            assert_eq!(store.retrieve(&small[3]).unwrap(), b"blob 3");
+            assert_eq!(store.retrieve(&delta).unwrap(), modified);
+            assert_eq!(store.delta_base(&delta).unwrap(), Some(base.clone()));
+            assert!(store.is_full(&base) && !store.is_full(&delta));
+            assert_eq!(store.store(b"blob 3").unwrap(), small[3]);
+            assert_eq!(store.list().unwrap().len(), 23);
+            // Reopening reads the indexes afresh
+            let reopened = ContentStore::new(root.clone(), compression).unwrap();
+            assert_eq!(reopened.retrieve(&small[19]).unwrap(), b"blob 19");
+
+            // Deleting packed blobs rewrites their pack without them
+            store.delete_many(&small[..10]).unwrap();
+            assert!(!store.exists(&small[0]));
+            assert_eq!(store.retrieve(&small[10]).unwrap(), b"blob 10");
+            assert_eq!(store.count().unwrap(), 13);
+            assert_eq!(fs::read_dir(root.join(PACK_DIR)).unwrap().count(), 2);
+        }
+    }
+
+    #[test]
+    fn test_pack_needs_range_reads() {
+        /// The local filesystem, reporting no range reads
+        struct NoRangeReads;
+
+        impl FileBackend for NoRangeReads {
+            fn name(&self) -> &'static str {
+                "no-range-reads"
+            }
+            fn read(&self, path: &Path) -> Result<Vec<u8>> {
+                LocalBackend.read(path)
+            }
+            fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+                LocalBackend.write(path, content)
+            }
+            fn exists(&self, path: &Path) -> bool {
+                LocalBackend.exists(path)
+            }
+            fn remove_file(&self, path: &Path) -> Result<()> {
+                LocalBackend.remove_file(path)
+            }
+            fn create_dir_all(&self, path: &Path) -> Result<()> {
+                LocalBackend.create_dir_all(path)
+            }
+            fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+                LocalBackend.rename(from, to)
+            }
+            fn list_files(&self, dir: &Path) -> Result<Vec<(PathBuf, u64)>> {
+                LocalBackend.list_files(dir)
+            }
+            fn capabilities(&self) -> Capabilities {
+                Capabilities {
+                    range_reads: false,
+                    ..Capabilities::ALL
+                }
+            }
+        }
+
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("content");
+        let store = ContentStore::new_with_backend(Arc::new(NoRangeReads), root, false).unwrap();
+        let hash = store.store(b"small").unwrap();
+
+        assert_eq!(store.pack(u64::MAX).unwrap(), 0);
+        assert!(matches!(
+            store.locate(&hash).unwrap(),
+            Some(BlobLocation::Loose(_))
+        ));
+        assert_eq!(store.retrieve(&hash).unwrap(), b"small");
+    }
+
+    #[test]
+    fn test_store_file_snapshot() {
+        let tmp = TempDir::new().unwrap();
+        let file = tmp.path().join("big.bin");
+        fs::write(&file, b"snapshot me".repeat(1000)).unwrap();
+
+        for compression in [false, true] {
+            let store =
+                ContentStore::new(tmp.path().join(format!("c{}", compression)), compression)
+                    .unwrap();
+            let hash = store.store_file(&file).unwrap();
+            assert_eq!(hash, ContentHash::from_file(&file).unwrap());
+            assert_eq!(store.store_file(&file).unwrap(), hash);
+            assert_eq!(store.retrieve(&hash).unwrap(), fs::read(&file).unwrap());
+            // Only the blob remains, no leftover snapshot
+            assert_eq!(store.count().unwrap(), 1);
+        }
+    }
+
+    #[test]
+    fn test_mmap_large_files() {
+        let tmp = TempDir::new().unwrap();
+        let file = tmp.path().join("big.bin");
+        let original = b"mapped line\n".repeat(90_000);
+        fs::write(&file, &original).unwrap();
+        assert!(original.len() as u64 > crate::mapped::MAP_MIN_BYTES);
+
+        for compression in [false, true] {
+            let store =
+                ContentStore::new(tmp.path().join(format!("c{}", compression)), compression)
+                    .unwrap()
+                    .with_delta(true)
+                    .with_mmap(true);
+            let base = store.store_file(&file).unwrap();
+            assert_eq!(base, ContentHash::from_file(&file).unwrap());
+            assert_eq!(store.hash_file(&file).unwrap(), base);
+            assert!(store.read_file(&file).unwrap().is_mapped());
+            if compression {
+                continue;
+            }
+
+            // The delta is computed against the mapped base
+            let mut modified = original.clone();
+            modified[7] = b'X';
+            let (hash, delta_base) = store.store_delta(&modified, &base).unwrap();
+            assert_eq!(delta_base, Some(base.clone()));
+            assert_eq!(store.retrieve(&hash).unwrap(), modified);
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let tmp = TempDir::new().unwrap();
+        let store = ContentStore::new(tmp.path().join("content"), false)
+            .unwrap()
+            .with_limits(Some(100), Some(60));
+
+        assert!(matches!(
+            store.store(&[1u8; 61]),
+            Err(ReversibleError::QuotaExceeded(_))
+        ));
+        let stored = store.store(&[2u8; 60]).unwrap();
+        assert_eq!(store.headroom().unwrap(), Some(40));
+        assert!(matches!(
+            store.store(&[3u8; 50]),
+            Err(ReversibleError::QuotaExceeded(_))
+        ));
+
+        // Deduplicated content costs nothing
+        assert_eq!(store.store(&[2u8; 60]).unwrap(), stored);
+        store.store(&[4u8; 40]).unwrap();
+        assert_eq!(store.headroom().unwrap(), Some(0));
+    }
+
     #[test]
     fn test_content_hash() {
         let content = b"hello world";
@@ -222,6 +1457,57 @@ fn test_content_hash() {
         assert!(!hash.verify(b"different content"));
     }
 
+    #[test]
+    fn test_mixed_hash_algorithms() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("content");
+        let content = b"line of text\n".repeat(1000);
+        let sha = ContentStore::new(root.clone(), false)
+            .unwrap()
+            .with_delta(true);
+        let old = sha.store(&content).unwrap();
+        assert_eq!(old.hash_algorithm(), Some(HashAlgorithm::Sha256));
+
+        // Switching algorithm names new blobs differently, under their own
+        // directory, while the old ones stay readable
+        let store = ContentStore::new(root.clone(), false)
+            .unwrap()
+            .with_delta(true)
+            .with_algorithm(HashAlgorithm::Blake3);
+        let new = store.store(&content).unwrap();
+        assert!(new.0.starts_with("blake3:"));
+        assert_eq!(new.raw_hash().len(), 64);
+        assert_ne!(new, old);
+        assert!(new.verify(&content) && !new.verify(b"other"));
+        assert_eq!(new.rehash(&content), new);
+        assert!(store.content_path(&new).starts_with(root.join("blake3")));
+        assert_eq!(store.retrieve(&old).unwrap(), content);
+        assert_eq!(store.retrieve(&new).unwrap(), content);
+
+        // A blake3 delta against a sha256 base
+        let mut modified = content.clone();
+        modified[0] = b'L';
+        let (changed, base) = store.store_delta(&modified, &old).unwrap();
+        assert_eq!(base, Some(old.clone()));
+        assert_eq!(store.retrieve(&changed).unwrap(), modified);
+
+        let file = tmp.path().join("file");
+        fs::write(&file, &content).unwrap();
+        assert_eq!(store.store_file(&file).unwrap(), new);
+
+        let mut listed: Vec<_> = store.list().unwrap().into_iter().map(|(h, _)| h).collect();
+        listed.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut expected = vec![old.clone(), new.clone(), changed.clone()];
+        expected.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(listed, expected);
+
+        assert_eq!(store.pack(u64::MAX).unwrap(), 3);
+        assert_eq!(store.retrieve(&new).unwrap(), content);
+        // Whichever algorithm a store names new blobs with
+        let reopened = ContentStore::new(root, false).unwrap();
+        assert_eq!(reopened.retrieve(&changed).unwrap(), modified);
+    }
+
     #[test]
     fn test_store_and_retrieve() {
         let tmp = TempDir::new().unwrap();
@@ -258,4 +1544,82 @@ fn test_deduplication() {
         assert_eq!(hash1, hash2);
         assert_eq!(store.count().unwrap(), 1);
     }
+
+    #[test]
+    fn test_store_delta() {
+        let tmp = TempDir::new().unwrap();
+        let store = ContentStore::new(tmp.path().to_path_buf(), true)
+            .unwrap()
+            .with_delta(true);
+
+        let original = b"line of base content that repeats\n".repeat(500);
+        let base = store.store(&original).unwrap();
+
+        let mut modified = original.clone();
+        modified[1000] = b'X';
+        let (hash, delta_base) = store.store_delta(&modified, &base).unwrap();
+
+        assert_eq!(delta_base, Some(base.clone()));
+        assert!(!store.is_full(&hash));
+        assert!(store.exists(&hash));
+        assert_eq!(store.retrieve(&hash).unwrap(), modified);
+
+        store.delete(&hash).unwrap();
+        assert!(!store.exists(&hash));
+    }
+
+    #[test]
+    fn test_materialize_delta() {
+        let tmp = TempDir::new().unwrap();
+        let store = ContentStore::new(tmp.path().to_path_buf(), true)
+            .unwrap()
+            .with_delta(true);
+
+        let original = b"line of base content that repeats\n".repeat(500);
+        let base = store.store(&original).unwrap();
+        let mut modified = original.clone();
+        modified[1000] = b'X';
+        let (hash, _) = store.store_delta(&modified, &base).unwrap();
+        store.pack(u64::MAX).unwrap();
+
+        assert!(store.materialize(&hash).unwrap());
+        assert!(store.is_full(&hash));
+        assert_eq!(store.delta_base(&hash).unwrap(), None);
+        assert!(!store.materialize(&hash).unwrap());
+
+        store.delete(&base).unwrap();
+        assert_eq!(store.retrieve(&hash).unwrap(), modified);
+    }
+
+    #[test]
+    fn test_store_delta_disabled_stores_full() {
+        let tmp = TempDir::new().unwrap();
+        let store = ContentStore::new(tmp.path().to_path_buf(), false).unwrap();
+
+        let original = b"abcdefgh".repeat(1024);
+        let base = store.store(&original).unwrap();
+        let mut modified = original.clone();
+        modified[0] = b'z';
+
+        let (hash, delta_base) = store.store_delta(&modified, &base).unwrap();
+        assert_eq!(delta_base, None);
+        assert!(store.is_full(&hash));
+    }
+
+    #[test]
+    fn test_import_keeps_foreign_hash() {
+        let tmp = TempDir::new().unwrap();
+        let source = ContentStore::new(tmp.path().join("a"), false)
+            .unwrap()
+            .with_algorithm(HashAlgorithm::Blake3);
+        let target = ContentStore::new(tmp.path().join("b"), true).unwrap();
+
+        let hash = source.store(b"replicated").unwrap();
+        target.import(&hash, b"replicated").unwrap();
+        assert_eq!(target.retrieve(&hash).unwrap(), b"replicated");
+        assert!(matches!(
+            target.import(&hash, b"tampered"),
+            Err(ReversibleError::ContentIntegrityError { .. })
+        ));
+    }
 }
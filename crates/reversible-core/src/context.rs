@@ -0,0 +1,156 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Operation Context: where and from what an operation was run
+// Host, process, parent command and working directory, recorded beside the
+// user and time so history can be traced back to the session that made it
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+
+/// Longest parent command line recorded, in bytes
+const MAX_COMMAND_LEN: usize = 1024;
+
+/// What context is recorded on each operation (`Config.context`). Each
+/// part can be turned off where it would say too much.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContextCapture {
+    pub hostname: bool,
+    /// The process ID and its parent's command line
+    pub process: bool,
+    pub cwd: bool,
+    /// A SHA-256 of the environment variables, which tells two
+    /// environments apart without recording what is in them
+    pub environment: bool,
+}
+
+impl Default for ContextCapture {
+    fn default() -> Self {
+        Self {
+            hostname: true,
+            process: true,
+            cwd: true,
+            environment: false,
+        }
+    }
+}
+
+impl ContextCapture {
+    /// Record nothing
+    pub const NONE: Self = Self {
+        hostname: false,
+        process: false,
+        cwd: false,
+        environment: false,
+    };
+
+    /// The context of this process, as far as it is to be recorded;
+    /// `None` if nothing is
+    pub fn capture(&self) -> Option<OperationContext> {
+        if *self == Self::NONE {
+            return None;
+        }
+        Some(OperationContext {
+            hostname: self
+                .hostname
+                .then(whoami::fallible::hostname)
+                .and_then(Result::ok),
+            pid: self.process.then(std::process::id),
+            parent_command: self.process.then(parent_command).flatten(),
+            cwd: self.cwd.then(std::env::current_dir).and_then(Result::ok),
+            environment: self.environment.then(environment_fingerprint),
+        })
+    }
+}
+
+/// Where and from what an operation was run (see [`ContextCapture`])
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OperationContext {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// ID of the process that recorded the operation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    /// Command line of its parent (the shell, script or build step that
+    /// ran it), where the platform tells
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_command: Option<String>,
+    /// Working directory of the process
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+    /// SHA-256 of the sorted `NAME=value` environment variables
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
+}
+
+/// Command line of the parent process, arguments separated by spaces and
+/// cut to [`MAX_COMMAND_LEN`]. Read from `/proc`, so Linux only.
+fn parent_command() -> Option<String> {
+    #[cfg(target_os = "linux")]
+    {
+        let ppid = std::os::unix::process::parent_id();
+        let raw = std::fs::read(format!("/proc/{}/cmdline", ppid)).ok()?;
+        let mut command = raw
+            .split(|&b| b == 0)
+            .filter(|arg| !arg.is_empty())
+            .map(String::from_utf8_lossy)
+            .collect::<Vec<_>>()
+            .join(" ");
+        if command.len() > MAX_COMMAND_LEN {
+            let mut end = MAX_COMMAND_LEN;
+            while !command.is_char_boundary(end) {
+                end -= 1;
+            }
+            command.truncate(end);
+        }
+        (!command.is_empty()).then_some(command)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = MAX_COMMAND_LEN;
+        None
+    }
+}
+
+/// Hex SHA-256 of the environment, variables sorted by name
+fn environment_fingerprint() -> String {
+    let mut vars: Vec<_> = std::env::vars_os().collect();
+    vars.sort();
+    let mut hasher = Sha256::new();
+    for (name, value) in vars {
+        hasher.update(name.as_encoded_bytes());
+        hasher.update(b"=");
+        hasher.update(value.as_encoded_bytes());
+        hasher.update(b"\0");
+    }
+    hex::encode(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture_records_only_what_is_enabled() {
+        assert_eq!(ContextCapture::NONE.capture(), None);
+
+        let context = ContextCapture::default().capture().unwrap();
+        assert_eq!(context.pid, Some(std::process::id()));
+        assert_eq!(context.cwd, std::env::current_dir().ok());
+        assert!(context.environment.is_none());
+        #[cfg(target_os = "linux")]
+        assert!(context.parent_command.is_some());
+
+        let context = ContextCapture {
+            environment: true,
+            ..ContextCapture::NONE
+        }
+        .capture()
+        .unwrap();
+        assert!(context.hostname.is_none() && context.pid.is_none() && context.cwd.is_none());
+        assert_eq!(context.environment.unwrap().len(), 64);
+    }
+}
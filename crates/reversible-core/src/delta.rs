@@ -0,0 +1,763 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Delta Storage: Efficient storage for file modifications
+// Stores only the differences when modifying large files
+//
+// Binary deltas use the bsdiff algorithm (Percival, "Naive differences of
+// executable code", 2003): a suffix array over the original content finds
+// the longest matches, matches are extended approximately, and the result is
+// encoded as (diff, extra, seek) control triples. The diff stream is mostly
+// zero bytes, so the whole payload is deflated before it is stored.
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// Threshold: use delta if original file is larger than this
+const DELTA_THRESHOLD: usize = 4096; // 4KB
+
+/// Maximum delta size as percentage of original (if delta is larger, store full content)
+const MAX_DELTA_RATIO: f64 = 0.5; // 50%
+
+/// Inputs above this size are stored in full: suffix sorting takes about
+/// 20 bytes per input byte at its peak, so this keeps it under ~320MB
+pub const DELTA_MAX_SIZE: usize = 16 * 1024 * 1024; // 16MB
+
+/// Magic prefix for serialized deltas
+const DELTA_MAGIC: &[u8; 8] = b"JKDELTA1";
+
+/// A delta representing changes between two versions of content
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delta {
+    /// Type of delta encoding used
+    pub encoding: DeltaEncoding,
+    /// The delta data
+    pub data: Vec<u8>,
+    /// Original content size (for validation)
+    pub original_size: usize,
+    /// New content size (for validation)
+    pub new_size: usize,
+}
+
+/// Type of delta encoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeltaEncoding {
+    /// Full content (no delta, used when delta would be larger)
+    Full,
+    /// bsdiff control/diff/extra streams, deflate-compressed
+    Bsdiff,
+}
+
+impl DeltaEncoding {
+    fn tag(self) -> u8 {
+        match self {
+            Self::Full => 0,
+            Self::Bsdiff => 1,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(Self::Full),
+            1 => Some(Self::Bsdiff),
+            _ => None,
+        }
+    }
+}
+
+/// Line-based diff for text files (used for display, not storage)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LineDiff {
+    /// List of hunks (groups of changes)
+    pub hunks: Vec<DiffHunk>,
+}
+
+/// A hunk in a line diff
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffHunk {
+    /// Starting line in original (0-indexed)
+    pub original_start: usize,
+    /// Number of lines in original
+    pub original_count: usize,
+    /// Starting line in new (0-indexed)
+    pub new_start: usize,
+    /// Number of lines in new
+    pub new_count: usize,
+    /// The changed lines
+    pub lines: Vec<DiffLine>,
+}
+
+/// A line in a diff hunk
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DiffLine {
+    /// Line exists in both (context)
+    Context(String),
+    /// Line only in original (removed)
+    Removed(String),
+    /// Line only in new (added)
+    Added(String),
+}
+
+/// One bsdiff control triple
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Control {
+    /// Bytes to add from original + diff stream
+    diff_len: usize,
+    /// Bytes to copy verbatim from the extra stream
+    extra_len: usize,
+    /// Signed adjustment of the read position in the original
+    seek: i64,
+}
+
+impl Delta {
+    /// Compute delta between original and new content
+    pub fn compute(original: &[u8], new: &[u8]) -> Self {
+        // If original is small, just store full content
+        if original.len() < DELTA_THRESHOLD || original.len() > DELTA_MAX_SIZE {
+            return Self::full(new);
+        }
+
+        if let Some(delta) = Self::compute_bsdiff(original, new) {
+            let delta_size = delta.data.len();
+            let max_size = (original.len() as f64 * MAX_DELTA_RATIO) as usize;
+
+            if delta_size < max_size && delta_size < new.len() {
+                return delta;
+            }
+        }
+
+        // Fall back to full content
+        Self::full(new)
+    }
+
+    /// Create a delta that stores full content
+    fn full(content: &[u8]) -> Self {
+        Self {
+            encoding: DeltaEncoding::Full,
+            data: content.to_vec(),
+            original_size: 0,
+            new_size: content.len(),
+        }
+    }
+
+    /// Compute a bsdiff delta
+    fn compute_bsdiff(original: &[u8], new: &[u8]) -> Option<Self> {
+        let (controls, diff, extra) = bsdiff(original, new);
+
+        let mut payload = Vec::with_capacity(diff.len() / 4 + extra.len() + 64);
+        write_varint(&mut payload, controls.len() as u64);
+        for c in &controls {
+            write_varint(&mut payload, c.diff_len as u64);
+            write_varint(&mut payload, c.extra_len as u64);
+            write_varint(&mut payload, zigzag(c.seek));
+        }
+        payload.extend_from_slice(&diff);
+        payload.extend_from_slice(&extra);
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&payload).ok()?;
+        let data = encoder.finish().ok()?;
+
+        Some(Self {
+            encoding: DeltaEncoding::Bsdiff,
+            data,
+            original_size: original.len(),
+            new_size: new.len(),
+        })
+    }
+
+    /// Apply delta to original content to get new content
+    pub fn apply(&self, original: &[u8]) -> Option<Vec<u8>> {
+        match self.encoding {
+            DeltaEncoding::Full => Some(self.data.clone()),
+            DeltaEncoding::Bsdiff => self.apply_bsdiff(original),
+        }
+    }
+
+    /// Apply a bsdiff delta
+    fn apply_bsdiff(&self, original: &[u8]) -> Option<Vec<u8>> {
+        if original.len() != self.original_size {
+            return None;
+        }
+
+        let mut payload = Vec::new();
+        DeflateDecoder::new(self.data.as_slice())
+            .read_to_end(&mut payload)
+            .ok()?;
+
+        let mut cursor = 0;
+        let count = read_varint(&payload, &mut cursor)? as usize;
+        let mut controls = Vec::with_capacity(count.min(payload.len()));
+        let mut diff_total = 0usize;
+        for _ in 0..count {
+            let control = Control {
+                diff_len: read_varint(&payload, &mut cursor)? as usize,
+                extra_len: read_varint(&payload, &mut cursor)? as usize,
+                seek: unzigzag(read_varint(&payload, &mut cursor)?),
+            };
+            diff_total = diff_total.checked_add(control.diff_len)?;
+            controls.push(control);
+        }
+
+        let diff = payload.get(cursor..cursor.checked_add(diff_total)?)?;
+        let extra = payload.get(cursor + diff_total..)?;
+
+        // Every output byte comes from the diff or extra stream, so a
+        // new_size that disagrees is corrupt and must not size the buffer
+        if diff_total.checked_add(extra.len())? != self.new_size {
+            return None;
+        }
+
+        let mut result = Vec::with_capacity(self.new_size);
+        let (mut old_pos, mut diff_pos, mut extra_pos) = (0i64, 0usize, 0usize);
+
+        for c in controls {
+            for &d in diff.get(diff_pos..diff_pos + c.diff_len)? {
+                let old = original.get(usize::try_from(old_pos).ok()?)?;
+                result.push(old.wrapping_add(d));
+                old_pos += 1;
+            }
+            diff_pos += c.diff_len;
+
+            result.extend_from_slice(extra.get(extra_pos..extra_pos.checked_add(c.extra_len)?)?);
+            extra_pos += c.extra_len;
+
+            old_pos = old_pos.checked_add(c.seek)?;
+        }
+
+        if result.len() != self.new_size || extra_pos != extra.len() {
+            return None;
+        }
+
+        Some(result)
+    }
+
+    /// Check if this delta uses full content storage
+    pub fn is_full(&self) -> bool {
+        self.encoding == DeltaEncoding::Full
+    }
+
+    /// Serialize the delta (for the content store)
+    pub fn into_bytes(self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.data.len() + 32);
+        out.extend_from_slice(DELTA_MAGIC);
+        out.push(self.encoding.tag());
+        write_varint(&mut out, self.original_size as u64);
+        write_varint(&mut out, self.new_size as u64);
+        out.extend_from_slice(&self.data);
+        out
+    }
+
+    /// Parse from bytes
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let rest = data.strip_prefix(DELTA_MAGIC.as_slice())?;
+        let (&tag, rest) = rest.split_first()?;
+        let encoding = DeltaEncoding::from_tag(tag)?;
+        let mut cursor = 0;
+        let original_size = read_varint(rest, &mut cursor)? as usize;
+        let new_size = read_varint(rest, &mut cursor)? as usize;
+
+        Some(Self {
+            encoding,
+            data: rest[cursor..].to_vec(),
+            original_size,
+            new_size,
+        })
+    }
+}
+
+/// Check if content is likely text (not binary)
+pub fn is_likely_text(content: &[u8]) -> bool {
+    if content.is_empty() {
+        return true;
+    }
+
+    // Check first 8KB for null bytes or high ratio of non-printable chars
+    let sample_size = content.len().min(8192);
+    let sample = &content[..sample_size];
+
+    let mut non_text_count = 0;
+    for &byte in sample {
+        if byte == 0 {
+            return false; // Null byte = binary
+        }
+        if byte < 32 && byte != b'\t' && byte != b'\n' && byte != b'\r' {
+            non_text_count += 1;
+        }
+    }
+
+    // If more than 10% non-text characters, consider it binary
+    (non_text_count as f64 / sample_size as f64) < 0.1
+}
+
+/// Compute an LCS-based line diff between two texts (for display)
+pub fn line_diff(original: &str, new: &str) -> LineDiff {
+    let original_lines: Vec<&str> = original.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    compute_lcs_diff(&original_lines, &new_lines)
+}
+
+/// For each line of `new`, the index of the line of `original` it was
+/// carried over from, or `None` if it was added
+pub fn line_alignment(original: &str, new: &str) -> Vec<Option<usize>> {
+    let original_lines: Vec<&str> = original.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    lcs_changes(&original_lines, &new_lines)
+        .into_iter()
+        .filter_map(|(i, _, line)| match line {
+            DiffLine::Context(_) => Some(Some(i)),
+            DiffLine::Added(_) => Some(None),
+            DiffLine::Removed(_) => None,
+        })
+        .collect()
+}
+
+/// Compute LCS-based diff between two lists of lines
+fn compute_lcs_diff(original: &[&str], new: &[&str]) -> LineDiff {
+    let mut hunks = Vec::new();
+    let changes = lcs_changes(original, new);
+
+    // Group changes into hunks with up to 3 lines of context either side
+    let context_lines = 3;
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for (k, (_, _, line)) in changes.iter().enumerate() {
+        if matches!(line, DiffLine::Context(_)) {
+            continue;
+        }
+        let start = k.saturating_sub(context_lines);
+        let end = (k + context_lines).min(changes.len() - 1);
+        match ranges.last_mut() {
+            Some((_, last_end)) if start <= *last_end + 1 => *last_end = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+
+    for (start, end) in ranges {
+        let lines: Vec<DiffLine> = changes[start..=end]
+            .iter()
+            .map(|(_, _, line)| line.clone())
+            .collect();
+        let count = |keep: fn(&DiffLine) -> bool| lines.iter().filter(|l| keep(l)).count();
+        hunks.push(DiffHunk {
+            original_start: changes[start].0,
+            original_count: count(|l| !matches!(l, DiffLine::Added(_))),
+            new_start: changes[start].1,
+            new_count: count(|l| !matches!(l, DiffLine::Removed(_))),
+            lines,
+        });
+    }
+
+    LineDiff { hunks }
+}
+
+/// Line-by-line edit script from `original` to `new`, in order, with the
+/// original and new line index at each step
+fn lcs_changes(original: &[&str], new: &[&str]) -> Vec<(usize, usize, DiffLine)> {
+    let (orig_len, new_len) = (original.len(), new.len());
+
+    // Build edit graph using simple DP
+    let mut lcs = vec![vec![0usize; new_len + 1]; orig_len + 1];
+
+    for i in 1..=orig_len {
+        for j in 1..=new_len {
+            if original[i - 1] == new[j - 1] {
+                lcs[i][j] = lcs[i - 1][j - 1] + 1;
+            } else {
+                lcs[i][j] = lcs[i - 1][j].max(lcs[i][j - 1]);
+            }
+        }
+    }
+
+    // Backtrack to find differences
+    let mut i = orig_len;
+    let mut j = new_len;
+    let mut changes: Vec<(usize, usize, DiffLine)> = Vec::new();
+
+    while i > 0 || j > 0 {
+        if i > 0 && j > 0 && original[i - 1] == new[j - 1] {
+            changes.push((i - 1, j - 1, DiffLine::Context(original[i - 1].to_string())));
+            i -= 1;
+            j -= 1;
+        } else if j > 0 && (i == 0 || lcs[i][j - 1] >= lcs[i - 1][j]) {
+            changes.push((i, j - 1, DiffLine::Added(new[j - 1].to_string())));
+            j -= 1;
+        } else if i > 0 {
+            changes.push((i - 1, j, DiffLine::Removed(original[i - 1].to_string())));
+            i -= 1;
+        }
+    }
+
+    changes.reverse();
+    changes
+}
+
+/// Build the suffix array of `data` by prefix doubling with radix sort.
+///
+/// The returned array has `data.len() + 1` entries; the empty suffix
+/// (index `data.len()`) sorts first, as in the reference bsdiff.
+fn suffix_array(data: &[u8]) -> Vec<u32> {
+    let n = data.len();
+    assert!(n < u32::MAX as usize, "suffix array input too large");
+    // Rank of the empty suffix is 0; bytes are shifted up by one
+    let mut rank = Vec::with_capacity(n + 1);
+    rank.extend(data.iter().map(|&b| u32::from(b) + 1));
+    rank.push(0);
+    let mut sa = vec![0u32; n + 1];
+    let mut tmp = vec![0u32; n + 1];
+    let mut next = vec![0u32; n + 1];
+    let buckets = n.max(256) + 1;
+
+    let mut k = 1;
+    loop {
+        let second = |i: u32| {
+            let i = i as usize;
+            if i + k <= n {
+                rank[i + k]
+            } else {
+                0
+            }
+        };
+        counting_sort(0..=n as u32, &mut tmp, second, buckets);
+        counting_sort(tmp.iter().copied(), &mut sa, |i| rank[i as usize], buckets);
+
+        next[sa[0] as usize] = 0;
+        for w in 1..=n {
+            let (a, b) = (sa[w - 1], sa[w]);
+            let differs = rank[a as usize] != rank[b as usize] || second(a) != second(b);
+            next[b as usize] = next[a as usize] + u32::from(differs);
+        }
+        std::mem::swap(&mut rank, &mut next);
+
+        if rank[sa[n] as usize] as usize == n || k > n {
+            break;
+        }
+        k *= 2;
+    }
+
+    sa
+}
+
+/// Stable counting sort of `input` into `output` by `key` (< `buckets`)
+fn counting_sort<I>(input: I, output: &mut [u32], key: impl Fn(u32) -> u32, buckets: usize)
+where
+    I: Iterator<Item = u32> + Clone,
+{
+    let mut start = vec![0u32; buckets + 1];
+    for i in input.clone() {
+        start[key(i) as usize + 1] += 1;
+    }
+    for b in 1..=buckets {
+        start[b] += start[b - 1];
+    }
+    for i in input {
+        let b = key(i) as usize;
+        output[start[b] as usize] = i;
+        start[b] += 1;
+    }
+}
+
+/// Length of the common prefix of two slices
+fn match_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+/// Binary-search the suffix array for the longest match of `target`.
+///
+/// Returns `(position in original, match length)`.
+fn search(sa: &[u32], original: &[u8], target: &[u8]) -> (usize, usize) {
+    let (mut st, mut en) = (0, sa.len() - 1);
+
+    while en - st >= 2 {
+        let mid = st + (en - st) / 2;
+        let suffix = &original[sa[mid] as usize..];
+        let n = suffix.len().min(target.len());
+        if suffix[..n] < target[..n] {
+            st = mid;
+        } else {
+            en = mid;
+        }
+    }
+
+    let (st, en) = (sa[st] as usize, sa[en] as usize);
+    let x = match_len(&original[st..], target);
+    let y = match_len(&original[en..], target);
+    if x > y {
+        (st, x)
+    } else {
+        (en, y)
+    }
+}
+
+/// Core bsdiff: produce control triples plus diff and extra streams
+fn bsdiff(old: &[u8], new: &[u8]) -> (Vec<Control>, Vec<u8>, Vec<u8>) {
+    let sa = suffix_array(old);
+    let (old_size, new_size) = (old.len() as i64, new.len());
+
+    let mut controls = Vec::new();
+    let mut diff = Vec::new();
+    let mut extra = Vec::new();
+
+    let (mut scan, mut len, mut pos) = (0usize, 0usize, 0usize);
+    let (mut last_scan, mut last_pos, mut last_offset) = (0usize, 0usize, 0i64);
+
+    // Does new[i] match the byte at the current diagonal in old?
+    let matches_at = |i: usize, offset: i64| {
+        let j = i as i64 + offset;
+        j >= 0 && j < old_size && old[j as usize] == new[i]
+    };
+
+    while scan < new_size {
+        let mut old_score = 0i64;
+        scan += len;
+        let mut scsc = scan;
+
+        while scan < new_size {
+            (pos, len) = search(&sa, old, &new[scan..]);
+
+            while scsc < scan + len {
+                if matches_at(scsc, last_offset) {
+                    old_score += 1;
+                }
+                scsc += 1;
+            }
+
+            let score = len as i64;
+            if (score == old_score && len != 0) || score > old_score + 8 {
+                break;
+            }
+
+            if matches_at(scan, last_offset) {
+                old_score -= 1;
+            }
+            scan += 1;
+        }
+
+        if len as i64 != old_score || scan == new_size {
+            // Extend the previous match forwards
+            let (mut s, mut sf, mut len_f) = (0i64, 0i64, 0usize);
+            let mut i = 0usize;
+            while last_scan + i < scan && last_pos + i < old.len() {
+                if old[last_pos + i] == new[last_scan + i] {
+                    s += 1;
+                }
+                i += 1;
+                if s * 2 - i as i64 > sf * 2 - len_f as i64 {
+                    sf = s;
+                    len_f = i;
+                }
+            }
+
+            // Extend the new match backwards
+            let mut len_b = 0usize;
+            if scan < new_size {
+                let (mut s, mut sb) = (0i64, 0i64);
+                let mut i = 1usize;
+                while scan >= last_scan + i && pos >= i {
+                    if old[pos - i] == new[scan - i] {
+                        s += 1;
+                    }
+                    if s * 2 - i as i64 > sb * 2 - len_b as i64 {
+                        sb = s;
+                        len_b = i;
+                    }
+                    i += 1;
+                }
+            }
+
+            // Resolve overlap between the two extensions
+            if last_scan + len_f > scan - len_b {
+                let overlap = (last_scan + len_f) - (scan - len_b);
+                let (mut s, mut ss, mut len_s) = (0i64, 0i64, 0usize);
+                for i in 0..overlap {
+                    if new[last_scan + len_f - overlap + i] == old[last_pos + len_f - overlap + i] {
+                        s += 1;
+                    }
+                    if new[scan - len_b + i] == old[pos - len_b + i] {
+                        s -= 1;
+                    }
+                    if s > ss {
+                        ss = s;
+                        len_s = i + 1;
+                    }
+                }
+                len_f = len_f + len_s - overlap;
+                len_b -= len_s;
+            }
+
+            for i in 0..len_f {
+                diff.push(new[last_scan + i].wrapping_sub(old[last_pos + i]));
+            }
+            let extra_start = last_scan + len_f;
+            let extra_end = scan - len_b;
+            extra.extend_from_slice(&new[extra_start..extra_end]);
+
+            controls.push(Control {
+                diff_len: len_f,
+                extra_len: extra_end - extra_start,
+                seek: (pos as i64 - len_b as i64) - (last_pos as i64 + len_f as i64),
+            });
+
+            last_scan = scan - len_b;
+            last_pos = pos - len_b;
+            last_offset = pos as i64 - scan as i64;
+        }
+    }
+
+    (controls, diff, extra)
+}
+
+/// Write an unsigned LEB128 varint
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Read an unsigned LEB128 varint, advancing `cursor`
+fn read_varint(data: &[u8], cursor: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *data.get(*cursor)?;
+        *cursor += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn unzigzag(v: u64) -> i64 {
+    ((v >> 1) as i64) ^ -((v & 1) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_likely_text() {
+        assert!(is_likely_text(b"Hello, world!"));
+        assert!(is_likely_text(b"Line 1\nLine 2\nLine 3"));
+        assert!(!is_likely_text(b"\x00\x01\x02\x03"));
+        assert!(is_likely_text(b""));
+    }
+
+    #[test]
+    fn test_full_delta() {
+        let original = b"small";
+        let new = b"new content";
+
+        let delta = Delta::compute(original, new);
+        assert!(delta.is_full());
+        assert_eq!(delta.apply(original).unwrap(), new.to_vec());
+    }
+
+    #[test]
+    fn test_text_delta_is_small() {
+        let original = b"line 1\nline 2\nline 3\nline 4\nline 5\n".repeat(200);
+        let new_str = String::from_utf8(original.clone()).unwrap();
+        let mut lines: Vec<&str> = new_str.lines().collect();
+        lines[49] = "modified line 50";
+        let new_content = lines.join("\n") + "\n";
+
+        let delta = Delta::compute(&original, new_content.as_bytes());
+
+        assert!(!delta.is_full());
+        assert!(delta.data.len() < original.len() / 10);
+        let restored = delta.apply(&original).unwrap();
+        assert_eq!(restored, new_content.as_bytes());
+    }
+
+    #[test]
+    fn test_binary_delta_with_insertions() {
+        let original: Vec<u8> = (0..20_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut new = original.clone();
+        new.splice(1000..1000, [0xde, 0xad, 0xbe, 0xef]);
+        new.drain(15_000..15_100);
+        new[9_000] ^= 0xff;
+
+        let delta = Delta::compute(&original, &new);
+        assert_eq!(delta.encoding, DeltaEncoding::Bsdiff);
+        assert_eq!(delta.apply(&original).unwrap(), new);
+    }
+
+    #[test]
+    fn test_delta_roundtrip() {
+        let original =
+            b"Original content here\nWith multiple lines\nAnd some more text\n".repeat(50);
+        let new =
+            b"Modified content here\nWith multiple lines\nAnd some different text\n".repeat(50);
+
+        let delta = Delta::compute(&original, &new);
+        let restored = delta.apply(&original).unwrap();
+
+        assert_eq!(restored, new.to_vec());
+    }
+
+    #[test]
+    fn test_serialization_roundtrip() {
+        let original = vec![7u8; 8192];
+        let mut new = original.clone();
+        new[100] = 8;
+
+        let delta = Delta::compute(&original, &new);
+        let parsed = Delta::from_bytes(&delta.clone().into_bytes()).unwrap();
+        assert_eq!(parsed, delta);
+        assert_eq!(parsed.apply(&original).unwrap(), new);
+    }
+
+    #[test]
+    fn test_apply_rejects_wrong_base() {
+        let original = b"abcdefgh".repeat(1024);
+        let mut new = original.clone();
+        new[10] = b'z';
+
+        let delta = Delta::compute(&original, &new);
+        assert!(delta.apply(&original[1..]).is_none());
+    }
+
+    #[test]
+    fn test_suffix_array_sorted() {
+        let data = b"banana";
+        let sa = suffix_array(data);
+        assert_eq!(sa, vec![6, 5, 3, 1, 0, 4, 2]);
+    }
+
+    #[test]
+    fn test_line_diff_hunks_have_bounded_context() {
+        let original: String = (0..40).map(|i| format!("line {}\n", i)).collect();
+        let new = original
+            .replace("line 5\n", "line five\n")
+            .replace("line 30\n", "line thirty\n");
+
+        let diff = line_diff(&original, &new);
+        assert_eq!(diff.hunks.len(), 2);
+        let first = &diff.hunks[0];
+        assert_eq!((first.original_start, first.original_count), (2, 7));
+        assert_eq!((first.new_start, first.new_count), (2, 7));
+        assert_eq!(first.lines.len(), 8);
+    }
+
+    #[test]
+    fn test_line_diff() {
+        let diff = line_diff("a\nb\nc\n", "a\nx\nc\n");
+        assert_eq!(diff.hunks.len(), 1);
+        assert!(diff.hunks[0]
+            .lines
+            .contains(&DiffLine::Removed("b".to_string())));
+        assert!(diff.hunks[0]
+            .lines
+            .contains(&DiffLine::Added("x".to_string())));
+    }
+}
@@ -4,6 +4,7 @@
 //
 // Error types for reversible-core
 
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
 /// Result type alias for reversible-core operations
@@ -39,6 +40,21 @@ pub enum ReversibleError {
     #[error("Invalid operation ID: {0}")]
     InvalidOperationId(String),
 
+    #[error("Later operations depend on {id}: {}", dependents.join(", "))]
+    HasDependents { id: String, dependents: Vec<String> },
+
+    #[error("Deltas stored against {hash} depend on it: {}", deltas.join(", "))]
+    DeltaDependents { hash: String, deltas: Vec<String> },
+
+    #[error("Invalid transaction ID: {0}")]
+    InvalidTransactionId(String),
+
+    #[error("Rollback incomplete: {0}")]
+    RollbackIncomplete(String),
+
+    #[error("Idempotency key already used for a different operation: {0}")]
+    IdempotencyConflict(String),
+
     #[error("Content integrity error: expected {expected}, got {actual}")]
     ContentIntegrityError { expected: String, actual: String },
 
@@ -48,9 +64,69 @@ pub enum ReversibleError {
     #[error("Permission denied: {0}")]
     PermissionDenied(String),
 
-    #[error("Invalid glob pattern: {0}")]
+    #[error("Invalid pattern: {0}")]
     InvalidPattern(String),
 
+    #[error("Invalid file mode: {0}")]
+    InvalidMode(String),
+
+    #[error("Storage quota exceeded: {0}")]
+    QuotaExceeded(String),
+
+    #[error("Insufficient disk space: {0}")]
+    InsufficientSpace(String),
+
+    #[error("Invalid actor: {0}")]
+    InvalidActor(String),
+
+    #[error("Invalid retention period: {0}")]
+    InvalidRetention(String),
+
+    #[error("Invalid reference: {0}")]
+    InvalidReference(String),
+
+    #[error("Under legal hold: {0}")]
+    LegalHold(String),
+
+    #[error("Protected path: {0}")]
+    ProtectedPath(String),
+
+    #[error("Outside the root: {0}")]
+    OutsideRoot(String),
+
+    #[error("Snapshot not found: {0}")]
+    SnapshotNotFound(String),
+
+    #[error("Invalid store backup: {0}")]
+    InvalidBackup(String),
+
+    #[error("Daemon error: {0}")]
+    Daemon(String),
+
+    #[error("Remote not found: {0}")]
+    RemoteNotFound(String),
+
+    #[error("Histories have diverged: {0}")]
+    Diverged(String),
+
+    #[error("Workspace not found: {0}")]
+    WorkspaceNotFound(String),
+
+    #[error("Access denied: {0}")]
+    AccessDenied(String),
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("Storage backend error: {0}")]
+    Backend(String),
+
+    #[error("Not supported: {0}")]
+    Unsupported(String),
+
+    #[error("File locked: {0}")]
+    Locked(String),
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
@@ -59,4 +135,189 @@ pub enum ReversibleError {
 
     #[error("Glob pattern error: {0}")]
     Glob(#[from] glob::PatternError),
+
+    /// Another error, with the path or operation it concerns
+    #[error(transparent)]
+    Context(Box<ErrorContext>),
+}
+
+/// An error with the path and operation it arose on. Displays as the
+/// error itself, and its [`std::error::Error::source`] is the error's.
+#[derive(Debug)]
+pub struct ErrorContext {
+    pub error: ReversibleError,
+    pub path: Option<PathBuf>,
+    pub operation_id: Option<String>,
+}
+
+impl std::fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl std::error::Error for ErrorContext {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+impl ReversibleError {
+    /// The error without any [`ErrorContext`], to match on
+    pub fn inner(&self) -> &ReversibleError {
+        match self {
+            Self::Context(context) => context.error.inner(),
+            error => error,
+        }
+    }
+
+    /// Stable machine-readable code for the kind of error, for library,
+    /// FFI and JSON consumers to act on rather than parsing messages
+    pub fn code(&self) -> &'static str {
+        match self.inner() {
+            Self::NotInitialized(_) => "not_initialized",
+            Self::FileNotFound(_) => "file_not_found",
+            Self::DirectoryNotFound(_) => "directory_not_found",
+            Self::PathExists(_) => "path_exists",
+            Self::OperationFailed(_) => "operation_failed",
+            Self::NoActiveTransaction => "no_active_transaction",
+            Self::TransactionActive(_) => "transaction_active",
+            Self::NothingToUndo => "nothing_to_undo",
+            Self::InvalidOperationId(_) => "invalid_operation_id",
+            Self::HasDependents { .. } => "has_dependents",
+            Self::DeltaDependents { .. } => "delta_dependents",
+            Self::InvalidTransactionId(_) => "invalid_transaction_id",
+            Self::RollbackIncomplete(_) => "rollback_incomplete",
+            Self::IdempotencyConflict(_) => "idempotency_conflict",
+            Self::ContentIntegrityError { .. } => "content_integrity",
+            Self::MetadataCorrupted(_) => "metadata_corrupted",
+            Self::PermissionDenied(_) => "permission_denied",
+            Self::InvalidPattern(_) | Self::Glob(_) => "invalid_pattern",
+            Self::InvalidMode(_) => "invalid_mode",
+            Self::QuotaExceeded(_) => "quota_exceeded",
+            Self::InsufficientSpace(_) => "insufficient_space",
+            Self::InvalidActor(_) => "invalid_actor",
+            Self::InvalidRetention(_) => "invalid_retention",
+            Self::InvalidReference(_) => "invalid_reference",
+            Self::LegalHold(_) => "legal_hold",
+            Self::ProtectedPath(_) => "protected_path",
+            Self::OutsideRoot(_) => "outside_root",
+            Self::SnapshotNotFound(_) => "snapshot_not_found",
+            Self::InvalidBackup(_) => "invalid_backup",
+            Self::Daemon(_) => "daemon",
+            Self::RemoteNotFound(_) => "remote_not_found",
+            Self::Diverged(_) => "diverged",
+            Self::WorkspaceNotFound(_) => "workspace_not_found",
+            Self::AccessDenied(_) => "access_denied",
+            Self::InvalidConfig(_) => "invalid_config",
+            Self::Backend(_) => "backend",
+            Self::Unsupported(_) => "unsupported",
+            Self::Locked(_) => "locked",
+            Self::Io(e) => match e.kind() {
+                std::io::ErrorKind::NotFound => "file_not_found",
+                std::io::ErrorKind::AlreadyExists => "path_exists",
+                std::io::ErrorKind::PermissionDenied => "permission_denied",
+                _ => "io",
+            },
+            Self::Json(_) => "json",
+            Self::Context(_) => unreachable!("inner() strips context"),
+        }
+    }
+
+    /// The path the error concerns, if known
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Context(context) => context.path.as_deref().or_else(|| context.error.path()),
+            Self::FileNotFound(path) | Self::DirectoryNotFound(path) | Self::PathExists(path) => {
+                Some(Path::new(path))
+            }
+            _ => None,
+        }
+    }
+
+    /// The ID of the operation the error concerns, if any
+    pub fn operation_id(&self) -> Option<&str> {
+        match self {
+            Self::Context(context) => context
+                .operation_id
+                .as_deref()
+                .or_else(|| context.error.operation_id()),
+            Self::InvalidOperationId(id) | Self::HasDependents { id, .. } => Some(id),
+            _ => None,
+        }
+    }
+
+    /// Record the path the error arose on, unless it already has one
+    pub fn with_path(self, path: &Path) -> Self {
+        self.with_context(|context| {
+            context.path.get_or_insert_with(|| path.to_path_buf());
+        })
+    }
+
+    /// Record the operation the error arose on, unless it already has one
+    pub fn with_operation(self, id: &str) -> Self {
+        self.with_context(|context| {
+            context.operation_id.get_or_insert_with(|| id.to_string());
+        })
+    }
+
+    fn with_context(self, f: impl FnOnce(&mut ErrorContext)) -> Self {
+        let mut context = match self {
+            Self::Context(context) => context,
+            error => Box::new(ErrorContext {
+                error,
+                path: None,
+                operation_id: None,
+            }),
+        };
+        f(&mut context);
+        Self::Context(context)
+    }
+
+    /// Whether the error is confined to one operation, so a rollback can
+    /// move on to the next. Storage and metadata failures are not: they
+    /// would fail every remaining operation the same way.
+    pub fn is_recoverable(&self) -> bool {
+        !matches!(
+            self.inner(),
+            Self::NotInitialized(_)
+                | Self::MetadataCorrupted(_)
+                | Self::QuotaExceeded(_)
+                | Self::InsufficientSpace(_)
+                | Self::Backend(_)
+                | Self::Unsupported(_)
+                | Self::Json(_)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn test_error_codes_and_context() {
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "gone");
+        let error = ReversibleError::from(io)
+            .with_path(Path::new("a.txt"))
+            .with_operation("op-1")
+            .with_path(Path::new("ignored.txt"));
+        assert_eq!(error.code(), "file_not_found");
+        assert_eq!(error.path(), Some(Path::new("a.txt")));
+        assert_eq!(error.operation_id(), Some("op-1"));
+        assert_eq!(error.to_string(), "IO error: gone");
+        assert_eq!(error.source().unwrap().to_string(), "gone");
+        assert!(matches!(error.inner(), ReversibleError::Io(_)));
+
+        let error = ReversibleError::HasDependents {
+            id: "op-2".to_string(),
+            dependents: vec!["op-3".to_string()],
+        };
+        assert_eq!(error.code(), "has_dependents");
+        assert_eq!(error.operation_id(), Some("op-2"));
+        assert!(!ReversibleError::Backend("down".to_string())
+            .with_path(Path::new("x"))
+            .is_recoverable());
+    }
 }
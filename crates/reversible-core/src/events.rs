@@ -0,0 +1,123 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Events: in-process notifications of what the stores and executor do
+// For observers such as a daemon; diagnostics go through `tracing`
+
+use crate::content_store::ContentHash;
+use crate::metadata::OperationType;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+/// Something that happened to the files or the history
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// An operation is about to run
+    OperationStarted {
+        op_type: OperationType,
+        path: PathBuf,
+    },
+    /// An operation ran (and was recorded, unless run without history)
+    OperationCompleted {
+        id: String,
+        op_type: OperationType,
+        path: PathBuf,
+    },
+    /// An operation failed; nothing was recorded
+    OperationFailed {
+        op_type: OperationType,
+        path: PathBuf,
+        error: String,
+    },
+    /// A new blob was written to the content store
+    ContentStored {
+        hash: ContentHash,
+        /// Size of the content
+        bytes: u64,
+        /// Bytes written, after compression or delta encoding
+        stored_bytes: u64,
+    },
+    /// An operation was undone by the operation `undo_operation_id`
+    UndoPerformed {
+        operation_id: String,
+        undo_operation_id: String,
+    },
+    TransactionBegun {
+        id: String,
+    },
+    TransactionCommitted {
+        id: String,
+    },
+    TransactionRolledBack {
+        id: String,
+    },
+}
+
+/// Fan-out of [`Event`]s to subscribers.
+///
+/// Clones share subscribers, so one bus can be handed to every store.
+/// Publishing never blocks; subscribers whose receiver was dropped are
+/// forgotten. With no subscribers, publishing costs one uncontended lock.
+#[derive(Debug, Clone, Default)]
+pub struct EventBus {
+    subscribers: Arc<Mutex<Vec<Sender<Event>>>>,
+}
+
+impl EventBus {
+    /// Create a bus with no subscribers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Receive every event published from now on
+    pub fn subscribe(&self) -> Receiver<Event> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(sender);
+        receiver
+    }
+
+    /// Send `event` to every subscriber
+    pub fn publish(&self, event: Event) {
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscribers_receive_events() {
+        let bus = EventBus::new();
+        bus.publish(Event::TransactionBegun {
+            id: "before".to_string(),
+        });
+
+        let first = bus.subscribe();
+        let second = bus.clone().subscribe();
+        let event = Event::TransactionCommitted {
+            id: "tx".to_string(),
+        };
+        bus.publish(event.clone());
+        assert_eq!(first.try_recv().unwrap(), event);
+        assert_eq!(second.try_recv().unwrap(), event);
+        assert!(first.try_recv().is_err());
+
+        // A dropped receiver is forgotten
+        drop(second);
+        bus.publish(event.clone());
+        assert_eq!(bus.subscribers.lock().unwrap().len(), 1);
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({"event": "transaction_committed", "id": "tx"})
+        );
+    }
+}
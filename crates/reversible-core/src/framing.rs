@@ -0,0 +1,148 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Log Framing: optional zstd compression of the JSON logs
+// A log file is either plain JSON or a zstd frame of it, told apart by the
+// zstd magic number, so readers take both and compressed and plain files
+// can sit side by side. Reads are streamed, so a large log is never held
+// in memory as text as well as parsed.
+
+use std::io::{BufRead, BufReader, Cursor, Read};
+use std::path::Path;
+
+use crate::backend::FileBackend;
+use crate::error::Result;
+
+/// First bytes of every zstd frame
+pub const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// zstd level logs are compressed at: logs are compressed once, when they
+/// are sealed, and read often, so a slow level is worth it
+pub const COMPRESSION_LEVEL: i32 = 12;
+
+/// Whether `bytes` start with a zstd frame
+pub fn is_compressed(bytes: &[u8]) -> bool {
+    bytes.starts_with(&ZSTD_MAGIC)
+}
+
+/// Compress `plain` into a single zstd frame
+pub fn compress(plain: &[u8]) -> Result<Vec<u8>> {
+    Ok(zstd::encode_all(plain, COMPRESSION_LEVEL)?)
+}
+
+/// `content` compressed if `compress` is set, otherwise as is
+pub fn encode(content: Vec<u8>, compress: bool) -> Result<Vec<u8>> {
+    if compress {
+        self::compress(&content)
+    } else {
+        Ok(content)
+    }
+}
+
+/// Plain text read from a log file that may be compressed
+pub struct LogReader<'a> {
+    inner: Box<dyn BufRead + Send + 'a>,
+    compressed: bool,
+}
+
+impl<'a> LogReader<'a> {
+    /// Read plain text from `reader`, decompressing it as it is read if it
+    /// holds a zstd frame
+    pub fn new<R: Read + Send + 'a>(mut reader: R) -> Result<Self> {
+        let mut head = Vec::with_capacity(ZSTD_MAGIC.len());
+        reader
+            .by_ref()
+            .take(ZSTD_MAGIC.len() as u64)
+            .read_to_end(&mut head)?;
+        let compressed = is_compressed(&head);
+        let reader = Cursor::new(head).chain(reader);
+        let inner: Box<dyn BufRead + Send + 'a> = if compressed {
+            Box::new(BufReader::new(zstd::Decoder::new(reader)?))
+        } else {
+            Box::new(BufReader::new(reader))
+        };
+        Ok(Self { inner, compressed })
+    }
+
+    /// Whether the file is compressed
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+}
+
+impl Read for LogReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl BufRead for LogReader<'_> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt)
+    }
+}
+
+/// Open the log file at `path` on `backend` for streaming plain text
+pub fn open(backend: &dyn FileBackend, path: &Path) -> Result<LogReader<'static>> {
+    LogReader::new(backend.open_read(path)?)
+}
+
+/// What compressing a set of log files saved
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Compressed {
+    /// Files compressed
+    pub files: usize,
+    /// Their size before, in bytes
+    pub before: u64,
+    /// Their size after, in bytes
+    pub after: u64,
+}
+
+impl Compressed {
+    /// Count one file compressed from `before` to `after` bytes
+    pub fn add(&mut self, before: u64, after: u64) {
+        self.files += 1;
+        self.before += before;
+        self.after += after;
+    }
+
+    /// Fold in another set
+    pub fn merge(&mut self, other: Compressed) {
+        self.files += other.files;
+        self.before += other.before;
+        self.after += other.after;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decoder_reads_plain_and_compressed() {
+        let plain = b"{\"a\": 1}\n{\"b\": 2}\n".repeat(100);
+        let compressed = compress(&plain).unwrap();
+        assert!(is_compressed(&compressed));
+        assert!(compressed.len() < plain.len() / 10);
+
+        for bytes in [&plain, &compressed] {
+            let mut reader = LogReader::new(bytes.as_slice()).unwrap();
+            assert_eq!(reader.is_compressed(), bytes == &compressed);
+            let mut text = Vec::new();
+            reader.read_to_end(&mut text).unwrap();
+            assert_eq!(text, plain);
+        }
+        // Shorter than the magic number
+        let mut text = Vec::new();
+        LogReader::new(&b"{}"[..])
+            .unwrap()
+            .read_to_end(&mut text)
+            .unwrap();
+        assert_eq!(text, b"{}");
+    }
+}
@@ -15,21 +15,47 @@
 // use: content-addressed storage, operation metadata, and the
 // ReversibleExecutor trait.
 
-#![forbid(unsafe_code)]
+// Unsafe code is denied everywhere but the Win32 calls in `ntfs` and the
+// file mapping in `mapped`
+#![deny(unsafe_code)]
 
+pub mod actor;
+pub mod backend;
 pub mod content_store;
+pub mod context;
+pub mod delta;
 pub mod error;
+pub mod events;
+pub mod framing;
+pub mod lock;
 pub mod manifest;
+pub mod mapped;
 pub mod metadata;
+pub mod ntfs;
+pub mod ownership;
+pub mod pack;
+pub mod refs;
 pub mod transaction;
 
-pub use content_store::{ContentHash, ContentStore};
-pub use error::{Result, ReversibleError};
+pub use actor::{Actor, ActorResolver, EnvActorResolver};
+pub use backend::{
+    CachingBackend, Capabilities, FileBackend, LocalBackend, RetryBackend, RetryPolicy,
+};
+pub use content_store::{BlobLocation, ContentHash, ContentStore, HashAlgorithm};
+pub use context::{ContextCapture, OperationContext};
+pub use delta::Delta;
+pub use error::{ErrorContext, Result, ReversibleError};
+pub use events::{Event, EventBus};
+pub use lock::{FileLock, FileLocking, LockMode};
 pub use manifest::ManifestEmitter;
-pub use metadata::{FileMetadata, MetadataStore, OperationLog, OperationMetadata, OperationType};
+pub use mapped::FileContent;
+pub use metadata::{
+    operation_id_for_key, ByteAccounting, DamagedRecord, DataStream, DependencyGraph, FileMetadata,
+    MetadataStore, OperationLog, OperationMetadata, OperationType, PreservedAttribute, PruneRule,
+};
 pub use transaction::{
-    OperationPreview, Transaction, TransactionLog, TransactionManager, TransactionPreview,
-    TransactionState,
+    ContentChange, OperationPreview, RollbackStatus, Transaction, TransactionLog,
+    TransactionManager, TransactionPreview, TransactionState,
 };
 
 /// Trait that any reversible operation system must implement.
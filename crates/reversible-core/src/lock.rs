@@ -0,0 +1,181 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// File Locking: advisory locks on the files an operation changes
+// Held from capturing the original content until the change is made, so a
+// cooperating process cannot slip a write in between and leave history
+// holding the wrong original. Locks are flock(2) locks on Unix and are
+// not taken elsewhere: Windows byte-range locks are mandatory and would
+// block the change itself.
+
+use crate::error::{Result, ReversibleError};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How long to sleep between attempts while waiting for a lock
+const RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Whether operations lock the files they change (`Config.file_locking`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileLocking {
+    pub enabled: bool,
+    /// How long to wait for another process to release a file before
+    /// failing with [`ReversibleError::Locked`]; 0 fails at once
+    pub timeout_ms: u64,
+}
+
+impl Default for FileLocking {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            timeout_ms: 5000,
+        }
+    }
+}
+
+/// Whether a lock keeps out other readers too
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// For a file being read: others may read, but not change it
+    Shared,
+    /// For a file being changed
+    Exclusive,
+}
+
+/// An advisory lock on a file, released when dropped
+#[derive(Debug)]
+pub struct FileLock {
+    path: PathBuf,
+    // Dropping the file releases the lock
+    _file: File,
+}
+
+impl FileLock {
+    /// The locked file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl FileLocking {
+    /// Lock `path`, waiting up to the timeout for another holder to let
+    /// go. `None` when locking is off or unsupported here, or there is no
+    /// file to lock.
+    pub fn lock(&self, path: &Path, mode: LockMode) -> Result<Option<FileLock>> {
+        if !self.enabled || !cfg!(unix) || !path.is_file() {
+            return Ok(None);
+        }
+        // Opened for reading: the lock is what matters, and read access is
+        // all a shared lock on a read-only file allows
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let deadline = Instant::now() + Duration::from_millis(self.timeout_ms);
+        loop {
+            let attempt = match mode {
+                LockMode::Shared => file.try_lock_shared(),
+                LockMode::Exclusive => file.try_lock(),
+            };
+            match attempt {
+                Ok(()) => {
+                    return Ok(Some(FileLock {
+                        path: path.to_path_buf(),
+                        _file: file,
+                    }))
+                }
+                Err(std::fs::TryLockError::WouldBlock) if Instant::now() < deadline => {
+                    std::thread::sleep(RETRY_INTERVAL);
+                }
+                Err(std::fs::TryLockError::WouldBlock) => {
+                    return Err(ReversibleError::Locked(format!(
+                        "{} is locked by another process (waited {} ms)",
+                        path.display(),
+                        self.timeout_ms
+                    )));
+                }
+                // Filesystems without locks (some network mounts) go unlocked
+                Err(std::fs::TryLockError::Error(e))
+                    if e.kind() == std::io::ErrorKind::Unsupported =>
+                {
+                    tracing::debug!(path = %path.display(), "file locking unsupported here");
+                    return Ok(None);
+                }
+                Err(std::fs::TryLockError::Error(e)) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Lock each of `paths` in order, releasing those already taken if one
+    /// cannot be. Paths are sorted first, so two processes locking the
+    /// same files cannot each hold one the other waits for.
+    pub fn lock_all(&self, paths: &[(&Path, LockMode)]) -> Result<Vec<FileLock>> {
+        let mut paths = paths.to_vec();
+        paths.sort_by_key(|(path, _)| *path);
+        paths.dedup_by(|(a, a_mode), (b, b_mode)| {
+            // Keep the stronger of two locks on the same file
+            if a == b && *a_mode == LockMode::Exclusive {
+                *b_mode = LockMode::Exclusive;
+            }
+            a == b
+        });
+        let mut locks = Vec::new();
+        for (path, mode) in paths {
+            locks.extend(self.lock(path, mode)?);
+        }
+        Ok(locks)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_locks_exclude_each_other_until_dropped() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("a.txt");
+        std::fs::write(&path, "a").unwrap();
+        let locking = FileLocking {
+            enabled: true,
+            timeout_ms: 50,
+        };
+
+        let held = locking.lock(&path, LockMode::Exclusive).unwrap().unwrap();
+        assert_eq!(held.path(), path);
+        let error = locking.lock(&path, LockMode::Shared).unwrap_err();
+        assert_eq!(error.code(), "locked");
+        drop(held);
+
+        // Readers share; a writer waits for them
+        let first = locking.lock(&path, LockMode::Shared).unwrap();
+        let second = locking.lock(&path, LockMode::Shared).unwrap();
+        assert!(first.is_some() && second.is_some());
+        assert!(locking.lock(&path, LockMode::Exclusive).is_err());
+        drop((first, second));
+
+        let locks = locking
+            .lock_all(&[(&path, LockMode::Shared), (&path, LockMode::Exclusive)])
+            .unwrap();
+        assert_eq!(locks.len(), 1);
+        assert!(locking.lock(&path, LockMode::Shared).is_err());
+        drop(locks);
+
+        // Nothing to lock, or locking off
+        assert!(locking
+            .lock(&tmp.path().join("missing"), LockMode::Exclusive)
+            .unwrap()
+            .is_none());
+        let off = FileLocking {
+            enabled: false,
+            ..locking
+        };
+        assert!(off.lock(&path, LockMode::Exclusive).unwrap().is_none());
+    }
+}
@@ -41,7 +41,7 @@ pub fn generate(subsystem: &str, store: &MetadataStore) -> String {
 
         // Header
         manifest.push_str("@manifest\n");
-        manifest.push_str(&format!("  version = \"1.0\"\n"));
+        manifest.push_str("  version = \"1.0\"\n");
         manifest.push_str(&format!("  subsystem = \"{}\"\n", subsystem));
         manifest.push_str(&format!("  timestamp = \"{}\"\n", timestamp));
         manifest.push_str(&format!(
@@ -105,7 +105,7 @@ fn compute_merkle_root(operations: &[OperationMetadata]) -> Vec<u8> {
             };
 
             let current = hasher.finalize_reset();
-            hasher.update(&current);
+            hasher.update(current);
             hasher.update(&op_hash);
         }
 
@@ -0,0 +1,97 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Mapped: a file's content, memory-mapped when it is large
+// Hashing, delta computation and comparison of multi-GB files then read
+// through the page cache instead of holding a second copy in memory.
+// Maps can misbehave on network filesystems, so callers can turn them off.
+
+use crate::error::Result;
+use memmap2::Mmap;
+use std::fs::{self, File};
+use std::ops::Deref;
+use std::path::Path;
+
+/// Files smaller than this are read rather than mapped: a map costs a
+/// syscall and page faults that only pay off for larger files
+pub const MAP_MIN_BYTES: u64 = 1024 * 1024;
+
+/// A file's bytes, either mapped or read into memory
+pub enum FileContent {
+    Mapped(Mmap),
+    Read(Vec<u8>),
+}
+
+impl FileContent {
+    /// Read `path`, mapping it if `map` is set and it holds at least
+    /// [`MAP_MIN_BYTES`]
+    pub fn open(path: &Path, map: bool) -> Result<Self> {
+        if map {
+            let file = File::open(path)?;
+            let meta = file.metadata()?;
+            if meta.is_file() && meta.len() >= MAP_MIN_BYTES {
+                return Ok(Self::Mapped(map_file(&file)?));
+            }
+        }
+        Ok(Self::Read(fs::read(path)?))
+    }
+
+    /// Whether the content is mapped rather than read
+    pub fn is_mapped(&self) -> bool {
+        matches!(self, Self::Mapped(_))
+    }
+}
+
+impl Deref for FileContent {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Mapped(map) => map,
+            Self::Read(bytes) => bytes,
+        }
+    }
+}
+
+impl AsRef<[u8]> for FileContent {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+#[allow(unsafe_code)]
+fn map_file(file: &File) -> std::io::Result<Mmap> {
+    // SAFETY: the map is read-only and private to this process. Another
+    // process truncating the file while it is mapped would fault on
+    // access; that is the risk `memory_map: false` exists to avoid.
+    unsafe { Mmap::map(file) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_maps_only_large_files() {
+        let temp = TempDir::new().unwrap();
+        let small = temp.path().join("small");
+        let large = temp.path().join("large");
+        fs::write(&small, "small").unwrap();
+        let bytes: Vec<u8> = (0..MAP_MIN_BYTES).map(|i| i as u8).collect();
+        fs::write(&large, &bytes).unwrap();
+
+        let content = FileContent::open(&small, true).unwrap();
+        assert!(!content.is_mapped());
+        assert_eq!(&*content, b"small");
+
+        let content = FileContent::open(&large, true).unwrap();
+        assert!(content.is_mapped());
+        assert_eq!(&*content, bytes.as_slice());
+
+        let content = FileContent::open(&large, false).unwrap();
+        assert!(!content.is_mapped());
+        assert_eq!(&*content, bytes.as_slice());
+    }
+}
@@ -5,12 +5,22 @@
 // Metadata Store: Operation log with complete reverse information
 // Implements the formal model from the JanusKey white paper
 
+use crate::actor::Actor;
+use crate::backend::{FileBackend, LocalBackend};
 use crate::content_store::ContentHash;
+use crate::context::OperationContext;
 use crate::error::{Result, ReversibleError};
+use crate::framing::{self, Compressed};
+use crate::lock::{FileLock, FileLocking, LockMode};
+use crate::refs::{self, References};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
+use std::io::BufRead;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use uuid::Uuid;
 
 /// Operation type identifier.
@@ -21,7 +31,7 @@
 /// - Move is self-inverse (swap source/destination)
 /// - Copy → Delete (of the copy)
 /// - Chmod/Chown are self-inverse (store old values)
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum OperationType {
     Delete,
@@ -71,18 +81,102 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 pub struct FileMetadata {
     /// Unix permissions (e.g., 0o644)
     pub permissions: u32,
-    /// File owner (username or uid)
+    /// File owner's uid
     pub owner: String,
-    /// File group (groupname or gid)
+    /// File group's gid
     pub group: String,
+    /// Owner's user name where it was captured, if known there
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner_name: Option<String>,
+    /// Group's name where it was captured, if known there
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group_name: Option<String>,
     /// Original file size
     pub size: u64,
     /// Last modification time
     pub modified: DateTime<Utc>,
+    /// Last access time, where the platform records one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accessed: Option<DateTime<Utc>>,
     /// Is this a symbolic link?
     pub is_symlink: bool,
     /// Symlink target if is_symlink
     pub symlink_target: Option<String>,
+    /// Windows file attributes (`FILE_ATTRIBUTE_*`: read-only, hidden,
+    /// system, archive)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attributes: Option<u32>,
+    /// NTFS alternate data streams, captured by [`crate::ntfs::capture_streams`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub streams: Vec<DataStream>,
+    /// Metadata of the directory holding it (delete), for an undo that
+    /// has to recreate that directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<Box<FileMetadata>>,
+}
+
+/// A named NTFS data stream (`file.txt:Zone.Identifier`) whose content
+/// is in the ContentStore
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataStream {
+    /// Stream name, without the leading `:` or the `:$DATA` type
+    pub name: String,
+    /// Hash of the stream's content
+    pub content_hash: ContentHash,
+}
+
+/// What a copy carries over from its source besides the content
+/// (`jk cp --preserve`). The mode is always carried over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PreservedAttribute {
+    /// Permission bits
+    Mode,
+    /// Modification and access times
+    Timestamps,
+    /// Owner and group, by ID
+    Ownership,
+    /// Extended attributes
+    Xattr,
+}
+
+impl PreservedAttribute {
+    pub const ALL: [Self; 4] = [Self::Mode, Self::Timestamps, Self::Ownership, Self::Xattr];
+
+    /// Parse a comma-separated list of attributes, or `all`
+    pub fn parse_list(list: &str) -> std::result::Result<BTreeSet<Self>, String> {
+        if list == "all" {
+            return Ok(Self::ALL.into_iter().collect());
+        }
+        list.split(',').map(str::trim).map(str::parse).collect()
+    }
+}
+
+impl std::fmt::Display for PreservedAttribute {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mode => write!(f, "mode"),
+            Self::Timestamps => write!(f, "timestamps"),
+            Self::Ownership => write!(f, "ownership"),
+            Self::Xattr => write!(f, "xattr"),
+        }
+    }
+}
+
+impl std::str::FromStr for PreservedAttribute {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|attribute| attribute.to_string() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown attribute '{}' (expected mode, timestamps, ownership, xattr or all)",
+                    s
+                )
+            })
+    }
 }
 
 impl FileMetadata {
@@ -91,17 +185,25 @@ pub fn from_path(path: &Path) -> Result<Self> {
         let metadata = fs::symlink_metadata(path)?;
 
         #[cfg(unix)]
-        let (permissions, owner, group) = {
+        let (permissions, owner, group, owner_name, group_name) = {
             use std::os::unix::fs::MetadataExt;
             (
                 metadata.mode(),
                 metadata.uid().to_string(),
                 metadata.gid().to_string(),
+                crate::ownership::user_name(metadata.uid()),
+                crate::ownership::group_name(metadata.gid()),
             )
         };
 
         #[cfg(not(unix))]
-        let (permissions, owner, group) = (0o644, "unknown".to_string(), "unknown".to_string());
+        let (permissions, owner, group, owner_name, group_name) = (
+            0o644,
+            "unknown".to_string(),
+            "unknown".to_string(),
+            None,
+            None,
+        );
 
         let is_symlink = metadata.file_type().is_symlink();
         let symlink_target = if is_symlink {
@@ -112,17 +214,56 @@ pub fn from_path(path: &Path) -> Result<Self> {
             None
         };
 
+        #[cfg(windows)]
+        let attributes = {
+            use std::os::windows::fs::MetadataExt;
+            Some(metadata.file_attributes())
+        };
+
+        #[cfg(not(windows))]
+        let attributes = None;
+
         Ok(Self {
             permissions,
             owner,
             group,
+            owner_name,
+            group_name,
             size: metadata.len(),
             modified: DateTime::from(metadata.modified()?),
+            accessed: metadata.accessed().ok().map(DateTime::from),
             is_symlink,
             symlink_target,
+            attributes,
+            streams: Vec::new(),
+            parent: None,
         })
     }
 
+    /// Restore the captured modification and access times. Restore any
+    /// streams first, as writing them changes the times, and the owner
+    /// after, as only the owner may set them.
+    pub fn restore_times(&self, path: &Path) -> Result<()> {
+        let mut times = fs::FileTimes::new().set_modified(self.modified.into());
+        if let Some(accessed) = self.accessed {
+            times = times.set_accessed(accessed.into());
+        }
+        #[cfg(windows)]
+        let file = {
+            use std::os::windows::fs::OpenOptionsExt;
+            // FILE_WRITE_ATTRIBUTES, and FILE_FLAG_BACKUP_SEMANTICS to
+            // open directories
+            fs::OpenOptions::new()
+                .access_mode(0x100)
+                .custom_flags(0x0200_0000)
+                .open(path)?
+        };
+        #[cfg(not(windows))]
+        let file = fs::File::open(path)?;
+        file.set_times(times)?;
+        Ok(())
+    }
+
     /// Apply metadata to a file (restore permissions)
     #[cfg(unix)]
     pub fn apply(&self, path: &Path) -> Result<()> {
@@ -132,7 +273,17 @@ pub fn apply(&self, path: &Path) -> Result<()> {
         Ok(())
     }
 
-    #[cfg(not(unix))]
+    /// Apply metadata to a file (restore attributes). Restore any streams
+    /// first: a read-only file cannot have them written.
+    #[cfg(windows)]
+    pub fn apply(&self, path: &Path) -> Result<()> {
+        if let Some(attributes) = self.attributes {
+            crate::ntfs::set_attributes(path, attributes)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(any(unix, windows)))]
     pub fn apply(&self, _path: &Path) -> Result<()> {
         Ok(())
     }
@@ -153,6 +304,25 @@ pub struct OperationMetadata {
     pub timestamp: DateTime<Utc>,
     /// User who performed the operation
     pub user: String,
+    /// Actor `user` declared it was acting for (see [`crate::actor`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+    /// Workspace of a shared store the operation was recorded in; its
+    /// paths are relative to that workspace's root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+    /// Host, process and working directory the operation was run from,
+    /// as far as they were recorded (see [`crate::context`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<OperationContext>,
+    /// References to what the operation was made for, such as a CI build
+    /// or ticket (see [`crate::refs`]), including its transaction's
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub refs: References,
+    /// What capturing the original content read and wrote to the content
+    /// store (delete and modify)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<ByteAccounting>,
     /// Primary path affected
     pub path: PathBuf,
     /// Secondary path (for move/copy operations)
@@ -161,16 +331,66 @@ pub struct OperationMetadata {
     pub content_hash: Option<ContentHash>,
     /// Hash of new content (for modify — references ContentStore)
     pub new_content_hash: Option<ContentHash>,
+    /// Base blob when `content_hash` is stored as a delta
+    #[serde(default)]
+    pub delta_base: Option<ContentHash>,
+    /// Hash of the original content when it is stored encrypted;
+    /// `content_hash` is then the hash of the envelope holding it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plaintext_hash: Option<ContentHash>,
     /// Original file metadata (permissions, owner, timestamps)
     pub original_metadata: Option<FileMetadata>,
-    /// New metadata (for chmod/chown)
+    /// New metadata (for chmod/chown, and the copy a copy made)
     pub new_metadata: Option<FileMetadata>,
+    /// What a copy carried over from its source besides the content
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub preserved: BTreeSet<PreservedAttribute>,
     /// Transaction ID if part of a transaction
     pub transaction_id: Option<String>,
     /// Whether this operation has been undone
     pub undone: bool,
     /// ID of the undo operation (if undone)
     pub undo_operation_id: Option<String>,
+    /// For an undo that restored content: the hash of the file re-read
+    /// from disk afterwards, equal to the undone operation's `content_hash`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verified_hash: Option<ContentHash>,
+    /// Idempotency key the operation was executed under; its ID is then
+    /// [`operation_id_for_key`] of the key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// Fingerprint of the operation's parameters, to tell a retry from a
+    /// different operation reusing the key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_fingerprint: Option<String>,
+}
+
+/// Bytes an operation read to capture original content, and bytes it
+/// added to the content store for it. `stored` is 0 where the content was
+/// already stored, and smaller than `read` where it was compressed or
+/// stored as a delta.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ByteAccounting {
+    pub read: u64,
+    pub stored: u64,
+}
+
+impl ByteAccounting {
+    /// `stored` as a fraction of `read`; `None` when nothing was read
+    pub fn ratio(&self) -> Option<f64> {
+        (self.read > 0).then(|| self.stored as f64 / self.read as f64)
+    }
+}
+
+/// Operation ID for an idempotency key: a version 8 UUID from the key's
+/// SHA-256, so the same key always names the same operation
+pub fn operation_id_for_key(key: &str) -> String {
+    let digest = Sha256::digest(format!("januskey-idempotency:{}", key));
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    uuid::Builder::from_custom_bytes(bytes)
+        .into_uuid()
+        .to_string()
 }
 
 impl OperationMetadata {
@@ -181,15 +401,26 @@ pub fn new(op_type: OperationType, path: PathBuf) -> Self {
             op_type,
             timestamp: Utc::now(),
             user: whoami::username(),
+            actor: None,
+            workspace: None,
+            context: None,
+            refs: References::new(),
+            bytes: None,
             path,
             path_secondary: None,
             content_hash: None,
             new_content_hash: None,
+            delta_base: None,
+            plaintext_hash: None,
             original_metadata: None,
             new_metadata: None,
+            preserved: BTreeSet::new(),
             transaction_id: None,
             undone: false,
             undo_operation_id: None,
+            verified_hash: None,
+            idempotency_key: None,
+            request_fingerprint: None,
         }
     }
 
@@ -211,17 +442,81 @@ pub fn with_new_content_hash(mut self, hash: ContentHash) -> Self {
         self
     }
 
+    /// Builder: record the delta base of the stored original content
+    pub fn with_delta_base(mut self, hash: ContentHash) -> Self {
+        self.delta_base = Some(hash);
+        self
+    }
+
+    /// Builder: record that `content_hash` holds this content encrypted
+    pub fn with_plaintext_hash(mut self, hash: ContentHash) -> Self {
+        self.plaintext_hash = Some(hash);
+        self
+    }
+
     /// Builder: set original file metadata
     pub fn with_original_metadata(mut self, metadata: FileMetadata) -> Self {
         self.original_metadata = Some(metadata);
         self
     }
 
+    /// Builder: record what capturing the original content cost
+    pub fn with_bytes(mut self, bytes: ByteAccounting) -> Self {
+        self.bytes = Some(bytes);
+        self
+    }
+
     /// Builder: set transaction ID
     pub fn with_transaction_id(mut self, id: String) -> Self {
         self.transaction_id = Some(id);
         self
     }
+
+    /// Builder: execute under an idempotency key, taking the ID it names
+    pub fn with_idempotency_key(mut self, key: &str, fingerprint: String) -> Self {
+        self.id = operation_id_for_key(key);
+        self.idempotency_key = Some(key.to_string());
+        self.request_fingerprint = Some(fingerprint);
+        self
+    }
+
+    /// Bytes undoing this writes back into the tree: the recorded size of
+    /// the content a delete or modify replaced
+    pub fn restore_bytes(&self) -> u64 {
+        match (self.content_hash.is_some(), &self.original_metadata) {
+            (true, Some(metadata)) => metadata.size,
+            _ => 0,
+        }
+    }
+
+    /// Hashes of the alternate data streams captured with the original
+    /// content, which undo needs as much as `content_hash`
+    pub fn stream_hashes(&self) -> impl Iterator<Item = &ContentHash> {
+        self.original_metadata
+            .iter()
+            .flat_map(|metadata| metadata.streams.iter().map(|stream| &stream.content_hash))
+    }
+
+    /// Apply `f` to the primary and secondary paths
+    fn map_paths(&mut self, f: impl Fn(&Path) -> PathBuf) {
+        self.path = f(&self.path);
+        if let Some(secondary) = &self.path_secondary {
+            self.path_secondary = Some(f(secondary));
+        }
+    }
+}
+
+/// Two logs interleaved by timestamp, taking from `a` first on ties
+fn merge_by_time<'a>(
+    a: &'a [OperationMetadata],
+    b: &'a [OperationMetadata],
+) -> impl Iterator<Item = &'a OperationMetadata> {
+    let (mut a, mut b) = (a.iter().peekable(), b.iter().peekable());
+    std::iter::from_fn(move || match (a.peek(), b.peek()) {
+        (Some(x), Some(y)) if y.timestamp < x.timestamp => b.next(),
+        (Some(_), _) => a.next(),
+        (None, _) => b.next(),
+    })
 }
 
 /// Serializable operation log (the append-only ledger)
@@ -242,53 +537,866 @@ fn default() -> Self {
     }
 }
 
+/// Maximum size of a log segment before appends rotate to a new one
+pub const SEGMENT_MAX_BYTES: u64 = 1024 * 1024;
+
+/// Maximum segment size on backends without a native append, where each
+/// append rewrites the whole segment
+pub const EMULATED_APPEND_SEGMENT_MAX_BYTES: u64 = 64 * 1024;
+
+/// Fewest characters of an operation ID shown to people
+pub const SHORT_ID_LEN: usize = 8;
+
+/// File in the log directory locked shared by appends and exclusively
+/// while the log is rewritten
+const LOG_LOCK: &str = "log.lock";
+
+/// How long to wait for another process to finish with the log
+const LOG_LOCK_TIMEOUT_MS: u64 = 30_000;
+
+/// One line of the on-disk operation log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum LogRecord {
+    /// An operation was recorded (replaces any earlier record with its ID)
+    Append(Box<OperationMetadata>),
+    /// An operation was undone by another operation
+    Undone {
+        id: String,
+        undo_operation_id: String,
+    },
+    /// An undo's restored content was re-read and matched
+    Verified { id: String, hash: ContentHash },
+    /// Operations recorded together; a torn line drops all of them
+    Batch(Vec<OperationMetadata>),
+}
+
+impl LogRecord {
+    /// The record with paths under `root` made relative to it
+    fn relative_to(&self, root: &Path) -> Self {
+        let relative = |op: &OperationMetadata| {
+            let mut op = op.clone();
+            op.map_paths(|path| match path.strip_prefix(root) {
+                Ok(relative) if !relative.as_os_str().is_empty() => relative.to_path_buf(),
+                _ => path.to_path_buf(),
+            });
+            op
+        };
+        match self {
+            Self::Append(op) => Self::Append(Box::new(relative(op))),
+            Self::Batch(ops) => Self::Batch(ops.iter().map(relative).collect()),
+            record => record.clone(),
+        }
+    }
+}
+
+/// A log line skipped by [`MetadataStore::recover_with_backend`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DamagedRecord {
+    /// Sequence number of the segment holding the line
+    pub segment: u64,
+    /// 1-based line number within the segment
+    pub line: usize,
+    /// Why the line could not be read
+    pub error: String,
+    /// The line as found
+    pub text: String,
+}
+
+/// Which operations pruning keeps before dependencies are added: an
+/// operation is kept if any condition holds
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PruneRule {
+    /// The last `keep` operations
+    pub keep: usize,
+    /// Operations recorded at or after this time
+    pub newer_than: Option<DateTime<Utc>>,
+    /// The newest operation on each path that stored its content, so
+    /// every path keeps a version to restore
+    pub latest_per_path: bool,
+}
+
+impl PruneRule {
+    /// Keep the last `keep` operations and nothing else
+    pub fn keep(keep: usize) -> Self {
+        Self {
+            keep,
+            ..Self::default()
+        }
+    }
+}
+
+/// Which live operations depend on which, through the paths they touch.
+///
+/// Operation B depends on an earlier operation A when B touched a path A
+/// wrote: undoing A would rewrite that path under B. A copy writes only its
+/// destination (its undo deletes the copy), a move writes both its paths,
+/// and every other operation writes its path. Undone operations and the
+/// undos that reversed them cancel out and take no part.
+#[derive(Debug, Clone, Default)]
+pub struct DependencyGraph {
+    /// Position in the log of each live operation, by ID
+    order: HashMap<String, usize>,
+    /// Live operations depending directly on each operation
+    dependents: HashMap<String, Vec<String>>,
+}
+
+impl DependencyGraph {
+    /// Build the graph of `operations`, in log order
+    pub fn build(operations: &[OperationMetadata]) -> Self {
+        let compensations: HashSet<&str> = operations
+            .iter()
+            .filter(|op| op.undone)
+            .filter_map(|op| op.undo_operation_id.as_deref())
+            .collect();
+
+        let mut graph = Self::default();
+        // The last live operation to write each path; earlier writers are
+        // reached through it
+        let mut writers: HashMap<&Path, &str> = HashMap::new();
+        for (pos, op) in operations.iter().enumerate() {
+            if op.undone || compensations.contains(op.id.as_str()) {
+                continue;
+            }
+            graph.order.insert(op.id.clone(), pos);
+            let touched = std::iter::once(op.path.as_path()).chain(op.path_secondary.as_deref());
+            for path in touched {
+                if let Some(&writer) = writers.get(path) {
+                    let dependents = graph.dependents.entry(writer.to_string()).or_default();
+                    if !dependents.contains(&op.id) {
+                        dependents.push(op.id.clone());
+                    }
+                }
+            }
+            let written = match op.op_type {
+                OperationType::Copy => vec![op.path_secondary.as_deref().unwrap_or(&op.path)],
+                OperationType::Move => std::iter::once(op.path.as_path())
+                    .chain(op.path_secondary.as_deref())
+                    .collect(),
+                _ => vec![op.path.as_path()],
+            };
+            for path in written {
+                writers.insert(path, &op.id);
+            }
+        }
+        graph
+    }
+
+    /// Live operations depending directly on `id`, oldest first
+    pub fn dependents(&self, id: &str) -> &[String] {
+        self.dependents.get(id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Every live operation depending on `id` directly or through others,
+    /// oldest first. Undoing them newest first, then `id`, is safe.
+    pub fn descendants(&self, id: &str) -> Vec<String> {
+        let mut found: HashSet<&str> = HashSet::new();
+        let mut pending: Vec<&str> = vec![id];
+        while let Some(next) = pending.pop() {
+            for dependent in self.dependents(next) {
+                if found.insert(dependent) {
+                    pending.push(dependent);
+                }
+            }
+        }
+        let mut descendants: Vec<String> = found.into_iter().map(str::to_string).collect();
+        descendants.sort_by_key(|id| self.order[id]);
+        descendants
+    }
+
+    /// Whether undoing `id` alone disturbs no later operation
+    pub fn is_independent(&self, id: &str) -> bool {
+        self.dependents(id).is_empty()
+    }
+}
+
 /// Metadata store for operation logging.
 ///
-/// Wraps an `OperationLog` with filesystem persistence.
+/// The log is a directory of line-delimited JSON segments
+/// (`00000001.jsonl`, `00000002.jsonl`, ...) on a [`FileBackend`].
+/// Appends add one line to the newest segment, rotating to a new segment
+/// once it reaches [`SEGMENT_MAX_BYTES`], so earlier records are never
+/// rewritten. Opening streams the segments into an in-memory log and ID
+/// index; [`MetadataStore::compact`] folds them back into fresh segments.
+///
+/// Segments no longer appended to can be compressed with zstd
+/// (`00000001.jsonl.zst`, see [`MetadataStore::compress_segments`]), and
+/// with [`MetadataStore::set_compression`] each is compressed as it fills.
+///
+/// Once a root is set, paths under it are written relative to it and
+/// read back against it, so the directory can be moved or restored
+/// elsewhere with its history.
+///
+/// Several roots can share one store as workspaces. Once a workspace is
+/// set, the operations recorded in other workspaces are kept aside: they
+/// are rewritten with the log but not listed, undone or pruned.
 pub struct MetadataStore {
-    /// Path to the metadata file
+    /// Backend holding the log segments
+    backend: Arc<dyn FileBackend>,
+    /// Directory of log segments
     path: PathBuf,
     /// Cached operation log
     log: OperationLog,
+    /// Position of each operation in `log.operations`, by ID
+    index: HashMap<String, usize>,
+    /// Sequence numbers of the segments on disk, oldest first
+    segments: Vec<u64>,
+    /// Segments stored compressed
+    compressed: HashSet<u64>,
+    /// Uncompressed copies of compressed segments, left by an interrupted
+    /// compression
+    superseded: Vec<PathBuf>,
+    /// Compress each segment once it is full
+    compress: bool,
+    /// Size of the newest segment in bytes
+    active_size: u64,
+    /// Declared actor stamped on appended operations
+    actor: Option<String>,
+    /// Directory paths are recorded relative to, see
+    /// [`MetadataStore::set_root`]
+    root: Option<PathBuf>,
+    /// Operations on disk with absolute paths under `root`
+    unmigrated: usize,
+    /// Workspace stamped on appended operations, see
+    /// [`MetadataStore::set_workspace`]
+    workspace: Option<String>,
+    /// Operations of other workspaces, in log order
+    others: Vec<OperationMetadata>,
+    /// Context stamped on appended operations, see
+    /// [`MetadataStore::set_context`]
+    context: Option<OperationContext>,
+    /// References stamped on appended operations, see
+    /// [`MetadataStore::set_refs`]
+    refs: References,
+    /// Opened by [`MetadataStore::recover_with_backend`]: reloads skip
+    /// unparseable lines too
+    recovered: bool,
 }
 
 impl MetadataStore {
     /// Create or open a metadata store
     pub fn new(path: PathBuf) -> Result<Self> {
-        let log = if path.exists() {
-            let content = ({
-                use std::io::Read;
-                std::fs::File::open(&path).and_then(|mut f| {
-                    let mut buf = String::new();
-                    f.take(10 * 1024 * 1024).read_to_string(&mut buf)?;
-                    Ok(buf)
-                })
-            })?;
-            serde_json::from_str(&content)
-                .map_err(|e| ReversibleError::MetadataCorrupted(e.to_string()))?
+        Self::new_with_backend(Arc::new(LocalBackend), path)
+    }
+
+    /// Create or open a metadata store at `path` on the given backend.
+    ///
+    /// A single-file log from earlier versions (`<path>.json`) is migrated
+    /// into segments on first open.
+    pub fn new_with_backend(backend: Arc<dyn FileBackend>, path: PathBuf) -> Result<Self> {
+        let mut store = Self {
+            backend,
+            path,
+            log: OperationLog::default(),
+            index: HashMap::new(),
+            segments: Vec::new(),
+            compressed: HashSet::new(),
+            superseded: Vec::new(),
+            compress: false,
+            active_size: 0,
+            actor: None,
+            root: None,
+            unmigrated: 0,
+            workspace: None,
+            others: Vec::new(),
+            context: None,
+            refs: References::new(),
+            recovered: false,
+        };
+        store.load(None)?;
+
+        let legacy = store.path.with_extension("json");
+        if store.segments.is_empty() && legacy != store.path && store.backend.exists(&legacy) {
+            store.migrate(&legacy)?;
+        }
+
+        Ok(store)
+    }
+
+    /// Open the store at `path` like [`MetadataStore::new_with_backend`],
+    /// but skip log lines that cannot be parsed instead of failing.
+    ///
+    /// Returns the skipped lines. Nothing on disk changes; call
+    /// [`MetadataStore::compact`] to rewrite the log without them.
+    pub fn recover_with_backend(
+        backend: Arc<dyn FileBackend>,
+        path: PathBuf,
+    ) -> Result<(Self, Vec<DamagedRecord>)> {
+        let mut store = Self {
+            backend,
+            path,
+            log: OperationLog::default(),
+            index: HashMap::new(),
+            segments: Vec::new(),
+            compressed: HashSet::new(),
+            superseded: Vec::new(),
+            compress: false,
+            active_size: 0,
+            actor: None,
+            root: None,
+            unmigrated: 0,
+            workspace: None,
+            others: Vec::new(),
+            context: None,
+            refs: References::new(),
+            recovered: true,
+        };
+        let mut damaged = Vec::new();
+        store.load(Some(&mut damaged))?;
+        Ok((store, damaged))
+    }
+
+    /// Path of the segment with sequence number `seq`
+    fn segment_path(&self, seq: u64) -> PathBuf {
+        if self.compressed.contains(&seq) {
+            self.path.join(format!("{:08}.jsonl.zst", seq))
         } else {
-            OperationLog::default()
+            self.path.join(format!("{:08}.jsonl", seq))
+        }
+    }
+
+    /// Replay every segment into the in-memory log, streaming each one.
+    /// Unparseable lines are an error, or collected into `damaged` when it
+    /// is given.
+    fn load(&mut self, mut damaged: Option<&mut Vec<DamagedRecord>>) -> Result<()> {
+        let mut segments: Vec<(u64, u64)> = Vec::new();
+        for (path, size) in self.backend.list_files(&self.path)? {
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let (stem, compressed) = match name.strip_suffix(".zst") {
+                Some(stem) => (stem, true),
+                None => (name, false),
+            };
+            let Some(seq) = stem
+                .strip_suffix(".jsonl")
+                .and_then(|seq| seq.parse::<u64>().ok())
+            else {
+                continue;
+            };
+            if compressed {
+                self.compressed.insert(seq);
+            }
+            segments.push((seq, size));
+        }
+        segments.sort_unstable();
+        // A segment both compressed and not was being compressed; the
+        // compressed copy is complete, as it is written before the other
+        // is removed
+        segments.dedup_by(|(seq, _), (kept, _)| seq == kept);
+        for &(seq, _) in &segments {
+            if self.compressed.contains(&seq) {
+                let plain = self.path.join(format!("{:08}.jsonl", seq));
+                if self.backend.exists(&plain) {
+                    self.superseded.push(plain);
+                }
+            }
+        }
+
+        let mut torn = false;
+        for &(seq, _) in &segments {
+            let mut reader = framing::open(self.backend.as_ref(), &self.segment_path(seq))?;
+            // Each line is replayed once the next is read, so the last one
+            // is known
+            let mut pending: Option<(usize, String)> = None;
+            let mut buf = Vec::new();
+            let mut number = 0;
+            torn = false;
+            loop {
+                buf.clear();
+                if reader.read_until(b'\n', &mut buf)? == 0 {
+                    break;
+                }
+                number += 1;
+                torn = !buf.ends_with(b"\n");
+                let line = String::from_utf8_lossy(&buf);
+                if line.trim().is_empty() {
+                    continue;
+                }
+                if let Some((i, line)) = pending.take() {
+                    self.replay_line(seq, i, &line, false, damaged.as_deref_mut())?;
+                }
+                pending = Some((number, line.trim_end_matches(['\n', '\r']).to_string()));
+            }
+            if let Some((i, line)) = pending {
+                self.replay_line(seq, i, &line, torn, damaged.as_deref_mut())?;
+            }
+        }
+
+        self.segments = segments.iter().map(|&(seq, _)| seq).collect();
+        tracing::debug!(
+            segments = self.segments.len(),
+            operations = self.log.operations.len(),
+            "operation log loaded"
+        );
+        // Never append after a torn line, or to a compressed segment;
+        // start the next segment instead
+        self.active_size = match segments.last() {
+            Some(_) if torn => SEGMENT_MAX_BYTES,
+            Some((seq, _)) if self.compressed.contains(seq) => SEGMENT_MAX_BYTES,
+            Some(&(_, size)) => size,
+            None => 0,
+        };
+        Ok(())
+    }
+
+    /// Replay line `number` of segment `seq`. If it fails to parse, it is
+    /// skipped when `torn` (a write interrupted mid-append), collected
+    /// into `damaged` when that is given, and an error otherwise.
+    fn replay_line(
+        &mut self,
+        seq: u64,
+        number: usize,
+        line: &str,
+        torn: bool,
+        damaged: Option<&mut Vec<DamagedRecord>>,
+    ) -> Result<()> {
+        match serde_json::from_str(line) {
+            Ok(record) => self.replay(record),
+            Err(_) if torn => {
+                tracing::warn!(segment = seq, "ignoring torn final log line");
+            }
+            Err(e) => match damaged {
+                Some(damaged) => damaged.push(DamagedRecord {
+                    segment: seq,
+                    line: number,
+                    error: e.to_string(),
+                    text: line.to_string(),
+                }),
+                None => {
+                    return Err(ReversibleError::MetadataCorrupted(format!(
+                        "segment {}: {}",
+                        seq, e
+                    )))
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// Apply one record to the in-memory log
+    fn replay(&mut self, record: LogRecord) {
+        match record {
+            LogRecord::Append(op) if self.is_foreign(&op) => {
+                match self.others.iter_mut().find(|other| other.id == op.id) {
+                    Some(other) => *other = *op,
+                    None => self.others.push(*op),
+                }
+            }
+            LogRecord::Append(op) => match self.index.get(&op.id) {
+                Some(&pos) => self.log.operations[pos] = *op,
+                None => {
+                    self.index.insert(op.id.clone(), self.log.operations.len());
+                    self.log.operations.push(*op);
+                }
+            },
+            LogRecord::Undone {
+                id,
+                undo_operation_id,
+            } => {
+                if let Some(op) = self.get_any_mut(&id) {
+                    op.undone = true;
+                    op.undo_operation_id = Some(undo_operation_id);
+                }
+            }
+            LogRecord::Verified { id, hash } => {
+                if let Some(op) = self.get_any_mut(&id) {
+                    op.verified_hash = Some(hash);
+                }
+            }
+            LogRecord::Batch(ops) => {
+                for op in ops {
+                    self.replay(LogRecord::Append(Box::new(op)));
+                }
+            }
+        }
+    }
+
+    /// Import a single-file log from earlier versions
+    fn migrate(&mut self, legacy: &Path) -> Result<()> {
+        let _lock = self.lock(LockMode::Exclusive)?;
+        // Another process opening the store may have migrated it first
+        self.reload()?;
+        if !self.segments.is_empty() || !self.backend.exists(legacy) {
+            return Ok(());
+        }
+        tracing::info!(log = %legacy.display(), "migrating single-file operation log");
+        let content = self.backend.read_to_string(legacy, u64::MAX)?;
+        let log: OperationLog = serde_json::from_str(&content)
+            .map_err(|e| ReversibleError::MetadataCorrupted(e.to_string()))?;
+        self.rewrite(log.operations)?;
+        self.backend.remove_file(legacy)
+    }
+
+    /// Lock the log directory, so a rewrite in one process cannot drop
+    /// records another appends. Only local logs are locked.
+    fn lock(&self, mode: LockMode) -> Result<Option<FileLock>> {
+        if !self.backend.is_local() {
+            return Ok(None);
+        }
+        let path = self.path.join(LOG_LOCK);
+        if !path.is_file() {
+            fs::create_dir_all(&self.path)?;
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)?;
+        }
+        let locking = FileLocking {
+            enabled: true,
+            timeout_ms: LOG_LOCK_TIMEOUT_MS,
+        };
+        locking.lock(&path, mode)
+    }
+
+    /// Lock the log for a rewrite and read it again, so the rewrite starts
+    /// from what is on disk rather than what this store last saw
+    fn lock_for_rewrite(&mut self) -> Result<Option<FileLock>> {
+        let lock = self.lock(LockMode::Exclusive)?;
+        self.reload()?;
+        Ok(lock)
+    }
+
+    /// Replay the log from disk again, keeping the workspace and root
+    fn reload(&mut self) -> Result<()> {
+        self.log = OperationLog::default();
+        self.index.clear();
+        self.segments.clear();
+        self.compressed.clear();
+        self.superseded.clear();
+        self.others.clear();
+        if self.recovered {
+            self.load(Some(&mut Vec::new()))?;
+        } else {
+            self.load(None)?;
+        }
+        if let Some(root) = self.root.clone() {
+            self.set_root(&root);
+        }
+        Ok(())
+    }
+
+    /// Append a record under a shared lock on the log. If another process
+    /// rewrote the log since it was read, it is read again first, so the
+    /// record lands after the rewritten segments.
+    fn append_record(&mut self, record: &LogRecord) -> Result<()> {
+        let _lock = self.lock(LockMode::Shared)?;
+        if let Some(&seq) = self.segments.last() {
+            if !self.backend.exists(&self.segment_path(seq)) {
+                self.reload()?;
+            }
+        }
+        self.write_record(record)
+    }
+
+    /// Size at which the newest segment is full
+    fn segment_max_bytes(&self) -> u64 {
+        if self.backend.capabilities().append {
+            SEGMENT_MAX_BYTES
+        } else {
+            EMULATED_APPEND_SEGMENT_MAX_BYTES
+        }
+    }
+
+    /// Append a record to the newest segment, rotating when it is full
+    fn write_record(&mut self, record: &LogRecord) -> Result<()> {
+        let mut line = match &self.root {
+            Some(root) => serde_json::to_vec(&record.relative_to(root))?,
+            None => serde_json::to_vec(record)?,
         };
+        line.push(b'\n');
+
+        if self.segments.is_empty() || self.active_size >= self.segment_max_bytes() {
+            let sealed = self.segments.last().copied();
+            self.segments.push(sealed.map_or(1, |seq| seq + 1));
+            self.active_size = 0;
+            if let Some(seq) = sealed.filter(|seq| self.compress && !self.compressed.contains(seq))
+            {
+                self.compress_segment(seq)?;
+            }
+        }
+        let seq = *self.segments.last().expect("segment just ensured");
+        self.backend.append(&self.segment_path(seq), &line)?;
+        self.active_size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Replace the log on disk with `operations`. Callers other than the
+    /// first open hold the lock from [`MetadataStore::lock_for_rewrite`].
+    ///
+    /// New segments are written after the existing ones before those are
+    /// removed; replaying both (after a crash in between) yields the same
+    /// operations, since a later record for an ID replaces earlier ones.
+    fn rewrite(&mut self, operations: Vec<OperationMetadata>) -> Result<()> {
+        let old_segments = self.segments.clone();
+        // Start a fresh segment after the existing ones, and compress the
+        // new ones only once all are written
+        self.active_size = SEGMENT_MAX_BYTES;
+        let compress = std::mem::replace(&mut self.compress, false);
 
-        Ok(Self { path, log })
+        let all: Vec<OperationMetadata> =
+            merge_by_time(&operations, &self.others).cloned().collect();
+        let written = all
+            .into_iter()
+            .try_for_each(|op| self.write_record(&LogRecord::Append(Box::new(op))));
+        self.compress = compress;
+        written?;
+        self.segments.retain(|seq| !old_segments.contains(seq));
+        for seq in old_segments {
+            self.backend.remove_file(&self.segment_path(seq))?;
+            self.compressed.remove(&seq);
+        }
+        for path in std::mem::take(&mut self.superseded) {
+            self.backend.remove_file(&path)?;
+        }
+        if self.compress {
+            self.compress_segments()?;
+        }
+
+        self.index = operations
+            .iter()
+            .enumerate()
+            .map(|(pos, op)| (op.id.clone(), pos))
+            .collect();
+        self.log.operations = operations;
+        self.unmigrated = 0;
+        Ok(())
     }
 
     /// Append an operation to the log
-    pub fn append(&mut self, metadata: OperationMetadata) -> Result<()> {
-        self.log.operations.push(metadata);
-        self.save()
+    pub fn append(&mut self, mut metadata: OperationMetadata) -> Result<()> {
+        self.stamp(&mut metadata);
+        self.append_record(&LogRecord::Append(Box::new(metadata.clone())))?;
+        self.replay(LogRecord::Append(Box::new(metadata)));
+        Ok(())
     }
 
-    /// Save the log to disk
-    fn save(&self) -> Result<()> {
-        if let Some(parent) = self.path.parent() {
-            fs::create_dir_all(parent)?;
+    /// Append several operations as one log record and sync it, so that
+    /// after a crash either all of them are in the log or none are
+    pub fn append_batch(&mut self, mut operations: Vec<OperationMetadata>) -> Result<()> {
+        if operations.is_empty() {
+            return Ok(());
+        }
+        for metadata in &mut operations {
+            self.stamp(metadata);
         }
-        let content = serde_json::to_string_pretty(&self.log)?;
-        fs::write(&self.path, content)?;
+        let record = LogRecord::Batch(operations);
+        self.append_record(&record)?;
+        let seq = *self.segments.last().expect("segment just written");
+        self.backend.sync(&self.segment_path(seq))?;
+        self.replay(record);
         Ok(())
     }
 
+    /// Fill in what appended operations carry unless they have their own:
+    /// the actor, workspace, context and references
+    fn stamp(&self, metadata: &mut OperationMetadata) {
+        if metadata.actor.is_none() {
+            metadata.actor = self.actor.clone();
+        }
+        if metadata.workspace.is_none() {
+            metadata.workspace = self.workspace.clone();
+        }
+        if metadata.context.is_none() {
+            metadata.context = self.context.clone();
+        }
+        refs::inherit(&mut metadata.refs, &self.refs);
+    }
+
+    /// Record `actor`'s declared name on operations appended from now on
+    /// (the OS user is recorded by [`OperationMetadata::new`])
+    pub fn set_actor(&mut self, actor: &Actor) {
+        self.actor = actor.declared.clone();
+    }
+
+    /// Record `context` on operations appended from now on that do not
+    /// carry their own
+    pub fn set_context(&mut self, context: Option<OperationContext>) {
+        self.context = context;
+    }
+
+    /// Declared actor stamped on appended operations, if any
+    pub fn actor(&self) -> Option<&str> {
+        self.actor.as_deref()
+    }
+
+    /// Record `refs` on operations appended from now on, beside any they
+    /// carry themselves
+    pub fn set_refs(&mut self, refs: References) {
+        self.refs = refs;
+    }
+
+    /// References stamped on appended operations
+    pub fn refs(&self) -> &References {
+        &self.refs
+    }
+
+    /// Record paths under `root` relative to it from now on, and resolve
+    /// the relative paths already read against it. Paths outside `root`
+    /// stay absolute.
+    ///
+    /// Operations written before paths were stored relative keep their
+    /// absolute paths until [`MetadataStore::migrate_paths`] rewrites them.
+    pub fn set_root(&mut self, root: &Path) {
+        let under_root = |path: &Path| path.is_absolute() && path.starts_with(root);
+        self.unmigrated = 0;
+        for op in &mut self.log.operations {
+            if under_root(&op.path) || op.path_secondary.as_deref().is_some_and(under_root) {
+                self.unmigrated += 1;
+            }
+            op.map_paths(|path| root.join(path));
+        }
+        self.root = Some(root.to_path_buf());
+    }
+
+    /// Directory paths are recorded relative to, if set
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    /// Record operations appended from now on in workspace `id`, and set
+    /// aside those recorded in other workspaces. Operations recorded
+    /// before workspaces were used belong to every workspace.
+    ///
+    /// Call before [`MetadataStore::set_root`], which resolves only this
+    /// workspace's paths.
+    pub fn set_workspace(&mut self, id: &str) {
+        self.workspace = Some(id.to_string());
+        let operations = std::mem::take(&mut self.log.operations);
+        let (others, own): (Vec<_>, Vec<_>) =
+            operations.into_iter().partition(|op| self.is_foreign(op));
+        self.others = merge_by_time(&self.others, &others).cloned().collect();
+        self.index = own
+            .iter()
+            .enumerate()
+            .map(|(pos, op)| (op.id.clone(), pos))
+            .collect();
+        self.log.operations = own;
+    }
+
+    /// Workspace appended operations are recorded in, if set
+    pub fn workspace(&self) -> Option<&str> {
+        self.workspace.as_deref()
+    }
+
+    /// Operations recorded in other workspaces, oldest first, with paths
+    /// relative to their workspaces' roots
+    pub fn other_workspaces(&self) -> &[OperationMetadata] {
+        &self.others
+    }
+
+    /// Operations of every workspace, oldest first
+    pub fn all_operations(&self) -> Vec<OperationMetadata> {
+        merge_by_time(&self.log.operations, &self.others)
+            .cloned()
+            .collect()
+    }
+
+    /// Whether `op` was recorded in a workspace other than this store's
+    fn is_foreign(&self, op: &OperationMetadata) -> bool {
+        matches!((&self.workspace, &op.workspace), (Some(own), Some(theirs)) if own != theirs)
+    }
+
+    /// Operation by ID, whichever workspace recorded it
+    fn get_any_mut(&mut self, id: &str) -> Option<&mut OperationMetadata> {
+        match self.index.get(id) {
+            Some(&pos) => Some(&mut self.log.operations[pos]),
+            None => self.others.iter_mut().find(|op| op.id == id),
+        }
+    }
+
+    /// Rewrite the log with paths relative to the root if it holds
+    /// absolute ones under it. Returns the number of operations migrated.
+    pub fn migrate_paths(&mut self) -> Result<usize> {
+        if self.unmigrated == 0 || self.root.is_none() {
+            return Ok(0);
+        }
+        let _lock = self.lock_for_rewrite()?;
+        // Another process may have migrated the log meanwhile
+        let unmigrated = self.unmigrated;
+        if unmigrated > 0 {
+            tracing::info!(
+                operations = unmigrated,
+                "storing operation paths relative to root"
+            );
+            self.rewrite(self.log.operations.clone())?;
+        }
+        Ok(unmigrated)
+    }
+
+    /// Fold all segments into the fewest segments holding the current
+    /// operations. Returns the number of segments before and after.
+    pub fn compact(&mut self) -> Result<(usize, usize)> {
+        let _lock = self.lock_for_rewrite()?;
+        let before = self.segments.len();
+        let operations = self.log.operations.clone();
+        self.rewrite(operations)?;
+        Ok((before, self.segments.len()))
+    }
+
+    /// Compress each segment once it is full, from now on
+    pub fn set_compression(&mut self, compress: bool) {
+        self.compress = compress;
+    }
+
+    /// Compress every segment but the newest, which appends still go to.
+    /// Segments already compressed are left as they are.
+    pub fn compress_segments(&mut self) -> Result<Compressed> {
+        let mut compressed = Compressed::default();
+        let sealed: Vec<u64> = self
+            .segments
+            .iter()
+            .rev()
+            .skip(1)
+            .filter(|seq| !self.compressed.contains(seq))
+            .copied()
+            .collect();
+        for seq in sealed.into_iter().rev() {
+            let (before, after) = self.compress_segment(seq)?;
+            compressed.add(before, after);
+        }
+        for path in std::mem::take(&mut self.superseded) {
+            self.backend.remove_file(&path)?;
+        }
+        Ok(compressed)
+    }
+
+    /// Replace segment `seq` with a compressed copy, returning its size
+    /// before and after. The copy is complete before the segment is
+    /// removed, and is the one read if both are found.
+    fn compress_segment(&mut self, seq: u64) -> Result<(u64, u64)> {
+        let path = self.segment_path(seq);
+        let plain = self.backend.read(&path)?;
+        let compressed = framing::compress(&plain)?;
+        self.compressed.insert(seq);
+        let compressed_path = self.segment_path(seq);
+        if let Err(e) = self
+            .backend
+            .write(&compressed_path, &compressed)
+            .and_then(|()| self.backend.sync(&compressed_path))
+        {
+            self.compressed.remove(&seq);
+            return Err(e);
+        }
+        self.backend.remove_file(&path)?;
+        Ok((plain.len() as u64, compressed.len() as u64))
+    }
+
+    /// Number of log segments stored compressed
+    pub fn compressed_segment_count(&self) -> usize {
+        self.compressed.len()
+    }
+
+    /// The backend the log is stored on
+    pub fn backend(&self) -> &Arc<dyn FileBackend> {
+        &self.backend
+    }
+
+    /// Number of log segments on disk
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
     /// Get all operations
     pub fn operations(&self) -> &[OperationMetadata] {
         &self.log.operations
@@ -296,12 +1404,54 @@ pub fn operations(&self) -> &[OperationMetadata] {
 
     /// Get operation by ID
     pub fn get(&self, id: &str) -> Option<&OperationMetadata> {
-        self.log.operations.iter().find(|op| op.id == id)
+        self.index.get(id).map(|&pos| &self.log.operations[pos])
+    }
+
+    /// Find an operation by ID or unique ID prefix
+    pub fn find(&self, prefix: &str) -> Result<&OperationMetadata> {
+        if let Some(op) = self.get(prefix) {
+            return Ok(op);
+        }
+        let mut matches = self
+            .log
+            .operations
+            .iter()
+            .filter(|op| op.id.starts_with(prefix));
+        match (matches.next(), matches.next()) {
+            (Some(op), None) if !prefix.is_empty() => Ok(op),
+            (Some(_), Some(_)) => Err(ReversibleError::InvalidOperationId(format!(
+                "{} matches more than one operation",
+                prefix
+            ))),
+            _ => Err(ReversibleError::InvalidOperationId(prefix.to_string())),
+        }
+    }
+
+    /// Length of the shortest prefix that tells every operation's ID apart,
+    /// and at least [`SHORT_ID_LEN`], for showing IDs people can type
+    pub fn short_id_len(&self) -> usize {
+        let mut ids: Vec<&str> = self
+            .log
+            .operations
+            .iter()
+            .map(|op| op.id.as_str())
+            .collect();
+        ids.sort_unstable();
+        ids.windows(2)
+            .map(|pair| {
+                let common = pair[0]
+                    .bytes()
+                    .zip(pair[1].bytes())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                (common + 1).min(pair[0].len().max(pair[1].len()))
+            })
+            .fold(SHORT_ID_LEN, usize::max)
     }
 
     /// Get mutable operation by ID
     pub fn get_mut(&mut self, id: &str) -> Option<&mut OperationMetadata> {
-        self.log.operations.iter_mut().find(|op| op.id == id)
+        self.index.get(id).map(|&pos| &mut self.log.operations[pos])
     }
 
     /// Get last N non-undone operations
@@ -331,10 +1481,26 @@ pub fn transaction_operations(&self, transaction_id: &str) -> Vec<&OperationMeta
 
     /// Mark operation as undone
     pub fn mark_undone(&mut self, id: &str, undo_op_id: &str) -> Result<()> {
-        if let Some(op) = self.get_mut(id) {
-            op.undone = true;
-            op.undo_operation_id = Some(undo_op_id.to_string());
-            self.save()?;
+        if self.get(id).is_some() || self.others.iter().any(|op| op.id == id) {
+            let record = LogRecord::Undone {
+                id: id.to_string(),
+                undo_operation_id: undo_op_id.to_string(),
+            };
+            self.append_record(&record)?;
+            self.replay(record);
+        }
+        Ok(())
+    }
+
+    /// Record that an undo's restored content was verified against `hash`
+    pub fn mark_verified(&mut self, id: &str, hash: ContentHash) -> Result<()> {
+        if self.get(id).is_some() || self.others.iter().any(|op| op.id == id) {
+            let record = LogRecord::Verified {
+                id: id.to_string(),
+                hash,
+            };
+            self.append_record(&record)?;
+            self.replay(record);
         }
         Ok(())
     }
@@ -359,22 +1525,190 @@ pub fn filter_by_path(&self, pattern: &str) -> Result<Vec<&OperationMetadata>> {
             .collect())
     }
 
+    /// Every operation that touched the file now at `path`, oldest first.
+    ///
+    /// Follows the rename graph backwards: when the file arrived at `path`
+    /// through a move, operations recorded under its earlier names (before
+    /// that move) are included too. Copies are listed but not followed.
+    pub fn path_history(&self, path: &Path) -> Vec<&OperationMetadata> {
+        let mut current = path.to_path_buf();
+        let mut history = Vec::new();
+
+        for op in self.log.operations.iter().rev() {
+            let arrived = op.path_secondary.as_deref() == Some(current.as_path());
+            if op.path != current && !arrived {
+                continue;
+            }
+            history.push(op);
+            if arrived && op.op_type == OperationType::Move {
+                current = op.path.clone();
+            }
+        }
+
+        history.reverse();
+        history
+    }
+
+    /// Earlier names of the file now at `path`, most recent first
+    pub fn previous_names(&self, path: &Path) -> Vec<PathBuf> {
+        let mut names: Vec<PathBuf> = Vec::new();
+        for op in self.path_history(path).into_iter().rev() {
+            if op.op_type == OperationType::Move && op.path != path && !names.contains(&op.path) {
+                names.push(op.path.clone());
+            }
+        }
+        names
+    }
+
+    /// Dependencies between the live operations, see [`DependencyGraph`]
+    pub fn dependency_graph(&self) -> DependencyGraph {
+        DependencyGraph::build(&self.log.operations)
+    }
+
     /// Get operation count
     pub fn count(&self) -> usize {
         self.log.operations.len()
     }
 
-    /// Prune old operations (keep last N)
+    /// Operations `prune(keep)` would remove, oldest first
+    pub fn prune_candidates(&self, keep: usize) -> Vec<&OperationMetadata> {
+        self.prune_candidates_pinned(keep, &HashSet::new())
+    }
+
+    /// Operations `prune_pinned(keep, pinned)` would remove, oldest first
+    pub fn prune_candidates_pinned(
+        &self,
+        keep: usize,
+        pinned: &HashSet<String>,
+    ) -> Vec<&OperationMetadata> {
+        self.prune_candidates_by(&PruneRule::keep(keep), pinned)
+    }
+
+    /// Operations `prune_by(rule, pinned)` would remove, oldest first
+    pub fn prune_candidates_by(
+        &self,
+        rule: &PruneRule,
+        pinned: &HashSet<String>,
+    ) -> Vec<&OperationMetadata> {
+        self.log
+            .operations
+            .iter()
+            .zip(self.retained(rule, pinned))
+            .filter(|(_, retained)| !retained)
+            .map(|(op, _)| op)
+            .collect()
+    }
+
+    /// Prune old operations, keeping at least the last `keep`.
+    ///
+    /// Older operations are kept too when pruning them would leave the
+    /// log inconsistent: see [`Self::retained`].
     pub fn prune(&mut self, keep: usize) -> Result<usize> {
-        let original_count = self.log.operations.len();
-        if original_count <= keep {
-            return Ok(0);
+        self.prune_pinned(keep, &HashSet::new())
+    }
+
+    /// Prune like [`Self::prune`], but also keep the operations whose IDs
+    /// are `pinned` (and what they depend on)
+    pub fn prune_pinned(&mut self, keep: usize, pinned: &HashSet<String>) -> Result<usize> {
+        self.prune_by(&PruneRule::keep(keep), pinned)
+    }
+
+    /// Prune the operations `rule` does not keep, other than the `pinned`
+    /// ones and what the kept operations depend on
+    pub fn prune_by(&mut self, rule: &PruneRule, pinned: &HashSet<String>) -> Result<usize> {
+        let _lock = self.lock_for_rewrite()?;
+        let retained = self.retained(rule, pinned);
+        let kept: Vec<OperationMetadata> = self
+            .log
+            .operations
+            .iter()
+            .zip(&retained)
+            .filter(|(_, &retained)| retained)
+            .map(|(op, _)| op.clone())
+            .collect();
+
+        let removed = self.log.operations.len() - kept.len();
+        if removed > 0 {
+            tracing::info!(removed, kept = kept.len(), "pruning operation log");
+            self.rewrite(kept)?;
         }
+        Ok(removed)
+    }
 
-        let to_remove = original_count - keep;
-        self.log.operations.drain(0..to_remove);
-        self.save()?;
-        Ok(to_remove)
+    /// Which operations survive `prune_by(rule, pinned)`, by position.
+    ///
+    /// The operations `rule` keeps and any `pinned` are retained, along
+    /// with everything they depend on:
+    /// - every operation of a transaction any retained operation is in,
+    ///   so transactions are never split;
+    /// - both ends of an undo (the operation and the one that undid it),
+    ///   so no undo record or `undone` flag is left pointing at nothing;
+    /// - the operations that stored a retained operation's delta base, so
+    ///   the blob a delta needs is still accounted for in the log.
+    fn retained(&self, rule: &PruneRule, pinned: &HashSet<String>) -> Vec<bool> {
+        let ops = &self.log.operations;
+        let cut = ops.len().saturating_sub(rule.keep);
+        // Newest operation with stored content on each path
+        let mut latest: HashMap<&Path, usize> = HashMap::new();
+        if rule.latest_per_path {
+            for (pos, op) in ops.iter().enumerate() {
+                if op.content_hash.is_some() {
+                    latest.insert(&op.path, pos);
+                }
+            }
+        }
+
+        let mut by_transaction: HashMap<&str, Vec<usize>> = HashMap::new();
+        let mut undone_by: HashMap<&str, usize> = HashMap::new();
+        let mut by_blob: HashMap<&ContentHash, Vec<usize>> = HashMap::new();
+        for (pos, op) in ops.iter().enumerate() {
+            if let Some(tid) = &op.transaction_id {
+                by_transaction.entry(tid).or_default().push(pos);
+            }
+            if let Some(undo_id) = &op.undo_operation_id {
+                undone_by.insert(undo_id, pos);
+            }
+            for hash in [&op.content_hash, &op.new_content_hash]
+                .into_iter()
+                .flatten()
+            {
+                by_blob.entry(hash).or_default().push(pos);
+            }
+        }
+
+        let mut retained: Vec<bool> = ops
+            .iter()
+            .enumerate()
+            .map(|(pos, op)| {
+                pos >= cut
+                    || pinned.contains(&op.id)
+                    || rule.newer_than.is_some_and(|since| op.timestamp >= since)
+                    || latest.get(op.path.as_path()) == Some(&pos)
+            })
+            .collect();
+        let mut pending: Vec<usize> = (0..ops.len()).filter(|&pos| retained[pos]).collect();
+        while let Some(pos) = pending.pop() {
+            let op = &ops[pos];
+            let mut needed: Vec<usize> = Vec::new();
+            if let Some(tid) = &op.transaction_id {
+                needed.extend(&by_transaction[tid.as_str()]);
+            }
+            if let Some(undo_id) = &op.undo_operation_id {
+                needed.extend(self.index.get(undo_id));
+            }
+            needed.extend(undone_by.get(op.id.as_str()));
+            if let Some(base) = &op.delta_base {
+                needed.extend(by_blob.get(base).into_iter().flatten());
+            }
+
+            for dep in needed {
+                if !retained[dep] {
+                    retained[dep] = true;
+                    pending.push(dep);
+                }
+            }
+        }
+        retained
     }
 }
 
@@ -418,4 +1752,583 @@ fn test_metadata_store() {
         assert_eq!(store2.count(), 1);
         assert!(store2.get(&id).is_some());
     }
+
+    #[test]
+    fn test_find_by_unique_prefix() {
+        let tmp = TempDir::new().unwrap();
+        let mut store = MetadataStore::new(tmp.path().join("metadata.json")).unwrap();
+        for id in ["abcd1234-0000", "abcd5678-0000", "ef012345-0000"] {
+            let mut meta = OperationMetadata::new(OperationType::Delete, PathBuf::from("/a"));
+            meta.id = id.to_string();
+            store.append(meta).unwrap();
+        }
+
+        assert_eq!(store.find("ef").unwrap().id, "ef012345-0000");
+        assert_eq!(store.find("abcd5").unwrap().id, "abcd5678-0000");
+        assert_eq!(store.find("abcd1234-0000").unwrap().id, "abcd1234-0000");
+        assert!(matches!(
+            store.find("abcd"),
+            Err(ReversibleError::InvalidOperationId(message)) if message.contains("more than one")
+        ));
+        assert!(store.find("99").is_err());
+        assert!(store.find("").is_err());
+
+        assert_eq!(store.short_id_len(), SHORT_ID_LEN);
+        let mut meta = OperationMetadata::new(OperationType::Delete, PathBuf::from("/b"));
+        meta.id = "abcd1234-0001".to_string();
+        store.append(meta).unwrap();
+        assert_eq!(store.short_id_len(), 13);
+    }
+
+    #[test]
+    fn test_dependency_graph() {
+        let tmp = TempDir::new().unwrap();
+        let mut store = MetadataStore::new(tmp.path().join("metadata.json")).unwrap();
+        let (a, b, c) = (
+            PathBuf::from("/w/a.txt"),
+            PathBuf::from("/w/b.txt"),
+            PathBuf::from("/w/c.txt"),
+        );
+        let mut append = |op: OperationMetadata| {
+            let id = op.id.clone();
+            store.append(op).unwrap();
+            id
+        };
+
+        let modify = append(OperationMetadata::new(OperationType::Modify, a.clone()));
+        let copy = append(
+            OperationMetadata::new(OperationType::Copy, a.clone()).with_secondary_path(c.clone()),
+        );
+        let moved = append(
+            OperationMetadata::new(OperationType::Move, a.clone()).with_secondary_path(b.clone()),
+        );
+        let edit = append(OperationMetadata::new(OperationType::Modify, b.clone()));
+        let graph = store.dependency_graph();
+
+        assert_eq!(graph.dependents(&modify), [copy.clone(), moved.clone()]);
+        assert_eq!(
+            graph.descendants(&modify),
+            [copy.clone(), moved.clone(), edit.clone()]
+        );
+        // Moving the copy's source away leaves the copy alone
+        assert!(graph.is_independent(&copy));
+        assert_eq!(graph.descendants(&moved), std::slice::from_ref(&edit));
+        assert!(graph.is_independent(&edit));
+
+        // Once the edit is undone, the move stands alone
+        let undo = OperationMetadata::new(OperationType::Modify, b.clone());
+        let undo_id = undo.id.clone();
+        store.append(undo).unwrap();
+        store.mark_undone(&edit, &undo_id).unwrap();
+        assert!(store.dependency_graph().is_independent(&moved));
+    }
+
+    #[test]
+    fn test_path_history_follows_renames() {
+        let tmp = TempDir::new().unwrap();
+        let mut store = MetadataStore::new(tmp.path().join("metadata.json")).unwrap();
+        let (a, b, c) = (
+            PathBuf::from("/w/a.txt"),
+            PathBuf::from("/w/b.txt"),
+            PathBuf::from("/w/c.txt"),
+        );
+
+        store
+            .append(OperationMetadata::new(OperationType::Modify, a.clone()))
+            .unwrap();
+        store
+            .append(
+                OperationMetadata::new(OperationType::Move, a.clone())
+                    .with_secondary_path(b.clone()),
+            )
+            .unwrap();
+        // A new, unrelated file appears under the old name
+        store
+            .append(OperationMetadata::new(OperationType::Create, a.clone()))
+            .unwrap();
+        store
+            .append(
+                OperationMetadata::new(OperationType::Move, b.clone())
+                    .with_secondary_path(c.clone()),
+            )
+            .unwrap();
+        store
+            .append(OperationMetadata::new(OperationType::Modify, c.clone()))
+            .unwrap();
+
+        let history = store.path_history(&c);
+        let types: Vec<_> = history.iter().map(|op| op.op_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                OperationType::Modify,
+                OperationType::Move,
+                OperationType::Move,
+                OperationType::Modify
+            ]
+        );
+        assert_eq!(store.previous_names(&c), vec![b, a.clone()]);
+        assert_eq!(store.path_history(&a).len(), 3);
+    }
+
+    #[test]
+    fn test_log_appends_rotate_and_compact() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("metadata");
+        let mut store = MetadataStore::new(path.clone()).unwrap();
+
+        let padding = "x".repeat(4096);
+        let mut ids = Vec::new();
+        for i in 0..300 {
+            let meta = OperationMetadata::new(OperationType::Modify, PathBuf::from(&padding))
+                .with_secondary_path(PathBuf::from(format!("/w/{}", i)));
+            ids.push(meta.id.clone());
+            store.append(meta).unwrap();
+        }
+        store.mark_undone(&ids[0], &ids[1]).unwrap();
+        assert!(store.segment_count() > 1);
+
+        let mut store = MetadataStore::new(path.clone()).unwrap();
+        assert_eq!(store.count(), 300);
+        assert!(store.get(&ids[0]).unwrap().undone);
+        assert_eq!(store.last_n(1)[0].id, ids[299]);
+
+        let before = store.segment_count();
+        assert_eq!(store.compact().unwrap(), (before, before));
+        assert_eq!(store.prune(10).unwrap(), 290);
+        assert_eq!(store.segment_count(), 1);
+
+        let store = MetadataStore::new(path).unwrap();
+        assert_eq!(store.count(), 10);
+        assert!(store.get(&ids[0]).is_none());
+        assert!(store.get(&ids[299]).is_some());
+    }
+
+    #[test]
+    fn test_rewrites_keep_records_appended_elsewhere() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("metadata");
+        let mut first = MetadataStore::new(path.clone()).unwrap();
+        let own = OperationMetadata::new(OperationType::Create, PathBuf::from("/w/a"));
+        first.append(own.clone()).unwrap();
+        let mut second = MetadataStore::new(path.clone()).unwrap();
+        let theirs = OperationMetadata::new(OperationType::Create, PathBuf::from("/w/b"));
+        second.append(theirs.clone()).unwrap();
+
+        // The compaction reads the record the other store appended
+        first.compact().unwrap();
+        assert!(first.get(&theirs.id).is_some());
+
+        // and an append after it goes to a segment replayed after the
+        // rewritten ones, so its undo is not lost
+        second.mark_undone(&own.id, &theirs.id).unwrap();
+        first.prune(10).unwrap();
+        let store = MetadataStore::new(path).unwrap();
+        assert_eq!(store.count(), 2);
+        assert!(store.get(&own.id).unwrap().undone);
+        assert!(store.get(&theirs.id).is_some());
+    }
+
+    #[test]
+    fn test_sealed_segments_are_compressed() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("metadata");
+        let mut store = MetadataStore::new(path.clone()).unwrap();
+
+        let padding = "x".repeat(4096);
+        let mut ids = Vec::new();
+        let mut append = |store: &mut MetadataStore, n: usize| {
+            for _ in 0..n {
+                let meta = OperationMetadata::new(OperationType::Modify, PathBuf::from(&padding));
+                ids.push(meta.id.clone());
+                store.append(meta).unwrap();
+            }
+        };
+        append(&mut store, 600);
+        let segments = store.segment_count();
+        assert!(segments > 2);
+
+        let compressed = store.compress_segments().unwrap();
+        assert_eq!(compressed.files, segments - 1);
+        assert!(compressed.after * 10 < compressed.before);
+        assert!(path.join("00000001.jsonl.zst").exists());
+        assert!(!path.join("00000001.jsonl").exists());
+        assert_eq!(store.compress_segments().unwrap().files, 0);
+
+        // Reopened, appends go to the plain newest segment, and with
+        // compression on each segment is compressed as it fills
+        let mut store = MetadataStore::new(path.clone()).unwrap();
+        assert_eq!(store.count(), 600);
+        store.set_compression(true);
+        append(&mut store, 600);
+        assert_eq!(store.compressed_segment_count(), store.segment_count() - 1);
+
+        // An interrupted compression leaves both copies; the compressed
+        // one is read and the other removed on the next compression
+        fs::write(path.join("00000001.jsonl"), b"not json\n").unwrap();
+        let mut store = MetadataStore::new(path.clone()).unwrap();
+        assert_eq!(store.count(), 1200);
+        assert!(store.get(&ids[0]).is_some());
+        store.compress_segments().unwrap();
+        assert!(!path.join("00000001.jsonl").exists());
+
+        assert_eq!(store.prune(10).unwrap(), 1190);
+        let store = MetadataStore::new(path).unwrap();
+        assert_eq!(store.count(), 10);
+        assert!(store.get(&ids[1199]).is_some());
+    }
+
+    #[test]
+    fn test_emulated_append_keeps_segments_short() {
+        /// The local filesystem through whole-file reads and writes only
+        struct WholeFiles;
+
+        impl FileBackend for WholeFiles {
+            fn name(&self) -> &'static str {
+                "whole-files"
+            }
+            fn read(&self, path: &Path) -> Result<Vec<u8>> {
+                LocalBackend.read(path)
+            }
+            fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+                LocalBackend.write(path, content)
+            }
+            fn exists(&self, path: &Path) -> bool {
+                LocalBackend.exists(path)
+            }
+            fn remove_file(&self, path: &Path) -> Result<()> {
+                LocalBackend.remove_file(path)
+            }
+            fn create_dir_all(&self, path: &Path) -> Result<()> {
+                LocalBackend.create_dir_all(path)
+            }
+            fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+                LocalBackend.rename(from, to)
+            }
+            fn list_files(&self, dir: &Path) -> Result<Vec<(PathBuf, u64)>> {
+                LocalBackend.list_files(dir)
+            }
+        }
+
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("metadata");
+        let mut store =
+            MetadataStore::new_with_backend(Arc::new(WholeFiles), path.clone()).unwrap();
+        let padding = "x".repeat(4096);
+        for _ in 0..64 {
+            store
+                .append(OperationMetadata::new(
+                    OperationType::Modify,
+                    PathBuf::from(&padding),
+                ))
+                .unwrap();
+        }
+        // About 256 KiB: one segment locally, several when every append
+        // rewrites its segment
+        assert!(store.segment_count() >= 4);
+        for (_, size) in LocalBackend.list_files(&path).unwrap() {
+            assert!(size < EMULATED_APPEND_SEGMENT_MAX_BYTES + 8192);
+        }
+        let store = MetadataStore::new_with_backend(Arc::new(WholeFiles), path).unwrap();
+        assert_eq!(store.count(), 64);
+    }
+
+    #[test]
+    fn test_prune_keeps_dependencies() {
+        let tmp = TempDir::new().unwrap();
+        let mut store = MetadataStore::new(tmp.path().join("metadata")).unwrap();
+        let op = |path: &str| OperationMetadata::new(OperationType::Modify, PathBuf::from(path));
+
+        // 0: undone by 4; 1-2: one transaction; 3: base of 5's delta
+        let undone = op("/undone");
+        let tx_first = op("/tx/a").with_transaction_id("tx".to_string());
+        let tx_second = op("/tx/b").with_transaction_id("tx".to_string());
+        let base = op("/base").with_content_hash(ContentHash::from_string("base"));
+        let undo = op("/undone");
+        let delta = op("/base")
+            .with_content_hash(ContentHash::from_string("delta"))
+            .with_delta_base(ContentHash::from_string("base"));
+        let plain = op("/plain");
+        let ids: Vec<String> = [&undone, &tx_first, &tx_second, &base, &undo, &delta, &plain]
+            .iter()
+            .map(|op| op.id.clone())
+            .collect();
+        for meta in [undone, tx_first, tx_second, base, undo, delta, plain] {
+            store.append(meta).unwrap();
+        }
+        store.mark_undone(&ids[0], &ids[4]).unwrap();
+
+        // Keeping the last 4 keeps the operation their undo undid (0)
+        let candidates: Vec<&str> = store
+            .prune_candidates(4)
+            .iter()
+            .map(|op| op.id.as_str())
+            .collect();
+        assert_eq!(candidates, [ids[1].as_str(), ids[2].as_str()]);
+
+        // Keeping the second half of the transaction keeps all of it, and
+        // keeping the delta keeps the operation that stored its base
+        assert!(store.prune_candidates(5).is_empty());
+        assert_eq!(store.prune(2).unwrap(), 4);
+        assert!(store.get(&ids[3]).is_some());
+        assert!(store.get(&ids[1]).is_none());
+    }
+
+    #[test]
+    fn test_prune_by_age() {
+        let tmp = TempDir::new().unwrap();
+        let mut store = MetadataStore::new(tmp.path().join("metadata")).unwrap();
+        let now = Utc::now();
+        let op = |path: &str, days: i64| {
+            let mut op = OperationMetadata::new(OperationType::Modify, PathBuf::from(path))
+                .with_content_hash(ContentHash::from_string(path));
+            op.timestamp = now - chrono::Duration::days(days);
+            op
+        };
+
+        // 0: old, only version of /a; 1-2: old, /b changed again later;
+        // 3: old but in a transaction with 4, which is recent
+        let only = op("/a", 100);
+        let b_old = op("/b", 90);
+        let b_mid = op("/b", 80);
+        let tx_old = op("/c", 70).with_transaction_id("tx".to_string());
+        let tx_new = op("/d", 1).with_transaction_id("tx".to_string());
+        let b_new = op("/b", 1);
+        let ids: Vec<String> = [&only, &b_old, &b_mid, &tx_old, &tx_new, &b_new]
+            .iter()
+            .map(|op| op.id.clone())
+            .collect();
+        for meta in [only, b_old, b_mid, tx_old, tx_new, b_new] {
+            store.append(meta).unwrap();
+        }
+
+        let pinned = HashSet::new();
+        let candidates = |store: &MetadataStore, rule: &PruneRule| -> Vec<String> {
+            store
+                .prune_candidates_by(rule, &pinned)
+                .iter()
+                .map(|op| op.id.clone())
+                .collect()
+        };
+        let rule = PruneRule {
+            newer_than: Some(now - chrono::Duration::days(30)),
+            ..PruneRule::default()
+        };
+        assert_eq!(candidates(&store, &rule), ids[..3].to_vec());
+
+        // Keeping a version per path spares /a's only one
+        let rule = PruneRule {
+            latest_per_path: true,
+            ..rule
+        };
+        assert_eq!(candidates(&store, &rule), ids[1..3].to_vec());
+        assert_eq!(store.prune_by(&rule, &pinned).unwrap(), 2);
+        assert!(store.get(&ids[0]).is_some());
+        assert!(store.get(&ids[3]).is_some());
+    }
+
+    #[test]
+    fn test_log_ignores_torn_final_line() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("metadata");
+        let mut store = MetadataStore::new(path.clone()).unwrap();
+        store
+            .append(OperationMetadata::new(
+                OperationType::Delete,
+                PathBuf::from("/a"),
+            ))
+            .unwrap();
+
+        // A crash mid-append leaves half a record
+        LocalBackend
+            .append(&path.join("00000001.jsonl"), b"{\"append\":{\"id\":")
+            .unwrap();
+        assert_eq!(MetadataStore::new(path.clone()).unwrap().count(), 1);
+
+        // Later appends go to a new segment, not after the torn line
+        let mut store = MetadataStore::new(path.clone()).unwrap();
+        store
+            .append(OperationMetadata::new(
+                OperationType::Delete,
+                PathBuf::from("/b"),
+            ))
+            .unwrap();
+        assert_eq!(MetadataStore::new(path).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_append_batch_is_one_record() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("metadata");
+        let mut store = MetadataStore::new(path.clone()).unwrap();
+        let batch: Vec<_> = ["/a", "/b", "/c"]
+            .iter()
+            .map(|p| OperationMetadata::new(OperationType::Delete, PathBuf::from(p)))
+            .collect();
+        store.append_batch(batch).unwrap();
+        assert_eq!(MetadataStore::new(path.clone()).unwrap().count(), 3);
+
+        // Tearing the batch's line loses the whole batch, never part of it
+        let segment = path.join("00000001.jsonl");
+        let mut content = std::fs::read(&segment).unwrap();
+        content.truncate(content.len() - 40);
+        std::fs::write(&segment, content).unwrap();
+        assert_eq!(MetadataStore::new(path).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_recover_skips_damaged_lines() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("metadata");
+        let mut store = MetadataStore::new(path.clone()).unwrap();
+        store
+            .append(OperationMetadata::new(
+                OperationType::Delete,
+                PathBuf::from("/a"),
+            ))
+            .unwrap();
+        LocalBackend
+            .append(&path.join("00000001.jsonl"), b"not json\n")
+            .unwrap();
+        store
+            .append(OperationMetadata::new(
+                OperationType::Delete,
+                PathBuf::from("/b"),
+            ))
+            .unwrap();
+        assert!(matches!(
+            MetadataStore::new(path.clone()),
+            Err(ReversibleError::MetadataCorrupted(_))
+        ));
+
+        let (mut store, damaged) =
+            MetadataStore::recover_with_backend(Arc::new(LocalBackend), path.clone()).unwrap();
+        assert_eq!(store.count(), 2);
+        assert_eq!(damaged.len(), 1);
+        assert_eq!((damaged[0].segment, damaged[0].line), (1, 2));
+        assert_eq!(damaged[0].text, "not json");
+
+        store.compact().unwrap();
+        assert_eq!(MetadataStore::new(path).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_legacy_log_is_migrated() {
+        let tmp = TempDir::new().unwrap();
+        let legacy = tmp.path().join("metadata.json");
+        let log = OperationLog {
+            operations: vec![OperationMetadata::new(
+                OperationType::Delete,
+                PathBuf::from("/old.txt"),
+            )],
+            ..OperationLog::default()
+        };
+        fs::write(&legacy, serde_json::to_string(&log).unwrap()).unwrap();
+
+        let store = MetadataStore::new(tmp.path().join("metadata")).unwrap();
+        assert_eq!(store.count(), 1);
+        assert!(!legacy.exists());
+        assert_eq!(
+            MetadataStore::new(tmp.path().join("metadata"))
+                .unwrap()
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_paths_are_stored_relative_to_root() {
+        let tmp = TempDir::new().unwrap();
+        let store_path = tmp.path().join("metadata");
+        let (old_root, new_root) = (PathBuf::from("/old/project"), PathBuf::from("/new/project"));
+        let read_log = || -> String {
+            fs::read_dir(&store_path)
+                .unwrap()
+                .map(|entry| fs::read_to_string(entry.unwrap().path()).unwrap())
+                .collect()
+        };
+
+        // Written before paths were stored relative
+        let mut store = MetadataStore::new(store_path.clone()).unwrap();
+        store
+            .append(OperationMetadata::new(
+                OperationType::Delete,
+                old_root.join("a.txt"),
+            ))
+            .unwrap();
+        assert!(read_log().contains("/old/project/a.txt"));
+
+        let mut store = MetadataStore::new(store_path.clone()).unwrap();
+        store.set_root(&old_root);
+        assert_eq!(store.migrate_paths().unwrap(), 1);
+        store
+            .append(
+                OperationMetadata::new(OperationType::Move, old_root.join("b.txt"))
+                    .with_secondary_path(old_root.join("sub/c.txt")),
+            )
+            .unwrap();
+        store
+            .append(OperationMetadata::new(
+                OperationType::Delete,
+                PathBuf::from("/elsewhere/d.txt"),
+            ))
+            .unwrap();
+        assert!(!read_log().contains("/old/project"));
+        assert!(read_log().contains("/elsewhere/d.txt"));
+
+        // Opened from where the project moved to
+        let mut store = MetadataStore::new(store_path.clone()).unwrap();
+        store.set_root(&new_root);
+        assert_eq!(store.migrate_paths().unwrap(), 0);
+        let ops = store.operations();
+        assert_eq!(ops[0].path, new_root.join("a.txt"));
+        assert_eq!(ops[1].path, new_root.join("b.txt"));
+        assert_eq!(ops[1].path_secondary, Some(new_root.join("sub/c.txt")));
+        assert_eq!(ops[2].path, PathBuf::from("/elsewhere/d.txt"));
+    }
+
+    #[test]
+    fn test_workspaces_share_a_log() {
+        let tmp = TempDir::new().unwrap();
+        let store_path = tmp.path().join("metadata");
+        let open = |workspace: &str, root: &str| {
+            let mut store = MetadataStore::new(store_path.clone()).unwrap();
+            store.set_workspace(workspace);
+            store.set_root(Path::new(root));
+            store
+        };
+
+        // Recorded before workspaces were used: seen by both
+        let mut store = MetadataStore::new(store_path.clone()).unwrap();
+        store
+            .append(OperationMetadata::new(
+                OperationType::Delete,
+                "/a/old.txt".into(),
+            ))
+            .unwrap();
+        let mut alpha = open("alpha", "/a");
+        alpha
+            .append(OperationMetadata::new(OperationType::Delete, "/a/f".into()))
+            .unwrap();
+        let mut beta = open("beta", "/b");
+        beta.append(OperationMetadata::new(OperationType::Delete, "/b/f".into()))
+            .unwrap();
+        let undone = beta.operations()[1].id.clone();
+        beta.mark_undone(&undone, "undo").unwrap();
+
+        let mut alpha = open("alpha", "/a");
+        assert_eq!(alpha.count(), 2);
+        assert_eq!(alpha.operations()[1].path, PathBuf::from("/a/f"));
+        assert_eq!(alpha.other_workspaces().len(), 1);
+        assert_eq!(alpha.other_workspaces()[0].path, PathBuf::from("f"));
+        assert!(alpha.other_workspaces()[0].undone);
+
+        // Pruning one workspace leaves the other's history alone
+        alpha.prune(0).unwrap();
+        assert_eq!(alpha.count(), 0);
+        let beta = open("beta", "/b");
+        assert_eq!(beta.count(), 1);
+        assert_eq!(beta.operations()[0].path, PathBuf::from("/b/f"));
+        assert_eq!(beta.all_operations().len(), 1);
+    }
 }
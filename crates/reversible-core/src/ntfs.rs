@@ -0,0 +1,211 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// NTFS: file attributes and alternate data streams
+// Captured alongside a file's content so that undoing a delete or modify
+// on Windows restores the hidden/read-only/system bits and every named
+// stream. Other platforms have neither, and capture nothing.
+
+use crate::content_store::ContentStore;
+use crate::error::Result;
+use crate::metadata::DataStream;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The path that opens stream `name` of `path` (`file.txt:name`)
+pub fn stream_path(path: &Path, name: &str) -> PathBuf {
+    let mut stream = OsString::from(path.as_os_str());
+    stream.push(":");
+    stream.push(name);
+    PathBuf::from(stream)
+}
+
+/// The name of a named data stream as Windows lists it (`:name:$DATA`),
+/// or `None` for the unnamed stream holding the file's content
+pub fn parse_stream_name(raw: &str) -> Option<&str> {
+    raw.strip_prefix(':')
+        .and_then(|rest| rest.strip_suffix(":$DATA"))
+        .filter(|name| !name.is_empty())
+}
+
+/// Store the content of each named stream of `path`
+#[cfg(windows)]
+pub fn capture_streams(content_store: &ContentStore, path: &Path) -> Result<Vec<DataStream>> {
+    let mut streams = Vec::new();
+    for raw in sys::stream_names(path)? {
+        let Some(name) = parse_stream_name(&raw) else {
+            continue;
+        };
+        let content = fs::read(stream_path(path, name))?;
+        streams.push(DataStream {
+            name: name.to_string(),
+            content_hash: content_store.store(&content)?,
+        });
+    }
+    Ok(streams)
+}
+
+#[cfg(not(windows))]
+pub fn capture_streams(_content_store: &ContentStore, _path: &Path) -> Result<Vec<DataStream>> {
+    Ok(Vec::new())
+}
+
+/// Write `streams` back to `path`, replacing any of the same name
+pub fn restore_streams(
+    content_store: &ContentStore,
+    path: &Path,
+    streams: &[DataStream],
+) -> Result<()> {
+    if !cfg!(windows) {
+        if !streams.is_empty() {
+            tracing::warn!(
+                "{}: {} alternate data stream(s) cannot be restored off NTFS",
+                path.display(),
+                streams.len()
+            );
+        }
+        return Ok(());
+    }
+    for stream in streams {
+        let content = content_store.retrieve(&stream.content_hash)?;
+        // Not `fs::write`: creating over a hidden or system file fails
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(stream_path(path, &stream.name))?;
+        file.set_len(0)?;
+        std::io::Write::write_all(&mut file, &content)?;
+    }
+    Ok(())
+}
+
+/// Set the `FILE_ATTRIBUTE_*` bits of `path`
+#[cfg(windows)]
+pub fn set_attributes(path: &Path, attributes: u32) -> Result<()> {
+    Ok(sys::set_attributes(path, attributes)?)
+}
+
+#[cfg(windows)]
+#[allow(unsafe_code)]
+mod sys {
+    use std::ffi::c_void;
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+    use windows_sys::Win32::Foundation::{ERROR_HANDLE_EOF, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Storage::FileSystem::{
+        FindClose, FindFirstStreamW, FindNextStreamW, FindStreamInfoStandard, SetFileAttributesW,
+        WIN32_FIND_STREAM_DATA,
+    };
+
+    fn wide(path: &Path) -> Vec<u16> {
+        path.as_os_str().encode_wide().chain(Some(0)).collect()
+    }
+
+    fn is_eof(error: &io::Error) -> bool {
+        error.raw_os_error() == Some(ERROR_HANDLE_EOF as i32)
+    }
+
+    pub fn set_attributes(path: &Path, attributes: u32) -> io::Result<()> {
+        let path = wide(path);
+        // SAFETY: `path` is NUL-terminated and outlives the call
+        if unsafe { SetFileAttributesW(path.as_ptr(), attributes) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Every data stream of `path`, as `:name:$DATA`
+    pub fn stream_names(path: &Path) -> io::Result<Vec<String>> {
+        let path = wide(path);
+        // SAFETY: all-zero is a valid WIN32_FIND_STREAM_DATA
+        let mut data: WIN32_FIND_STREAM_DATA = unsafe { std::mem::zeroed() };
+        let buffer = &mut data as *mut WIN32_FIND_STREAM_DATA as *mut c_void;
+        // SAFETY: `path` is NUL-terminated; `buffer` is the struct the
+        // standard info level fills
+        let handle = unsafe { FindFirstStreamW(path.as_ptr(), FindStreamInfoStandard, buffer, 0) };
+        if handle == INVALID_HANDLE_VALUE {
+            // No streams at all, as for a directory
+            let error = io::Error::last_os_error();
+            return if is_eof(&error) {
+                Ok(Vec::new())
+            } else {
+                Err(error)
+            };
+        }
+
+        let mut names = Vec::new();
+        let error = loop {
+            let name = &data.cStreamName;
+            let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+            names.push(String::from_utf16_lossy(&name[..len]));
+            // SAFETY: `handle` is open and `buffer` still points at `data`
+            if unsafe { FindNextStreamW(handle, buffer) } == 0 {
+                break io::Error::last_os_error();
+            }
+        };
+        // SAFETY: `handle` came from FindFirstStreamW and is closed once
+        unsafe { FindClose(handle) };
+        if is_eof(&error) {
+            Ok(names)
+        } else {
+            Err(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_stream_names() {
+        assert_eq!(parse_stream_name("::$DATA"), None);
+        assert_eq!(
+            parse_stream_name(":Zone.Identifier:$DATA"),
+            Some("Zone.Identifier")
+        );
+        assert_eq!(parse_stream_name("Zone.Identifier"), None);
+        assert_eq!(
+            stream_path(Path::new("dir/file.txt"), "meta"),
+            PathBuf::from("dir/file.txt:meta")
+        );
+    }
+
+    #[test]
+    fn test_streams_round_trip() {
+        let temp = TempDir::new().unwrap();
+        let store = ContentStore::new(temp.path().join("content"), false).unwrap();
+        let file = temp.path().join("file.txt");
+        fs::write(&file, "content").unwrap();
+
+        let streams = capture_streams(&store, &file).unwrap();
+        if cfg!(windows) {
+            assert!(streams.is_empty());
+            fs::write(stream_path(&file, "meta"), "tagged").unwrap();
+            let streams = capture_streams(&store, &file).unwrap();
+            assert_eq!(streams.len(), 1);
+
+            fs::remove_file(&file).unwrap();
+            fs::write(&file, "content").unwrap();
+            restore_streams(&store, &file, &streams).unwrap();
+            assert_eq!(
+                fs::read_to_string(stream_path(&file, "meta")).unwrap(),
+                "tagged"
+            );
+        } else {
+            assert!(streams.is_empty());
+            // Nothing to restore them to: skipped, not an error
+            let stream = DataStream {
+                name: "meta".to_string(),
+                content_hash: store.store(b"tagged").unwrap(),
+            };
+            restore_streams(&store, &file, &[stream]).unwrap();
+            assert_eq!(fs::read_to_string(&file).unwrap(), "content");
+        }
+    }
+}
@@ -0,0 +1,231 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Ownership: file owner and group, by ID and by name
+// IDs are what the filesystem stores; names are what still mean the same
+// user on another machine. Names are read from /etc/passwd and
+// /etc/group, so users known only to a directory service have none.
+
+use crate::error::Result;
+use crate::metadata::FileMetadata;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+const PASSWD: &str = "/etc/passwd";
+const GROUP: &str = "/etc/group";
+
+/// Name and ID of each entry of a passwd- or group-format database
+pub fn parse_database(content: &str) -> Vec<(String, u32)> {
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse().ok()?;
+            (!name.is_empty()).then(|| (name.to_string(), id))
+        })
+        .collect()
+}
+
+fn database(path: &str) -> Vec<(String, u32)> {
+    fs::read_to_string(path)
+        .map(|content| parse_database(&content))
+        .unwrap_or_default()
+}
+
+/// Name of the user with ID `uid`, if this machine knows one
+pub fn user_name(uid: u32) -> Option<String> {
+    database(PASSWD)
+        .into_iter()
+        .find_map(|(name, id)| (id == uid).then_some(name))
+}
+
+/// ID of the user named `name` on this machine
+pub fn user_id(name: &str) -> Option<u32> {
+    database(PASSWD)
+        .into_iter()
+        .find_map(|(n, id)| (n == name).then_some(id))
+}
+
+/// Name of the group with ID `gid`, if this machine knows one
+pub fn group_name(gid: u32) -> Option<String> {
+    database(GROUP)
+        .into_iter()
+        .find_map(|(name, id)| (id == gid).then_some(name))
+}
+
+/// ID of the group named `name` on this machine
+pub fn group_id(name: &str) -> Option<u32> {
+    database(GROUP)
+        .into_iter()
+        .find_map(|(n, id)| (n == name).then_some(id))
+}
+
+/// How undo gives a recreated file its captured owner and group
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OwnershipMode {
+    /// Leave the file owned by whoever recreated it
+    None,
+    /// The captured numeric IDs, whatever they name here
+    Id,
+    /// The users and groups of the captured names on this machine,
+    /// falling back to the IDs for names it does not know
+    #[default]
+    Name,
+    /// As `name`, but through the `users` and `groups` tables first
+    Map,
+}
+
+/// Ownership restoration (`Config.ownership`)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OwnershipConfig {
+    pub restore: OwnershipMode,
+    /// For `map`: captured user (name or ID) to the user (name or ID) to
+    /// restore
+    pub users: BTreeMap<String, String>,
+    /// For `map`: captured group (name or ID) to the group to restore
+    pub groups: BTreeMap<String, String>,
+}
+
+impl OwnershipConfig {
+    /// The user ID to give a file whose owner was captured as `meta`, or
+    /// `None` to leave it
+    pub fn uid(&self, meta: &FileMetadata) -> Option<u32> {
+        self.resolve(
+            "user",
+            &self.users,
+            &meta.owner,
+            meta.owner_name.as_deref(),
+            user_id,
+        )
+    }
+
+    /// The group ID to give a file whose group was captured as `meta`, or
+    /// `None` to leave it
+    pub fn gid(&self, meta: &FileMetadata) -> Option<u32> {
+        self.resolve(
+            "group",
+            &self.groups,
+            &meta.group,
+            meta.group_name.as_deref(),
+            group_id,
+        )
+    }
+
+    fn resolve(
+        &self,
+        kind: &str,
+        table: &BTreeMap<String, String>,
+        id: &str,
+        name: Option<&str>,
+        lookup: impl Fn(&str) -> Option<u32>,
+    ) -> Option<u32> {
+        let by_id = || id.parse().ok();
+        let by_name = |name: &str| {
+            let found = lookup(name);
+            if found.is_none() {
+                tracing::warn!("no {} named {} here; restoring ID {}", kind, name, id);
+            }
+            found
+        };
+        match self.restore {
+            OwnershipMode::None => None,
+            OwnershipMode::Id => by_id(),
+            OwnershipMode::Name => name.and_then(by_name).or_else(by_id),
+            OwnershipMode::Map => {
+                let mapped = name
+                    .and_then(|name| table.get(name))
+                    .or_else(|| table.get(id));
+                match mapped {
+                    Some(target) => target.parse().ok().or_else(|| {
+                        let found = lookup(target);
+                        if found.is_none() {
+                            tracing::warn!("{} {} is mapped to unknown {}", kind, id, target);
+                        }
+                        found
+                    }),
+                    None => name.and_then(by_name).or_else(by_id),
+                }
+            }
+        }
+    }
+
+    /// Give `path` the owner and group `meta` captured, as configured.
+    /// Only root may give files away, so a refusal is logged, not failed.
+    #[cfg(unix)]
+    pub fn restore(&self, meta: &FileMetadata, path: &Path) -> Result<()> {
+        let (uid, gid) = (self.uid(meta), self.gid(meta));
+        if uid.is_none() && gid.is_none() {
+            return Ok(());
+        }
+        match std::os::unix::fs::lchown(path, uid, gid) {
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                tracing::warn!("{}: cannot restore owner: {}", path.display(), e);
+                Ok(())
+            }
+            result => Ok(result?),
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn restore(&self, _meta: &FileMetadata, _path: &Path) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_database() {
+        let passwd = "# comment\nroot:x:0:0:root:/root:/bin/sh\nalice:x:1000:1000::/home/alice:/bin/sh\nbroken\nbad:x:nan:0::/:/bin/sh\n";
+        assert_eq!(
+            parse_database(passwd),
+            vec![("root".to_string(), 0), ("alice".to_string(), 1000)]
+        );
+        assert_eq!(
+            parse_database("wheel:x:10:alice,bob\n"),
+            vec![("wheel".to_string(), 10)]
+        );
+    }
+
+    #[test]
+    fn test_resolve_modes() {
+        let temp = TempDir::new().unwrap();
+        let file = temp.path().join("file");
+        fs::write(&file, "x").unwrap();
+        let mut meta = FileMetadata::from_path(&file).unwrap();
+        meta.owner = "4242".to_string();
+        meta.owner_name = Some("no-such-user-here".to_string());
+        meta.group = "4343".to_string();
+        meta.group_name = None;
+
+        let config = |restore| OwnershipConfig {
+            restore,
+            ..OwnershipConfig::default()
+        };
+        assert_eq!(config(OwnershipMode::None).uid(&meta), None);
+        assert_eq!(config(OwnershipMode::Id).uid(&meta), Some(4242));
+        // Unknown names fall back to the captured IDs
+        assert_eq!(config(OwnershipMode::Name).uid(&meta), Some(4242));
+        assert_eq!(config(OwnershipMode::Name).gid(&meta), Some(4343));
+
+        let mapped = OwnershipConfig {
+            restore: OwnershipMode::Map,
+            users: BTreeMap::from([("no-such-user-here".to_string(), "1001".to_string())]),
+            groups: BTreeMap::from([("4343".to_string(), "2002".to_string())]),
+        };
+        assert_eq!(mapped.uid(&meta), Some(1001));
+        assert_eq!(mapped.gid(&meta), Some(2002));
+        meta.owner_name = None;
+        assert_eq!(mapped.uid(&meta), Some(4242));
+    }
+}
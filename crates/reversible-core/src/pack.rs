@@ -0,0 +1,194 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Packfiles: many small content blobs in one file, found through an index
+// Saves a file (and inode) per blob; `ContentStore::pack` folds loose ones in
+
+use crate::backend::FileBackend;
+use crate::content_store::ContentHash;
+use crate::error::{Result, ReversibleError};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Directory under the content store root holding packs
+pub const PACK_DIR: &str = "pack";
+
+/// Largest pack written, in bytes; further blobs go into another pack
+pub const PACK_MAX_BYTES: u64 = 8 * 1024 * 1024;
+
+/// How a stored object is encoded, packed or loose
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObjectKind {
+    /// Full content as is
+    Plain,
+    /// Full content, gzip-compressed
+    Gzip,
+    /// A delta object: `<base hash>\n<serialized delta>`
+    Delta,
+}
+
+/// Where one object lies in its pack
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackEntry {
+    pub hash: ContentHash,
+    pub kind: ObjectKind,
+    pub offset: u64,
+    pub len: u64,
+}
+
+/// Index of a pack, stored as `pack-<id>.idx` beside `pack-<id>.pack`.
+///
+/// The pack is written before its index and removed after it, so a pack
+/// without an index (left by a crash) holds nothing the store relies on.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PackIndex {
+    pub entries: Vec<PackEntry>,
+}
+
+/// Every packed object by hash, with the pack holding it
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PackTable {
+    objects: HashMap<ContentHash, (PathBuf, PackEntry)>,
+}
+
+impl PackTable {
+    /// Read every pack index in `dir`
+    pub(crate) fn load(backend: &dyn FileBackend, dir: &Path) -> Result<Self> {
+        let mut objects = HashMap::new();
+        if !backend.exists(dir) {
+            return Ok(Self { objects });
+        }
+        for (path, _) in backend.list_files(dir)? {
+            if path.extension().is_none_or(|ext| ext != "idx") {
+                continue;
+            }
+            let pack = path.with_extension("pack");
+            for entry in read_index(backend, &path)?.entries {
+                objects.insert(entry.hash.clone(), (pack.clone(), entry));
+            }
+        }
+        Ok(Self { objects })
+    }
+
+    /// The pack holding `hash` and where in it
+    pub(crate) fn get(&self, hash: &ContentHash) -> Option<&(PathBuf, PackEntry)> {
+        self.objects.get(hash)
+    }
+
+    /// Every packed object, in no particular order
+    pub(crate) fn entries(&self) -> impl Iterator<Item = &PackEntry> {
+        self.objects.values().map(|(_, entry)| entry)
+    }
+}
+
+fn read_index(backend: &dyn FileBackend, path: &Path) -> Result<PackIndex> {
+    serde_json::from_slice(&backend.read(path)?).map_err(|e| {
+        ReversibleError::MetadataCorrupted(format!("pack index {}: {}", path.display(), e))
+    })
+}
+
+/// Write `objects` as a new pack in `dir` and return the pack's path
+pub(crate) fn write_pack(
+    backend: &dyn FileBackend,
+    dir: &Path,
+    objects: &[(ContentHash, ObjectKind, Vec<u8>)],
+) -> Result<PathBuf> {
+    let mut data = Vec::new();
+    let mut index = PackIndex::default();
+    for (hash, kind, bytes) in objects {
+        index.entries.push(PackEntry {
+            hash: hash.clone(),
+            kind: *kind,
+            offset: data.len() as u64,
+            len: bytes.len() as u64,
+        });
+        data.extend_from_slice(bytes);
+    }
+
+    let pack = dir.join(format!("pack-{}.pack", uuid::Uuid::new_v4().simple()));
+    backend.create_dir_all(dir)?;
+    backend.write(&pack, &data)?;
+    backend.write(&pack.with_extension("idx"), &serde_json::to_vec(&index)?)?;
+    tracing::debug!(pack = %pack.display(), objects = objects.len(), bytes = data.len(), "pack written");
+    Ok(pack)
+}
+
+/// Drop `hashes` from `pack` by writing its other objects to a new pack
+/// and removing the old one (and writing nothing if none remain)
+pub(crate) fn remove_from_pack(
+    backend: &dyn FileBackend,
+    pack: &Path,
+    hashes: &HashSet<ContentHash>,
+) -> Result<()> {
+    let index_path = pack.with_extension("idx");
+    let index = read_index(backend, &index_path)?;
+    let kept: Vec<&PackEntry> = index
+        .entries
+        .iter()
+        .filter(|entry| !hashes.contains(&entry.hash))
+        .collect();
+
+    if !kept.is_empty() {
+        let objects = kept
+            .into_iter()
+            .map(|entry| {
+                let bytes = backend.read_range(pack, entry.offset, entry.len)?;
+                Ok((entry.hash.clone(), entry.kind, bytes))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let dir = pack.parent().unwrap_or(Path::new("."));
+        write_pack(backend, dir, &objects)?;
+    }
+    backend.remove_file(&index_path)?;
+    backend.remove_file(pack)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::LocalBackend;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_write_and_shrink_pack() {
+        let tmp = TempDir::new().unwrap();
+        let backend = LocalBackend;
+        let objects: Vec<_> = ["one", "two", "three"]
+            .iter()
+            .map(|s| {
+                (
+                    ContentHash::from_string(s),
+                    ObjectKind::Plain,
+                    s.as_bytes().to_vec(),
+                )
+            })
+            .collect();
+        let pack = write_pack(&backend, tmp.path(), &objects).unwrap();
+
+        let table = PackTable::load(&backend, tmp.path()).unwrap();
+        let (path, entry) = table.get(&ContentHash::from_string("two")).unwrap();
+        assert_eq!(
+            backend.read_range(path, entry.offset, entry.len).unwrap(),
+            b"two"
+        );
+
+        let gone: HashSet<_> = [ContentHash::from_string("two")].into();
+        remove_from_pack(&backend, &pack, &gone).unwrap();
+        assert!(!backend.exists(&pack));
+        let table = PackTable::load(&backend, tmp.path()).unwrap();
+        assert!(table.get(&ContentHash::from_string("two")).is_none());
+        let (path, entry) = table.get(&ContentHash::from_string("three")).unwrap();
+        assert_eq!(
+            backend.read_range(path, entry.offset, entry.len).unwrap(),
+            b"three"
+        );
+
+        // Removing the rest removes the pack altogether
+        let rest: HashSet<_> = table.entries().map(|e| e.hash.clone()).collect();
+        remove_from_pack(&backend, path, &rest).unwrap();
+        assert!(backend.list_files(tmp.path()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,163 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// External References: what outside the store a change was made for
+// Operations and transactions carry key/value references to the systems
+// behind them, such as a CI build or a ticket (`ci:build=1234`,
+// `ticket=JIRA-99`), so a file change can be traced back to them
+
+use crate::error::{Result, ReversibleError};
+use std::collections::BTreeMap;
+
+/// Environment variable of references to record on operations and
+/// transactions: `key=value` pairs separated by commas
+pub const REFS_ENV: &str = "JANUSKEY_REFS";
+
+/// Longest accepted key
+const MAX_KEY_LEN: usize = 64;
+
+/// Longest accepted value
+const MAX_VALUE_LEN: usize = 256;
+
+/// References by key
+pub type References = BTreeMap<String, String>;
+
+/// Parse one `key=value` reference. Keys are ASCII letters, digits and
+/// `:._-`; values are non-empty, without commas or control characters.
+pub fn parse_reference(spec: &str) -> Result<(String, String)> {
+    let invalid = |why: &str| ReversibleError::InvalidReference(format!("{:?}: {}", spec, why));
+    let (key, value) = spec
+        .split_once('=')
+        .ok_or_else(|| invalid("expected key=value"))?;
+    validate_key(key).map_err(|why| invalid(&why))?;
+    if value.is_empty() {
+        return Err(invalid("empty value"));
+    }
+    if value.chars().count() > MAX_VALUE_LEN {
+        return Err(invalid(&format!(
+            "value longer than {} characters",
+            MAX_VALUE_LEN
+        )));
+    }
+    if value.chars().any(|c| c == ',' || c.is_control()) {
+        return Err(invalid("value contains a comma or control characters"));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+fn validate_key(key: &str) -> std::result::Result<(), String> {
+    if key.is_empty() {
+        return Err("empty key".to_string());
+    }
+    if key.len() > MAX_KEY_LEN {
+        return Err(format!("key longer than {} characters", MAX_KEY_LEN));
+    }
+    if !key
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, ':' | '.' | '_' | '-'))
+    {
+        return Err("keys are letters, digits and : . _ -".to_string());
+    }
+    Ok(())
+}
+
+/// Parse comma-separated references, as [`REFS_ENV`] holds them. A key
+/// given twice keeps its last value.
+pub fn parse_references(list: &str) -> Result<References> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|spec| !spec.is_empty())
+        .map(parse_reference)
+        .collect()
+}
+
+/// Format references as [`parse_references`] reads them
+pub fn format_references(refs: &References) -> String {
+    refs.iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// The references in [`REFS_ENV`]; none if it is unset
+pub fn from_env() -> Result<References> {
+    match std::env::var(REFS_ENV) {
+        Ok(list) => parse_references(&list),
+        Err(_) => Ok(References::new()),
+    }
+}
+
+/// Add `defaults` to `refs`, keeping the value `refs` already has for a
+/// key
+pub fn inherit(refs: &mut References, defaults: &References) {
+    for (key, value) in defaults {
+        refs.entry(key.clone()).or_insert_with(|| value.clone());
+    }
+}
+
+/// A query for references: `key` matches any value of the key, and
+/// `key=value` that value only
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefQuery {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+impl RefQuery {
+    /// Parse `key` or `key=value`
+    pub fn parse(spec: &str) -> Result<Self> {
+        if spec.contains('=') {
+            let (key, value) = parse_reference(spec)?;
+            return Ok(Self {
+                key,
+                value: Some(value),
+            });
+        }
+        validate_key(spec)
+            .map_err(|why| ReversibleError::InvalidReference(format!("{:?}: {}", spec, why)))?;
+        Ok(Self {
+            key: spec.to_string(),
+            value: None,
+        })
+    }
+
+    /// Whether `refs` has the key, with the value if one is asked for
+    pub fn matches(&self, refs: &References) -> bool {
+        refs.get(&self.key)
+            .is_some_and(|value| self.value.as_ref().is_none_or(|wanted| wanted == value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_match() {
+        let refs = parse_references("ci:build=1234, ticket=JIRA-99,ci:build=1235").unwrap();
+        assert_eq!(refs.len(), 2);
+        assert_eq!(refs["ci:build"], "1235");
+        assert_eq!(parse_references(&format_references(&refs)).unwrap(), refs);
+        assert_eq!(
+            parse_reference("url=https://ci.example/job?id=7")
+                .unwrap()
+                .1,
+            "https://ci.example/job?id=7"
+        );
+        for bad in ["", "build", "=1", "ci build=1", "ci:build=", "a=b\nc"] {
+            assert!(parse_reference(bad).is_err(), "{:?}", bad);
+        }
+
+        let mut inherited = parse_references("ticket=JIRA-100").unwrap();
+        inherit(&mut inherited, &refs);
+        assert_eq!(inherited["ticket"], "JIRA-100");
+        assert_eq!(inherited["ci:build"], "1235");
+
+        assert!(RefQuery::parse("ci:build").unwrap().matches(&refs));
+        assert!(RefQuery::parse("ci:build=1235").unwrap().matches(&refs));
+        assert!(!RefQuery::parse("ci:build=1234").unwrap().matches(&refs));
+        assert!(!RefQuery::parse("deploy").unwrap().matches(&refs));
+        assert!(RefQuery::parse("ci build").is_err());
+    }
+}
@@ -9,11 +9,19 @@
 // lives in januskey-cli, not here. This module provides only the data
 // types and persistence — no filesystem side effects.
 
+use crate::actor::Actor;
+use crate::backend::{FileBackend, LocalBackend};
 use crate::error::{Result, ReversibleError};
+use crate::events::{Event, EventBus};
+use crate::framing::{self, Compressed};
+use crate::metadata::{DependencyGraph, OperationMetadata};
+use crate::refs::References;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::fs;
+use std::collections::BTreeMap;
+use std::io::Read;
 use std::path::PathBuf;
+use std::sync::Arc;
 use uuid::Uuid;
 
 /// Transaction state
@@ -25,6 +33,30 @@ pub enum TransactionState {
     Committed,
     /// Transaction has been rolled back
     RolledBack,
+    /// Rollback stopped with operations left to undo; see
+    /// [`Transaction::rollback_status`]
+    PartiallyRolledBack,
+}
+
+impl std::fmt::Display for TransactionState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Active => write!(f, "active"),
+            Self::Committed => write!(f, "committed"),
+            Self::RolledBack => write!(f, "rolled back"),
+            Self::PartiallyRolledBack => write!(f, "partially rolled back"),
+        }
+    }
+}
+
+/// Outcome of undoing one operation while rolling back
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RollbackStatus {
+    /// The operation was undone
+    Undone,
+    /// Undoing the operation failed with this error
+    Failed(String),
 }
 
 /// A transaction grouping multiple operations.
@@ -48,6 +80,16 @@ pub struct Transaction {
     pub operation_ids: Vec<String>,
     /// User who started the transaction
     pub user: String,
+    /// Actor `user` declared it was acting for (see [`crate::actor`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+    /// References to what the transaction was made for (see
+    /// [`crate::refs`]); its operations inherit them
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub refs: References,
+    /// Per-operation outcome of the last rollback attempt, by operation ID
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub rollback_status: BTreeMap<String, RollbackStatus>,
 }
 
 impl Transaction {
@@ -61,6 +103,9 @@ pub fn new(name: Option<String>) -> Self {
             state: TransactionState::Active,
             operation_ids: Vec::new(),
             user: whoami::username(),
+            actor: None,
+            refs: References::new(),
+            rollback_status: BTreeMap::new(),
         }
     }
 
@@ -85,6 +130,81 @@ pub fn rollback(&mut self) {
         self.state = TransactionState::RolledBack;
         self.completed_at = Some(Utc::now());
     }
+
+    /// Best-effort reconstruction of transaction `id` from its recorded
+    /// operations (oldest first), for when the transaction log is lost.
+    /// The name is unknown; it is taken as rolled back if every operation
+    /// was undone, else committed. Its references are those every
+    /// operation shares, since operations inherit their transaction's.
+    pub fn from_operations(id: &str, operations: &[&OperationMetadata]) -> Self {
+        let all_undone = !operations.is_empty() && operations.iter().all(|op| op.undone);
+        let first = operations.first();
+        Self {
+            id: id.to_string(),
+            name: None,
+            started_at: first.map_or_else(Utc::now, |op| op.timestamp),
+            completed_at: operations.last().map(|op| op.timestamp),
+            state: if all_undone {
+                TransactionState::RolledBack
+            } else {
+                TransactionState::Committed
+            },
+            operation_ids: operations.iter().map(|op| op.id.clone()).collect(),
+            user: first.map_or_else(whoami::username, |op| op.user.clone()),
+            actor: first.and_then(|op| op.actor.clone()),
+            refs: first.map_or_else(References::new, |op| {
+                op.refs
+                    .iter()
+                    .filter(|(key, value)| {
+                        operations
+                            .iter()
+                            .all(|other| other.refs.get(*key) == Some(value))
+                    })
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect()
+            }),
+            rollback_status: BTreeMap::new(),
+        }
+    }
+
+    /// Operations a rollback has not yet undone, newest first
+    pub fn rollback_remaining(&self) -> impl Iterator<Item = &String> {
+        self.operation_ids
+            .iter()
+            .rev()
+            .filter(|id| self.rollback_status.get(*id) != Some(&RollbackStatus::Undone))
+    }
+
+    /// [`Transaction::rollback_remaining`] in the order to undo them:
+    /// each after every one of them depending on it in `graph`, as those
+    /// touched its paths later, and otherwise newest first. Undoing a move
+    /// before the modify made at its destination would move the wrong
+    /// content back; this holds even if `operation_ids` is not in the
+    /// order the operations ran.
+    pub fn rollback_order(&self, graph: &DependencyGraph) -> Vec<String> {
+        let mut pending: Vec<&String> = self.rollback_remaining().collect();
+        let blockers: BTreeMap<&str, Vec<String>> = pending
+            .iter()
+            .map(|id| {
+                let within = graph
+                    .descendants(id)
+                    .into_iter()
+                    .filter(|later| pending.contains(&later))
+                    .collect();
+                (id.as_str(), within)
+            })
+            .collect();
+        let mut order: Vec<String> = Vec::with_capacity(pending.len());
+        while !pending.is_empty() {
+            // The graph follows log order, so there is always a next one
+            let next = pending
+                .iter()
+                .position(|id| blockers[id.as_str()].iter().all(|b| order.contains(b)))
+                .unwrap_or(0);
+            order.push(pending.remove(next).clone());
+        }
+        order
+    }
 }
 
 /// Transaction log for persistence
@@ -111,41 +231,91 @@ pub fn new() -> Self {
 /// the consuming crate's executor, not here. This manager tracks
 /// transaction state and persists it.
 pub struct TransactionManager {
+    /// Backend holding the transaction log
+    backend: Arc<dyn FileBackend>,
     /// Path to transaction log file
     path: PathBuf,
     /// Transaction log
     log: TransactionLog,
+    /// Write the log compressed (see [`crate::framing`])
+    compress: bool,
+    /// Declared actor stamped on new transactions
+    actor: Option<String>,
+    /// References stamped on new transactions
+    refs: References,
+    /// Where transactions beginning and ending are announced
+    events: EventBus,
 }
 
 impl TransactionManager {
     /// Create or open a transaction manager
     pub fn new(path: PathBuf) -> Result<Self> {
-        let log = if path.exists() {
-            let content = ({
-                use std::io::Read;
-                std::fs::File::open(&path).and_then(|mut f| {
-                    let mut buf = String::new();
-                    f.take(10 * 1024 * 1024).read_to_string(&mut buf)?;
-                    Ok(buf)
-                })
-            })?;
-            serde_json::from_str(&content)
-                .map_err(|e| ReversibleError::MetadataCorrupted(e.to_string()))?
+        Self::new_with_backend(Arc::new(LocalBackend), path)
+    }
+
+    /// Create or open a transaction manager at `path` on the given backend
+    pub fn new_with_backend(backend: Arc<dyn FileBackend>, path: PathBuf) -> Result<Self> {
+        let (log, compress) = if backend.exists(&path) {
+            let reader = framing::open(backend.as_ref(), &path)?;
+            let compress = reader.is_compressed();
+            let log = serde_json::from_reader(reader.take(10 * 1024 * 1024))
+                .map_err(|e| ReversibleError::MetadataCorrupted(e.to_string()))?;
+            (log, compress)
         } else {
-            TransactionLog::new()
+            (TransactionLog::new(), false)
         };
 
-        Ok(Self { path, log })
+        Ok(Self {
+            backend,
+            path,
+            log,
+            compress,
+            actor: None,
+            refs: References::new(),
+            events: EventBus::default(),
+        })
     }
 
     /// Save the log to disk
     fn save(&self) -> Result<()> {
-        if let Some(parent) = self.path.parent() {
-            fs::create_dir_all(parent)?;
+        let content = serde_json::to_vec_pretty(&self.log)?;
+        self.backend
+            .write(&self.path, &framing::encode(content, self.compress)?)
+    }
+
+    /// Write the log compressed from now on. A log read compressed stays
+    /// compressed.
+    pub fn set_compression(&mut self, compress: bool) {
+        self.compress |= compress;
+    }
+
+    /// Rewrite the log compressed, if it is not already
+    pub fn compress(&mut self) -> Result<Compressed> {
+        let mut compressed = Compressed::default();
+        if self.compress || !self.backend.exists(&self.path) {
+            return Ok(compressed);
         }
-        let content = serde_json::to_string_pretty(&self.log)?;
-        fs::write(&self.path, content)?;
-        Ok(())
+        let before = self.backend.read(&self.path)?.len() as u64;
+        self.compress = true;
+        self.save()?;
+        compressed.add(before, self.backend.read(&self.path)?.len() as u64);
+        Ok(compressed)
+    }
+
+    /// Record `actor`'s declared name on transactions begun from now on
+    pub fn set_actor(&mut self, actor: &Actor) {
+        self.actor = actor.declared.clone();
+    }
+
+    /// Record `refs` on transactions begun from now on
+    pub fn set_refs(&mut self, refs: References) {
+        self.refs = refs;
+    }
+
+    /// Publish transactions beginning, committing and rolling back on
+    /// `events`
+    pub fn set_events(&mut self, events: EventBus) {
+        self.events = events;
     }
 
     /// Begin a new transaction
@@ -154,11 +324,15 @@ pub fn begin(&mut self, name: Option<String>) -> Result<&Transaction> {
             return Err(ReversibleError::TransactionActive(active_id.clone()));
         }
 
-        let transaction = Transaction::new(name);
+        let mut transaction = Transaction::new(name);
+        transaction.actor = self.actor.clone();
+        transaction.refs = self.refs.clone();
         let id = transaction.id.clone();
         self.log.transactions.push(transaction);
-        self.log.active_transaction_id = Some(id);
+        self.log.active_transaction_id = Some(id.clone());
         self.save()?;
+        tracing::info!(transaction = %id, "transaction begun");
+        self.events.publish(Event::TransactionBegun { id });
 
         // SAFETY: We just pushed a transaction above, so last() is guaranteed Some
         Ok(self
@@ -191,6 +365,20 @@ pub fn add_operation(&mut self, operation_id: String) -> Result<()> {
         self.save()
     }
 
+    /// Add several operations to the active transaction, saving once
+    pub fn add_operations(
+        &mut self,
+        operation_ids: impl IntoIterator<Item = String>,
+    ) -> Result<()> {
+        let transaction = self
+            .active_mut()
+            .ok_or(ReversibleError::NoActiveTransaction)?;
+        for operation_id in operation_ids {
+            transaction.add_operation(operation_id);
+        }
+        self.save()
+    }
+
     /// Commit the active transaction (marks state only — no filesystem effects)
     pub fn commit(&mut self) -> Result<Transaction> {
         let transaction = self
@@ -200,6 +388,10 @@ pub fn commit(&mut self) -> Result<Transaction> {
         let result = transaction.clone();
         self.log.active_transaction_id = None;
         self.save()?;
+        tracing::info!(transaction = %result.id, operations = result.operation_ids.len(), "transaction committed");
+        self.events.publish(Event::TransactionCommitted {
+            id: result.id.clone(),
+        });
         Ok(result)
     }
 
@@ -208,21 +400,99 @@ pub fn commit(&mut self) -> Result<Transaction> {
     /// The caller is responsible for actually undoing the operations
     /// via the appropriate executor before calling this.
     pub fn mark_rolled_back(&mut self) -> Result<Transaction> {
-        let transaction = self
-            .active_mut()
-            .ok_or(ReversibleError::NoActiveTransaction)?;
+        let id = self
+            .active()
+            .ok_or(ReversibleError::NoActiveTransaction)?
+            .id
+            .clone();
+        self.mark_rolled_back_by_id(&id)
+    }
+
+    /// Mark the transaction `id` rolled back, whether it is active or a
+    /// resumed partial rollback
+    pub fn mark_rolled_back_by_id(&mut self, id: &str) -> Result<Transaction> {
+        let transaction = self.get_mut(id)?;
         transaction.rollback();
         let result = transaction.clone();
-        self.log.active_transaction_id = None;
+        if self.log.active_transaction_id.as_deref() == Some(id) {
+            self.log.active_transaction_id = None;
+        }
+        self.save()?;
+        tracing::info!(transaction = %id, "transaction rolled back");
+        self.events
+            .publish(Event::TransactionRolledBack { id: id.to_string() });
+        Ok(result)
+    }
+
+    /// Mark the transaction `id` partially rolled back. It stops being
+    /// the active transaction, so no further operations join it.
+    pub fn mark_partially_rolled_back(&mut self, id: &str) -> Result<Transaction> {
+        let transaction = self.get_mut(id)?;
+        transaction.state = TransactionState::PartiallyRolledBack;
+        transaction.completed_at = None;
+        let result = transaction.clone();
+        if self.log.active_transaction_id.as_deref() == Some(id) {
+            self.log.active_transaction_id = None;
+        }
         self.save()?;
+        tracing::warn!(transaction = %id, "transaction partially rolled back");
         Ok(result)
     }
 
+    /// Record the outcome of undoing `operation_id` while rolling back
+    /// `id`. Saved at once, so a crashed rollback can be resumed.
+    pub fn record_rollback_step(
+        &mut self,
+        id: &str,
+        operation_id: &str,
+        status: RollbackStatus,
+    ) -> Result<()> {
+        self.get_mut(id)?
+            .rollback_status
+            .insert(operation_id.to_string(), status);
+        self.save()
+    }
+
+    /// Replace the whole log with `transactions`, none of them active
+    pub fn restore(&mut self, transactions: Vec<Transaction>) -> Result<()> {
+        self.log.transactions = transactions;
+        self.log.active_transaction_id = None;
+        self.save()
+    }
+
+    fn get_mut(&mut self, id: &str) -> Result<&mut Transaction> {
+        self.log
+            .transactions
+            .iter_mut()
+            .find(|t| t.id == id)
+            .ok_or_else(|| ReversibleError::InvalidTransactionId(id.to_string()))
+    }
+
     /// Get transaction by ID
     pub fn get(&self, id: &str) -> Option<&Transaction> {
         self.log.transactions.iter().find(|t| t.id == id)
     }
 
+    /// Find a transaction by ID or unique ID prefix
+    pub fn find(&self, prefix: &str) -> Result<&Transaction> {
+        if let Some(tx) = self.get(prefix) {
+            return Ok(tx);
+        }
+        let mut matches = self
+            .log
+            .transactions
+            .iter()
+            .filter(|t| t.id.starts_with(prefix));
+        match (matches.next(), matches.next()) {
+            (Some(tx), None) if !prefix.is_empty() => Ok(tx),
+            (Some(_), Some(_)) => Err(ReversibleError::InvalidTransactionId(format!(
+                "{} matches more than one transaction",
+                prefix
+            ))),
+            _ => Err(ReversibleError::InvalidTransactionId(prefix.to_string())),
+        }
+    }
+
     /// Get all transactions
     pub fn all(&self) -> &[Transaction] {
         &self.log.transactions
@@ -250,9 +520,55 @@ pub struct TransactionPreview {
 /// Single operation preview entry
 #[derive(Debug)]
 pub struct OperationPreview {
+    pub op_id: String,
     pub op_type: String,
     pub path: std::path::PathBuf,
     pub secondary_path: Option<std::path::PathBuf>,
+    /// What the operation did to the file's content; left unset by
+    /// `from_transaction` for callers that can read the content to fill in
+    pub change: Option<ContentChange>,
+}
+
+/// A file's content before and after an operation; `None` where the file
+/// did not exist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentChange {
+    pub before: Option<Vec<u8>>,
+    pub after: Option<Vec<u8>>,
+}
+
+impl ContentChange {
+    /// Bytes gained (or, negative, lost)
+    pub fn byte_delta(&self) -> i64 {
+        let len = |side: &Option<Vec<u8>>| side.as_ref().map_or(0, |c| c.len() as i64);
+        len(&self.after) - len(&self.before)
+    }
+
+    /// Line diff of the two sides, if both are text
+    pub fn line_diff(&self) -> Option<crate::delta::LineDiff> {
+        let text = |side: &Option<Vec<u8>>| match side {
+            Some(content) if crate::delta::is_likely_text(content) => {
+                std::str::from_utf8(content).ok().map(str::to_string)
+            }
+            Some(_) => None,
+            None => Some(String::new()),
+        };
+        Some(crate::delta::line_diff(
+            &text(&self.before)?,
+            &text(&self.after)?,
+        ))
+    }
+
+    /// Lines added and removed, if both sides are text
+    pub fn line_counts(&self) -> Option<(usize, usize)> {
+        let diff = self.line_diff()?;
+        let lines = diff.hunks.iter().flat_map(|hunk| &hunk.lines);
+        Some(lines.fold((0, 0), |(added, removed), line| match line {
+            crate::delta::DiffLine::Added(_) => (added + 1, removed),
+            crate::delta::DiffLine::Removed(_) => (added, removed + 1),
+            crate::delta::DiffLine::Context(_) => (added, removed),
+        }))
+    }
 }
 
 impl TransactionPreview {
@@ -272,9 +588,11 @@ pub fn from_transaction(
                 }
 
                 operations.push(OperationPreview {
+                    op_id: meta.id.clone(),
                     op_type: meta.op_type.to_string(),
                     path: meta.path.clone(),
                     secondary_path: meta.path_secondary.clone(),
+                    change: None,
                 });
             }
         }
@@ -292,6 +610,28 @@ mod tests {
     use super::*;
     use tempfile::TempDir;
 
+    #[test]
+    fn test_compressed_log_stays_compressed() {
+        let tmp = TempDir::new().expect("failed to create temp dir");
+        let path = tmp.path().join("transactions");
+        let mut manager = TransactionManager::new(path.clone()).unwrap();
+        for i in 0..20 {
+            manager.begin(Some(format!("deploy {}", i))).unwrap();
+            manager.commit().unwrap();
+        }
+
+        let compressed = manager.compress().unwrap();
+        assert_eq!(compressed.files, 1);
+        assert!(compressed.after < compressed.before);
+        assert!(framing::is_compressed(&std::fs::read(&path).unwrap()));
+        assert_eq!(manager.compress().unwrap().files, 0);
+
+        let mut manager = TransactionManager::new(path.clone()).unwrap();
+        assert_eq!(manager.all().len(), 20);
+        manager.begin(None).unwrap();
+        assert!(framing::is_compressed(&std::fs::read(&path).unwrap()));
+    }
+
     #[test]
     fn test_transaction_lifecycle() {
         let tmp = TempDir::new().expect("failed to create temp dir");
@@ -318,6 +658,91 @@ fn test_transaction_lifecycle() {
         assert_eq!(tx.state, TransactionState::Committed);
         assert_eq!(tx.operation_ids.len(), 2);
         assert!(!manager.has_active());
+
+        // Lookup by unique prefix
+        assert_eq!(manager.find(&tx.id[..8]).unwrap().id, tx.id);
+        assert!(matches!(
+            manager.find("not-a-transaction"),
+            Err(ReversibleError::InvalidTransactionId(_))
+        ));
+    }
+
+    #[test]
+    fn test_partial_rollback() {
+        let tmp = TempDir::new().expect("failed to create temp dir");
+        let path = tmp.path().join("transactions.json");
+        let mut manager =
+            TransactionManager::new(path.clone()).expect("failed to create transaction manager");
+
+        let id = manager.begin(None).unwrap().id.clone();
+        for op in ["op-1", "op-2", "op-3"] {
+            manager.add_operation(op.to_string()).unwrap();
+        }
+        manager
+            .record_rollback_step(&id, "op-3", RollbackStatus::Undone)
+            .unwrap();
+        manager
+            .record_rollback_step(&id, "op-2", RollbackStatus::Failed("busy".to_string()))
+            .unwrap();
+        let tx = manager.mark_partially_rolled_back(&id).unwrap();
+        assert_eq!(tx.state, TransactionState::PartiallyRolledBack);
+        assert!(!manager.has_active());
+        assert_eq!(
+            tx.rollback_remaining().collect::<Vec<_>>(),
+            vec!["op-2", "op-1"]
+        );
+
+        // Statuses survive a reload, and resuming can finish the rollback
+        let mut manager = TransactionManager::new(path).unwrap();
+        assert_eq!(
+            manager.get(&id).unwrap().rollback_status["op-2"],
+            RollbackStatus::Failed("busy".to_string())
+        );
+        let tx = manager.mark_rolled_back_by_id(&id).unwrap();
+        assert_eq!(tx.state, TransactionState::RolledBack);
+    }
+
+    #[test]
+    fn test_rollback_order_follows_dependencies() {
+        use crate::metadata::OperationType;
+        let op = |id: &str, op_type, path: &str| {
+            let mut op = OperationMetadata::new(op_type, PathBuf::from(path));
+            op.id = id.to_string();
+            op
+        };
+        // Log order: move a -> b then modify b; create c then append to
+        // it; create d/e then move it to d/f/e
+        let log = vec![
+            op("move", OperationType::Move, "a").with_secondary_path(PathBuf::from("b")),
+            op("modify", OperationType::Modify, "b"),
+            op("create", OperationType::Create, "c"),
+            op("append", OperationType::Modify, "c"),
+            op("create-d", OperationType::Create, "d/e"),
+            op("move-d", OperationType::Move, "d/e").with_secondary_path(PathBuf::from("d/f/e")),
+        ];
+        let graph = DependencyGraph::build(&log);
+
+        // Recorded out of order, newest-first would undo the move first
+        let mut tx = Transaction::new(None);
+        for id in ["modify", "move", "append", "create", "move-d", "create-d"] {
+            tx.add_operation(id.to_string());
+        }
+        assert_eq!(
+            tx.rollback_order(&graph),
+            vec!["move-d", "create-d", "append", "create", "modify", "move"]
+        );
+
+        // In order, it is newest first
+        let mut tx = Transaction::new(None);
+        for op in &log {
+            tx.add_operation(op.id.clone());
+        }
+        tx.rollback_status
+            .insert("move-d".to_string(), RollbackStatus::Undone);
+        assert_eq!(
+            tx.rollback_order(&graph),
+            vec!["create-d", "append", "create", "modify", "move"]
+        );
     }
 
     #[test]
@@ -0,0 +1,62 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+//
+// Peak memory of delta computation at the largest input it accepts.
+// Runs in its own test binary so the counting allocator sees nothing else.
+
+use reversible_core::delta::{Delta, DELTA_MAX_SIZE};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct Counting;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let now = CURRENT.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+        PEAK.fetch_max(now, Ordering::SeqCst);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        CURRENT.fetch_sub(layout.size(), Ordering::SeqCst);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+#[test]
+fn delta_compute_peak_memory_is_bounded() {
+    let n = DELTA_MAX_SIZE;
+    // Pseudo-random bytes, so the suffix sort needs several passes
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let original: Vec<u8> = (0..n)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect();
+    let mut new = original.clone();
+    for i in (0..n).step_by(4096) {
+        new[i] = new[i].wrapping_add(1);
+    }
+
+    let baseline = CURRENT.load(Ordering::SeqCst);
+    PEAK.store(baseline, Ordering::SeqCst);
+    let delta = Delta::compute(&original, &new);
+    let peak = PEAK.load(Ordering::SeqCst) - baseline;
+
+    assert!(!delta.is_full());
+    assert!(
+        peak <= 21 * n,
+        "peak {} bytes is over 21 bytes per input byte",
+        peak
+    );
+    assert_eq!(delta.apply(&original).unwrap(), new);
+}
@@ -6,6 +6,7 @@
 
 use proptest::prelude::*;
 use reversible_core::content_store::{ContentHash, ContentStore};
+use reversible_core::delta::Delta;
 use reversible_core::transaction::{TransactionManager, TransactionState};
 use tempfile::TempDir;
 
@@ -121,3 +122,73 @@ fn transaction_operation_count(n in 0usize..50) {
         prop_assert_eq!(tx.operation_ids.len(), n);
     }
 }
+
+// --- Delta properties ---
+
+/// Derive `new` from `base` by applying byte-level edits, so the inputs
+/// share long runs the delta algorithm can actually exploit.
+fn mutate(base: &[u8], edits: &[(usize, u8, u8)]) -> Vec<u8> {
+    let mut out = base.to_vec();
+    for &(at, kind, byte) in edits {
+        let at = if out.is_empty() { 0 } else { at % out.len() };
+        match kind % 3 {
+            0 if !out.is_empty() => out[at] = byte,
+            1 => out.insert(at, byte),
+            _ if !out.is_empty() => {
+                let end = (at + byte as usize).min(out.len());
+                out.drain(at..end);
+            }
+            _ => out.push(byte),
+        }
+    }
+    out
+}
+
+proptest! {
+    /// apply(compute(a, b), a) == b for unrelated inputs.
+    #[test]
+    fn delta_roundtrip_arbitrary(
+        a in proptest::collection::vec(any::<u8>(), 0..8192),
+        b in proptest::collection::vec(any::<u8>(), 0..8192),
+    ) {
+        let delta = Delta::compute(&a, &b);
+        prop_assert_eq!(delta.apply(&a), Some(b));
+    }
+
+    /// apply(compute(a, b), a) == b when b is an edited copy of a.
+    #[test]
+    fn delta_roundtrip_mutated(
+        a in proptest::collection::vec(0u8..8, 4096..8192),
+        edits in proptest::collection::vec((any::<usize>(), any::<u8>(), any::<u8>()), 0..32),
+    ) {
+        let b = mutate(&a, &edits);
+        let delta = Delta::compute(&a, &b);
+        prop_assert_eq!(delta.apply(&a), Some(b));
+    }
+
+    /// Serialization preserves the delta exactly.
+    #[test]
+    fn delta_serialization_roundtrip(
+        a in proptest::collection::vec(0u8..4, 4096..8192),
+        edits in proptest::collection::vec((any::<usize>(), any::<u8>(), any::<u8>()), 0..8),
+    ) {
+        let b = mutate(&a, &edits);
+        let delta = Delta::compute(&a, &b);
+        let parsed = Delta::from_bytes(&delta.clone().into_bytes());
+        prop_assert_eq!(parsed, Some(delta));
+    }
+
+    /// Delta-stored content retrieves to the exact original bytes.
+    #[test]
+    fn content_store_delta_roundtrip(
+        a in proptest::collection::vec(0u8..8, 4096..8192),
+        edits in proptest::collection::vec((any::<usize>(), any::<u8>(), any::<u8>()), 1..8),
+    ) {
+        let tmp = TempDir::new().unwrap();
+        let store = ContentStore::new(tmp.path().to_path_buf(), true).unwrap().with_delta(true);
+        let base = store.store(&a).unwrap();
+        let b = mutate(&a, &edits);
+        let (hash, _) = store.store_delta(&b, &base).unwrap();
+        prop_assert_eq!(store.retrieve(&hash).unwrap(), b);
+    }
+}
@@ -5,6 +5,7 @@
 // Each test here corresponds to a discovered issue.
 
 use reversible_core::content_store::{ContentHash, ContentStore};
+use reversible_core::delta::Delta;
 use reversible_core::transaction::TransactionManager;
 use tempfile::TempDir;
 
@@ -72,3 +73,22 @@ fn content_hash_verify_mismatch() {
         "Mismatched content must return false"
     );
 }
+
+/// Regression: a delta whose header claims a huge output size must be
+/// rejected before that size is used to allocate.
+#[test]
+fn delta_with_oversized_new_size_returns_none() {
+    let original = b"abcdefgh".repeat(1024);
+    let mut new = original.clone();
+    new[100] = b'z';
+
+    let bytes = Delta::compute(&original, &new).into_bytes();
+    // Magic, tag, then the two sizes (8192 each, two varint bytes apiece)
+    let mut crafted = bytes[..11].to_vec();
+    crafted.extend_from_slice(&[0xff; 8]);
+    crafted.push(0x7f);
+    crafted.extend_from_slice(&bytes[13..]);
+
+    let delta = Delta::from_bytes(&crafted).unwrap();
+    assert!(delta.apply(&original).is_none());
+}
@@ -0,0 +1,27 @@
+// SPDX-License-Identifier: MPL-2.0
+// Copyright (c) Jonathan D.A. Jewell <j.d.a.jewell@open.ac.uk>
+// SPDX-FileCopyrightText: 2026 Jonathan D.A. Jewell
+//
+// Fuzz target: apply(compute(a, b), a) == b for arbitrary inputs,
+// and malformed serialized deltas never panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use reversible_core::delta::Delta;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+    let rest = &data[2..];
+    let split = u16::from_le_bytes([data[0], data[1]]) as usize % (rest.len() + 1);
+    let (a, b) = rest.split_at(split);
+
+    let delta = Delta::compute(a, b);
+    assert_eq!(delta.apply(a).as_deref(), Some(b));
+
+    if let Some(parsed) = Delta::from_bytes(data) {
+        let _ = parsed.apply(a);
+    }
+});